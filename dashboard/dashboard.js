@@ -0,0 +1,212 @@
+// Small console on top of the admin api, the token stays in the session storage of the tab.
+"use strict";
+
+const state = { namespace: null, bucket: null, prefix: "" };
+
+function token() {
+  return sessionStorage.getItem("s3-proxy-token") || "";
+}
+
+async function api(method, path, body) {
+  const response = await fetch(path, {
+    method,
+    headers: {
+      Authorization: `Bearer ${token()}`,
+      "Content-Type": "application/json",
+    },
+    body: body === undefined ? undefined : JSON.stringify(body),
+  });
+
+  if (!response.ok) {
+    throw new Error(`${method} ${path}: ${response.status} ${await response.text()}`);
+  }
+  if (!(response.headers.get("content-type") || "").includes("application/json")) {
+    return null;
+  }
+  return response.json();
+}
+
+function showError(error) {
+  const element = document.getElementById("error");
+  element.textContent = error ? error.message : "";
+  element.hidden = !error;
+}
+
+function formatBytes(bytes) {
+  const units = ["B", "KiB", "MiB", "GiB", "TiB"];
+  let unit = 0;
+  while (bytes >= 1024 && unit < units.length - 1) {
+    bytes /= 1024;
+    unit += 1;
+  }
+  return `${bytes.toFixed(unit === 0 ? 0 : 1)} ${units[unit]}`;
+}
+
+function element(tag, text, attributes = {}) {
+  const node = document.createElement(tag);
+  if (text !== undefined) {
+    node.textContent = text;
+  }
+  for (const [name, value] of Object.entries(attributes)) {
+    node.setAttribute(name, value);
+  }
+  return node;
+}
+
+async function loadNamespaces() {
+  const names = await api("GET", "/namespaces");
+  const usages = await Promise.all(
+    names.map((name) => api("GET", `/namespaces/${name}/usage`)),
+  );
+  const largest = Math.max(1, ...usages.map((usage) => usage.bytes));
+
+  const body = document.querySelector("#namespaces tbody");
+  body.replaceChildren();
+  names.forEach((name, index) => {
+    const usage = usages[index];
+    const row = element("tr", undefined, { "data-href": name });
+    row.append(element("td", name), element("td", usage.objects));
+
+    const storage = element("td", formatBytes(usage.bytes));
+    const bar = element("div", undefined, { class: "bar" });
+    bar.style.width = `${(usage.bytes / largest) * 100}%`;
+    storage.append(bar);
+    row.append(storage);
+
+    row.addEventListener("click", () => openNamespace(name).catch(showError));
+    body.append(row);
+  });
+}
+
+async function openNamespace(name) {
+  state.namespace = name;
+  state.bucket = null;
+  state.prefix = "";
+
+  const section = document.getElementById("namespace");
+  section.hidden = false;
+  section.querySelector("h2").textContent = name;
+  document.getElementById("new-key").hidden = true;
+
+  await Promise.all([loadKeys(), loadBuckets()]);
+  await loadObjects();
+}
+
+async function loadKeys() {
+  const keys = await api("GET", `/namespaces/${state.namespace}/keys`);
+  const list = document.getElementById("keys");
+  list.replaceChildren();
+  for (const key of keys) {
+    const item = element("li", `${key} `);
+    const remove = element("button", "delete");
+    remove.addEventListener("click", async () => {
+      if (!confirm(`Delete key ${key}?`)) {
+        return;
+      }
+      try {
+        await api("DELETE", `/namespaces/${state.namespace}/keys/${key}`);
+        await loadKeys();
+      } catch (error) {
+        showError(error);
+      }
+    });
+    item.append(remove);
+    list.append(item);
+  }
+}
+
+async function loadBuckets() {
+  const buckets = await api("GET", `/namespaces/${state.namespace}/buckets`);
+  const list = document.getElementById("buckets");
+  list.replaceChildren();
+  for (const bucket of buckets) {
+    const item = element("li", bucket, { "data-href": bucket });
+    item.addEventListener("click", () => {
+      state.bucket = bucket;
+      state.prefix = "";
+      loadObjects().catch(showError);
+    });
+    list.append(item);
+  }
+}
+
+async function loadObjects() {
+  const body = document.querySelector("#objects tbody");
+  body.replaceChildren();
+  document.getElementById("location").textContent = state.bucket
+    ? `${state.bucket}/${state.prefix}`
+    : "select a bucket";
+  if (!state.bucket) {
+    return;
+  }
+
+  const query = new URLSearchParams({ prefix: state.prefix });
+  const objects = await api(
+    "GET",
+    `/namespaces/${state.namespace}/buckets/${state.bucket}/objects?${query}`,
+  );
+
+  if (state.prefix) {
+    const up = element("tr", undefined, { "data-href": ".." });
+    up.append(element("td", ".."), element("td"), element("td"));
+    up.addEventListener("click", () => {
+      state.prefix = state.prefix.replace(/[^/]*\/$/, "");
+      loadObjects().catch(showError);
+    });
+    body.append(up);
+  }
+
+  for (const object of objects) {
+    const row = element("tr");
+    row.append(
+      element("td", object.key.slice(state.prefix.length)),
+      element("td", object.is_dir ? "" : formatBytes(object.size)),
+      element("td", object.last_modified || ""),
+    );
+    if (object.is_dir) {
+      row.setAttribute("data-href", object.key);
+      row.addEventListener("click", () => {
+        state.prefix = object.key;
+        loadObjects().catch(showError);
+      });
+    }
+    body.append(row);
+  }
+}
+
+document.getElementById("login").addEventListener("submit", (event) => {
+  event.preventDefault();
+  sessionStorage.setItem("s3-proxy-token", document.getElementById("token").value);
+  showError(null);
+  loadNamespaces().catch(showError);
+});
+
+document.getElementById("create-namespace").addEventListener("submit", async (event) => {
+  event.preventDefault();
+  const input = document.getElementById("namespace-name");
+  try {
+    await api("POST", "/namespaces", { name: input.value });
+    input.value = "";
+    await loadNamespaces();
+  } catch (error) {
+    showError(error);
+  }
+});
+
+document.getElementById("create-key").addEventListener("click", async () => {
+  try {
+    const key = await api("POST", `/namespaces/${state.namespace}/keys`);
+    const output = document.getElementById("new-key");
+    output.textContent =
+      `access key: ${key.access_key}\nsecret key: ${key.secret_key}\n` +
+      "the secret key is only shown once";
+    output.hidden = false;
+    await loadKeys();
+  } catch (error) {
+    showError(error);
+  }
+});
+
+if (token()) {
+  loadNamespaces().catch(showError);
+}
@@ -0,0 +1,233 @@
+use crate::context::RequestSummary;
+use crate::metadata::{MetadataError, MetadataStore};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+const USAGE_PREFIX: &str = "usage::";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AccountingConfig {
+    /// how often the counters are added to the metadata store, 0 disables accounting
+    #[serde(default = "default_flush_interval_secs")]
+    pub flush_interval_secs: u64,
+}
+
+impl Default for AccountingConfig {
+    fn default() -> Self {
+        AccountingConfig {
+            flush_interval_secs: default_flush_interval_secs(),
+        }
+    }
+}
+
+fn default_flush_interval_secs() -> u64 {
+    10
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Counters {
+    pub requests: u64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+}
+
+impl Counters {
+    fn add(&mut self, other: &Counters) {
+        self.requests += other.requests;
+        self.bytes_in += other.bytes_in;
+        self.bytes_out += other.bytes_out;
+    }
+}
+
+/// (namespace, bucket)
+pub type UsageKey = (String, String);
+
+/// Hourly request counters per namespace and bucket.
+///
+/// Requests are counted in memory and added to the metadata store every flush, so every
+/// instance of the proxy contributes to the same counters.
+pub struct Accounting {
+    enabled: bool,
+    pending: Mutex<HashMap<(UsageKey, u64), Counters>>,
+}
+
+impl Accounting {
+    pub fn new(config: &AccountingConfig) -> Accounting {
+        Accounting {
+            enabled: config.flush_interval_secs > 0,
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn record(&self, summary: &RequestSummary) {
+        let context = summary.context;
+        let Some(namespace) = context.namespace() else {
+            return;
+        };
+        if !self.enabled {
+            return;
+        }
+
+        let bucket = context
+            .path
+            .trim_start_matches('/')
+            .split('/')
+            .next()
+            .unwrap_or_default();
+        let key = ((namespace.to_string(), bucket.to_string()), current_hour());
+
+        let mut pending = self.pending.lock().expect("lock is not poisoned");
+        pending.entry(key).or_default().add(&Counters {
+            requests: 1,
+            bytes_in: context.request_bytes(),
+            bytes_out: summary.response_bytes,
+        });
+    }
+
+    /// Adds the pending counters to the metadata store.
+    pub async fn flush(&self, metadata: &MetadataStore) -> Result<(), MetadataError> {
+        let pending = std::mem::take(&mut *self.pending.lock().expect("lock is not poisoned"));
+
+        for (((namespace, bucket), hour), counters) in pending {
+            let prefix = format!("{}{}::{}::{}::", USAGE_PREFIX, hour, namespace, bucket);
+            for (name, value) in [
+                ("requests", counters.requests),
+                ("bytes_in", counters.bytes_in),
+                ("bytes_out", counters.bytes_out),
+            ] {
+                if value > 0 {
+                    metadata
+                        .incr_by(&format!("{}{}", prefix, name), value)
+                        .await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The counters of the last `hours` hours, including the current one.
+    pub async fn query(
+        &self,
+        metadata: &MetadataStore,
+        hours: u64,
+    ) -> Result<BTreeMap<UsageKey, Counters>, MetadataError> {
+        self.flush(metadata).await?;
+
+        let mut usage: BTreeMap<UsageKey, Counters> = BTreeMap::new();
+        let current = current_hour();
+
+        for hour in current.saturating_sub(hours.saturating_sub(1))..=current {
+            let prefix = format!("{}{}::", USAGE_PREFIX, hour);
+            let keys = metadata.keys(&prefix).await?;
+
+            for chunk in keys.chunks(100) {
+                let values = metadata.get_many(chunk).await?;
+
+                for (key, value) in chunk.iter().zip(values) {
+                    let mut parts = key[prefix.len()..].splitn(3, "::");
+                    let (Some(namespace), Some(bucket), Some(name)) =
+                        (parts.next(), parts.next(), parts.next())
+                    else {
+                        continue;
+                    };
+                    let value = value.and_then(|x| x.parse().ok()).unwrap_or(0);
+
+                    let counters = usage
+                        .entry((namespace.to_string(), bucket.to_string()))
+                        .or_default();
+                    match name {
+                        "requests" => counters.requests += value,
+                        "bytes_in" => counters.bytes_in += value,
+                        "bytes_out" => counters.bytes_out += value,
+                        _ => (),
+                    }
+                }
+            }
+        }
+
+        Ok(usage)
+    }
+}
+
+fn current_hour() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        / 3600
+}
+
+/// Parses report windows like `90m`, `24h` or `7d` into whole hours, rounded up.
+pub fn parse_window(window: &str) -> Option<u64> {
+    let split = window.len().checked_sub(1)?;
+    let (amount, unit) = window.split_at(split);
+    let amount: u64 = amount.parse().ok()?;
+
+    let duration = match unit {
+        "m" => Duration::from_secs(amount * 60),
+        "h" => Duration::from_secs(amount * 3600),
+        "d" => Duration::from_secs(amount * 86400),
+        _ => return None,
+    };
+
+    Some(duration.as_secs().div_ceil(3600).max(1))
+}
+
+pub async fn flush_periodically(
+    accounting: std::sync::Arc<Accounting>,
+    metadata: MetadataStore,
+    every: Duration,
+) {
+    let mut interval = tokio::time::interval(every);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(error) = accounting.flush(&metadata).await {
+            tracing::error!("unable to flush the usage counters: {}", error);
+        }
+    }
+}
+
+#[tokio::test]
+async fn counters_are_flushed_and_queried() {
+    let metadata = MetadataStore::memory();
+    let accounting = Accounting::new(&AccountingConfig::default());
+
+    let key = (
+        (String::from("tenant"), String::from("photos")),
+        current_hour(),
+    );
+    for _ in 0..2 {
+        accounting
+            .pending
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_default()
+            .add(&Counters {
+                requests: 1,
+                bytes_in: 10,
+                bytes_out: 5,
+            });
+        accounting.flush(&metadata).await.unwrap();
+    }
+
+    let usage = accounting.query(&metadata, 24).await.unwrap();
+    assert_eq!(
+        usage[&(String::from("tenant"), String::from("photos"))],
+        Counters {
+            requests: 2,
+            bytes_in: 20,
+            bytes_out: 10,
+        }
+    );
+
+    assert_eq!(parse_window("24h"), Some(24));
+    assert_eq!(parse_window("7d"), Some(168));
+    assert_eq!(parse_window("90m"), Some(2));
+    assert_eq!(parse_window("7w"), None);
+}
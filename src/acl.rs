@@ -0,0 +1,214 @@
+use crate::error::S3Error;
+use crate::metadata::{MetadataError, MetadataStore};
+use axum::http::{HeaderMap, HeaderName};
+use serde::{Deserialize, Serialize};
+
+/// `object_acl::{namespace}/{bucket}/{key}` marks objects anyone may read, private objects have
+/// no record
+pub const OBJECT_ACL_PREFIX: &str = "object_acl::";
+
+pub static ACL_HEADER: HeaderName = HeaderName::from_static("x-amz-acl");
+
+/// The grantee of `READ` in a `public-read` ACL.
+pub const ALL_USERS: &str = "http://acs.amazonaws.com/groups/global/AllUsers";
+
+/// ACLs are reduced to the owner with full control, optionally with everyone reading.
+///
+/// A public-read bucket can be listed without a signature on its custom domain, a public-read
+/// object is served below `/_public/` and on custom domains without a signature like the objects
+/// of a bucket an admin made public. The S3 api never changes that public flag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CannedAcl {
+    Private,
+    PublicRead,
+}
+
+impl CannedAcl {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CannedAcl::Private => "private",
+            CannedAcl::PublicRead => "public-read",
+        }
+    }
+
+    /// The `x-amz-acl` value, the bucket owner is the object owner so its grants are private.
+    pub fn parse(value: &str) -> Result<CannedAcl, S3Error> {
+        match value {
+            "private" | "bucket-owner-read" | "bucket-owner-full-control" => Ok(CannedAcl::Private),
+            "public-read" => Ok(CannedAcl::PublicRead),
+            "public-read-write" | "authenticated-read" | "aws-exec-read" | "log-delivery-write" => {
+                Err(S3Error::NotImplemented(format!(
+                    "the {} canned ACL is not supported",
+                    value
+                )))
+            }
+            _ => Err(S3Error::InvalidArgument(format!(
+                "{} is not a canned ACL",
+                value
+            ))),
+        }
+    }
+
+    pub fn is_public(&self) -> bool {
+        *self == CannedAcl::PublicRead
+    }
+}
+
+/// The `x-amz-acl` of a request.
+pub fn requested(headers: &HeaderMap) -> Result<Option<CannedAcl>, S3Error> {
+    let Some(value) = headers.get(&ACL_HEADER) else {
+        return Ok(None);
+    };
+    let value = value
+        .to_str()
+        .map_err(|_| S3Error::InvalidArgument(String::from("Invalid x-amz-acl header")))?;
+    CannedAcl::parse(value).map(Some)
+}
+
+/// The ACL a `PutBucketAcl` or `PutObjectAcl` asks for, in the `x-amz-acl` header or the body.
+pub fn from_request(headers: &HeaderMap, body: &[u8], owner: &str) -> Result<CannedAcl, S3Error> {
+    if let Some(acl) = requested(headers)? {
+        return Ok(acl);
+    }
+    let policy: AccessControlPolicy = quick_xml::de::from_str(std::str::from_utf8(body)?)?;
+    policy.canned(owner)
+}
+
+/// The `<AccessControlPolicy>` body of `PutBucketAcl` and `PutObjectAcl`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct AccessControlPolicy {
+    #[serde(default)]
+    pub access_control_list: AccessControlList,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct AccessControlList {
+    #[serde(rename = "Grant", default)]
+    pub grants: Vec<Grant>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Grant {
+    pub grantee: Grantee,
+    pub permission: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct Grantee {
+    #[serde(rename = "ID")]
+    pub id: Option<String>,
+    #[serde(rename = "URI")]
+    pub uri: Option<String>,
+}
+
+impl AccessControlPolicy {
+    /// The canned ACL the grants amount to, `owner` is the namespace of the bucket. Grants to
+    /// the owner are implied, other grants than `READ` for everyone are refused.
+    pub fn canned(&self, owner: &str) -> Result<CannedAcl, S3Error> {
+        let mut acl = CannedAcl::Private;
+        for grant in &self.access_control_list.grants {
+            if grant.grantee.id.as_deref() == Some(owner) {
+                continue;
+            }
+            if grant.grantee.uri.as_deref() == Some(ALL_USERS) && grant.permission == "READ" {
+                acl = CannedAcl::PublicRead;
+                continue;
+            }
+            return Err(S3Error::NotImplemented(String::from(
+                "only grants to the owner and READ for AllUsers are supported",
+            )));
+        }
+        Ok(acl)
+    }
+}
+
+/// The ACL of the object at `path`.
+pub async fn stored(metadata: &MetadataStore, path: &str) -> Result<CannedAcl, MetadataError> {
+    let acl = metadata
+        .get(&format!("{}{}", OBJECT_ACL_PREFIX, path))
+        .await?;
+    Ok(match acl {
+        Some(_) => CannedAcl::PublicRead,
+        None => CannedAcl::Private,
+    })
+}
+
+/// Stores the ACL of the object at `path`, `private` is the default so it is not stored.
+pub async fn store(
+    metadata: &MetadataStore,
+    path: &str,
+    acl: Option<CannedAcl>,
+) -> Result<(), MetadataError> {
+    match acl.filter(CannedAcl::is_public) {
+        Some(acl) => {
+            metadata
+                .set(&format!("{}{}", OBJECT_ACL_PREFIX, path), acl.as_str())
+                .await
+        }
+        None => remove(metadata, path).await,
+    }
+}
+
+pub async fn remove(metadata: &MetadataStore, path: &str) -> Result<(), MetadataError> {
+    metadata
+        .delete(&format!("{}{}", OBJECT_ACL_PREFIX, path))
+        .await
+}
+
+#[tokio::test]
+async fn acls_are_parsed_and_stored() {
+    let xml = r#"<AccessControlPolicy xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+       <Owner><ID>tenant</ID><DisplayName>tenant</DisplayName></Owner>
+       <AccessControlList>
+          <Grant>
+             <Grantee xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:type="CanonicalUser">
+                <ID>tenant</ID>
+             </Grantee>
+             <Permission>FULL_CONTROL</Permission>
+          </Grant>
+          <Grant>
+             <Grantee xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:type="Group">
+                <URI>http://acs.amazonaws.com/groups/global/AllUsers</URI>
+             </Grantee>
+             <Permission>READ</Permission>
+          </Grant>
+       </AccessControlList>
+    </AccessControlPolicy>"#;
+    let policy: AccessControlPolicy = quick_xml::de::from_str(xml).unwrap();
+    assert_eq!(policy.canned("tenant").unwrap(), CannedAcl::PublicRead);
+    // the grant to the owner is only implied for the owner itself
+    assert!(policy.canned("other").is_err());
+    assert_eq!(
+        AccessControlPolicy::default().canned("tenant").unwrap(),
+        CannedAcl::Private
+    );
+
+    assert_eq!(
+        CannedAcl::parse("bucket-owner-full-control").unwrap(),
+        CannedAcl::Private
+    );
+    assert!(matches!(
+        CannedAcl::parse("public-read-write"),
+        Err(S3Error::NotImplemented(_))
+    ));
+    assert!(matches!(
+        CannedAcl::parse("everyone"),
+        Err(S3Error::InvalidArgument(_))
+    ));
+
+    let metadata = MetadataStore::memory();
+    let path = "tenant/site/index.html";
+    assert_eq!(stored(&metadata, path).await.unwrap(), CannedAcl::Private);
+    store(&metadata, path, Some(CannedAcl::PublicRead))
+        .await
+        .unwrap();
+    assert_eq!(
+        stored(&metadata, path).await.unwrap(),
+        CannedAcl::PublicRead
+    );
+    store(&metadata, path, None).await.unwrap();
+    assert_eq!(stored(&metadata, path).await.unwrap(), CannedAcl::Private);
+}
@@ -0,0 +1,688 @@
+use crate::error::S3Error;
+use crate::AppState;
+use axum::extract::{Path, State};
+use axum::http::header::CONTENT_TYPE;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+
+/// Where the ACME server fetches the HTTP-01 key authorizations.
+pub const CHALLENGE_PATH: &str = "/.well-known/acme-challenge/";
+/// `acme_challenge::{token}` holds the key authorization while an order is validated
+pub const CHALLENGE_PREFIX: &str = "acme_challenge::";
+/// The PKCS#8 account key, shared by all instances so they renew with the same account
+pub const ACCOUNT_KEY: &str = "acme_account_key";
+
+/// Obtains and renews certificates of the external host names, needs the `acme` feature.
+///
+/// The HTTP-01 challenges are answered on the S3 listener, which has to be reachable on port 80
+/// under every domain.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AcmeConfig {
+    /// comma separated host names, each gets a certificate of its own
+    #[serde(deserialize_with = "crate::comma_separated")]
+    pub domains: Vec<String>,
+    /// `{domain}.pem` with the chain and key is written here, use it as the
+    /// `S3_PROXY__HTTP3__CERT_DIR`
+    pub cert_dir: String,
+    /// e-mail address for expiry notices of the ACME server
+    pub contact: Option<String>,
+    #[serde(default = "default_directory_url")]
+    pub directory_url: String,
+    #[serde(default = "default_renew_before_days")]
+    pub renew_before_days: u64,
+    /// how often the certificates are checked for renewal
+    #[serde(default = "default_check_interval_secs")]
+    pub check_interval_secs: u64,
+}
+
+fn default_directory_url() -> String {
+    String::from("https://acme-v02.api.letsencrypt.org/directory")
+}
+
+fn default_renew_before_days() -> u64 {
+    30
+}
+
+fn default_check_interval_secs() -> u64 {
+    43200
+}
+
+/// Checks the configuration and renews the certificates in the background.
+pub fn start(state: &AppState, config: &AcmeConfig) -> anyhow::Result<()> {
+    if let Some(domain) = config
+        .domains
+        .iter()
+        .find(|x| !crate::domains::is_valid_domain(x))
+    {
+        anyhow::bail!(
+            "S3_PROXY__ACME__DOMAINS has {}, only lowercase host names without wildcards can be \
+             validated over HTTP-01",
+            domain
+        );
+    }
+
+    #[cfg(feature = "acme")]
+    {
+        tokio::spawn(client::renew_periodically(state.clone(), config.clone()));
+        Ok(())
+    }
+
+    #[cfg(not(feature = "acme"))]
+    {
+        let _ = state;
+        anyhow::bail!(
+            "acme is configured for {} but s3-proxy is compiled without the `acme` feature",
+            config.domains.join(", ")
+        )
+    }
+}
+
+/// `GET /.well-known/acme-challenge/:token`, answered by every instance behind the load
+/// balancer because the key authorization is in the metadata store.
+pub async fn challenge(
+    Path(token): Path<String>,
+    State(AppState { metadata, .. }): State<AppState>,
+) -> Result<Response, S3Error> {
+    match metadata
+        .get(&format!("{}{}", CHALLENGE_PREFIX, token))
+        .await?
+    {
+        Some(key_authorization) => Ok((
+            [(CONTENT_TYPE, "application/octet-stream")],
+            key_authorization,
+        )
+            .into_response()),
+        None => Ok(StatusCode::NOT_FOUND.into_response()),
+    }
+}
+
+/// One DER element.
+#[cfg_attr(not(feature = "acme"), allow(dead_code))]
+fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![tag];
+    if content.len() < 0x80 {
+        encoded.push(content.len() as u8);
+    } else {
+        let length = content.len().to_be_bytes();
+        let length = &length[length.iter().take_while(|x| **x == 0).count()..];
+        encoded.push(0x80 | length.len() as u8);
+        encoded.extend_from_slice(length);
+    }
+    encoded.extend_from_slice(content);
+    encoded
+}
+
+#[cfg_attr(not(feature = "acme"), allow(dead_code))]
+fn der_sequence(elements: &[Vec<u8>]) -> Vec<u8> {
+    der(0x30, &elements.concat())
+}
+
+#[cfg_attr(not(feature = "acme"), allow(dead_code))]
+fn der_oid(arcs: &[u64]) -> Vec<u8> {
+    let mut content = vec![(arcs[0] * 40 + arcs[1]) as u8];
+    for arc in &arcs[2..] {
+        let mut bytes = vec![(arc & 0x7f) as u8];
+        let mut rest = arc >> 7;
+        while rest > 0 {
+            bytes.push((rest & 0x7f) as u8 | 0x80);
+            rest >>= 7;
+        }
+        content.extend(bytes.iter().rev());
+    }
+    der(0x06, &content)
+}
+
+/// Splits the first DER element off `input`: its tag, its content and what follows.
+fn read_der(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, input) = input.split_first()?;
+    let (&length, mut input) = input.split_first()?;
+    let length = if length < 0x80 {
+        length as usize
+    } else {
+        let bytes = (length & 0x7f) as usize;
+        if bytes > std::mem::size_of::<usize>() || input.len() < bytes {
+            return None;
+        }
+        let (length, rest) = input.split_at(bytes);
+        input = rest;
+        length.iter().fold(0, |acc, x| (acc << 8) | *x as usize)
+    };
+    if input.len() < length {
+        return None;
+    }
+    let (content, rest) = input.split_at(length);
+    Some((tag, content, rest))
+}
+
+/// The unix timestamp a DER certificate expires at.
+pub fn not_after(certificate: &[u8]) -> Option<i64> {
+    let (_, certificate, _) = read_der(certificate)?;
+    let (_, tbs, _) = read_der(certificate)?;
+    let (tag, _, mut tbs) = read_der(tbs)?;
+    // the version is optional, then comes the serial number
+    if tag == 0xa0 {
+        (_, _, tbs) = read_der(tbs)?;
+    }
+    // the signature algorithm and the issuer
+    let (_, _, tbs) = read_der(tbs)?;
+    let (_, _, tbs) = read_der(tbs)?;
+    let (_, validity, _) = read_der(tbs)?;
+    let (_, _, validity) = read_der(validity)?;
+    let (tag, time, _) = read_der(validity)?;
+
+    let time = std::str::from_utf8(time).ok()?.strip_suffix('Z')?;
+    let (year, rest) = match tag {
+        // UTCTime, years from 1950 to 2049
+        0x17 => {
+            let year: i32 = time.get(..2)?.parse().ok()?;
+            (
+                if year < 50 { 2000 + year } else { 1900 + year },
+                &time[2..],
+            )
+        }
+        // GeneralizedTime
+        0x18 => (time.get(..4)?.parse().ok()?, &time[4..]),
+        _ => return None,
+    };
+    let field = |index: usize| rest.get(index * 2..index * 2 + 2)?.parse::<u32>().ok();
+    let date = chrono::NaiveDate::from_ymd_opt(year, field(0)?, field(1)?)?;
+    let date_time = date.and_hms_opt(field(2)?, field(3)?, field(4)?)?;
+
+    Some(date_time.and_utc().timestamp())
+}
+
+#[cfg(feature = "acme")]
+mod client {
+    use super::{der, der_oid, der_sequence, not_after, AcmeConfig, ACCOUNT_KEY, CHALLENGE_PREFIX};
+    use crate::AppState;
+    use anyhow::Context;
+    use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+    use base64::Engine;
+    use reqwest::header::{CONTENT_TYPE, LOCATION};
+    use ring::rand::SystemRandom;
+    use ring::signature::{
+        EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING, ECDSA_P256_SHA256_FIXED_SIGNING,
+    };
+    use serde::Deserialize;
+    use serde_json::{json, Value};
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    const BAD_NONCE: &str = "urn:ietf:params:acme:error:badNonce";
+    /// polls of an authorization or order before giving up
+    const MAX_POLLS: usize = 60;
+    const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Directory {
+        new_nonce: String,
+        new_account: String,
+        new_order: String,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Order {
+        authorizations: Vec<String>,
+        finalize: String,
+        certificate: Option<String>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Authorization {
+        status: String,
+        challenges: Vec<Challenge>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Challenge {
+        #[serde(rename = "type")]
+        kind: String,
+        url: String,
+        token: String,
+    }
+
+    #[derive(Debug, Default, Deserialize)]
+    struct Problem {
+        #[serde(default, rename = "type")]
+        kind: String,
+        #[serde(default)]
+        detail: String,
+    }
+
+    fn base64url(data: impl AsRef<[u8]>) -> String {
+        URL_SAFE_NO_PAD.encode(data)
+    }
+
+    /// An account session with the ACME server, requests are signed JWS with ES256.
+    struct AcmeClient {
+        http: reqwest::Client,
+        directory: Directory,
+        key: EcdsaKeyPair,
+        rng: SystemRandom,
+        /// the account url once registered, the public key is sent until then
+        kid: Option<String>,
+        nonce: Option<String>,
+    }
+
+    impl AcmeClient {
+        async fn connect(state: &AppState, config: &AcmeConfig) -> anyhow::Result<AcmeClient> {
+            let rng = SystemRandom::new();
+            let pkcs8 = match state.metadata.get(ACCOUNT_KEY).await? {
+                Some(pkcs8) => STANDARD.decode(pkcs8)?,
+                None => {
+                    let pkcs8 =
+                        EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng)
+                            .map_err(|_| anyhow::anyhow!("unable to generate the account key"))?;
+                    state
+                        .metadata
+                        .set(ACCOUNT_KEY, &STANDARD.encode(pkcs8.as_ref()))
+                        .await?;
+                    pkcs8.as_ref().to_vec()
+                }
+            };
+            let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &pkcs8, &rng)
+                .map_err(|x| anyhow::anyhow!("invalid account key: {}", x))?;
+
+            let http = reqwest::Client::builder()
+                .timeout(Duration::from_secs(30))
+                .build()?;
+            let directory = http
+                .get(&config.directory_url)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await
+                .with_context(|| format!("{} is not an ACME directory", config.directory_url))?;
+
+            let mut client = AcmeClient {
+                http,
+                directory,
+                key,
+                rng,
+                kid: None,
+                nonce: None,
+            };
+            // registering an existing key returns its account
+            let contact: Vec<_> = config
+                .contact
+                .iter()
+                .map(|x| format!("mailto:{}", x))
+                .collect();
+            let new_account = client.directory.new_account.clone();
+            let response = client
+                .post(
+                    &new_account,
+                    Some(json!({ "termsOfServiceAgreed": true, "contact": contact })),
+                )
+                .await?;
+            client.kid = Some(location(&response)?);
+
+            Ok(client)
+        }
+
+        fn jwk(&self) -> Value {
+            // the uncompressed point, 0x04 followed by x and y
+            let point = self.key.public_key().as_ref();
+            json!({
+                "crv": "P-256",
+                "kty": "EC",
+                "x": base64url(&point[1..33]),
+                "y": base64url(&point[33..65]),
+            })
+        }
+
+        /// RFC 7638, the members in lexicographic order without whitespace.
+        fn thumbprint(&self) -> String {
+            let jwk = self.jwk().to_string();
+            base64url(ring::digest::digest(&ring::digest::SHA256, jwk.as_bytes()))
+        }
+
+        async fn nonce(&mut self) -> anyhow::Result<String> {
+            if let Some(nonce) = self.nonce.take() {
+                return Ok(nonce);
+            }
+
+            let response = self.http.head(&self.directory.new_nonce).send().await?;
+            replay_nonce(&response).context("the ACME server sent no nonce")
+        }
+
+        /// Sends `payload` signed to `url`, `None` is a POST-as-GET.
+        async fn post(
+            &mut self,
+            url: &str,
+            payload: Option<Value>,
+        ) -> anyhow::Result<reqwest::Response> {
+            let payload = payload
+                .map(|x| base64url(x.to_string()))
+                .unwrap_or_default();
+            let mut retried = false;
+
+            loop {
+                let mut protected =
+                    json!({ "alg": "ES256", "nonce": self.nonce().await?, "url": url });
+                match &self.kid {
+                    Some(kid) => protected["kid"] = json!(kid),
+                    None => protected["jwk"] = self.jwk(),
+                }
+                let protected = base64url(protected.to_string());
+                let signature = self
+                    .key
+                    .sign(&self.rng, format!("{}.{}", protected, payload).as_bytes())
+                    .map_err(|_| anyhow::anyhow!("unable to sign the ACME request"))?;
+                let body = json!({
+                    "protected": protected,
+                    "payload": payload,
+                    "signature": base64url(signature),
+                });
+
+                let response = self
+                    .http
+                    .post(url)
+                    .header(CONTENT_TYPE, "application/jose+json")
+                    .body(body.to_string())
+                    .send()
+                    .await?;
+                self.nonce = replay_nonce(&response);
+                if response.status().is_success() {
+                    return Ok(response);
+                }
+
+                let status = response.status();
+                let problem: Problem = response.json().await.unwrap_or_default();
+                // nonces expire, the server sends a fresh one with the error
+                if problem.kind == BAD_NONCE && !retried {
+                    retried = true;
+                    continue;
+                }
+                anyhow::bail!(
+                    "{} answered {} {}: {}",
+                    url,
+                    status,
+                    problem.kind,
+                    problem.detail
+                );
+            }
+        }
+
+        /// Polls the authorization or order until it has `status`.
+        async fn wait(&mut self, url: &str, status: &str) -> anyhow::Result<Value> {
+            for _ in 0..MAX_POLLS {
+                let value: Value = self.post(url, None).await?.json().await?;
+                match value["status"].as_str() {
+                    Some(current) if current == status => return Ok(value),
+                    Some("invalid") => anyhow::bail!("{} is invalid: {}", url, value),
+                    _ => tokio::time::sleep(POLL_INTERVAL).await,
+                }
+            }
+
+            anyhow::bail!("{} did not become {} in time", url, status)
+        }
+    }
+
+    fn replay_nonce(response: &reqwest::Response) -> Option<String> {
+        response
+            .headers()
+            .get("replay-nonce")
+            .and_then(|x| x.to_str().ok())
+            .map(String::from)
+    }
+
+    fn location(response: &reqwest::Response) -> anyhow::Result<String> {
+        response
+            .headers()
+            .get(LOCATION)
+            .and_then(|x| x.to_str().ok())
+            .map(String::from)
+            .context("the ACME server sent no location")
+    }
+
+    fn pem(label: &str, der: &[u8]) -> String {
+        let encoded = STANDARD.encode(der);
+        let lines: Vec<_> = encoded
+            .as_bytes()
+            .chunks(64)
+            .map(|x| std::str::from_utf8(x).expect("base64 is ascii"))
+            .collect();
+        format!(
+            "-----BEGIN {label}-----\n{}\n-----END {label}-----\n",
+            lines.join("\n")
+        )
+    }
+
+    /// A PKCS#10 request for `domain` in the subject and subject alternative name.
+    fn csr(domain: &str, key: &EcdsaKeyPair, rng: &SystemRandom) -> anyhow::Result<Vec<u8>> {
+        let common_name = der_sequence(&[der_oid(&[2, 5, 4, 3]), der(0x0c, domain.as_bytes())]);
+        let subject = der_sequence(&[der(0x31, &common_name)]);
+
+        let mut point = vec![0];
+        point.extend_from_slice(key.public_key().as_ref());
+        let public_key = der_sequence(&[
+            der_sequence(&[
+                der_oid(&[1, 2, 840, 10045, 2, 1]),
+                der_oid(&[1, 2, 840, 10045, 3, 1, 7]),
+            ]),
+            der(0x03, &point),
+        ]);
+
+        let names = der_sequence(&[der(0x82, domain.as_bytes())]);
+        let extensions =
+            der_sequence(&[der_sequence(&[der_oid(&[2, 5, 29, 17]), der(0x04, &names)])]);
+        let attributes = der(
+            0xa0,
+            &der_sequence(&[
+                der_oid(&[1, 2, 840, 113549, 1, 9, 14]),
+                der(0x31, &extensions),
+            ]),
+        );
+
+        let info = der_sequence(&[der(0x02, &[0]), subject, public_key, attributes]);
+        let signature = key
+            .sign(rng, &info)
+            .map_err(|_| anyhow::anyhow!("unable to sign the certificate request"))?;
+        let mut signature_bits = vec![0];
+        signature_bits.extend_from_slice(signature.as_ref());
+
+        Ok(der_sequence(&[
+            info,
+            der_sequence(&[der_oid(&[1, 2, 840, 10045, 4, 3, 2])]),
+            der(0x03, &signature_bits),
+        ]))
+    }
+
+    fn certificate_path(config: &AcmeConfig, domain: &str) -> PathBuf {
+        PathBuf::from(&config.cert_dir).join(format!("{}.pem", domain))
+    }
+
+    /// Whether the certificate of `domain` is missing or expires within the renewal window.
+    fn is_due(config: &AcmeConfig, domain: &str) -> bool {
+        let Ok(pem) = std::fs::read(certificate_path(config, domain)) else {
+            return true;
+        };
+        let expires_at = rustls_pemfile::certs(&mut pem.as_slice())
+            .next()
+            .and_then(|x| x.ok())
+            .and_then(|x| not_after(&x));
+        let renew_at = chrono::Utc::now().timestamp()
+            + (config.renew_before_days * 86400)
+                .try_into()
+                .unwrap_or(i64::MAX);
+
+        expires_at.is_none_or(|x| x <= renew_at)
+    }
+
+    /// Orders a certificate for `domain` and writes it with its new key to the certificate
+    /// directory.
+    async fn issue(state: &AppState, config: &AcmeConfig, domain: &str) -> anyhow::Result<()> {
+        let mut client = AcmeClient::connect(state, config).await?;
+
+        let new_order = client.directory.new_order.clone();
+        let response = client
+            .post(
+                &new_order,
+                Some(json!({ "identifiers": [{ "type": "dns", "value": domain }] })),
+            )
+            .await?;
+        let order_url = location(&response)?;
+        let order: Order = response.json().await?;
+
+        for authorization_url in &order.authorizations {
+            let authorization: Authorization =
+                client.post(authorization_url, None).await?.json().await?;
+            if authorization.status == "valid" {
+                continue;
+            }
+            let challenge = authorization
+                .challenges
+                .iter()
+                .find(|x| x.kind == "http-01")
+                .context("the ACME server offers no http-01 challenge")?;
+
+            let key = format!("{}{}", CHALLENGE_PREFIX, challenge.token);
+            let key_authorization = format!("{}.{}", challenge.token, client.thumbprint());
+            state.metadata.set(&key, &key_authorization).await?;
+
+            let result = async {
+                client.post(&challenge.url, Some(json!({}))).await?;
+                client.wait(authorization_url, "valid").await
+            }
+            .await;
+            state.metadata.delete(&key).await?;
+            result?;
+        }
+        client.wait(&order_url, "ready").await?;
+
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng)
+            .map_err(|_| anyhow::anyhow!("unable to generate the certificate key"))?;
+        let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng)
+            .map_err(|x| anyhow::anyhow!("invalid certificate key: {}", x))?;
+        let csr = csr(domain, &key, &rng)?;
+        client
+            .post(&order.finalize, Some(json!({ "csr": base64url(csr) })))
+            .await?;
+        let order: Order = serde_json::from_value(client.wait(&order_url, "valid").await?)?;
+        let certificate_url = order
+            .certificate
+            .context("the ACME server sent no certificate")?;
+        let chain = client.post(&certificate_url, None).await?.text().await?;
+
+        // written next to the certificate and renamed, so a reload never reads half a file
+        let path = certificate_path(config, domain);
+        let partial = PathBuf::from(&config.cert_dir).join(format!(".{}.pem.partial", domain));
+        std::fs::create_dir_all(&config.cert_dir)?;
+        std::fs::write(
+            &partial,
+            format!("{}{}", chain, pem("PRIVATE KEY", pkcs8.as_ref())),
+        )?;
+        std::fs::rename(&partial, &path)?;
+
+        Ok(())
+    }
+
+    pub async fn renew_periodically(state: AppState, config: AcmeConfig) {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(config.check_interval_secs.max(60)));
+
+        loop {
+            interval.tick().await;
+            // a single account key and one order at a time against the rate limits
+            let Some(_lease) = state.leases.for_job("acme").await else {
+                continue;
+            };
+
+            for domain in config.domains.iter().filter(|x| is_due(&config, x)) {
+                match issue(&state, &config, domain).await {
+                    Ok(()) => tracing::info!("obtained a certificate for {}", domain),
+                    Err(error) => {
+                        tracing::error!(
+                            "unable to obtain a certificate for {}: {:#}",
+                            domain,
+                            error
+                        )
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn certificate_expiry_is_read() {
+    // a self-signed certificate valid until 2024-04-01T00:00:00Z
+    let certificate = hex::decode(concat!(
+        "3082016d30820113a003020102021463262f170fa758ade964c2af891eb7761acc81c8300a06082a",
+        "8648ce3d040302300c310a300806035504030c0161301e170d3234303130313030303030305a170d",
+        "3234303430313030303030305a300c310a300806035504030c01613059301306072a8648ce3d0201",
+        "06082a8648ce3d030107034200047cdd3d8055b25269b2b368e8312b791a20eeb8501f60f0173061",
+        "0119e4101adfd20d8e28e84748cb155611ef914490e5448a8f3e1502e45d255d481aae8a7fcca353",
+        "3051301d0603551d0e0416041432d43bfa475087b4404c176a78817ab40a9a8921301f0603551d23",
+        "04183016801432d43bfa475087b4404c176a78817ab40a9a8921300f0603551d130101ff04053003",
+        "0101ff300a06082a8648ce3d040302034800304502204c7a7260acdebf0abf62d234fa4726c0e0c4",
+        "03ac7b60a0174381eb65366c85a6022100f2874b2bbc36278b59843ecc898ab7695e5db6ef3f0f3f",
+        "d1618450a170b6fa29",
+    ))
+    .unwrap();
+
+    assert_eq!(not_after(&certificate), Some(1711929600));
+    assert_eq!(not_after(&certificate[..100]), None);
+
+    assert_eq!(
+        der_oid(&[1, 2, 840, 10045, 2, 1]),
+        hex::decode("06072a8648ce3d0201").unwrap()
+    );
+    let long = der(0x04, &[0; 300]);
+    assert_eq!(&long[..4], &[0x04, 0x82, 0x01, 0x2c]);
+    assert_eq!(read_der(&long).map(|x| x.1.len()), Some(300));
+}
+
+#[tokio::test]
+async fn challenges_are_answered_on_custom_domains() {
+    use crate::domains::DomainMapping;
+    use axum::body::Body;
+    use axum::extract::Request;
+    use axum::http::header::HOST;
+    use tower::ServiceExt;
+
+    let state = AppState::builder(crate::Config::builder().build())
+        .metadata(crate::metadata::MetadataStore::memory())
+        .build()
+        .unwrap();
+    state
+        .domains
+        .set(
+            &state.metadata,
+            "assets.example.com",
+            &DomainMapping {
+                namespace: String::from("tenant"),
+                bucket: String::from("assets"),
+            },
+        )
+        .await
+        .unwrap();
+    state
+        .metadata
+        .set(&format!("{}token", CHALLENGE_PREFIX), "token.thumbprint")
+        .await
+        .unwrap();
+    let app = crate::router(state);
+
+    let request = |token: &str| {
+        Request::builder()
+            .uri(format!("{}{}", CHALLENGE_PATH, token))
+            .header(HOST, "assets.example.com")
+            .body(Body::empty())
+            .unwrap()
+    };
+    let response = app.clone().oneshot(request("token")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(&body[..], b"token.thumbprint");
+
+    let response = app.oneshot(request("unknown")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
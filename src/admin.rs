@@ -0,0 +1,1569 @@
+use crate::accounting;
+use crate::axum_ext::is_hidden;
+use crate::bucket_policy::BucketPolicy;
+use crate::domains::{self, DomainMapping};
+use crate::namespaces::{self, AccessKey, Namespaces, Quota};
+use crate::object_lock;
+use crate::rate_limit::NamespaceLimits;
+use crate::transfer::{self, Location};
+use crate::trash;
+use crate::AppState;
+use axum::extract::{Path, Query, Request, State};
+use axum::http::header::{AUTHORIZATION, CONTENT_TYPE};
+use axum::http::{Method, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Json, Response};
+use axum::routing::{delete, get, post, put};
+use axum::Router;
+use axum_route_error::RouteError;
+use futures::StreamExt;
+use opendal::Metakey;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+/// Routes served on the separate admin listener, all guarded by the admin token.
+pub fn router(state: AppState) -> Router {
+    let router = Router::new()
+        .route("/audit", get(audit))
+        .route("/metrics", get(metrics))
+        .route("/usage", get(usage_report))
+        .route("/namespaces", get(list_namespaces).post(create_namespace))
+        .route("/provision", post(provision))
+        .route("/inventory", get(inventory))
+        .route("/public-access", get(public_access))
+        .route("/transfers", post(create_transfer))
+        .route("/domains", get(list_domains))
+        .route(
+            "/domains/:domain",
+            get(get_domain).put(set_domain).delete(delete_domain),
+        )
+        .route(
+            "/namespaces/:namespace",
+            get(get_namespace).delete(delete_namespace),
+        )
+        .route(
+            "/namespaces/:namespace/keys",
+            get(list_keys).post(create_key),
+        )
+        .route(
+            "/namespaces/:namespace/keys/:access_key",
+            delete(delete_key),
+        )
+        .route(
+            "/namespaces/:namespace/keys/:access_key/rotate",
+            post(rotate_key),
+        )
+        .route("/namespaces/:namespace/buckets", get(list_buckets))
+        .route(
+            "/namespaces/:namespace/buckets/:bucket",
+            put(create_bucket).delete(delete_bucket),
+        )
+        .route(
+            "/namespaces/:namespace/quota",
+            get(get_quota).put(set_quota).delete(delete_quota),
+        )
+        .route(
+            "/namespaces/:namespace/limits",
+            get(get_limits).put(set_limits).delete(delete_limits),
+        )
+        .route(
+            "/namespaces/:namespace/buckets/:bucket/quota",
+            get(get_bucket_quota)
+                .put(set_bucket_quota)
+                .delete(delete_bucket_quota),
+        )
+        .route(
+            "/namespaces/:namespace/buckets/:bucket/freeze",
+            get(get_freeze).put(freeze_bucket).delete(unfreeze_bucket),
+        )
+        .route(
+            "/namespaces/:namespace/buckets/:bucket/public",
+            get(get_public).put(publish_bucket).delete(unpublish_bucket),
+        )
+        .route(
+            "/namespaces/:namespace/policy",
+            get(get_policy).put(set_policy).delete(delete_policy),
+        )
+        .route(
+            "/namespaces/:namespace/buckets/:bucket/objects",
+            get(list_objects),
+        )
+        .route("/namespaces/:namespace/usage", get(usage))
+        .route(
+            "/namespaces/:namespace/trash",
+            get(list_trash).delete(empty_trash),
+        )
+        .route("/namespaces/:namespace/trash/restore", post(restore_trash))
+        .layer(middleware::from_fn_with_state(state.clone(), record))
+        .layer(middleware::from_fn_with_state(state.clone(), authorize));
+
+    // the console asks for the token itself, so its assets are served without one
+    #[cfg(feature = "dashboard")]
+    let router = router.merge(crate::dashboard::router());
+
+    router.with_state(state)
+}
+
+async fn authorize(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let expected = state.config.admin_token.as_deref().unwrap_or_default();
+    let token = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| x.strip_prefix("Bearer "));
+
+    match token {
+        Some(token) if !expected.is_empty() && tokens_match(token, expected) => next.run(req).await,
+        _ => (StatusCode::UNAUTHORIZED, "invalid admin token").into_response(),
+    }
+}
+
+/// Compares the digests in constant time, so neither the time nor the length of the token leak.
+fn tokens_match(token: &str, expected: &str) -> bool {
+    use sha2::{Digest, Sha256};
+    use subtle::ConstantTimeEq;
+
+    Sha256::digest(token)
+        .as_slice()
+        .ct_eq(Sha256::digest(expected).as_slice())
+        .into()
+}
+
+/// Changes made through the admin api end up in the audit log like mutating S3 requests.
+async fn record(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+    let response = next.run(req).await;
+
+    if let Some(audit) = &state.audit {
+        if method != Method::GET && response.status().is_success() {
+            audit.record_admin(&method, &path, response.status());
+        }
+    }
+
+    response
+}
+
+#[derive(Debug, Deserialize)]
+struct AuditQuery {
+    namespace: Option<String>,
+    #[serde(default = "default_limit")]
+    limit: usize,
+}
+
+fn default_limit() -> usize {
+    100
+}
+
+async fn audit(
+    State(AppState { audit, .. }): State<AppState>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Response, RouteError> {
+    let Some(audit) = audit else {
+        return Ok((StatusCode::NOT_FOUND, "audit log is not enabled").into_response());
+    };
+
+    let events = audit
+        .query(query.namespace.as_deref(), query.limit.min(10_000))
+        .await?;
+
+    Ok(Json(events).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+struct UsageQuery {
+    #[serde(default = "default_window")]
+    window: String,
+    namespace: Option<String>,
+    #[serde(default)]
+    format: UsageFormat,
+}
+
+fn default_window() -> String {
+    String::from("24h")
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum UsageFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct UsageRow {
+    namespace: String,
+    bucket: String,
+    storage_bytes: u64,
+    objects: u64,
+    requests: u64,
+    bytes_in: u64,
+    bytes_out: u64,
+}
+
+/// Storage per bucket right now, with the request counters of the window.
+async fn usage_report(
+    State(AppState {
+        opendal_operator,
+        accounting,
+        metadata,
+        ..
+    }): State<AppState>,
+    Query(query): Query<UsageQuery>,
+) -> Result<Response, RouteError> {
+    let Some(hours) = accounting::parse_window(&query.window) else {
+        return Err(RouteError::new_bad_request()
+            .set_public_error_message("window is a number followed by m, h or d"));
+    };
+
+    let mut rows: BTreeMap<(String, String), UsageRow> = BTreeMap::new();
+
+    let namespaces = match &query.namespace {
+        Some(namespace) => vec![format!("{}/", namespace)],
+        None => {
+            let mut namespaces = Vec::new();
+            let mut lister = opendal_operator.lister("/").await?;
+            while let Some(entry) = lister.next().await {
+                let entry = entry?;
+                if entry.metadata().is_dir() {
+                    namespaces.push(entry.path().to_string());
+                }
+            }
+            namespaces
+        }
+    };
+    for namespace_path in namespaces {
+        let mut buckets = opendal_operator.lister(&namespace_path).await?;
+        while let Some(entry) = buckets.next().await {
+            let entry = entry?;
+            if !entry.metadata().is_dir() || entry.path() == namespace_path {
+                continue;
+            }
+
+            let (storage_bytes, objects) =
+                crate::metrics::storage_usage(&opendal_operator, entry.path()).await?;
+            let namespace = namespace_path.trim_end_matches('/').to_string();
+            let bucket = entry.name().trim_end_matches('/').to_string();
+            rows.insert(
+                (namespace.clone(), bucket.clone()),
+                UsageRow {
+                    namespace,
+                    bucket,
+                    storage_bytes,
+                    objects,
+                    ..UsageRow::default()
+                },
+            );
+        }
+    }
+
+    for ((namespace, bucket), counters) in accounting.query(&metadata, hours).await? {
+        if query.namespace.as_ref().is_some_and(|x| *x != namespace) {
+            continue;
+        }
+
+        let row = rows
+            .entry((namespace.clone(), bucket.clone()))
+            .or_insert_with(|| UsageRow {
+                namespace,
+                bucket,
+                ..UsageRow::default()
+            });
+        row.requests = counters.requests;
+        row.bytes_in = counters.bytes_in;
+        row.bytes_out = counters.bytes_out;
+    }
+
+    let rows: Vec<_> = rows.into_values().collect();
+    match query.format {
+        UsageFormat::Json => Ok(Json(rows).into_response()),
+        UsageFormat::Csv => {
+            let mut csv = String::from(
+                "namespace,bucket,storage_bytes,objects,requests,bytes_in,bytes_out\n",
+            );
+            for row in rows {
+                csv.push_str(&format!(
+                    "{},{},{},{},{},{},{}\n",
+                    csv_field(&row.namespace),
+                    csv_field(&row.bucket),
+                    row.storage_bytes,
+                    row.objects,
+                    row.requests,
+                    row.bytes_in,
+                    row.bytes_out
+                ));
+            }
+
+            Ok(([(CONTENT_TYPE, "text/csv")], csv).into_response())
+        }
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+async fn metrics(
+    State(AppState { metrics, .. }): State<AppState>,
+) -> Result<impl IntoResponse, RouteError> {
+    Ok(([(CONTENT_TYPE, prometheus::TEXT_FORMAT)], metrics.encode()?))
+}
+
+fn not_found(message: &str) -> RouteError {
+    RouteError::new_not_found().set_public_error_message(message)
+}
+
+fn check_namespace(namespace: &str) -> Result<(), RouteError> {
+    if !namespaces::is_valid_name(namespace) {
+        return Err(RouteError::new_bad_request()
+            .set_public_error_message("namespaces are 1 to 63 letters, digits, '-' or '_'"));
+    }
+
+    Ok(())
+}
+
+async fn list_namespaces(
+    State(AppState { metadata, .. }): State<AppState>,
+) -> Result<impl IntoResponse, RouteError> {
+    Ok(Json(Namespaces::new(&metadata).list().await?))
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateNamespace {
+    name: String,
+}
+
+async fn create_namespace(
+    State(AppState {
+        metadata,
+        opendal_operator,
+        default_buckets,
+        ..
+    }): State<AppState>,
+    Json(body): Json<CreateNamespace>,
+) -> Result<Response, RouteError> {
+    check_namespace(&body.name)?;
+
+    if !Namespaces::new(&metadata).create(&body.name).await? {
+        return Err(RouteError::new_conflict().set_public_error_message("namespace already exists"));
+    }
+    default_buckets
+        .ensure(&metadata, &opendal_operator, &body.name)
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(json!({ "name": body.name }))).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+struct Provision {
+    name: String,
+    #[serde(default)]
+    buckets: Vec<String>,
+    quota: Option<Quota>,
+}
+
+#[derive(Debug, Serialize)]
+struct Provisioned {
+    name: String,
+    #[serde(flatten)]
+    key: AccessKey,
+    buckets: Vec<String>,
+}
+
+/// Onboards a tenant in one call: the namespace with its first key, buckets and quota.
+///
+/// When a step fails the earlier steps are undone, so a failed call can be retried as is.
+async fn provision(
+    State(AppState {
+        metadata,
+        opendal_operator,
+        default_buckets,
+        ..
+    }): State<AppState>,
+    Json(mut body): Json<Provision>,
+) -> Result<Response, RouteError> {
+    check_namespace(&body.name)?;
+    // created with the others, so they are also removed when a step fails
+    for bucket in default_buckets.buckets() {
+        if !body.buckets.contains(bucket) {
+            body.buckets.push(bucket.clone());
+        }
+    }
+    for bucket in &body.buckets {
+        check_bucket(bucket)?;
+    }
+
+    let namespaces = Namespaces::new(&metadata);
+    if !namespaces.create(&body.name).await? {
+        return Err(RouteError::new_conflict().set_public_error_message("namespace already exists"));
+    }
+
+    let mut created_dirs = Vec::new();
+    match provision_namespace(&namespaces, &opendal_operator, &body, &mut created_dirs).await {
+        Ok(key) => Ok((
+            StatusCode::CREATED,
+            Json(Provisioned {
+                name: body.name,
+                key,
+                buckets: body.buckets,
+            }),
+        )
+            .into_response()),
+        Err(error) => {
+            for path in created_dirs.iter().rev() {
+                if let Err(error) = opendal_operator.delete(path).await {
+                    tracing::error!(
+                        "unable to remove {} after a failed provision: {}",
+                        path,
+                        error
+                    );
+                }
+            }
+            if let Err(error) = namespaces.delete(&body.name).await {
+                tracing::error!(
+                    "unable to remove namespace {} after a failed provision: {}",
+                    body.name,
+                    error
+                );
+            }
+
+            Err(error)
+        }
+    }
+}
+
+async fn provision_namespace(
+    namespaces: &Namespaces<'_>,
+    operator: &opendal::Operator,
+    body: &Provision,
+    created_dirs: &mut Vec<String>,
+) -> Result<AccessKey, RouteError> {
+    let key = namespaces.create_key(&body.name).await?;
+    if let Some(quota) = &body.quota {
+        namespaces.set_quota(&body.name, quota).await?;
+    }
+
+    let mut dirs = vec![format!("{}/", body.name)];
+    dirs.extend(
+        body.buckets
+            .iter()
+            .map(|bucket| format!("{}/{}/", body.name, bucket)),
+    );
+    for dir in dirs {
+        if !operator.is_exist(&dir).await? {
+            operator.create_dir(&dir).await?;
+            created_dirs.push(dir);
+        }
+    }
+
+    Ok(key)
+}
+
+#[derive(Debug, Deserialize)]
+struct InventoryQuery {
+    /// the last namespace of the previous page
+    after: Option<String>,
+    #[serde(default = "default_inventory_limit")]
+    limit: usize,
+}
+
+fn default_inventory_limit() -> usize {
+    20
+}
+
+#[derive(Debug, Serialize)]
+struct InventoryNamespace {
+    name: String,
+    /// false for namespaces that only exist in the backend
+    registered: bool,
+    bytes: u64,
+    objects: u64,
+    buckets: Vec<InventoryBucket>,
+}
+
+#[derive(Debug, Serialize)]
+struct InventoryBucket {
+    name: String,
+    bytes: u64,
+    objects: u64,
+}
+
+/// Every namespace of the deployment with its buckets and sizes, paged by namespace name.
+async fn inventory(
+    State(AppState {
+        metadata,
+        opendal_operator,
+        ..
+    }): State<AppState>,
+    Query(query): Query<InventoryQuery>,
+) -> Result<impl IntoResponse, RouteError> {
+    let registered: BTreeSet<String> = Namespaces::new(&metadata)
+        .list()
+        .await?
+        .into_iter()
+        .collect();
+
+    let mut names = registered.clone();
+    let mut lister = opendal_operator.lister("/").await?;
+    while let Some(entry) = lister.next().await {
+        let entry = entry?;
+        if entry.metadata().is_dir() && entry.path() != "/" {
+            names.insert(entry.path().trim_end_matches('/').to_string());
+        }
+    }
+
+    let limit = query.limit.clamp(1, 1000);
+    let mut page: Vec<_> = names
+        .into_iter()
+        .filter(|name| query.after.as_ref().is_none_or(|after| name > after))
+        .take(limit + 1)
+        .collect();
+    let next = if page.len() > limit {
+        page.truncate(limit);
+        page.last().cloned()
+    } else {
+        None
+    };
+
+    let mut namespaces = Vec::with_capacity(page.len());
+    for name in page {
+        let path = format!("{}/", name);
+        let mut buckets = Vec::new();
+        if opendal_operator.is_exist(&path).await? {
+            let mut lister = opendal_operator.lister(&path).await?;
+            while let Some(entry) = lister.next().await {
+                let entry = entry?;
+                if !entry.metadata().is_dir() || entry.path() == path {
+                    continue;
+                }
+
+                let (bytes, objects) =
+                    crate::metrics::storage_usage(&opendal_operator, entry.path()).await?;
+                buckets.push(InventoryBucket {
+                    name: entry.name().trim_end_matches('/').to_string(),
+                    bytes,
+                    objects,
+                });
+            }
+        }
+
+        namespaces.push(InventoryNamespace {
+            registered: registered.contains(&name),
+            bytes: buckets.iter().map(|x| x.bytes).sum(),
+            objects: buckets.iter().map(|x| x.objects).sum(),
+            name,
+            buckets,
+        });
+    }
+
+    Ok(Json(json!({ "namespaces": namespaces, "next": next })))
+}
+
+/// Buckets and prefixes that anyone can read or write, see `s3-proxy public-access`.
+async fn public_access(
+    State(AppState {
+        metadata, domains, ..
+    }): State<AppState>,
+) -> Result<impl IntoResponse, RouteError> {
+    let exposures = crate::exposure::report(&metadata, &domains).await?;
+
+    Ok(Json(json!({ "exposures": exposures })))
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateTransfer {
+    from: Location,
+    to: Location,
+    /// removes the source once everything is copied
+    #[serde(default, rename = "move")]
+    remove_source: bool,
+}
+
+/// Copies or moves a bucket or prefix to another namespace without leaving the backend.
+///
+/// A move deletes the source objects, so it is refused while one of them is locked. Admins
+/// bypass governance retention, but compliance retention and legal holds apply to them too.
+async fn create_transfer(
+    State(AppState {
+        opendal_operator,
+        etag_cache,
+        metadata,
+        ..
+    }): State<AppState>,
+    Json(body): Json<CreateTransfer>,
+) -> Result<impl IntoResponse, RouteError> {
+    for location in [&body.from, &body.to] {
+        check_namespace(&location.namespace)?;
+        check_bucket(&location.bucket)?;
+        if !location.prefix.is_empty() && !location.prefix.ends_with('/') {
+            return Err(
+                RouteError::new_bad_request().set_public_error_message("prefix must end with /")
+            );
+        }
+    }
+    let source_dir = format!("{}/{}/", body.from.namespace, body.from.bucket);
+    if !opendal_operator.is_exist(&source_dir).await? {
+        return Err(not_found("bucket not found"));
+    }
+    if body.from.namespace == body.to.namespace
+        && body.from.bucket == body.to.bucket
+        && (body.to.prefix.starts_with(&body.from.prefix)
+            || body.from.prefix.starts_with(&body.to.prefix))
+    {
+        return Err(RouteError::new_bad_request()
+            .set_public_error_message("source and destination overlap"));
+    }
+    let source = format!(
+        "{}/{}/{}",
+        body.from.namespace, body.from.bucket, body.from.prefix
+    );
+    if body.remove_source
+        && !object_lock::locked_below(&metadata, &source, true)
+            .await?
+            .is_empty()
+    {
+        return Err(RouteError::new_conflict()
+            .set_public_error_message("objects below the source are locked"));
+    }
+
+    opendal_operator
+        .create_dir(&format!("{}/{}/", body.to.namespace, body.to.bucket))
+        .await?;
+    let summary = transfer::transfer(
+        &opendal_operator,
+        &etag_cache,
+        &body.from,
+        &body.to,
+        body.remove_source,
+    )
+    .await?;
+
+    Ok(Json(summary))
+}
+
+async fn get_namespace(
+    Path(namespace): Path<String>,
+    State(AppState { metadata, .. }): State<AppState>,
+) -> Result<impl IntoResponse, RouteError> {
+    let namespaces = Namespaces::new(&metadata);
+    if !namespaces.exists(&namespace).await? {
+        return Err(not_found("namespace not found"));
+    }
+
+    Ok(Json(json!({
+        "name": namespace,
+        "keys": namespaces.keys(&namespace).await?,
+        "quota": namespaces.quota(&namespace).await?,
+        "has_policy": namespaces.policy(&namespace).await?.is_some(),
+    })))
+}
+
+async fn delete_namespace(
+    Path(namespace): Path<String>,
+    State(AppState {
+        metadata,
+        credentials,
+        default_buckets,
+        ..
+    }): State<AppState>,
+) -> Result<StatusCode, RouteError> {
+    let namespaces = Namespaces::new(&metadata);
+    if !namespaces.exists(&namespace).await? {
+        return Err(not_found("namespace not found"));
+    }
+
+    for access_key in namespaces.delete(&namespace).await? {
+        credentials.invalidate(&access_key);
+    }
+    default_buckets.invalidate(&namespace);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn list_keys(
+    Path(namespace): Path<String>,
+    State(AppState { metadata, .. }): State<AppState>,
+) -> Result<impl IntoResponse, RouteError> {
+    Ok(Json(Namespaces::new(&metadata).keys(&namespace).await?))
+}
+
+/// The secret key is only returned here, it can not be read back later.
+async fn create_key(
+    Path(namespace): Path<String>,
+    State(AppState { metadata, .. }): State<AppState>,
+) -> Result<Response, RouteError> {
+    let namespaces = Namespaces::new(&metadata);
+    if !namespaces.exists(&namespace).await? {
+        return Err(not_found("namespace not found"));
+    }
+
+    let key = namespaces.create_key(&namespace).await?;
+
+    Ok((StatusCode::CREATED, Json(key)).into_response())
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RotateKey {
+    /// defaults to `S3_PROXY__CREDENTIALS__ROTATION_GRACE_SECS`
+    grace_secs: Option<u64>,
+}
+
+/// Returns the new secret key once, the old one is accepted until the grace period ends.
+async fn rotate_key(
+    Path((namespace, access_key)): Path<(String, String)>,
+    State(AppState {
+        config,
+        metadata,
+        credentials,
+        ..
+    }): State<AppState>,
+    body: Option<Json<RotateKey>>,
+) -> Result<Json<AccessKey>, RouteError> {
+    let namespaces = Namespaces::new(&metadata);
+    if !namespaces.keys(&namespace).await?.contains(&access_key) {
+        return Err(not_found("key not found"));
+    }
+
+    let grace_secs = body
+        .map(|Json(x)| x)
+        .unwrap_or_default()
+        .grace_secs
+        .unwrap_or(config.credentials.rotation_grace_secs);
+    let key = namespaces
+        .rotate_key(&access_key, Duration::from_secs(grace_secs))
+        .await?
+        .ok_or_else(|| not_found("key not found"))?;
+    credentials.invalidate(&access_key);
+
+    Ok(Json(key))
+}
+
+async fn delete_key(
+    Path((namespace, access_key)): Path<(String, String)>,
+    State(AppState {
+        metadata,
+        credentials,
+        ..
+    }): State<AppState>,
+) -> Result<StatusCode, RouteError> {
+    let namespaces = Namespaces::new(&metadata);
+    if !namespaces.keys(&namespace).await?.contains(&access_key) {
+        return Err(not_found("key not found"));
+    }
+
+    namespaces.delete_key(&access_key).await?;
+    credentials.invalidate(&access_key);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn list_buckets(
+    Path(namespace): Path<String>,
+    State(AppState {
+        opendal_operator, ..
+    }): State<AppState>,
+) -> Result<impl IntoResponse, RouteError> {
+    check_namespace(&namespace)?;
+
+    let mut buckets = Vec::new();
+    let mut lister = opendal_operator
+        .lister_with(&format!("{}/", namespace))
+        .await?;
+    while let Some(entry) = lister.next().await {
+        let entry = entry?;
+        if entry.metadata().is_dir() && !is_hidden(entry.name()) {
+            buckets.push(entry.name().trim_end_matches('/').to_string());
+        }
+    }
+
+    Ok(Json(buckets))
+}
+
+fn check_bucket(bucket: &str) -> Result<(), RouteError> {
+    if !namespaces::is_valid_name(bucket) {
+        return Err(RouteError::new_bad_request()
+            .set_public_error_message("buckets are 1 to 63 letters, digits, '-' or '_'"));
+    }
+
+    Ok(())
+}
+
+async fn create_bucket(
+    Path((namespace, bucket)): Path<(String, String)>,
+    State(AppState {
+        opendal_operator, ..
+    }): State<AppState>,
+) -> Result<StatusCode, RouteError> {
+    check_namespace(&namespace)?;
+    check_bucket(&bucket)?;
+
+    opendal_operator
+        .create_dir(&format!("{}/{}/", namespace, bucket))
+        .await?;
+
+    Ok(StatusCode::CREATED)
+}
+
+/// Only empty buckets are deleted, like the S3 api.
+async fn delete_bucket(
+    Path((namespace, bucket)): Path<(String, String)>,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        ..
+    }): State<AppState>,
+) -> Result<StatusCode, RouteError> {
+    check_namespace(&namespace)?;
+    check_bucket(&bucket)?;
+
+    let path = format!("{}/{}/", namespace, bucket);
+    if !opendal_operator.is_exist(&path).await? {
+        return Err(not_found("bucket not found"));
+    }
+    let mut lister = opendal_operator.lister(&path).await?;
+    while let Some(entry) = lister.next().await {
+        if entry?.path() != path {
+            return Err(RouteError::new_conflict().set_public_error_message("bucket is not empty"));
+        }
+    }
+
+    opendal_operator.delete(&path).await?;
+    Namespaces::new(&metadata)
+        .delete_bucket_records(&namespace, &bucket)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize)]
+struct ObjectsQuery {
+    #[serde(default)]
+    prefix: String,
+    #[serde(default = "default_limit")]
+    limit: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct ObjectEntry {
+    key: String,
+    is_dir: bool,
+    size: u64,
+    last_modified: Option<String>,
+}
+
+/// One level of the objects below `prefix`, directories end with a `/`.
+async fn list_objects(
+    Path((namespace, bucket)): Path<(String, String)>,
+    Query(query): Query<ObjectsQuery>,
+    State(AppState {
+        opendal_operator, ..
+    }): State<AppState>,
+) -> Result<impl IntoResponse, RouteError> {
+    check_namespace(&namespace)?;
+    check_bucket(&bucket)?;
+    if !query.prefix.is_empty() && !query.prefix.ends_with('/') {
+        return Err(
+            RouteError::new_bad_request().set_public_error_message("prefix must end with /")
+        );
+    }
+
+    let root = format!("{}/{}/", namespace, bucket);
+    let path = format!("{}{}", root, query.prefix);
+    let mut lister = opendal_operator
+        .lister_with(&path)
+        .metakey(Metakey::ContentLength | Metakey::LastModified)
+        .await?;
+
+    let mut objects = Vec::new();
+    while let Some(entry) = lister.next().await {
+        let entry = entry?;
+        if entry.path() == path {
+            continue;
+        }
+        if objects.len() >= query.limit.min(10_000) {
+            break;
+        }
+
+        let metadata = entry.metadata();
+        objects.push(ObjectEntry {
+            key: entry.path().trim_start_matches(&root).to_string(),
+            is_dir: metadata.is_dir(),
+            size: metadata.content_length(),
+            last_modified: metadata.last_modified().map(|x| x.to_rfc3339()),
+        });
+    }
+
+    Ok(Json(objects))
+}
+
+async fn get_quota(
+    Path(namespace): Path<String>,
+    State(AppState { metadata, .. }): State<AppState>,
+) -> Result<impl IntoResponse, RouteError> {
+    Ok(Json(Namespaces::new(&metadata).quota(&namespace).await?))
+}
+
+async fn set_quota(
+    Path(namespace): Path<String>,
+    State(AppState { metadata, .. }): State<AppState>,
+    Json(quota): Json<Quota>,
+) -> Result<impl IntoResponse, RouteError> {
+    check_namespace(&namespace)?;
+    Namespaces::new(&metadata)
+        .set_quota(&namespace, &quota)
+        .await?;
+
+    Ok(Json(quota))
+}
+
+async fn delete_quota(
+    Path(namespace): Path<String>,
+    State(AppState { metadata, .. }): State<AppState>,
+) -> Result<StatusCode, RouteError> {
+    Namespaces::new(&metadata).delete_quota(&namespace).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_limits(
+    Path(namespace): Path<String>,
+    State(AppState { metadata, .. }): State<AppState>,
+) -> Result<impl IntoResponse, RouteError> {
+    Ok(Json(Namespaces::new(&metadata).limits(&namespace).await?))
+}
+
+async fn set_limits(
+    Path(namespace): Path<String>,
+    State(AppState {
+        metadata,
+        rate_limiter,
+        ..
+    }): State<AppState>,
+    Json(limits): Json<NamespaceLimits>,
+) -> Result<impl IntoResponse, RouteError> {
+    check_namespace(&namespace)?;
+    Namespaces::new(&metadata)
+        .set_limits(&namespace, &limits)
+        .await?;
+    rate_limiter.invalidate(&namespace);
+
+    Ok(Json(limits))
+}
+
+async fn delete_limits(
+    Path(namespace): Path<String>,
+    State(AppState {
+        metadata,
+        rate_limiter,
+        ..
+    }): State<AppState>,
+) -> Result<StatusCode, RouteError> {
+    Namespaces::new(&metadata).delete_limits(&namespace).await?;
+    rate_limiter.invalidate(&namespace);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_bucket_quota(
+    Path((namespace, bucket)): Path<(String, String)>,
+    State(AppState { metadata, .. }): State<AppState>,
+) -> Result<impl IntoResponse, RouteError> {
+    Ok(Json(
+        Namespaces::new(&metadata)
+            .bucket_quota(&namespace, &bucket)
+            .await?,
+    ))
+}
+
+async fn set_bucket_quota(
+    Path((namespace, bucket)): Path<(String, String)>,
+    State(AppState { metadata, .. }): State<AppState>,
+    Json(quota): Json<Quota>,
+) -> Result<impl IntoResponse, RouteError> {
+    check_namespace(&namespace)?;
+    check_bucket(&bucket)?;
+    Namespaces::new(&metadata)
+        .set_bucket_quota(&namespace, &bucket, &quota)
+        .await?;
+
+    Ok(Json(quota))
+}
+
+async fn delete_bucket_quota(
+    Path((namespace, bucket)): Path<(String, String)>,
+    State(AppState { metadata, .. }): State<AppState>,
+) -> Result<StatusCode, RouteError> {
+    Namespaces::new(&metadata)
+        .delete_bucket_quota(&namespace, &bucket)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_freeze(
+    Path((namespace, bucket)): Path<(String, String)>,
+    State(AppState { metadata, .. }): State<AppState>,
+) -> Result<impl IntoResponse, RouteError> {
+    let frozen = Namespaces::new(&metadata)
+        .is_frozen(&namespace, &bucket)
+        .await?;
+
+    Ok(Json(json!({ "frozen": frozen })))
+}
+
+/// Makes the bucket read-only, for legal holds or migrations.
+async fn freeze_bucket(
+    Path((namespace, bucket)): Path<(String, String)>,
+    State(AppState { metadata, .. }): State<AppState>,
+) -> Result<StatusCode, RouteError> {
+    check_namespace(&namespace)?;
+    check_bucket(&bucket)?;
+    Namespaces::new(&metadata)
+        .set_frozen(&namespace, &bucket, true)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn unfreeze_bucket(
+    Path((namespace, bucket)): Path<(String, String)>,
+    State(AppState { metadata, .. }): State<AppState>,
+) -> Result<StatusCode, RouteError> {
+    Namespaces::new(&metadata)
+        .set_frozen(&namespace, &bucket, false)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_public(
+    Path((namespace, bucket)): Path<(String, String)>,
+    State(AppState { metadata, .. }): State<AppState>,
+) -> Result<impl IntoResponse, RouteError> {
+    let public = Namespaces::new(&metadata)
+        .is_public(&namespace, &bucket)
+        .await?;
+
+    Ok(Json(json!({ "public": public })))
+}
+
+/// Serves the objects of the bucket below `/_public/` without a signature.
+async fn publish_bucket(
+    Path((namespace, bucket)): Path<(String, String)>,
+    State(AppState { metadata, .. }): State<AppState>,
+) -> Result<StatusCode, RouteError> {
+    check_namespace(&namespace)?;
+    check_bucket(&bucket)?;
+    Namespaces::new(&metadata)
+        .set_public(&namespace, &bucket, true)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn unpublish_bucket(
+    Path((namespace, bucket)): Path<(String, String)>,
+    State(AppState { metadata, .. }): State<AppState>,
+) -> Result<StatusCode, RouteError> {
+    Namespaces::new(&metadata)
+        .set_public(&namespace, &bucket, false)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn list_domains(
+    State(AppState {
+        metadata, domains, ..
+    }): State<AppState>,
+) -> Result<impl IntoResponse, RouteError> {
+    Ok(Json(domains.list(&metadata).await?))
+}
+
+fn check_domain(domain: &str) -> Result<(), RouteError> {
+    if !domains::is_valid_domain(domain) {
+        return Err(RouteError::new_bad_request()
+            .set_public_error_message("domains are lowercase host names without port"));
+    }
+
+    Ok(())
+}
+
+async fn get_domain(
+    Path(domain): Path<String>,
+    State(AppState {
+        metadata, domains, ..
+    }): State<AppState>,
+) -> Result<impl IntoResponse, RouteError> {
+    match domains.lookup(&metadata, &domain).await? {
+        Some(mapping) => Ok(Json(mapping)),
+        None => Err(not_found("domain not found")),
+    }
+}
+
+/// Serves the bucket on the domain, the DNS of the domain has to point at the S3 listener.
+async fn set_domain(
+    Path(domain): Path<String>,
+    State(AppState {
+        metadata, domains, ..
+    }): State<AppState>,
+    Json(body): Json<DomainMapping>,
+) -> Result<StatusCode, RouteError> {
+    check_domain(&domain)?;
+    check_namespace(&body.namespace)?;
+    check_bucket(&body.bucket)?;
+    domains.set(&metadata, &domain, &body).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn delete_domain(
+    Path(domain): Path<String>,
+    State(AppState {
+        metadata, domains, ..
+    }): State<AppState>,
+) -> Result<StatusCode, RouteError> {
+    domains.delete(&metadata, &domain).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_policy(
+    Path(namespace): Path<String>,
+    State(AppState { metadata, .. }): State<AppState>,
+) -> Result<Response, RouteError> {
+    match Namespaces::new(&metadata).policy(&namespace).await? {
+        Some(policy) => Ok(([(CONTENT_TYPE, "application/json")], policy).into_response()),
+        None => Err(not_found("namespace has no policy")),
+    }
+}
+
+async fn set_policy(
+    Path(namespace): Path<String>,
+    State(AppState { metadata, .. }): State<AppState>,
+    Json(policy): Json<serde_json::Value>,
+) -> Result<StatusCode, RouteError> {
+    check_namespace(&namespace)?;
+    // enforced on every bucket of the namespace like a bucket policy
+    let policy = policy.to_string();
+    if let Err(error) = BucketPolicy::parse_namespace(&policy) {
+        return Err(RouteError::new_bad_request().set_public_error_message(&error.message()));
+    }
+    Namespaces::new(&metadata)
+        .set_policy(&namespace, &policy)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn delete_policy(
+    Path(namespace): Path<String>,
+    State(AppState { metadata, .. }): State<AppState>,
+) -> Result<StatusCode, RouteError> {
+    Namespaces::new(&metadata).delete_policy(&namespace).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn usage(
+    Path(namespace): Path<String>,
+    State(AppState {
+        opendal_operator, ..
+    }): State<AppState>,
+) -> Result<impl IntoResponse, RouteError> {
+    check_namespace(&namespace)?;
+
+    let (bytes, objects) =
+        crate::metrics::storage_usage(&opendal_operator, &format!("{}/", namespace)).await?;
+
+    Ok(Json(json!({ "bytes": bytes, "objects": objects })))
+}
+
+async fn list_trash(
+    Path(namespace): Path<String>,
+    State(AppState {
+        opendal_operator, ..
+    }): State<AppState>,
+) -> Result<impl IntoResponse, RouteError> {
+    check_namespace(&namespace)?;
+
+    Ok(Json(trash::list(&opendal_operator, &namespace).await?))
+}
+
+#[derive(Debug, Deserialize)]
+struct RestoreTrash {
+    id: String,
+}
+
+async fn restore_trash(
+    Path(namespace): Path<String>,
+    State(AppState {
+        opendal_operator,
+        etag_cache,
+        ..
+    }): State<AppState>,
+    Json(body): Json<RestoreTrash>,
+) -> Result<StatusCode, RouteError> {
+    check_namespace(&namespace)?;
+
+    if !trash::restore(&opendal_operator, &namespace, &body.id).await? {
+        return Err(not_found("object not found in the trash"));
+    }
+    if let Some((_, path)) = body.id.split_once('/') {
+        etag_cache.invalidate(&format!("{}/{}", namespace, path));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn empty_trash(
+    Path(namespace): Path<String>,
+    State(AppState {
+        opendal_operator, ..
+    }): State<AppState>,
+) -> Result<StatusCode, RouteError> {
+    check_namespace(&namespace)?;
+
+    opendal_operator
+        .remove_all(&format!("{}/{}/", namespace, trash::TRASH_DIR))
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[tokio::test]
+async fn admin_manages_namespaces_and_keys() {
+    use axum::body::Body;
+    use tower::ServiceExt;
+
+    let config = crate::Config::builder()
+        .admin("127.0.0.1:0", "token")
+        .build();
+    let state = AppState::builder(config)
+        .metadata(crate::metadata::MetadataStore::memory())
+        .build()
+        .unwrap();
+    let app = router(state.clone());
+
+    let request = |method: &str, uri: &str, body: &str| {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(AUTHORIZATION, "Bearer token")
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+
+    let response = app
+        .clone()
+        .oneshot(request("POST", "/namespaces", r#"{"name":"tenant"}"#))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let response = app
+        .clone()
+        .oneshot(request("POST", "/namespaces/tenant/keys", ""))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let key: namespaces::AccessKey = serde_json::from_slice(&body).unwrap();
+
+    let identity = state
+        .auth
+        .authorize(&crate::auth::AuthRequest {
+            access_key: &key.access_key,
+            operation: crate::operation::S3Operation::ListBuckets,
+            parts: &Request::new(()).into_parts().0,
+        })
+        .await
+        .unwrap();
+    assert_eq!(identity.namespace, "tenant");
+
+    let response = app
+        .clone()
+        .oneshot(request(
+            "PUT",
+            "/namespaces/tenant/quota",
+            r#"{"max_bytes":10}"#,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app
+        .clone()
+        .oneshot(request("PUT", "/namespaces/tenant/buckets/photos", ""))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let response = app
+        .clone()
+        .oneshot(request("GET", "/namespaces/tenant/buckets", ""))
+        .await
+        .unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(&body[..], br#"["photos"]"#);
+
+    // namespace policies are enforced, so they are checked like bucket policies
+    let response = app
+        .clone()
+        .oneshot(request(
+            "PUT",
+            "/namespaces/tenant/policy",
+            r#"{"Statement": {"Effect": "Allow", "Principal": "*", "Action": "iam:PassRole", "Resource": "*"}}"#,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = app
+        .clone()
+        .oneshot(request(
+            "PUT",
+            "/namespaces/tenant/policy",
+            r#"{"Statement": {"Effect": "Deny", "Principal": "*", "Action": "s3:DeleteObject", "Resource": "arn:aws:s3:::*/*"}}"#,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let response = app
+        .oneshot(request("DELETE", "/namespaces/tenant", ""))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(state.auth.secret_key(&key.access_key).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn locked_objects_are_not_moved() {
+    use axum::body::Body;
+    use tower::ServiceExt;
+
+    let config = crate::Config::builder()
+        .admin("127.0.0.1:0", "token")
+        .build();
+    let state = AppState::builder(config)
+        .metadata(crate::metadata::MetadataStore::memory())
+        .build()
+        .unwrap();
+    let app = router(state.clone());
+    state
+        .opendal_operator
+        .write("old/logs/a.log", "hello")
+        .await
+        .unwrap();
+    object_lock::store(
+        &state.metadata,
+        "old/logs/a.log",
+        &object_lock::ObjectLock {
+            retention: None,
+            legal_hold: true,
+        },
+    )
+    .await
+    .unwrap();
+
+    let transfer = |remove_source: bool| {
+        let body = json!({
+            "from": {"namespace": "old", "bucket": "logs"},
+            "to": {"namespace": "new", "bucket": "logs"},
+            "move": remove_source,
+        });
+        Request::builder()
+            .method("POST")
+            .uri("/transfers")
+            .header(AUTHORIZATION, "Bearer token")
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+
+    let response = app.clone().oneshot(transfer(true)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert!(state
+        .opendal_operator
+        .is_exist("old/logs/a.log")
+        .await
+        .unwrap());
+    // copying leaves the locked object in place
+    let response = app.clone().oneshot(transfer(false)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(state
+        .opendal_operator
+        .is_exist("new/logs/a.log")
+        .await
+        .unwrap());
+}
+
+#[tokio::test]
+async fn usage_report_renders_csv() {
+    use axum::body::Body;
+    use tower::ServiceExt;
+
+    let config = crate::Config::builder()
+        .admin("127.0.0.1:0", "token")
+        .build();
+    let state = AppState::builder(config)
+        .metadata(crate::metadata::MetadataStore::memory())
+        .build()
+        .unwrap();
+    state
+        .opendal_operator
+        .write("tenant/photos/a.txt", "hello")
+        .await
+        .unwrap();
+    let app = router(state);
+
+    let request = |uri: &str| {
+        Request::builder()
+            .uri(uri)
+            .header(AUTHORIZATION, "Bearer token")
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let response = app
+        .clone()
+        .oneshot(request("/usage?window=7d&format=csv"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(
+        std::str::from_utf8(&body).unwrap(),
+        "namespace,bucket,storage_bytes,objects,requests,bytes_in,bytes_out\ntenant,photos,5,1,0,0,0\n"
+    );
+
+    let response = app.oneshot(request("/usage?window=7w")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(csv_field("a,\"b\""), "\"a,\"\"b\"\"\"");
+}
+
+#[tokio::test]
+async fn provision_creates_a_namespace_in_one_call() {
+    use axum::body::Body;
+    use tower::ServiceExt;
+
+    let config = crate::Config::builder()
+        .admin("127.0.0.1:0", "token")
+        .build();
+    let state = AppState::builder(config)
+        .metadata(crate::metadata::MetadataStore::memory())
+        .build()
+        .unwrap();
+    let app = router(state.clone());
+
+    let request = |body: &str| {
+        Request::builder()
+            .method("POST")
+            .uri("/provision")
+            .header(AUTHORIZATION, "Bearer token")
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+    let body = r#"{"name":"tenant","buckets":["photos","logs"],"quota":{"max_bytes":1000}}"#;
+
+    let response = app.clone().oneshot(request(body)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let key: AccessKey = serde_json::from_slice(&body).unwrap();
+
+    let namespaces = Namespaces::new(&state.metadata);
+    assert_eq!(
+        namespaces.keys("tenant").await.unwrap(),
+        vec![key.access_key]
+    );
+    assert_eq!(
+        namespaces.quota("tenant").await.unwrap().max_bytes,
+        Some(1000)
+    );
+    assert!(state
+        .opendal_operator
+        .is_exist("tenant/logs/")
+        .await
+        .unwrap());
+
+    let response = app.oneshot(request(r#"{"name":"tenant"}"#)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn inventory_pages_through_all_namespaces() {
+    use axum::body::Body;
+    use tower::ServiceExt;
+
+    let config = crate::Config::builder()
+        .admin("127.0.0.1:0", "token")
+        .build();
+    let state = AppState::builder(config)
+        .metadata(crate::metadata::MetadataStore::memory())
+        .build()
+        .unwrap();
+    Namespaces::new(&state.metadata)
+        .create("empty")
+        .await
+        .unwrap();
+    for path in ["alpha/photos/a.txt", "alpha/logs/b.txt", "beta/data/c.txt"] {
+        state.opendal_operator.write(path, "data").await.unwrap();
+    }
+    let app = router(state);
+
+    let get = |uri: &str| {
+        let request = Request::builder()
+            .uri(uri)
+            .header(AUTHORIZATION, "Bearer token")
+            .body(Body::empty())
+            .unwrap();
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        }
+    };
+
+    let page = get("/inventory?limit=2").await;
+    assert_eq!(page["next"], "beta");
+    assert_eq!(page["namespaces"][0]["name"], "alpha");
+    assert_eq!(page["namespaces"][0]["registered"], false);
+    assert_eq!(page["namespaces"][0]["bytes"], 8);
+    assert_eq!(
+        page["namespaces"][0]["buckets"].as_array().unwrap().len(),
+        2
+    );
+
+    let page = get("/inventory?limit=2&after=beta").await;
+    assert_eq!(page["next"], serde_json::Value::Null);
+    assert_eq!(page["namespaces"][0]["name"], "empty");
+    assert_eq!(page["namespaces"][0]["registered"], true);
+}
+
+#[test]
+fn admin_tokens_are_compared_whole() {
+    assert!(tokens_match("secret-token", "secret-token"));
+    assert!(!tokens_match("secret-toke", "secret-token"));
+    assert!(!tokens_match("secret-token-", "secret-token"));
+    assert!(!tokens_match("", "secret-token"));
+}
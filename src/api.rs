@@ -1,22 +1,89 @@
 use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
-use crate::signature::VerifiedRequest;
-use crate::{templates, AppState};
-use axum::body::Body;
-use axum::extract::{Path, State};
-use axum::http::header::{CONTENT_LENGTH, CONTENT_TYPE};
-use axum::http::{HeaderMap, HeaderValue, StatusCode};
-use axum::response::IntoResponse;
-use axum_route_error::RouteError;
+use crate::axum_ext::{
+    check_key, decoded_query_value, is_hidden, query_value, BucketPath, ObjectPath,
+};
+use crate::bucket_policy::BucketPolicy;
+use crate::buffer_pool::BufferPool;
+use crate::checksums::ChecksumAlgorithm;
+use crate::cors::CorsConfiguration;
+use crate::error::S3Error;
+use crate::etag_cache::{self, Precondition, Validators};
+use crate::events::{BucketEvent, ObjectEvent, ObjectMetadata};
+use crate::inventory::InventoryConfiguration;
+use crate::lifecycle::LifecycleConfiguration;
+use crate::metadata::MetadataStore;
+use crate::namespaces::Namespaces;
+use crate::notifications::NotificationConfiguration;
+use crate::object_lock::{
+    self, LegalHoldDocument, ObjectLock, ObjectLockConfiguration, RetentionDocument,
+};
+use crate::post_policy::{self, PostPolicy};
+use crate::replication::{self, ReplicationConfiguration};
+use crate::select::{self, SelectRequest, Selection};
+use crate::signature::{self, VerifiedRequest};
+use crate::sqs::QueueTarget;
+use crate::tagging::{self, Tagging};
+use crate::transforms::{self, TransformRequest, TransformedObject};
+use crate::versioning::{VersioningConfiguration, VersioningStatus};
+use crate::{
+    acl, checksums, etags, expiration, integrity, multipart, quota, range, response_overrides,
+    storage_class, templates, trash, AppState, Config,
+};
+use askama::Template;
+use axum::body::{Body, Bytes};
+use axum::extract::{FromRequest, Multipart, RawQuery, Request, State};
+use axum::http::header::{
+    InvalidHeaderValue, ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG,
+    LAST_MODIFIED, LOCATION,
+};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Json, Response};
+use axum::BoxError;
+use futures::{stream, Stream, StreamExt, TryStreamExt};
+use md5::{Digest, Md5};
 use opendal::Metakey;
-use tokio_stream::StreamExt;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use rand::distributions::{Alphanumeric, DistString};
+use serde::Serialize;
+use serde_json::json;
+
+const REPLICATION_STATUS: HeaderName = HeaderName::from_static("x-amz-replication-status");
+
+/// the most keys a listing page has, also when the client asks for more
+const MAX_LIST_KEYS: usize = 1000;
+
+/// Listings are json instead of S3 XML with the non-standard `?format=json`.
+fn wants_json(query: &Option<String>) -> bool {
+    query
+        .as_deref()
+        .and_then(|x| query_value(x, "format"))
+        .is_some_and(|x| x == "json")
+}
+
+#[derive(Debug, Serialize)]
+struct JsonBucket<'a> {
+    name: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonObject<'a> {
+    key: &'a str,
+    size: u64,
+    etag: Option<&'a str>,
+    last_modified: Option<String>,
+}
 
 pub async fn list_buckets(
     State(AppState {
         opendal_operator, ..
     }): State<AppState>,
+    RawQuery(query): RawQuery,
     signature: VerifiedRequest,
-) -> Result<impl IntoResponse, RouteError> {
+) -> Result<Response, S3Error> {
     let namespace = &signature.namespace;
 
     // let bucket = "testing";
@@ -36,20 +103,25 @@ pub async fn list_buckets(
     while let Some(entry) = lister.next().await {
         match entry {
             Ok(x) => {
-                if x.metadata().is_dir() {
+                if x.metadata().is_dir() && !is_hidden(x.name()) {
                     buckets.push(templates::ListBucketItem {
                         name: x.name().trim_end_matches('/').to_string().into(),
                         timestamp: None,
                     })
                 }
             }
-            Err(e) => {
-                tracing::error!("{}", e.to_string());
-                return Err(RouteError::new_internal_server());
-            }
+            Err(e) => return Err(S3Error::internal(e)),
         }
     }
 
+    if wants_json(&query) {
+        let buckets: Vec<_> = buckets
+            .iter()
+            .map(|x| JsonBucket { name: &x.name })
+            .collect();
+        return Ok(Json(json!({ "buckets": buckets })).into_response());
+    }
+
     // let datetime = OffsetDateTime::from_unix_timestamp(1706911595)?;
     // let tmp_timestamp = datetime.format(&Rfc3339).unwrap();
 
@@ -73,17 +145,30 @@ pub async fn list_buckets(
 }
 
 pub async fn create_bucket(
-    Path(bucket_name): Path<String>,
+    BucketPath(bucket_name): BucketPath,
     State(AppState {
-        opendal_operator, ..
+        opendal_operator,
+        metadata,
+        event_hooks,
+        listing_cache,
+        ..
     }): State<AppState>,
     signature: VerifiedRequest,
-) -> Result<impl IntoResponse, RouteError> {
+) -> Result<impl IntoResponse, S3Error> {
     let namespace = &signature.namespace;
 
-    let utf8_slice = std::str::from_utf8(&signature.bytes)?;
+    let bytes = signature.body.bytes().await?;
+    let utf8_slice = std::str::from_utf8(&bytes)?;
 
-    let _body: Option<templates::CreateBucket> = quick_xml::de::from_str(utf8_slice)?;
+    let body: Option<templates::CreateBucket> = quick_xml::de::from_str(utf8_slice)?;
+    let region = body
+        .and_then(|x| x.location_constraint)
+        .filter(|x| !x.is_empty());
+    let object_lock_enabled = signature
+        .headers
+        .get(&object_lock::BUCKET_LOCK_ENABLED_HEADER)
+        .is_some_and(|x| x.as_bytes().eq_ignore_ascii_case(b"true"));
+    let acl = acl::requested(&signature.headers)?;
 
     opendal_operator
         .create_dir(&format!("{}/", namespace))
@@ -91,138 +176,2942 @@ pub async fn create_bucket(
     opendal_operator
         .create_dir(&format!("{}/{}/", namespace, bucket_name))
         .await?;
+    let namespaces = Namespaces::new(&metadata);
+    namespaces
+        .set_region(namespace, &bucket_name, region.as_deref())
+        .await?;
+    if let Some(acl) = acl {
+        namespaces
+            .set_bucket_acl(namespace, &bucket_name, acl)
+            .await?;
+    }
+    if object_lock_enabled {
+        // locked objects are kept when they are replaced, so the bucket is versioned like in S3
+        namespaces
+            .set_bucket_versioning(namespace, &bucket_name, VersioningStatus::Enabled)
+            .await?;
+        namespaces
+            .set_object_lock_configuration(
+                namespace,
+                &bucket_name,
+                &ObjectLockConfiguration::enabled(),
+            )
+            .await?;
+    }
+    listing_cache.invalidate(namespace, &bucket_name);
+
+    event_hooks
+        .bucket_create(&BucketEvent {
+            namespace: namespace.clone(),
+            bucket: bucket_name,
+        })
+        .await;
 
     Ok("OK".into_response())
 }
 
-pub async fn create_object(
-    Path((bucket_name, object_name)): Path<(String, String)>,
-    header_map: HeaderMap,
+/// Only empty buckets are deleted, their records in the metadata store go with them.
+pub async fn delete_bucket(
+    BucketPath(bucket_name): BucketPath,
     State(AppState {
-        opendal_operator, ..
+        opendal_operator,
+        metadata,
+        listing_cache,
+        ..
     }): State<AppState>,
     signature: VerifiedRequest,
-) -> Result<impl IntoResponse, RouteError> {
-    let namespace = signature.namespace;
+) -> Result<impl IntoResponse, S3Error> {
+    let namespace = &signature.namespace;
+
+    let path = format!("{}/{}/", namespace, bucket_name);
+    if !opendal_operator.is_exist(&path).await? {
+        return Err(S3Error::NoSuchBucket);
+    }
+    let mut lister = opendal_operator.lister(&path).await?;
+    while let Some(entry) = lister.next().await {
+        if entry?.path() != path {
+            return Err(S3Error::BucketNotEmpty);
+        }
+    }
+
+    opendal_operator.delete(&path).await?;
+    Namespaces::new(&metadata)
+        .delete_bucket_records(namespace, &bucket_name)
+        .await?;
+    listing_cache.invalidate(namespace, &bucket_name);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn get_bucket_notification(
+    BucketPath(bucket_name): BucketPath,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        ..
+    }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let namespace = &signature.namespace;
 
-    if opendal_operator
-        .is_exist(&format!("{}/{}", namespace, bucket_name))
+    if !opendal_operator
+        .is_exist(&format!("{}/{}/", namespace, bucket_name))
         .await?
     {
-        return Ok((StatusCode::NOT_FOUND, "NOT FOUND").into_response());
+        return Err(S3Error::NoSuchBucket);
     }
 
-    let mut writer = opendal_operator.write_with(
-        &format!("{}/{}/{}", namespace, bucket_name, object_name),
-        signature.bytes,
-    );
+    let configuration = Namespaces::new(&metadata)
+        .notification_configuration(namespace, &bucket_name)
+        .await?;
+    let template = templates::NotificationConfigurationTemplate {
+        configuration: &configuration,
+    };
+
+    Ok(askama_axum::into_response(&template))
+}
+
+/// Only queue targets are accepted, their events are delivered by the `sqs` publisher.
+pub async fn put_bucket_notification(
+    BucketPath(bucket_name): BucketPath,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        config,
+        ..
+    }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let namespace = &signature.namespace;
+
+    if !opendal_operator
+        .is_exist(&format!("{}/{}/", namespace, bucket_name))
+        .await?
+    {
+        return Err(S3Error::NoSuchBucket);
+    }
+
+    let bytes = signature.body.bytes().await?;
+    let mut configuration: NotificationConfiguration =
+        quick_xml::de::from_str(std::str::from_utf8(&bytes)?)?;
+    configuration.validate().map_err(S3Error::InvalidArgument)?;
 
-    writer = if let Some(content_type) = header_map.get(CONTENT_TYPE) {
-        if let Ok(content_type) = content_type.to_str() {
-            writer.content_type(content_type)
-        } else {
-            writer
+    if !configuration.queue_configurations.is_empty() && config.sqs.is_none() {
+        return Err(S3Error::InvalidArgument(String::from(
+            "queue notifications are not configured on this proxy",
+        )));
+    }
+    for queue in &mut configuration.queue_configurations {
+        if QueueTarget::parse(&queue.queue).is_none() {
+            return Err(S3Error::InvalidArgument(format!(
+                "{} is not a queue arn or url",
+                queue.queue
+            )));
         }
-    } else {
-        writer
-    };
+        queue
+            .id
+            .get_or_insert_with(|| Alphanumeric.sample_string(&mut rand::thread_rng(), 16));
+    }
 
-    writer.await?;
+    Namespaces::new(&metadata)
+        .set_notification_configuration(namespace, &bucket_name, &configuration)
+        .await?;
 
     Ok("OK".into_response())
 }
 
-pub async fn get_object(
-    Path((bucket_name, object_name)): Path<(String, String)>,
+/// `us-east-1` is the default region, buckets created without `LocationConstraint` are in it
+/// and S3 answers with an empty constraint for them.
+pub async fn get_bucket_location(
+    BucketPath(bucket_name): BucketPath,
     State(AppState {
-        opendal_operator, ..
+        opendal_operator,
+        metadata,
+        ..
     }): State<AppState>,
     signature: VerifiedRequest,
-) -> Result<impl IntoResponse, RouteError> {
-    let namespace = signature.namespace;
+) -> Result<impl IntoResponse, S3Error> {
+    let namespace = &signature.namespace;
 
-    if opendal_operator
-        .is_exist(&format!("{}/{}", namespace, bucket_name))
+    if !opendal_operator
+        .is_exist(&format!("{}/{}/", namespace, bucket_name))
         .await?
     {
-        return Ok((StatusCode::NOT_FOUND, "NOT FOUND").into_response());
+        return Err(S3Error::NoSuchBucket);
     }
 
-    let filepath = format!("{}/{}/{}", namespace, bucket_name, object_name);
-    let metadata = if let Ok(metadata) = opendal_operator.stat(&filepath).await {
-        metadata
-    } else {
-        // maybe actually check if the error is not found :D
-        return Ok((StatusCode::NOT_FOUND, "NOT FOUND").into_response());
+    let region = Namespaces::new(&metadata)
+        .region(namespace, &bucket_name)
+        .await?
+        .filter(|x| x != "us-east-1")
+        .unwrap_or_default();
+    let template = templates::LocationConstraintTemplate { region: &region };
+
+    Ok(askama_axum::into_response(&template))
+}
+
+pub async fn get_bucket_replication(
+    BucketPath(bucket_name): BucketPath,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        ..
+    }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let namespace = &signature.namespace;
+
+    if !opendal_operator
+        .is_exist(&format!("{}/{}/", namespace, bucket_name))
+        .await?
+    {
+        return Err(S3Error::NoSuchBucket);
+    }
+
+    let configuration = Namespaces::new(&metadata)
+        .replication_configuration(namespace, &bucket_name)
+        .await?;
+    if configuration.rules.is_empty() {
+        return Err(S3Error::ReplicationConfigurationNotFound);
+    }
+    let template = templates::ReplicationConfigurationTemplate {
+        configuration: &configuration,
     };
 
-    let reader = opendal_operator.reader(&filepath).await?;
+    Ok(askama_axum::into_response(&template))
+}
 
-    let mut response_headers = HeaderMap::new();
+/// The objects are copied by the replicator of `S3_PROXY__REPLICATION__*`.
+pub async fn put_bucket_replication(
+    BucketPath(bucket_name): BucketPath,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        config,
+        ..
+    }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let namespace = &signature.namespace;
 
-    if let Some(content_type) = metadata.content_type() {
-        response_headers.insert(CONTENT_TYPE, HeaderValue::from_str(content_type)?);
+    if !opendal_operator
+        .is_exist(&format!("{}/{}/", namespace, bucket_name))
+        .await?
+    {
+        return Err(S3Error::NoSuchBucket);
     }
 
-    response_headers.insert(
-        CONTENT_LENGTH,
-        HeaderValue::from_str(&metadata.content_length().to_string())?,
-    );
+    let bytes = signature.body.bytes().await?;
+    let configuration: ReplicationConfiguration =
+        quick_xml::de::from_str(std::str::from_utf8(&bytes)?)?;
+    configuration.validate().map_err(S3Error::InvalidArgument)?;
+
+    if config.replication.is_none() {
+        return Err(S3Error::InvalidArgument(String::from(
+            "replication is not configured on this proxy",
+        )));
+    }
 
-    Ok((response_headers, Body::from_stream(reader)).into_response())
+    Namespaces::new(&metadata)
+        .set_replication_configuration(namespace, &bucket_name, &configuration)
+        .await?;
+
+    Ok("OK".into_response())
 }
 
-pub async fn list_objects(
-    Path(bucket_name): Path<String>,
+pub async fn delete_bucket_replication(
+    BucketPath(bucket_name): BucketPath,
+    State(AppState { metadata, .. }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    Namespaces::new(&metadata)
+        .delete_replication_configuration(&signature.namespace, &bucket_name)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn get_bucket_lifecycle(
+    BucketPath(bucket_name): BucketPath,
     State(AppState {
-        opendal_operator, ..
+        opendal_operator,
+        metadata,
+        ..
     }): State<AppState>,
     signature: VerifiedRequest,
-) -> Result<impl IntoResponse, RouteError> {
+) -> Result<impl IntoResponse, S3Error> {
     let namespace = &signature.namespace;
 
-    let mut lister = opendal_operator
-        .lister_with(&format!("{}/{}/", namespace, bucket_name))
-        .recursive(true)
-        .metakey(Metakey::ContentLength | Metakey::Etag | Metakey::LastModified)
+    if !opendal_operator
+        .is_exist(&format!("{}/{}/", namespace, bucket_name))
+        .await?
+    {
+        return Err(S3Error::NoSuchBucket);
+    }
+
+    let configuration = Namespaces::new(&metadata)
+        .lifecycle_configuration(namespace, &bucket_name)
+        .await?
+        .ok_or(S3Error::NoSuchLifecycleConfiguration)?;
+    let template = templates::LifecycleConfigurationTemplate {
+        configuration: &configuration,
+    };
+
+    Ok(askama_axum::into_response(&template))
+}
+
+/// The rules are applied by the worker of `S3_PROXY__LIFECYCLE__*`.
+pub async fn put_bucket_lifecycle(
+    BucketPath(bucket_name): BucketPath,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        ..
+    }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let namespace = &signature.namespace;
+
+    if !opendal_operator
+        .is_exist(&format!("{}/{}/", namespace, bucket_name))
+        .await?
+    {
+        return Err(S3Error::NoSuchBucket);
+    }
+
+    let bytes = signature.body.bytes().await?;
+    let configuration: LifecycleConfiguration =
+        quick_xml::de::from_str(std::str::from_utf8(&bytes)?)?;
+    configuration.validate()?;
+
+    Namespaces::new(&metadata)
+        .set_lifecycle_configuration(namespace, &bucket_name, &configuration)
         .await?;
 
-    let mut objects = Vec::new();
-    while let Some(entry) = lister.next().await {
-        match entry {
-            Ok(x) => {
-                let metadata = x.metadata();
-                if metadata.is_file() {
-                    let key = x.name().to_string().into();
-                    let etag = metadata.etag().map(|y| Cow::from(y.to_string()));
-                    let last_modified = metadata
-                        .last_modified()
-                        .map(|dt| Cow::from(dt.to_rfc3339()));
-                    let size = metadata.content_length();
-                    objects.push(templates::ListObjectItem {
-                        key,
-                        etag,
-                        last_modified,
-                        size,
-                    })
-                }
-            }
-            Err(e) => {
-                tracing::error!("{}", e.to_string());
-                return Err(RouteError::new_internal_server());
-            }
-        }
+    Ok("OK".into_response())
+}
+
+pub async fn delete_bucket_lifecycle(
+    BucketPath(bucket_name): BucketPath,
+    State(AppState { metadata, .. }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    Namespaces::new(&metadata)
+        .delete_lifecycle_configuration(&signature.namespace, &bucket_name)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn get_bucket_cors(
+    BucketPath(bucket_name): BucketPath,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        ..
+    }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let namespace = &signature.namespace;
+
+    if !opendal_operator
+        .is_exist(&format!("{}/{}/", namespace, bucket_name))
+        .await?
+    {
+        return Err(S3Error::NoSuchBucket);
     }
 
-    let template = templates::ListObjectsTemplate {
-        objects,
-        is_truncated: false,
-        marker: Cow::from(""),
-        next_marker: Cow::from(""),
-        bucket_name: Cow::from(bucket_name),
-        prefix: Cow::from(""),
-        max_keys: 1000,
+    let configuration = Namespaces::new(&metadata)
+        .cors_configuration(namespace, &bucket_name)
+        .await?
+        .ok_or(S3Error::NoSuchCORSConfiguration)?;
+    let template = templates::CorsConfigurationTemplate {
+        configuration: &configuration,
     };
 
     Ok(askama_axum::into_response(&template))
 }
+
+/// Preflights and responses of the bucket use these rules instead of `S3_PROXY__CORS__*`.
+pub async fn put_bucket_cors(
+    BucketPath(bucket_name): BucketPath,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        ..
+    }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let namespace = &signature.namespace;
+
+    if !opendal_operator
+        .is_exist(&format!("{}/{}/", namespace, bucket_name))
+        .await?
+    {
+        return Err(S3Error::NoSuchBucket);
+    }
+
+    let bytes = signature.body.bytes().await?;
+    let configuration: CorsConfiguration = quick_xml::de::from_str(std::str::from_utf8(&bytes)?)?;
+    configuration.validate()?;
+
+    Namespaces::new(&metadata)
+        .set_cors_configuration(namespace, &bucket_name, &configuration)
+        .await?;
+
+    Ok("OK".into_response())
+}
+
+pub async fn delete_bucket_cors(
+    BucketPath(bucket_name): BucketPath,
+    State(AppState { metadata, .. }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    Namespaces::new(&metadata)
+        .delete_cors_configuration(&signature.namespace, &bucket_name)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn get_bucket_policy(
+    BucketPath(bucket_name): BucketPath,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        ..
+    }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let namespace = &signature.namespace;
+
+    if !opendal_operator
+        .is_exist(&format!("{}/{}/", namespace, bucket_name))
+        .await?
+    {
+        return Err(S3Error::NoSuchBucket);
+    }
+
+    let policy = Namespaces::new(&metadata)
+        .bucket_policy(namespace, &bucket_name)
+        .await?
+        .ok_or(S3Error::NoSuchBucketPolicy)?;
+
+    Ok(([(CONTENT_TYPE, "application/json")], policy).into_response())
+}
+
+/// The policy is checked after the signature of every request to the bucket, see
+/// `bucket_policy`.
+pub async fn put_bucket_policy(
+    BucketPath(bucket_name): BucketPath,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        ..
+    }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let namespace = &signature.namespace;
+
+    if !opendal_operator
+        .is_exist(&format!("{}/{}/", namespace, bucket_name))
+        .await?
+    {
+        return Err(S3Error::NoSuchBucket);
+    }
+
+    let bytes = signature.body.bytes().await?;
+    let policy = std::str::from_utf8(&bytes)?;
+    BucketPolicy::parse(policy, &bucket_name)?;
+
+    Namespaces::new(&metadata)
+        .set_bucket_policy(namespace, &bucket_name, policy)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn delete_bucket_policy(
+    BucketPath(bucket_name): BucketPath,
+    State(AppState { metadata, .. }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    Namespaces::new(&metadata)
+        .delete_bucket_policy(&signature.namespace, &bucket_name)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn get_bucket_tagging(
+    BucketPath(bucket_name): BucketPath,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        ..
+    }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let namespace = &signature.namespace;
+
+    if !opendal_operator
+        .is_exist(&format!("{}/{}/", namespace, bucket_name))
+        .await?
+    {
+        return Err(S3Error::NoSuchBucket);
+    }
+
+    let tagging = Namespaces::new(&metadata)
+        .bucket_tagging(namespace, &bucket_name)
+        .await?
+        .ok_or(S3Error::NoSuchTagSet)?;
+
+    Ok(askama_axum::into_response(&templates::TaggingTemplate {
+        tagging: &tagging,
+    }))
+}
+
+pub async fn put_bucket_tagging(
+    BucketPath(bucket_name): BucketPath,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        ..
+    }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let namespace = &signature.namespace;
+
+    if !opendal_operator
+        .is_exist(&format!("{}/{}/", namespace, bucket_name))
+        .await?
+    {
+        return Err(S3Error::NoSuchBucket);
+    }
+
+    let bytes = signature.body.bytes().await?;
+    let tagging: Tagging = quick_xml::de::from_str(std::str::from_utf8(&bytes)?)?;
+    tagging
+        .validate(tagging::MAX_BUCKET_TAGS)
+        .map_err(S3Error::InvalidTag)?;
+    Namespaces::new(&metadata)
+        .set_bucket_tagging(namespace, &bucket_name, &tagging)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn delete_bucket_tagging(
+    BucketPath(bucket_name): BucketPath,
+    State(AppState { metadata, .. }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    Namespaces::new(&metadata)
+        .delete_bucket_tagging(&signature.namespace, &bucket_name)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// A public-read bucket can be listed by anyone on its custom domain, its objects stay private
+/// unless they have a public-read ACL of their own.
+pub async fn get_bucket_acl(
+    BucketPath(bucket_name): BucketPath,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        ..
+    }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let namespace = &signature.namespace;
+
+    if !opendal_operator
+        .is_exist(&format!("{}/{}/", namespace, bucket_name))
+        .await?
+    {
+        return Err(S3Error::NoSuchBucket);
+    }
+
+    let acl = Namespaces::new(&metadata)
+        .bucket_acl(namespace, &bucket_name)
+        .await?;
+
+    Ok(askama_axum::into_response(
+        &templates::AccessControlPolicyTemplate {
+            owner: namespace,
+            acl,
+            all_users: acl::ALL_USERS,
+        },
+    ))
+}
+
+pub async fn put_bucket_acl(
+    BucketPath(bucket_name): BucketPath,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        ..
+    }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let namespace = &signature.namespace;
+
+    if !opendal_operator
+        .is_exist(&format!("{}/{}/", namespace, bucket_name))
+        .await?
+    {
+        return Err(S3Error::NoSuchBucket);
+    }
+
+    let bytes = signature.body.bytes().await?;
+    let acl = acl::from_request(&signature.headers, &bytes, namespace)?;
+    Namespaces::new(&metadata)
+        .set_bucket_acl(namespace, &bucket_name, acl)
+        .await?;
+
+    Ok("OK".into_response())
+}
+
+pub async fn get_bucket_versioning(
+    BucketPath(bucket_name): BucketPath,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        ..
+    }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let namespace = &signature.namespace;
+
+    if !opendal_operator
+        .is_exist(&format!("{}/{}/", namespace, bucket_name))
+        .await?
+    {
+        return Err(S3Error::NoSuchBucket);
+    }
+
+    let status = Namespaces::new(&metadata)
+        .bucket_versioning(namespace, &bucket_name)
+        .await?;
+
+    Ok(askama_axum::into_response(
+        &templates::VersioningConfigurationTemplate {
+            status: status.map(|x| x.as_str()),
+        },
+    ))
+}
+
+pub async fn put_bucket_versioning(
+    BucketPath(bucket_name): BucketPath,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        ..
+    }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let namespace = &signature.namespace;
+
+    if !opendal_operator
+        .is_exist(&format!("{}/{}/", namespace, bucket_name))
+        .await?
+    {
+        return Err(S3Error::NoSuchBucket);
+    }
+
+    let bytes = signature.body.bytes().await?;
+    let configuration: VersioningConfiguration =
+        quick_xml::de::from_str(std::str::from_utf8(&bytes)?)?;
+    if configuration.mfa_delete.as_deref() == Some("Enabled") {
+        return Err(S3Error::NotImplemented(String::from(
+            "MFA delete is not supported",
+        )));
+    }
+    let status = configuration
+        .status
+        .as_deref()
+        .and_then(VersioningStatus::parse)
+        .ok_or(S3Error::MalformedXML)?;
+    let namespaces = Namespaces::new(&metadata);
+    if status != VersioningStatus::Enabled
+        && namespaces
+            .object_lock_configuration(namespace, &bucket_name)
+            .await?
+            .is_some()
+    {
+        return Err(S3Error::InvalidBucketState(String::from(
+            "An Object Lock configuration is present on this bucket, so the versioning state \
+             cannot be changed.",
+        )));
+    }
+    namespaces
+        .set_bucket_versioning(namespace, &bucket_name, status)
+        .await?;
+
+    Ok("OK".into_response())
+}
+
+pub async fn get_object_lock_configuration(
+    BucketPath(bucket_name): BucketPath,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        ..
+    }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let namespace = &signature.namespace;
+
+    if !opendal_operator
+        .is_exist(&format!("{}/{}/", namespace, bucket_name))
+        .await?
+    {
+        return Err(S3Error::NoSuchBucket);
+    }
+
+    let configuration = Namespaces::new(&metadata)
+        .object_lock_configuration(namespace, &bucket_name)
+        .await?
+        .ok_or(S3Error::ObjectLockConfigurationNotFound)?;
+
+    Ok(askama_axum::into_response(
+        &templates::ObjectLockConfigurationTemplate {
+            default_retention: configuration.rule.as_ref().map(|x| &x.default_retention),
+        },
+    ))
+}
+
+/// Object lock is enabled on versioned buckets, or changes the default retention of a bucket
+/// that has it. It cannot be disabled again.
+pub async fn put_object_lock_configuration(
+    BucketPath(bucket_name): BucketPath,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        ..
+    }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let namespace = &signature.namespace;
+
+    if !opendal_operator
+        .is_exist(&format!("{}/{}/", namespace, bucket_name))
+        .await?
+    {
+        return Err(S3Error::NoSuchBucket);
+    }
+
+    let bytes = signature.body.bytes().await?;
+    let configuration: ObjectLockConfiguration =
+        quick_xml::de::from_str(std::str::from_utf8(&bytes)?)?;
+    configuration.validate()?;
+
+    let namespaces = Namespaces::new(&metadata);
+    if namespaces
+        .object_lock_configuration(namespace, &bucket_name)
+        .await?
+        .is_none()
+        && namespaces
+            .bucket_versioning(namespace, &bucket_name)
+            .await?
+            != Some(VersioningStatus::Enabled)
+    {
+        return Err(S3Error::InvalidBucketState(String::from(
+            "Versioning must be 'Enabled' on the bucket to apply a Object Lock configuration",
+        )));
+    }
+    namespaces
+        .set_object_lock_configuration(namespace, &bucket_name, &configuration)
+        .await?;
+
+    Ok("OK".into_response())
+}
+
+fn missing_object_lock() -> S3Error {
+    S3Error::InvalidRequest(String::from("Bucket is missing Object Lock Configuration"))
+}
+
+/// The lock of an object written to `filepath`. A locked object is not overwritten, and in a
+/// bucket with object lock the object gets the default retention when it asks for none.
+async fn lock_for_write(
+    metadata: &MetadataStore,
+    namespace: &str,
+    bucket_name: &str,
+    filepath: &str,
+    mut requested: ObjectLock,
+    bypass_governance: bool,
+) -> Result<ObjectLock, S3Error> {
+    let Some(configuration) = Namespaces::new(metadata)
+        .object_lock_configuration(namespace, bucket_name)
+        .await?
+    else {
+        return match requested.is_empty() {
+            true => Ok(requested),
+            false => Err(missing_object_lock()),
+        };
+    };
+
+    object_lock::check_unlocked(metadata, filepath, bypass_governance).await?;
+    configuration.apply_default(&mut requested, chrono::Utc::now().timestamp());
+    Ok(requested)
+}
+
+/// Versioned buckets keep the object a write replaces in the trash.
+async fn preserve_replaced(
+    operator: &opendal::Operator,
+    metadata: &MetadataStore,
+    namespace: &str,
+    bucket_name: &str,
+    object_name: &str,
+) -> Result<(), S3Error> {
+    let status = Namespaces::new(metadata)
+        .bucket_versioning(namespace, bucket_name)
+        .await?;
+    if status != Some(VersioningStatus::Enabled) {
+        return Ok(());
+    }
+
+    match trash::preserve(operator, namespace, bucket_name, object_name).await {
+        Err(error) if error.kind() != opendal::ErrorKind::NotFound => Err(error.into()),
+        _ => Ok(()),
+    }
+}
+
+/// `GetBucketInventoryConfiguration` with `?id=`, `ListBucketInventoryConfigurations` without.
+pub async fn get_bucket_inventory(
+    BucketPath(bucket_name): BucketPath,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        ..
+    }): State<AppState>,
+    RawQuery(query): RawQuery,
+    signature: VerifiedRequest,
+) -> Result<Response, S3Error> {
+    let namespace = &signature.namespace;
+
+    if !opendal_operator
+        .is_exist(&format!("{}/{}/", namespace, bucket_name))
+        .await?
+    {
+        return Err(S3Error::NoSuchBucket);
+    }
+
+    let configurations = Namespaces::new(&metadata)
+        .inventory_configurations(namespace, &bucket_name)
+        .await?;
+    let Some(id) = query.as_deref().and_then(|x| query_value(x, "id")) else {
+        let template = templates::ListInventoryConfigurationsTemplate {
+            configurations: &configurations,
+        };
+        return Ok(askama_axum::into_response(&template));
+    };
+
+    let configuration = configurations
+        .iter()
+        .find(|x| x.id == id)
+        .ok_or(S3Error::NoSuchConfiguration)?;
+    let template = templates::InventoryConfigurationTemplate { configuration };
+
+    Ok(askama_axum::into_response(&template))
+}
+
+/// The reports are written by the worker of `S3_PROXY__INVENTORY__INTERVAL_SECS`.
+pub async fn put_bucket_inventory(
+    BucketPath(bucket_name): BucketPath,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        ..
+    }): State<AppState>,
+    RawQuery(query): RawQuery,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let namespace = &signature.namespace;
+
+    if !opendal_operator
+        .is_exist(&format!("{}/{}/", namespace, bucket_name))
+        .await?
+    {
+        return Err(S3Error::NoSuchBucket);
+    }
+
+    let bytes = signature.body.bytes().await?;
+    let configuration: InventoryConfiguration =
+        quick_xml::de::from_str(std::str::from_utf8(&bytes)?)?;
+    configuration.validate().map_err(S3Error::InvalidArgument)?;
+    if query.as_deref().and_then(|x| query_value(x, "id")) != Some(configuration.id.as_str()) {
+        return Err(S3Error::InvalidArgument(String::from(
+            "the id parameter does not match the id of the configuration",
+        )));
+    }
+    let destination = configuration.destination_bucket().unwrap_or_default();
+    if !opendal_operator
+        .is_exist(&format!("{}/{}/", namespace, destination))
+        .await?
+    {
+        return Err(S3Error::InvalidArgument(format!(
+            "the destination bucket {} does not exist",
+            destination
+        )));
+    }
+
+    let namespaces = Namespaces::new(&metadata);
+    let mut configurations = namespaces
+        .inventory_configurations(namespace, &bucket_name)
+        .await?;
+    configurations.retain(|x| x.id != configuration.id);
+    configurations.push(configuration);
+    configurations.sort_by(|a, b| a.id.cmp(&b.id));
+    namespaces
+        .set_inventory_configurations(namespace, &bucket_name, &configurations)
+        .await?;
+
+    Ok("OK".into_response())
+}
+
+pub async fn delete_bucket_inventory(
+    BucketPath(bucket_name): BucketPath,
+    State(AppState { metadata, .. }): State<AppState>,
+    RawQuery(query): RawQuery,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let namespace = &signature.namespace;
+    let id = query
+        .as_deref()
+        .and_then(|x| query_value(x, "id"))
+        .ok_or(S3Error::NoSuchConfiguration)?;
+
+    let namespaces = Namespaces::new(&metadata);
+    let mut configurations = namespaces
+        .inventory_configurations(namespace, &bucket_name)
+        .await?;
+    let count = configurations.len();
+    configurations.retain(|x| x.id != id);
+    if configurations.len() == count {
+        return Err(S3Error::NoSuchConfiguration);
+    }
+    namespaces
+        .set_inventory_configurations(namespace, &bucket_name, &configurations)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn create_object(
+    ObjectPath(bucket_name, object_name): ObjectPath,
+    State(state): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let namespace = signature.namespace;
+
+    if !state
+        .opendal_operator
+        .is_exist(&format!("{}/{}/", namespace, bucket_name))
+        .await?
+    {
+        return Err(S3Error::NoSuchBucket);
+    }
+
+    let ttl = expiration::ttl(&signature.headers)?;
+    let checksum = checksums::requested(&signature.headers)?;
+    let requested_storage_class = storage_class::requested(&signature.headers)?;
+    let requested_acl = acl::requested(&signature.headers)?;
+    let filepath = format!("{}/{}/{}", namespace, bucket_name, object_name);
+    let lock = lock_for_write(
+        &state.metadata,
+        &namespace,
+        &bucket_name,
+        &filepath,
+        object_lock::requested(&signature.headers, chrono::Utc::now().timestamp())?,
+        object_lock::bypasses_governance(&signature.headers),
+    )
+    .await?;
+    let content_type = signature
+        .headers
+        .get(CONTENT_TYPE)
+        .and_then(|x| x.to_str().ok())
+        .map(String::from);
+
+    let object = NewObject {
+        content_type,
+        storage_class: requested_storage_class,
+        acl: requested_acl,
+        lock,
+        ttl,
+        ..NewObject::new(&namespace, &bucket_name, &object_name)
+    };
+    let (WrittenBody { etag, checksum, .. }, expires_at) =
+        finish_write(&state, object, signature.body.into_stream(), checksum).await?;
+
+    let mut response = "OK".into_response();
+    response
+        .headers_mut()
+        .insert(ETAG, HeaderValue::from_str(&etag)?);
+    if let Some((algorithm, checksum)) = checksum {
+        response
+            .headers_mut()
+            .insert(algorithm.header(), HeaderValue::from_str(&checksum)?);
+    }
+    // without the worker the object is never deleted
+    if state.config.expiration.interval_secs > 0 {
+        if let Some(expiration) = expires_at.and_then(expiration::header_value) {
+            response
+                .headers_mut()
+                .insert(expiration::EXPIRATION_HEADER.clone(), expiration);
+        }
+    }
+
+    Ok(response)
+}
+
+/// Browser uploads, a form with the policy and its signature followed by the file. Fields after
+/// the file are ignored like S3 does.
+pub async fn post_object(
+    BucketPath(bucket_name): BucketPath,
+    State(state): State<AppState>,
+    request: Request,
+) -> Result<Response, S3Error> {
+    let (parts, body) = request.into_parts();
+    // the parts are needed for the authorization after the fields are read
+    let mut form_request = Request::new(body);
+    *form_request.extensions_mut() = parts.extensions.clone();
+    if let Some(content_type) = parts.headers.get(CONTENT_TYPE) {
+        form_request
+            .headers_mut()
+            .insert(CONTENT_TYPE, content_type.clone());
+    }
+    let mut form = Multipart::from_request(form_request, &state)
+        .await
+        .map_err(|_| S3Error::MalformedPOSTRequest)?;
+
+    let mut fields = BTreeMap::new();
+    let file = loop {
+        let Some(field) = form
+            .next_field()
+            .await
+            .map_err(|_| S3Error::MalformedPOSTRequest)?
+        else {
+            return Err(S3Error::InvalidArgument(String::from(
+                "POST requires exactly one file upload per request.",
+            )));
+        };
+        let name = field.name().unwrap_or_default().to_lowercase();
+        if name == "file" {
+            break field;
+        }
+        let value = field
+            .text()
+            .await
+            .map_err(|_| S3Error::MalformedPOSTRequest)?;
+        fields.insert(name, value);
+    };
+
+    let Some(key) = fields.get("key") else {
+        return Err(S3Error::InvalidArgument(String::from(
+            "Bucket POST must contain a field named 'key'. If it is specified, please check the \
+             order of the fields.",
+        )));
+    };
+    let object_name = key.replace("${filename}", file.file_name().unwrap_or_default());
+    check_key(&object_name)?;
+    fields.insert(String::from("key"), object_name.clone());
+    fields.insert(String::from("bucket"), bucket_name.clone());
+
+    let identity = signature::verify_form(&state, &parts, &fields).await?;
+    let policy = PostPolicy::decode(&fields["policy"])?;
+    policy.check(&fields, SystemTime::now())?;
+    let requested_acl = fields
+        .get("acl")
+        .map(|x| acl::CannedAcl::parse(x))
+        .transpose()?;
+
+    let namespace = identity.namespace;
+    if !state
+        .opendal_operator
+        .is_exist(&format!("{}/{}/", namespace, bucket_name))
+        .await?
+    {
+        return Err(S3Error::NoSuchBucket);
+    }
+    if object_name.is_empty() {
+        return Err(S3Error::InvalidArgument(String::from(
+            "User key must have a length greater than 0.",
+        )));
+    }
+
+    let filepath = format!("{}/{}/{}", namespace, bucket_name, object_name);
+    let lock = lock_for_write(
+        &state.metadata,
+        &namespace,
+        &bucket_name,
+        &filepath,
+        ObjectLock::default(),
+        false,
+    )
+    .await?;
+    let content_type = fields
+        .get("content-type")
+        .map(String::as_str)
+        .or(file.content_type())
+        .map(String::from);
+
+    let object = NewObject {
+        content_type,
+        acl: requested_acl,
+        lock,
+        ..NewObject::new(&namespace, &bucket_name, &object_name)
+    };
+    let file = file.map_err(|_| S3Error::MalformedPOSTRequest);
+    let body = post_policy::limit_length(file, policy.content_length_range());
+    let (WrittenBody { etag, .. }, _) = finish_write(&state, object, body, None).await?;
+
+    let location = format!("/{}/{}", bucket_name, object_name);
+    if let Some(redirect) = fields.get("success_action_redirect") {
+        let encode = |x: &str| utf8_percent_encode(x, NON_ALPHANUMERIC).to_string();
+        let separator = if redirect.contains('?') { '&' } else { '?' };
+        let redirect = format!(
+            "{}{}bucket={}&key={}&etag={}",
+            redirect,
+            separator,
+            encode(&bucket_name),
+            encode(&object_name),
+            encode(&etag)
+        );
+        return Ok((
+            StatusCode::SEE_OTHER,
+            [(LOCATION, HeaderValue::from_str(&redirect)?)],
+        )
+            .into_response());
+    }
+
+    let mut response = match fields.get("success_action_status").map(String::as_str) {
+        Some("200") => StatusCode::OK.into_response(),
+        Some("201") => {
+            let template = templates::PostResponseTemplate {
+                location: &location,
+                bucket: &bucket_name,
+                key: &object_name,
+                etag: &etag,
+            };
+            (StatusCode::CREATED, askama_axum::into_response(&template)).into_response()
+        }
+        _ => StatusCode::NO_CONTENT.into_response(),
+    };
+    response
+        .headers_mut()
+        .insert(ETAG, HeaderValue::from_str(&etag)?);
+    response
+        .headers_mut()
+        .insert(LOCATION, HeaderValue::from_str(&location)?);
+
+    Ok(response)
+}
+
+/// What was learned about a body while it was written.
+struct WrittenBody {
+    content_length: u64,
+    etag: String,
+    checksum: Option<(ChecksumAlgorithm, String)>,
+}
+
+/// What is recorded about an object written by `PutObject`, a browser upload or
+/// `CompleteMultipartUpload`.
+struct NewObject<'a> {
+    namespace: &'a str,
+    bucket: &'a str,
+    key: &'a str,
+    content_type: Option<String>,
+    storage_class: Option<&'a str>,
+    acl: Option<acl::CannedAcl>,
+    lock: ObjectLock,
+    ttl: Option<Duration>,
+    /// the ETag of an object uploaded in parts, the md5 of the body otherwise
+    etag: Option<String>,
+    /// staged bytes the object replaces, its parts when it is assembled from them
+    replaced_staged: u64,
+}
+
+impl<'a> NewObject<'a> {
+    fn new(namespace: &'a str, bucket: &'a str, key: &'a str) -> NewObject<'a> {
+        NewObject {
+            namespace,
+            bucket,
+            key,
+            content_type: None,
+            storage_class: None,
+            acl: None,
+            lock: ObjectLock::default(),
+            ttl: None,
+            etag: None,
+            replaced_staged: 0,
+        }
+    }
+}
+
+/// Writes an object and records it: checks the quota, streams the body, keeps the object it
+/// replaces in the trash of a versioned bucket and only then commits the body, stores its
+/// metadata, invalidates the caches and calls the event hooks.
+///
+/// The replaced object is preserved once the new body is verified, a rejected body leaves
+/// neither a new object nor a copy in the trash. Returns when the object expires.
+async fn finish_write<E>(
+    state: &AppState,
+    object: NewObject<'_>,
+    body: impl Stream<Item = Result<Bytes, E>>,
+    checksum: Option<(ChecksumAlgorithm, Option<String>)>,
+) -> Result<(WrittenBody, Option<u64>), S3Error>
+where
+    S3Error: From<E>,
+{
+    let AppState {
+        opendal_operator,
+        metadata,
+        ..
+    } = state;
+    let NewObject {
+        namespace,
+        bucket,
+        key,
+        ..
+    } = object;
+    let filepath = format!("{}/{}/{}", namespace, bucket, key);
+
+    let remaining_bytes = quota::remaining_bytes_replacing(
+        opendal_operator,
+        metadata,
+        namespace,
+        bucket,
+        key,
+        object.replaced_staged,
+    )
+    .await?;
+    let mut writer = opendal_operator.writer_with(&filepath);
+    if let Some(content_type) = &object.content_type {
+        writer = writer.content_type(content_type);
+    }
+    let mut writer = writer.await?;
+    let mut written = stream_body(
+        &mut writer,
+        body,
+        &state.buffer_pool,
+        remaining_bytes,
+        checksum,
+    )
+    .await?;
+    if let Err(error) = preserve_replaced(opendal_operator, metadata, namespace, bucket, key).await
+    {
+        writer.abort().await?;
+        return Err(error);
+    }
+    writer.close().await?;
+
+    if let Some(etag) = object.etag {
+        written.etag = etag;
+    }
+    etags::store(metadata, &filepath, &written.etag).await?;
+    match &written.checksum {
+        Some((algorithm, checksum)) => {
+            checksums::store(metadata, &filepath, *algorithm, checksum).await?
+        }
+        None => checksums::remove(metadata, &filepath).await?,
+    }
+    // tags belong to the upload, an overwritten object loses them like in S3
+    tagging::remove_object_tags(metadata, &filepath).await?;
+    storage_class::store(metadata, &filepath, object.storage_class).await?;
+    acl::store(metadata, &filepath, object.acl).await?;
+    object_lock::store(metadata, &filepath, &object.lock).await?;
+    state.etag_cache.invalidate(&filepath);
+    state.read_cache.invalidate(&filepath);
+    state.listing_cache.invalidate(namespace, bucket);
+    let expires_at = expiration::schedule(metadata, &filepath, object.ttl).await?;
+
+    state
+        .event_hooks
+        .put(&ObjectEvent {
+            namespace: namespace.to_string(),
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            metadata: ObjectMetadata {
+                content_type: object.content_type,
+                content_length: written.content_length,
+                etag: Some(written.etag.clone()),
+            },
+        })
+        .await;
+
+    Ok((written, expires_at))
+}
+
+/// [`stream_body`] and closes the writer.
+async fn write_body<E>(
+    mut writer: opendal::Writer,
+    body: impl Stream<Item = Result<Bytes, E>>,
+    buffer_pool: &Arc<BufferPool>,
+    remaining_bytes: Option<u64>,
+    checksum: Option<(ChecksumAlgorithm, Option<String>)>,
+) -> Result<WrittenBody, S3Error>
+where
+    S3Error: From<E>,
+{
+    let written = stream_body(&mut writer, body, buffer_pool, remaining_bytes, checksum).await?;
+    writer.close().await?;
+    Ok(written)
+}
+
+/// Streams the body into the writer, computing the ETag and the checksum the client asked for
+/// on the way. The writer is left open for the caller to close.
+///
+/// Nothing is committed before close, so a tampered body, a checksum mismatch or a body over
+/// `remaining_bytes` aborts the writer and never ends up in the backend.
+async fn stream_body<E>(
+    writer: &mut opendal::Writer,
+    body: impl Stream<Item = Result<Bytes, E>>,
+    buffer_pool: &Arc<BufferPool>,
+    remaining_bytes: Option<u64>,
+    checksum: Option<(ChecksumAlgorithm, Option<String>)>,
+) -> Result<WrittenBody, S3Error>
+where
+    S3Error: From<E>,
+{
+    let mut body = std::pin::pin!(body);
+    // the body arrives in small chunks, these are gathered so the backend gets larger writes
+    let mut buffer = buffer_pool.get();
+    let mut content_length = 0;
+    let mut hasher = Md5::new();
+    let mut checksum_hasher = checksum.as_ref().map(|(algorithm, _)| algorithm.hasher());
+
+    while let Some(chunk) = body.next().await {
+        match chunk {
+            Ok(chunk) => {
+                content_length += chunk.len() as u64;
+                if remaining_bytes.is_some_and(|x| content_length > x) {
+                    writer.abort().await?;
+                    return Err(S3Error::QuotaExceeded);
+                }
+                hasher.update(&chunk);
+                if let Some(checksum_hasher) = &mut checksum_hasher {
+                    checksum_hasher.update(&chunk);
+                }
+                buffer.extend_from_slice(&chunk);
+                if buffer.is_full() {
+                    writer.write(buffer.take()).await?;
+                }
+            }
+            Err(error) => {
+                writer.abort().await?;
+                return Err(error.into());
+            }
+        }
+    }
+
+    if !buffer.is_empty() {
+        writer.write(buffer.take()).await?;
+    }
+    let checksum = match (checksum, checksum_hasher) {
+        (Some((algorithm, expected)), Some(checksum_hasher)) => {
+            let actual = checksum_hasher.finalize();
+            if expected.is_some_and(|x| x != actual) {
+                writer.abort().await?;
+                return Err(S3Error::BadDigest(algorithm.as_str().to_string()));
+            }
+            Some((algorithm, actual))
+        }
+        _ => None,
+    };
+
+    Ok(WrittenBody {
+        content_length,
+        etag: etags::md5_etag(hasher),
+        checksum,
+    })
+}
+
+pub async fn delete_object(
+    ObjectPath(bucket_name, object_name): ObjectPath,
+    State(state): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let bypass_governance = object_lock::bypasses_governance(&signature.headers);
+    if !remove_object(
+        &state,
+        &signature.namespace,
+        &bucket_name,
+        &object_name,
+        bypass_governance,
+    )
+    .await?
+    {
+        return Err(S3Error::NoSuchKey);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `DeleteObjects` accepts up to this many keys per request, like S3.
+const MAX_DELETE_KEYS: usize = 1000;
+
+/// `DeleteObjects`, keys that do not exist are reported as deleted like S3 does.
+pub async fn delete_objects(
+    BucketPath(bucket_name): BucketPath,
+    State(state): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let namespace = &signature.namespace;
+
+    if !state
+        .opendal_operator
+        .is_exist(&format!("{}/{}/", namespace, bucket_name))
+        .await?
+    {
+        return Err(S3Error::NoSuchBucket);
+    }
+
+    let bytes = signature.body.bytes().await?;
+    let request: templates::DeleteObjectsRequest =
+        quick_xml::de::from_str(std::str::from_utf8(&bytes)?)?;
+    if request.objects.is_empty() || request.objects.len() > MAX_DELETE_KEYS {
+        return Err(S3Error::MalformedXML);
+    }
+
+    let bypass_governance = object_lock::bypasses_governance(&signature.headers);
+    let mut deleted = Vec::new();
+    let mut errors = Vec::new();
+    for object in request.objects {
+        match remove_object(
+            &state,
+            namespace,
+            &bucket_name,
+            &object.key,
+            bypass_governance,
+        )
+        .await
+        {
+            Ok(_) => deleted.push(object.key),
+            Err(error) => {
+                tracing::warn!(
+                    "unable to delete {}/{} in a batch: {}",
+                    bucket_name,
+                    object.key,
+                    error
+                );
+                errors.push(templates::DeleteError {
+                    key: object.key,
+                    code: error.code(),
+                    message: error.message(),
+                })
+            }
+        }
+    }
+
+    let template = templates::DeleteResultTemplate {
+        // quiet mode only reports the failures
+        deleted: if request.quiet { &[] } else { &deleted },
+        errors: &errors,
+    };
+
+    Ok(askama_axum::into_response(&template))
+}
+
+/// Deletes an object or moves it into the trash, `false` when it does not exist. Locked objects
+/// are not deleted.
+pub(crate) async fn remove_object(
+    state: &AppState,
+    namespace: &str,
+    bucket_name: &str,
+    object_name: &str,
+    bypass_governance: bool,
+) -> Result<bool, S3Error> {
+    check_key(object_name)?;
+    let filepath = format!("{}/{}/{}", namespace, bucket_name, object_name);
+
+    if !state.opendal_operator.is_exist(&filepath).await? {
+        return Ok(false);
+    }
+
+    let namespaces = Namespaces::new(&state.metadata);
+    // only buckets with object lock have locked objects
+    if namespaces
+        .object_lock_configuration(namespace, bucket_name)
+        .await?
+        .is_some()
+    {
+        object_lock::check_unlocked(&state.metadata, &filepath, bypass_governance).await?;
+    }
+    let versioning = namespaces.bucket_versioning(namespace, bucket_name).await?;
+    if state.config.trash.enabled || versioning == Some(VersioningStatus::Enabled) {
+        trash::discard(&state.opendal_operator, namespace, bucket_name, object_name).await?;
+    } else {
+        state.opendal_operator.delete(&filepath).await?;
+    }
+    etags::remove(&state.metadata, &filepath).await?;
+    checksums::remove(&state.metadata, &filepath).await?;
+    tagging::remove_object_tags(&state.metadata, &filepath).await?;
+    storage_class::remove(&state.metadata, &filepath).await?;
+    acl::remove(&state.metadata, &filepath).await?;
+    object_lock::remove(&state.metadata, &filepath).await?;
+    state.etag_cache.invalidate(&filepath);
+    state.read_cache.invalidate(&filepath);
+    state.listing_cache.invalidate(namespace, bucket_name);
+    expiration::schedule(&state.metadata, &filepath, None).await?;
+
+    state
+        .event_hooks
+        .delete(&ObjectEvent {
+            namespace: namespace.to_string(),
+            bucket: bucket_name.to_string(),
+            key: object_name.to_string(),
+            metadata: ObjectMetadata::default(),
+        })
+        .await;
+
+    Ok(true)
+}
+
+pub async fn get_object(
+    ObjectPath(bucket_name, object_name): ObjectPath,
+    State(AppState {
+        opendal_operator,
+        etag_cache,
+        coalescer,
+        event_hooks,
+        metadata: metadata_store,
+        config,
+        transforms,
+        read_cache,
+        ..
+    }): State<AppState>,
+    RawQuery(query): RawQuery,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let namespace = signature.namespace;
+    let filepath = format!("{}/{}/{}", namespace, bucket_name, object_name);
+
+    let transform_request = TransformRequest {
+        namespace: &namespace,
+        bucket: &bucket_name,
+        key: &object_name,
+        query: query.as_deref().unwrap_or_default(),
+        headers: &signature.headers,
+    };
+    let transforms = transforms.matching(&transform_request);
+    let overrides = response_overrides::requested(query.as_deref().unwrap_or_default())?;
+
+    // answer revalidations of recently seen objects without going to the backend, the
+    // validators of transformed objects are not sent so those are never revalidated
+    if let Some(validators) = etag_cache.get(&filepath).filter(|_| transforms.is_empty()) {
+        let precondition = etag_cache::preconditions(
+            &signature.headers,
+            Some(&validators.etag),
+            validators.last_modified_date(),
+        );
+        if precondition == Precondition::NotModified {
+            return Ok(not_modified(&validators)?.into_response());
+        }
+    }
+
+    let metadata = match read_cache.fresh(&filepath) {
+        Some(metadata) => metadata,
+        None => {
+            if !opendal_operator
+                .is_exist(&format!("{}/{}/", namespace, bucket_name))
+                .await?
+            {
+                return Err(S3Error::NoSuchBucket);
+            }
+
+            let mut metadata = opendal_operator.stat(&filepath).await?;
+            etags::fill(&metadata_store, &filepath, &mut metadata).await?;
+            metadata
+        }
+    };
+
+    let validators = metadata.etag().map(|etag| Validators {
+        etag: etag.to_string(),
+        last_modified: metadata.last_modified().map(http_date),
+    });
+    if let Some(validators) = &validators {
+        etag_cache.insert(&filepath, validators.clone());
+    }
+    if transforms.is_empty() {
+        match etag_cache::preconditions(
+            &signature.headers,
+            metadata.etag(),
+            metadata.last_modified(),
+        ) {
+            Precondition::Proceed => (),
+            Precondition::NotModified => {
+                return Ok(match &validators {
+                    Some(validators) => not_modified(validators)?.into_response(),
+                    None => StatusCode::NOT_MODIFIED.into_response(),
+                })
+            }
+            Precondition::Failed => return Err(S3Error::PreconditionFailed),
+        }
+    }
+
+    // transforms change the length, so ranges are only served for untransformed objects
+    let range = match transforms.is_empty() {
+        true => range::requested(&signature.headers, metadata.content_length())?,
+        false => None,
+    };
+
+    let mut response_headers = HeaderMap::new();
+
+    if let Some(validators) = &validators {
+        insert_validators(&mut response_headers, validators)?;
+    }
+    if transforms.is_empty() {
+        response_headers.insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    }
+
+    let content_type = content_type(metadata.content_type(), &object_name);
+    response_headers.insert(CONTENT_TYPE, HeaderValue::from_str(&content_type)?);
+
+    response_headers.insert(
+        CONTENT_LENGTH,
+        HeaderValue::from_str(&metadata.content_length().to_string())?,
+    );
+
+    if config.replication.is_some() {
+        let status =
+            replication::status(&metadata_store, &namespace, &bucket_name, &object_name).await?;
+        if let Some(status) = status {
+            response_headers.insert(REPLICATION_STATUS, HeaderValue::from_str(&status)?);
+        }
+    }
+
+    if config.expiration.interval_secs > 0 {
+        let expires_at = expiration::expires_at(&metadata_store, &filepath).await?;
+        if let Some(expiration) = expires_at.and_then(expiration::header_value) {
+            response_headers.insert(expiration::EXPIRATION_HEADER.clone(), expiration);
+        }
+    }
+
+    // like S3 the header is left out for STANDARD objects
+    if let Some(class) = storage_class::stored(&metadata_store, &filepath).await? {
+        response_headers.insert(
+            storage_class::STORAGE_CLASS_HEADER.clone(),
+            HeaderValue::from_str(&class)?,
+        );
+    }
+
+    for (name, value) in object_lock::stored(&metadata_store, &filepath)
+        .await?
+        .headers()
+    {
+        response_headers.insert(name, value);
+    }
+
+    let tag_count = tagging::object_tags(&metadata_store, &filepath)
+        .await?
+        .tags()
+        .len();
+    if tag_count > 0 {
+        response_headers.insert(
+            tagging::TAGGING_COUNT_HEADER.clone(),
+            HeaderValue::from(tag_count),
+        );
+    }
+
+    // the checksum covers the whole object, like S3 it is left out of partial responses
+    if range.is_none() && transforms.is_empty() && checksums::wanted(&signature.headers) {
+        if let Some((name, value)) = checksums::header(&metadata_store, &filepath).await? {
+            response_headers.insert(name, value);
+        }
+    }
+
+    response_overrides::apply(&mut response_headers, &overrides);
+
+    if !event_hooks.is_empty() {
+        event_hooks
+            .get(&ObjectEvent {
+                namespace: namespace.clone(),
+                bucket: bucket_name.clone(),
+                key: object_name.clone(),
+                metadata: ObjectMetadata {
+                    content_type: metadata.content_type().map(String::from),
+                    content_length: metadata.content_length(),
+                    etag: metadata.etag().map(String::from),
+                },
+            })
+            .await;
+    }
+
+    // partial reads go straight to the backend, the cache and the integrity check need the
+    // whole object
+    if let Some(range) = range {
+        response_headers.insert(
+            CONTENT_RANGE,
+            HeaderValue::from_str(&range.content_range(metadata.content_length()))?,
+        );
+        response_headers.insert(
+            CONTENT_LENGTH,
+            HeaderValue::from_str(&range.content_length().to_string())?,
+        );
+        let body = opendal_operator
+            .reader_with(&filepath)
+            .range(range.start..=range.end)
+            .await?;
+        return Ok((
+            StatusCode::PARTIAL_CONTENT,
+            response_headers,
+            Body::from_stream(body),
+        )
+            .into_response());
+    }
+
+    let body = match read_cache.open(&filepath, &metadata).await {
+        Some(body) => body,
+        // without an etag there is no way to tell if concurrent requests want the same version
+        None => match &validators {
+            Some(validators) if coalescer.should_coalesce(metadata.content_length()) => {
+                let key = format!("{}@{}", filepath, validators.etag);
+                let reader = opendal_operator.clone();
+                let path = filepath.clone();
+                let body = coalescer.read(key, move || {
+                    futures::stream::once(async move { reader.reader(&path).await }).try_flatten()
+                });
+                read_cache.fill(&filepath, &metadata, body.boxed())
+            }
+            _ => {
+                let body = opendal_operator.reader(&filepath).await?;
+                read_cache.fill(&filepath, &metadata, body.boxed())
+            }
+        },
+    };
+    let body = match metadata.etag().and_then(integrity::md5_etag) {
+        Some(expected) if config.verify_integrity => {
+            let path = filepath.clone();
+            integrity::verify(body, expected, move || {
+                tracing::error!("{} does not match its etag, the response is aborted", path);
+                // the cache was filled from the same stream
+                read_cache.invalidate(&path);
+            })
+        }
+        _ => body,
+    };
+    let body = Body::from_stream(body);
+
+    if transforms.is_empty() {
+        return Ok((response_headers, body).into_response());
+    }
+
+    let object = transforms::apply(
+        &transforms,
+        &transform_request,
+        TransformedObject {
+            content_type: Some(content_type),
+            body: body.into_data_stream().map_err(BoxError::from).boxed(),
+        },
+    )
+    .await?;
+    response_headers.remove(ETAG);
+    response_headers.remove(CONTENT_LENGTH);
+    match object.content_type {
+        Some(content_type) => {
+            response_headers.insert(CONTENT_TYPE, HeaderValue::from_str(&content_type)?);
+        }
+        None => {
+            response_headers.remove(CONTENT_TYPE);
+        }
+    }
+    response_overrides::apply(&mut response_headers, &overrides);
+
+    Ok((response_headers, Body::from_stream(object.body)).into_response())
+}
+
+/// Lists the attributes `GetObjectAttributes` returns, as sent in `x-amz-object-attributes`.
+const OBJECT_ATTRIBUTES_HEADER: HeaderName = HeaderName::from_static("x-amz-object-attributes");
+
+/// `GetObjectAttributes`, the metadata of an object without its body. Completing a multipart
+/// upload joins its parts into one object, so `ObjectParts` is accepted but not returned.
+pub async fn get_object_attributes(
+    ObjectPath(bucket_name, object_name): ObjectPath,
+    State(AppState {
+        opendal_operator,
+        metadata: metadata_store,
+        ..
+    }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let namespace = &signature.namespace;
+
+    if !opendal_operator
+        .is_exist(&format!("{}/{}/", namespace, bucket_name))
+        .await?
+    {
+        return Err(S3Error::NoSuchBucket);
+    }
+
+    let attributes: Vec<_> = signature
+        .headers
+        .get_all(OBJECT_ATTRIBUTES_HEADER)
+        .iter()
+        .filter_map(|x| x.to_str().ok())
+        .flat_map(|x| x.split(','))
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .collect();
+    if attributes.is_empty() {
+        return Err(S3Error::InvalidArgument(format!(
+            "{} is required",
+            OBJECT_ATTRIBUTES_HEADER
+        )));
+    }
+    if let Some(unknown) = attributes.iter().find(|x| {
+        ![
+            "ETag",
+            "Checksum",
+            "ObjectParts",
+            "StorageClass",
+            "ObjectSize",
+        ]
+        .contains(x)
+    }) {
+        return Err(S3Error::InvalidArgument(format!(
+            "Invalid attribute name specified: {}",
+            unknown
+        )));
+    }
+    let wants = |name| attributes.contains(&name);
+
+    let filepath = format!("{}/{}/{}", namespace, bucket_name, object_name);
+    let mut metadata = opendal_operator.stat(&filepath).await?;
+    if !metadata.is_file() {
+        return Err(S3Error::NoSuchKey);
+    }
+    etags::fill(&metadata_store, &filepath, &mut metadata).await?;
+
+    let checksum = match wants("Checksum") {
+        true => checksums::stored(&metadata_store, &filepath).await?,
+        false => None,
+    };
+    let stored_class = match wants("StorageClass") {
+        true => Some(
+            storage_class::stored(&metadata_store, &filepath)
+                .await?
+                .unwrap_or_else(|| String::from(storage_class::STANDARD)),
+        ),
+        false => None,
+    };
+
+    let mut response_headers = HeaderMap::new();
+    if let Some(last_modified) = metadata.last_modified() {
+        response_headers.insert(
+            LAST_MODIFIED,
+            HeaderValue::from_str(&http_date(last_modified))?,
+        );
+    }
+
+    let template = templates::ObjectAttributesTemplate {
+        etag: metadata
+            .etag()
+            .filter(|_| wants("ETag"))
+            .map(|x| x.trim_matches('"')),
+        checksum: checksum
+            .as_ref()
+            .map(|(algorithm, checksum)| (algorithm.element(), checksum.as_str())),
+        storage_class: stored_class.as_deref(),
+        object_size: wants("ObjectSize").then(|| metadata.content_length()),
+    };
+
+    Ok((response_headers, askama_axum::into_response(&template)))
+}
+
+pub async fn create_multipart_upload(
+    ObjectPath(bucket_name, object_name): ObjectPath,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        ..
+    }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let namespace = &signature.namespace;
+
+    if !opendal_operator
+        .is_exist(&format!("{}/{}/", namespace, bucket_name))
+        .await?
+    {
+        return Err(S3Error::NoSuchBucket);
+    }
+
+    let content_type = signature
+        .headers
+        .get(CONTENT_TYPE)
+        .and_then(|x| x.to_str().ok())
+        .map(String::from);
+    let lock = object_lock::requested(&signature.headers, chrono::Utc::now().timestamp())?;
+    let acl = acl::requested(&signature.headers)?;
+    let storage_class = storage_class::requested(&signature.headers)?.map(String::from);
+    if !lock.is_empty()
+        && Namespaces::new(&metadata)
+            .object_lock_configuration(namespace, &bucket_name)
+            .await?
+            .is_none()
+    {
+        return Err(missing_object_lock());
+    }
+    let upload = multipart::PendingUpload {
+        content_type,
+        lock,
+        acl,
+        storage_class,
+        ..multipart::PendingUpload::new(&bucket_name, &object_name)
+    };
+    let upload_id = multipart::initiate(&metadata, namespace, &upload).await?;
+
+    Ok(askama_axum::into_response(
+        &templates::InitiateMultipartUploadTemplate {
+            bucket: &bucket_name,
+            key: &object_name,
+            upload_id: &upload_id,
+        },
+    ))
+}
+
+/// `UploadPart`, the part is staged next to the buckets until the upload is completed.
+pub async fn upload_part(
+    ObjectPath(bucket_name, object_name): ObjectPath,
+    RawQuery(query): RawQuery,
+    State(AppState {
+        opendal_operator,
+        buffer_pool,
+        metadata,
+        ..
+    }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let namespace = &signature.namespace;
+    let query = query.unwrap_or_default();
+
+    let upload_id = decoded_query_value(&query, "uploadId").unwrap_or_default();
+    let part_number = query_value(&query, "partNumber")
+        .and_then(multipart::part_number)
+        .ok_or_else(|| {
+            S3Error::InvalidArgument(format!(
+                "Part number must be an integer between 1 and {}, inclusive",
+                multipart::MAX_PART_NUMBER
+            ))
+        })?;
+    match multipart::pending(&metadata, namespace, &upload_id).await? {
+        Some(upload) if upload.bucket == bucket_name && upload.key == object_name => (),
+        _ => return Err(S3Error::NoSuchUpload),
+    }
+
+    let part_path = multipart::part_path(namespace, &upload_id, part_number);
+    // a part uploaded again replaces the earlier one, which is not counted twice
+    let replaced = match opendal_operator.stat(&part_path).await {
+        Ok(part) => part.content_length(),
+        Err(error) if error.kind() == opendal::ErrorKind::NotFound => 0,
+        Err(error) => return Err(error.into()),
+    };
+    let remaining_bytes = quota::remaining_bytes_replacing(
+        &opendal_operator,
+        &metadata,
+        namespace,
+        &bucket_name,
+        &object_name,
+        replaced,
+    )
+    .await?;
+    let checksum = checksums::requested(&signature.headers)?;
+    let copy_source = multipart::copy_source(&signature.headers)?;
+    let writer = opendal_operator.writer(&part_path).await?;
+
+    let WrittenBody { etag, checksum, .. } = match &copy_source {
+        // `UploadPartCopy`
+        Some((source_bucket, source_key)) => {
+            let source = format!("{}/{}/{}", namespace, source_bucket, source_key);
+            let object = opendal_operator.stat(&source).await?;
+            if !object.is_file() {
+                return Err(S3Error::NoSuchKey);
+            }
+            let mut reader = opendal_operator.reader_with(&source);
+            if let Some(value) = signature.headers.get(&multipart::COPY_SOURCE_RANGE_HEADER) {
+                let range = range::copy_source_range(
+                    value.to_str().unwrap_or_default(),
+                    object.content_length(),
+                )?;
+                reader = reader.range(range.start..=range.end);
+            }
+            let body = reader.await?.map_err(S3Error::internal);
+            write_body(writer, body, &buffer_pool, remaining_bytes, None).await?
+        }
+        None => {
+            write_body(
+                writer,
+                signature.body.into_stream(),
+                &buffer_pool,
+                remaining_bytes,
+                checksum,
+            )
+            .await?
+        }
+    };
+    // a part uploaded again replaces the earlier one, its etag with it
+    etags::store(&metadata, &part_path, &etag).await?;
+
+    if copy_source.is_some() {
+        let template = templates::CopyPartResultTemplate {
+            etag: &etag,
+            last_modified: &chrono::Utc::now().to_rfc3339(),
+        };
+        return Ok(askama_axum::into_response(&template));
+    }
+
+    let mut response = "OK".into_response();
+    response
+        .headers_mut()
+        .insert(ETAG, HeaderValue::from_str(&etag)?);
+    if let Some((algorithm, checksum)) = checksum {
+        response
+            .headers_mut()
+            .insert(algorithm.header(), HeaderValue::from_str(&checksum)?);
+    }
+
+    Ok(response)
+}
+
+/// `CompleteMultipartUpload`, streams the staged parts into the object in the order the body
+/// lists them.
+///
+/// The parts are staged as objects of their own since an opendal writer, and with it the
+/// multipart upload of the backend, cannot outlive the request that opened it, and opendal
+/// cannot compose objects. So completing reads and writes the parts once more. The assembled
+/// object is checked against the quota before it is written, its staged parts are not counted
+/// twice.
+pub async fn complete_multipart_upload(
+    ObjectPath(bucket_name, object_name): ObjectPath,
+    RawQuery(query): RawQuery,
+    State(state): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let AppState {
+        opendal_operator,
+        metadata,
+        ..
+    } = &state;
+    let namespace = signature.namespace;
+    let upload_id = query
+        .as_deref()
+        .and_then(|x| decoded_query_value(x, "uploadId"))
+        .unwrap_or_default();
+    let upload = match multipart::pending(metadata, &namespace, &upload_id).await? {
+        Some(upload) if upload.bucket == bucket_name && upload.key == object_name => upload,
+        _ => return Err(S3Error::NoSuchUpload),
+    };
+
+    let bytes = signature.body.bytes().await?;
+    let request: multipart::CompleteMultipartUpload =
+        quick_xml::de::from_str(std::str::from_utf8(&bytes)?)?;
+    if request.parts.is_empty() {
+        return Err(S3Error::MalformedXML);
+    }
+
+    let mut part_paths = Vec::with_capacity(request.parts.len());
+    let mut previous = 0;
+    for part in &request.parts {
+        if part.part_number <= previous {
+            return Err(S3Error::InvalidPartOrder);
+        }
+        previous = part.part_number;
+
+        let part_path = multipart::part_path(&namespace, &upload_id, part.part_number);
+        let stored = metadata
+            .get(&format!("{}{}", etags::ETAG_PREFIX, part_path))
+            .await?;
+        if stored.as_deref().map(|x| x.trim_matches('"')) != Some(part.etag.trim_matches('"')) {
+            return Err(S3Error::InvalidPart);
+        }
+        part_paths.push(part_path);
+    }
+    let etag = multipart::composite_etag(request.parts.iter().map(|x| x.etag.as_str()))
+        .ok_or(S3Error::InvalidPart)?;
+
+    for (index, part_path) in part_paths.iter().enumerate() {
+        let part_length = opendal_operator.stat(part_path).await?.content_length();
+        if index + 1 < part_paths.len() && part_length < multipart::MIN_PART_SIZE {
+            return Err(S3Error::EntityTooSmall);
+        }
+    }
+    let staging_dir = multipart::staging_dir(&namespace, &upload_id);
+    let (staged, _) = crate::metrics::storage_usage(opendal_operator, &staging_dir).await?;
+
+    let filepath = format!("{}/{}/{}", namespace, bucket_name, object_name);
+    let lock = lock_for_write(
+        metadata,
+        &namespace,
+        &bucket_name,
+        &filepath,
+        upload.lock.clone(),
+        object_lock::bypasses_governance(&signature.headers),
+    )
+    .await?;
+    let object = NewObject {
+        content_type: upload.content_type.clone(),
+        storage_class: upload.storage_class.as_deref(),
+        acl: upload.acl,
+        lock,
+        etag: Some(etag.clone()),
+        replaced_staged: staged,
+        ..NewObject::new(&namespace, &bucket_name, &object_name)
+    };
+    let operator = opendal_operator.clone();
+    let body = stream::iter(part_paths)
+        .then(move |part_path| {
+            let operator = operator.clone();
+            async move { operator.reader(&part_path).await }
+        })
+        .map_ok(|reader| reader.map_err(S3Error::internal))
+        .map_err(S3Error::from)
+        .try_flatten();
+    finish_write(&state, object, body, None).await?;
+    multipart::remove(opendal_operator, metadata, &namespace, &upload_id).await?;
+
+    let template = templates::CompleteMultipartUploadTemplate {
+        location: &format!("/{}/{}", bucket_name, object_name),
+        bucket: &bucket_name,
+        key: &object_name,
+        etag: &etag,
+    };
+
+    Ok(askama_axum::into_response(&template))
+}
+
+/// The path of an existing object, tags can only be set on objects that exist.
+async fn existing_object(
+    operator: &opendal::Operator,
+    namespace: &str,
+    bucket_name: &str,
+    object_name: &str,
+) -> Result<String, S3Error> {
+    let filepath = format!("{}/{}/{}", namespace, bucket_name, object_name);
+    match operator.stat(&filepath).await {
+        Ok(metadata) if metadata.is_file() => Ok(filepath),
+        Ok(_) => Err(S3Error::NoSuchKey),
+        Err(error) => Err(error.into()),
+    }
+}
+
+pub async fn get_object_tagging(
+    ObjectPath(bucket_name, object_name): ObjectPath,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        ..
+    }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let filepath = existing_object(
+        &opendal_operator,
+        &signature.namespace,
+        &bucket_name,
+        &object_name,
+    )
+    .await?;
+    let tagging = tagging::object_tags(&metadata, &filepath).await?;
+
+    Ok(askama_axum::into_response(&templates::TaggingTemplate {
+        tagging: &tagging,
+    }))
+}
+
+pub async fn put_object_tagging(
+    ObjectPath(bucket_name, object_name): ObjectPath,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        ..
+    }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let filepath = existing_object(
+        &opendal_operator,
+        &signature.namespace,
+        &bucket_name,
+        &object_name,
+    )
+    .await?;
+
+    let bytes = signature.body.bytes().await?;
+    let tagging: Tagging = quick_xml::de::from_str(std::str::from_utf8(&bytes)?)?;
+    tagging
+        .validate(tagging::MAX_OBJECT_TAGS)
+        .map_err(S3Error::InvalidTag)?;
+    tagging::set_object_tags(&metadata, &filepath, &tagging).await?;
+
+    Ok("OK".into_response())
+}
+
+/// `SelectObjectContent`, runs a SQL query over a CSV or JSON object and streams the matching
+/// records back as S3 event stream messages.
+pub async fn select_object_content(
+    ObjectPath(bucket_name, object_name): ObjectPath,
+    State(AppState {
+        opendal_operator, ..
+    }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let filepath = existing_object(
+        &opendal_operator,
+        &signature.namespace,
+        &bucket_name,
+        &object_name,
+    )
+    .await?;
+
+    let bytes = signature.body.bytes().await?;
+    let request: SelectRequest = quick_xml::de::from_str(std::str::from_utf8(&bytes)?)?;
+    let selection = Selection::new(&request)?;
+
+    let object = opendal_operator.reader(&filepath).await?;
+    let body = Body::from_stream(selection.run(object));
+
+    Ok((
+        [(
+            CONTENT_TYPE,
+            HeaderValue::from_static(select::EVENT_STREAM_CONTENT_TYPE),
+        )],
+        body,
+    ))
+}
+
+pub async fn delete_object_tagging(
+    ObjectPath(bucket_name, object_name): ObjectPath,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        ..
+    }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let filepath = existing_object(
+        &opendal_operator,
+        &signature.namespace,
+        &bucket_name,
+        &object_name,
+    )
+    .await?;
+    tagging::remove_object_tags(&metadata, &filepath).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn get_object_acl(
+    ObjectPath(bucket_name, object_name): ObjectPath,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        ..
+    }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let filepath = existing_object(
+        &opendal_operator,
+        &signature.namespace,
+        &bucket_name,
+        &object_name,
+    )
+    .await?;
+    let acl = acl::stored(&metadata, &filepath).await?;
+
+    Ok(askama_axum::into_response(
+        &templates::AccessControlPolicyTemplate {
+            owner: &signature.namespace,
+            acl,
+            all_users: acl::ALL_USERS,
+        },
+    ))
+}
+
+pub async fn put_object_acl(
+    ObjectPath(bucket_name, object_name): ObjectPath,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        ..
+    }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let filepath = existing_object(
+        &opendal_operator,
+        &signature.namespace,
+        &bucket_name,
+        &object_name,
+    )
+    .await?;
+
+    let bytes = signature.body.bytes().await?;
+    let acl = acl::from_request(&signature.headers, &bytes, &signature.namespace)?;
+    acl::store(&metadata, &filepath, Some(acl)).await?;
+
+    Ok("OK".into_response())
+}
+
+/// Retentions and legal holds are only set on objects in buckets with object lock.
+async fn require_object_lock(
+    metadata: &MetadataStore,
+    namespace: &str,
+    bucket_name: &str,
+) -> Result<(), S3Error> {
+    match Namespaces::new(metadata)
+        .object_lock_configuration(namespace, bucket_name)
+        .await?
+    {
+        Some(_) => Ok(()),
+        None => Err(missing_object_lock()),
+    }
+}
+
+pub async fn get_object_retention(
+    ObjectPath(bucket_name, object_name): ObjectPath,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        ..
+    }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let namespace = &signature.namespace;
+    let filepath =
+        existing_object(&opendal_operator, namespace, &bucket_name, &object_name).await?;
+    require_object_lock(&metadata, namespace, &bucket_name).await?;
+
+    let retention = object_lock::stored(&metadata, &filepath)
+        .await?
+        .retention
+        .ok_or(S3Error::NoSuchObjectLockConfiguration)?;
+
+    Ok(askama_axum::into_response(&templates::RetentionTemplate {
+        mode: retention.mode.as_str(),
+        retain_until_date: retention.retain_until_date(),
+    }))
+}
+
+/// Replaces the retention of an object, an active retention is only made stricter unless a
+/// `GOVERNANCE` retention is bypassed.
+pub async fn put_object_retention(
+    ObjectPath(bucket_name, object_name): ObjectPath,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        ..
+    }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let namespace = &signature.namespace;
+    let filepath =
+        existing_object(&opendal_operator, namespace, &bucket_name, &object_name).await?;
+    require_object_lock(&metadata, namespace, &bucket_name).await?;
+
+    let bytes = signature.body.bytes().await?;
+    let document: RetentionDocument = quick_xml::de::from_str(std::str::from_utf8(&bytes)?)?;
+    let now = chrono::Utc::now().timestamp();
+    let retention = document.retention(now)?;
+
+    let mut lock = object_lock::stored(&metadata, &filepath).await?;
+    lock.check_retention_change(
+        retention.as_ref(),
+        object_lock::bypasses_governance(&signature.headers),
+        now,
+    )?;
+    lock.retention = retention;
+    object_lock::store(&metadata, &filepath, &lock).await?;
+
+    Ok("OK".into_response())
+}
+
+pub async fn get_object_legal_hold(
+    ObjectPath(bucket_name, object_name): ObjectPath,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        ..
+    }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let namespace = &signature.namespace;
+    let filepath =
+        existing_object(&opendal_operator, namespace, &bucket_name, &object_name).await?;
+    require_object_lock(&metadata, namespace, &bucket_name).await?;
+
+    let lock = object_lock::stored(&metadata, &filepath).await?;
+
+    Ok(askama_axum::into_response(&templates::LegalHoldTemplate {
+        status: if lock.legal_hold { "ON" } else { "OFF" },
+    }))
+}
+
+/// Legal holds are set and lifted by anyone allowed to, unlike retentions they have no end.
+pub async fn put_object_legal_hold(
+    ObjectPath(bucket_name, object_name): ObjectPath,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        ..
+    }): State<AppState>,
+    signature: VerifiedRequest,
+) -> Result<impl IntoResponse, S3Error> {
+    let namespace = &signature.namespace;
+    let filepath =
+        existing_object(&opendal_operator, namespace, &bucket_name, &object_name).await?;
+    require_object_lock(&metadata, namespace, &bucket_name).await?;
+
+    let bytes = signature.body.bytes().await?;
+    let document: LegalHoldDocument = quick_xml::de::from_str(std::str::from_utf8(&bytes)?)?;
+    let mut lock = object_lock::stored(&metadata, &filepath).await?;
+    lock.legal_hold = document.is_on()?;
+    object_lock::store(&metadata, &filepath, &lock).await?;
+
+    Ok("OK".into_response())
+}
+
+/// The stored content type, or one guessed from the extension of the key for backends that
+/// drop it.
+pub(crate) fn content_type(stored: Option<&str>, key: &str) -> String {
+    match stored {
+        Some(content_type) if !content_type.is_empty() => content_type.to_string(),
+        _ => mime_guess::from_path(key)
+            .first_or_octet_stream()
+            .to_string(),
+    }
+}
+
+pub(crate) fn http_date(date_time: chrono::DateTime<chrono::Utc>) -> String {
+    date_time.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+pub(crate) fn insert_validators(
+    header_map: &mut HeaderMap,
+    validators: &Validators,
+) -> Result<(), InvalidHeaderValue> {
+    header_map.insert(
+        ETAG,
+        HeaderValue::from_str(&format!(
+            "\"{}\"",
+            etag_cache::normalize_etag(&validators.etag)
+        ))?,
+    );
+    if let Some(last_modified) = &validators.last_modified {
+        header_map.insert(LAST_MODIFIED, HeaderValue::from_str(last_modified)?);
+    }
+
+    Ok(())
+}
+
+pub(crate) fn not_modified(
+    validators: &Validators,
+) -> Result<impl IntoResponse, InvalidHeaderValue> {
+    let mut response_headers = HeaderMap::new();
+    insert_validators(&mut response_headers, validators)?;
+
+    Ok((StatusCode::NOT_MODIFIED, response_headers))
+}
+
+pub async fn list_objects(
+    BucketPath(bucket_name): BucketPath,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        config,
+        listing_cache,
+        ..
+    }): State<AppState>,
+    RawQuery(query): RawQuery,
+    signature: VerifiedRequest,
+) -> Result<Response, S3Error> {
+    let namespace = &signature.namespace;
+    if let Some(cached) = listing_cache.get(namespace, &bucket_name, query.as_deref()) {
+        return Ok(([(CONTENT_TYPE, cached.content_type)], cached.body).into_response());
+    }
+
+    let query_str = query.as_deref().unwrap_or_default();
+    let (page, content_type) = if query_value(query_str, "list-type") == Some("2") {
+        (
+            list_objects_v2(
+                &opendal_operator,
+                &metadata,
+                &config,
+                namespace,
+                &bucket_name,
+                query_str,
+            )
+            .await?,
+            "application/xml",
+        )
+    } else if wants_json(&query) {
+        (
+            list_objects_json(
+                &opendal_operator,
+                &metadata,
+                &config,
+                namespace,
+                &bucket_name,
+                query_str,
+            )
+            .await?,
+            "application/json",
+        )
+    } else {
+        (
+            list_objects_v1(
+                &opendal_operator,
+                &metadata,
+                &config,
+                namespace,
+                &bucket_name,
+                query_str,
+            )
+            .await?,
+            "application/xml",
+        )
+    };
+
+    // a page holds at most `MAX_LIST_KEYS` entries, so it is rendered as one chunk
+    let body = listing_cache.fill(
+        namespace,
+        &bucket_name,
+        query.as_deref(),
+        content_type,
+        stream::once(std::future::ready(Ok(page))),
+    );
+    Ok(([(CONTENT_TYPE, content_type)], Body::from_stream(body)).into_response())
+}
+
+/// `ListObjectVersions`, objects are written in place so every key has one version, the `null`
+/// version S3 lists for objects written while versioning was off.
+///
+/// The objects a versioned bucket keeps in the trash are not versions and deletes leave no
+/// delete marker, so neither noncurrent versions nor delete markers are listed.
+pub async fn list_object_versions(
+    BucketPath(bucket_name): BucketPath,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        config,
+        ..
+    }): State<AppState>,
+    RawQuery(query): RawQuery,
+    signature: VerifiedRequest,
+) -> Result<Response, S3Error> {
+    let namespace = &signature.namespace;
+    let query = query.unwrap_or_default();
+
+    if !opendal_operator
+        .is_exist(&format!("{}/{}/", namespace, bucket_name))
+        .await?
+    {
+        return Err(S3Error::NoSuchBucket);
+    }
+
+    let key_marker = decoded_query_value(&query, "key-marker").unwrap_or_default();
+    let version_id_marker = decoded_query_value(&query, "version-id-marker").unwrap_or_default();
+    let prefix = decoded_query_value(&query, "prefix").unwrap_or_default();
+    let delimiter = decoded_query_value(&query, "delimiter").unwrap_or_default();
+    let max_keys = max_keys(&query)?;
+    if !version_id_marker.is_empty() {
+        if key_marker.is_empty() {
+            return Err(S3Error::InvalidArgument(String::from(
+                "A version-id marker cannot be specified without a key marker.",
+            )));
+        }
+        if version_id_marker != "null" {
+            return Err(S3Error::InvalidArgument(String::from(
+                "Invalid version id specified",
+            )));
+        }
+    }
+    // the only version of the marker key is the `null` one, so the page starts after the key
+    // with or without the version id marker
+    let page = list_page(
+        &opendal_operator,
+        &metadata,
+        &config,
+        namespace,
+        &bucket_name,
+        &ListQuery {
+            prefix: &prefix,
+            delimiter: &delimiter,
+            after: Some(key_marker.as_str()).filter(|x| !x.is_empty()),
+            max_keys,
+        },
+    )
+    .await?;
+
+    let versions: Vec<_> = page
+        .objects
+        .iter()
+        .zip(&page.storage_classes)
+        .map(
+            |((key, metadata), storage_class)| templates::ObjectVersion {
+                key,
+                // the template quotes the etag
+                etag: metadata.etag().map(|x| x.trim_matches('"')),
+                last_modified: metadata.last_modified().map(|x| x.to_rfc3339()),
+                size: metadata.content_length(),
+                storage_class,
+            },
+        )
+        .collect();
+    let next_version_id_marker = page
+        .next_marker
+        .as_ref()
+        .filter(|x| !page.common_prefixes.contains(x))
+        .map(|_| "null");
+
+    Ok(askama_axum::into_response(
+        &templates::ListObjectVersionsTemplate {
+            bucket_name: &bucket_name,
+            prefix: &prefix,
+            delimiter: &delimiter,
+            key_marker: &key_marker,
+            version_id_marker: &version_id_marker,
+            next_key_marker: page.next_marker.as_deref(),
+            next_version_id_marker,
+            max_keys,
+            is_truncated: page.is_truncated,
+            versions: &versions,
+            owner: namespace,
+            common_prefixes: &page.common_prefixes,
+        },
+    ))
+}
+
+/// `max-keys` of a listing, capped at `MAX_LIST_KEYS`.
+fn max_keys(query: &str) -> Result<usize, S3Error> {
+    match query_value(query, "max-keys") {
+        Some(x) => Ok(x
+            .parse::<usize>()
+            .map_err(|_| S3Error::InvalidArgument(String::from("max-keys is not a number")))?
+            .min(MAX_LIST_KEYS)),
+        None => Ok(MAX_LIST_KEYS),
+    }
+}
+
+/// What a listing asks for, shared by the v1, v2 and json listings.
+struct ListQuery<'a> {
+    prefix: &'a str,
+    /// empty when the keys are not grouped
+    delimiter: &'a str,
+    /// only keys and common prefixes after this one are listed
+    after: Option<&'a str>,
+    max_keys: usize,
+}
+
+/// One page of a listing, `next_marker` is the last key or common prefix of a truncated page.
+struct ListPage {
+    objects: Vec<(String, opendal::Metadata)>,
+    /// the class of each object
+    storage_classes: Vec<String>,
+    common_prefixes: Vec<String>,
+    is_truncated: bool,
+    next_marker: Option<String>,
+}
+
+/// Pages are cut from the keys in lexicographic order like S3. Backends that can start a listing
+/// after a key list in that order, so they are listed from the marker until the page is full.
+/// Other backends list in their own order and are listed in full, only the first keys after the
+/// marker are kept so a page holds at most `max_keys` entries whatever the size of the bucket.
+/// With a delimiter the keys that contain it after the prefix are rolled up into one common
+/// prefix, which counts as one key.
+async fn list_page(
+    operator: &opendal::Operator,
+    metadata_store: &MetadataStore,
+    config: &Config,
+    namespace: &str,
+    bucket_name: &str,
+    list: &ListQuery<'_>,
+) -> Result<ListPage, S3Error> {
+    let root = format!("{}/{}/", namespace, bucket_name);
+    // only the directory the prefix points into is listed
+    let dir = match list.prefix.rfind('/') {
+        Some(index) => &list.prefix[..=index],
+        None => "",
+    };
+    let ordered = operator.info().full_capability().list_with_start_after;
+    let mut lister = operator
+        .lister_with(&format!("{}{}", root, dir))
+        .recursive(true);
+    if let Some(after) = list.after.filter(|_| ordered) {
+        lister = lister.start_after(&format!("{}{}", root, after));
+    }
+    let mut lister = match lister.await {
+        Ok(lister) => Some(lister),
+        Err(error) if error.kind() == opendal::ErrorKind::NotFound => None,
+        Err(error) => return Err(error.into()),
+    };
+
+    // a common prefix maps to `None`
+    let mut entries = BTreeMap::new();
+    while let Some(entry) = match &mut lister {
+        Some(lister) => lister.try_next().await?,
+        None => None,
+    } {
+        if !entry.metadata().is_file() {
+            continue;
+        }
+        let (path, metadata) = entry.into_parts();
+        let key = path.strip_prefix(&root).unwrap_or(&path);
+        let Some(rest) = key.strip_prefix(list.prefix) else {
+            continue;
+        };
+
+        let (name, metadata) = match rest.find(list.delimiter) {
+            Some(index) if !list.delimiter.is_empty() => (
+                &key[..list.prefix.len() + index + list.delimiter.len()],
+                None,
+            ),
+            _ => (key, Some(metadata)),
+        };
+        if list.after.is_some_and(|after| name <= after) {
+            continue;
+        }
+        entries.insert(name.to_string(), metadata);
+        // one entry more than the page tells whether it is truncated
+        if entries.len() > list.max_keys + 1 {
+            entries.pop_last();
+        }
+        if ordered && entries.len() > list.max_keys {
+            break;
+        }
+    }
+
+    let is_truncated = entries.len() > list.max_keys;
+    let entries: Vec<_> = entries.into_iter().take(list.max_keys).collect();
+    let next_marker = entries
+        .last()
+        .filter(|_| is_truncated)
+        .map(|(name, _)| name.clone());
+
+    let mut objects = Vec::new();
+    let mut common_prefixes = Vec::new();
+    for (name, metadata) in entries {
+        match metadata {
+            Some(metadata) => objects.push((name, metadata)),
+            None => common_prefixes.push(name),
+        }
+    }
+
+    // the lister would stat entries one by one when the backend does not return this
+    // metadata while listing, so only the entries of the page without it are stat'ed, concurrently
+    let required = Metakey::ContentLength | Metakey::Etag | Metakey::LastModified;
+    let mut objects: Vec<_> = stream::iter(objects)
+        .map(|(key, metadata)| {
+            let path = format!("{}{}", root, key);
+            async move {
+                let metakey = metadata.metakey();
+                if metakey.contains(Metakey::Complete) || metakey.contains(required) {
+                    return Ok((key, metadata));
+                }
+                Ok::<_, opendal::Error>((key, operator.stat(&path).await?))
+            }
+        })
+        .buffered(config.list_stat_concurrency.max(1))
+        .try_collect()
+        .await?;
+    etags::fill_many(metadata_store, &root, &mut objects).await?;
+    let storage_classes = storage_class::stored_many(metadata_store, &root, &objects).await?;
+
+    Ok(ListPage {
+        objects,
+        storage_classes,
+        common_prefixes,
+        is_truncated,
+        next_marker,
+    })
+}
+
+/// One page of `ListObjects`, resumed after `marker`.
+async fn list_objects_v1(
+    operator: &opendal::Operator,
+    metadata_store: &MetadataStore,
+    config: &Config,
+    namespace: &str,
+    bucket_name: &str,
+    query: &str,
+) -> Result<String, S3Error> {
+    let marker = decoded_query_value(query, "marker").unwrap_or_default();
+    let prefix = decoded_query_value(query, "prefix").unwrap_or_default();
+    let delimiter = decoded_query_value(query, "delimiter").unwrap_or_default();
+    let max_keys = max_keys(query)?;
+    let page = list_page(
+        operator,
+        metadata_store,
+        config,
+        namespace,
+        bucket_name,
+        &ListQuery {
+            prefix: &prefix,
+            delimiter: &delimiter,
+            after: Some(marker.as_str()).filter(|x| !x.is_empty()),
+            max_keys,
+        },
+    )
+    .await?;
+
+    let mut body = templates::ListObjectsStartTemplate {
+        marker: Cow::from(marker.as_str()),
+        bucket_name: Cow::from(bucket_name),
+        prefix: Cow::from(prefix.as_str()),
+        delimiter: Cow::from(delimiter.as_str()),
+        max_keys: max_keys as u64,
+    }
+    .render()?;
+    for ((key, metadata), storage_class) in page.objects.iter().zip(&page.storage_classes) {
+        body.push_str(
+            &render_list_object(key, metadata, storage_class, None).map_err(S3Error::internal)?,
+        );
+    }
+    body.push_str(
+        &templates::ListObjectsEndTemplate {
+            common_prefixes: &page.common_prefixes,
+            is_truncated: page.is_truncated,
+            next_marker: Cow::from(page.next_marker.unwrap_or_default()),
+        }
+        .render()?,
+    );
+
+    Ok(body)
+}
+
+/// The `format=json` listing, paged like `ListObjects`.
+async fn list_objects_json(
+    operator: &opendal::Operator,
+    metadata_store: &MetadataStore,
+    config: &Config,
+    namespace: &str,
+    bucket_name: &str,
+    query: &str,
+) -> Result<String, S3Error> {
+    let marker = decoded_query_value(query, "marker");
+    let prefix = decoded_query_value(query, "prefix").unwrap_or_default();
+    let delimiter = decoded_query_value(query, "delimiter").unwrap_or_default();
+    let page = list_page(
+        operator,
+        metadata_store,
+        config,
+        namespace,
+        bucket_name,
+        &ListQuery {
+            prefix: &prefix,
+            delimiter: &delimiter,
+            after: marker.as_deref(),
+            max_keys: max_keys(query)?,
+        },
+    )
+    .await?;
+
+    let objects: Vec<_> = page
+        .objects
+        .iter()
+        .map(|(key, metadata)| JsonObject {
+            key,
+            size: metadata.content_length(),
+            etag: metadata.etag(),
+            last_modified: metadata.last_modified().map(|x| x.to_rfc3339()),
+        })
+        .collect();
+
+    serde_json::to_string(&json!({
+        "bucket": bucket_name,
+        "prefix": prefix,
+        "is_truncated": page.is_truncated,
+        "next_marker": page.next_marker,
+        "objects": objects,
+        "common_prefixes": page.common_prefixes,
+    }))
+    .map_err(S3Error::internal)
+}
+
+/// One page of `ListObjectsV2`.
+async fn list_objects_v2(
+    operator: &opendal::Operator,
+    metadata_store: &MetadataStore,
+    config: &Config,
+    namespace: &str,
+    bucket_name: &str,
+    query: &str,
+) -> Result<String, S3Error> {
+    let continuation_token = query_value(query, "continuation-token");
+    let start_after = decoded_query_value(query, "start-after");
+    let prefix = decoded_query_value(query, "prefix").unwrap_or_default();
+    let delimiter = decoded_query_value(query, "delimiter").unwrap_or_default();
+    let max_keys = max_keys(query)?;
+    let fetch_owner = query_value(query, "fetch-owner") == Some("true");
+    // the token is the last key of the previous page, it takes precedence over start-after
+    let after = match continuation_token {
+        Some(token) => Some(
+            hex::decode(token)
+                .ok()
+                .and_then(|x| String::from_utf8(x).ok())
+                .ok_or_else(|| {
+                    S3Error::InvalidArgument(String::from(
+                        "The continuation token provided is incorrect",
+                    ))
+                })?,
+        ),
+        None => start_after.clone(),
+    };
+
+    let page = list_page(
+        operator,
+        metadata_store,
+        config,
+        namespace,
+        bucket_name,
+        &ListQuery {
+            prefix: &prefix,
+            delimiter: &delimiter,
+            after: after.as_deref(),
+            max_keys,
+        },
+    )
+    .await?;
+
+    let owner = fetch_owner.then_some(namespace);
+    let mut contents = String::new();
+    for ((key, metadata), storage_class) in page.objects.iter().zip(&page.storage_classes) {
+        contents.push_str(
+            &render_list_object(key, metadata, storage_class, owner).map_err(S3Error::internal)?,
+        );
+    }
+
+    Ok(templates::ListObjectsV2Template {
+        bucket_name,
+        prefix: &prefix,
+        delimiter: &delimiter,
+        key_count: page.objects.len() + page.common_prefixes.len(),
+        max_keys,
+        is_truncated: page.is_truncated,
+        continuation_token,
+        next_continuation_token: page.next_marker.map(hex::encode),
+        start_after: start_after.as_deref(),
+        contents,
+        common_prefixes: &page.common_prefixes,
+    }
+    .render()?)
+}
+
+fn render_list_object(
+    key: &str,
+    metadata: &opendal::Metadata,
+    storage_class: &str,
+    owner: Option<&str>,
+) -> Result<String, BoxError> {
+    let item = templates::ListObjectItem {
+        key: Cow::from(key),
+        // the template quotes the etag, backends return it quoted already
+        etag: metadata.etag().map(|x| Cow::from(x.trim_matches('"'))),
+        last_modified: metadata
+            .last_modified()
+            .map(|dt| Cow::from(dt.to_rfc3339())),
+        size: metadata.content_length(),
+        storage_class: Cow::from(storage_class),
+        owner: owner.map(Cow::from),
+    };
+
+    Ok(item.render()?)
+}
@@ -0,0 +1,290 @@
+use crate::context::RequestSummary;
+use crate::metadata::MetadataStore;
+use axum::http::{Method, StatusCode};
+use deadpool_redis::redis;
+use deadpool_redis::Pool;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+
+const CHANNEL_SIZE: usize = 1024;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditSink {
+    File,
+    Redis,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AuditConfig {
+    pub sink: AuditSink,
+    /// file to append the json lines to, used by the `file` sink
+    pub path: Option<PathBuf>,
+    /// redis stream to add the events to, used by the `redis` sink
+    #[serde(default = "default_stream")]
+    pub stream: String,
+}
+
+fn default_stream() -> String {
+    String::from("s3_proxy::audit")
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AuditEvent {
+    pub time: String,
+    pub request_id: String,
+    pub access_key: String,
+    pub namespace: String,
+    pub operation: String,
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+}
+
+impl AuditEvent {
+    pub fn from_summary(summary: &RequestSummary) -> AuditEvent {
+        let context = summary.context;
+
+        AuditEvent {
+            time: OffsetDateTime::now_utc()
+                .format(&Rfc3339)
+                .unwrap_or_default(),
+            request_id: context.request_id.clone(),
+            access_key: context.access_key().unwrap_or_default().to_string(),
+            namespace: context.namespace().unwrap_or_default().to_string(),
+            operation: context.operation.to_string(),
+            method: context.method.to_string(),
+            path: context.path.clone(),
+            status: summary.status.as_u16(),
+        }
+    }
+}
+
+/// Append only log of every successful mutating request.
+///
+/// Events are handed to a background task so recording never blocks a response.
+pub struct AuditLog {
+    config: AuditConfig,
+    /// only set for the redis sink
+    pool: Option<Pool>,
+    sender: mpsc::Sender<AuditEvent>,
+}
+
+impl AuditLog {
+    pub fn start(config: AuditConfig, metadata: &MetadataStore) -> anyhow::Result<AuditLog> {
+        let pool = match config.sink {
+            AuditSink::File => {
+                anyhow::ensure!(
+                    config.path.is_some(),
+                    "audit.path is required for the file audit sink"
+                );
+                None
+            }
+            AuditSink::Redis => Some(
+                metadata
+                    .redis_pool()
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("the redis audit sink needs redis metadata"))?,
+            ),
+        };
+
+        let (sender, receiver) = mpsc::channel(CHANNEL_SIZE);
+        tokio::spawn(write_events(config.clone(), pool.clone(), receiver));
+
+        Ok(AuditLog {
+            config,
+            pool,
+            sender,
+        })
+    }
+
+    pub fn record(&self, summary: &RequestSummary) {
+        if !summary.status.is_success() || !summary.context.operation.is_mutating() {
+            return;
+        }
+
+        if let Err(error) = self.sender.try_send(AuditEvent::from_summary(summary)) {
+            tracing::error!("unable to record audit event: {}", error);
+        }
+    }
+
+    /// Records a change made through the admin api.
+    pub fn record_admin(&self, method: &Method, path: &str, status: StatusCode) {
+        let namespace = path
+            .strip_prefix("/namespaces/")
+            .map(|x| x.split('/').next().unwrap_or_default())
+            .unwrap_or_default();
+
+        let event = AuditEvent {
+            time: OffsetDateTime::now_utc()
+                .format(&Rfc3339)
+                .unwrap_or_default(),
+            request_id: String::new(),
+            access_key: String::from("admin"),
+            namespace: namespace.to_string(),
+            operation: String::from("Admin"),
+            method: method.to_string(),
+            path: path.to_string(),
+            status: status.as_u16(),
+        };
+
+        if let Err(error) = self.sender.try_send(event) {
+            tracing::error!("unable to record audit event: {}", error);
+        }
+    }
+
+    /// Returns the most recent events first, optionally only for one namespace.
+    pub async fn query(
+        &self,
+        namespace: Option<&str>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<AuditEvent>> {
+        let events = match self.config.sink {
+            AuditSink::File => {
+                let path = self.config.path.as_ref().expect("checked on start");
+                let content = match tokio::fs::read_to_string(path).await {
+                    Ok(content) => content,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+                    Err(e) => return Err(e.into()),
+                };
+
+                content
+                    .lines()
+                    .rev()
+                    .filter_map(|line| serde_json::from_str::<AuditEvent>(line).ok())
+                    .filter(|event| namespace.is_none_or(|ns| event.namespace == ns))
+                    .take(limit)
+                    .collect()
+            }
+            AuditSink::Redis => {
+                let mut conn = self.pool.as_ref().expect("checked on start").get().await?;
+                let mut events = Vec::new();
+                let mut end = String::from("+");
+
+                // page through the stream since the namespace filter happens client side
+                loop {
+                    let entries: Vec<(String, Vec<String>)> = redis::cmd("XREVRANGE")
+                        .arg(&self.config.stream)
+                        .arg(&end)
+                        .arg("-")
+                        .arg("COUNT")
+                        .arg(limit.max(100))
+                        .query_async(&mut conn)
+                        .await?;
+                    let page_size = entries.len();
+
+                    for (id, fields) in entries {
+                        end = format!("({}", id);
+                        let Some(event) = fields
+                            .chunks(2)
+                            .find(|pair| pair[0] == "event")
+                            .and_then(|pair| serde_json::from_str::<AuditEvent>(&pair[1]).ok())
+                        else {
+                            continue;
+                        };
+                        if namespace.is_none_or(|ns| event.namespace == ns) {
+                            events.push(event);
+                        }
+                    }
+
+                    if events.len() >= limit || page_size < limit.max(100) {
+                        break;
+                    }
+                }
+
+                events.truncate(limit);
+                events
+            }
+        };
+
+        Ok(events)
+    }
+}
+
+async fn write_events(
+    config: AuditConfig,
+    pool: Option<Pool>,
+    mut receiver: mpsc::Receiver<AuditEvent>,
+) {
+    while let Some(event) = receiver.recv().await {
+        if let Err(error) = write_event(&config, &pool, &event).await {
+            tracing::error!("unable to write audit event: {}", error);
+        }
+    }
+}
+
+async fn write_event(
+    config: &AuditConfig,
+    pool: &Option<Pool>,
+    event: &AuditEvent,
+) -> anyhow::Result<()> {
+    let line = serde_json::to_string(event)?;
+
+    match config.sink {
+        AuditSink::File => {
+            let path = config.path.as_ref().expect("checked on start");
+            let mut file = tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .await?;
+            file.write_all(format!("{}\n", line).as_bytes()).await?;
+        }
+        AuditSink::Redis => {
+            let mut conn = pool.as_ref().expect("checked on start").get().await?;
+            let _: String = redis::cmd("XADD")
+                .arg(&config.stream)
+                .arg("*")
+                .arg("event")
+                .arg(line)
+                .query_async(&mut conn)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn file_sink_records_and_queries_events() {
+    let path = std::env::temp_dir().join(format!(
+        "s3-proxy-audit-{}.log",
+        OffsetDateTime::now_utc().unix_timestamp_nanos()
+    ));
+    let config = AuditConfig {
+        sink: AuditSink::File,
+        path: Some(path.clone()),
+        stream: default_stream(),
+    };
+    let pool = None;
+
+    for (namespace, request_id) in [("one", "1"), ("two", "2"), ("one", "3")] {
+        let event = AuditEvent {
+            time: String::from("2024-02-03T12:57:27Z"),
+            request_id: request_id.to_string(),
+            access_key: namespace.to_string(),
+            namespace: namespace.to_string(),
+            operation: String::from("PutObject"),
+            method: String::from("PUT"),
+            path: String::from("/bucket/key"),
+            status: 200,
+        };
+        write_event(&config, &pool, &event).await.unwrap();
+    }
+
+    let (sender, _receiver) = mpsc::channel(1);
+    let audit = AuditLog {
+        config,
+        pool,
+        sender,
+    };
+    let events = audit.query(Some("one"), 10).await.unwrap();
+    let _ = std::fs::remove_file(path);
+
+    let request_ids: Vec<_> = events.iter().map(|x| x.request_id.as_str()).collect();
+    assert_eq!(request_ids, vec!["3", "1"]);
+}
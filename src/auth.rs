@@ -0,0 +1,106 @@
+use crate::credentials::CredentialsCache;
+use crate::error::S3Error;
+use crate::metadata::MetadataStore;
+use crate::operation::S3Operation;
+use crate::signature::Identity;
+use async_trait::async_trait;
+use axum::http::request::Parts;
+
+/// A request whose signature matched, waiting for the authorization decision.
+pub struct AuthRequest<'a> {
+    pub access_key: &'a str,
+    pub operation: S3Operation,
+    pub parts: &'a Parts,
+}
+
+/// Looks up credentials and decides who may do what, the SigV4 checks stay in the proxy.
+#[async_trait]
+pub trait AuthProvider: Send + Sync {
+    /// The secret key to verify the signature with, `None` for unknown access keys.
+    async fn secret_key(&self, access_key: &str) -> Result<Option<String>, S3Error>;
+
+    /// The secret key a rotation replaced, tried when the signature does not match the current
+    /// one. By default rotated secrets stop working right away.
+    async fn previous_secret_key(&self, _access_key: &str) -> Result<Option<String>, S3Error> {
+        Ok(None)
+    }
+
+    /// Called after the signature is verified, returns the identity the request runs as.
+    ///
+    /// By default every key is allowed everything in the namespace named after the key.
+    async fn authorize(&self, request: &AuthRequest<'_>) -> Result<Identity, S3Error> {
+        Ok(Identity {
+            access_key: request.access_key.to_string(),
+            namespace: request.access_key.to_string(),
+        })
+    }
+}
+
+/// The default provider, reads the secret keys and their namespaces from the metadata store.
+pub struct MetadataAuthProvider {
+    metadata: MetadataStore,
+    credentials: CredentialsCache,
+}
+
+impl MetadataAuthProvider {
+    pub fn new(metadata: MetadataStore, credentials: CredentialsCache) -> MetadataAuthProvider {
+        MetadataAuthProvider {
+            metadata,
+            credentials,
+        }
+    }
+}
+
+#[async_trait]
+impl AuthProvider for MetadataAuthProvider {
+    async fn secret_key(&self, access_key: &str) -> Result<Option<String>, S3Error> {
+        Ok(self
+            .credentials
+            .secret_key(&self.metadata, access_key)
+            .await?)
+    }
+
+    async fn previous_secret_key(&self, access_key: &str) -> Result<Option<String>, S3Error> {
+        Ok(self
+            .credentials
+            .previous_secret_key(&self.metadata, access_key)
+            .await?)
+    }
+
+    async fn authorize(&self, request: &AuthRequest<'_>) -> Result<Identity, S3Error> {
+        Ok(Identity {
+            access_key: request.access_key.to_string(),
+            namespace: self
+                .credentials
+                .namespace(&self.metadata, request.access_key)
+                .await?,
+        })
+    }
+}
+
+#[tokio::test]
+async fn metadata_provider_uses_the_key_as_namespace() {
+    let metadata = MetadataStore::memory();
+    metadata.set("secret_key::AKEY", "secret").await.unwrap();
+    let provider = MetadataAuthProvider::new(
+        metadata,
+        CredentialsCache::new(&crate::credentials::CredentialsConfig::default()),
+    );
+
+    assert_eq!(
+        provider.secret_key("AKEY").await.unwrap().as_deref(),
+        Some("secret")
+    );
+    assert_eq!(provider.secret_key("OTHER").await.unwrap(), None);
+
+    let (parts, _) = axum::http::Request::new(()).into_parts();
+    let identity = provider
+        .authorize(&AuthRequest {
+            access_key: "AKEY",
+            operation: S3Operation::ListBuckets,
+            parts: &parts,
+        })
+        .await
+        .unwrap();
+    assert_eq!(identity.namespace, "AKEY");
+}
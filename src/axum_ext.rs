@@ -1,5 +1,13 @@
-use axum::routing::MethodRouter;
+use crate::error::S3Error;
+use crate::plugins::RewrittenPath;
+use async_trait::async_trait;
+use axum::extract::{FromRequestParts, Path};
+use axum::extract::{Request, State};
+use axum::http::request::Parts;
+use axum::routing::{any, MethodRouter};
 use axum::Router;
+use std::sync::Arc;
+use tower::ServiceExt;
 
 /// from https://stackoverflow.com/questions/75355826/route-paths-with-or-without-of-trailing-slashes-in-rust-axum
 pub trait RouterExt<S>
@@ -7,6 +15,12 @@ where
     S: Clone + Send + Sync + 'static,
 {
     fn directory_route(self, path: &str, method_router: MethodRouter<S>) -> Self;
+
+    /// `/:bucket_name`, with or without trailing slash.
+    fn bucket_route(self, method_router: impl Into<MethodRouter<S>>) -> Self;
+
+    /// `/:bucket_name/*object_name`, the key can contain slashes.
+    fn object_route(self, method_router: impl Into<MethodRouter<S>>) -> Self;
 }
 
 impl<S> RouterExt<S> for Router<S>
@@ -17,4 +31,197 @@ where
         self.route(path, method_router.clone())
             .route(&format!("{path}/"), method_router)
     }
+
+    fn bucket_route(self, method_router: impl Into<MethodRouter<S>>) -> Self {
+        self.directory_route("/:bucket_name", method_router.into())
+    }
+
+    fn object_route(self, method_router: impl Into<MethodRouter<S>>) -> Self {
+        self.route("/:bucket_name/*object_name", method_router.into())
+    }
+}
+
+/// Dispatches on the S3 subresource in the query string, like `?acl` or `?uploads`.
+///
+/// Requests without a registered subresource go to the default method router.
+pub struct Subresources<S> {
+    default: MethodRouter<S>,
+    subresources: Vec<(&'static str, MethodRouter<S>)>,
+}
+
+impl<S> Subresources<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    pub fn new(default: MethodRouter<S>) -> Subresources<S> {
+        Subresources {
+            default,
+            subresources: Vec::new(),
+        }
+    }
+
+    pub fn on(mut self, subresource: &'static str, method_router: MethodRouter<S>) -> Self {
+        self.subresources.push((subresource, method_router));
+        self
+    }
+}
+
+impl<S> From<Subresources<S>> for MethodRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    fn from(value: Subresources<S>) -> Self {
+        if value.subresources.is_empty() {
+            return value.default;
+        }
+
+        let routers = Arc::new(value);
+        any(move |State(state): State<S>, req: Request| {
+            let routers = routers.clone();
+            async move {
+                let method_router = req
+                    .uri()
+                    .query()
+                    .and_then(|query| {
+                        routers
+                            .subresources
+                            .iter()
+                            .find(|(name, _)| has_query_key(query, name))
+                    })
+                    .map(|(_, method_router)| method_router)
+                    .unwrap_or(&routers.default);
+
+                method_router.clone().with_state(state).oneshot(req).await
+            }
+        })
+    }
+}
+
+/// Whether the query string has `key`, with or without a value.
+pub(crate) fn has_query_key(query: &str, key: &str) -> bool {
+    query
+        .split('&')
+        .any(|pair| pair.split_once('=').map_or(pair, |(name, _)| name) == key)
+}
+
+/// The first value of `key` in the query string, percent-decoded.
+pub(crate) fn decoded_query_value(query: &str, key: &str) -> Option<String> {
+    query_value(query, key).map(|x| {
+        percent_encoding::percent_decode_str(x)
+            .decode_utf8_lossy()
+            .into_owned()
+    })
+}
+
+/// The first value of `key` in the query string, not percent-decoded.
+pub(crate) fn query_value<'a>(query: &'a str, key: &str) -> Option<&'a str> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(name, _)| *name == key)
+        .map(|(_, value)| value)
+}
+
+/// The `:bucket_name` of the path, or the bucket an interceptor rewrote it to.
+pub struct BucketPath(pub String);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for BucketPath {
+    type Rejection = S3Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(rewritten) = parts.extensions.get::<RewrittenPath>() {
+            return Ok(BucketPath(rewritten.bucket.clone()));
+        }
+
+        let Path(bucket_name) = Path::<String>::from_request_parts(parts, state)
+            .await
+            .map_err(S3Error::internal)?;
+        if is_hidden(&bucket_name) {
+            return Err(S3Error::NoSuchBucket);
+        }
+        Ok(BucketPath(bucket_name))
+    }
+}
+
+/// The `:bucket_name` and `:object_name` of the path, or where an interceptor rewrote them to.
+pub struct ObjectPath(pub String, pub String);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ObjectPath {
+    type Rejection = S3Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(RewrittenPath {
+            bucket,
+            key: Some(key),
+        }) = parts.extensions.get::<RewrittenPath>()
+        {
+            return Ok(ObjectPath(bucket.clone(), key.clone()));
+        }
+
+        let Path((bucket_name, object_name)) =
+            Path::<(String, String)>::from_request_parts(parts, state)
+                .await
+                .map_err(S3Error::internal)?;
+        if is_hidden(&bucket_name) {
+            return Err(S3Error::NoSuchBucket);
+        }
+        check_key(&object_name)?;
+        Ok(ObjectPath(bucket_name, object_name))
+    }
+}
+
+/// Directories like the trash live next to the buckets, valid bucket names never start with a
+/// `.` so these are not reachable through the S3 api.
+pub fn is_hidden(bucket_name: &str) -> bool {
+    bucket_name.starts_with('.')
+}
+
+/// Keys become backend paths below the bucket, so `..`, `.` and empty segments could point to
+/// another bucket or namespace.
+pub fn check_key(key: &str) -> Result<(), S3Error> {
+    if key
+        .split('/')
+        .any(|x| x.is_empty() || x == "." || x == "..")
+    {
+        return Err(S3Error::InvalidArgument(String::from(
+            "Object keys must not contain empty, . or .. segments",
+        )));
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn object_routes_dispatch_on_subresources() {
+    use axum::body::Body;
+    use axum::routing::get;
+
+    let app: Router = Router::new()
+        .bucket_route(get(|| async { "bucket" }))
+        .object_route(
+            Subresources::new(get(
+                |Path((_, key)): Path<(String, String)>| async move { key },
+            ))
+            .on("acl", get(|| async { "acl" })),
+        );
+
+    for (uri, expected) in [
+        ("/testing", "bucket"),
+        ("/testing/", "bucket"),
+        ("/testing/a/b.txt", "a/b.txt"),
+        ("/testing/a/b.txt?acl", "acl"),
+        ("/testing/a/b.txt?versionId=1&acl=", "acl"),
+        ("/testing/a/b.txt?aclx", "a/b.txt"),
+    ] {
+        let response = app
+            .clone()
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, expected, "{uri}");
+    }
 }
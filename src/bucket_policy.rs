@@ -0,0 +1,476 @@
+use crate::error::S3Error;
+use crate::operation::S3Operation;
+use axum::http::Method;
+use serde::Deserialize;
+
+/// S3 refuses larger policies.
+pub const MAX_POLICY_SIZE: usize = 20 * 1024;
+const RESOURCE_PREFIX: &str = "arn:aws:s3:::";
+
+/// A JSON field that is one value or a list of them.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+impl<T> OneOrMany<T> {
+    fn as_slice(&self) -> &[T] {
+        match self {
+            OneOrMany::One(x) => std::slice::from_ref(x),
+            OneOrMany::Many(x) => x,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+enum Principal {
+    /// `"*"`
+    Everyone(String),
+    Aws {
+        #[serde(rename = "AWS")]
+        aws: OneOrMany<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+enum Effect {
+    Allow,
+    Deny,
+}
+
+/// `Condition`, `NotAction` and the like are refused, an ignored condition would allow more
+/// than the policy says.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "PascalCase", deny_unknown_fields)]
+struct Statement {
+    #[serde(default)]
+    sid: Option<String>,
+    effect: Effect,
+    principal: Principal,
+    action: OneOrMany<String>,
+    resource: OneOrMany<String>,
+}
+
+/// The IAM style policy of a bucket, set with `PutBucketPolicy`, or of every bucket of a
+/// namespace, set on the admin api.
+///
+/// Principals are `*`, access keys or `arn:aws:iam::<namespace>:root` for every key of a
+/// namespace. Keys of the namespace of the bucket are allowed unless a statement denies them,
+/// keys of other namespaces need a statement that allows them.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct BucketPolicy {
+    #[serde(default)]
+    version: Option<String>,
+    #[serde(rename = "Id", default)]
+    id: Option<String>,
+    statement: OneOrMany<Statement>,
+}
+
+/// What a request acts on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resource<'a> {
+    Bucket(&'a str),
+    Object(&'a str, &'a str),
+    /// the keys are in the body, like for `DeleteObjects` and browser uploads
+    AnyObject(&'a str),
+}
+
+/// Who signed the request.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Requester<'a> {
+    pub access_key: &'a str,
+    pub namespace: &'a str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Decision {
+    Allow,
+    Deny,
+    /// no statement applies
+    Default,
+}
+
+impl Decision {
+    /// The decision of two policies that both apply, like the bucket and the namespace policy.
+    pub fn and(self, other: Decision) -> Decision {
+        match (self, other) {
+            (Decision::Deny, _) | (_, Decision::Deny) => Decision::Deny,
+            (Decision::Allow, _) | (_, Decision::Allow) => Decision::Allow,
+            (Decision::Default, Decision::Default) => Decision::Default,
+        }
+    }
+}
+
+fn malformed(message: &str) -> S3Error {
+    S3Error::MalformedPolicy(String::from(message))
+}
+
+/// `*` matches any run of characters and `?` one character.
+///
+/// Iterative so a pattern with many `*` takes at most `pattern × value` steps: on a mismatch
+/// only the last `*` is retried, one character further.
+fn matches(pattern: &str, value: &str, ignore_case: bool) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let value: Vec<char> = value.chars().collect();
+    let equal = |wildcard: char, x: char| {
+        wildcard == '?' || x == wildcard || (ignore_case && x.eq_ignore_ascii_case(&wildcard))
+    };
+
+    let (mut p, mut v) = (0, 0);
+    // the position after the last `*` and the value position it is matched up to
+    let mut star = None;
+    while v < value.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            p += 1;
+            star = Some((p, v));
+        } else if p < pattern.len() && equal(pattern[p], value[v]) {
+            p += 1;
+            v += 1;
+        } else if let Some((after_star, matched)) = star {
+            p = after_star;
+            v = matched + 1;
+            star = Some((after_star, v));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|x| *x == '*')
+}
+
+impl Resource<'_> {
+    pub fn bucket(&self) -> &str {
+        match self {
+            Resource::Bucket(bucket)
+            | Resource::Object(bucket, _)
+            | Resource::AnyObject(bucket) => bucket,
+        }
+    }
+
+    /// Whether the resource pattern covers the resource. Allow statements have to cover
+    /// every key of `AnyObject`, deny statements apply when they cover some key.
+    fn is_covered_by(&self, pattern: &str, effect: Effect) -> bool {
+        let Some(pattern) = pattern.strip_prefix(RESOURCE_PREFIX) else {
+            return pattern == "*";
+        };
+        match (self, effect) {
+            (Resource::Bucket(bucket), _) => matches(pattern, bucket, false),
+            (Resource::Object(bucket, key), _) => {
+                matches(pattern, &format!("{}/{}", bucket, key), false)
+            }
+            (Resource::AnyObject(bucket), Effect::Allow) => {
+                matches(pattern, &format!("{}/*", bucket), false)
+            }
+            (Resource::AnyObject(bucket), Effect::Deny) => pattern
+                .split_once('/')
+                .is_some_and(|(pattern, _)| matches(pattern, bucket, false)),
+        }
+    }
+}
+
+impl Statement {
+    fn applies(&self, requester: &Requester, action: &str, resource: &Resource) -> bool {
+        let principal = match &self.principal {
+            Principal::Everyone(x) => x == "*",
+            Principal::Aws { aws } => aws.as_slice().iter().any(|x| {
+                x == "*"
+                    || x == requester.access_key
+                    || *x == format!("arn:aws:iam::{}:root", requester.namespace)
+            }),
+        };
+
+        principal
+            && self
+                .action
+                .as_slice()
+                .iter()
+                .any(|x| matches(x, action, true))
+            && self
+                .resource
+                .as_slice()
+                .iter()
+                .any(|x| resource.is_covered_by(x, self.effect))
+    }
+}
+
+impl BucketPolicy {
+    /// Parses and checks a policy of the bucket, the error is sent back to the client.
+    pub fn parse(policy: &str, bucket: &str) -> Result<BucketPolicy, S3Error> {
+        BucketPolicy::parse_for(policy, Some(bucket))
+    }
+
+    /// Parses and checks a namespace policy, its resources are any of the buckets.
+    pub fn parse_namespace(policy: &str) -> Result<BucketPolicy, S3Error> {
+        BucketPolicy::parse_for(policy, None)
+    }
+
+    fn parse_for(policy: &str, bucket: Option<&str>) -> Result<BucketPolicy, S3Error> {
+        if policy.len() > MAX_POLICY_SIZE {
+            return Err(malformed("Policies must be no more than 20 KB"));
+        }
+        let policy: BucketPolicy = serde_json::from_str(policy)
+            .map_err(|error| S3Error::MalformedPolicy(format!("Invalid policy: {}", error)))?;
+
+        if policy
+            .version
+            .as_deref()
+            .is_some_and(|x| x != "2012-10-17" && x != "2008-10-17")
+        {
+            return Err(malformed("Policy has an invalid version"));
+        }
+        if policy.statement.as_slice().is_empty() {
+            return Err(malformed("Could not parse the policy: Statement is empty!"));
+        }
+        for statement in policy.statement.as_slice() {
+            if matches!(&statement.principal, Principal::Everyone(x) if x != "*") {
+                return Err(malformed("Invalid principal in policy"));
+            }
+            if statement
+                .action
+                .as_slice()
+                .iter()
+                .any(|x| x != "*" && !x.to_ascii_lowercase().starts_with("s3:"))
+            {
+                return Err(malformed("Policy has invalid action"));
+            }
+            // the resources of a bucket policy have to be in the bucket, like S3 requires
+            let resources = statement.resource.as_slice();
+            if resources.is_empty()
+                || resources
+                    .iter()
+                    .any(|x| match x.strip_prefix(RESOURCE_PREFIX) {
+                        Some(x) => bucket.is_some_and(|bucket| {
+                            x.split('/')
+                                .next()
+                                .is_none_or(|x| !matches(x, bucket, false))
+                        }),
+                        None => bucket.is_some() || x != "*",
+                    })
+            {
+                return Err(malformed("Policy has invalid resource"));
+            }
+        }
+
+        Ok(policy)
+    }
+
+    /// Deny statements win over allow statements.
+    pub fn evaluate(&self, requester: &Requester, action: &str, resource: &Resource) -> Decision {
+        let applying = self
+            .statement
+            .as_slice()
+            .iter()
+            .filter(|x| x.applies(requester, action, resource));
+
+        let mut decision = Decision::Default;
+        for statement in applying {
+            match statement.effect {
+                Effect::Deny => return Decision::Deny,
+                Effect::Allow => decision = Decision::Allow,
+            }
+        }
+        decision
+    }
+}
+
+/// The resource of a request to `/{bucket}/{key}`, the key is percent-decoded.
+pub fn resource<'a>(operation: S3Operation, bucket: &'a str, key: &'a str) -> Resource<'a> {
+    match operation {
+        S3Operation::DeleteObjects | S3Operation::PostObject => Resource::AnyObject(bucket),
+        _ if key.is_empty() => Resource::Bucket(bucket),
+        _ => Resource::Object(bucket, key),
+    }
+}
+
+/// The IAM action of an operation, `None` for the ones a bucket policy does not cover.
+/// `HEAD` requests have no operation of their own, they need the action of the `GET`.
+pub fn action(operation: S3Operation, method: &Method, has_key: bool) -> Option<&'static str> {
+    let action = match operation {
+        S3Operation::Unknown if method == Method::HEAD && has_key => "s3:GetObject",
+        S3Operation::Unknown if method == Method::HEAD => "s3:ListBucket",
+        S3Operation::ListObjects => "s3:ListBucket",
+        S3Operation::ListObjectVersions => "s3:ListBucketVersions",
+        S3Operation::DeleteBucket => "s3:DeleteBucket",
+        S3Operation::GetBucketLocation => "s3:GetBucketLocation",
+        S3Operation::GetObject | S3Operation::SelectObjectContent => "s3:GetObject",
+        S3Operation::GetObjectAttributes => "s3:GetObjectAttributes",
+        S3Operation::GetObjectTagging => "s3:GetObjectTagging",
+        S3Operation::PutObjectTagging => "s3:PutObjectTagging",
+        S3Operation::DeleteObjectTagging => "s3:DeleteObjectTagging",
+        S3Operation::GetObjectAcl => "s3:GetObjectAcl",
+        S3Operation::PutObjectAcl => "s3:PutObjectAcl",
+        S3Operation::GetObjectRetention => "s3:GetObjectRetention",
+        S3Operation::PutObjectRetention => "s3:PutObjectRetention",
+        S3Operation::GetObjectLegalHold => "s3:GetObjectLegalHold",
+        S3Operation::PutObjectLegalHold => "s3:PutObjectLegalHold",
+        S3Operation::CreateMultipartUpload
+        | S3Operation::UploadPart
+        | S3Operation::CompleteMultipartUpload
+        | S3Operation::PutObject
+        | S3Operation::PostObject => "s3:PutObject",
+        S3Operation::DeleteObject | S3Operation::DeleteObjects => "s3:DeleteObject",
+        S3Operation::GetBucketNotification => "s3:GetBucketNotification",
+        S3Operation::PutBucketNotification => "s3:PutBucketNotification",
+        S3Operation::GetBucketReplication => "s3:GetReplicationConfiguration",
+        S3Operation::PutBucketReplication | S3Operation::DeleteBucketReplication => {
+            "s3:PutReplicationConfiguration"
+        }
+        S3Operation::GetBucketLifecycleConfiguration => "s3:GetLifecycleConfiguration",
+        S3Operation::PutBucketLifecycleConfiguration | S3Operation::DeleteBucketLifecycle => {
+            "s3:PutLifecycleConfiguration"
+        }
+        S3Operation::GetBucketTagging => "s3:GetBucketTagging",
+        S3Operation::PutBucketTagging | S3Operation::DeleteBucketTagging => "s3:PutBucketTagging",
+        S3Operation::GetBucketCors => "s3:GetBucketCORS",
+        S3Operation::PutBucketCors | S3Operation::DeleteBucketCors => "s3:PutBucketCORS",
+        S3Operation::GetBucketPolicy => "s3:GetBucketPolicy",
+        S3Operation::PutBucketPolicy => "s3:PutBucketPolicy",
+        S3Operation::DeleteBucketPolicy => "s3:DeleteBucketPolicy",
+        S3Operation::GetBucketAcl => "s3:GetBucketAcl",
+        S3Operation::PutBucketAcl => "s3:PutBucketAcl",
+        S3Operation::GetBucketVersioning => "s3:GetBucketVersioning",
+        S3Operation::PutBucketVersioning => "s3:PutBucketVersioning",
+        S3Operation::GetObjectLockConfiguration => "s3:GetBucketObjectLockConfiguration",
+        S3Operation::PutObjectLockConfiguration => "s3:PutBucketObjectLockConfiguration",
+        S3Operation::GetBucketInventoryConfiguration
+        | S3Operation::ListBucketInventoryConfigurations => "s3:GetInventoryConfiguration",
+        S3Operation::PutBucketInventoryConfiguration
+        | S3Operation::DeleteBucketInventoryConfiguration => "s3:PutInventoryConfiguration",
+        // creating a bucket and listing buckets are not about an existing bucket
+        S3Operation::ListBuckets | S3Operation::CreateBucket | S3Operation::Unknown => return None,
+    };
+    Some(action)
+}
+
+#[test]
+fn policies_are_parsed_and_evaluated() {
+    let policy = BucketPolicy::parse(
+        r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {
+                    "Effect": "Allow",
+                    "Principal": {"AWS": "arn:aws:iam::partner:root"},
+                    "Action": ["s3:Get*", "s3:ListBucket"],
+                    "Resource": ["arn:aws:s3:::photos", "arn:aws:s3:::photos/public/*"]
+                },
+                {
+                    "Effect": "Deny",
+                    "Principal": "*",
+                    "Action": "s3:DeleteObject",
+                    "Resource": "arn:aws:s3:::photos/keep/*"
+                }
+            ]
+        }"#,
+        "photos",
+    )
+    .unwrap();
+    let partner = Requester {
+        access_key: "PARTNERKEY",
+        namespace: "partner",
+    };
+    let owner = Requester {
+        access_key: "OWNERKEY",
+        namespace: "owner",
+    };
+
+    let get = |requester, key| {
+        policy.evaluate(requester, "s3:GetObject", &Resource::Object("photos", key))
+    };
+    assert_eq!(get(&partner, "public/a.jpg"), Decision::Allow);
+    assert_eq!(get(&partner, "private/a.jpg"), Decision::Default);
+    assert_eq!(get(&owner, "public/a.jpg"), Decision::Default);
+    assert_eq!(
+        policy.evaluate(&partner, "s3:listbucket", &Resource::Bucket("photos")),
+        Decision::Allow
+    );
+    assert_eq!(
+        policy.evaluate(
+            &owner,
+            "s3:DeleteObject",
+            &Resource::Object("photos", "keep/a.jpg")
+        ),
+        Decision::Deny
+    );
+    // the keys of a batch delete are not known up front
+    assert_eq!(
+        policy.evaluate(&owner, "s3:DeleteObject", &Resource::AnyObject("photos")),
+        Decision::Deny
+    );
+    assert_eq!(
+        policy.evaluate(&partner, "s3:GetObject", &Resource::AnyObject("photos")),
+        Decision::Default
+    );
+
+    let invalid = [
+        r#"not json"#,
+        r#"{"Statement": []}"#,
+        r#"{"Statement": {"Effect": "Allow", "Principal": "*", "Action": "s3:GetObject", "Resource": "arn:aws:s3:::other/*"}}"#,
+        r#"{"Statement": {"Effect": "Allow", "Principal": "*", "Action": "iam:PassRole", "Resource": "arn:aws:s3:::photos/*"}}"#,
+        r#"{"Statement": {"Effect": "Allow", "Principal": "someone", "Action": "s3:GetObject", "Resource": "arn:aws:s3:::photos/*"}}"#,
+        r#"{"Statement": {"Effect": "Allow", "Principal": "*", "Action": "s3:GetObject", "Resource": "arn:aws:s3:::photos/*", "Condition": {"Bool": {"aws:SecureTransport": "true"}}}}"#,
+    ];
+    for policy in invalid {
+        assert!(BucketPolicy::parse(policy, "photos").is_err(), "{}", policy);
+    }
+}
+
+#[test]
+fn namespace_policies_cover_every_bucket() {
+    let policy = BucketPolicy::parse_namespace(
+        r#"{"Statement": {"Effect": "Deny", "Principal": "*", "Action": "s3:Delete*", "Resource": "*"}}"#,
+    )
+    .unwrap();
+    let owner = Requester {
+        access_key: "OWNERKEY",
+        namespace: "owner",
+    };
+    assert_eq!(
+        policy.evaluate(
+            &owner,
+            "s3:DeleteObject",
+            &Resource::Object("logs", "a.log")
+        ),
+        Decision::Deny
+    );
+    assert!(BucketPolicy::parse_namespace(
+        r#"{"Statement": {"Effect": "Allow", "Principal": "*", "Action": "s3:GetObject", "Resource": "arn:aws:iam::owner:root"}}"#,
+    )
+    .is_err());
+    // bucket policies stay within their bucket
+    assert!(BucketPolicy::parse(
+        r#"{"Statement": {"Effect": "Deny", "Principal": "*", "Action": "s3:Delete*", "Resource": "*"}}"#,
+        "logs",
+    )
+    .is_err());
+
+    assert_eq!(Decision::Allow.and(Decision::Deny), Decision::Deny);
+    assert_eq!(Decision::Default.and(Decision::Allow), Decision::Allow);
+    assert_eq!(Decision::Default.and(Decision::Default), Decision::Default);
+}
+
+#[test]
+fn wildcards_match_in_linear_passes() {
+    assert!(matches("photos/*", "photos/a/b.jpg", false));
+    assert!(matches("photos/*.jpg", "photos/a.png.jpg", false));
+    assert!(matches("ph?tos", "photos", false));
+    assert!(matches("*", "", false));
+    assert!(matches("s3:get*", "s3:GetObject", true));
+    assert!(!matches("s3:get*", "s3:GetObject", false));
+    assert!(!matches("photos/*.jpg", "photos/a.png", false));
+    assert!(!matches("ph?tos", "phtos", false));
+
+    // would take ages when every `*` is retried at every position
+    let key = "a".repeat(100_000);
+    let pattern = format!("{}b", "*a".repeat(20));
+    assert!(!matches(&pattern, &key, false));
+    assert!(matches(
+        &format!("{}*", pattern),
+        &format!("{}b", key),
+        false
+    ));
+}
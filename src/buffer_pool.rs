@@ -0,0 +1,155 @@
+use bytes::{Bytes, BytesMut};
+use serde::Deserialize;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BufferPoolConfig {
+    /// size of the buffers, upload chunks are gathered up to this size before being written
+    #[serde(default = "default_buffer_size")]
+    pub buffer_size: usize,
+    /// maximum amount of idle buffers that are kept around
+    #[serde(default = "default_max_buffers")]
+    pub max_buffers: usize,
+}
+
+impl Default for BufferPoolConfig {
+    fn default() -> Self {
+        BufferPoolConfig {
+            buffer_size: default_buffer_size(),
+            max_buffers: default_max_buffers(),
+        }
+    }
+}
+
+fn default_buffer_size() -> usize {
+    256 * 1024
+}
+
+fn default_max_buffers() -> usize {
+    64
+}
+
+/// Pool of `BytesMut` buffers that are reused between requests.
+#[derive(Debug)]
+pub struct BufferPool {
+    buffers: Mutex<Vec<BytesMut>>,
+    buffer_size: usize,
+    max_buffers: usize,
+}
+
+impl BufferPool {
+    pub fn new(config: &BufferPoolConfig) -> BufferPool {
+        BufferPool {
+            buffers: Mutex::new(Vec::new()),
+            buffer_size: config.buffer_size.max(1),
+            max_buffers: config.max_buffers,
+        }
+    }
+
+    /// Takes an idle buffer from the pool, or allocates a new one.
+    pub fn get(self: &Arc<Self>) -> PooledBuffer {
+        let buffer = self
+            .buffers
+            .lock()
+            .expect("lock is not poisoned")
+            .pop()
+            .unwrap_or_else(|| BytesMut::with_capacity(self.buffer_size));
+
+        PooledBuffer {
+            buffer,
+            pool: self.clone(),
+        }
+    }
+
+    fn put(&self, mut buffer: BytesMut) {
+        let mut buffers = self.buffers.lock().expect("lock is not poisoned");
+        if buffers.len() >= self.max_buffers {
+            return;
+        }
+
+        buffer.clear();
+        // reclaims the allocation when all bytes taken from the buffer are dropped
+        buffer.reserve(self.buffer_size);
+        buffers.push(buffer);
+    }
+}
+
+/// Buffer that goes back to its pool when dropped.
+#[derive(Debug)]
+pub struct PooledBuffer {
+    buffer: BytesMut,
+    pool: Arc<BufferPool>,
+}
+
+impl PooledBuffer {
+    pub fn is_full(&self) -> bool {
+        self.buffer.len() >= self.pool.buffer_size
+    }
+
+    /// Takes the gathered bytes out of the buffer.
+    ///
+    /// Once the returned `Bytes` are dropped the allocation is reclaimed by the next write,
+    /// so the buffer does not have to allocate again.
+    pub fn take(&mut self) -> Bytes {
+        self.buffer.split().freeze()
+    }
+}
+
+impl Deref for PooledBuffer {
+    type Target = BytesMut;
+
+    fn deref(&self) -> &Self::Target {
+        &self.buffer
+    }
+}
+
+impl DerefMut for PooledBuffer {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buffer
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        self.pool.put(std::mem::take(&mut self.buffer));
+    }
+}
+
+#[test]
+fn buffers_are_reused() {
+    let pool = Arc::new(BufferPool::new(&BufferPoolConfig {
+        buffer_size: 16,
+        max_buffers: 1,
+    }));
+
+    let mut buffer = pool.get();
+    buffer.extend_from_slice(b"hello");
+    let address = buffer.as_ptr();
+    drop(buffer);
+
+    let buffer = pool.get();
+    assert!(buffer.is_empty());
+    assert_eq!(buffer.as_ptr(), address);
+}
+
+#[test]
+fn take_reclaims_the_allocation() {
+    let pool = Arc::new(BufferPool::new(&BufferPoolConfig {
+        buffer_size: 16,
+        max_buffers: 1,
+    }));
+
+    let mut buffer = pool.get();
+    buffer.extend_from_slice(&[1; 16]);
+    assert!(buffer.is_full());
+
+    let bytes = buffer.take();
+    let address = bytes.as_ptr();
+    assert_eq!(bytes.len(), 16);
+    drop(bytes);
+
+    buffer.extend_from_slice(&[2; 16]);
+    assert_eq!(buffer.as_ptr(), address);
+    assert_eq!(&buffer.take()[..], &[2; 16]);
+}
@@ -0,0 +1,698 @@
+use crate::auth::{AuthProvider, MetadataAuthProvider};
+use crate::axum_ext::{RouterExt, Subresources};
+use crate::events::{EventHooks, ObjectEventHook};
+use crate::metadata::MetadataStore;
+use crate::plugins::{Interceptors, RequestInterceptor};
+use crate::transforms::{ObjectTransform, Transforms};
+use crate::{
+    accounting, acme, api, audit, buffer_pool, chaos, circuit_breaker, coalescing, compression,
+    context, cors, credentials, default_buckets, domains, etag_cache, kafka, lease, listing_cache,
+    load_shedding, metrics, nats, plugins, public, rate_limit, read_cache, replication, sampling,
+    signature, sqs, strict, versioning, AppState, Config, REQUEST_ID_HEADER,
+};
+use anyhow::Context;
+use axum::extract::{DefaultBodyLimit, Request};
+use axum::handler::Handler;
+use axum::middleware;
+use axum::response::IntoResponse;
+use axum::routing::{get, post, put, Route};
+use axum::Router;
+use deadpool_redis::Pool;
+use opendal::{Operator, Scheme};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::Arc;
+use tower::{Layer, Service, ServiceBuilder};
+use tower_http::catch_panic::CatchPanicLayer;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tower_http::trace::TraceLayer;
+use tracing::Level;
+
+/// Builds a `Config` without environment variables, every option starts at its default.
+///
+/// ```no_run
+/// let config = s3_proxy::Config::builder()
+///     .opendal(opendal::Scheme::Memory, Default::default())
+///     .redis_url("redis://127.0.0.1:6379")
+///     .build();
+/// ```
+#[derive(Debug)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl Default for ConfigBuilder {
+    fn default() -> Self {
+        ConfigBuilder {
+            config: Config {
+                server_host: crate::default_host(),
+                external_server_host: crate::default_external_host(),
+                redis: None,
+                redis_pool: Default::default(),
+                opendal_provider: Scheme::Memory,
+                opendal: HashMap::new(),
+                default_buckets: Vec::new(),
+                strict: false,
+                verify_integrity: false,
+                list_stat_concurrency: crate::default_list_stat_concurrency(),
+                log_level: crate::default_log_level(),
+                log_stdout: true,
+                log_file: None,
+                slow_request_threshold_ms: None,
+                trace_sampling: Default::default(),
+                sentry_dsn: None,
+                sentry_environment: None,
+                admin_server_host: None,
+                admin_token: None,
+                audit: None,
+                kafka: None,
+                nats: None,
+                sqs: None,
+                replication: None,
+                metrics: Default::default(),
+                etag_cache: Default::default(),
+                read_cache: Default::default(),
+                listing_cache: Default::default(),
+                load_shedding: Default::default(),
+                rate_limit: Default::default(),
+                coalescing: Default::default(),
+                circuit_breaker: Default::default(),
+                http: Default::default(),
+                http3: None,
+                compression: Default::default(),
+                credentials: Default::default(),
+                buffer_pool: Default::default(),
+                hashing: Default::default(),
+                accounting: Default::default(),
+                trash: Default::default(),
+                expiration: Default::default(),
+                inventory: Default::default(),
+                lifecycle: Default::default(),
+                leases: Default::default(),
+                public: Default::default(),
+                domains: Default::default(),
+                cors: None,
+                acme: None,
+                chaos: None,
+            },
+        }
+    }
+}
+
+impl ConfigBuilder {
+    pub fn server_host(mut self, server_host: impl Into<String>) -> Self {
+        self.config.server_host = server_host.into();
+        self
+    }
+
+    /// The host clients use to reach the proxy, it is part of the signed request.
+    pub fn external_server_host(mut self, external_server_host: impl Into<String>) -> Self {
+        self.config.external_server_host = external_server_host.into();
+        self
+    }
+
+    pub fn redis(mut self, redis: deadpool_redis::Config) -> Self {
+        self.config.redis = Some(redis);
+        self
+    }
+
+    pub fn redis_url(self, url: impl Into<String>) -> Self {
+        self.redis(deadpool_redis::Config::from_url(url))
+    }
+
+    /// The storage backend and its options, see `--backends` for the available schemes.
+    pub fn opendal(mut self, scheme: Scheme, options: HashMap<String, String>) -> Self {
+        self.config.opendal_provider = scheme;
+        self.config.opendal = options;
+        self
+    }
+
+    pub fn log_level(mut self, log_level: Level) -> Self {
+        self.config.log_level = log_level;
+        self
+    }
+
+    pub fn admin(mut self, admin_server_host: impl Into<String>, token: impl Into<String>) -> Self {
+        self.config.admin_server_host = Some(admin_server_host.into());
+        self.config.admin_token = Some(token.into());
+        self
+    }
+
+    /// Escape hatch for the options that do not have their own method.
+    pub fn with(mut self, f: impl FnOnce(&mut Config)) -> Self {
+        f(&mut self.config);
+        self
+    }
+
+    pub fn build(self) -> Config {
+        self.config
+    }
+}
+
+type RouterLayer = Box<dyn FnOnce(Router<AppState>) -> Router<AppState> + Send>;
+
+/// Builds the S3 router with extra layers around the handlers.
+///
+/// From the outside in a request passes the `pre_auth` layers, the signature check, the rate
+/// limit, chaos injection, the interceptors, strict mode, the `versionId` check, the `post_auth`
+/// layers and the `pre_backend` layers before it reaches the handler. Layers are only called for
+/// requests that match an S3 route. After authentication the
+/// `signature::Identity` is available in the request extensions.
+///
+/// ```no_run
+/// # fn example(app_state: s3_proxy::AppState) -> axum::Router {
+/// s3_proxy::builder::RouterBuilder::new(app_state)
+///     .post_auth(axum::middleware::from_fn(
+///         |req: axum::extract::Request, next: axum::middleware::Next| async move {
+///             let identity = req.extensions().get::<s3_proxy::signature::Identity>().cloned();
+///             tracing::info!(?identity, "authenticated");
+///             next.run(req).await
+///         },
+///     ))
+///     .build()
+/// # }
+/// ```
+pub struct RouterBuilder {
+    app_state: AppState,
+    pre_auth: Vec<RouterLayer>,
+    post_auth: Vec<RouterLayer>,
+    pre_backend: Vec<RouterLayer>,
+}
+
+macro_rules! hook {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        pub fn $name<L>(mut self, layer: L) -> Self
+        where
+            L: Layer<Route> + Clone + Send + 'static,
+            L::Service: Service<Request> + Clone + Send + 'static,
+            <L::Service as Service<Request>>::Response: IntoResponse + 'static,
+            <L::Service as Service<Request>>::Error: Into<Infallible> + 'static,
+            <L::Service as Service<Request>>::Future: Send + 'static,
+        {
+            self.$name
+                .push(Box::new(move |router: Router<AppState>| router.route_layer(layer)));
+            self
+        }
+    };
+}
+
+impl RouterBuilder {
+    pub fn new(app_state: AppState) -> RouterBuilder {
+        RouterBuilder {
+            app_state,
+            pre_auth: Vec::new(),
+            post_auth: Vec::new(),
+            pre_backend: Vec::new(),
+        }
+    }
+
+    hook!(
+        /// Runs before the signature is checked, e.g. to rewrite requests of older clients.
+        pre_auth
+    );
+    hook!(
+        /// Runs after the signature is checked and the built-in request checks passed, e.g. for
+        /// extra authorization rules.
+        post_auth
+    );
+    hook!(
+        /// Runs right before the handler, e.g. for per namespace rate limits.
+        pre_backend
+    );
+
+    pub fn build(self) -> Router {
+        let app_state = self.app_state;
+
+        let mut s3 = Router::new()
+            .route("/", get(api::list_buckets))
+            .bucket_route(
+                Subresources::new(
+                    get(api::list_objects)
+                        .put(api::create_bucket)
+                        .delete(api::delete_bucket)
+                        // browser uploads are limited by their policy instead
+                        .post(api::post_object.layer(DefaultBodyLimit::disable())),
+                )
+                .on("location", get(api::get_bucket_location))
+                .on("versions", get(api::list_object_versions))
+                .on(
+                    "versioning",
+                    get(api::get_bucket_versioning).put(api::put_bucket_versioning),
+                )
+                .on(
+                    "object-lock",
+                    get(api::get_object_lock_configuration).put(api::put_object_lock_configuration),
+                )
+                .on("delete", post(api::delete_objects))
+                .on(
+                    "notification",
+                    get(api::get_bucket_notification).put(api::put_bucket_notification),
+                )
+                .on(
+                    "inventory",
+                    get(api::get_bucket_inventory)
+                        .put(api::put_bucket_inventory)
+                        .delete(api::delete_bucket_inventory),
+                )
+                .on(
+                    "lifecycle",
+                    get(api::get_bucket_lifecycle)
+                        .put(api::put_bucket_lifecycle)
+                        .delete(api::delete_bucket_lifecycle),
+                )
+                .on(
+                    "replication",
+                    get(api::get_bucket_replication)
+                        .put(api::put_bucket_replication)
+                        .delete(api::delete_bucket_replication),
+                )
+                .on(
+                    "tagging",
+                    get(api::get_bucket_tagging)
+                        .put(api::put_bucket_tagging)
+                        .delete(api::delete_bucket_tagging),
+                )
+                .on(
+                    "cors",
+                    get(api::get_bucket_cors)
+                        .put(api::put_bucket_cors)
+                        .delete(api::delete_bucket_cors),
+                )
+                .on(
+                    "policy",
+                    get(api::get_bucket_policy)
+                        .put(api::put_bucket_policy)
+                        .delete(api::delete_bucket_policy),
+                )
+                .on("acl", get(api::get_bucket_acl).put(api::put_bucket_acl)),
+            )
+            .object_route(
+                Subresources::new(
+                    get(api::get_object)
+                        .layer(compression::layer(&app_state.config.compression))
+                        .layer(middleware::map_response(compression::weaken_etag))
+                        .put(api::create_object)
+                        .delete(api::delete_object),
+                )
+                .on("attributes", get(api::get_object_attributes))
+                .on("select", post(api::select_object_content))
+                .on("uploads", post(api::create_multipart_upload))
+                .on(
+                    "uploadId",
+                    put(api::upload_part).post(api::complete_multipart_upload),
+                )
+                .on(
+                    "tagging",
+                    get(api::get_object_tagging)
+                        .put(api::put_object_tagging)
+                        .delete(api::delete_object_tagging),
+                )
+                .on("acl", get(api::get_object_acl).put(api::put_object_acl))
+                .on(
+                    "retention",
+                    get(api::get_object_retention).put(api::put_object_retention),
+                )
+                .on(
+                    "legal-hold",
+                    get(api::get_object_legal_hold).put(api::put_object_legal_hold),
+                ),
+            );
+
+        // the layers added last are the outermost, so the first registered layer runs first
+        for layer in self.pre_backend.into_iter().rev() {
+            s3 = layer(s3);
+        }
+        for layer in self.post_auth.into_iter().rev() {
+            s3 = layer(s3);
+        }
+        s3 = s3.route_layer(middleware::from_fn(versioning::reject_version_ids));
+        if app_state.config.strict {
+            s3 = s3.route_layer(middleware::from_fn(strict::validate));
+        }
+        if !app_state.interceptors.is_empty() {
+            s3 = s3.route_layer(middleware::from_fn_with_state(
+                app_state.clone(),
+                plugins::intercept,
+            ));
+        }
+        if app_state.chaos.is_some() {
+            s3 = s3.route_layer(middleware::from_fn_with_state(
+                app_state.clone(),
+                chaos::inject,
+            ));
+        }
+        s3 = s3.route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            rate_limit::limit,
+        ));
+        s3 = s3.route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            signature::authenticate,
+        ));
+        for layer in self.pre_auth.into_iter().rev() {
+            s3 = layer(s3);
+        }
+
+        let router = Router::new()
+            .route("/_metadata", get(crate::asdfg))
+            .route("/_public/:namespace/:bucket/*key", get(public::get_object))
+            .route("/.well-known/acme-challenge/:token", get(acme::challenge))
+            .merge(s3)
+            .layer(
+                ServiceBuilder::new()
+                    .layer(SetRequestIdLayer::new(
+                        REQUEST_ID_HEADER.clone(),
+                        MakeRequestUuid,
+                    ))
+                    .layer(PropagateRequestIdLayer::new(REQUEST_ID_HEADER.clone()))
+                    .layer(
+                        TraceLayer::new_for_http()
+                            .make_span_with(sampling::SampledMakeSpan::new(
+                                app_state.config.trace_sampling.clone(),
+                            ))
+                            // failures are reported with their context by the request tracking
+                            .on_failure(()),
+                    )
+                    .layer(middleware::from_fn_with_state(
+                        app_state.clone(),
+                        context::track,
+                    ))
+                    // preflights are unsigned and not S3 requests, the context tells the
+                    // namespace of signed requests once they are handled
+                    .layer(middleware::from_fn_with_state(
+                        app_state.clone(),
+                        cors::handle,
+                    ))
+                    .layer(middleware::from_fn_with_state(
+                        app_state.clone(),
+                        load_shedding::limit,
+                    ))
+                    .layer(middleware::from_fn_with_state(
+                        app_state.clone(),
+                        circuit_breaker::guard,
+                    ))
+                    .layer(CatchPanicLayer::new()),
+            )
+            .with_state(app_state.clone());
+
+        // custom domains choose the bucket before routing, so they wrap the whole router
+        Router::new().fallback_service(
+            middleware::from_fn_with_state(app_state, domains::rewrite).layer(router),
+        )
+    }
+}
+
+/// Builds an `AppState`, existing metadata stores and operators can be passed in instead of
+/// being created from the config.
+pub struct AppStateBuilder {
+    config: Config,
+    metadata: Option<MetadataStore>,
+    opendal_operator: Option<Operator>,
+    event_hooks: Vec<Arc<dyn ObjectEventHook>>,
+    interceptors: Vec<Arc<dyn RequestInterceptor>>,
+    transforms: Vec<(String, String, Arc<dyn ObjectTransform>)>,
+    auth: Option<Arc<dyn AuthProvider>>,
+}
+
+impl AppStateBuilder {
+    pub fn new(config: Config) -> AppStateBuilder {
+        AppStateBuilder {
+            config,
+            metadata: None,
+            opendal_operator: None,
+            event_hooks: Vec::new(),
+            interceptors: Vec::new(),
+            transforms: Vec::new(),
+            auth: None,
+        }
+    }
+
+    pub fn metadata(mut self, metadata: MetadataStore) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    pub fn metadata_pool(self, metadata_pool: Pool) -> Self {
+        self.metadata(MetadataStore::Redis(metadata_pool))
+    }
+
+    pub fn opendal_operator(mut self, opendal_operator: Operator) -> Self {
+        self.opendal_operator = Some(opendal_operator);
+        self
+    }
+
+    /// Registers a hook that is called after objects and buckets are changed or read.
+    pub fn event_hook(mut self, hook: impl ObjectEventHook + 'static) -> Self {
+        self.event_hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Registers an interceptor that can change or reject requests after the signature check.
+    pub fn interceptor(mut self, interceptor: impl RequestInterceptor + 'static) -> Self {
+        self.interceptors.push(Arc::new(interceptor));
+        self
+    }
+
+    /// Registers a transform for the GETs of objects below `prefix` in buckets named `bucket`.
+    pub fn object_transform(
+        mut self,
+        bucket: impl Into<String>,
+        prefix: impl Into<String>,
+        transform: impl ObjectTransform + 'static,
+    ) -> Self {
+        self.transforms
+            .push((bucket.into(), prefix.into(), Arc::new(transform)));
+        self
+    }
+
+    /// Replaces the secret key lookup in the metadata store with another user system.
+    pub fn auth_provider(mut self, auth: impl AuthProvider + 'static) -> Self {
+        self.auth = Some(Arc::new(auth));
+        self
+    }
+
+    pub fn build(self) -> anyhow::Result<AppState> {
+        let config = self.config;
+
+        anyhow::ensure!(
+            config.admin_server_host.is_none() || config.admin_token.is_some(),
+            "admin_token is required when admin_server_host is set, \
+             set S3_PROXY__ADMIN_TOKEN or use ConfigBuilder::admin"
+        );
+
+        if let Some(bucket) = config
+            .default_buckets
+            .iter()
+            .find(|x| !crate::namespaces::is_valid_name(x))
+        {
+            anyhow::bail!(
+                "invalid bucket {} in S3_PROXY__DEFAULT_BUCKETS, \
+                 buckets are 1 to 63 letters, digits, '-' or '_'",
+                bucket
+            );
+        }
+
+        let metadata = match (self.metadata, &config.redis) {
+            (Some(metadata), _) => metadata,
+            (None, Some(redis_config)) => {
+                let mut redis_config = redis_config.clone();
+                config.redis_pool.apply(&mut redis_config);
+                MetadataStore::Redis(
+                    redis_config
+                        .create_pool(Some(deadpool_redis::Runtime::Tokio1))
+                        .context(
+                            "unable to create the redis pool, \
+                             check the S3_PROXY__REDIS__* settings",
+                        )?,
+                )
+            }
+            (None, None) => anyhow::bail!(
+                "no metadata store configured, set S3_PROXY__REDIS__URL \
+                 or use ConfigBuilder::redis_url"
+            ),
+        };
+
+        let opendal_operator = match self.opendal_operator {
+            Some(operator) => operator,
+            None => Operator::via_map(config.opendal_provider, config.opendal.clone())
+                .with_context(|| {
+                    format!(
+                        "unable to create the {} storage backend, \
+                         check the S3_PROXY__OPENDAL__* settings",
+                        config.opendal_provider
+                    )
+                })?,
+        };
+
+        let audit = match &config.audit {
+            Some(audit_config) => Some(Arc::new(
+                audit::AuditLog::start(audit_config.clone(), &metadata).context(
+                    "unable to start the audit log, check the S3_PROXY__AUDIT__* settings",
+                )?,
+            )),
+            None => None,
+        };
+
+        let leases = Arc::new(lease::Leases::new(&config.leases, &metadata));
+        let mut event_hooks = self.event_hooks;
+        if let Some(kafka_config) = &config.kafka {
+            event_hooks.push(Arc::new(kafka::start(kafka_config).context(
+                "unable to start the kafka publisher, check the S3_PROXY__KAFKA__* settings",
+            )?));
+        }
+        if let Some(nats_config) = &config.nats {
+            event_hooks.push(Arc::new(nats::start(nats_config).context(
+                "unable to start the nats publisher, check the S3_PROXY__NATS__* settings",
+            )?));
+        }
+        if let Some(sqs_config) = &config.sqs {
+            event_hooks.push(Arc::new(sqs::start(sqs_config, &metadata).context(
+                "unable to start the sqs publisher, check the S3_PROXY__SQS__* settings",
+            )?));
+        }
+        if let Some(replication_config) = &config.replication {
+            event_hooks.push(Arc::new(
+                replication::Replicator::start(
+                    replication_config,
+                    &opendal_operator,
+                    &metadata,
+                    &leases,
+                )
+                .context(
+                    "unable to start the replication, check the S3_PROXY__REPLICATION__* settings",
+                )?,
+            ));
+        }
+
+        let metrics = Arc::new(metrics::Metrics::new(&config.metrics)?);
+        let etag_cache = etag_cache::EtagCache::new(&config.etag_cache);
+        let read_cache = read_cache::ReadCache::new(&config.read_cache)
+            .context("unable to prepare S3_PROXY__READ_CACHE__DIR")?;
+        let listing_cache = listing_cache::ListingCache::new(&config.listing_cache);
+        let load_shedder = Arc::new(
+            load_shedding::LoadShedder::new(&config.load_shedding).with_context(|| {
+                format!(
+                    "invalid S3_PROXY__LOAD_SHEDDING__OPERATIONS, the operations are: {}",
+                    crate::operation::S3Operation::ALL
+                        .iter()
+                        .map(|x| x.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })?,
+        );
+        let rate_limiter = Arc::new(rate_limit::RateLimiter::new(&config.rate_limit, &metadata));
+        let coalescer = coalescing::Coalescer::new(&config.coalescing);
+        let circuit_breaker = Arc::new(circuit_breaker::CircuitBreaker::new(
+            &config.circuit_breaker,
+        ));
+        let credentials = credentials::CredentialsCache::new(&config.credentials);
+        let buffer_pool = Arc::new(buffer_pool::BufferPool::new(&config.buffer_pool));
+        let sha256 = config.hashing.sha256.select()?;
+        let accounting = Arc::new(accounting::Accounting::new(&config.accounting));
+        let domains = domains::Domains::new(&config.domains);
+        let chaos = config
+            .chaos
+            .as_ref()
+            .map(|x| chaos::Chaos::new(x).map(Arc::new))
+            .transpose()
+            .context("invalid S3_PROXY__CHAOS__* settings")?;
+        let config_default_buckets = config.default_buckets.clone();
+
+        let auth = self.auth.unwrap_or_else(|| {
+            Arc::new(MetadataAuthProvider::new(
+                metadata.clone(),
+                credentials.clone(),
+            ))
+        });
+
+        Ok(AppState {
+            metadata,
+            config: Arc::new(config),
+            opendal_operator,
+            audit,
+            metrics,
+            etag_cache,
+            read_cache,
+            listing_cache,
+            load_shedder,
+            rate_limiter,
+            leases,
+            chaos,
+            circuit_breaker,
+            coalescer,
+            auth,
+            credentials,
+            buffer_pool,
+            sha256,
+            accounting,
+            event_hooks: EventHooks::new(event_hooks),
+            interceptors: Interceptors::new(self.interceptors),
+            transforms: Transforms::new(self.transforms),
+            domains,
+            default_buckets: default_buckets::DefaultBuckets::new(config_default_buckets),
+        })
+    }
+}
+
+#[tokio::test]
+async fn app_state_from_built_config() {
+    let config = Config::builder()
+        .opendal(Scheme::Memory, HashMap::new())
+        .redis_url("redis://127.0.0.1:6379")
+        .with(|config| config.list_stat_concurrency = 4)
+        .build();
+
+    let app_state = AppState::builder(config).build().unwrap();
+    assert_eq!(app_state.config.list_stat_concurrency, 4);
+
+    let error = AppState::builder(Config::builder().build())
+        .build()
+        .err()
+        .unwrap();
+    assert!(error.to_string().contains("S3_PROXY__REDIS__URL"));
+}
+
+#[tokio::test]
+async fn router_hooks_run_around_authentication() {
+    use axum::body::Body;
+    use axum::http::StatusCode;
+    use tower::ServiceExt;
+
+    let config = Config::builder()
+        .redis_url("redis://127.0.0.1:6379")
+        .build();
+    let app_state = AppState::builder(config).build().unwrap();
+
+    let app = RouterBuilder::new(app_state)
+        .pre_auth(middleware::from_fn(|req: Request, next: middleware::Next| async move {
+            if req.headers().contains_key("x-blocked") {
+                return StatusCode::FORBIDDEN.into_response();
+            }
+            next.run(req).await
+        }))
+        .post_auth(middleware::from_fn(|_: Request, _: middleware::Next| async move {
+            StatusCode::IM_A_TEAPOT.into_response()
+        }))
+        .build();
+
+    let blocked = Request::get("/")
+        .header("x-blocked", "1")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(blocked).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // without a signature the request never reaches the post_auth layer
+    let unsigned = Request::get("/").body(Body::empty()).unwrap();
+    let response = app.oneshot(unsigned).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let body = std::str::from_utf8(&body).unwrap();
+    assert!(body.contains("<Code>AccessDenied</Code>"), "{body}");
+    assert!(body.contains("<Resource>/</Resource>"), "{body}");
+}
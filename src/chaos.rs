@@ -0,0 +1,283 @@
+use crate::error::S3Error;
+use crate::signature::Identity;
+use crate::AppState;
+use axum::body::Body;
+use axum::extract::{Request, State};
+use axum::http::header::CONTENT_LENGTH;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use bytes::Bytes;
+use futures::StreamExt;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::Deserialize;
+use std::time::Duration;
+
+/// Faults injected into the responses of the S3 api, to test how clients cope with them.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChaosConfig {
+    /// fraction of the requests that get a fault, between 0.0 and 1.0
+    pub rate: f64,
+    /// `latency`, `internal_error`, `slow_down`, `truncate` or `slow_body`, one is picked at
+    /// random per faulty request
+    #[serde(
+        default = "default_faults",
+        deserialize_with = "crate::comma_separated"
+    )]
+    pub faults: Vec<String>,
+    /// only requests signed with this access key get faults, other clients are left alone
+    pub access_key: Option<String>,
+    /// delay before the request is handled for `latency`
+    #[serde(default = "default_latency_ms")]
+    pub latency_ms: u64,
+    /// `slow_body` sends chunks of this size with `slow_body_delay_ms` between them
+    #[serde(default = "default_slow_body_chunk_bytes")]
+    pub slow_body_chunk_bytes: usize,
+    #[serde(default = "default_slow_body_delay_ms")]
+    pub slow_body_delay_ms: u64,
+}
+
+fn default_faults() -> Vec<String> {
+    Fault::ALL.iter().map(|x| x.as_str().to_string()).collect()
+}
+
+fn default_latency_ms() -> u64 {
+    2000
+}
+
+fn default_slow_body_chunk_bytes() -> usize {
+    1024
+}
+
+fn default_slow_body_delay_ms() -> u64 {
+    100
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// the request is handled after a delay
+    Latency,
+    /// `500 InternalError` without handling the request
+    InternalError,
+    /// `503 SlowDown` without handling the request
+    SlowDown,
+    /// the body ends halfway and the connection is closed
+    Truncate,
+    /// the body is sent in small chunks with a delay in between
+    SlowBody,
+}
+
+impl Fault {
+    pub const ALL: &'static [Fault] = &[
+        Fault::Latency,
+        Fault::InternalError,
+        Fault::SlowDown,
+        Fault::Truncate,
+        Fault::SlowBody,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Fault::Latency => "latency",
+            Fault::InternalError => "internal_error",
+            Fault::SlowDown => "slow_down",
+            Fault::Truncate => "truncate",
+            Fault::SlowBody => "slow_body",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Fault> {
+        Fault::ALL
+            .iter()
+            .find(|x| x.as_str().eq_ignore_ascii_case(name))
+            .copied()
+    }
+}
+
+/// The validated `S3_PROXY__CHAOS__*` settings.
+#[derive(Debug)]
+pub struct Chaos {
+    config: ChaosConfig,
+    faults: Vec<Fault>,
+}
+
+impl Chaos {
+    pub fn new(config: &ChaosConfig) -> anyhow::Result<Chaos> {
+        anyhow::ensure!(
+            (0.0..=1.0).contains(&config.rate),
+            "the rate has to be between 0.0 and 1.0"
+        );
+        let faults = config
+            .faults
+            .iter()
+            .map(|name| {
+                Fault::parse(name).ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "unknown fault {}, the faults are: {}",
+                        name,
+                        default_faults().join(", ")
+                    )
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        anyhow::ensure!(!faults.is_empty(), "no faults are configured");
+
+        Ok(Chaos {
+            config: config.clone(),
+            faults,
+        })
+    }
+
+    /// The fault for a request of `access_key`, if it gets one.
+    pub fn pick(&self, access_key: &str) -> Option<Fault> {
+        if self
+            .config
+            .access_key
+            .as_ref()
+            .is_some_and(|x| x != access_key)
+        {
+            return None;
+        }
+
+        let mut rng = rand::thread_rng();
+        if !rng.gen_bool(self.config.rate) {
+            return None;
+        }
+        self.faults.choose(&mut rng).copied()
+    }
+}
+
+/// Injects the configured faults, runs after authentication so they can be limited to a key.
+pub async fn inject(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let (Some(chaos), Some(identity)) = (&state.chaos, req.extensions().get::<Identity>()) else {
+        return next.run(req).await;
+    };
+    let Some(fault) = chaos.pick(&identity.access_key) else {
+        return next.run(req).await;
+    };
+    tracing::info!(
+        "injecting {} into {} {}",
+        fault.as_str(),
+        req.method(),
+        req.uri()
+    );
+
+    match fault {
+        Fault::Latency => {
+            tokio::time::sleep(Duration::from_millis(chaos.config.latency_ms)).await;
+            next.run(req).await
+        }
+        Fault::InternalError => S3Error::InternalError("injected fault".into()).into_response(),
+        Fault::SlowDown => S3Error::SlowDown.into_response(),
+        Fault::Truncate => {
+            let response = next.run(req).await;
+            let length = response
+                .headers()
+                .get(CONTENT_LENGTH)
+                .and_then(|x| x.to_str().ok()?.parse::<u64>().ok());
+            let Some(length) = length.filter(|x| *x > 1) else {
+                return response;
+            };
+            let (parts, body) = response.into_parts();
+            Response::from_parts(parts, Body::from_stream(truncate(body, length / 2)))
+        }
+        Fault::SlowBody => {
+            let (parts, body) = next.run(req).await.into_parts();
+            let body = slow(
+                body,
+                chaos.config.slow_body_chunk_bytes.max(1),
+                Duration::from_millis(chaos.config.slow_body_delay_ms),
+            );
+            Response::from_parts(parts, Body::from_stream(body))
+        }
+    }
+}
+
+/// Sends the first `limit` bytes and then fails, which closes the connection mid-body.
+fn truncate(
+    body: Body,
+    limit: u64,
+) -> impl futures::Stream<Item = Result<Bytes, axum::Error>> + Send + 'static {
+    body.into_data_stream()
+        .scan(limit as usize, |remaining, chunk| {
+            let chunk = match chunk {
+                _ if *remaining == 0 => None,
+                Ok(chunk) => {
+                    let chunk = chunk.slice(..chunk.len().min(*remaining));
+                    *remaining -= chunk.len();
+                    Some(Ok(chunk))
+                }
+                Err(error) => Some(Err(error)),
+            };
+            futures::future::ready(chunk)
+        })
+        .chain(futures::stream::once(async {
+            Err(axum::Error::new("injected truncation"))
+        }))
+}
+
+/// Splits the body into `chunk_size` pieces with `delay` before each of them.
+fn slow(
+    body: Body,
+    chunk_size: usize,
+    delay: Duration,
+) -> impl futures::Stream<Item = Result<Bytes, axum::Error>> + Send + 'static {
+    body.into_data_stream()
+        .flat_map(move |chunk| {
+            let pieces: Vec<Result<Bytes, axum::Error>> = match chunk {
+                Ok(chunk) => (0..chunk.len())
+                    .step_by(chunk_size)
+                    .map(|start| Ok(chunk.slice(start..chunk.len().min(start + chunk_size))))
+                    .collect(),
+                Err(error) => vec![Err(error)],
+            };
+            futures::stream::iter(pieces)
+        })
+        .then(move |piece| async move {
+            tokio::time::sleep(delay).await;
+            piece
+        })
+}
+
+#[test]
+fn faults_are_limited_to_the_test_key() {
+    let config = ChaosConfig {
+        rate: 1.0,
+        faults: vec![String::from("slow_down")],
+        access_key: Some(String::from("chaos-monkey")),
+        latency_ms: default_latency_ms(),
+        slow_body_chunk_bytes: default_slow_body_chunk_bytes(),
+        slow_body_delay_ms: default_slow_body_delay_ms(),
+    };
+    let chaos = Chaos::new(&config).unwrap();
+    assert_eq!(chaos.pick("chaos-monkey"), Some(Fault::SlowDown));
+    assert_eq!(chaos.pick("production"), None);
+
+    let never = Chaos::new(&ChaosConfig {
+        rate: 0.0,
+        access_key: None,
+        ..config.clone()
+    })
+    .unwrap();
+    assert_eq!(never.pick("production"), None);
+
+    assert!(Chaos::new(&ChaosConfig {
+        faults: vec![String::from("meteor")],
+        ..config
+    })
+    .is_err());
+}
+
+#[tokio::test]
+async fn truncated_bodies_fail_halfway() {
+    let chunks: Vec<_> = truncate(Body::from("0123456789"), 5).collect().await;
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(chunks[0].as_ref().unwrap(), &Bytes::from("01234"));
+    assert!(chunks[1].is_err());
+
+    let chunks: Vec<_> = slow(Body::from("0123456789"), 4, Duration::ZERO)
+        .map(Result::unwrap)
+        .collect()
+        .await;
+    assert_eq!(chunks, vec!["0123", "4567", "89"]);
+}
@@ -0,0 +1,246 @@
+use crate::error::S3Error;
+use crate::metadata::{MetadataError, MetadataStore};
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use sha1::Digest;
+
+/// `checksum::{namespace}/{bucket}/{key}` holds `{algorithm} {base64 checksum}` of the upload
+pub const CHECKSUM_PREFIX: &str = "checksum::";
+/// The algorithm of a checksum the client sends in a trailer, or wants computed.
+pub static SDK_ALGORITHM_HEADER: HeaderName =
+    HeaderName::from_static("x-amz-sdk-checksum-algorithm");
+pub static ALGORITHM_HEADER: HeaderName = HeaderName::from_static("x-amz-checksum-algorithm");
+/// `ENABLED` on `GET` and `HEAD` returns the stored checksum.
+pub static MODE_HEADER: HeaderName = HeaderName::from_static("x-amz-checksum-mode");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    Crc32,
+    Crc32c,
+    Sha1,
+    Sha256,
+}
+
+impl ChecksumAlgorithm {
+    pub const ALL: &'static [ChecksumAlgorithm] = &[
+        ChecksumAlgorithm::Crc32,
+        ChecksumAlgorithm::Crc32c,
+        ChecksumAlgorithm::Sha1,
+        ChecksumAlgorithm::Sha256,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Crc32 => "CRC32",
+            ChecksumAlgorithm::Crc32c => "CRC32C",
+            ChecksumAlgorithm::Sha1 => "SHA1",
+            ChecksumAlgorithm::Sha256 => "SHA256",
+        }
+    }
+
+    /// `x-amz-checksum-{algorithm}`, carries the checksum on uploads and reads.
+    pub fn header(&self) -> HeaderName {
+        HeaderName::from_static(match self {
+            ChecksumAlgorithm::Crc32 => "x-amz-checksum-crc32",
+            ChecksumAlgorithm::Crc32c => "x-amz-checksum-crc32c",
+            ChecksumAlgorithm::Sha1 => "x-amz-checksum-sha1",
+            ChecksumAlgorithm::Sha256 => "x-amz-checksum-sha256",
+        })
+    }
+
+    /// The element of the checksum in `GetObjectAttributes`.
+    pub fn element(&self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Crc32 => "ChecksumCRC32",
+            ChecksumAlgorithm::Crc32c => "ChecksumCRC32C",
+            ChecksumAlgorithm::Sha1 => "ChecksumSHA1",
+            ChecksumAlgorithm::Sha256 => "ChecksumSHA256",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<ChecksumAlgorithm> {
+        ChecksumAlgorithm::ALL
+            .iter()
+            .find(|x| x.as_str().eq_ignore_ascii_case(name))
+            .copied()
+    }
+
+    pub fn hasher(&self) -> ChecksumHasher {
+        match self {
+            ChecksumAlgorithm::Crc32 => ChecksumHasher::Crc32(crc32fast::Hasher::new()),
+            ChecksumAlgorithm::Crc32c => ChecksumHasher::Crc32c(0),
+            ChecksumAlgorithm::Sha1 => ChecksumHasher::Sha1(sha1::Sha1::new()),
+            ChecksumAlgorithm::Sha256 => ChecksumHasher::Sha256(sha2::Sha256::new()),
+        }
+    }
+}
+
+pub enum ChecksumHasher {
+    Crc32(crc32fast::Hasher),
+    Crc32c(u32),
+    Sha1(sha1::Sha1),
+    Sha256(sha2::Sha256),
+}
+
+impl ChecksumHasher {
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            ChecksumHasher::Crc32(hasher) => hasher.update(data),
+            ChecksumHasher::Crc32c(crc) => *crc = crc32c::crc32c_append(*crc, data),
+            ChecksumHasher::Sha1(hasher) => hasher.update(data),
+            ChecksumHasher::Sha256(hasher) => hasher.update(data),
+        }
+    }
+
+    /// The base64 checksum, the crcs are encoded big endian like S3 does.
+    pub fn finalize(self) -> String {
+        match self {
+            ChecksumHasher::Crc32(hasher) => STANDARD.encode(hasher.finalize().to_be_bytes()),
+            ChecksumHasher::Crc32c(crc) => STANDARD.encode(crc.to_be_bytes()),
+            ChecksumHasher::Sha1(hasher) => STANDARD.encode(hasher.finalize()),
+            ChecksumHasher::Sha256(hasher) => STANDARD.encode(hasher.finalize()),
+        }
+    }
+}
+
+/// The checksum an upload asked for, with the value the body has to match when it was sent
+/// as a header. A value in a trailer is not checked, the checksum is only computed then.
+pub fn requested(
+    headers: &HeaderMap,
+) -> Result<Option<(ChecksumAlgorithm, Option<String>)>, S3Error> {
+    for algorithm in ChecksumAlgorithm::ALL {
+        if let Some(value) = headers.get(algorithm.header()) {
+            let value = value.to_str().ok().filter(|x| STANDARD.decode(x).is_ok());
+            let Some(value) = value else {
+                return Err(S3Error::InvalidRequest(format!(
+                    "Value for {} header is invalid.",
+                    algorithm.header()
+                )));
+            };
+            return Ok(Some((*algorithm, Some(value.to_string()))));
+        }
+    }
+
+    let Some(name) = headers
+        .get(&SDK_ALGORITHM_HEADER)
+        .or_else(|| headers.get(&ALGORITHM_HEADER))
+    else {
+        return Ok(None);
+    };
+    match name.to_str().ok().and_then(ChecksumAlgorithm::parse) {
+        Some(algorithm) => Ok(Some((algorithm, None))),
+        None => Err(S3Error::InvalidRequest(String::from(
+            "Checksum algorithm provided is unsupported. Please try again with any of the valid \
+             types: [CRC32, CRC32C, SHA1, SHA256]",
+        ))),
+    }
+}
+
+/// Whether a read asked for the checksum with `x-amz-checksum-mode: ENABLED`.
+pub fn wanted(headers: &HeaderMap) -> bool {
+    headers
+        .get(&MODE_HEADER)
+        .is_some_and(|x| x.as_bytes().eq_ignore_ascii_case(b"ENABLED"))
+}
+
+pub async fn store(
+    metadata: &MetadataStore,
+    path: &str,
+    algorithm: ChecksumAlgorithm,
+    checksum: &str,
+) -> Result<(), MetadataError> {
+    metadata
+        .set(
+            &format!("{}{}", CHECKSUM_PREFIX, path),
+            &format!("{} {}", algorithm.as_str(), checksum),
+        )
+        .await
+}
+
+/// The algorithm and checksum stored for the object at `path`.
+pub async fn stored(
+    metadata: &MetadataStore,
+    path: &str,
+) -> Result<Option<(ChecksumAlgorithm, String)>, MetadataError> {
+    let Some(stored) = metadata
+        .get(&format!("{}{}", CHECKSUM_PREFIX, path))
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    Ok(stored.split_once(' ').and_then(|(algorithm, checksum)| {
+        Some((ChecksumAlgorithm::parse(algorithm)?, checksum.to_string()))
+    }))
+}
+
+/// The header and value of the checksum stored for the object at `path`.
+pub async fn header(
+    metadata: &MetadataStore,
+    path: &str,
+) -> Result<Option<(HeaderName, HeaderValue)>, MetadataError> {
+    Ok(stored(metadata, path)
+        .await?
+        .and_then(|(algorithm, checksum)| {
+            Some((algorithm.header(), HeaderValue::from_str(&checksum).ok()?))
+        }))
+}
+
+pub async fn remove(metadata: &MetadataStore, path: &str) -> Result<(), MetadataError> {
+    metadata
+        .delete(&format!("{}{}", CHECKSUM_PREFIX, path))
+        .await
+}
+
+#[test]
+fn checksums_match_the_s3_encoding() {
+    let checksum = |algorithm: ChecksumAlgorithm| {
+        let mut hasher = algorithm.hasher();
+        hasher.update(b"hello ");
+        hasher.update(b"world");
+        hasher.finalize()
+    };
+
+    assert_eq!(checksum(ChecksumAlgorithm::Crc32), "DUoRhQ==");
+    assert_eq!(checksum(ChecksumAlgorithm::Crc32c), "yZRlqg==");
+    assert_eq!(
+        checksum(ChecksumAlgorithm::Sha1),
+        "Kq5sNclPz7QV2+lfQIuc6R7oRu0="
+    );
+    assert_eq!(
+        checksum(ChecksumAlgorithm::Sha256),
+        "uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek="
+    );
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        ChecksumAlgorithm::Crc32c.header(),
+        HeaderValue::from_static("yZRlqg=="),
+    );
+    assert_eq!(
+        requested(&headers).unwrap(),
+        Some((ChecksumAlgorithm::Crc32c, Some(String::from("yZRlqg=="))))
+    );
+    headers.insert(
+        ChecksumAlgorithm::Crc32c.header(),
+        HeaderValue::from_static("not base64!"),
+    );
+    assert!(requested(&headers).is_err());
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        SDK_ALGORITHM_HEADER.clone(),
+        HeaderValue::from_static("sha1"),
+    );
+    assert_eq!(
+        requested(&headers).unwrap(),
+        Some((ChecksumAlgorithm::Sha1, None))
+    );
+    headers.insert(
+        SDK_ALGORITHM_HEADER.clone(),
+        HeaderValue::from_static("md4"),
+    );
+    assert!(requested(&headers).is_err());
+    assert_eq!(requested(&HeaderMap::new()).unwrap(), None);
+}
@@ -0,0 +1,204 @@
+use crate::error::S3Error;
+use crate::metadata::MetadataError;
+use crate::AppState;
+use axum::extract::{Request, State};
+use axum::http::header::RETRY_AFTER;
+use axum::http::HeaderValue;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CircuitBreakerConfig {
+    /// consecutive backend or metadata failures that open the circuit, not set disables it
+    pub failure_threshold: Option<u32>,
+    /// how long requests are rejected before one request probes for recovery
+    #[serde(default = "default_open_secs")]
+    pub open_secs: u64,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        CircuitBreakerConfig {
+            failure_threshold: None,
+            open_secs: default_open_secs(),
+        }
+    }
+}
+
+fn default_open_secs() -> u64 {
+    10
+}
+
+/// What a request failed to reach, added to the response extensions of internal errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dependency {
+    Backend,
+    Metadata,
+}
+
+impl Dependency {
+    pub fn of(error: &(dyn std::error::Error + 'static)) -> Option<Dependency> {
+        if error.is::<opendal::Error>() {
+            Some(Dependency::Backend)
+        } else if error.is::<MetadataError>()
+            || error.is::<deadpool_redis::PoolError>()
+            || error.is::<deadpool_redis::redis::RedisError>()
+        {
+            Some(Dependency::Metadata)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Dependency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Dependency::Backend => write!(f, "backend"),
+            Dependency::Metadata => write!(f, "metadata"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum CircuitState {
+    Closed {
+        failures: u32,
+    },
+    Open {
+        until: Instant,
+    },
+    /// one request is probing, another probe is let through when it does not finish in time
+    HalfOpen {
+        until: Instant,
+    },
+}
+
+/// Fails fast with `ServiceUnavailable` once the backend or metadata store keeps failing, so
+/// a dead dependency does not tie up every request in timeouts.
+pub struct CircuitBreaker {
+    failure_threshold: Option<u32>,
+    open: Duration,
+    state: Mutex<CircuitState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: &CircuitBreakerConfig) -> CircuitBreaker {
+        CircuitBreaker {
+            failure_threshold: config.failure_threshold.map(|x| x.max(1)),
+            open: Duration::from_secs(config.open_secs),
+            state: Mutex::new(CircuitState::Closed { failures: 0 }),
+        }
+    }
+
+    /// Returns how long to wait when the request is rejected.
+    pub fn try_pass(&self) -> Result<(), Duration> {
+        self.try_pass_at(Instant::now())
+    }
+
+    fn try_pass_at(&self, now: Instant) -> Result<(), Duration> {
+        let mut state = self.state.lock().expect("lock is not poisoned");
+        match *state {
+            CircuitState::Closed { .. } => Ok(()),
+            CircuitState::Open { until } | CircuitState::HalfOpen { until } if now < until => {
+                Err(until - now)
+            }
+            CircuitState::Open { .. } | CircuitState::HalfOpen { .. } => {
+                *state = CircuitState::HalfOpen {
+                    until: now + self.open,
+                };
+                Ok(())
+            }
+        }
+    }
+
+    /// Records the outcome of a request that passed, `None` is a success.
+    pub fn record(&self, failure: Option<Dependency>) {
+        let Some(threshold) = self.failure_threshold else {
+            return;
+        };
+        let mut state = self.state.lock().expect("lock is not poisoned");
+
+        let Some(dependency) = failure else {
+            if !matches!(*state, CircuitState::Closed { .. }) {
+                tracing::info!("circuit closed, the backend and metadata store recovered");
+            }
+            *state = CircuitState::Closed { failures: 0 };
+            return;
+        };
+
+        match *state {
+            CircuitState::Closed { failures } if failures + 1 < threshold => {
+                *state = CircuitState::Closed {
+                    failures: failures + 1,
+                };
+            }
+            // requests that started before the circuit opened do not extend it
+            CircuitState::Open { .. } => (),
+            CircuitState::Closed { .. } | CircuitState::HalfOpen { .. } => {
+                tracing::warn!(
+                    "circuit opened for {}s after {} failures",
+                    self.open.as_secs(),
+                    dependency
+                );
+                *state = CircuitState::Open {
+                    until: Instant::now() + self.open,
+                };
+            }
+        }
+    }
+}
+
+pub async fn guard(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let breaker = &state.circuit_breaker;
+    if breaker.failure_threshold.is_none() {
+        return next.run(req).await;
+    }
+
+    if let Err(retry_after) = breaker.try_pass() {
+        let mut response = S3Error::ServiceUnavailable.into_response();
+        response
+            .headers_mut()
+            .insert(RETRY_AFTER, HeaderValue::from(retry_after.as_secs().max(1)));
+        return response;
+    }
+
+    let response = next.run(req).await;
+    breaker.record(response.extensions().get::<Dependency>().copied());
+
+    response
+}
+
+#[test]
+fn circuit_opens_after_repeated_failures() {
+    let breaker = CircuitBreaker::new(&CircuitBreakerConfig {
+        failure_threshold: Some(2),
+        open_secs: 10,
+    });
+
+    breaker.record(Some(Dependency::Backend));
+    breaker.record(None);
+    breaker.record(Some(Dependency::Backend));
+    assert!(breaker.try_pass().is_ok());
+
+    breaker.record(Some(Dependency::Metadata));
+    assert!(breaker.try_pass().is_err());
+
+    // after the open period a single probe passes
+    let later = Instant::now() + Duration::from_secs(11);
+    assert!(breaker.try_pass_at(later).is_ok());
+    assert!(breaker.try_pass_at(later).is_err());
+    breaker.record(None);
+    assert!(breaker.try_pass().is_ok());
+
+    let error = opendal::Error::new(opendal::ErrorKind::Unexpected, "connection refused");
+    let response = S3Error::from(error).into_response();
+    assert_eq!(
+        response.extensions().get::<Dependency>(),
+        Some(&Dependency::Backend)
+    );
+}
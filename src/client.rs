@@ -0,0 +1,350 @@
+use anyhow::Context;
+use aws_credential_types::Credentials;
+use aws_sigv4::http_request::{
+    sign, PayloadChecksumKind, PercentEncodingMode, SignableBody, SignableRequest, SigningSettings,
+    UriPathNormalizationMode,
+};
+use aws_sigv4::sign::v4::SigningParams;
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::{Method, Request};
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client as HttpClient;
+use hyper_util::rt::TokioExecutor;
+use serde::Deserialize;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Settings of the client subcommands, read from `S3_PROXY__CLIENT__*`.
+///
+/// The keys fall back to `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ClientConfig {
+    #[serde(default = "default_endpoint")]
+    pub endpoint: String,
+    pub access_key: Option<String>,
+    pub secret_key: Option<String>,
+    #[serde(default = "default_region")]
+    pub region: String,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        ClientConfig {
+            endpoint: default_endpoint(),
+            access_key: None,
+            secret_key: None,
+            region: default_region(),
+        }
+    }
+}
+
+fn default_endpoint() -> String {
+    String::from("http://127.0.0.1:3000")
+}
+
+fn default_region() -> String {
+    String::from("us-east-1")
+}
+
+impl ClientConfig {
+    pub fn from_env() -> Result<Self, config::ConfigError> {
+        #[derive(Deserialize)]
+        struct Env {
+            #[serde(default)]
+            client: ClientConfig,
+        }
+
+        let cfg = config::Config::builder()
+            .add_source(config::Environment::with_prefix("S3_PROXY").separator("__"))
+            .build()?;
+
+        cfg.try_deserialize::<Env>().map(|x| x.client)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListAllMyBucketsResult {
+    buckets: BucketList,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BucketList {
+    #[serde(default)]
+    bucket: Vec<BucketEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BucketEntry {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListBucketResult {
+    #[serde(default)]
+    contents: Vec<ObjectEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ObjectEntry {
+    pub key: String,
+    pub size: u64,
+}
+
+/// Minimal SigV4 client for the `ls`, `cp`, `rm` and `mb` subcommands.
+pub struct Client {
+    endpoint: String,
+    credentials: Credentials,
+    region: String,
+    http: HttpClient<HttpConnector, Full<Bytes>>,
+}
+
+impl Client {
+    pub fn new(config: ClientConfig) -> anyhow::Result<Client> {
+        let access_key = config
+            .access_key
+            .or_else(|| std::env::var("AWS_ACCESS_KEY_ID").ok())
+            .context("set S3_PROXY__CLIENT__ACCESS_KEY or AWS_ACCESS_KEY_ID")?;
+        let secret_key = config
+            .secret_key
+            .or_else(|| std::env::var("AWS_SECRET_ACCESS_KEY").ok())
+            .context("set S3_PROXY__CLIENT__SECRET_KEY or AWS_SECRET_ACCESS_KEY")?;
+
+        Ok(Client {
+            endpoint: config.endpoint.trim_end_matches('/').to_string(),
+            credentials: Credentials::new(access_key, secret_key, None, None, "s3-proxy"),
+            region: config.region,
+            http: HttpClient::builder(TokioExecutor::new()).build_http(),
+        })
+    }
+
+    async fn send(&self, method: Method, path: &str, body: Bytes) -> anyhow::Result<Bytes> {
+        let uri = format!("{}{}", self.endpoint, encode_path(path));
+
+        let mut settings = SigningSettings::default();
+        settings.percent_encoding_mode = PercentEncodingMode::Single;
+        settings.payload_checksum_kind = PayloadChecksumKind::XAmzSha256;
+        settings.uri_path_normalization_mode = UriPathNormalizationMode::Disabled;
+
+        let identity = self.credentials.clone().into();
+        let params = SigningParams::builder()
+            .identity(&identity)
+            .region(&self.region)
+            .name("s3")
+            .time(SystemTime::now())
+            .settings(settings)
+            .build()?;
+
+        let host = uri
+            .parse::<hyper::Uri>()?
+            .authority()
+            .context("the endpoint has no host")?
+            .to_string();
+        let signable = SignableRequest::new(
+            method.as_str(),
+            &uri,
+            std::iter::once(("host", host.as_str())),
+            SignableBody::Bytes(&body),
+        )?;
+        let (instructions, _) = sign(signable, &params.into())?.into_parts();
+
+        let mut request = Request::builder()
+            .method(method)
+            .uri(&uri)
+            .header("host", &host)
+            .body(Full::new(body))?;
+        instructions.apply_to_request_http1x(&mut request);
+
+        let response = self.http.request(request).await?;
+        let status = response.status();
+        let body = response.into_body().collect().await?.to_bytes();
+
+        if !status.is_success() {
+            anyhow::bail!("{} {}", status, String::from_utf8_lossy(&body));
+        }
+
+        Ok(body)
+    }
+
+    pub async fn list_buckets(&self) -> anyhow::Result<Vec<String>> {
+        let body = self.send(Method::GET, "/", Bytes::new()).await?;
+        let result: ListAllMyBucketsResult = quick_xml::de::from_str(std::str::from_utf8(&body)?)?;
+
+        Ok(result.buckets.bucket.into_iter().map(|x| x.name).collect())
+    }
+
+    pub async fn list_objects(
+        &self,
+        bucket: &str,
+        prefix: &str,
+    ) -> anyhow::Result<Vec<ObjectEntry>> {
+        let body = self
+            .send(Method::GET, &format!("/{}", bucket), Bytes::new())
+            .await?;
+        let result: ListBucketResult = quick_xml::de::from_str(std::str::from_utf8(&body)?)?;
+
+        Ok(result
+            .contents
+            .into_iter()
+            .filter(|x| x.key.starts_with(prefix))
+            .collect())
+    }
+
+    pub async fn create_bucket(&self, bucket: &str) -> anyhow::Result<()> {
+        self.send(Method::PUT, &format!("/{}", bucket), Bytes::new())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_object(&self, bucket: &str, key: &str) -> anyhow::Result<Bytes> {
+        self.send(Method::GET, &format!("/{}/{}", bucket, key), Bytes::new())
+            .await
+    }
+
+    pub async fn put_object(&self, bucket: &str, key: &str, body: Bytes) -> anyhow::Result<()> {
+        self.send(Method::PUT, &format!("/{}/{}", bucket, key), body)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete_object(&self, bucket: &str, key: &str) -> anyhow::Result<()> {
+        self.send(
+            Method::DELETE,
+            &format!("/{}/{}", bucket, key),
+            Bytes::new(),
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+/// Percent encodes everything but the unreserved characters and `/`.
+fn encode_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// An `s3://bucket/key` location, the key can be empty.
+#[derive(Debug, PartialEq)]
+pub struct S3Url<'a> {
+    pub bucket: &'a str,
+    pub key: &'a str,
+}
+
+impl<'a> S3Url<'a> {
+    pub fn parse(url: &'a str) -> Option<S3Url<'a>> {
+        let rest = url.strip_prefix("s3://")?;
+        let (bucket, key) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return None;
+        }
+
+        Some(S3Url { bucket, key })
+    }
+}
+
+const USAGE: &str = "usage:
+    s3-proxy ls [s3://bucket[/prefix]]
+    s3-proxy cp <file|s3://bucket/key> <file|s3://bucket/key>
+    s3-proxy rm s3://bucket/key
+    s3-proxy mb s3://bucket";
+
+/// Runs a client subcommand, returns `None` when `command` is not one.
+pub async fn run(command: &str, args: &[String]) -> Option<anyhow::Result<()>> {
+    if !matches!(command, "ls" | "cp" | "rm" | "mb") {
+        return None;
+    }
+
+    Some(run_command(command, args).await)
+}
+
+async fn run_command(command: &str, args: &[String]) -> anyhow::Result<()> {
+    let client = Client::new(ClientConfig::from_env()?)?;
+    let args: Vec<_> = args.iter().map(String::as_str).collect();
+
+    match (command, args.as_slice()) {
+        ("ls", []) => {
+            for bucket in client.list_buckets().await? {
+                println!("{}", bucket);
+            }
+        }
+        ("ls", [url]) => {
+            let url = S3Url::parse(url).context(USAGE)?;
+            for object in client.list_objects(url.bucket, url.key).await? {
+                println!("{:>12} {}", object.size, object.key);
+            }
+        }
+        ("cp", [from, to]) => match (S3Url::parse(from), S3Url::parse(to)) {
+            (None, Some(to)) => {
+                let body = tokio::fs::read(from).await?;
+                let key = match to.key {
+                    "" => file_name(from)?,
+                    key if key.ends_with('/') => format!("{}{}", key, file_name(from)?),
+                    key => key.to_string(),
+                };
+                client.put_object(to.bucket, &key, body.into()).await?;
+            }
+            (Some(from), None) if !from.key.is_empty() => {
+                let body = client.get_object(from.bucket, from.key).await?;
+                tokio::fs::write(to, body).await?;
+            }
+            (Some(from), Some(to)) if !from.key.is_empty() && !to.key.is_empty() => {
+                let body = client.get_object(from.bucket, from.key).await?;
+                client.put_object(to.bucket, to.key, body).await?;
+            }
+            _ => anyhow::bail!(USAGE),
+        },
+        ("rm", [url]) => match S3Url::parse(url) {
+            Some(url) if !url.key.is_empty() => client.delete_object(url.bucket, url.key).await?,
+            _ => anyhow::bail!(USAGE),
+        },
+        ("mb", [url]) => match S3Url::parse(url) {
+            Some(url) if url.key.is_empty() => client.create_bucket(url.bucket).await?,
+            _ => anyhow::bail!(USAGE),
+        },
+        _ => anyhow::bail!(USAGE),
+    }
+
+    Ok(())
+}
+
+fn file_name(path: &str) -> anyhow::Result<String> {
+    Path::new(path)
+        .file_name()
+        .and_then(|x| x.to_str())
+        .map(String::from)
+        .context("the source has no file name")
+}
+
+#[test]
+fn s3_urls_are_parsed() {
+    assert_eq!(
+        S3Url::parse("s3://bucket/a/b.txt"),
+        Some(S3Url {
+            bucket: "bucket",
+            key: "a/b.txt"
+        })
+    );
+    assert_eq!(
+        S3Url::parse("s3://bucket"),
+        Some(S3Url {
+            bucket: "bucket",
+            key: ""
+        })
+    );
+    assert_eq!(S3Url::parse("bucket/key"), None);
+    assert_eq!(encode_path("/b/a b+c.txt"), "/b/a%20b%2Bc.txt");
+}
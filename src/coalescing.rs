@@ -0,0 +1,175 @@
+use axum::body::Bytes;
+use futures::{Stream, StreamExt};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CoalescingConfig {
+    /// objects up to this size are read once for all concurrent requests, 0 disables coalescing
+    #[serde(default = "default_max_object_bytes")]
+    pub max_object_bytes: u64,
+}
+
+impl Default for CoalescingConfig {
+    fn default() -> Self {
+        CoalescingConfig {
+            max_object_bytes: default_max_object_bytes(),
+        }
+    }
+}
+
+fn default_max_object_bytes() -> u64 {
+    8 * 1024 * 1024
+}
+
+#[derive(Debug, Default)]
+struct Flight {
+    chunks: Vec<Bytes>,
+    done: bool,
+    error: Option<String>,
+}
+
+type Flights = Arc<Mutex<HashMap<String, watch::Receiver<Flight>>>>;
+
+/// Single-flight reads: concurrent requests for the same object share one backend read.
+///
+/// The chunks are kept until the read is finished so requests that join late replay them
+/// from the start, which is why only small objects are coalesced.
+#[derive(Clone)]
+pub struct Coalescer {
+    max_object_bytes: u64,
+    flights: Flights,
+}
+
+impl Coalescer {
+    pub fn new(config: &CoalescingConfig) -> Coalescer {
+        Coalescer {
+            max_object_bytes: config.max_object_bytes,
+            flights: Flights::default(),
+        }
+    }
+
+    pub fn should_coalesce(&self, content_length: u64) -> bool {
+        self.max_object_bytes > 0 && content_length <= self.max_object_bytes
+    }
+
+    /// Joins the running read for `key`, or starts one with `read` when there is none.
+    ///
+    /// `key` should identify the object version (e.g. path and etag), so requests never get
+    /// the contents of a different version.
+    pub fn read<S, F>(
+        &self,
+        key: String,
+        read: F,
+    ) -> impl Stream<Item = Result<Bytes, io::Error>> + Send + 'static
+    where
+        F: FnOnce() -> S,
+        S: Stream<Item = Result<Bytes, io::Error>> + Send + 'static,
+    {
+        let receiver = {
+            let mut flights = self.flights.lock().expect("lock is not poisoned");
+
+            match flights.get(&key) {
+                Some(receiver) => receiver.clone(),
+                None => {
+                    let (sender, receiver) = watch::channel(Flight::default());
+                    flights.insert(key.clone(), receiver.clone());
+                    tokio::spawn(lead(self.flights.clone(), key, read(), sender));
+                    receiver
+                }
+            }
+        };
+
+        follow(receiver)
+    }
+}
+
+async fn lead<S>(flights: Flights, key: String, stream: S, sender: watch::Sender<Flight>)
+where
+    S: Stream<Item = Result<Bytes, io::Error>> + Send + 'static,
+{
+    let mut stream = std::pin::pin!(stream);
+
+    while let Some(chunk) = stream.next().await {
+        // only the receiver in the map is left, nobody is interested anymore
+        if sender.receiver_count() <= 1 {
+            let error = String::from("object read cancelled");
+            sender.send_modify(|flight| flight.error = Some(error));
+            break;
+        }
+
+        match chunk {
+            Ok(chunk) => sender.send_modify(|flight| flight.chunks.push(chunk)),
+            Err(error) => {
+                sender.send_modify(|flight| flight.error = Some(error.to_string()));
+                break;
+            }
+        }
+    }
+
+    // remove the flight before it is marked as done, new requests start a fresh read
+    flights.lock().expect("lock is not poisoned").remove(&key);
+    sender.send_modify(|flight| flight.done = true);
+}
+
+fn follow(receiver: watch::Receiver<Flight>) -> impl Stream<Item = Result<Bytes, io::Error>> {
+    futures::stream::unfold(Some((receiver, 0)), |state| async move {
+        let (mut receiver, index) = state?;
+
+        let flight = match receiver
+            .wait_for(|flight| flight.chunks.len() > index || flight.done || flight.error.is_some())
+            .await
+        {
+            Ok(flight) => flight,
+            Err(_) => {
+                let error = io::Error::other("object read stopped unexpectedly");
+                return Some((Err(error), None));
+            }
+        };
+
+        if let Some(chunk) = flight.chunks.get(index).cloned() {
+            drop(flight);
+            return Some((Ok(chunk), Some((receiver, index + 1))));
+        }
+
+        let error = flight.error.clone().map(io::Error::other);
+        drop(flight);
+        error.map(|error| (Err(error), None))
+    })
+}
+
+#[tokio::test]
+async fn coalescer_reads_once_for_concurrent_requests() {
+    use futures::TryStreamExt;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let coalescer = Coalescer::new(&CoalescingConfig::default());
+    let reads = Arc::new(AtomicUsize::new(0));
+    let (release, released) = tokio::sync::oneshot::channel::<()>();
+    let mut released = Some(released);
+
+    let mut readers = Vec::new();
+    for _ in 0..3 {
+        let reads = reads.clone();
+        let released = released.take();
+        readers.push(coalescer.read(String::from("ns/bucket/key@abc"), move || {
+            reads.fetch_add(1, Ordering::SeqCst);
+            let released = released.expect("only the first request reads");
+            futures::stream::once(async move {
+                released.await.unwrap();
+                Ok(Bytes::from("hello "))
+            })
+            .chain(futures::stream::iter([Ok(Bytes::from("world"))]))
+        }));
+    }
+    release.send(()).unwrap();
+
+    for reader in readers {
+        let body: Vec<Bytes> = reader.try_collect().await.unwrap();
+        assert_eq!(body.concat(), b"hello world");
+    }
+    assert_eq!(reads.load(Ordering::SeqCst), 1);
+}
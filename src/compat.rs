@@ -0,0 +1,297 @@
+use crate::credentials::SECRET_KEY_PREFIX;
+use crate::metadata::MetadataStore;
+use crate::{server, AppState, Config};
+use anyhow::Context;
+use opendal::{Operator, Scheme};
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::path::Path;
+use tokio::process::Command;
+
+const USAGE: &str = "usage: s3-proxy compat-test mint [image]
+    s3-proxy compat-test s3-tests <s3-tests checkout> [pytest args..]";
+
+const MINT_IMAGE: &str = "minio/mint";
+
+/// The suites log in as these, the alt user is a second namespace for the acl tests.
+const USERS: [(&str, &str); 2] = [
+    ("COMPATMAIN", "compatmainsecretkeynotreal0123456789"),
+    ("COMPATALT", "compataltsecretkeynotreal01234567890"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Pass,
+    Fail,
+    Skip,
+}
+
+/// Passed, failed and skipped tests per S3 operation.
+#[derive(Debug, Default, PartialEq)]
+pub struct Matrix {
+    pub operations: BTreeMap<String, [usize; 3]>,
+}
+
+impl Matrix {
+    pub fn add(&mut self, operation: &str, outcome: Outcome) {
+        let counts = self.operations.entry(operation.to_string()).or_default();
+        counts[outcome as usize] += 1;
+    }
+
+    pub fn print(&self) {
+        let width = self.operations.keys().map(|x| x.len()).max().unwrap_or(0);
+        let mut total = [0; 3];
+
+        println!(
+            "{:width$}  {:>5}  {:>5}  {:>5}",
+            "operation", "pass", "fail", "skip"
+        );
+        for (operation, counts) in &self.operations {
+            let status = match counts {
+                [_, 0, _] => "supported",
+                [0, _, _] => "unsupported",
+                _ => "partial",
+            };
+            println!(
+                "{:width$}  {:>5}  {:>5}  {:>5}  {}",
+                operation, counts[0], counts[1], counts[2], status
+            );
+            for (total, count) in total.iter_mut().zip(counts) {
+                *total += count;
+            }
+        }
+        println!(
+            "{:width$}  {:>5}  {:>5}  {:>5}",
+            "total", total[0], total[1], total[2]
+        );
+    }
+}
+
+/// The `compat-test` subcommand, runs an S3 compatibility suite against a proxy with in-memory
+/// storage and metadata.
+pub async fn run(args: &[String]) -> anyhow::Result<()> {
+    let args: Vec<_> = args.iter().map(String::as_str).collect();
+    let workdir = std::env::temp_dir().join(format!("s3-proxy-compat-{}", std::process::id()));
+    tokio::fs::create_dir_all(&workdir).await?;
+
+    let addr = start().await?;
+
+    let matrix = match args.as_slice() {
+        ["mint"] => run_mint(addr, MINT_IMAGE, &workdir).await?,
+        ["mint", image] => run_mint(addr, image, &workdir).await?,
+        ["s3-tests", checkout, pytest_args @ ..] => {
+            run_s3_tests(addr, Path::new(checkout), pytest_args, &workdir).await?
+        }
+        _ => anyhow::bail!(USAGE),
+    };
+    matrix.print();
+
+    tokio::fs::remove_dir_all(&workdir).await?;
+
+    Ok(())
+}
+
+/// Serves the S3 api on a random local port, until the process exits.
+async fn start() -> anyhow::Result<SocketAddr> {
+    let mut config = Config::builder()
+        .server_host("127.0.0.1:0")
+        .opendal(Scheme::Memory, HashMap::new())
+        .build();
+
+    let listener = server::bind(&config.server_host, &config.http).await?;
+    let addr = listener.local_addr()?;
+    config.external_server_host = format!("http://{}", addr);
+
+    let metadata = MetadataStore::memory();
+    for (access_key, secret_key) in USERS {
+        metadata
+            .set(&format!("{}{}", SECRET_KEY_PREFIX, access_key), secret_key)
+            .await?;
+    }
+
+    let app_state = AppState::builder(config)
+        .metadata(metadata)
+        .opendal_operator(Operator::via_map(Scheme::Memory, HashMap::new())?)
+        .build()?;
+
+    tokio::spawn(server::serve(
+        listener,
+        crate::router(app_state.clone()),
+        app_state.config.http.clone(),
+    ));
+
+    Ok(addr)
+}
+
+/// Runs the mint container on the host network, it logs a json line per sdk call.
+async fn run_mint(addr: SocketAddr, image: &str, workdir: &Path) -> anyhow::Result<Matrix> {
+    let (access_key, secret_key) = USERS[0];
+    let status = Command::new("docker")
+        .args(["run", "--rm", "--network", "host"])
+        .args(["-e", &format!("SERVER_ENDPOINT={}", addr)])
+        .args(["-e", &format!("ACCESS_KEY={}", access_key)])
+        .args(["-e", &format!("SECRET_KEY={}", secret_key)])
+        .args(["-e", "ENABLE_HTTPS=0", "-e", "MINT_MODE=core"])
+        .args(["-v", &format!("{}:/mint/log", workdir.display())])
+        .arg(image)
+        .status()
+        .await
+        .context("unable to run docker")?;
+    // mint exits non-zero when a test failed, the log has the details
+    eprintln!("mint exited with {}", status);
+
+    let log = tokio::fs::read_to_string(workdir.join("log.json"))
+        .await
+        .context("mint did not write a log")?;
+
+    Ok(mint_matrix(&log))
+}
+
+#[derive(Deserialize)]
+struct MintEntry {
+    function: Option<String>,
+    status: String,
+}
+
+fn mint_matrix(log: &str) -> Matrix {
+    let mut matrix = Matrix::default();
+
+    for entry in log
+        .lines()
+        .filter_map(|x| serde_json::from_str::<MintEntry>(x).ok())
+    {
+        let Some(function) = entry.function else {
+            continue;
+        };
+        // `PutObject(bucketName, objectName, reader)`, some sdks log a trailing description
+        let operation = function.split(['(', ' ']).next().unwrap_or_default();
+        let outcome = match entry.status.as_str() {
+            "PASS" => Outcome::Pass,
+            "FAIL" => Outcome::Fail,
+            _ => Outcome::Skip,
+        };
+        matrix.add(operation, outcome);
+    }
+
+    matrix
+}
+
+/// Runs ceph's s3-tests with pytest from its checkout, the results come from the junit report.
+async fn run_s3_tests(
+    addr: SocketAddr,
+    checkout: &Path,
+    pytest_args: &[&str],
+    workdir: &Path,
+) -> anyhow::Result<Matrix> {
+    let conf = workdir.join("s3tests.conf");
+    tokio::fs::write(&conf, s3_tests_conf(addr)).await?;
+    let report = workdir.join("report.xml");
+
+    let default_args = ["s3tests_boto3/functional/test_s3.py"];
+    let status = Command::new("python3")
+        .args(["-m", "pytest", "-q"])
+        .arg(format!("--junitxml={}", report.display()))
+        .args(if pytest_args.is_empty() {
+            &default_args[..]
+        } else {
+            pytest_args
+        })
+        .env("S3TEST_CONF", &conf)
+        .current_dir(checkout)
+        .status()
+        .await
+        .context("unable to run pytest")?;
+    eprintln!("s3-tests exited with {}", status);
+
+    let report = tokio::fs::read_to_string(&report)
+        .await
+        .context("s3-tests did not write a report")?;
+
+    junit_matrix(&report)
+}
+
+fn s3_tests_conf(addr: SocketAddr) -> String {
+    let mut conf = format!(
+        "[DEFAULT]\nhost = {}\nport = {}\nis_secure = False\nssl_verify = False\n\n\
+         [fixtures]\nbucket prefix = compat-{{random}}-\n",
+        addr.ip(),
+        addr.port()
+    );
+    for (section, (access_key, secret_key)) in
+        [("main", USERS[0]), ("alt", USERS[1]), ("tenant", USERS[1])]
+    {
+        conf.push_str(&format!(
+            "\n[s3 {section}]\ndisplay_name = {access_key}\nuser_id = {access_key}\n\
+             email = {access_key}@example.com\naccess_key = {access_key}\n\
+             secret_key = {secret_key}\n"
+        ));
+    }
+
+    conf
+}
+
+#[derive(Deserialize)]
+struct JunitReport {
+    #[serde(rename = "testsuite", default)]
+    suites: Vec<JunitSuite>,
+}
+
+#[derive(Deserialize)]
+struct JunitSuite {
+    #[serde(rename = "testcase", default)]
+    cases: Vec<JunitCase>,
+}
+
+#[derive(Deserialize)]
+struct JunitCase {
+    #[serde(rename = "@name")]
+    name: String,
+    failure: Option<IgnoredAny>,
+    error: Option<IgnoredAny>,
+    skipped: Option<IgnoredAny>,
+}
+
+fn junit_matrix(report: &str) -> anyhow::Result<Matrix> {
+    let report: JunitReport = quick_xml::de::from_str(report)?;
+    let mut matrix = Matrix::default();
+
+    for case in report.suites.iter().flat_map(|x| &x.cases) {
+        // the tests are named after what they cover, `test_bucket_list_empty` counts for
+        // `bucket_list`
+        let name = case.name.trim_start_matches("test_");
+        let operation = name.splitn(3, '_').take(2).collect::<Vec<_>>().join("_");
+        let outcome = if case.failure.is_some() || case.error.is_some() {
+            Outcome::Fail
+        } else if case.skipped.is_some() {
+            Outcome::Skip
+        } else {
+            Outcome::Pass
+        };
+        matrix.add(&operation, outcome);
+    }
+
+    Ok(matrix)
+}
+
+#[test]
+fn suite_results_are_grouped_per_operation() {
+    let log = r#"{"name":"aws-sdk-go","function":"PutObject(bucketName, objectName, reader)","status":"PASS"}
+{"name":"aws-sdk-go","function":"PutObject(bucketName, objectName, reader)","status":"FAIL"}
+{"name":"aws-sdk-go","function":"SelectObjectContent(input)","status":"NA"}
+not json"#;
+    let matrix = mint_matrix(log);
+    assert_eq!(matrix.operations["PutObject"], [1, 1, 0]);
+    assert_eq!(matrix.operations["SelectObjectContent"], [0, 0, 1]);
+
+    let report = r#"<?xml version="1.0" encoding="utf-8"?>
+<testsuites><testsuite name="pytest" tests="3">
+<testcase classname="s3tests_boto3.functional.test_s3" name="test_bucket_list_empty" time="0.1"/>
+<testcase classname="s3tests_boto3.functional.test_s3" name="test_bucket_list_many" time="0.1"><failure message="assert">trace</failure></testcase>
+<testcase classname="s3tests_boto3.functional.test_s3" name="test_lifecycle_set" time="0.1"><skipped message="not supported"/></testcase>
+</testsuite></testsuites>"#;
+    let matrix = junit_matrix(report).unwrap();
+    assert_eq!(matrix.operations["bucket_list"], [1, 1, 0]);
+    assert_eq!(matrix.operations["lifecycle_set"], [0, 0, 1]);
+}
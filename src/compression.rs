@@ -0,0 +1,128 @@
+use axum::http::header::{CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG};
+use axum::http::{HeaderValue, Response, StatusCode};
+use serde::Deserialize;
+use tower_http::compression::{CompressionLayer, Predicate};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CompressionConfig {
+    /// compress text-like objects when the client sends `Accept-Encoding`
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "crate::default_true")]
+    pub gzip: bool,
+    #[serde(default = "crate::default_true")]
+    pub zstd: bool,
+    /// smaller objects are sent as is, compressing them is not worth it
+    #[serde(default = "default_min_size_bytes")]
+    pub min_size_bytes: u64,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        CompressionConfig {
+            enabled: false,
+            gzip: true,
+            zstd: true,
+            min_size_bytes: default_min_size_bytes(),
+        }
+    }
+}
+
+fn default_min_size_bytes() -> u64 {
+    1024
+}
+
+/// Compresses text-like responses on the fly with the enabled algorithms.
+pub fn layer(config: &CompressionConfig) -> CompressionLayer<Compressible> {
+    CompressionLayer::new()
+        .no_br()
+        .no_deflate()
+        .gzip(config.enabled && config.gzip)
+        .zstd(config.enabled && config.zstd)
+        .compress_when(Compressible {
+            min_size_bytes: config.min_size_bytes,
+        })
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Compressible {
+    min_size_bytes: u64,
+}
+
+impl Predicate for Compressible {
+    fn should_compress<B>(&self, response: &Response<B>) -> bool
+    where
+        B: http_body::Body,
+    {
+        let headers = response.headers();
+        if headers.contains_key(CONTENT_ENCODING) {
+            return false;
+        }
+        // the content range describes the uncompressed bytes
+        if response.status() == StatusCode::PARTIAL_CONTENT || headers.contains_key(CONTENT_RANGE) {
+            return false;
+        }
+
+        let large_enough = headers
+            .get(CONTENT_LENGTH)
+            .and_then(|x| x.to_str().ok())
+            .and_then(|x| x.parse::<u64>().ok())
+            .is_none_or(|length| length >= self.min_size_bytes);
+
+        large_enough
+            && headers
+                .get(CONTENT_TYPE)
+                .and_then(|x| x.to_str().ok())
+                .is_some_and(is_text_like)
+    }
+}
+
+pub fn is_text_like(content_type: &str) -> bool {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+
+    essence.starts_with("text/")
+        || essence.ends_with("+json")
+        || essence.ends_with("+xml")
+        || matches!(
+            essence.as_str(),
+            "application/json"
+                | "application/xml"
+                | "application/javascript"
+                | "application/x-ndjson"
+                | "application/yaml"
+                | "application/csv"
+        )
+}
+
+/// A compressed body is a different representation of the object, so its etag can only be weak.
+pub async fn weaken_etag<B>(mut response: Response<B>) -> Response<B> {
+    if !response.headers().contains_key(CONTENT_ENCODING) {
+        return response;
+    }
+
+    let weak = response
+        .headers()
+        .get(ETAG)
+        .and_then(|x| x.to_str().ok())
+        .filter(|etag| !etag.starts_with("W/"))
+        .and_then(|etag| HeaderValue::from_str(&format!("W/{}", etag)).ok());
+    if let Some(weak) = weak {
+        response.headers_mut().insert(ETAG, weak);
+    }
+
+    response
+}
+
+#[test]
+fn text_like_content_types() {
+    assert!(is_text_like("text/csv"));
+    assert!(is_text_like("application/json; charset=utf-8"));
+    assert!(is_text_like("application/vnd.api+json"));
+    assert!(!is_text_like("image/png"));
+    assert!(!is_text_like("application/octet-stream"));
+}
@@ -0,0 +1,197 @@
+use crate::error::S3ErrorBody;
+use crate::operation::S3Operation;
+use crate::{AppState, REQUEST_ID_HEADER};
+use axum::body::{Body, Bytes};
+use axum::extract::{Request, State};
+use axum::http::header::CONTENT_LENGTH;
+use axum::http::{Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::Response;
+use http_body::{Body as HttpBody, Frame, SizeHint};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+/// Per request bookkeeping, shared between the middleware and the extractors via the
+/// request extensions.
+#[derive(Debug)]
+pub struct RequestContext {
+    pub operation: S3Operation,
+    pub method: Method,
+    pub path: String,
+    pub request_id: String,
+    pub started: Instant,
+    auth: OnceLock<Duration>,
+    access_key: OnceLock<String>,
+    namespace: OnceLock<String>,
+    request_bytes: AtomicU64,
+}
+
+impl RequestContext {
+    pub fn new(
+        method: Method,
+        path: String,
+        request_id: String,
+        operation: S3Operation,
+    ) -> RequestContext {
+        RequestContext {
+            operation,
+            method,
+            path,
+            request_id,
+            started: Instant::now(),
+            auth: OnceLock::new(),
+            access_key: OnceLock::new(),
+            namespace: OnceLock::new(),
+            request_bytes: AtomicU64::new(0),
+        }
+    }
+
+    /// Called by the signature extractor once the request is verified.
+    pub fn record_auth(&self, access_key: &str, namespace: &str, duration: Duration) {
+        let _ = self.auth.set(duration);
+        let _ = self.access_key.set(access_key.to_string());
+        let _ = self.namespace.set(namespace.to_string());
+    }
+
+    pub fn add_request_bytes(&self, bytes: u64) {
+        self.request_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn auth_duration(&self) -> Option<Duration> {
+        self.auth.get().copied()
+    }
+
+    pub fn access_key(&self) -> Option<&str> {
+        self.access_key.get().map(String::as_str)
+    }
+
+    pub fn namespace(&self) -> Option<&str> {
+        self.namespace.get().map(String::as_str)
+    }
+
+    pub fn request_bytes(&self) -> u64 {
+        self.request_bytes.load(Ordering::Relaxed)
+    }
+}
+
+/// Everything known about a request after the response body has been sent (or dropped).
+#[derive(Debug)]
+pub struct RequestSummary<'a> {
+    pub context: &'a RequestContext,
+    pub status: StatusCode,
+    pub response_bytes: u64,
+    /// time until the response headers were ready
+    pub handler: Duration,
+    /// time spent streaming the response body
+    pub body: Duration,
+    pub total: Duration,
+}
+
+/// Middleware that attaches a `RequestContext` to every request and reports on it once the
+/// response is finished.
+pub async fn track(State(state): State<AppState>, mut req: Request, next: Next) -> Response {
+    let operation = S3Operation::from_request(req.method(), req.uri());
+    let request_id = req
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|x| x.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let context = Arc::new(RequestContext::new(
+        req.method().clone(),
+        req.uri().path().to_string(),
+        request_id,
+        operation,
+    ));
+    req.extensions_mut().insert(context.clone());
+
+    let span = tracing::Span::current();
+    let mut response = next.run(req).await;
+    let handler = context.started.elapsed();
+
+    // error bodies are rendered without the request, fill in where the error happened
+    if let Some(error) = response.extensions().get::<S3ErrorBody>() {
+        let body = error.render(&context.path, &context.request_id);
+        // the router already set the length of the body without the resource and request id
+        response.headers_mut().remove(CONTENT_LENGTH);
+        *response.body_mut() = Body::from(body);
+    }
+
+    let status = response.status();
+
+    response.map(|inner| {
+        Body::new(TrackedBody {
+            inner,
+            state,
+            context,
+            span,
+            status,
+            handler,
+            response_bytes: 0,
+        })
+    })
+}
+
+struct TrackedBody {
+    inner: Body,
+    state: AppState,
+    context: Arc<RequestContext>,
+    /// the (possibly unsampled) request span
+    span: tracing::Span,
+    status: StatusCode,
+    handler: Duration,
+    response_bytes: u64,
+}
+
+impl HttpBody for TrackedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let poll = Pin::new(&mut self.inner).poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &poll {
+            if let Some(data) = frame.data_ref() {
+                self.response_bytes += data.len() as u64;
+            }
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for TrackedBody {
+    fn drop(&mut self) {
+        let _entered = self.span.enter();
+        let total = self.context.started.elapsed();
+        let summary = RequestSummary {
+            context: &self.context,
+            status: self.status,
+            response_bytes: self.response_bytes,
+            handler: self.handler,
+            body: total.saturating_sub(self.handler),
+            total,
+        };
+
+        crate::sampling::report(&summary);
+        crate::slow_requests::report(&self.state.config, &summary);
+        crate::error_reporting::report(&summary);
+        self.state.metrics.record(&summary);
+        self.state.accounting.record(&summary);
+        if let Some(audit) = &self.state.audit {
+            audit.record(&summary);
+        }
+    }
+}
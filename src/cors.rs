@@ -0,0 +1,533 @@
+use crate::context::RequestContext;
+use crate::domains::DomainMapping;
+use crate::error::S3Error;
+use crate::metadata::MetadataError;
+use crate::namespaces::Namespaces;
+use crate::AppState;
+use axum::extract::{Request, State};
+use axum::http::header::{
+    ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
+    ACCESS_CONTROL_EXPOSE_HEADERS, ACCESS_CONTROL_MAX_AGE, ACCESS_CONTROL_REQUEST_HEADERS,
+    ACCESS_CONTROL_REQUEST_METHOD, ORIGIN, VARY,
+};
+use axum::http::{HeaderMap, HeaderValue, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// A bucket has at most this many rules, like S3.
+const MAX_RULES: usize = 100;
+const MAX_ID_LENGTH: usize = 255;
+const SUPPORTED_METHODS: &[&str] = &["GET", "PUT", "HEAD", "POST", "DELETE"];
+
+/// The CORS rule of buckets without a CORS configuration of their own.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CorsConfig {
+    /// comma separated origins like `https://app.example.com`, one `*` matches any part
+    #[serde(deserialize_with = "crate::comma_separated")]
+    pub allowed_origins: Vec<String>,
+    #[serde(
+        default = "default_allowed_methods",
+        deserialize_with = "crate::comma_separated"
+    )]
+    pub allowed_methods: Vec<String>,
+    /// request headers browsers may send, `*` allows all
+    #[serde(
+        default = "default_allowed_headers",
+        deserialize_with = "crate::comma_separated"
+    )]
+    pub allowed_headers: Vec<String>,
+    /// response headers scripts may read
+    #[serde(
+        default = "default_expose_headers",
+        deserialize_with = "crate::comma_separated"
+    )]
+    pub expose_headers: Vec<String>,
+    /// how long browsers may cache a preflight response
+    pub max_age_secs: Option<u64>,
+}
+
+fn default_allowed_methods() -> Vec<String> {
+    ["GET", "HEAD", "PUT", "POST", "DELETE"]
+        .map(String::from)
+        .to_vec()
+}
+
+fn default_allowed_headers() -> Vec<String> {
+    vec![String::from("*")]
+}
+
+fn default_expose_headers() -> Vec<String> {
+    vec![String::from("ETag"), String::from("x-amz-request-id")]
+}
+
+impl CorsConfig {
+    fn rule(&self) -> CorsRule {
+        CorsRule {
+            id: None,
+            allowed_origins: self.allowed_origins.clone(),
+            allowed_methods: self.allowed_methods.clone(),
+            allowed_headers: self.allowed_headers.clone(),
+            expose_headers: self.expose_headers.clone(),
+            max_age_seconds: self.max_age_secs,
+        }
+    }
+}
+
+/// The `<CORSConfiguration>` of a bucket, set with `PutBucketCors`. It replaces the rule of
+/// `S3_PROXY__CORS__*` for the bucket.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CorsConfiguration {
+    #[serde(rename = "CORSRule", default)]
+    pub rules: Vec<CorsRule>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CorsRule {
+    #[serde(rename = "ID", default)]
+    pub id: Option<String>,
+    #[serde(rename = "AllowedOrigin", default)]
+    pub allowed_origins: Vec<String>,
+    #[serde(rename = "AllowedMethod", default)]
+    pub allowed_methods: Vec<String>,
+    #[serde(rename = "AllowedHeader", default)]
+    pub allowed_headers: Vec<String>,
+    #[serde(rename = "ExposeHeader", default)]
+    pub expose_headers: Vec<String>,
+    #[serde(default)]
+    pub max_age_seconds: Option<u64>,
+}
+
+impl CorsConfiguration {
+    /// Checks the configuration like S3, the error is sent back to the client.
+    pub fn validate(&self) -> Result<(), S3Error> {
+        if self.rules.is_empty() || self.rules.len() > MAX_RULES {
+            return Err(S3Error::MalformedXML);
+        }
+
+        let mut ids = HashSet::new();
+        for rule in &self.rules {
+            if let Some(id) = &rule.id {
+                if id.len() > MAX_ID_LENGTH || !ids.insert(id) {
+                    return Err(S3Error::InvalidArgument(String::from(
+                        "The ID of a CORS rule is invalid or not unique",
+                    )));
+                }
+            }
+            if rule.allowed_origins.is_empty() || rule.allowed_methods.is_empty() {
+                return Err(S3Error::MalformedXML);
+            }
+            if let Some(method) = rule
+                .allowed_methods
+                .iter()
+                .find(|x| !SUPPORTED_METHODS.contains(&x.as_str()))
+            {
+                return Err(S3Error::InvalidRequest(format!(
+                    "Found unsupported HTTP method in CORS config. Unsupported method is {}",
+                    method
+                )));
+            }
+            if let Some(origin) = rule
+                .allowed_origins
+                .iter()
+                .find(|x| x.matches('*').count() > 1)
+            {
+                return Err(S3Error::InvalidRequest(format!(
+                    "AllowedOrigin \"{}\" can not have more than one wildcard.",
+                    origin
+                )));
+            }
+            if let Some(header) = rule
+                .allowed_headers
+                .iter()
+                .find(|x| x.matches('*').count() > 1)
+            {
+                return Err(S3Error::InvalidRequest(format!(
+                    "AllowedHeader \"{}\" can not have more than one wildcard.",
+                    header
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// `pattern` with at most one `*` that matches any part of `value`.
+fn matches_wildcard(pattern: &str, value: &str) -> bool {
+    match pattern.split_once('*') {
+        Some((prefix, suffix)) => {
+            value.len() >= prefix.len() + suffix.len()
+                && value.starts_with(prefix)
+                && value.ends_with(suffix)
+        }
+        None => pattern == value,
+    }
+}
+
+impl CorsRule {
+    fn allows_origin(&self, origin: &str) -> bool {
+        self.allowed_origins
+            .iter()
+            .any(|allowed| matches_wildcard(allowed, origin))
+    }
+
+    fn allows_method(&self, method: &str) -> bool {
+        self.allowed_methods.iter().any(|x| x == method)
+    }
+
+    /// Header names are matched case insensitively, `x-amz-*` allows every header of AWS.
+    fn allows_headers(&self, headers: &str) -> bool {
+        headers
+            .split(',')
+            .map(|x| x.trim().to_ascii_lowercase())
+            .filter(|x| !x.is_empty())
+            .all(|header| {
+                self.allowed_headers
+                    .iter()
+                    .any(|x| matches_wildcard(&x.to_ascii_lowercase(), &header))
+            })
+    }
+
+    /// S3 answers `*` for rules that allow every origin and the origin itself otherwise.
+    fn allow_origin(&self, origin: &HeaderValue) -> HeaderValue {
+        if self.allowed_origins.iter().any(|x| x == "*") {
+            HeaderValue::from_static("*")
+        } else {
+            origin.clone()
+        }
+    }
+}
+
+/// The bucket a request is for, with its namespace when the request tells it without a
+/// signature.
+fn target(req: &Request) -> Option<(Option<String>, String)> {
+    if let Some(mapping) = req.extensions().get::<DomainMapping>() {
+        return Some((Some(mapping.namespace.clone()), mapping.bucket.clone()));
+    }
+
+    let path = req.uri().path().trim_start_matches('/');
+    if let Some(public) = path.strip_prefix("_public/") {
+        let mut parts = public.splitn(3, '/');
+        let namespace = parts.next()?;
+        let bucket = parts.next()?;
+        return Some((Some(namespace.to_string()), bucket.to_string()));
+    }
+    let bucket = path.split('/').next().filter(|x| !x.is_empty())?;
+    Some((None, bucket.to_string()))
+}
+
+/// The rules of the bucket, or of the proxy when the bucket has none. Without a namespace the
+/// rules of the buckets with this name in every namespace apply, the request that follows the
+/// preflight is signed and only gets the headers of its own bucket.
+async fn rules(
+    state: &AppState,
+    namespace: Option<&str>,
+    bucket: Option<&str>,
+) -> Result<Option<Vec<CorsRule>>, MetadataError> {
+    let namespaces = Namespaces::new(&state.metadata);
+    let configured = match (namespace, bucket) {
+        (Some(namespace), Some(bucket)) => namespaces
+            .cors_configuration(namespace, bucket)
+            .await?
+            .map(|x| x.rules),
+        (None, Some(bucket)) => {
+            let mut rules = Vec::new();
+            for namespace in namespaces.cors_namespaces(bucket).await? {
+                if let Some(configuration) =
+                    namespaces.cors_configuration(&namespace, bucket).await?
+                {
+                    rules.extend(configuration.rules);
+                }
+            }
+            Some(rules).filter(|x| !x.is_empty())
+        }
+        (_, None) => None,
+    };
+
+    Ok(configured.or_else(|| state.config.cors.as_ref().map(|x| vec![x.rule()])))
+}
+
+/// Answers preflight requests and adds the CORS headers to responses of allowed origins, with
+/// the rules of the bucket or else the rule of `S3_PROXY__CORS__*`.
+///
+/// Preflights are not signed, so this runs before authentication. Requests of other origins are
+/// served without the headers and the browser blocks them.
+pub async fn handle(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let Some(origin) = req.headers().get(ORIGIN).cloned() else {
+        return next.run(req).await;
+    };
+    let target = target(&req);
+    let bucket = target.as_ref().map(|(_, bucket)| bucket.as_str());
+
+    if req.method() == Method::OPTIONS && req.headers().contains_key(ACCESS_CONTROL_REQUEST_METHOD)
+    {
+        let namespace = target
+            .as_ref()
+            .and_then(|(namespace, _)| namespace.as_deref());
+        return match rules(&state, namespace, bucket).await {
+            Ok(Some(rules)) => preflight(&rules, &origin, req.headers()),
+            Ok(None) => next.run(req).await,
+            Err(error) => S3Error::from(error).into_response(),
+        };
+    }
+
+    let context = req.extensions().get::<Arc<RequestContext>>().cloned();
+    let method = req.method().to_string();
+    let mut response = next.run(req).await;
+
+    // signed requests only get the headers of the bucket in their own namespace, the others the
+    // rule of the proxy
+    let namespace = target
+        .as_ref()
+        .and_then(|(namespace, _)| namespace.as_deref())
+        .or(context.as_ref().and_then(|x| x.namespace()));
+    let rules = match rules(&state, namespace, namespace.and(bucket)).await {
+        Ok(Some(rules)) => rules,
+        Ok(None) => return response,
+        Err(error) => {
+            tracing::error!("unable to read the CORS configuration: {}", error);
+            return response;
+        }
+    };
+
+    let headers = response.headers_mut();
+    headers.append(VARY, HeaderValue::from_static("Origin"));
+    let rule = origin.to_str().ok().and_then(|origin| {
+        rules
+            .iter()
+            .find(|x| x.allows_origin(origin) && x.allows_method(&method))
+    });
+    if let Some(rule) = rule {
+        headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, rule.allow_origin(&origin));
+        if let Ok(expose) = HeaderValue::from_str(&rule.expose_headers.join(", ")) {
+            if !expose.is_empty() {
+                headers.insert(ACCESS_CONTROL_EXPOSE_HEADERS, expose);
+            }
+        }
+    }
+
+    response
+}
+
+/// Answers with the first rule that allows the origin, method and headers.
+fn preflight(rules: &[CorsRule], origin: &HeaderValue, request_headers: &HeaderMap) -> Response {
+    let method = request_headers
+        .get(ACCESS_CONTROL_REQUEST_METHOD)
+        .and_then(|x| x.to_str().ok())
+        .unwrap_or_default();
+    let requested_headers = request_headers
+        .get(ACCESS_CONTROL_REQUEST_HEADERS)
+        .and_then(|x| x.to_str().ok());
+
+    let rule = origin.to_str().ok().and_then(|origin| {
+        rules.iter().find(|rule| {
+            rule.allows_origin(origin)
+                && rule.allows_method(method)
+                && requested_headers.is_none_or(|x| rule.allows_headers(x))
+        })
+    });
+    let Some(rule) = rule else {
+        return S3Error::AccessDenied.into_response();
+    };
+
+    let mut response = StatusCode::OK.into_response();
+    let headers = response.headers_mut();
+    headers.insert(VARY, HeaderValue::from_static("Origin"));
+    headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, rule.allow_origin(origin));
+    if let Ok(methods) = HeaderValue::from_str(&rule.allowed_methods.join(", ")) {
+        headers.insert(ACCESS_CONTROL_ALLOW_METHODS, methods);
+    }
+    // the requested headers are echoed, browsers do not accept `*` in every case
+    if let Some(requested) = request_headers.get(ACCESS_CONTROL_REQUEST_HEADERS) {
+        headers.insert(ACCESS_CONTROL_ALLOW_HEADERS, requested.clone());
+    }
+    if let Some(max_age) = rule.max_age_seconds {
+        headers.insert(ACCESS_CONTROL_MAX_AGE, HeaderValue::from(max_age));
+    }
+
+    response
+}
+
+#[test]
+fn origins_match_with_one_wildcard() {
+    let config = CorsConfig {
+        allowed_origins: vec![
+            String::from("https://app.example.com"),
+            String::from("https://*.preview.example.com"),
+        ],
+        allowed_methods: default_allowed_methods(),
+        allowed_headers: vec![String::from("content-type"), String::from("x-amz-meta-*")],
+        expose_headers: default_expose_headers(),
+        max_age_secs: None,
+    }
+    .rule();
+
+    assert!(config.allows_origin("https://app.example.com"));
+    assert!(config.allows_origin("https://pr-12.preview.example.com"));
+    assert!(!config.allows_origin("https://preview.example.com"));
+    assert!(!config.allows_origin("https://evil.com"));
+    assert!(config.allows_headers("Content-Type"));
+    assert!(!config.allows_headers("content-type, x-amz-acl"));
+    assert!(config.allows_headers("content-type, X-Amz-Meta-Owner"));
+    assert!(!config.allows_method("PATCH"));
+}
+
+#[tokio::test]
+async fn preflights_are_answered_before_authentication() {
+    use axum::body::Body;
+    use tower::ServiceExt;
+
+    let config = crate::Config::builder()
+        .with(|config| {
+            config.cors = Some(CorsConfig {
+                allowed_origins: vec![String::from("https://app.example.com")],
+                allowed_methods: vec![String::from("GET"), String::from("PUT")],
+                allowed_headers: default_allowed_headers(),
+                expose_headers: vec![String::from("ETag")],
+                max_age_secs: Some(600),
+            })
+        })
+        .build();
+    let state = AppState::builder(config)
+        .metadata(crate::metadata::MetadataStore::memory())
+        .build()
+        .unwrap();
+    let app = crate::router(state);
+    let request = |method: Method, origin: &str, request_method: Option<&str>| {
+        let mut request = Request::builder()
+            .method(method)
+            .uri("/testing/a.txt")
+            .header(ORIGIN, origin);
+        if let Some(request_method) = request_method {
+            request = request
+                .header(ACCESS_CONTROL_REQUEST_METHOD, request_method)
+                .header(ACCESS_CONTROL_REQUEST_HEADERS, "content-type");
+        }
+        request.body(Body::empty()).unwrap()
+    };
+
+    let response = app
+        .clone()
+        .oneshot(request(
+            Method::OPTIONS,
+            "https://app.example.com",
+            Some("PUT"),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let headers = response.headers();
+    assert_eq!(
+        headers[ACCESS_CONTROL_ALLOW_ORIGIN],
+        "https://app.example.com"
+    );
+    assert_eq!(headers[ACCESS_CONTROL_ALLOW_METHODS], "GET, PUT");
+    assert_eq!(headers[ACCESS_CONTROL_ALLOW_HEADERS], "content-type");
+    assert_eq!(headers[ACCESS_CONTROL_MAX_AGE], "600");
+
+    let response = app
+        .clone()
+        .oneshot(request(
+            Method::OPTIONS,
+            "https://app.example.com",
+            Some("DELETE"),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // the signature is still checked, the error carries the headers so scripts can read it
+    let response = app
+        .clone()
+        .oneshot(request(Method::GET, "https://app.example.com", None))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(
+        response.headers()[ACCESS_CONTROL_ALLOW_ORIGIN],
+        "https://app.example.com"
+    );
+    assert_eq!(response.headers()[ACCESS_CONTROL_EXPOSE_HEADERS], "ETag");
+
+    let response = app
+        .oneshot(request(Method::GET, "https://evil.example.com", None))
+        .await
+        .unwrap();
+    assert!(!response.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+}
+
+#[tokio::test]
+async fn buckets_use_their_own_rules() {
+    use axum::body::Body;
+    use tower::ServiceExt;
+
+    let state = AppState::builder(crate::Config::builder().build())
+        .metadata(crate::metadata::MetadataStore::memory())
+        .build()
+        .unwrap();
+    let configuration: CorsConfiguration = quick_xml::de::from_str(
+        r#"<CORSConfiguration>
+            <CORSRule>
+                <AllowedOrigin>https://*.example.com</AllowedOrigin>
+                <AllowedMethod>GET</AllowedMethod>
+                <AllowedHeader>x-amz-*</AllowedHeader>
+                <ExposeHeader>ETag</ExposeHeader>
+            </CORSRule>
+        </CORSConfiguration>"#,
+    )
+    .unwrap();
+    assert!(configuration.validate().is_ok());
+    Namespaces::new(&state.metadata)
+        .set_cors_configuration("tenant", "assets", &configuration)
+        .await
+        .unwrap();
+    let app = crate::router(state);
+    let preflight = |bucket: &str, headers: &str| {
+        Request::builder()
+            .method(Method::OPTIONS)
+            .uri(format!("/{}/logo.svg", bucket))
+            .header(ORIGIN, "https://www.example.com")
+            .header(ACCESS_CONTROL_REQUEST_METHOD, "GET")
+            .header(ACCESS_CONTROL_REQUEST_HEADERS, headers)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let response = app
+        .clone()
+        .oneshot(preflight("assets", "x-amz-date"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[ACCESS_CONTROL_ALLOW_ORIGIN],
+        "https://www.example.com"
+    );
+    assert_eq!(response.headers()[ACCESS_CONTROL_ALLOW_METHODS], "GET");
+
+    let response = app
+        .clone()
+        .oneshot(preflight("assets", "content-type"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // other buckets have no rules and the proxy none either
+    let response = app.oneshot(preflight("other", "x-amz-date")).await.unwrap();
+    assert!(!response.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+
+    let invalid = [
+        "<CORSRule><AllowedOrigin>*</AllowedOrigin><AllowedMethod>PATCH</AllowedMethod></CORSRule>",
+        "<CORSRule><AllowedOrigin>https://*.*.com</AllowedOrigin><AllowedMethod>GET</AllowedMethod></CORSRule>",
+        "<CORSRule><AllowedMethod>GET</AllowedMethod></CORSRule>",
+    ];
+    for rules in invalid {
+        let xml = format!("<CORSConfiguration>{}</CORSConfiguration>", rules);
+        let configuration: CorsConfiguration = quick_xml::de::from_str(&xml).unwrap();
+        assert!(configuration.validate().is_err(), "{}", rules);
+    }
+}
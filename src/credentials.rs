@@ -0,0 +1,219 @@
+use crate::metadata::{MetadataError, MetadataStore};
+use moka::sync::Cache;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime};
+
+pub const SECRET_KEY_PREFIX: &str = "secret_key::";
+/// namespace of the access key, keys without one use the access key as namespace
+pub const KEY_NAMESPACE_PREFIX: &str = "key_namespace::";
+/// the secret key a rotation replaced, as `PreviousSecretKey` json
+pub const PREVIOUS_SECRET_KEY_PREFIX: &str = "previous_secret_key::";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CredentialsConfig {
+    /// maximum amount of cached secret keys, 0 disables the cache
+    #[serde(default = "default_cache_capacity")]
+    pub cache_capacity: u64,
+    /// revoked or rotated keys are picked up after this
+    #[serde(default = "default_cache_ttl_secs")]
+    pub cache_ttl_secs: u64,
+    /// load all secret keys from redis before accepting requests
+    #[serde(default)]
+    pub warm_on_startup: bool,
+    /// how long the old secret keeps working after a rotation through the admin api
+    #[serde(default = "default_rotation_grace_secs")]
+    pub rotation_grace_secs: u64,
+}
+
+impl Default for CredentialsConfig {
+    fn default() -> Self {
+        CredentialsConfig {
+            cache_capacity: default_cache_capacity(),
+            cache_ttl_secs: default_cache_ttl_secs(),
+            warm_on_startup: false,
+            rotation_grace_secs: default_rotation_grace_secs(),
+        }
+    }
+}
+
+fn default_cache_capacity() -> u64 {
+    10_000
+}
+
+fn default_cache_ttl_secs() -> u64 {
+    60
+}
+
+fn default_rotation_grace_secs() -> u64 {
+    86400
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PreviousSecretKey {
+    pub secret_key: String,
+    /// unix timestamp in seconds
+    pub expires_at: u64,
+}
+
+impl PreviousSecretKey {
+    pub fn is_expired(&self) -> bool {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.expires_at <= now
+    }
+}
+
+/// Cache of access key to secret key, so not every request has to go to redis.
+#[derive(Clone)]
+pub struct CredentialsCache {
+    /// cache is already an Arc
+    cache: Option<Cache<String, String>>,
+    namespaces: Option<Cache<String, String>>,
+}
+
+impl CredentialsCache {
+    pub fn new(config: &CredentialsConfig) -> CredentialsCache {
+        let build = || {
+            (config.cache_capacity > 0).then(|| {
+                Cache::builder()
+                    .max_capacity(config.cache_capacity)
+                    .time_to_live(Duration::from_secs(config.cache_ttl_secs))
+                    .build()
+            })
+        };
+
+        CredentialsCache {
+            cache: build(),
+            namespaces: build(),
+        }
+    }
+
+    /// Returns the namespace `access_key` belongs to.
+    pub async fn namespace(
+        &self,
+        metadata: &MetadataStore,
+        access_key: &str,
+    ) -> Result<String, MetadataError> {
+        if let Some(namespace) = self.namespaces.as_ref().and_then(|x| x.get(access_key)) {
+            return Ok(namespace);
+        }
+
+        let namespace = metadata
+            .get(&format!("{}{}", KEY_NAMESPACE_PREFIX, access_key))
+            .await?
+            .unwrap_or_else(|| access_key.to_string());
+
+        if let Some(cache) = &self.namespaces {
+            cache.insert(access_key.to_string(), namespace.clone());
+        }
+
+        Ok(namespace)
+    }
+
+    /// Forgets a deleted or changed key on this instance, others pick it up after the ttl.
+    pub fn invalidate(&self, access_key: &str) {
+        if let Some(cache) = &self.cache {
+            cache.invalidate(access_key);
+        }
+        if let Some(cache) = &self.namespaces {
+            cache.invalidate(access_key);
+        }
+    }
+
+    /// Returns the secret key for `access_key`, from the cache or otherwise from the metadata.
+    pub async fn secret_key(
+        &self,
+        metadata: &MetadataStore,
+        access_key: &str,
+    ) -> Result<Option<String>, MetadataError> {
+        if let Some(secret_key) = self.cache.as_ref().and_then(|x| x.get(access_key)) {
+            return Ok(Some(secret_key));
+        }
+
+        let secret_key = metadata
+            .get(&format!("{}{}", SECRET_KEY_PREFIX, access_key))
+            .await?;
+
+        // unknown keys are not cached, so newly created keys work right away
+        if let (Some(cache), Some(secret_key)) = (&self.cache, &secret_key) {
+            cache.insert(access_key.to_string(), secret_key.clone());
+        }
+
+        Ok(secret_key)
+    }
+
+    /// Returns the secret key `access_key` had before its rotation, while the grace period lasts.
+    ///
+    /// Not cached, it is only needed for signatures that do not match the current secret.
+    pub async fn previous_secret_key(
+        &self,
+        metadata: &MetadataStore,
+        access_key: &str,
+    ) -> Result<Option<String>, MetadataError> {
+        let previous = metadata
+            .get(&format!("{}{}", PREVIOUS_SECRET_KEY_PREFIX, access_key))
+            .await?
+            .and_then(|x| serde_json::from_str::<PreviousSecretKey>(&x).ok());
+
+        Ok(previous.filter(|x| !x.is_expired()).map(|x| x.secret_key))
+    }
+
+    /// Loads all secret keys into the cache, returns the amount of loaded keys.
+    pub async fn warm(&self, metadata: &MetadataStore) -> Result<usize, MetadataError> {
+        let Some(cache) = &self.cache else {
+            return Ok(0);
+        };
+
+        let keys = metadata.keys(SECRET_KEY_PREFIX).await?;
+
+        let mut loaded = 0;
+        for chunk in keys.chunks(100) {
+            let secret_keys = metadata.get_many(chunk).await?;
+
+            for (key, secret_key) in chunk.iter().zip(secret_keys) {
+                let (Some(access_key), Some(secret_key)) =
+                    (key.strip_prefix(SECRET_KEY_PREFIX), secret_key)
+                else {
+                    continue;
+                };
+                cache.insert(access_key.to_string(), secret_key);
+                loaded += 1;
+            }
+        }
+
+        Ok(loaded)
+    }
+}
+
+#[tokio::test]
+async fn disabled_cache_is_not_warmed() {
+    let pool = deadpool_redis::Config::from_url("redis://127.0.0.1:1")
+        .create_pool(Some(deadpool_redis::Runtime::Tokio1))
+        .unwrap();
+    let metadata = MetadataStore::Redis(pool);
+    let cache = CredentialsCache::new(&CredentialsConfig {
+        cache_capacity: 0,
+        ..CredentialsConfig::default()
+    });
+
+    assert_eq!(cache.warm(&metadata).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn warm_loads_memory_metadata() {
+    let metadata = MetadataStore::memory();
+    metadata.set("secret_key::AKEY", "secret").await.unwrap();
+    let cache = CredentialsCache::new(&CredentialsConfig::default());
+
+    assert_eq!(cache.warm(&metadata).await.unwrap(), 1);
+    assert_eq!(
+        cache
+            .secret_key(&metadata, "AKEY")
+            .await
+            .unwrap()
+            .as_deref(),
+        Some("secret")
+    );
+}
@@ -0,0 +1,54 @@
+use crate::AppState;
+use axum::http::header::CONTENT_TYPE;
+use axum::response::{Html, IntoResponse};
+use axum::routing::get;
+use axum::Router;
+
+const INDEX: &str = include_str!("../dashboard/index.html");
+const SCRIPT: &str = include_str!("../dashboard/dashboard.js");
+const STYLE: &str = include_str!("../dashboard/dashboard.css");
+
+/// The web console, compiled into the binary and served under `/ui`.
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/ui", get(|| async { Html(INDEX) }))
+        .route("/ui/", get(|| async { Html(INDEX) }))
+        .route(
+            "/ui/dashboard.js",
+            get(|| async { ([(CONTENT_TYPE, "text/javascript")], SCRIPT).into_response() }),
+        )
+        .route(
+            "/ui/dashboard.css",
+            get(|| async { ([(CONTENT_TYPE, "text/css")], STYLE).into_response() }),
+        )
+}
+
+#[tokio::test]
+async fn console_is_served_without_token() {
+    use axum::body::Body;
+    use axum::extract::Request;
+    use axum::http::StatusCode;
+    use tower::ServiceExt;
+
+    let config = crate::Config::builder()
+        .admin("127.0.0.1:0", "token")
+        .build();
+    let state = AppState::builder(config)
+        .metadata(crate::metadata::MetadataStore::memory())
+        .build()
+        .unwrap();
+    let app = crate::admin::router(state);
+
+    let response = app
+        .clone()
+        .oneshot(Request::get("/ui").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app
+        .oneshot(Request::get("/namespaces").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
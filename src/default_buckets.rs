@@ -0,0 +1,81 @@
+use crate::error::S3Error;
+use crate::metadata::MetadataStore;
+use crate::namespaces::DEFAULT_BUCKETS_PREFIX;
+use moka::sync::Cache;
+use opendal::Operator;
+use std::sync::Arc;
+
+/// Creates the `S3_PROXY__DEFAULT_BUCKETS` of a namespace the first time it is used.
+///
+/// Namespaces remember that they got their default buckets, so a default bucket that is
+/// deleted later is not created again.
+#[derive(Clone)]
+pub struct DefaultBuckets {
+    buckets: Arc<Vec<String>>,
+    /// namespaces known to have their buckets, so requests do not go to the metadata store
+    provisioned: Cache<String, ()>,
+}
+
+impl DefaultBuckets {
+    pub fn new(buckets: Vec<String>) -> DefaultBuckets {
+        DefaultBuckets {
+            buckets: Arc::new(buckets),
+            provisioned: Cache::new(10_000),
+        }
+    }
+
+    pub fn buckets(&self) -> &[String] {
+        &self.buckets
+    }
+
+    pub async fn ensure(
+        &self,
+        metadata: &MetadataStore,
+        operator: &Operator,
+        namespace: &str,
+    ) -> Result<(), S3Error> {
+        if self.buckets.is_empty() || self.provisioned.contains_key(namespace) {
+            return Ok(());
+        }
+
+        let record = format!("{}{}", DEFAULT_BUCKETS_PREFIX, namespace);
+        if metadata.get(&record).await?.is_none() {
+            for bucket in self.buckets.iter() {
+                let path = format!("{}/{}/", namespace, bucket);
+                if !operator.is_exist(&path).await? {
+                    operator.create_dir(&path).await?;
+                }
+            }
+            metadata.set(&record, "1").await?;
+        }
+
+        self.provisioned.insert(namespace.to_string(), ());
+        Ok(())
+    }
+
+    /// Forgets a deleted namespace, so it gets its buckets again when it is used again.
+    pub fn invalidate(&self, namespace: &str) {
+        self.provisioned.invalidate(namespace);
+    }
+}
+
+#[tokio::test]
+async fn default_buckets_are_created_once() {
+    let metadata = MetadataStore::memory();
+    let operator = Operator::via_map(opendal::Scheme::Memory, Default::default()).unwrap();
+    let default_buckets = DefaultBuckets::new(vec![String::from("uploads")]);
+
+    default_buckets
+        .ensure(&metadata, &operator, "tenant")
+        .await
+        .unwrap();
+    assert!(operator.is_exist("tenant/uploads/").await.unwrap());
+
+    // a deleted default bucket stays deleted, also on other instances
+    operator.delete("tenant/uploads/").await.unwrap();
+    DefaultBuckets::new(vec![String::from("uploads")])
+        .ensure(&metadata, &operator, "tenant")
+        .await
+        .unwrap();
+    assert!(!operator.is_exist("tenant/uploads/").await.unwrap());
+}
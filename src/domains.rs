@@ -0,0 +1,326 @@
+use crate::error::S3Error;
+use crate::metadata::{MetadataError, MetadataStore};
+use crate::namespaces::Namespaces;
+use crate::{acl, AppState};
+use axum::extract::{Request, State};
+use axum::http::header::{AUTHORIZATION, HOST};
+use axum::http::uri::{Authority, PathAndQuery};
+use axum::http::{Method, Uri};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use moka::sync::Cache;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::time::Duration;
+
+pub const DOMAIN_PREFIX: &str = "domain::";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DomainsConfig {
+    /// maximum amount of cached domain lookups, 0 disables the cache
+    #[serde(default = "default_cache_capacity")]
+    pub cache_capacity: u64,
+    /// changes made on other instances are picked up after this
+    #[serde(default = "default_cache_ttl_secs")]
+    pub cache_ttl_secs: u64,
+}
+
+impl Default for DomainsConfig {
+    fn default() -> Self {
+        DomainsConfig {
+            cache_capacity: default_cache_capacity(),
+            cache_ttl_secs: default_cache_ttl_secs(),
+        }
+    }
+}
+
+fn default_cache_capacity() -> u64 {
+    10_000
+}
+
+fn default_cache_ttl_secs() -> u64 {
+    60
+}
+
+/// The bucket a custom domain serves, added to the request extensions when the host matches.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DomainMapping {
+    pub namespace: String,
+    pub bucket: String,
+}
+
+/// Custom domains mapped to buckets, lookups of unknown hosts are cached as well.
+#[derive(Clone)]
+pub struct Domains {
+    /// cache is already an Arc
+    cache: Option<Cache<String, Option<DomainMapping>>>,
+}
+
+impl Domains {
+    pub fn new(config: &DomainsConfig) -> Domains {
+        Domains {
+            cache: (config.cache_capacity > 0).then(|| {
+                Cache::builder()
+                    .max_capacity(config.cache_capacity)
+                    .time_to_live(Duration::from_secs(config.cache_ttl_secs))
+                    .build()
+            }),
+        }
+    }
+
+    /// The bucket `domain` is mapped to, the domain is a host name without port.
+    pub async fn lookup(
+        &self,
+        metadata: &MetadataStore,
+        domain: &str,
+    ) -> Result<Option<DomainMapping>, MetadataError> {
+        if let Some(mapping) = self.cache.as_ref().and_then(|x| x.get(domain)) {
+            return Ok(mapping);
+        }
+
+        let mapping = metadata
+            .get(&format!("{}{}", DOMAIN_PREFIX, domain))
+            .await?
+            .and_then(|x| serde_json::from_str(&x).ok());
+
+        if let Some(cache) = &self.cache {
+            cache.insert(domain.to_string(), mapping.clone());
+        }
+
+        Ok(mapping)
+    }
+
+    pub async fn list(
+        &self,
+        metadata: &MetadataStore,
+    ) -> Result<BTreeMap<String, DomainMapping>, MetadataError> {
+        let keys = metadata.keys(DOMAIN_PREFIX).await?;
+        let values = metadata.get_many(&keys).await?;
+
+        Ok(keys
+            .iter()
+            .zip(values)
+            .filter_map(|(key, value)| {
+                let domain = key.strip_prefix(DOMAIN_PREFIX)?;
+                let mapping = serde_json::from_str(&value?).ok()?;
+                Some((domain.to_string(), mapping))
+            })
+            .collect())
+    }
+
+    /// Changes are picked up right away on this instance, others pick them up after the ttl.
+    pub async fn set(
+        &self,
+        metadata: &MetadataStore,
+        domain: &str,
+        mapping: &DomainMapping,
+    ) -> Result<(), MetadataError> {
+        let mapping = serde_json::to_string(mapping).expect("mapping serializes");
+        metadata
+            .set(&format!("{}{}", DOMAIN_PREFIX, domain), &mapping)
+            .await?;
+        self.invalidate(domain);
+
+        Ok(())
+    }
+
+    pub async fn delete(
+        &self,
+        metadata: &MetadataStore,
+        domain: &str,
+    ) -> Result<(), MetadataError> {
+        metadata
+            .delete(&format!("{}{}", DOMAIN_PREFIX, domain))
+            .await?;
+        self.invalidate(domain);
+
+        Ok(())
+    }
+
+    fn invalidate(&self, domain: &str) {
+        if let Some(cache) = &self.cache {
+            cache.invalidate(domain);
+        }
+    }
+}
+
+/// Host names of 1 to 253 letters, digits, '-' and '.', lowercase like the lookups.
+pub fn is_valid_domain(domain: &str) -> bool {
+    !domain.is_empty()
+        && domain.len() <= 253
+        && domain
+            .split('.')
+            .all(|label| !label.is_empty() && !label.starts_with('-') && !label.ends_with('-'))
+        && domain
+            .bytes()
+            .all(|x| x.is_ascii_lowercase() || x.is_ascii_digit() || x == b'-' || x == b'.')
+}
+
+/// The host name of the request without port, lowercased.
+fn request_domain(req: &Request) -> Option<String> {
+    // http/2 requests carry the host in the uri instead of the header
+    let authority = match req.uri().authority() {
+        Some(authority) => authority.clone(),
+        None => Authority::from_str(req.headers().get(HOST)?.to_str().ok()?).ok()?,
+    };
+
+    Some(authority.host().to_ascii_lowercase())
+}
+
+/// Public buckets and objects with a public-read ACL are read without a signature.
+async fn is_public_read(
+    metadata: &MetadataStore,
+    mapping: &DomainMapping,
+    path: &str,
+) -> Result<bool, MetadataError> {
+    if Namespaces::new(metadata)
+        .is_public(&mapping.namespace, &mapping.bucket)
+        .await?
+    {
+        return Ok(true);
+    }
+    let key =
+        percent_encoding::percent_decode_str(path.trim_start_matches('/')).decode_utf8_lossy();
+    if key.is_empty() {
+        return Ok(false);
+    }
+    let filepath = format!("{}/{}/{}", mapping.namespace, mapping.bucket, key);
+    Ok(acl::stored(metadata, &filepath).await?.is_public())
+}
+
+/// Serves custom domains from their bucket, by rewriting `/{key}` to the path of the bucket.
+///
+/// Requests without a signature read from `/_public/` when the bucket or object is public,
+/// signed requests are verified against the path the client sent and only accepted for keys of
+/// the namespace of the bucket or where its bucket policy allows them.
+pub async fn rewrite(State(state): State<AppState>, mut req: Request, next: Next) -> Response {
+    // ACME validates the certificates of custom domains on the domain itself
+    if req.uri().path().starts_with(crate::acme::CHALLENGE_PATH) {
+        return next.run(req).await;
+    }
+    let Some(domain) = request_domain(&req) else {
+        return next.run(req).await;
+    };
+    let external_domain = Uri::from_str(&state.config.external_server_host)
+        .ok()
+        .and_then(|x| x.host().map(|x| x.to_ascii_lowercase()));
+    if external_domain.as_deref() == Some(domain.as_str()) {
+        return next.run(req).await;
+    }
+
+    let mapping = match state.domains.lookup(&state.metadata, &domain).await {
+        Ok(Some(mapping)) => mapping,
+        Ok(None) => return next.run(req).await,
+        Err(error) => return S3Error::from(error).into_response(),
+    };
+
+    let public = !req.headers().contains_key(AUTHORIZATION)
+        && matches!(*req.method(), Method::GET | Method::HEAD)
+        && match is_public_read(&state.metadata, &mapping, req.uri().path()).await {
+            Ok(public) => public,
+            Err(error) => return S3Error::from(error).into_response(),
+        };
+
+    let path_and_query = req
+        .uri()
+        .path_and_query()
+        .map(|x| x.as_str())
+        .unwrap_or("/");
+    let path_and_query = if public {
+        format!(
+            "/_public/{}/{}{}",
+            mapping.namespace, mapping.bucket, path_and_query
+        )
+    } else {
+        format!("/{}{}", mapping.bucket, path_and_query)
+    };
+
+    let mut parts = req.uri().clone().into_parts();
+    parts.path_and_query = match PathAndQuery::from_str(&path_and_query) {
+        Ok(path_and_query) => Some(path_and_query),
+        Err(error) => return S3Error::internal(error).into_response(),
+    };
+    *req.uri_mut() = match Uri::from_parts(parts) {
+        Ok(uri) => uri,
+        Err(error) => return S3Error::internal(error).into_response(),
+    };
+    req.extensions_mut().insert(mapping);
+
+    next.run(req).await
+}
+
+#[tokio::test]
+async fn custom_domains_serve_their_bucket() {
+    use axum::body::Body;
+    use axum::http::StatusCode;
+    use tower::ServiceExt;
+
+    let state = AppState::builder(crate::Config::builder().build())
+        .metadata(MetadataStore::memory())
+        .build()
+        .unwrap();
+    state
+        .opendal_operator
+        .write("tenant/assets/logo.svg", "<svg/>")
+        .await
+        .unwrap();
+    let namespaces = Namespaces::new(&state.metadata);
+    namespaces
+        .set_public("tenant", "assets", true)
+        .await
+        .unwrap();
+    state
+        .domains
+        .set(
+            &state.metadata,
+            "assets.example.com",
+            &DomainMapping {
+                namespace: String::from("tenant"),
+                bucket: String::from("assets"),
+            },
+        )
+        .await
+        .unwrap();
+    let app = crate::router(state.clone());
+    let get = |host: &str, uri: &str| {
+        Request::get(uri)
+            .header(HOST, host)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let response = app
+        .clone()
+        .oneshot(get("Assets.Example.com:8080", "/logo.svg"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(body, "<svg/>");
+
+    // other hosts keep the path style api, which needs a signature
+    let response = app
+        .clone()
+        .oneshot(get("0.0.0.0:3000", "/assets/logo.svg"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    namespaces
+        .set_public("tenant", "assets", false)
+        .await
+        .unwrap();
+    let response = app
+        .oneshot(get("assets.example.com", "/logo.svg"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    assert!(is_valid_domain("assets.example.com"));
+    assert!(!is_valid_domain("Assets.example.com"));
+    assert!(!is_valid_domain("assets..example.com"));
+    assert!(!is_valid_domain("-assets.example.com"));
+}
@@ -0,0 +1,424 @@
+use crate::circuit_breaker::Dependency;
+use crate::metadata::MetadataError;
+use crate::payload::PayloadError;
+use crate::templates::ErrorTemplate;
+use askama::Template;
+use axum::http::header::{InvalidHeaderValue, CONTENT_TYPE};
+use axum::http::{HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use std::fmt;
+
+/// Errors of the S3 api, rendered as the S3 `<Error>` xml document.
+#[derive(Debug)]
+pub enum S3Error {
+    AccessDenied,
+    /// the access key in the signature is unknown
+    InvalidAccessKeyId,
+    /// a browser upload breaks a condition of its policy
+    InvalidAccordingToPolicy(String),
+    /// the object is under a retention or legal hold
+    ObjectLocked,
+    SignatureDoesNotMatch,
+    /// the request is signed for another region than the one of the bucket
+    AuthorizationHeaderMalformed(String),
+    InvalidArgument(String),
+    InvalidRequest(String),
+    /// the tag set breaks the limits of S3
+    InvalidTag(String),
+    MalformedXML,
+    /// the body of a browser upload is not a multipart form with a file
+    MalformedPOSTRequest,
+    InvalidPolicyDocument(String),
+    NoSuchBucket,
+    NoSuchKey,
+    /// the upload id is unknown in the namespace, or not for this object
+    NoSuchUpload,
+    /// a part of `CompleteMultipartUpload` was not uploaded, or has another etag
+    InvalidPart,
+    /// the parts of `CompleteMultipartUpload` are not in ascending order
+    InvalidPartOrder,
+    /// only empty buckets can be deleted
+    BucketNotEmpty,
+    /// the `Range` starts after the end of the object
+    InvalidRange,
+    /// an `If-Match` or `If-Unmodified-Since` condition does not hold
+    PreconditionFailed,
+    /// the body does not match the signed `x-amz-content-sha256`
+    XAmzContentSHA256Mismatch,
+    /// the body does not match the `x-amz-checksum-*` header
+    BadDigest(String),
+    EntityTooLarge,
+    EntityTooSmall,
+    /// the `x-amz-storage-class` is not one S3 knows
+    InvalidStorageClass,
+    IncompleteBody,
+    NotImplemented(String),
+    SlowDown,
+    /// the circuit breaker is open
+    ServiceUnavailable,
+    /// the upload would exceed the quota of its namespace or bucket
+    QuotaExceeded,
+    /// the bucket has no replication configuration
+    ReplicationConfigurationNotFound,
+    NoSuchConfiguration,
+    /// the bucket has no tags
+    NoSuchTagSet,
+    /// the bucket has no lifecycle rules
+    NoSuchLifecycleConfiguration,
+    /// the bucket has no CORS rules
+    NoSuchCORSConfiguration,
+    /// the bucket has no policy
+    NoSuchBucketPolicy,
+    MalformedPolicy(String),
+    /// the bucket has no object lock configuration
+    ObjectLockConfigurationNotFound,
+    /// the object has no retention
+    NoSuchObjectLockConfiguration,
+    /// the versioning or object lock state of the bucket does not allow the change
+    InvalidBucketState(String),
+    /// unexpected errors, the cause is logged but not sent to the client
+    InternalError(Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// The code and message of an error response, the request tracking fills in the resource and
+/// request id once the response passes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct S3ErrorBody {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl S3ErrorBody {
+    pub fn render(&self, resource: &str, request_id: &str) -> String {
+        ErrorTemplate {
+            code: self.code,
+            message: &self.message,
+            resource,
+            request_id,
+        }
+        .render()
+        .unwrap_or_default()
+    }
+}
+
+impl S3Error {
+    pub fn internal(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> S3Error {
+        S3Error::InternalError(error.into())
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            S3Error::AccessDenied => "AccessDenied",
+            S3Error::InvalidAccessKeyId => "InvalidAccessKeyId",
+            S3Error::InvalidAccordingToPolicy(_) => "AccessDenied",
+            S3Error::ObjectLocked => "AccessDenied",
+            S3Error::SignatureDoesNotMatch => "SignatureDoesNotMatch",
+            S3Error::InvalidArgument(_) => "InvalidArgument",
+            S3Error::InvalidRequest(_) => "InvalidRequest",
+            S3Error::InvalidTag(_) => "InvalidTag",
+            S3Error::AuthorizationHeaderMalformed(_) => "AuthorizationHeaderMalformed",
+            S3Error::MalformedXML => "MalformedXML",
+            S3Error::MalformedPOSTRequest => "MalformedPOSTRequest",
+            S3Error::InvalidPolicyDocument(_) => "InvalidPolicyDocument",
+            S3Error::NoSuchBucket => "NoSuchBucket",
+            S3Error::NoSuchKey => "NoSuchKey",
+            S3Error::NoSuchUpload => "NoSuchUpload",
+            S3Error::InvalidPart => "InvalidPart",
+            S3Error::InvalidPartOrder => "InvalidPartOrder",
+            S3Error::BucketNotEmpty => "BucketNotEmpty",
+            S3Error::InvalidRange => "InvalidRange",
+            S3Error::PreconditionFailed => "PreconditionFailed",
+            S3Error::XAmzContentSHA256Mismatch => "XAmzContentSHA256Mismatch",
+            S3Error::BadDigest(_) => "BadDigest",
+            S3Error::EntityTooLarge => "EntityTooLarge",
+            S3Error::EntityTooSmall => "EntityTooSmall",
+            S3Error::InvalidStorageClass => "InvalidStorageClass",
+            S3Error::IncompleteBody => "IncompleteBody",
+            S3Error::NotImplemented(_) => "NotImplemented",
+            S3Error::SlowDown => "SlowDown",
+            S3Error::ServiceUnavailable => "ServiceUnavailable",
+            S3Error::QuotaExceeded => "QuotaExceeded",
+            S3Error::ReplicationConfigurationNotFound => "ReplicationConfigurationNotFoundError",
+            S3Error::NoSuchConfiguration => "NoSuchConfiguration",
+            S3Error::NoSuchTagSet => "NoSuchTagSet",
+            S3Error::NoSuchLifecycleConfiguration => "NoSuchLifecycleConfiguration",
+            S3Error::NoSuchCORSConfiguration => "NoSuchCORSConfiguration",
+            S3Error::NoSuchBucketPolicy => "NoSuchBucketPolicy",
+            S3Error::MalformedPolicy(_) => "MalformedPolicy",
+            S3Error::ObjectLockConfigurationNotFound => "ObjectLockConfigurationNotFoundError",
+            S3Error::NoSuchObjectLockConfiguration => "NoSuchObjectLockConfiguration",
+            S3Error::InvalidBucketState(_) => "InvalidBucketState",
+            S3Error::InternalError(_) => "InternalError",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            S3Error::AccessDenied
+            | S3Error::InvalidAccessKeyId
+            | S3Error::InvalidAccordingToPolicy(_)
+            | S3Error::ObjectLocked
+            | S3Error::SignatureDoesNotMatch
+            | S3Error::QuotaExceeded => StatusCode::FORBIDDEN,
+            S3Error::InvalidArgument(_)
+            | S3Error::InvalidRequest(_)
+            | S3Error::InvalidTag(_)
+            | S3Error::AuthorizationHeaderMalformed(_)
+            | S3Error::MalformedXML
+            | S3Error::MalformedPOSTRequest
+            | S3Error::InvalidPolicyDocument(_)
+            | S3Error::MalformedPolicy(_)
+            | S3Error::InvalidPart
+            | S3Error::InvalidPartOrder
+            | S3Error::XAmzContentSHA256Mismatch
+            | S3Error::BadDigest(_)
+            | S3Error::EntityTooLarge
+            | S3Error::EntityTooSmall
+            | S3Error::InvalidStorageClass
+            | S3Error::IncompleteBody => StatusCode::BAD_REQUEST,
+            S3Error::NoSuchBucket
+            | S3Error::NoSuchKey
+            | S3Error::NoSuchUpload
+            | S3Error::ReplicationConfigurationNotFound
+            | S3Error::NoSuchConfiguration
+            | S3Error::NoSuchTagSet
+            | S3Error::NoSuchLifecycleConfiguration
+            | S3Error::NoSuchCORSConfiguration
+            | S3Error::NoSuchBucketPolicy
+            | S3Error::ObjectLockConfigurationNotFound
+            | S3Error::NoSuchObjectLockConfiguration => StatusCode::NOT_FOUND,
+            S3Error::BucketNotEmpty | S3Error::InvalidBucketState(_) => StatusCode::CONFLICT,
+            S3Error::InvalidRange => StatusCode::RANGE_NOT_SATISFIABLE,
+            S3Error::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
+            S3Error::NotImplemented(_) => StatusCode::NOT_IMPLEMENTED,
+            S3Error::SlowDown | S3Error::ServiceUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            S3Error::InternalError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            S3Error::AccessDenied => String::from("Access Denied"),
+            S3Error::InvalidAccessKeyId => {
+                String::from("The AWS access key Id you provided does not exist in our records.")
+            }
+            S3Error::SignatureDoesNotMatch => String::from(
+                "The request signature we calculated does not match the signature you provided.",
+            ),
+            S3Error::InvalidArgument(message)
+            | S3Error::InvalidRequest(message)
+            | S3Error::InvalidTag(message)
+            | S3Error::InvalidPolicyDocument(message)
+            | S3Error::MalformedPolicy(message)
+            | S3Error::InvalidBucketState(message)
+            | S3Error::NotImplemented(message) => message.clone(),
+            S3Error::InvalidAccordingToPolicy(message) => {
+                format!("Invalid according to Policy: {}", message)
+            }
+            S3Error::ObjectLocked => {
+                String::from("Access Denied because object protected by object lock.")
+            }
+            S3Error::AuthorizationHeaderMalformed(region) => format!(
+                "The authorization header is malformed; the region is wrong; expecting '{}'",
+                region
+            ),
+            S3Error::MalformedXML => String::from(
+                "The XML you provided was not well-formed or did not validate against our \
+                 published schema.",
+            ),
+            S3Error::MalformedPOSTRequest => String::from(
+                "The body of your POST request is not well-formed multipart/form-data.",
+            ),
+            S3Error::NoSuchBucket => String::from("The specified bucket does not exist"),
+            S3Error::NoSuchKey => String::from("The specified key does not exist."),
+            S3Error::NoSuchUpload => String::from(
+                "The specified upload does not exist. The upload ID may be invalid, or the upload \
+                 may have been aborted or completed.",
+            ),
+            S3Error::InvalidPart => String::from(
+                "One or more of the specified parts could not be found. The part might not have \
+                 been uploaded, or the specified entity tag might not have matched the part's \
+                 entity tag.",
+            ),
+            S3Error::InvalidPartOrder => String::from(
+                "The list of parts was not in ascending order. Parts must be ordered by part \
+                 number.",
+            ),
+            S3Error::BucketNotEmpty => String::from("The bucket you tried to delete is not empty"),
+            S3Error::InvalidRange => String::from("The requested range is not satisfiable"),
+            S3Error::PreconditionFailed => {
+                String::from("At least one of the pre-conditions you specified did not hold")
+            }
+            S3Error::BadDigest(algorithm) => format!(
+                "The {} you specified did not match the calculated checksum.",
+                algorithm
+            ),
+            S3Error::XAmzContentSHA256Mismatch => String::from(
+                "The provided 'x-amz-content-sha256' header does not match what was computed.",
+            ),
+            S3Error::EntityTooLarge => {
+                String::from("Your proposed upload exceeds the maximum allowed object size.")
+            }
+            S3Error::EntityTooSmall => String::from(
+                "Your proposed upload is smaller than the minimum allowed object size.",
+            ),
+            S3Error::InvalidStorageClass => {
+                String::from("The storage class you specified is not valid")
+            }
+            S3Error::IncompleteBody => String::from(
+                "You did not provide the number of bytes specified by the Content-Length HTTP \
+                 header.",
+            ),
+            S3Error::SlowDown => String::from("Please reduce your request rate."),
+            S3Error::ServiceUnavailable => String::from("Service is unable to handle request."),
+            S3Error::QuotaExceeded => {
+                String::from("The upload exceeds the quota of the namespace or bucket.")
+            }
+            S3Error::ReplicationConfigurationNotFound => {
+                String::from("The replication configuration was not found.")
+            }
+            S3Error::NoSuchConfiguration => {
+                String::from("The specified configuration does not exist.")
+            }
+            S3Error::NoSuchTagSet => String::from("The TagSet does not exist"),
+            S3Error::NoSuchLifecycleConfiguration => {
+                String::from("The lifecycle configuration does not exist")
+            }
+            S3Error::NoSuchCORSConfiguration => {
+                String::from("The CORS configuration does not exist")
+            }
+            S3Error::NoSuchBucketPolicy => String::from("The bucket policy does not exist"),
+            S3Error::ObjectLockConfigurationNotFound => {
+                String::from("Object Lock configuration does not exist for this bucket")
+            }
+            S3Error::NoSuchObjectLockConfiguration => {
+                String::from("The specified object does not have a ObjectLock configuration")
+            }
+            S3Error::InternalError(_) => {
+                String::from("We encountered an internal error. Please try again.")
+            }
+        }
+    }
+}
+
+impl fmt::Display for S3Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            S3Error::InternalError(error) => write!(f, "{}: {}", self.code(), error),
+            _ => write!(f, "{}: {}", self.code(), self.message()),
+        }
+    }
+}
+
+impl std::error::Error for S3Error {}
+
+impl IntoResponse for S3Error {
+    fn into_response(self) -> Response {
+        let mut dependency = None;
+        if let S3Error::InternalError(error) = &self {
+            tracing::error!("{}", error);
+            dependency = Dependency::of(error.as_ref());
+        }
+
+        let body = S3ErrorBody {
+            code: self.code(),
+            message: self.message(),
+        };
+        let mut response = (
+            self.status(),
+            [(CONTENT_TYPE, HeaderValue::from_static("application/xml"))],
+            body.render("", ""),
+        )
+            .into_response();
+        response.extensions_mut().insert(body);
+        if let Some(dependency) = dependency {
+            response.extensions_mut().insert(dependency);
+        }
+
+        response
+    }
+}
+
+impl From<opendal::Error> for S3Error {
+    fn from(error: opendal::Error) -> Self {
+        match error.kind() {
+            opendal::ErrorKind::NotFound => S3Error::NoSuchKey,
+            opendal::ErrorKind::PermissionDenied => S3Error::AccessDenied,
+            opendal::ErrorKind::RateLimited => S3Error::SlowDown,
+            _ => S3Error::internal(error),
+        }
+    }
+}
+
+impl From<PayloadError> for S3Error {
+    fn from(error: PayloadError) -> Self {
+        match error {
+            PayloadError::Mismatch => S3Error::XAmzContentSHA256Mismatch,
+            PayloadError::TooLarge => S3Error::EntityTooLarge,
+            PayloadError::Body(_) => S3Error::IncompleteBody,
+        }
+    }
+}
+
+impl From<MetadataError> for S3Error {
+    fn from(error: MetadataError) -> Self {
+        S3Error::internal(error)
+    }
+}
+
+impl From<deadpool_redis::PoolError> for S3Error {
+    fn from(error: deadpool_redis::PoolError) -> Self {
+        S3Error::internal(error)
+    }
+}
+
+impl From<deadpool_redis::redis::RedisError> for S3Error {
+    fn from(error: deadpool_redis::redis::RedisError) -> Self {
+        S3Error::internal(error)
+    }
+}
+
+impl From<std::time::SystemTimeError> for S3Error {
+    fn from(error: std::time::SystemTimeError) -> Self {
+        S3Error::internal(error)
+    }
+}
+
+impl From<quick_xml::DeError> for S3Error {
+    fn from(_: quick_xml::DeError) -> Self {
+        S3Error::MalformedXML
+    }
+}
+
+impl From<std::str::Utf8Error> for S3Error {
+    fn from(_: std::str::Utf8Error) -> Self {
+        S3Error::MalformedXML
+    }
+}
+
+impl From<InvalidHeaderValue> for S3Error {
+    fn from(error: InvalidHeaderValue) -> Self {
+        S3Error::internal(error)
+    }
+}
+
+impl From<askama::Error> for S3Error {
+    fn from(error: askama::Error) -> Self {
+        S3Error::internal(error)
+    }
+}
+
+#[test]
+fn s3_error_response() {
+    let response = S3Error::NoSuchKey.into_response();
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(
+        response.extensions().get::<S3ErrorBody>().unwrap().code,
+        "NoSuchKey"
+    );
+    assert_eq!(
+        S3Error::from(PayloadError::Mismatch).code(),
+        "XAmzContentSHA256Mismatch"
+    );
+}
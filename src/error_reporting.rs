@@ -0,0 +1,80 @@
+use crate::context::RequestSummary;
+use crate::Config;
+
+/// Keeps the error reporting client alive, events are flushed when it is dropped.
+pub struct ErrorReportingGuard {
+    #[cfg(feature = "sentry")]
+    _guard: Option<sentry::ClientInitGuard>,
+}
+
+#[cfg(feature = "sentry")]
+pub fn init(config: &Config) -> ErrorReportingGuard {
+    let guard = config.sentry_dsn.as_deref().map(|dsn| {
+        sentry::init((
+            dsn,
+            sentry::ClientOptions {
+                release: sentry::release_name!(),
+                environment: config.sentry_environment.clone().map(Into::into),
+                attach_stacktrace: true,
+                ..Default::default()
+            },
+        ))
+    });
+
+    ErrorReportingGuard { _guard: guard }
+}
+
+#[cfg(not(feature = "sentry"))]
+pub fn init(config: &Config) -> ErrorReportingGuard {
+    if config.sentry_dsn.is_some() {
+        tracing::warn!("sentry_dsn is set but s3-proxy is compiled without the `sentry` feature");
+    }
+
+    ErrorReportingGuard {}
+}
+
+/// Reports unexpected server errors, client errors are expected and are not reported.
+pub fn report(summary: &RequestSummary) {
+    if !summary.status.is_server_error() {
+        return;
+    }
+
+    capture(summary);
+}
+
+#[cfg(feature = "sentry")]
+fn capture(summary: &RequestSummary) {
+    let context = summary.context;
+
+    sentry::with_scope(
+        |scope| {
+            scope.set_tag("operation", context.operation);
+            scope.set_tag("method", &context.method);
+            scope.set_tag("status", summary.status.as_u16());
+            if let Some(namespace) = context.namespace() {
+                scope.set_tag("namespace", namespace);
+            }
+            if let Some(access_key) = context.access_key() {
+                scope.set_user(Some(sentry::User {
+                    id: Some(access_key.to_string()),
+                    ..Default::default()
+                }));
+            }
+            scope.set_extra("path", context.path.clone().into());
+            scope.set_extra("request_bytes", context.request_bytes().into());
+            scope.set_extra("duration_ms", (summary.total.as_millis() as u64).into());
+        },
+        || {
+            sentry::capture_message(
+                &format!(
+                    "{} {} returned {}",
+                    context.operation, context.path, summary.status
+                ),
+                sentry::Level::Error,
+            )
+        },
+    );
+}
+
+#[cfg(not(feature = "sentry"))]
+fn capture(_summary: &RequestSummary) {}
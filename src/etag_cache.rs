@@ -0,0 +1,288 @@
+use axum::http::header::{IF_MATCH, IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_UNMODIFIED_SINCE};
+use axum::http::{HeaderMap, HeaderName};
+use chrono::{DateTime, Utc};
+use moka::sync::Cache;
+use serde::Deserialize;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EtagCacheConfig {
+    /// maximum amount of cached objects, 0 disables the cache
+    #[serde(default = "default_capacity")]
+    pub capacity: u64,
+    /// entries expire after this, so changes made behind the proxy are picked up eventually
+    #[serde(default = "default_ttl_secs")]
+    pub ttl_secs: u64,
+}
+
+impl Default for EtagCacheConfig {
+    fn default() -> Self {
+        EtagCacheConfig {
+            capacity: default_capacity(),
+            ttl_secs: default_ttl_secs(),
+        }
+    }
+}
+
+fn default_capacity() -> u64 {
+    10_000
+}
+
+fn default_ttl_secs() -> u64 {
+    60
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Validators {
+    pub etag: String,
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    pub fn last_modified_date(&self) -> Option<DateTime<Utc>> {
+        let last_modified = DateTime::parse_from_rfc2822(self.last_modified.as_deref()?).ok()?;
+        Some(last_modified.with_timezone(&Utc))
+    }
+}
+
+/// Cache of object path to its validators, used to answer `If-None-Match` requests with a
+/// `304 Not Modified` without going to the backend.
+#[derive(Clone)]
+pub struct EtagCache {
+    /// cache is already an Arc
+    cache: Option<Cache<String, Validators>>,
+}
+
+impl EtagCache {
+    pub fn new(config: &EtagCacheConfig) -> EtagCache {
+        let cache = (config.capacity > 0).then(|| {
+            Cache::builder()
+                .max_capacity(config.capacity)
+                .time_to_live(Duration::from_secs(config.ttl_secs))
+                .build()
+        });
+
+        EtagCache { cache }
+    }
+
+    pub fn get(&self, path: &str) -> Option<Validators> {
+        self.cache.as_ref()?.get(path)
+    }
+
+    pub fn insert(&self, path: &str, validators: Validators) {
+        if let Some(cache) = &self.cache {
+            cache.insert(path.to_string(), validators);
+        }
+    }
+
+    pub fn invalidate(&self, path: &str) {
+        if let Some(cache) = &self.cache {
+            cache.invalidate(path);
+        }
+    }
+}
+
+/// Strips the quotes and weak prefix, etags are compared weakly for `If-None-Match`.
+pub fn normalize_etag(etag: &str) -> &str {
+    etag.trim().trim_start_matches("W/").trim_matches('"')
+}
+
+/// Whether the `If-None-Match` header matches the given etag.
+pub fn if_none_match(header_map: &HeaderMap, etag: &str) -> bool {
+    matches(header_map, IF_NONE_MATCH, Some(etag), false)
+}
+
+/// `If-Match` compares strongly, a weak etag on either side never matches, `If-None-Match`
+/// weakly (RFC 9110 13.1.1 and 13.1.2).
+fn matches(header_map: &HeaderMap, header: HeaderName, etag: Option<&str>, strong: bool) -> bool {
+    let is_weak = |x: &str| x.trim().starts_with("W/");
+    let etag = etag.filter(|x| !(strong && is_weak(x))).map(normalize_etag);
+
+    header_map
+        .get_all(header)
+        .iter()
+        .filter_map(|x| x.to_str().ok())
+        .flat_map(|x| x.split(','))
+        .any(|candidate| {
+            candidate.trim() == "*"
+                || (!(strong && is_weak(candidate)) && Some(normalize_etag(candidate)) == etag)
+        })
+}
+
+fn date(header_map: &HeaderMap, header: HeaderName) -> Option<DateTime<Utc>> {
+    let value = header_map.get(header)?.to_str().ok()?;
+    DateTime::parse_from_rfc2822(value)
+        .ok()
+        .map(|x| x.with_timezone(&Utc))
+}
+
+/// The outcome of the conditional headers of a `GET` or `HEAD`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precondition {
+    Proceed,
+    /// `304 Not Modified`
+    NotModified,
+    /// `412 Precondition Failed`
+    Failed,
+}
+
+/// Evaluates `If-Match`, `If-Unmodified-Since`, `If-None-Match` and `If-Modified-Since` in the
+/// order of RFC 7232, the dates are ignored when the matching etag header is present.
+pub fn preconditions(
+    header_map: &HeaderMap,
+    etag: Option<&str>,
+    last_modified: Option<DateTime<Utc>>,
+) -> Precondition {
+    // http dates have no sub-second precision
+    let modified_after =
+        |since: DateTime<Utc>| last_modified.is_some_and(|x| x.timestamp() > since.timestamp());
+
+    if header_map.contains_key(IF_MATCH) {
+        if !matches(header_map, IF_MATCH, etag, true) {
+            return Precondition::Failed;
+        }
+    } else if date(header_map, IF_UNMODIFIED_SINCE).is_some_and(modified_after) {
+        return Precondition::Failed;
+    }
+
+    if header_map.contains_key(IF_NONE_MATCH) {
+        if matches(header_map, IF_NONE_MATCH, etag, false) {
+            return Precondition::NotModified;
+        }
+    } else if date(header_map, IF_MODIFIED_SINCE)
+        .is_some_and(|since| last_modified.is_some() && !modified_after(since))
+    {
+        return Precondition::NotModified;
+    }
+
+    Precondition::Proceed
+}
+
+#[test]
+fn if_none_match_test() {
+    let mut header_map = HeaderMap::new();
+    header_map.insert(
+        axum::http::header::IF_NONE_MATCH,
+        axum::http::HeaderValue::from_static(r#""abc", W/"def""#),
+    );
+
+    assert!(if_none_match(&header_map, "abc"));
+    assert!(if_none_match(&header_map, r#""def""#));
+    assert!(!if_none_match(&header_map, "ghi"));
+    assert!(!if_none_match(&HeaderMap::new(), "abc"));
+}
+
+#[test]
+fn disabled_cache_stores_nothing() {
+    let cache = EtagCache::new(&EtagCacheConfig {
+        capacity: 0,
+        ttl_secs: 60,
+    });
+    let validators = Validators {
+        etag: String::from("abc"),
+        last_modified: None,
+    };
+
+    cache.insert("ns/bucket/key", validators.clone());
+    assert_eq!(cache.get("ns/bucket/key"), None);
+
+    let cache = EtagCache::new(&EtagCacheConfig::default());
+    cache.insert("ns/bucket/key", validators.clone());
+    assert_eq!(cache.get("ns/bucket/key"), Some(validators));
+    cache.invalidate("ns/bucket/key");
+    assert_eq!(cache.get("ns/bucket/key"), None);
+}
+
+#[test]
+fn preconditions_follow_the_rfc_order() {
+    use axum::http::HeaderValue;
+
+    let headers = |pairs: &[(HeaderName, &'static str)]| {
+        let mut header_map = HeaderMap::new();
+        for (name, value) in pairs {
+            header_map.insert(name.clone(), HeaderValue::from_static(value));
+        }
+        header_map
+    };
+    let last_modified = DateTime::parse_from_rfc2822("Wed, 21 Oct 2015 07:28:00 GMT")
+        .unwrap()
+        .with_timezone(&Utc);
+    let check =
+        |header_map: &HeaderMap| preconditions(header_map, Some("abc"), Some(last_modified));
+
+    assert_eq!(check(&HeaderMap::new()), Precondition::Proceed);
+    assert_eq!(
+        check(&headers(&[(IF_MATCH, r#""abc""#)])),
+        Precondition::Proceed
+    );
+    assert_eq!(
+        check(&headers(&[(IF_MATCH, r#""def""#)])),
+        Precondition::Failed
+    );
+    // weak etags never satisfy If-Match, but do If-None-Match
+    assert_eq!(
+        check(&headers(&[(IF_MATCH, r#"W/"abc""#)])),
+        Precondition::Failed
+    );
+    assert_eq!(
+        preconditions(
+            &headers(&[(IF_MATCH, r#""abc""#)]),
+            Some(r#"W/"abc""#),
+            None
+        ),
+        Precondition::Failed
+    );
+    assert_eq!(
+        check(&headers(&[(IF_NONE_MATCH, r#"W/"abc""#)])),
+        Precondition::NotModified
+    );
+    assert_eq!(
+        check(&headers(&[(
+            IF_UNMODIFIED_SINCE,
+            "Tue, 20 Oct 2015 07:28:00 GMT"
+        )])),
+        Precondition::Failed
+    );
+    // a matching etag wins over the date
+    assert_eq!(
+        check(&headers(&[
+            (IF_MATCH, r#""abc""#),
+            (IF_UNMODIFIED_SINCE, "Tue, 20 Oct 2015 07:28:00 GMT")
+        ])),
+        Precondition::Proceed
+    );
+    assert_eq!(
+        check(&headers(&[(IF_NONE_MATCH, "*")])),
+        Precondition::NotModified
+    );
+    assert_eq!(
+        check(&headers(&[(
+            IF_MODIFIED_SINCE,
+            "Wed, 21 Oct 2015 07:28:00 GMT"
+        )])),
+        Precondition::NotModified
+    );
+    assert_eq!(
+        check(&headers(&[(
+            IF_MODIFIED_SINCE,
+            "Tue, 20 Oct 2015 07:28:00 GMT"
+        )])),
+        Precondition::Proceed
+    );
+    assert_eq!(
+        check(&headers(&[
+            (IF_NONE_MATCH, r#""def""#),
+            (IF_MODIFIED_SINCE, "Wed, 21 Oct 2015 07:28:00 GMT")
+        ])),
+        Precondition::Proceed
+    );
+    // without an etag only a wildcard matches
+    assert_eq!(
+        preconditions(&headers(&[(IF_MATCH, "*")]), None, None),
+        Precondition::Proceed
+    );
+    assert_eq!(
+        preconditions(&headers(&[(IF_MATCH, r#""abc""#)]), None, None),
+        Precondition::Failed
+    );
+}
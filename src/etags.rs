@@ -0,0 +1,117 @@
+use crate::metadata::{MetadataError, MetadataStore};
+use md5::{Digest, Md5};
+
+/// `etag::{namespace}/{bucket}/{key}` holds the ETag computed while the object was uploaded
+pub const ETAG_PREFIX: &str = "etag::";
+
+/// The quoted md5 of the body, the ETag S3 gives objects uploaded in one part.
+pub fn md5_etag(hasher: Md5) -> String {
+    format!("\"{}\"", hex::encode(hasher.finalize()))
+}
+
+pub async fn store(metadata: &MetadataStore, path: &str, etag: &str) -> Result<(), MetadataError> {
+    metadata
+        .set(&format!("{}{}", ETAG_PREFIX, path), etag)
+        .await
+}
+
+pub async fn remove(metadata: &MetadataStore, path: &str) -> Result<(), MetadataError> {
+    metadata.delete(&format!("{}{}", ETAG_PREFIX, path)).await
+}
+
+/// Fills in the stored ETag of the object at `path` when the backend does not report one.
+pub async fn fill(
+    metadata: &MetadataStore,
+    path: &str,
+    object: &mut opendal::Metadata,
+) -> Result<(), MetadataError> {
+    if object.etag().is_some() {
+        return Ok(());
+    }
+    if let Some(etag) = metadata.get(&format!("{}{}", ETAG_PREFIX, path)).await? {
+        object.set_etag(&etag);
+    }
+    Ok(())
+}
+
+/// `fill` for the objects of a listing, `root` is the path their keys are relative to.
+pub async fn fill_many(
+    metadata: &MetadataStore,
+    root: &str,
+    objects: &mut [(String, opendal::Metadata)],
+) -> Result<(), MetadataError> {
+    let missing: Vec<_> = objects
+        .iter_mut()
+        .filter(|(_, object)| object.etag().is_none())
+        .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    let keys: Vec<_> = missing
+        .iter()
+        .map(|(key, _)| format!("{}{}{}", ETAG_PREFIX, root, key))
+        .collect();
+    let etags = metadata.get_many(&keys).await?;
+    for ((_, object), etag) in missing.into_iter().zip(etags) {
+        if let Some(etag) = etag {
+            object.set_etag(&etag);
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn stored_etags_fill_in_for_the_backend() {
+    let operator = opendal::Operator::new(opendal::services::Memory::default())
+        .unwrap()
+        .finish();
+    operator
+        .write("ns/bucket/a.txt", "hello world")
+        .await
+        .unwrap();
+    operator.write("ns/bucket/b.txt", "hello").await.unwrap();
+
+    let metadata = MetadataStore::memory();
+    let mut hasher = Md5::new();
+    hasher.update(b"hello world");
+    let etag = md5_etag(hasher);
+    assert_eq!(etag, "\"5eb63bbbe01eeed093cb22bb8f5acdc3\"");
+    store(&metadata, "ns/bucket/a.txt", &etag).await.unwrap();
+
+    let mut object = operator.stat("ns/bucket/a.txt").await.unwrap();
+    fill(&metadata, "ns/bucket/a.txt", &mut object)
+        .await
+        .unwrap();
+    assert_eq!(object.etag(), Some(etag.as_str()));
+
+    // the etag of the backend is kept
+    let mut object = object.with_etag(String::from("\"b\""));
+    fill(&metadata, "ns/bucket/a.txt", &mut object)
+        .await
+        .unwrap();
+    assert_eq!(object.etag(), Some("\"b\""));
+
+    let mut objects = vec![
+        (
+            String::from("a.txt"),
+            operator.stat("ns/bucket/a.txt").await.unwrap(),
+        ),
+        (
+            String::from("b.txt"),
+            operator.stat("ns/bucket/b.txt").await.unwrap(),
+        ),
+    ];
+    fill_many(&metadata, "ns/bucket/", &mut objects)
+        .await
+        .unwrap();
+    assert_eq!(objects[0].1.etag(), Some(etag.as_str()));
+    assert_eq!(objects[1].1.etag(), None);
+
+    remove(&metadata, "ns/bucket/a.txt").await.unwrap();
+    let mut object = operator.stat("ns/bucket/a.txt").await.unwrap();
+    fill(&metadata, "ns/bucket/a.txt", &mut object)
+        .await
+        .unwrap();
+    assert_eq!(object.etag(), None);
+}
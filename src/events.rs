@@ -0,0 +1,117 @@
+use async_trait::async_trait;
+use std::sync::Arc;
+
+/// The object an event is about.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectEvent {
+    pub namespace: String,
+    pub bucket: String,
+    pub key: String,
+    pub metadata: ObjectMetadata,
+}
+
+/// What is known about the object without an extra backend call.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ObjectMetadata {
+    pub content_type: Option<String>,
+    pub content_length: u64,
+    /// only known for reads, the backend computes it on write
+    pub etag: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BucketEvent {
+    pub namespace: String,
+    pub bucket: String,
+}
+
+/// Called after an operation succeeded, before the response is sent.
+///
+/// Hooks should return quickly, spawn a task for slow work like sending notifications.
+#[async_trait]
+pub trait ObjectEventHook: Send + Sync {
+    async fn on_put(&self, _event: &ObjectEvent) {}
+
+    /// for reads the event is sent once the headers are known, not when the body is done
+    async fn on_get(&self, _event: &ObjectEvent) {}
+
+    async fn on_delete(&self, _event: &ObjectEvent) {}
+
+    async fn on_bucket_create(&self, _event: &BucketEvent) {}
+}
+
+/// The registered hooks, called in registration order.
+#[derive(Clone, Default)]
+pub struct EventHooks {
+    hooks: Arc<Vec<Arc<dyn ObjectEventHook>>>,
+}
+
+impl EventHooks {
+    pub fn new(hooks: Vec<Arc<dyn ObjectEventHook>>) -> EventHooks {
+        EventHooks {
+            hooks: Arc::new(hooks),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    pub async fn put(&self, event: &ObjectEvent) {
+        for hook in self.hooks.iter() {
+            hook.on_put(event).await;
+        }
+    }
+
+    pub async fn get(&self, event: &ObjectEvent) {
+        for hook in self.hooks.iter() {
+            hook.on_get(event).await;
+        }
+    }
+
+    pub async fn delete(&self, event: &ObjectEvent) {
+        for hook in self.hooks.iter() {
+            hook.on_delete(event).await;
+        }
+    }
+
+    pub async fn bucket_create(&self, event: &BucketEvent) {
+        for hook in self.hooks.iter() {
+            hook.on_bucket_create(event).await;
+        }
+    }
+}
+
+#[tokio::test]
+async fn hooks_are_called_in_order() {
+    use std::sync::Mutex;
+
+    struct Recorder(&'static str, Arc<Mutex<Vec<String>>>);
+
+    #[async_trait]
+    impl ObjectEventHook for Recorder {
+        async fn on_put(&self, event: &ObjectEvent) {
+            self.1
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", self.0, event.key));
+        }
+    }
+
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let hooks = EventHooks::new(vec![
+        Arc::new(Recorder("first", calls.clone())),
+        Arc::new(Recorder("second", calls.clone())),
+    ]);
+
+    let event = ObjectEvent {
+        namespace: String::from("ns"),
+        bucket: String::from("bucket"),
+        key: String::from("key"),
+        metadata: ObjectMetadata::default(),
+    };
+    hooks.put(&event).await;
+    hooks.get(&event).await;
+
+    assert_eq!(*calls.lock().unwrap(), vec!["first key", "second key"]);
+}
@@ -0,0 +1,223 @@
+use crate::error::S3Error;
+use crate::events::{ObjectEvent, ObjectMetadata};
+use crate::metadata::{MetadataError, MetadataStore};
+use crate::{acl, checksums, etags, object_lock, storage_class, tagging, AppState};
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use serde::Deserialize;
+use std::time::{Duration, SystemTime};
+
+/// Non-standard PUT header, the object is deleted this many seconds after it was written.
+pub static TTL_HEADER: HeaderName = HeaderName::from_static("x-s3proxy-ttl-seconds");
+/// `expires::{namespace}/{bucket}/{key}` holds the unix timestamp the object expires at
+pub const EXPIRES_PREFIX: &str = "expires::";
+/// Tells clients when an object expires and by which lifecycle rule, like S3 does.
+pub static EXPIRATION_HEADER: HeaderName = HeaderName::from_static("x-amz-expiration");
+/// The rule id in `x-amz-expiration`, objects expire by their ttl instead of a bucket rule
+pub const TTL_RULE_ID: &str = "ttl";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExpirationConfig {
+    /// how often expired objects are deleted, 0 disables the worker
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+}
+
+impl Default for ExpirationConfig {
+    fn default() -> Self {
+        ExpirationConfig {
+            interval_secs: default_interval_secs(),
+        }
+    }
+}
+
+fn default_interval_secs() -> u64 {
+    60
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// The ttl a PUT asked for.
+pub fn ttl(headers: &HeaderMap) -> Result<Option<Duration>, S3Error> {
+    let Some(value) = headers.get(&TTL_HEADER) else {
+        return Ok(None);
+    };
+
+    match value.to_str().ok().and_then(|x| x.parse::<u64>().ok()) {
+        Some(seconds) if seconds > 0 => Ok(Some(Duration::from_secs(seconds))),
+        _ => Err(S3Error::InvalidArgument(format!(
+            "{} must be a positive number of seconds",
+            TTL_HEADER
+        ))),
+    }
+}
+
+/// Schedules the deletion of the object at `path`, without a ttl an earlier one is cleared
+/// so overwritten objects are kept. Returns the unix timestamp the object expires at.
+pub async fn schedule(
+    metadata: &MetadataStore,
+    path: &str,
+    ttl: Option<Duration>,
+) -> Result<Option<u64>, MetadataError> {
+    let key = format!("{}{}", EXPIRES_PREFIX, path);
+    match ttl {
+        Some(ttl) => {
+            let expires_at = unix_now() + ttl.as_secs();
+            metadata.set(&key, &expires_at.to_string()).await?;
+            Ok(Some(expires_at))
+        }
+        None => {
+            metadata.delete(&key).await?;
+            Ok(None)
+        }
+    }
+}
+
+/// The unix timestamp the object at `path` expires at.
+pub async fn expires_at(
+    metadata: &MetadataStore,
+    path: &str,
+) -> Result<Option<u64>, MetadataError> {
+    Ok(metadata
+        .get(&format!("{}{}", EXPIRES_PREFIX, path))
+        .await?
+        .and_then(|x| x.parse().ok()))
+}
+
+/// `expiry-date="Fri, 23 Dec 2022 00:00:00 GMT", rule-id="ttl"`
+pub fn header_value(expires_at: u64) -> Option<HeaderValue> {
+    let expires_at = chrono::DateTime::from_timestamp(expires_at.try_into().ok()?, 0)?;
+    HeaderValue::from_str(&format!(
+        "expiry-date=\"{}\", rule-id=\"{}\"",
+        crate::api::http_date(expires_at),
+        TTL_RULE_ID
+    ))
+    .ok()
+}
+
+/// Deletes the objects whose ttl passed, returns the amount of deleted objects.
+pub async fn expire(state: &AppState) -> Result<u64, S3Error> {
+    let now = unix_now();
+    let keys = state.metadata.keys(EXPIRES_PREFIX).await?;
+    let values = state.metadata.get_many(&keys).await?;
+
+    let mut expired = 0;
+    for (key, expires_at) in keys.iter().zip(values) {
+        let Some(expires_at) = expires_at.and_then(|x| x.parse::<u64>().ok()) else {
+            continue;
+        };
+        if expires_at > now {
+            continue;
+        }
+
+        let path = &key[EXPIRES_PREFIX.len()..];
+        // a lock outlives the ttl, the object expires once the lock is lifted
+        match object_lock::check_unlocked(&state.metadata, path, false).await {
+            Err(S3Error::ObjectLocked) => continue,
+            result => result?,
+        }
+        state.opendal_operator.delete(path).await?;
+        state.metadata.delete(key).await?;
+        etags::remove(&state.metadata, path).await?;
+        checksums::remove(&state.metadata, path).await?;
+        tagging::remove_object_tags(&state.metadata, path).await?;
+        storage_class::remove(&state.metadata, path).await?;
+        acl::remove(&state.metadata, path).await?;
+        object_lock::remove(&state.metadata, path).await?;
+        state.etag_cache.invalidate(path);
+        state.read_cache.invalidate(path);
+        expired += 1;
+
+        let mut parts = path.splitn(3, '/');
+        if let (Some(namespace), Some(bucket), Some(key)) =
+            (parts.next(), parts.next(), parts.next())
+        {
+            state.listing_cache.invalidate(namespace, bucket);
+            state
+                .event_hooks
+                .delete(&ObjectEvent {
+                    namespace: namespace.to_string(),
+                    bucket: bucket.to_string(),
+                    key: key.to_string(),
+                    metadata: ObjectMetadata::default(),
+                })
+                .await;
+        }
+    }
+
+    Ok(expired)
+}
+
+pub async fn expire_periodically(state: AppState, every: Duration) {
+    let mut interval = tokio::time::interval(every);
+
+    loop {
+        interval.tick().await;
+        let Some(_lease) = state.leases.for_job("expiration").await else {
+            continue;
+        };
+
+        match expire(&state).await {
+            Ok(0) => (),
+            Ok(expired) => tracing::info!("deleted {} expired objects", expired),
+            Err(error) => tracing::error!("unable to delete expired objects: {}", error),
+        }
+    }
+}
+
+#[tokio::test]
+async fn expired_objects_are_deleted() {
+    let state = AppState::builder(crate::Config::builder().build())
+        .metadata(MetadataStore::memory())
+        .build()
+        .unwrap();
+    for path in ["tenant/cache/a.json", "tenant/cache/b.json"] {
+        state.opendal_operator.write(path, "{}").await.unwrap();
+        schedule(&state.metadata, path, Some(Duration::from_secs(3600)))
+            .await
+            .unwrap();
+    }
+    state
+        .metadata
+        .set("expires::tenant/cache/a.json", "1")
+        .await
+        .unwrap();
+
+    assert_eq!(expire(&state).await.unwrap(), 1);
+    assert!(!state
+        .opendal_operator
+        .is_exist("tenant/cache/a.json")
+        .await
+        .unwrap());
+    assert!(state
+        .opendal_operator
+        .is_exist("tenant/cache/b.json")
+        .await
+        .unwrap());
+
+    // an overwrite without ttl keeps the object
+    schedule(&state.metadata, "tenant/cache/b.json", None)
+        .await
+        .unwrap();
+    assert!(state
+        .metadata
+        .keys(EXPIRES_PREFIX)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let mut headers = HeaderMap::new();
+    headers.insert(&TTL_HEADER, "0".parse().unwrap());
+    assert!(ttl(&headers).is_err());
+    headers.insert(&TTL_HEADER, "60".parse().unwrap());
+    assert_eq!(ttl(&headers).unwrap(), Some(Duration::from_secs(60)));
+
+    assert_eq!(
+        header_value(1671753600).unwrap(),
+        r#"expiry-date="Fri, 23 Dec 2022 00:00:00 GMT", rule-id="ttl""#
+    );
+}
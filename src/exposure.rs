@@ -0,0 +1,516 @@
+use crate::acl::OBJECT_ACL_PREFIX;
+use crate::domains::Domains;
+use crate::metadata::{MetadataError, MetadataStore};
+use crate::namespaces::{
+    Namespaces, BUCKET_ACL_PREFIX, BUCKET_POLICY_PREFIX, POLICY_PREFIX, PUBLIC_PREFIX,
+};
+use crate::AppState;
+use anyhow::Context;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Actions that let other clients read objects or list them.
+const READ_ACTIONS: &[&str] = &["s3:GetObject", "s3:GetObjectVersion", "s3:ListBucket"];
+/// Actions that let other clients change or remove objects.
+const WRITE_ACTIONS: &[&str] = &["s3:PutObject", "s3:DeleteObject", "s3:DeleteBucket"];
+
+/// What makes a bucket or prefix reachable without the keys of its namespace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExposureSource {
+    /// marked public on the admin api, served on `/_public/`
+    PublicBucket,
+    /// a custom domain of a public bucket
+    Domain,
+    /// an `Allow` statement for principals outside the namespace in the namespace policy,
+    /// enforced on every bucket of the namespace. Policies never allow unsigned requests, other
+    /// namespaces reach the bucket with their keys through its custom domain.
+    Policy,
+    /// the same in the policy of a bucket
+    BucketPolicy,
+    /// a `public-read` bucket ACL, anyone can list the keys on the custom domains of the bucket
+    BucketAcl,
+    /// a `public-read` object ACL, served on `/_public/` and the custom domains of the bucket
+    ObjectAcl,
+}
+
+impl ExposureSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExposureSource::PublicBucket => "public bucket",
+            ExposureSource::Domain => "domain",
+            ExposureSource::Policy => "namespace policy",
+            ExposureSource::BucketPolicy => "bucket policy",
+            ExposureSource::BucketAcl => "bucket acl",
+            ExposureSource::ObjectAcl => "object acl",
+        }
+    }
+}
+
+/// A bucket or prefix that anyone, or keys of other namespaces, can read or write.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Exposure {
+    pub namespace: String,
+    /// `*` when the policy names every bucket
+    pub bucket: String,
+    /// empty for the whole bucket, the key for object ACLs
+    pub prefix: String,
+    pub read: bool,
+    pub write: bool,
+    /// `false` for policy statements that only name other namespaces or their keys
+    pub everyone: bool,
+    pub source: ExposureSource,
+    /// the public path, the domain, the policy statement or the domains of a bucket ACL
+    pub detail: String,
+    /// the policy statement has a `Condition`, which is not evaluated
+    pub conditional: bool,
+}
+
+/// `*` matches any run of characters, actions are compared ignoring case like IAM does.
+fn matches(pattern: &str, value: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern.eq_ignore_ascii_case(value),
+        Some((head, tail)) => {
+            value.len() >= head.len()
+                && value.is_char_boundary(head.len())
+                && value[..head.len()].eq_ignore_ascii_case(head)
+                && (head.len()..=value.len())
+                    .filter(|x| value.is_char_boundary(*x))
+                    .any(|x| matches(tail, &value[x..]))
+        }
+    }
+}
+
+/// A policy field that is a string or a list of strings.
+fn strings(value: Option<&Value>) -> Vec<&str> {
+    match value {
+        Some(Value::String(x)) => vec![x.as_str()],
+        Some(Value::Array(x)) => x.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// The principals of a statement from outside the namespace, `own_keys` are the access keys of
+/// the namespace.
+fn outsiders<'a>(
+    namespace: &str,
+    own_keys: &[String],
+    principal: Option<&'a Value>,
+) -> Vec<&'a str> {
+    let principals = match principal {
+        Some(Value::String(x)) => vec![x.as_str()],
+        Some(Value::Object(x)) => strings(x.get("AWS")),
+        _ => Vec::new(),
+    };
+    let root = format!("arn:aws:iam::{}:root", namespace);
+    principals
+        .into_iter()
+        .filter(|x| *x != root && !own_keys.iter().any(|key| key == x))
+        .collect()
+}
+
+/// The statements of a namespace or bucket policy that allow principals outside the namespace.
+/// `Deny` statements are not subtracted, so the report can list more than is reachable but
+/// never less.
+pub fn analyze_policy(
+    namespace: &str,
+    own_keys: &[String],
+    policy: &Value,
+    source: ExposureSource,
+) -> Vec<Exposure> {
+    let statements = match policy.get("Statement") {
+        Some(Value::Array(x)) => x.iter().collect(),
+        Some(statement) => vec![statement],
+        None => Vec::new(),
+    };
+
+    let mut exposures = Vec::new();
+    for (index, statement) in statements.into_iter().enumerate() {
+        let principals = outsiders(namespace, own_keys, statement.get("Principal"));
+        if statement.get("Effect").and_then(Value::as_str) != Some("Allow") || principals.is_empty()
+        {
+            continue;
+        }
+        let everyone = principals.contains(&"*");
+
+        let actions = strings(statement.get("Action"));
+        let allows = |candidates: &[&str]| {
+            actions
+                .iter()
+                .any(|action| candidates.iter().any(|x| matches(action, x)))
+        };
+        let (read, write) = (allows(READ_ACTIONS), allows(WRITE_ACTIONS));
+        if !read && !write {
+            continue;
+        }
+
+        let mut detail = match statement.get("Sid").and_then(Value::as_str) {
+            Some(sid) => format!("statement {}", sid),
+            None => format!("statement {}", index),
+        };
+        if !everyone {
+            detail = format!("{} for {}", detail, principals.join(", "));
+        }
+        for resource in strings(statement.get("Resource")) {
+            let path = match resource {
+                "*" => "*",
+                _ => match resource.strip_prefix("arn:aws:s3:::") {
+                    Some(path) => path,
+                    None => continue,
+                },
+            };
+            let (bucket, prefix) = path.split_once('/').unwrap_or((path, ""));
+
+            exposures.push(Exposure {
+                namespace: namespace.to_string(),
+                bucket: bucket.to_string(),
+                prefix: prefix.trim_end_matches('*').to_string(),
+                read,
+                write,
+                everyone,
+                source,
+                detail: detail.clone(),
+                conditional: statement.get("Condition").is_some(),
+            });
+        }
+    }
+
+    exposures
+}
+
+/// Every bucket and prefix that can be reached without a key of its namespace.
+pub async fn report(
+    metadata: &MetadataStore,
+    domains: &Domains,
+) -> Result<Vec<Exposure>, MetadataError> {
+    let mut exposures = Vec::new();
+
+    for key in metadata.keys(PUBLIC_PREFIX).await? {
+        let Some((namespace, bucket)) = key[PUBLIC_PREFIX.len()..].rsplit_once("::") else {
+            continue;
+        };
+        exposures.push(Exposure {
+            namespace: namespace.to_string(),
+            bucket: bucket.to_string(),
+            prefix: String::new(),
+            read: true,
+            write: false,
+            everyone: true,
+            source: ExposureSource::PublicBucket,
+            detail: format!("/_public/{}/{}/", namespace, bucket),
+            conditional: false,
+        });
+    }
+
+    // the domains of private buckets still need a signature
+    let mapped = domains.list(metadata).await?;
+    for (domain, mapping) in &mapped {
+        let public = exposures.iter().any(|x| {
+            x.source == ExposureSource::PublicBucket
+                && x.namespace == mapping.namespace
+                && x.bucket == mapping.bucket
+        });
+        if public {
+            exposures.push(Exposure {
+                namespace: mapping.namespace.clone(),
+                bucket: mapping.bucket.clone(),
+                prefix: String::new(),
+                read: true,
+                write: false,
+                everyone: true,
+                source: ExposureSource::Domain,
+                detail: domain.clone(),
+                conditional: false,
+            });
+        }
+    }
+
+    for key in metadata.keys(BUCKET_ACL_PREFIX).await? {
+        let Some((namespace, bucket)) = key[BUCKET_ACL_PREFIX.len()..].rsplit_once("::") else {
+            continue;
+        };
+        // listing needs a custom domain, a bucket without one is listed for when it gets one
+        let names: Vec<_> = mapped
+            .iter()
+            .filter(|(_, x)| x.namespace == namespace && x.bucket == bucket)
+            .map(|(domain, _)| domain.as_str())
+            .collect();
+        exposures.push(Exposure {
+            namespace: namespace.to_string(),
+            bucket: bucket.to_string(),
+            prefix: String::new(),
+            read: true,
+            write: false,
+            everyone: true,
+            source: ExposureSource::BucketAcl,
+            detail: match names.is_empty() {
+                true => String::from("listing, no custom domain yet"),
+                false => format!("listing on {}", names.join(", ")),
+            },
+            conditional: false,
+        });
+    }
+
+    for key in metadata.keys(OBJECT_ACL_PREFIX).await? {
+        let path = &key[OBJECT_ACL_PREFIX.len()..];
+        let mut parts = path.splitn(3, '/');
+        let (Some(namespace), Some(bucket), Some(object)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            continue;
+        };
+        exposures.push(Exposure {
+            namespace: namespace.to_string(),
+            bucket: bucket.to_string(),
+            prefix: object.to_string(),
+            read: true,
+            write: false,
+            everyone: true,
+            source: ExposureSource::ObjectAcl,
+            detail: format!("/_public/{}", path),
+            conditional: false,
+        });
+    }
+
+    let namespaces = Namespaces::new(metadata);
+    let mut own_keys = HashMap::new();
+    for (prefix, source) in [
+        (POLICY_PREFIX, ExposureSource::Policy),
+        (BUCKET_POLICY_PREFIX, ExposureSource::BucketPolicy),
+    ] {
+        let keys = metadata.keys(prefix).await?;
+        let policies = metadata.get_many(&keys).await?;
+        for (key, policy) in keys.iter().zip(policies) {
+            let name = &key[prefix.len()..];
+            // bucket policies are keyed by namespace and bucket
+            let namespace = name
+                .rsplit_once("::")
+                .map_or(name, |(namespace, _)| namespace);
+            if !own_keys.contains_key(namespace) {
+                own_keys.insert(namespace.to_string(), namespaces.keys(namespace).await?);
+            }
+            match policy.map(|x| serde_json::from_str::<Value>(&x)) {
+                Some(Ok(policy)) => exposures.extend(analyze_policy(
+                    namespace,
+                    &own_keys[namespace],
+                    &policy,
+                    source,
+                )),
+                Some(Err(error)) => {
+                    tracing::warn!("the policy of {} is not json: {}", name, error)
+                }
+                None => (),
+            }
+        }
+    }
+
+    exposures.sort();
+    Ok(exposures)
+}
+
+/// The `public-access` subcommand, prints the report of the server config.
+pub async fn run(app_state: &AppState) -> anyhow::Result<()> {
+    let exposures = report(&app_state.metadata, &app_state.domains)
+        .await
+        .context("unable to read the metadata store")?;
+
+    for exposure in &exposures {
+        let access = match (exposure.read, exposure.write) {
+            (true, true) => "readable and writable",
+            (false, true) => "writable",
+            _ => "readable",
+        };
+        println!(
+            "{}/{}/{}{} is {} {} through the {} ({}){}",
+            exposure.namespace,
+            exposure.bucket,
+            exposure.prefix,
+            // object ACLs are about a single key
+            if exposure.source == ExposureSource::ObjectAcl {
+                ""
+            } else {
+                "*"
+            },
+            if exposure.everyone {
+                "publicly"
+            } else {
+                "cross-namespace"
+            },
+            access,
+            exposure.source.as_str(),
+            exposure.detail,
+            if exposure.conditional {
+                ", with a condition"
+            } else {
+                ""
+            }
+        );
+    }
+    println!("{} public buckets or prefixes", exposures.len());
+
+    Ok(())
+}
+
+#[test]
+fn public_policy_statements_are_found() {
+    let policy = serde_json::json!({
+        "Version": "2012-10-17",
+        "Statement": [
+            {
+                "Sid": "website",
+                "Effect": "Allow",
+                "Principal": "*",
+                "Action": "s3:GetObject",
+                "Resource": "arn:aws:s3:::site/public/*"
+            },
+            {
+                "Effect": "Allow",
+                "Principal": {"AWS": ["*"]},
+                "Action": ["s3:Put*"],
+                "Resource": ["arn:aws:s3:::uploads/*"],
+                "Condition": {"IpAddress": {"aws:SourceIp": "10.0.0.0/8"}}
+            },
+            {
+                "Effect": "Allow",
+                "Principal": {"AWS": ["arn:aws:iam::tenant:root", "AKIDTENANT"]},
+                "Action": "s3:*",
+                "Resource": "*"
+            },
+            {
+                "Sid": "partner",
+                "Effect": "Allow",
+                "Principal": {"AWS": ["AKIDTENANT", "arn:aws:iam::partner:root"]},
+                "Action": "s3:GetObject",
+                "Resource": "arn:aws:s3:::reports/*"
+            },
+            {
+                "Effect": "Deny",
+                "Principal": "*",
+                "Action": "s3:*",
+                "Resource": "*"
+            }
+        ]
+    });
+
+    // the namespace itself and its own keys are not exposures
+    let exposures = analyze_policy(
+        "tenant",
+        &[String::from("AKIDTENANT")],
+        &policy,
+        ExposureSource::Policy,
+    );
+    assert_eq!(exposures.len(), 3);
+    assert_eq!(
+        (
+            exposures[0].bucket.as_str(),
+            exposures[0].prefix.as_str(),
+            exposures[0].read,
+            exposures[0].write
+        ),
+        ("site", "public/", true, false)
+    );
+    assert_eq!(exposures[0].detail, "statement website");
+    assert_eq!(
+        (
+            exposures[1].bucket.as_str(),
+            exposures[1].read,
+            exposures[1].write,
+            exposures[1].conditional
+        ),
+        ("uploads", false, true, true)
+    );
+    assert!(exposures[1].everyone);
+    assert_eq!(exposures[2].bucket, "reports");
+    assert!(!exposures[2].everyone);
+    assert_eq!(
+        exposures[2].detail,
+        "statement partner for arn:aws:iam::partner:root"
+    );
+
+    assert!(matches("s3:*Object", "s3:GetObject"));
+    assert!(!matches("s3:Get*", "s3:PutObject"));
+}
+
+#[tokio::test]
+async fn public_buckets_and_their_domains_are_reported() {
+    use crate::domains::DomainMapping;
+    use crate::namespaces::Namespaces;
+
+    let metadata = MetadataStore::memory();
+    let domains = Domains::new(&Default::default());
+    let namespaces = Namespaces::new(&metadata);
+    namespaces
+        .set_public("tenant", "assets", true)
+        .await
+        .unwrap();
+    namespaces
+        .set_policy(
+            "tenant",
+            r#"{"Statement": {"Effect": "Allow", "Principal": "*", "Action": "*", "Resource": "*"}}"#,
+        )
+        .await
+        .unwrap();
+    for (domain, bucket) in [
+        ("assets.example.com", "assets"),
+        ("private.example.com", "logs"),
+    ] {
+        domains
+            .set(
+                &metadata,
+                domain,
+                &DomainMapping {
+                    namespace: String::from("tenant"),
+                    bucket: String::from(bucket),
+                },
+            )
+            .await
+            .unwrap();
+    }
+
+    // acls set over the S3 api
+    namespaces
+        .set_bucket_acl("tenant", "logs", crate::acl::CannedAcl::PublicRead)
+        .await
+        .unwrap();
+    crate::acl::store(
+        &metadata,
+        "tenant/docs/guide/index.html",
+        Some(crate::acl::CannedAcl::PublicRead),
+    )
+    .await
+    .unwrap();
+    namespaces
+        .set_bucket_policy(
+            "tenant",
+            "logs",
+            r#"{"Statement": {"Effect": "Allow", "Principal": {"AWS": "arn:aws:iam::auditor:root"}, "Action": "s3:GetObject", "Resource": "arn:aws:s3:::logs/*"}}"#,
+        )
+        .await
+        .unwrap();
+
+    let exposures = report(&metadata, &domains).await.unwrap();
+    let found: Vec<_> = exposures
+        .iter()
+        .map(|x| (x.bucket.as_str(), x.source, x.write))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            ("*", ExposureSource::Policy, true),
+            ("assets", ExposureSource::PublicBucket, false),
+            ("assets", ExposureSource::Domain, false),
+            ("docs", ExposureSource::ObjectAcl, false),
+            ("logs", ExposureSource::BucketPolicy, false),
+            ("logs", ExposureSource::BucketAcl, false),
+        ]
+    );
+    let object = &exposures[3];
+    assert_eq!(object.prefix, "guide/index.html");
+    assert_eq!(object.detail, "/_public/tenant/docs/guide/index.html");
+    assert_eq!(
+        exposures[4].detail,
+        "statement 0 for arn:aws:iam::auditor:root"
+    );
+    assert_eq!(exposures[5].detail, "listing on private.example.com");
+}
@@ -0,0 +1,124 @@
+use serde::Deserialize;
+use sha2::Digest;
+use std::fmt;
+
+/// Which SHA-256 implementation checks the signed payloads.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Sha256Implementation {
+    /// ring when compiled in, sha2 otherwise
+    #[default]
+    Auto,
+    /// RustCrypto sha2, uses the SHA extensions of the cpu when it has them
+    Sha2,
+    /// ring's assembly implementation, needs the `ring` feature
+    Ring,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct HashingConfig {
+    #[serde(default)]
+    pub sha256: Sha256Implementation,
+}
+
+impl Sha256Implementation {
+    /// Resolves `Auto`, fails for implementations that are not compiled in.
+    pub fn select(self) -> anyhow::Result<Sha256Implementation> {
+        match self {
+            Sha256Implementation::Auto if cfg!(feature = "ring") => Ok(Sha256Implementation::Ring),
+            Sha256Implementation::Auto => Ok(Sha256Implementation::Sha2),
+            Sha256Implementation::Ring if !cfg!(feature = "ring") => {
+                anyhow::bail!(
+                    "S3_PROXY__HASHING__SHA256 is ring but s3-proxy is compiled without the \
+                     `ring` feature"
+                )
+            }
+            implementation => Ok(implementation),
+        }
+    }
+
+    pub fn hasher(self) -> Sha256Hasher {
+        #[cfg(feature = "ring")]
+        if self != Sha256Implementation::Sha2 {
+            return Sha256Hasher::Ring(ring::digest::Context::new(&ring::digest::SHA256));
+        }
+
+        Sha256Hasher::Sha2(sha2::Sha256::new())
+    }
+}
+
+impl fmt::Display for Sha256Implementation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Sha256Implementation::Auto => f.write_str("auto"),
+            Sha256Implementation::Sha2 if cpu_has_sha_extensions() => {
+                f.write_str("sha2 with cpu sha extensions")
+            }
+            Sha256Implementation::Sha2 => f.write_str("sha2 in software"),
+            Sha256Implementation::Ring => f.write_str("ring"),
+        }
+    }
+}
+
+/// sha2 picks the accelerated code at runtime with the same check.
+fn cpu_has_sha_extensions() -> bool {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    return std::arch::is_x86_feature_detected!("sha")
+        && std::arch::is_x86_feature_detected!("sse4.1");
+
+    #[cfg(target_arch = "aarch64")]
+    return std::arch::is_aarch64_feature_detected!("sha2");
+
+    #[allow(unreachable_code)]
+    false
+}
+
+pub enum Sha256Hasher {
+    Sha2(sha2::Sha256),
+    #[cfg(feature = "ring")]
+    Ring(ring::digest::Context),
+}
+
+impl Default for Sha256Hasher {
+    fn default() -> Self {
+        Sha256Hasher::Sha2(sha2::Sha256::new())
+    }
+}
+
+impl Sha256Hasher {
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Sha256Hasher::Sha2(hasher) => hasher.update(data),
+            #[cfg(feature = "ring")]
+            Sha256Hasher::Ring(context) => context.update(data),
+        }
+    }
+
+    pub fn finalize(self) -> [u8; 32] {
+        match self {
+            Sha256Hasher::Sha2(hasher) => hasher.finalize().into(),
+            #[cfg(feature = "ring")]
+            Sha256Hasher::Ring(context) => context
+                .finish()
+                .as_ref()
+                .try_into()
+                .expect("sha256 is 32 bytes"),
+        }
+    }
+}
+
+#[test]
+fn implementations_hash_the_same() {
+    let implementation = Sha256Implementation::Auto.select().unwrap();
+    assert_ne!(implementation, Sha256Implementation::Auto);
+
+    for implementation in [implementation, Sha256Implementation::Sha2] {
+        let mut hasher = implementation.hasher();
+        hasher.update(b"hel");
+        hasher.update(b"lo");
+        assert_eq!(
+            hex::encode(hasher.finalize()),
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+    }
+}
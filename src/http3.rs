@@ -0,0 +1,217 @@
+use axum::Router;
+use serde::Deserialize;
+
+/// Serves the S3 api over QUIC next to the TCP listener, needs the `http3` feature.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Http3Config {
+    /// udp address to listen on, like `0.0.0.0:443`
+    pub host: String,
+    /// PEM certificate chain, QUIC always uses TLS
+    pub cert_path: String,
+    /// PEM private key of the certificate
+    pub key_path: String,
+    /// directory of `{host name}.pem` files with a certificate chain and key each, picked by SNI
+    /// before the certificate above. `_.example.com.pem` serves `*.example.com`
+    pub cert_dir: Option<String>,
+    /// how often the certificates are read again, 0 disables reloading
+    #[serde(default = "default_cert_reload_secs")]
+    pub cert_reload_secs: u64,
+}
+
+fn default_cert_reload_secs() -> u64 {
+    60
+}
+
+/// The bound QUIC endpoint, bound up front so a bad certificate fails the startup.
+pub struct Http3Listener {
+    #[cfg(feature = "http3")]
+    endpoint: quinn::Endpoint,
+    #[cfg(feature = "http3")]
+    certificates: std::sync::Arc<crate::sni::resolver::SniResolver>,
+    #[cfg(feature = "http3")]
+    cert_reload: Option<std::time::Duration>,
+}
+
+impl Http3Listener {
+    pub fn bind(config: &Http3Config) -> anyhow::Result<Http3Listener> {
+        #[cfg(feature = "http3")]
+        return listener::bind(config);
+
+        #[cfg(not(feature = "http3"))]
+        anyhow::bail!(
+            "http3 is configured for {} but s3-proxy is compiled without the `http3` feature",
+            config.host
+        )
+    }
+
+    /// The udp port, advertised to TCP clients in the `Alt-Svc` header.
+    pub fn port(&self) -> u16 {
+        #[cfg(feature = "http3")]
+        return self.endpoint.local_addr().map_or(0, |x| x.port());
+
+        #[cfg(not(feature = "http3"))]
+        0
+    }
+
+    pub async fn serve(self, app: Router) -> std::io::Result<()> {
+        #[cfg(feature = "http3")]
+        {
+            if let Some(every) = self.cert_reload {
+                tokio::spawn(self.certificates.reload_periodically(every));
+            }
+            listener::serve(self.endpoint, app).await;
+        }
+
+        #[cfg(not(feature = "http3"))]
+        let _ = app;
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "http3")]
+mod listener {
+    use super::{Http3Config, Http3Listener};
+    use crate::sni::resolver::SniResolver;
+    use anyhow::Context;
+    use axum::body::{Body, Bytes};
+    use axum::http::header::HOST;
+    use axum::http::{HeaderValue, Request};
+    use axum::Router;
+    use bytes::Buf;
+    use futures::StreamExt;
+    use h3::server::RequestResolver;
+    use quinn::crypto::rustls::QuicServerConfig;
+    use quinn::rustls;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    pub fn bind(config: &Http3Config) -> anyhow::Result<Http3Listener> {
+        let certificates = Arc::new(SniResolver::load(config)?);
+
+        let mut tls = rustls::ServerConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .with_no_client_auth()
+        .with_cert_resolver(certificates.clone());
+        tls.alpn_protocols = vec![b"h3".to_vec()];
+
+        let server_config =
+            quinn::ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(tls)?));
+        let addr = config
+            .host
+            .parse()
+            .with_context(|| format!("{} is not a socket address", config.host))?;
+
+        Ok(Http3Listener {
+            endpoint: quinn::Endpoint::server(server_config, addr)?,
+            certificates,
+            cert_reload: (config.cert_reload_secs > 0)
+                .then(|| Duration::from_secs(config.cert_reload_secs)),
+        })
+    }
+
+    pub async fn serve(endpoint: quinn::Endpoint, app: Router) {
+        while let Some(incoming) = endpoint.accept().await {
+            let app = app.clone();
+            tokio::spawn(async move {
+                let connection = match incoming.await {
+                    Ok(connection) => connection,
+                    Err(error) => {
+                        tracing::debug!("quic handshake failed: {}", error);
+                        return;
+                    }
+                };
+                let mut connection = match h3::server::Connection::new(h3_quinn::Connection::new(
+                    connection,
+                ))
+                .await
+                {
+                    Ok(connection) => connection,
+                    Err(error) => {
+                        tracing::debug!("http3 connection failed: {}", error);
+                        return;
+                    }
+                };
+
+                loop {
+                    match connection.accept().await {
+                        Ok(Some(resolver)) => {
+                            tokio::spawn(handle(resolver, app.clone()));
+                        }
+                        Ok(None) => break,
+                        Err(error) => {
+                            tracing::debug!("http3 connection closed with error: {}", error);
+                            break;
+                        }
+                    }
+                }
+            });
+        }
+    }
+
+    async fn handle(resolver: RequestResolver<h3_quinn::Connection, Bytes>, app: Router) {
+        let (request, stream) = match resolver.resolve_request().await {
+            Ok(request) => request,
+            Err(error) => {
+                tracing::debug!("unable to read http3 request: {}", error);
+                return;
+            }
+        };
+        let (mut sender, receiver) = stream.split();
+
+        let body = futures::stream::unfold(receiver, |mut receiver| async move {
+            match receiver.recv_data().await {
+                Ok(Some(mut data)) => {
+                    let chunk = data.copy_to_bytes(data.remaining());
+                    Some((Ok(chunk), receiver))
+                }
+                Ok(None) => None,
+                Err(error) => Some((Err(error), receiver)),
+            }
+        });
+        let (mut parts, ()) = request.into_parts();
+        // the signature covers the host header, http/3 only sends the authority
+        if !parts.headers.contains_key(HOST) {
+            if let Some(authority) = parts.uri.authority() {
+                if let Ok(host) = HeaderValue::from_str(authority.as_str()) {
+                    parts.headers.insert(HOST, host);
+                }
+            }
+        }
+        let request = Request::from_parts(parts, Body::from_stream(body));
+
+        let response = match app.oneshot(request).await {
+            Ok(response) => response,
+            Err(error) => match error {},
+        };
+        let (parts, body) = response.into_parts();
+        if let Err(error) = sender
+            .send_response(axum::http::Response::from_parts(parts, ()))
+            .await
+        {
+            tracing::debug!("unable to send http3 response: {}", error);
+            return;
+        }
+
+        let mut body = body.into_data_stream();
+        while let Some(chunk) = body.next().await {
+            let result = match chunk {
+                Ok(chunk) => sender.send_data(chunk).await,
+                Err(error) => {
+                    tracing::debug!("http3 response body failed: {}", error);
+                    return;
+                }
+            };
+            if let Err(error) = result {
+                tracing::debug!("unable to send http3 response body: {}", error);
+                return;
+            }
+        }
+        if let Err(error) = sender.finish().await {
+            tracing::debug!("unable to finish http3 response: {}", error);
+        }
+    }
+}
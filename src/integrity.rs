@@ -0,0 +1,90 @@
+use axum::body::Bytes;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use md5::{Digest, Md5};
+use std::io;
+
+/// The md5 in the ETag of an object uploaded in one part.
+///
+/// Multipart ETags (`"<md5>-<parts>"`) and the ETags of backends that do not hash the content
+/// can not be checked against the body.
+pub fn md5_etag(etag: &str) -> Option<[u8; 16]> {
+    let etag = etag.strip_prefix("W/").unwrap_or(etag).trim_matches('"');
+    let mut md5 = [0; 16];
+    hex::decode_to_slice(etag, &mut md5).ok()?;
+    Some(md5)
+}
+
+/// Passes `body` through and fails the stream at the end when it does not hash to `expected`.
+///
+/// The headers are already sent by then, so the client sees an aborted response instead of a
+/// complete corrupt one. `on_mismatch` is called before the error is returned.
+pub fn verify(
+    body: BoxStream<'static, io::Result<Bytes>>,
+    expected: [u8; 16],
+    on_mismatch: impl FnOnce() + Send + 'static,
+) -> BoxStream<'static, io::Result<Bytes>> {
+    let state = (body, Some((Md5::new(), on_mismatch)));
+
+    futures::stream::unfold(state, move |(mut body, mut hasher)| async move {
+        match body.next().await {
+            Some(Ok(chunk)) => {
+                if let Some((hasher, _)) = &mut hasher {
+                    hasher.update(&chunk);
+                }
+                Some((Ok(chunk), (body, hasher)))
+            }
+            Some(Err(error)) => Some((Err(error), (body, None))),
+            None => {
+                let (hasher, on_mismatch) = hasher?;
+                if hasher.finalize()[..] == expected {
+                    return None;
+                }
+                on_mismatch();
+                let error =
+                    io::Error::new(io::ErrorKind::InvalidData, "object does not match its etag");
+                Some((Err(error), (body, None)))
+            }
+        }
+    })
+    .boxed()
+}
+
+#[tokio::test]
+async fn corrupt_bodies_fail_at_the_end() {
+    use futures::TryStreamExt;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    // md5 of `hello world`
+    let expected = md5_etag("\"5eb63bbbe01eeed093cb22bb8f5acdc3\"").unwrap();
+    assert!(md5_etag("\"5eb63bbbe01eeed093cb22bb8f5acdc3-2\"").is_none());
+    assert!(md5_etag("1708426f7d0").is_none());
+
+    let body = |chunks: &[&'static str]| {
+        futures::stream::iter(
+            chunks
+                .iter()
+                .map(|x| Ok(Bytes::from(*x)))
+                .collect::<Vec<_>>(),
+        )
+        .boxed()
+    };
+    let read = verify(body(&["hello ", "world"]), expected, || unreachable!())
+        .map_ok(|x| x.to_vec())
+        .try_concat()
+        .await
+        .unwrap();
+    assert_eq!(read, b"hello world");
+
+    let mismatched = Arc::new(AtomicBool::new(false));
+    let flag = mismatched.clone();
+    let chunks: Vec<_> = verify(body(&["hello ", "w0rld"]), expected, move || {
+        flag.store(true, Ordering::SeqCst)
+    })
+    .collect()
+    .await;
+    assert_eq!(chunks.len(), 3);
+    assert!(chunks[2].is_err());
+    assert!(mismatched.load(Ordering::SeqCst));
+}
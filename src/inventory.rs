@@ -0,0 +1,514 @@
+use crate::error::S3Error;
+use crate::metadata::MetadataStore;
+use crate::namespaces::{Namespaces, INVENTORY_PREFIX};
+use crate::{storage_class, AppState};
+use flate2::write::GzEncoder;
+use futures::TryStreamExt;
+use md5::{Digest, Md5};
+use opendal::Metakey;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use rand::distributions::{Alphanumeric, DistString};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::{Duration, SystemTime};
+
+/// `inventory_run::{namespace}::{bucket}` holds the unix timestamp of the last report per id
+pub const INVENTORY_RUN_PREFIX: &str = "inventory_run::";
+
+/// The fields S3 adds after `Bucket` and `Key` that the proxy knows about.
+const OPTIONAL_FIELDS: &[&str] = &["Size", "LastModifiedDate", "ETag", "StorageClass"];
+
+/// Keys are url-encoded in the CSV like S3 does, `/` is kept.
+const KEY_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'/')
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct InventoryConfig {
+    /// how often the schedules are checked, 0 disables the reports
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+}
+
+impl Default for InventoryConfig {
+    fn default() -> Self {
+        InventoryConfig {
+            interval_secs: default_interval_secs(),
+        }
+    }
+}
+
+fn default_interval_secs() -> u64 {
+    3600
+}
+
+/// An `InventoryConfiguration` of a bucket, set with `PutBucketInventoryConfiguration`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct InventoryConfiguration {
+    pub id: String,
+    pub is_enabled: bool,
+    pub destination: InventoryDestination,
+    #[serde(default)]
+    pub filter: Option<InventoryFilter>,
+    /// only `Current`, buckets are not versioned
+    pub included_object_versions: String,
+    #[serde(default)]
+    pub optional_fields: InventoryOptionalFields,
+    pub schedule: InventorySchedule,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct InventoryDestination {
+    pub s3_bucket_destination: InventoryS3BucketDestination,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct InventoryS3BucketDestination {
+    #[serde(default)]
+    pub account_id: Option<String>,
+    /// `arn:aws:s3:::{bucket}`, a bucket of the same namespace
+    pub bucket: String,
+    /// only `CSV`
+    pub format: String,
+    #[serde(default)]
+    pub prefix: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct InventoryFilter {
+    #[serde(default)]
+    pub prefix: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InventoryOptionalFields {
+    #[serde(default, rename = "Field")]
+    pub fields: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct InventorySchedule {
+    /// `Daily` or `Weekly`
+    pub frequency: String,
+}
+
+impl InventoryConfiguration {
+    /// Checks the configuration, the error is the message for the client.
+    pub fn validate(&self) -> Result<(), String> {
+        let destination = &self.destination.s3_bucket_destination;
+        if self.id.is_empty() {
+            return Err(String::from("the configuration has no id"));
+        }
+        if destination.format != "CSV" {
+            return Err(format!("unsupported format {}", destination.format));
+        }
+        if self.destination_bucket().is_none() {
+            return Err(format!("{} is not a bucket arn", destination.bucket));
+        }
+        if self.included_object_versions != "Current" {
+            return Err(format!(
+                "unsupported included object versions {}",
+                self.included_object_versions
+            ));
+        }
+        if self.period().is_none() {
+            return Err(format!("unsupported frequency {}", self.schedule.frequency));
+        }
+        if let Some(field) = self
+            .optional_fields
+            .fields
+            .iter()
+            .find(|x| !OPTIONAL_FIELDS.contains(&x.as_str()))
+        {
+            return Err(format!("unsupported optional field {}", field));
+        }
+
+        Ok(())
+    }
+
+    pub fn destination_bucket(&self) -> Option<&str> {
+        self.destination
+            .s3_bucket_destination
+            .bucket
+            .strip_prefix("arn:aws:s3:::")
+            .filter(|x| !x.is_empty() && !x.contains('/'))
+    }
+
+    fn period(&self) -> Option<Duration> {
+        match self.schedule.frequency.as_str() {
+            "Daily" => Some(Duration::from_secs(86400)),
+            "Weekly" => Some(Duration::from_secs(7 * 86400)),
+            _ => None,
+        }
+    }
+
+    fn filter_prefix(&self) -> &str {
+        self.filter.as_ref().map_or("", |x| x.prefix.as_str())
+    }
+
+    /// `{prefix}/{source bucket}/{id}/`, the directory of the reports in the destination
+    fn report_prefix(&self, bucket: &str) -> String {
+        match self.destination.s3_bucket_destination.prefix.as_deref() {
+            Some(prefix) if !prefix.is_empty() => {
+                format!("{}/{}/{}/", prefix.trim_end_matches('/'), bucket, self.id)
+            }
+            _ => format!("{}/{}/", bucket, self.id),
+        }
+    }
+
+    fn file_schema(&self) -> String {
+        ["Bucket", "Key"]
+            .into_iter()
+            .chain(self.optional_fields.fields.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn csv_field(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+/// Writes the gzip'd CSV of the bucket and its `manifest.json` and `manifest.checksum` into the
+/// destination bucket, in the layout of S3 Inventory. Returns the key of the manifest.
+pub async fn export(
+    state: &AppState,
+    namespace: &str,
+    bucket: &str,
+    configuration: &InventoryConfiguration,
+) -> Result<String, S3Error> {
+    let Some(destination) = configuration.destination_bucket() else {
+        return Err(S3Error::InvalidArgument(String::from(
+            "the destination is not a bucket arn",
+        )));
+    };
+    let operator = &state.opendal_operator;
+    if !operator
+        .is_exist(&format!("{}/{}/", namespace, destination))
+        .await?
+    {
+        return Err(S3Error::NoSuchBucket);
+    }
+
+    let bucket_path = format!("{}/{}/", namespace, bucket);
+    let mut lister = operator
+        .lister_with(&bucket_path)
+        .recursive(true)
+        .metakey(Metakey::ContentLength | Metakey::LastModified | Metakey::Etag)
+        .await?;
+
+    let mut csv = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    while let Some(entry) = lister.try_next().await? {
+        let metadata = entry.metadata();
+        let key = entry.path().strip_prefix(&bucket_path).unwrap_or_default();
+        if !metadata.is_file() || !key.starts_with(configuration.filter_prefix()) {
+            continue;
+        }
+
+        let mut row = vec![
+            csv_field(bucket),
+            csv_field(&utf8_percent_encode(key, KEY_ENCODE_SET).to_string()),
+        ];
+        for field in &configuration.optional_fields.fields {
+            let value = match field.as_str() {
+                "Size" => metadata.content_length().to_string(),
+                "LastModifiedDate" => metadata
+                    .last_modified()
+                    .map(|x| x.to_rfc3339_opts(chrono::SecondsFormat::Millis, true))
+                    .unwrap_or_default(),
+                "ETag" => metadata
+                    .etag()
+                    .map(|x| x.trim_matches('"').to_string())
+                    .unwrap_or_default(),
+                "StorageClass" => storage_class::stored(&state.metadata, entry.path())
+                    .await?
+                    .unwrap_or_else(|| String::from(storage_class::STANDARD)),
+                _ => String::new(),
+            };
+            row.push(csv_field(&value));
+        }
+        writeln!(csv, "{}", row.join(",")).map_err(S3Error::internal)?;
+    }
+    let csv = csv.finish().map_err(S3Error::internal)?;
+
+    let now = chrono::Utc::now();
+    let report_prefix = configuration.report_prefix(bucket);
+    let data_key = format!(
+        "{}data/{}.csv.gz",
+        report_prefix,
+        Alphanumeric.sample_string(&mut rand::thread_rng(), 32)
+    );
+    let manifest_dir = format!("{}{}/", report_prefix, now.format("%Y-%m-%dT%H-%MZ"));
+
+    let manifest = json!({
+        "sourceBucket": bucket,
+        "destinationBucket": configuration.destination.s3_bucket_destination.bucket,
+        "version": "2016-11-30",
+        "creationTimestamp": now.timestamp_millis().to_string(),
+        "fileFormat": "CSV",
+        "fileSchema": configuration.file_schema(),
+        "files": [{
+            "key": data_key,
+            "size": csv.len(),
+            "MD5checksum": hex::encode(Md5::digest(&csv)),
+        }],
+    });
+    let manifest = serde_json::to_vec_pretty(&manifest).map_err(S3Error::internal)?;
+    let checksum = hex::encode(Md5::digest(&manifest));
+
+    let destination_path = format!("{}/{}/", namespace, destination);
+    operator
+        .write(&format!("{}{}", destination_path, data_key), csv)
+        .await?;
+    operator
+        .write(
+            &format!("{}{}manifest.json", destination_path, manifest_dir),
+            manifest,
+        )
+        .await?;
+    operator
+        .write(
+            &format!("{}{}manifest.checksum", destination_path, manifest_dir),
+            checksum,
+        )
+        .await?;
+    state.listing_cache.invalidate(namespace, destination);
+
+    Ok(format!("{}manifest.json", manifest_dir))
+}
+
+async fn last_runs(
+    metadata: &MetadataStore,
+    namespace: &str,
+    bucket: &str,
+) -> Result<HashMap<String, u64>, S3Error> {
+    let runs = metadata
+        .get(&format!(
+            "{}{}::{}",
+            INVENTORY_RUN_PREFIX, namespace, bucket
+        ))
+        .await?;
+
+    Ok(runs
+        .and_then(|x| serde_json::from_str(&x).ok())
+        .unwrap_or_default())
+}
+
+/// Exports the enabled configurations whose schedule is due, returns the amount of reports.
+pub async fn export_due(state: &AppState) -> Result<u64, S3Error> {
+    let now = unix_now();
+    let mut exported = 0;
+
+    for key in state.metadata.keys(INVENTORY_PREFIX).await? {
+        let Some((namespace, bucket)) = key[INVENTORY_PREFIX.len()..].rsplit_once("::") else {
+            continue;
+        };
+        let configurations = Namespaces::new(&state.metadata)
+            .inventory_configurations(namespace, bucket)
+            .await?;
+        let mut runs = last_runs(&state.metadata, namespace, bucket).await?;
+        let before = runs.clone();
+
+        for configuration in configurations.iter().filter(|x| x.is_enabled) {
+            let Some(period) = configuration.period() else {
+                continue;
+            };
+            let last_run = runs.get(&configuration.id).copied().unwrap_or_default();
+            if last_run + period.as_secs() > now {
+                continue;
+            }
+
+            match export(state, namespace, bucket, configuration).await {
+                Ok(_) => exported += 1,
+                Err(error) => tracing::warn!(
+                    "unable to export inventory {} of {}/{}: {}",
+                    configuration.id,
+                    namespace,
+                    bucket,
+                    error
+                ),
+            }
+            // a failed report is retried on the next schedule instead of every interval
+            runs.insert(configuration.id.clone(), now);
+        }
+
+        // runs of deleted configurations are forgotten
+        runs.retain(|id, _| configurations.iter().any(|x| &x.id == id));
+        if runs != before {
+            let runs = serde_json::to_string(&runs).expect("inventory runs serialize");
+            state
+                .metadata
+                .set(
+                    &format!("{}{}::{}", INVENTORY_RUN_PREFIX, namespace, bucket),
+                    &runs,
+                )
+                .await?;
+        }
+    }
+
+    Ok(exported)
+}
+
+pub async fn export_periodically(state: AppState, every: Duration) {
+    let mut interval = tokio::time::interval(every);
+
+    loop {
+        interval.tick().await;
+        let Some(_lease) = state.leases.for_job("inventory").await else {
+            continue;
+        };
+
+        match export_due(&state).await {
+            Ok(0) => (),
+            Ok(exported) => tracing::info!("exported {} inventory reports", exported),
+            Err(error) => tracing::error!("unable to export inventory reports: {}", error),
+        }
+    }
+}
+
+#[test]
+fn inventory_configurations_are_validated() {
+    let configuration: InventoryConfiguration = quick_xml::de::from_str(
+        r#"<InventoryConfiguration xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+            <Destination>
+                <S3BucketDestination>
+                    <Bucket>arn:aws:s3:::reports</Bucket>
+                    <Format>CSV</Format>
+                    <Prefix>inventory</Prefix>
+                </S3BucketDestination>
+            </Destination>
+            <IsEnabled>true</IsEnabled>
+            <Filter><Prefix>logs/</Prefix></Filter>
+            <Id>daily</Id>
+            <IncludedObjectVersions>Current</IncludedObjectVersions>
+            <OptionalFields>
+                <Field>Size</Field>
+                <Field>LastModifiedDate</Field>
+            </OptionalFields>
+            <Schedule><Frequency>Daily</Frequency></Schedule>
+        </InventoryConfiguration>"#,
+    )
+    .unwrap();
+
+    assert_eq!(configuration.validate(), Ok(()));
+    assert_eq!(configuration.destination_bucket(), Some("reports"));
+    assert_eq!(
+        configuration.file_schema(),
+        "Bucket, Key, Size, LastModifiedDate"
+    );
+    assert_eq!(
+        configuration.report_prefix("source"),
+        "inventory/source/daily/"
+    );
+
+    let mut parquet = configuration.clone();
+    parquet.destination.s3_bucket_destination.format = String::from("Parquet");
+    assert!(parquet.validate().is_err());
+
+    let mut encrypted = configuration;
+    encrypted
+        .optional_fields
+        .fields
+        .push(String::from("EncryptionStatus"));
+    assert!(encrypted.validate().is_err());
+}
+
+#[tokio::test]
+async fn inventory_reports_are_written_to_the_destination() {
+    use std::io::Read;
+
+    let state = AppState::builder(crate::Config::builder().build())
+        .metadata(MetadataStore::memory())
+        .build()
+        .unwrap();
+    let operator = &state.opendal_operator;
+    operator.create_dir("tenant/reports/").await.unwrap();
+    operator
+        .write("tenant/source/logs/a b.txt", "hello")
+        .await
+        .unwrap();
+    operator
+        .write("tenant/source/other.txt", "skipped")
+        .await
+        .unwrap();
+
+    let configuration = InventoryConfiguration {
+        id: String::from("daily"),
+        is_enabled: true,
+        destination: InventoryDestination {
+            s3_bucket_destination: InventoryS3BucketDestination {
+                account_id: None,
+                bucket: String::from("arn:aws:s3:::reports"),
+                format: String::from("CSV"),
+                prefix: None,
+            },
+        },
+        filter: Some(InventoryFilter {
+            prefix: String::from("logs/"),
+        }),
+        included_object_versions: String::from("Current"),
+        optional_fields: InventoryOptionalFields {
+            fields: vec![String::from("Size")],
+        },
+        schedule: InventorySchedule {
+            frequency: String::from("Daily"),
+        },
+    };
+    Namespaces::new(&state.metadata)
+        .set_inventory_configurations("tenant", "source", &[configuration])
+        .await
+        .unwrap();
+
+    assert_eq!(export_due(&state).await.unwrap(), 1);
+    // the next report is due tomorrow
+    assert_eq!(export_due(&state).await.unwrap(), 0);
+
+    let manifests: Vec<_> = operator
+        .list_with("tenant/reports/source/daily/")
+        .recursive(true)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|x| x.path().to_string())
+        .filter(|x| x.ends_with("manifest.json"))
+        .collect();
+    assert_eq!(manifests.len(), 1);
+    let manifest = operator.read(&manifests[0]).await.unwrap();
+    let checksum = operator
+        .read(&manifests[0].replace("manifest.json", "manifest.checksum"))
+        .await
+        .unwrap();
+    assert_eq!(checksum, hex::encode(Md5::digest(&manifest)).into_bytes());
+
+    let manifest: serde_json::Value = serde_json::from_slice(&manifest).unwrap();
+    assert_eq!(manifest["sourceBucket"], "source");
+    assert_eq!(manifest["fileSchema"], "Bucket, Key, Size");
+    let data_key = manifest["files"][0]["key"].as_str().unwrap();
+    let data = operator
+        .read(&format!("tenant/reports/{}", data_key))
+        .await
+        .unwrap();
+
+    let mut csv = String::new();
+    flate2::read::GzDecoder::new(&data[..])
+        .read_to_string(&mut csv)
+        .unwrap();
+    assert_eq!(csv, "\"source\",\"logs/a%20b.txt\",\"5\"\n");
+}
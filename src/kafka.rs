@@ -0,0 +1,220 @@
+use crate::notifications::EventFormat;
+use serde::Deserialize;
+use std::hash::{Hash, Hasher};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PartitionBy {
+    /// events of a bucket stay in order
+    #[default]
+    Bucket,
+    /// spreads the events of busy buckets, only the events of one key stay in order
+    Key,
+}
+
+/// Publishes object writes and deletes to a kafka topic, needs the `kafka` feature.
+#[derive(Debug, Clone, Deserialize)]
+pub struct KafkaConfig {
+    /// comma separated `host:port` list
+    pub brokers: String,
+    pub topic: String,
+    #[serde(default)]
+    pub format: EventFormat,
+    #[serde(default)]
+    pub partition_by: PartitionBy,
+}
+
+impl KafkaConfig {
+    fn brokers(&self) -> Vec<String> {
+        self.brokers
+            .split(',')
+            .map(str::trim)
+            .filter(|x| !x.is_empty())
+            .map(String::from)
+            .collect()
+    }
+}
+
+/// The record key, records with the same key end up in the same partition.
+#[cfg_attr(not(feature = "kafka"), allow(dead_code))]
+fn record_key(partition_by: PartitionBy, namespace: &str, bucket: &str, key: &str) -> String {
+    match partition_by {
+        PartitionBy::Bucket => format!("{}/{}", namespace, bucket),
+        PartitionBy::Key => format!("{}/{}/{}", namespace, bucket, key),
+    }
+}
+
+/// Picks the partition of a record key, stable across restarts.
+#[cfg_attr(not(feature = "kafka"), allow(dead_code))]
+fn partition_for(record_key: &str, partitions: &[i32]) -> i32 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    record_key.hash(&mut hasher);
+    partitions[(hasher.finish() % partitions.len() as u64) as usize]
+}
+
+#[cfg(feature = "kafka")]
+pub use sink::KafkaSink;
+
+#[cfg(feature = "kafka")]
+mod sink {
+    use super::{partition_for, record_key, KafkaConfig};
+    use crate::notifications::{Notification, NotificationSink};
+    use anyhow::Context;
+    use async_trait::async_trait;
+    use rskafka::client::partition::{Compression, PartitionClient, UnknownTopicHandling};
+    use rskafka::client::{Client, ClientBuilder};
+    use rskafka::record::Record;
+    use std::collections::{BTreeMap, HashMap};
+    use std::sync::Arc;
+    use tokio::sync::{Mutex, OnceCell};
+
+    /// Connects on the first event, so the proxy starts while the brokers are unreachable.
+    pub struct KafkaSink {
+        config: KafkaConfig,
+        client: OnceCell<Client>,
+        partitions: OnceCell<Vec<i32>>,
+        partition_clients: Mutex<HashMap<i32, Arc<PartitionClient>>>,
+    }
+
+    impl KafkaSink {
+        pub fn new(config: KafkaConfig) -> KafkaSink {
+            KafkaSink {
+                config,
+                client: OnceCell::new(),
+                partitions: OnceCell::new(),
+                partition_clients: Mutex::new(HashMap::new()),
+            }
+        }
+
+        async fn partition_client(&self, partition: i32) -> anyhow::Result<Arc<PartitionClient>> {
+            let mut clients = self.partition_clients.lock().await;
+            if let Some(client) = clients.get(&partition) {
+                return Ok(client.clone());
+            }
+
+            let client = Arc::new(
+                self.client()
+                    .await?
+                    .partition_client(
+                        self.config.topic.clone(),
+                        partition,
+                        UnknownTopicHandling::Retry,
+                    )
+                    .await?,
+            );
+            clients.insert(partition, client.clone());
+            Ok(client)
+        }
+
+        async fn client(&self) -> anyhow::Result<&Client> {
+            self.client
+                .get_or_try_init(|| async {
+                    ClientBuilder::new(self.config.brokers())
+                        .client_id("s3-proxy")
+                        .build()
+                        .await
+                        .context("unable to connect to the kafka brokers")
+                })
+                .await
+        }
+
+        async fn partitions(&self) -> anyhow::Result<&[i32]> {
+            let partitions = self
+                .partitions
+                .get_or_try_init(|| async {
+                    let topics = self.client().await?.list_topics().await?;
+                    let topic = topics
+                        .into_iter()
+                        .find(|x| x.name == self.config.topic)
+                        .with_context(|| format!("kafka topic {} not found", self.config.topic))?;
+                    anyhow::ensure!(
+                        !topic.partitions.is_empty(),
+                        "kafka topic {} has no partitions",
+                        self.config.topic
+                    );
+
+                    Ok(topic.partitions.into_iter().collect())
+                })
+                .await?;
+
+            Ok(partitions)
+        }
+    }
+
+    #[async_trait]
+    impl NotificationSink for KafkaSink {
+        fn name(&self) -> &'static str {
+            "kafka"
+        }
+
+        async fn publish(&self, notification: &Notification) -> anyhow::Result<()> {
+            let event = &notification.event;
+            let key = record_key(
+                self.config.partition_by,
+                &event.namespace,
+                &event.bucket,
+                &event.key,
+            );
+            let partition = partition_for(&key, self.partitions().await?);
+
+            let record = Record {
+                key: Some(key.into_bytes()),
+                value: Some(serde_json::to_vec(
+                    &notification.render(self.config.format),
+                )?),
+                headers: BTreeMap::from([(
+                    String::from("content-type"),
+                    b"application/json".to_vec(),
+                )]),
+                timestamp: chrono::Utc::now(),
+            };
+
+            self.partition_client(partition)
+                .await?
+                .produce(vec![record], Compression::NoCompression)
+                .await?;
+
+            Ok(())
+        }
+    }
+}
+
+/// The event hook for the `S3_PROXY__KAFKA__*` settings.
+pub fn start(config: &KafkaConfig) -> anyhow::Result<crate::notifications::Publisher> {
+    anyhow::ensure!(
+        !config.brokers().is_empty(),
+        "S3_PROXY__KAFKA__BROKERS has no brokers"
+    );
+
+    #[cfg(feature = "kafka")]
+    return Ok(crate::notifications::Publisher::start(KafkaSink::new(
+        config.clone(),
+    )));
+
+    #[cfg(not(feature = "kafka"))]
+    anyhow::bail!("kafka is configured but s3-proxy is compiled without the `kafka` feature")
+}
+
+#[test]
+fn records_are_partitioned_by_bucket_or_key() {
+    let partitions = [0, 1, 2, 3, 4, 5, 6, 7];
+    let by_bucket: Vec<_> = ["a.txt", "b.txt", "c.txt"]
+        .into_iter()
+        .map(|key| {
+            partition_for(
+                &record_key(PartitionBy::Bucket, "ns", "photos", key),
+                &partitions,
+            )
+        })
+        .collect();
+    assert!(by_bucket.iter().all(|x| *x == by_bucket[0]));
+
+    assert_eq!(
+        record_key(PartitionBy::Key, "ns", "photos", "a.txt"),
+        "ns/photos/a.txt"
+    );
+    assert_eq!(
+        partition_for("ns/photos/a.txt", &partitions),
+        partition_for("ns/photos/a.txt", &partitions)
+    );
+}
@@ -0,0 +1,267 @@
+use crate::metadata::{MetadataError, MetadataStore};
+use deadpool_redis::redis;
+use deadpool_redis::Pool;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+/// `lease::{name}` holds the token of the instance doing `name` until it expires
+pub const LEASE_PREFIX: &str = "lease::";
+
+/// Only the holder may extend or release a lease, after it expired it can belong to another.
+const EXTEND_SCRIPT: &str = "if redis.call('GET', KEYS[1]) == ARGV[1] then \
+    return redis.call('PEXPIRE', KEYS[1], ARGV[2]) else return 0 end";
+const RELEASE_SCRIPT: &str = "if redis.call('GET', KEYS[1]) == ARGV[1] then \
+    return redis.call('DEL', KEYS[1]) else return 0 end";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LeaseConfig {
+    /// how long the lease of a crashed instance blocks the others, holders extend it every
+    /// third of this
+    #[serde(default = "default_ttl_secs")]
+    pub ttl_secs: u64,
+}
+
+impl Default for LeaseConfig {
+    fn default() -> Self {
+        LeaseConfig {
+            ttl_secs: default_ttl_secs(),
+        }
+    }
+}
+
+fn default_ttl_secs() -> u64 {
+    30
+}
+
+/// Where the leases live, redis shares them between instances.
+enum Holders {
+    Redis(Pool),
+    /// the token and expiry per lease name
+    Local(Mutex<HashMap<String, (String, Instant)>>),
+}
+
+impl Holders {
+    async fn acquire(&self, key: &str, token: &str, ttl: Duration) -> Result<bool, MetadataError> {
+        match self {
+            Holders::Redis(pool) => {
+                let mut conn = pool.get().await?;
+                let set: Option<String> = redis::cmd("SET")
+                    .arg(key)
+                    .arg(token)
+                    .arg("NX")
+                    .arg("PX")
+                    .arg(ttl.as_millis() as u64)
+                    .query_async(&mut conn)
+                    .await?;
+                Ok(set.is_some())
+            }
+            Holders::Local(holders) => {
+                let mut holders = holders.lock().expect("lock is not poisoned");
+                let now = Instant::now();
+                if holders.get(key).is_some_and(|(_, expires)| *expires > now) {
+                    return Ok(false);
+                }
+                holders.insert(key.to_string(), (token.to_string(), now + ttl));
+                Ok(true)
+            }
+        }
+    }
+
+    /// `false` when the lease expired and was taken by someone else in the meantime.
+    async fn extend(&self, key: &str, token: &str, ttl: Duration) -> Result<bool, MetadataError> {
+        match self {
+            Holders::Redis(pool) => {
+                let mut conn = pool.get().await?;
+                let extended: i64 = redis::cmd("EVAL")
+                    .arg(EXTEND_SCRIPT)
+                    .arg(1)
+                    .arg(key)
+                    .arg(token)
+                    .arg(ttl.as_millis() as u64)
+                    .query_async(&mut conn)
+                    .await?;
+                Ok(extended == 1)
+            }
+            Holders::Local(holders) => {
+                let mut holders = holders.lock().expect("lock is not poisoned");
+                match holders.get_mut(key) {
+                    Some((holder, expires)) if holder == token => {
+                        *expires = Instant::now() + ttl;
+                        Ok(true)
+                    }
+                    _ => Ok(false),
+                }
+            }
+        }
+    }
+}
+
+/// Named locks with an expiry, for the work that must not run on two instances at once.
+///
+/// Without redis the leases only exclude the tasks of this instance.
+pub struct Leases {
+    ttl: Duration,
+    holders: Arc<Holders>,
+}
+
+impl Leases {
+    pub fn new(config: &LeaseConfig, metadata: &MetadataStore) -> Leases {
+        let holders = match metadata.redis_pool() {
+            Some(pool) => Holders::Redis(pool.clone()),
+            None => Holders::Local(Mutex::default()),
+        };
+
+        Leases {
+            ttl: Duration::from_secs(config.ttl_secs.max(1)),
+            holders: Arc::new(holders),
+        }
+    }
+
+    /// Takes the lease `name`, `None` while another task or instance holds it.
+    ///
+    /// The lease is extended in the background until the returned guard is dropped.
+    pub async fn try_acquire(&self, name: &str) -> Result<Option<Lease>, MetadataError> {
+        let key = format!("{}{}", LEASE_PREFIX, name);
+        let token = format!("{:032x}", rand::random::<u128>());
+        if !self.holders.acquire(&key, &token, self.ttl).await? {
+            return Ok(None);
+        }
+
+        let keep_alive = tokio::spawn(keep_alive(
+            self.holders.clone(),
+            key.clone(),
+            token.clone(),
+            self.ttl,
+        ));
+        Ok(Some(Lease {
+            holders: self.holders.clone(),
+            key,
+            token,
+            keep_alive,
+        }))
+    }
+
+    /// Waits up to `timeout` for the lease `name`.
+    pub async fn acquire(
+        &self,
+        name: &str,
+        timeout: Duration,
+    ) -> Result<Option<Lease>, MetadataError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(lease) = self.try_acquire(name).await? {
+                return Ok(Some(lease));
+            }
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    /// The lease for a periodic job, logs instead of failing so the job is retried next time.
+    pub async fn for_job(&self, name: &str) -> Option<Lease> {
+        match self.try_acquire(name).await {
+            Ok(Some(lease)) => Some(lease),
+            Ok(None) => {
+                tracing::debug!("skipping {}, another instance is running it", name);
+                None
+            }
+            Err(error) => {
+                tracing::error!("unable to take the {} lease: {}", name, error);
+                None
+            }
+        }
+    }
+}
+
+async fn keep_alive(holders: Arc<Holders>, key: String, token: String, ttl: Duration) {
+    let mut interval = tokio::time::interval(ttl / 3);
+    // the lease was just taken
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+
+        match holders.extend(&key, &token, ttl).await {
+            Ok(true) => (),
+            Ok(false) => {
+                tracing::warn!("lost {}, another instance may be doing the same work", key);
+                return;
+            }
+            Err(error) => tracing::warn!("unable to extend {}: {}", key, error),
+        }
+    }
+}
+
+/// Holds a lease until it is dropped.
+pub struct Lease {
+    holders: Arc<Holders>,
+    key: String,
+    token: String,
+    keep_alive: JoinHandle<()>,
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        self.keep_alive.abort();
+
+        match &*self.holders {
+            Holders::Redis(pool) => {
+                let pool = pool.clone();
+                let key = std::mem::take(&mut self.key);
+                let token = std::mem::take(&mut self.token);
+                tokio::spawn(async move {
+                    let result: Result<(), MetadataError> = async {
+                        let mut conn = pool.get().await?;
+                        redis::cmd("EVAL")
+                            .arg(RELEASE_SCRIPT)
+                            .arg(1)
+                            .arg(&key)
+                            .arg(&token)
+                            .query_async::<_, i64>(&mut conn)
+                            .await?;
+                        Ok(())
+                    }
+                    .await;
+                    // it expires on its own
+                    if let Err(error) = result {
+                        tracing::warn!("unable to release {}: {}", key, error);
+                    }
+                });
+            }
+            Holders::Local(holders) => {
+                let mut holders = holders.lock().expect("lock is not poisoned");
+                if holders
+                    .get(&self.key)
+                    .is_some_and(|(holder, _)| *holder == self.token)
+                {
+                    holders.remove(&self.key);
+                }
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn leases_are_held_by_one_task_at_a_time() {
+    let leases = Leases::new(&LeaseConfig { ttl_secs: 1 }, &MetadataStore::memory());
+
+    let lease = leases.try_acquire("expiration").await.unwrap().unwrap();
+    assert!(leases.try_acquire("expiration").await.unwrap().is_none());
+    assert!(leases.try_acquire("inventory").await.unwrap().is_some());
+
+    // kept alive past the ttl while held
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert!(leases.try_acquire("expiration").await.unwrap().is_none());
+
+    drop(lease);
+    assert!(leases
+        .acquire("expiration", Duration::from_secs(1))
+        .await
+        .unwrap()
+        .is_some());
+}
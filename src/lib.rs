@@ -0,0 +1,459 @@
+use axum::extract::State;
+use axum::http::header::ALT_SVC;
+use axum::http::{HeaderName, HeaderValue};
+use axum::response::{IntoResponse, Json};
+use axum::Router;
+use opendal::Operator;
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tracing::Level;
+
+pub mod accounting;
+pub mod acl;
+pub mod acme;
+pub mod admin;
+mod api;
+pub mod audit;
+pub mod auth;
+mod axum_ext;
+pub mod bucket_policy;
+pub mod buffer_pool;
+pub mod builder;
+pub mod chaos;
+pub mod checksums;
+pub mod circuit_breaker;
+pub mod client;
+pub mod coalescing;
+pub mod compat;
+pub mod compression;
+mod context;
+pub mod cors;
+pub mod credentials;
+#[cfg(feature = "dashboard")]
+mod dashboard;
+pub mod default_buckets;
+pub mod domains;
+pub mod error;
+pub mod error_reporting;
+pub mod etag_cache;
+pub mod etags;
+pub mod events;
+pub mod expiration;
+pub mod exposure;
+pub mod hashing;
+pub mod http3;
+pub mod integrity;
+pub mod inventory;
+pub mod kafka;
+pub mod lease;
+pub mod lifecycle;
+pub mod listing_cache;
+pub mod load_shedding;
+pub mod logging;
+pub mod metadata;
+pub mod metrics;
+pub mod migrations;
+pub mod multipart;
+pub mod namespaces;
+pub mod nats;
+pub mod notifications;
+pub mod object_lock;
+pub mod operation;
+pub mod payload;
+pub mod plugins;
+pub mod post_policy;
+pub mod public;
+pub mod quota;
+pub mod range;
+pub mod rate_limit;
+pub mod read_cache;
+pub mod replication;
+pub mod response_overrides;
+pub mod sampling;
+pub mod scan;
+pub mod select;
+pub mod server;
+pub mod signature;
+mod slow_requests;
+pub mod sni;
+pub mod sqs;
+pub mod storage_class;
+pub mod strict;
+pub mod tagging;
+mod templates;
+#[cfg(feature = "test-util")]
+pub mod test_util;
+pub mod transfer;
+pub mod transforms;
+pub mod trash;
+pub mod versioning;
+
+pub static REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-amz-request-id");
+
+#[derive(Debug, serde::Deserialize)]
+pub struct Config {
+    #[serde(default = "default_host")]
+    pub server_host: String,
+    #[serde(default = "default_external_host")]
+    pub external_server_host: String,
+    pub redis: Option<deadpool_redis::Config>,
+    #[serde(default)]
+    pub redis_pool: RedisPoolConfig,
+    #[serde(deserialize_with = "scheme_opendal")]
+    pub opendal_provider: opendal::Scheme,
+    pub opendal: HashMap<String, String>,
+    /// comma separated buckets that are created in every namespace the first time it is used
+    #[serde(default, deserialize_with = "comma_separated")]
+    pub default_buckets: Vec<String>,
+    /// reject unsupported `x-amz-*` headers and query parameters instead of ignoring them
+    #[serde(default)]
+    pub strict: bool,
+    /// hash objects while they are sent and abort responses that do not match their md5 etag
+    #[serde(default)]
+    pub verify_integrity: bool,
+    /// amount of concurrent stat calls while listing objects
+    #[serde(default = "default_list_stat_concurrency")]
+    pub list_stat_concurrency: usize,
+    #[serde(default = "default_log_level", deserialize_with = "log_level")]
+    pub log_level: Level,
+    #[serde(default = "default_true")]
+    pub log_stdout: bool,
+    pub log_file: Option<logging::LogFileConfig>,
+    /// requests taking longer than this are logged at WARN level
+    pub slow_request_threshold_ms: Option<u64>,
+    #[serde(default)]
+    pub trace_sampling: sampling::TraceSamplingConfig,
+    /// report unexpected server errors and panics to sentry, needs the `sentry` feature
+    pub sentry_dsn: Option<String>,
+    pub sentry_environment: Option<String>,
+    /// the admin api is only served when this is set
+    pub admin_server_host: Option<String>,
+    pub admin_token: Option<String>,
+    pub audit: Option<audit::AuditConfig>,
+    pub kafka: Option<kafka::KafkaConfig>,
+    pub nats: Option<nats::NatsConfig>,
+    pub sqs: Option<sqs::SqsConfig>,
+    pub replication: Option<replication::ReplicationConfig>,
+    #[serde(default)]
+    pub metrics: metrics::MetricsConfig,
+    #[serde(default)]
+    pub etag_cache: etag_cache::EtagCacheConfig,
+    #[serde(default)]
+    pub read_cache: read_cache::ReadCacheConfig,
+    #[serde(default)]
+    pub listing_cache: listing_cache::ListingCacheConfig,
+    #[serde(default)]
+    pub load_shedding: load_shedding::LoadSheddingConfig,
+    #[serde(default)]
+    pub rate_limit: rate_limit::RateLimitConfig,
+    #[serde(default)]
+    pub coalescing: coalescing::CoalescingConfig,
+    #[serde(default)]
+    pub circuit_breaker: circuit_breaker::CircuitBreakerConfig,
+    #[serde(default)]
+    pub http: server::HttpConfig,
+    pub http3: Option<http3::Http3Config>,
+    #[serde(default)]
+    pub compression: compression::CompressionConfig,
+    #[serde(default)]
+    pub credentials: credentials::CredentialsConfig,
+    #[serde(default)]
+    pub buffer_pool: buffer_pool::BufferPoolConfig,
+    #[serde(default)]
+    pub hashing: hashing::HashingConfig,
+    #[serde(default)]
+    pub accounting: accounting::AccountingConfig,
+    #[serde(default)]
+    pub trash: trash::TrashConfig,
+    #[serde(default)]
+    pub expiration: expiration::ExpirationConfig,
+    #[serde(default)]
+    pub inventory: inventory::InventoryConfig,
+    #[serde(default)]
+    pub lifecycle: lifecycle::LifecycleConfig,
+    #[serde(default)]
+    pub leases: lease::LeaseConfig,
+    #[serde(default)]
+    pub public: public::PublicConfig,
+    #[serde(default)]
+    pub domains: domains::DomainsConfig,
+    /// CORS rule for buckets without their own CORS configuration
+    pub cors: Option<cors::CorsConfig>,
+    pub acme: Option<acme::AcmeConfig>,
+    /// injects faults into responses, never enable it for production clients
+    pub chaos: Option<chaos::ChaosConfig>,
+}
+
+/// Overrides for the redis pool, the timeouts in the `deadpool_redis` config are hard to set
+/// from environment variables.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RedisPoolConfig {
+    pub max_size: Option<usize>,
+    /// how long a request waits for a free connection
+    pub wait_timeout_ms: Option<u64>,
+    pub create_timeout_ms: Option<u64>,
+    pub recycle_timeout_ms: Option<u64>,
+}
+
+impl RedisPoolConfig {
+    fn apply(&self, redis_config: &mut deadpool_redis::Config) {
+        let pool = redis_config.pool.get_or_insert_with(Default::default);
+
+        if let Some(max_size) = self.max_size {
+            pool.max_size = max_size;
+        }
+        if let Some(wait) = self.wait_timeout_ms {
+            pool.timeouts.wait = Some(Duration::from_millis(wait));
+        }
+        if let Some(create) = self.create_timeout_ms {
+            pool.timeouts.create = Some(Duration::from_millis(create));
+        }
+        if let Some(recycle) = self.recycle_timeout_ms {
+            pool.timeouts.recycle = Some(Duration::from_millis(recycle));
+        }
+    }
+}
+
+fn scheme_opendal<'de, D>(deserializer: D) -> Result<opendal::Scheme, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    use serde::de::Error;
+
+    String::deserialize(deserializer).and_then(|string| {
+        let scheme =
+            opendal::Scheme::from_str(&string).map_err(|err| Error::custom(err.to_string()))?;
+
+        if !opendal::Scheme::enabled().contains(&scheme) {
+            return Err(Error::custom(format!("{} support is not enabled", scheme)));
+        }
+
+        Ok(scheme)
+    })
+}
+
+fn log_level<'de, D>(deserializer: D) -> Result<Level, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    use serde::de::Error;
+
+    String::deserialize(deserializer)
+        .and_then(|string| Level::from_str(&string).map_err(|err| Error::custom(err.to_string())))
+}
+
+pub(crate) fn comma_separated<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(String::deserialize(deserializer)?
+        .split(',')
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .map(String::from)
+        .collect())
+}
+
+fn default_log_level() -> Level {
+    Level::ERROR
+}
+
+fn default_list_stat_concurrency() -> usize {
+    16
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_host() -> String {
+    String::from("0.0.0.0:3000")
+}
+
+fn default_external_host() -> String {
+    String::from("http://0.0.0.0:3000")
+}
+
+impl Config {
+    pub fn builder() -> builder::ConfigBuilder {
+        builder::ConfigBuilder::default()
+    }
+
+    pub fn from_env() -> Result<Self, config::ConfigError> {
+        let cfg = config::Config::builder()
+            .add_source(config::Environment::with_prefix("S3_PROXY").separator("__"))
+            .build()?;
+
+        cfg.try_deserialize()
+    }
+}
+
+#[derive(Clone)]
+pub struct AppState {
+    pub metadata: metadata::MetadataStore,
+    pub config: Arc<Config>,
+    /// opendal_operator is already an Arc
+    pub opendal_operator: Operator,
+    pub audit: Option<Arc<audit::AuditLog>>,
+    pub metrics: Arc<metrics::Metrics>,
+    pub etag_cache: etag_cache::EtagCache,
+    pub read_cache: read_cache::ReadCache,
+    pub listing_cache: listing_cache::ListingCache,
+    pub load_shedder: Arc<load_shedding::LoadShedder>,
+    pub rate_limiter: Arc<rate_limit::RateLimiter>,
+    /// keeps the background jobs from running on two instances at once
+    pub leases: Arc<lease::Leases>,
+    pub chaos: Option<Arc<chaos::Chaos>>,
+    pub circuit_breaker: Arc<circuit_breaker::CircuitBreaker>,
+    pub coalescer: coalescing::Coalescer,
+    pub credentials: credentials::CredentialsCache,
+    pub auth: Arc<dyn auth::AuthProvider>,
+    pub buffer_pool: Arc<buffer_pool::BufferPool>,
+    /// hashes the signed payloads, resolved from `config.hashing`
+    pub sha256: hashing::Sha256Implementation,
+    pub accounting: Arc<accounting::Accounting>,
+    pub event_hooks: events::EventHooks,
+    pub interceptors: plugins::Interceptors,
+    pub transforms: transforms::Transforms,
+    pub domains: domains::Domains,
+    pub default_buckets: default_buckets::DefaultBuckets,
+}
+
+impl AppState {
+    pub fn from_config(config: Config) -> anyhow::Result<AppState> {
+        AppState::builder(config).build()
+    }
+
+    pub fn builder(config: Config) -> builder::AppStateBuilder {
+        builder::AppStateBuilder::new(config)
+    }
+}
+
+/// Builds the S3 api, so it can be served on its own or nested in another axum app.
+///
+/// Use `RouterBuilder` to add layers around the handlers.
+pub fn router(app_state: AppState) -> Router {
+    builder::RouterBuilder::new(app_state).build()
+}
+
+/// Serves the S3 api and, when configured, the admin api until one of them fails.
+pub async fn run(app_state: AppState) -> anyhow::Result<()> {
+    tracing::info!("payloads are hashed with {}", app_state.sha256);
+    migrations::migrate(&app_state.metadata).await?;
+
+    if app_state.config.credentials.warm_on_startup {
+        match app_state.credentials.warm(&app_state.metadata).await {
+            Ok(loaded) => tracing::info!("loaded {} secret keys into the cache", loaded),
+            Err(error) => tracing::warn!("unable to warm the credentials cache: {}", error),
+        }
+    }
+
+    let flush_secs = app_state.config.accounting.flush_interval_secs;
+    if flush_secs > 0 {
+        tokio::spawn(accounting::flush_periodically(
+            app_state.accounting.clone(),
+            app_state.metadata.clone(),
+            Duration::from_secs(flush_secs),
+        ));
+    }
+
+    let trash = &app_state.config.trash;
+    if trash.enabled && trash.purge_interval_secs > 0 {
+        tokio::spawn(trash::purge_periodically(
+            app_state.opendal_operator.clone(),
+            app_state.leases.clone(),
+            Duration::from_secs(trash.retention_days * 86400),
+            Duration::from_secs(trash.purge_interval_secs),
+        ));
+    }
+
+    let expiration_secs = app_state.config.expiration.interval_secs;
+    if expiration_secs > 0 {
+        tokio::spawn(expiration::expire_periodically(
+            app_state.clone(),
+            Duration::from_secs(expiration_secs),
+        ));
+    }
+
+    let inventory_secs = app_state.config.inventory.interval_secs;
+    if inventory_secs > 0 {
+        tokio::spawn(inventory::export_periodically(
+            app_state.clone(),
+            Duration::from_secs(inventory_secs),
+        ));
+    }
+
+    let lifecycle_secs = app_state.config.lifecycle.interval_secs;
+    if lifecycle_secs > 0 {
+        tokio::spawn(lifecycle::apply_periodically(
+            app_state.clone(),
+            Duration::from_secs(lifecycle_secs),
+        ));
+    }
+
+    if let Some(acme_config) = &app_state.config.acme {
+        acme::start(&app_state, acme_config)?;
+    }
+
+    let mut app = router(app_state.clone());
+
+    let http_config = app_state.config.http.clone();
+    let listener = server::bind(&app_state.config.server_host, &http_config).await?;
+
+    if let Some(http3_config) = &app_state.config.http3 {
+        let http3_listener = http3::Http3Listener::bind(http3_config)?;
+        // browsers and some clients switch to http/3 after seeing this on a TCP response
+        let alt_svc =
+            HeaderValue::from_str(&format!("h3=\":{}\"; ma=86400", http3_listener.port()))?;
+        tokio::spawn(http3_listener.serve(app.clone()));
+        app = app.layer(axum::middleware::map_response(
+            move |mut response: axum::response::Response| {
+                let alt_svc = alt_svc.clone();
+                async move {
+                    response.headers_mut().insert(ALT_SVC, alt_svc);
+                    response
+                }
+            },
+        ));
+    }
+
+    if let Some(admin_host) = &app_state.config.admin_server_host {
+        let admin_listener = server::bind(admin_host, &http_config).await?;
+        let admin_app = admin::router(app_state.clone());
+
+        let refresh_secs = app_state.config.metrics.storage_refresh_secs;
+        if refresh_secs > 0 {
+            tokio::spawn(metrics::refresh_storage(
+                app_state.metrics.clone(),
+                app_state.opendal_operator.clone(),
+                Duration::from_secs(refresh_secs),
+            ));
+        }
+
+        tokio::try_join!(
+            server::serve(listener, app, http_config.clone()),
+            server::serve(admin_listener, admin_app, http_config)
+        )?;
+    } else {
+        server::serve(listener, app, http_config).await?;
+    }
+
+    Ok(())
+}
+
+pub(crate) async fn asdfg(
+    State(AppState { metadata, .. }): State<AppState>,
+) -> Result<impl IntoResponse, error::S3Error> {
+    let key = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_secs()
+        .to_string();
+    metadata.set(&key, "1").await?;
+
+    let res = metadata.keys("17068").await?;
+
+    Ok(Json(res))
+}
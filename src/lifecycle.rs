@@ -0,0 +1,605 @@
+use crate::error::S3Error;
+use crate::multipart;
+use crate::namespaces::{Namespaces, LIFECYCLE_PREFIX};
+use crate::tagging::{self, Tag};
+use crate::AppState;
+use futures::TryStreamExt;
+use opendal::Metakey;
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime};
+
+/// A bucket has at most this many rules, like S3.
+const MAX_RULES: usize = 1000;
+const MAX_ID_LENGTH: usize = 255;
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LifecycleConfig {
+    /// how often the rules of the buckets are applied, 0 disables the worker
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+}
+
+impl Default for LifecycleConfig {
+    fn default() -> Self {
+        LifecycleConfig {
+            interval_secs: default_interval_secs(),
+        }
+    }
+}
+
+fn default_interval_secs() -> u64 {
+    3600
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// The `<LifecycleConfiguration>` of a bucket, set with `PutBucketLifecycleConfiguration`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LifecycleConfiguration {
+    #[serde(rename = "Rule", default)]
+    pub rules: Vec<LifecycleRule>,
+}
+
+/// Rules expire current objects and abort incomplete uploads. Transitions and noncurrent
+/// versions are refused, every object is stored alike and replaced objects are kept by the
+/// trash.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct LifecycleRule {
+    #[serde(rename = "ID", default)]
+    pub id: Option<String>,
+    /// `Enabled` or `Disabled`
+    pub status: String,
+    #[serde(default)]
+    pub filter: Option<LifecycleFilter>,
+    /// the prefix of rules from before `Filter` existed
+    #[serde(default)]
+    pub prefix: Option<String>,
+    #[serde(default)]
+    pub expiration: Option<LifecycleExpiration>,
+    #[serde(default)]
+    pub abort_incomplete_multipart_upload: Option<AbortIncompleteMultipartUpload>,
+    #[serde(default, skip_serializing)]
+    pub transition: Vec<IgnoredAny>,
+    #[serde(default, skip_serializing)]
+    pub noncurrent_version_transition: Vec<IgnoredAny>,
+    #[serde(default, skip_serializing)]
+    pub noncurrent_version_expiration: Option<IgnoredAny>,
+}
+
+/// One of the members is set, or none to match every object.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct LifecycleFilter {
+    #[serde(default)]
+    pub prefix: Option<String>,
+    #[serde(default)]
+    pub tag: Option<Tag>,
+    #[serde(default)]
+    pub object_size_greater_than: Option<u64>,
+    #[serde(default)]
+    pub object_size_less_than: Option<u64>,
+    #[serde(default)]
+    pub and: Option<LifecycleAnd>,
+}
+
+/// The conditions of a filter that all have to hold.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct LifecycleAnd {
+    #[serde(default)]
+    pub prefix: Option<String>,
+    #[serde(rename = "Tag", default)]
+    pub tags: Vec<Tag>,
+    #[serde(default)]
+    pub object_size_greater_than: Option<u64>,
+    #[serde(default)]
+    pub object_size_less_than: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct LifecycleExpiration {
+    #[serde(default)]
+    pub days: Option<u64>,
+    /// midnight UTC in ISO 8601
+    #[serde(default)]
+    pub date: Option<String>,
+    /// there are no delete markers, it is accepted and ignored
+    #[serde(default)]
+    pub expired_object_delete_marker: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct AbortIncompleteMultipartUpload {
+    pub days_after_initiation: u64,
+}
+
+/// What a rule asks of an object, the flattened filter.
+struct Conditions<'a> {
+    prefix: &'a str,
+    tags: Vec<&'a Tag>,
+    greater_than: Option<u64>,
+    less_than: Option<u64>,
+}
+
+impl LifecycleConfiguration {
+    /// Checks the configuration like S3, the error is sent back to the client.
+    pub fn validate(&self) -> Result<(), S3Error> {
+        if self.rules.is_empty() || self.rules.len() > MAX_RULES {
+            return Err(S3Error::MalformedXML);
+        }
+
+        let mut ids = HashSet::new();
+        for rule in &self.rules {
+            rule.validate()?;
+            if let Some(id) = &rule.id {
+                if !ids.insert(id) {
+                    return Err(S3Error::InvalidArgument(String::from(
+                        "Rule ID must be unique. Found same ID for more than one rule",
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl LifecycleRule {
+    fn validate(&self) -> Result<(), S3Error> {
+        if self.id.as_ref().is_some_and(|x| x.len() > MAX_ID_LENGTH) {
+            return Err(S3Error::InvalidArgument(String::from(
+                "ID length should not exceed allowed limit of 255",
+            )));
+        }
+        if self.status != "Enabled" && self.status != "Disabled" {
+            return Err(S3Error::MalformedXML);
+        }
+        if !self.transition.is_empty()
+            || !self.noncurrent_version_transition.is_empty()
+            || self.noncurrent_version_expiration.is_some()
+        {
+            return Err(S3Error::NotImplemented(String::from(
+                "only Expiration and AbortIncompleteMultipartUpload lifecycle actions are \
+                 supported",
+            )));
+        }
+        if self.filter.is_some() && self.prefix.is_some() {
+            return Err(S3Error::MalformedXML);
+        }
+        if let Some(filter) = &self.filter {
+            let members = [
+                filter.prefix.is_some(),
+                filter.tag.is_some(),
+                filter.object_size_greater_than.is_some(),
+                filter.object_size_less_than.is_some(),
+                filter.and.is_some(),
+            ];
+            if members.into_iter().filter(|x| *x).count() > 1 {
+                return Err(S3Error::MalformedXML);
+            }
+        }
+
+        match (&self.expiration, &self.abort_incomplete_multipart_upload) {
+            (None, None) => {
+                return Err(S3Error::InvalidRequest(String::from(
+                    "At least one action needs to be specified in a rule",
+                )))
+            }
+            (_, Some(abort)) if abort.days_after_initiation == 0 => {
+                return Err(S3Error::InvalidArgument(String::from(
+                    "'DaysAfterInitiation' for AbortIncompleteMultipartUpload action must be a \
+                     positive integer",
+                )))
+            }
+            (_, Some(_)) if !self.conditions().tags.is_empty() => {
+                return Err(S3Error::InvalidRequest(String::from(
+                    "Tag based filter cannot be used with AbortIncompleteMultipartUpload action",
+                )))
+            }
+            _ => (),
+        }
+
+        if let Some(expiration) = &self.expiration {
+            match (expiration.days, &expiration.date) {
+                (Some(0), None) => {
+                    return Err(S3Error::InvalidArgument(String::from(
+                        "'Days' for Expiration action must be a positive integer",
+                    )))
+                }
+                (Some(_), None) => (),
+                (None, Some(date)) => {
+                    if expiration_date(date).is_none() {
+                        return Err(S3Error::InvalidArgument(String::from(
+                            "'Date' must be at midnight GMT",
+                        )));
+                    }
+                }
+                (None, None) if expiration.expired_object_delete_marker.is_some() => (),
+                _ => return Err(S3Error::MalformedXML),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.status == "Enabled"
+    }
+
+    fn conditions(&self) -> Conditions<'_> {
+        let filter = self.filter.as_ref();
+        let and = filter.and_then(|x| x.and.as_ref());
+        let prefix = self
+            .prefix
+            .as_deref()
+            .or(filter.and_then(|x| x.prefix.as_deref()))
+            .or(and.and_then(|x| x.prefix.as_deref()))
+            .unwrap_or_default();
+        let tags = filter
+            .and_then(|x| x.tag.as_ref())
+            .into_iter()
+            .chain(and.into_iter().flat_map(|x| &x.tags))
+            .collect();
+
+        Conditions {
+            prefix,
+            tags,
+            greater_than: filter
+                .and_then(|x| x.object_size_greater_than)
+                .or(and.and_then(|x| x.object_size_greater_than)),
+            less_than: filter
+                .and_then(|x| x.object_size_less_than)
+                .or(and.and_then(|x| x.object_size_less_than)),
+        }
+    }
+
+    /// Whether the rule expires the object at `now`, the tags are only looked at by rules with a
+    /// tag filter.
+    fn expires(
+        &self,
+        key: &str,
+        size: u64,
+        last_modified: Option<u64>,
+        tags: &[Tag],
+        now: u64,
+    ) -> bool {
+        let Some(expires_at) = self
+            .expiration
+            .as_ref()
+            .and_then(|x| x.expires_at(last_modified))
+        else {
+            return false;
+        };
+        let conditions = self.conditions();
+
+        expires_at <= now
+            && key.starts_with(conditions.prefix)
+            && conditions.greater_than.is_none_or(|x| size > x)
+            && conditions.less_than.is_none_or(|x| size < x)
+            && conditions.tags.iter().all(|x| tags.contains(x))
+    }
+}
+
+impl LifecycleExpiration {
+    /// The unix timestamp an object last modified at `last_modified` expires at. Like S3 the
+    /// days are rounded up to the next midnight UTC, backends without modification times only
+    /// expire objects by date.
+    fn expires_at(&self, last_modified: Option<u64>) -> Option<u64> {
+        match (self.days, &self.date) {
+            (Some(days), _) => last_modified
+                .map(|x| (x + days * SECONDS_PER_DAY).div_ceil(SECONDS_PER_DAY) * SECONDS_PER_DAY),
+            (None, Some(date)) => expiration_date(date),
+            (None, None) => None,
+        }
+    }
+}
+
+/// The unix timestamp of an expiration `Date`, `None` when it is not midnight UTC.
+fn expiration_date(date: &str) -> Option<u64> {
+    let date = chrono::DateTime::parse_from_rfc3339(date).ok()?.timestamp();
+    u64::try_from(date)
+        .ok()
+        .filter(|x| x % SECONDS_PER_DAY == 0)
+}
+
+/// What one pass over the buckets did.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LifecycleRun {
+    pub expired: u64,
+    pub aborted: u64,
+}
+
+/// Applies the enabled rules of every bucket with a lifecycle configuration at `now`.
+pub async fn apply(state: &AppState, now: u64) -> Result<LifecycleRun, S3Error> {
+    let mut run = LifecycleRun::default();
+
+    for key in state.metadata.keys(LIFECYCLE_PREFIX).await? {
+        let Some((namespace, bucket)) = key[LIFECYCLE_PREFIX.len()..].rsplit_once("::") else {
+            continue;
+        };
+        let Some(configuration) = Namespaces::new(&state.metadata)
+            .lifecycle_configuration(namespace, bucket)
+            .await?
+        else {
+            continue;
+        };
+        let rules: Vec<_> = configuration
+            .rules
+            .iter()
+            .filter(|x| x.is_enabled())
+            .collect();
+
+        if rules.iter().any(|x| x.expiration.is_some()) {
+            run.expired += expire_objects(state, namespace, bucket, &rules, now).await?;
+        }
+        if rules
+            .iter()
+            .any(|x| x.abort_incomplete_multipart_upload.is_some())
+        {
+            run.aborted += abort_uploads(state, namespace, bucket, &rules, now).await?;
+        }
+    }
+
+    Ok(run)
+}
+
+async fn expire_objects(
+    state: &AppState,
+    namespace: &str,
+    bucket: &str,
+    rules: &[&LifecycleRule],
+    now: u64,
+) -> Result<u64, S3Error> {
+    let bucket_path = format!("{}/{}/", namespace, bucket);
+    let mut lister = match state
+        .opendal_operator
+        .lister_with(&bucket_path)
+        .recursive(true)
+        .metakey(Metakey::ContentLength | Metakey::LastModified)
+        .await
+    {
+        Ok(lister) => lister,
+        Err(error) if error.kind() == opendal::ErrorKind::NotFound => return Ok(0),
+        Err(error) => return Err(error.into()),
+    };
+    let needs_tags = rules.iter().any(|x| !x.conditions().tags.is_empty());
+
+    // the keys are gathered first, the lister is not kept open while deleting
+    let mut expired_keys = Vec::new();
+    while let Some(entry) = lister.try_next().await? {
+        let metadata = entry.metadata();
+        let Some(key) = entry.path().strip_prefix(&bucket_path) else {
+            continue;
+        };
+        let last_modified = metadata
+            .last_modified()
+            .and_then(|x| u64::try_from(x.timestamp()).ok());
+        if !metadata.is_file() {
+            continue;
+        }
+
+        let tags = match needs_tags {
+            true => tagging::object_tags(&state.metadata, entry.path()).await?,
+            false => Default::default(),
+        };
+        if rules.iter().any(|rule| {
+            rule.expires(
+                key,
+                metadata.content_length(),
+                last_modified,
+                tags.tags(),
+                now,
+            )
+        }) {
+            expired_keys.push(key.to_string());
+        }
+    }
+
+    let mut expired = 0;
+    for key in expired_keys {
+        match crate::api::remove_object(state, namespace, bucket, &key, false).await {
+            Ok(true) => expired += 1,
+            Ok(false) => (),
+            // the rule applies again once the lock is lifted
+            Err(S3Error::ObjectLocked) => (),
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(expired)
+}
+
+async fn abort_uploads(
+    state: &AppState,
+    namespace: &str,
+    bucket: &str,
+    rules: &[&LifecycleRule],
+    now: u64,
+) -> Result<u64, S3Error> {
+    let mut aborted = 0;
+    for (upload_id, upload) in multipart::uploads(&state.metadata, namespace).await? {
+        if upload.bucket != bucket {
+            continue;
+        }
+        let stale = rules.iter().any(|rule| {
+            rule.abort_incomplete_multipart_upload
+                .as_ref()
+                .is_some_and(|x| {
+                    upload.initiated + x.days_after_initiation * SECONDS_PER_DAY <= now
+                        && upload.key.starts_with(rule.conditions().prefix)
+                })
+        });
+        if stale {
+            multipart::remove(
+                &state.opendal_operator,
+                &state.metadata,
+                namespace,
+                &upload_id,
+            )
+            .await?;
+            aborted += 1;
+        }
+    }
+    Ok(aborted)
+}
+
+pub async fn apply_periodically(state: AppState, every: Duration) {
+    let mut interval = tokio::time::interval(every);
+
+    loop {
+        interval.tick().await;
+        let Some(_lease) = state.leases.for_job("lifecycle").await else {
+            continue;
+        };
+
+        match apply(&state, unix_now()).await {
+            Ok(run) if run == LifecycleRun::default() => (),
+            Ok(run) => tracing::info!(
+                "lifecycle rules expired {} objects and aborted {} uploads",
+                run.expired,
+                run.aborted
+            ),
+            Err(error) => tracing::error!("unable to apply the lifecycle rules: {}", error),
+        }
+    }
+}
+
+#[test]
+fn lifecycle_configurations_are_parsed_and_checked() {
+    let xml = r#"<LifecycleConfiguration xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+       <Rule>
+          <ID>logs</ID>
+          <Filter><Prefix>logs/</Prefix></Filter>
+          <Status>Enabled</Status>
+          <Expiration><Days>30</Days></Expiration>
+          <AbortIncompleteMultipartUpload>
+             <DaysAfterInitiation>7</DaysAfterInitiation>
+          </AbortIncompleteMultipartUpload>
+       </Rule>
+       <Rule>
+          <ID>tmp</ID>
+          <Filter>
+             <And>
+                <Prefix>tmp/</Prefix>
+                <Tag><Key>temporary</Key><Value>true</Value></Tag>
+                <ObjectSizeGreaterThan>10</ObjectSizeGreaterThan>
+             </And>
+          </Filter>
+          <Status>Enabled</Status>
+          <Expiration><Date>2023-01-01T00:00:00.000Z</Date></Expiration>
+       </Rule>
+    </LifecycleConfiguration>"#;
+    let configuration: LifecycleConfiguration = quick_xml::de::from_str(xml).unwrap();
+    assert!(configuration.validate().is_ok());
+
+    let logs = &configuration.rules[0];
+    let day = SECONDS_PER_DAY;
+    // written at noon of day 1, it expires at midnight after 30 days
+    assert!(!logs.expires("logs/a.log", 1, Some(day + day / 2), &[], 31 * day));
+    assert!(logs.expires("logs/a.log", 1, Some(day + day / 2), &[], 32 * day));
+    assert!(!logs.expires("data/a.log", 1, Some(day + day / 2), &[], 32 * day));
+
+    let tmp = &configuration.rules[1];
+    let tags = [Tag {
+        key: String::from("temporary"),
+        value: String::from("true"),
+    }];
+    let now = 1_700_000_000;
+    assert!(tmp.expires("tmp/a", 11, None, &tags, now));
+    assert!(!tmp.expires("tmp/a", 11, None, &[], now));
+    assert!(!tmp.expires("tmp/a", 10, None, &tags, now));
+    assert!(!tmp.expires("tmp/a", 11, None, &tags, 1_600_000_000));
+
+    let invalid = [
+        // not midnight
+        r#"<Rule><Status>Enabled</Status><Expiration><Date>2023-01-01T10:00:00Z</Date></Expiration></Rule>"#,
+        r#"<Rule><Status>Enabled</Status><Expiration><Days>0</Days></Expiration></Rule>"#,
+        r#"<Rule><Status>Enabled</Status></Rule>"#,
+        r#"<Rule><Status>On</Status><Expiration><Days>1</Days></Expiration></Rule>"#,
+        r#"<Rule><Status>Enabled</Status><Transition><Days>1</Days><StorageClass>GLACIER</StorageClass></Transition></Rule>"#,
+        r#"<Rule><Status>Enabled</Status><Filter><Prefix>a</Prefix><Tag><Key>a</Key><Value>b</Value></Tag></Filter><Expiration><Days>1</Days></Expiration></Rule>"#,
+        r#"<Rule><ID>a</ID><Status>Enabled</Status><Expiration><Days>1</Days></Expiration></Rule><Rule><ID>a</ID><Status>Enabled</Status><Expiration><Days>2</Days></Expiration></Rule>"#,
+    ];
+    for rules in invalid {
+        let xml = format!("<LifecycleConfiguration>{}</LifecycleConfiguration>", rules);
+        let configuration: LifecycleConfiguration = quick_xml::de::from_str(&xml).unwrap();
+        assert!(configuration.validate().is_err(), "{}", rules);
+    }
+}
+
+#[tokio::test]
+async fn lifecycle_rules_expire_objects_and_abort_uploads() {
+    let state = AppState::builder(crate::Config::builder().build())
+        .metadata(crate::metadata::MetadataStore::memory())
+        .build()
+        .unwrap();
+    for path in ["tenant/logs/2024/a.log", "tenant/logs/keep.txt"] {
+        state.opendal_operator.write(path, "hello").await.unwrap();
+    }
+    let upload_id = multipart::initiate(
+        &state.metadata,
+        "tenant",
+        &multipart::PendingUpload::new("logs", "2024/b.log"),
+    )
+    .await
+    .unwrap();
+    state
+        .opendal_operator
+        .write(&multipart::part_path("tenant", &upload_id, 1), "part")
+        .await
+        .unwrap();
+
+    let configuration: LifecycleConfiguration = quick_xml::de::from_str(
+        r#"<LifecycleConfiguration><Rule>
+            <Filter><Prefix>2024/</Prefix></Filter>
+            <Status>Enabled</Status>
+            <Expiration><Date>2001-01-01T00:00:00Z</Date></Expiration>
+            <AbortIncompleteMultipartUpload><DaysAfterInitiation>1</DaysAfterInitiation></AbortIncompleteMultipartUpload>
+        </Rule></LifecycleConfiguration>"#,
+    )
+    .unwrap();
+    Namespaces::new(&state.metadata)
+        .set_lifecycle_configuration("tenant", "logs", &configuration)
+        .await
+        .unwrap();
+
+    // before the date, and the upload is too recent either way
+    assert_eq!(
+        apply(&state, 946_684_800).await.unwrap(),
+        LifecycleRun::default()
+    );
+    let later = unix_now() + 3 * SECONDS_PER_DAY;
+    assert_eq!(
+        apply(&state, later).await.unwrap(),
+        LifecycleRun {
+            expired: 1,
+            aborted: 1
+        }
+    );
+    assert!(!state
+        .opendal_operator
+        .is_exist("tenant/logs/2024/a.log")
+        .await
+        .unwrap());
+    assert!(state
+        .opendal_operator
+        .is_exist("tenant/logs/keep.txt")
+        .await
+        .unwrap());
+    assert_eq!(
+        multipart::pending(&state.metadata, "tenant", &upload_id)
+            .await
+            .unwrap(),
+        None
+    );
+}
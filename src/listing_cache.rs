@@ -0,0 +1,209 @@
+use axum::body::Bytes;
+use axum::BoxError;
+use futures::{Stream, StreamExt};
+use moka::sync::Cache;
+use serde::Deserialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ListingCacheConfig {
+    /// how long a rendered listing is served from memory, 0 disables the cache
+    #[serde(default)]
+    pub ttl_secs: u64,
+    /// total size of the cached listings
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: u64,
+    /// larger listings are not cached
+    #[serde(default = "default_max_listing_bytes")]
+    pub max_listing_bytes: usize,
+}
+
+impl Default for ListingCacheConfig {
+    fn default() -> Self {
+        ListingCacheConfig {
+            ttl_secs: 0,
+            max_bytes: default_max_bytes(),
+            max_listing_bytes: default_max_listing_bytes(),
+        }
+    }
+}
+
+fn default_max_bytes() -> u64 {
+    64 * 1024 * 1024
+}
+
+fn default_max_listing_bytes() -> usize {
+    1024 * 1024
+}
+
+#[derive(Debug, Clone)]
+pub struct CachedListing {
+    pub content_type: &'static str,
+    pub body: Bytes,
+}
+
+/// Rendered `ListObjects` responses per bucket and query string, for clients that list the
+/// same prefix many times per second.
+///
+/// Writes through the proxy drop the listings of their bucket, changes made behind the proxy
+/// show up after the ttl.
+#[derive(Clone)]
+pub struct ListingCache {
+    /// cache is already an Arc
+    cache: Option<Cache<String, CachedListing>>,
+    max_listing_bytes: usize,
+    /// bumped by every invalidation, listings rendered across one are not stored
+    generation: Arc<AtomicU64>,
+}
+
+impl ListingCache {
+    pub fn new(config: &ListingCacheConfig) -> ListingCache {
+        let cache = (config.ttl_secs > 0).then(|| {
+            Cache::builder()
+                .max_capacity(config.max_bytes)
+                .weigher(|key: &String, value: &CachedListing| {
+                    (key.len() + value.body.len())
+                        .try_into()
+                        .unwrap_or(u32::MAX)
+                })
+                .time_to_live(Duration::from_secs(config.ttl_secs))
+                .support_invalidation_closures()
+                .build()
+        });
+
+        ListingCache {
+            cache,
+            max_listing_bytes: config.max_listing_bytes,
+            generation: Arc::default(),
+        }
+    }
+
+    pub fn get(&self, namespace: &str, bucket: &str, query: Option<&str>) -> Option<CachedListing> {
+        self.cache.as_ref()?.get(&key(namespace, bucket, query))
+    }
+
+    /// Passes the rendered listing through and stores it once it is complete.
+    pub fn fill<S>(
+        &self,
+        namespace: &str,
+        bucket: &str,
+        query: Option<&str>,
+        content_type: &'static str,
+        body: S,
+    ) -> impl Stream<Item = Result<String, BoxError>> + Send + 'static
+    where
+        S: Stream<Item = Result<String, BoxError>> + Send + 'static,
+    {
+        let fill = self.cache.clone().map(|cache| Fill {
+            cache,
+            key: key(namespace, bucket, query),
+            content_type,
+            max_listing_bytes: self.max_listing_bytes,
+            generation: self.generation.clone(),
+            started_at: self.generation.load(Ordering::Acquire),
+            buffer: Some(Vec::new()),
+        });
+
+        futures::stream::unfold((Box::pin(body), fill), |(mut body, mut fill)| async move {
+            let chunk = body.next().await;
+            if let Some(fill) = &mut fill {
+                fill.push(chunk.as_ref());
+            }
+            chunk.map(|chunk| (chunk, (body, fill)))
+        })
+    }
+
+    /// Drops the listings of a bucket, for writes and deletes through the proxy.
+    pub fn invalidate(&self, namespace: &str, bucket: &str) {
+        let Some(cache) = &self.cache else {
+            return;
+        };
+        self.generation.fetch_add(1, Ordering::AcqRel);
+
+        let prefix = key(namespace, bucket, None);
+        if let Err(error) = cache.invalidate_entries_if(move |key, _| key.starts_with(&prefix)) {
+            tracing::error!("unable to invalidate the listing cache: {}", error);
+            cache.invalidate_all();
+        }
+    }
+}
+
+fn key(namespace: &str, bucket: &str, query: Option<&str>) -> String {
+    format!("{}/{}?{}", namespace, bucket, query.unwrap_or_default())
+}
+
+struct Fill {
+    cache: Cache<String, CachedListing>,
+    key: String,
+    content_type: &'static str,
+    max_listing_bytes: usize,
+    generation: Arc<AtomicU64>,
+    started_at: u64,
+    /// `None` once the listing is too large or failed
+    buffer: Option<Vec<u8>>,
+}
+
+impl Fill {
+    fn push(&mut self, chunk: Option<&Result<String, BoxError>>) {
+        let Some(buffer) = &mut self.buffer else {
+            return;
+        };
+
+        match chunk {
+            Some(Ok(chunk)) if buffer.len() + chunk.len() <= self.max_listing_bytes => {
+                buffer.extend_from_slice(chunk.as_bytes());
+            }
+            Some(_) => self.buffer = None,
+            None => {
+                let body = Bytes::from(self.buffer.take().unwrap_or_default());
+                if self.generation.load(Ordering::Acquire) == self.started_at {
+                    self.cache.insert(
+                        self.key.clone(),
+                        CachedListing {
+                            content_type: self.content_type,
+                            body,
+                        },
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn listings_are_cached_until_their_bucket_changes() {
+    let cache = ListingCache::new(&ListingCacheConfig {
+        ttl_secs: 60,
+        ..Default::default()
+    });
+    let render = |cache: &ListingCache, bucket: &str, query: Option<&str>| {
+        let body = futures::stream::iter([Ok(String::from("<a>")), Ok(String::from("</a>"))]);
+        cache
+            .fill("tenant", bucket, query, "application/xml", body)
+            .map(|x| x.unwrap())
+            .collect::<String>()
+    };
+
+    assert!(cache.get("tenant", "docs", None).is_none());
+    assert_eq!(render(&cache, "docs", None).await, "<a></a>");
+    render(&cache, "docs", Some("format=json")).await;
+    render(&cache, "docs-old", None).await;
+
+    let cached = cache.get("tenant", "docs", None).unwrap();
+    assert_eq!(cached.body, "<a></a>");
+    assert_eq!(cached.content_type, "application/xml");
+    assert!(cache.get("tenant", "docs", Some("format=json")).is_some());
+
+    cache.invalidate("tenant", "docs");
+    assert!(cache.get("tenant", "docs", None).is_none());
+    assert!(cache.get("tenant", "docs", Some("format=json")).is_none());
+    assert!(cache.get("tenant", "docs-old", None).is_some());
+
+    // a listing rendered while the bucket changed may miss the change
+    let body = render(&cache, "docs", None);
+    cache.invalidate("tenant", "docs");
+    body.await;
+    assert!(cache.get("tenant", "docs", None).is_none());
+}
@@ -0,0 +1,144 @@
+use crate::context::RequestContext;
+use crate::error::S3Error;
+use crate::operation::S3Operation;
+use crate::AppState;
+use axum::extract::{Request, State};
+use axum::http::header::RETRY_AFTER;
+use axum::http::HeaderValue;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use http_body_util::BodyExt;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LoadSheddingConfig {
+    /// maximum amount of requests handled at the same time
+    pub max_concurrent_requests: Option<usize>,
+    /// per operation limits, keyed by the operation name (case insensitive)
+    #[serde(default)]
+    pub operations: HashMap<String, usize>,
+    #[serde(default = "default_retry_after_secs")]
+    pub retry_after_secs: u64,
+}
+
+impl Default for LoadSheddingConfig {
+    fn default() -> Self {
+        LoadSheddingConfig {
+            max_concurrent_requests: None,
+            operations: HashMap::new(),
+            retry_after_secs: default_retry_after_secs(),
+        }
+    }
+}
+
+fn default_retry_after_secs() -> u64 {
+    1
+}
+
+/// Concurrency limits that reject requests with `SlowDown` instead of queueing them.
+pub struct LoadShedder {
+    global: Option<Arc<Semaphore>>,
+    operations: HashMap<S3Operation, Arc<Semaphore>>,
+    retry_after_secs: u64,
+}
+
+impl LoadShedder {
+    pub fn new(config: &LoadSheddingConfig) -> anyhow::Result<LoadShedder> {
+        let mut operations = HashMap::new();
+
+        for (name, limit) in &config.operations {
+            let operation = S3Operation::from_name(name)
+                .ok_or_else(|| anyhow::anyhow!("unknown operation in load_shedding: {}", name))?;
+            operations.insert(operation, Arc::new(Semaphore::new(*limit)));
+        }
+
+        Ok(LoadShedder {
+            global: config
+                .max_concurrent_requests
+                .map(|limit| Arc::new(Semaphore::new(limit))),
+            operations,
+            retry_after_secs: config.retry_after_secs,
+        })
+    }
+
+    /// Returns the permits for the request, or `None` when a limit is reached.
+    pub fn try_acquire(&self, operation: S3Operation) -> Option<Vec<OwnedSemaphorePermit>> {
+        let mut permits = Vec::with_capacity(2);
+
+        if let Some(global) = &self.global {
+            permits.push(global.clone().try_acquire_owned().ok()?);
+        }
+        if let Some(semaphore) = self.operations.get(&operation) {
+            permits.push(semaphore.clone().try_acquire_owned().ok()?);
+        }
+
+        Some(permits)
+    }
+}
+
+pub async fn limit(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let operation = match req.extensions().get::<Arc<RequestContext>>() {
+        Some(context) => context.operation,
+        None => S3Operation::from_request(req.method(), req.uri()),
+    };
+
+    let Some(permits) = state.load_shedder.try_acquire(operation) else {
+        let mut response = S3Error::SlowDown.into_response();
+        response.headers_mut().insert(
+            RETRY_AFTER,
+            HeaderValue::from(state.load_shedder.retry_after_secs),
+        );
+        return response;
+    };
+
+    let response = next.run(req).await;
+
+    // the permits are released once the response body is dropped, not when the headers are sent
+    response.map(|body| {
+        axum::body::Body::new(body.map_frame(move |frame| {
+            let _ = &permits;
+            frame
+        }))
+    })
+}
+
+#[test]
+fn load_shedder_limits_operations() {
+    let shedder = LoadShedder::new(&LoadSheddingConfig {
+        max_concurrent_requests: Some(2),
+        operations: HashMap::from([(String::from("getobject"), 1)]),
+        retry_after_secs: 1,
+    })
+    .unwrap();
+
+    let first = shedder.try_acquire(S3Operation::GetObject);
+    assert!(first.is_some());
+    assert!(shedder.try_acquire(S3Operation::GetObject).is_none());
+
+    let second = shedder.try_acquire(S3Operation::PutObject);
+    assert!(second.is_some());
+    assert!(shedder.try_acquire(S3Operation::PutObject).is_none());
+
+    drop(first);
+    let third = shedder.try_acquire(S3Operation::GetObject);
+    assert!(third.is_some());
+    assert!(shedder.try_acquire(S3Operation::ListBuckets).is_none());
+
+    drop(second);
+    drop(third);
+    assert!(shedder.try_acquire(S3Operation::ListBuckets).is_some());
+}
+
+#[test]
+fn load_shedder_rejects_unknown_operations() {
+    let result = LoadShedder::new(&LoadSheddingConfig {
+        max_concurrent_requests: None,
+        operations: HashMap::from([(String::from("getobjects"), 1)]),
+        retry_after_secs: 1,
+    });
+
+    assert!(result.is_err());
+}
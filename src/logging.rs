@@ -0,0 +1,223 @@
+use crate::Config;
+use serde::Deserialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogRotation {
+    Never,
+    Minutely,
+    Hourly,
+    Daily,
+    /// rotate once the file grows above `max_size_bytes`
+    Size,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LogFileConfig {
+    pub directory: PathBuf,
+    #[serde(default = "default_file_name")]
+    pub file_name: String,
+    #[serde(default = "default_rotation")]
+    pub rotation: LogRotation,
+    #[serde(default = "default_max_size_bytes")]
+    pub max_size_bytes: u64,
+    /// amount of log files to keep including the current one, keeps everything when not set
+    pub max_files: Option<usize>,
+}
+
+fn default_file_name() -> String {
+    String::from("s3-proxy.log")
+}
+
+fn default_rotation() -> LogRotation {
+    LogRotation::Daily
+}
+
+fn default_max_size_bytes() -> u64 {
+    100 * 1024 * 1024
+}
+
+/// Sets up logging to stdout and/or a rotating log file.
+///
+/// The returned guard flushes the file writer when dropped, so keep it alive in `main`.
+pub fn init(config: &Config) -> anyhow::Result<Option<WorkerGuard>> {
+    let level = LevelFilter::from_level(config.log_level);
+
+    let stdout_layer = config
+        .log_stdout
+        .then(|| tracing_subscriber::fmt::layer().with_filter(level));
+
+    let mut guard = None;
+    let file_layer = match &config.log_file {
+        Some(file_config) => {
+            let (writer, worker_guard) = tracing_appender::non_blocking(file_writer(file_config)?);
+            guard = Some(worker_guard);
+
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .with_ansi(false)
+                    .with_writer(writer)
+                    .with_filter(level),
+            )
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(stdout_layer)
+        .with(file_layer)
+        .init();
+
+    Ok(guard)
+}
+
+fn file_writer(config: &LogFileConfig) -> anyhow::Result<Box<dyn Write + Send>> {
+    fs::create_dir_all(&config.directory)?;
+
+    let rotation = match config.rotation {
+        LogRotation::Size => {
+            return Ok(Box::new(SizeRotatingWriter::new(
+                config.directory.join(&config.file_name),
+                config.max_size_bytes,
+                config.max_files,
+            )?));
+        }
+        LogRotation::Never => Rotation::NEVER,
+        LogRotation::Minutely => Rotation::MINUTELY,
+        LogRotation::Hourly => Rotation::HOURLY,
+        LogRotation::Daily => Rotation::DAILY,
+    };
+
+    let mut builder = RollingFileAppender::builder()
+        .rotation(rotation)
+        .filename_prefix(&config.file_name);
+    if let Some(max_files) = config.max_files {
+        builder = builder.max_log_files(max_files);
+    }
+
+    Ok(Box::new(builder.build(&config.directory)?))
+}
+
+/// Writes to `path`, moving it to `path.1`, `path.2`, ... once it grows above `max_size`.
+struct SizeRotatingWriter {
+    path: PathBuf,
+    max_size: u64,
+    max_files: Option<usize>,
+    file: File,
+    size: u64,
+}
+
+impl SizeRotatingWriter {
+    fn new(path: PathBuf, max_size: u64, max_files: Option<usize>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(SizeRotatingWriter {
+            path,
+            max_size,
+            max_files,
+            file,
+            size,
+        })
+    }
+
+    fn rotated_path(path: &Path, index: usize) -> PathBuf {
+        let mut name = path.as_os_str().to_owned();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+
+        // the current file counts towards max_files
+        let keep = self.max_files.map(|max| max.saturating_sub(1));
+        let mut last = 1;
+        while Self::rotated_path(&self.path, last).exists() {
+            last += 1;
+        }
+
+        for index in (1..last).rev() {
+            let from = Self::rotated_path(&self.path, index);
+            if keep.is_some_and(|keep| index >= keep) {
+                fs::remove_file(from)?;
+            } else {
+                fs::rename(from, Self::rotated_path(&self.path, index + 1))?;
+            }
+        }
+
+        if keep == Some(0) {
+            fs::remove_file(&self.path)?;
+        } else {
+            fs::rename(&self.path, Self::rotated_path(&self.path, 1))?;
+        }
+
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+
+        Ok(())
+    }
+}
+
+impl Write for SizeRotatingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[test]
+fn size_rotating_writer_keeps_max_files() {
+    let directory = std::env::temp_dir().join(format!(
+        "s3-proxy-logs-{}",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos()
+    ));
+    fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("test.log");
+
+    let mut writer = SizeRotatingWriter::new(path.clone(), 10, Some(3)).unwrap();
+    for line in [
+        "first line\n",
+        "second line\n",
+        "third line\n",
+        "fourth line\n",
+    ] {
+        writer.write_all(line.as_bytes()).unwrap();
+    }
+    writer.flush().unwrap();
+
+    let current = fs::read_to_string(&path).unwrap();
+    let first = fs::read_to_string(SizeRotatingWriter::rotated_path(&path, 1)).unwrap();
+    let second = fs::read_to_string(SizeRotatingWriter::rotated_path(&path, 2)).unwrap();
+    let third_exists = SizeRotatingWriter::rotated_path(&path, 3).exists();
+    fs::remove_dir_all(directory).unwrap();
+
+    assert_eq!(current, "fourth line\n");
+    assert_eq!(first, "third line\n");
+    assert_eq!(second, "second line\n");
+    assert!(!third_exists);
+}
@@ -0,0 +1,160 @@
+use deadpool_redis::redis::{AsyncCommands, AsyncIter, RedisError};
+use deadpool_redis::{Pool, PoolError};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+/// Where the metadata (secret keys, ...) is stored.
+///
+/// Production uses redis, the in-memory store is meant for tests and embedding.
+#[derive(Clone)]
+pub enum MetadataStore {
+    /// the pool is already an Arc
+    Redis(Pool),
+    Memory(Arc<RwLock<BTreeMap<String, String>>>),
+}
+
+impl MetadataStore {
+    pub fn memory() -> MetadataStore {
+        MetadataStore::Memory(Default::default())
+    }
+
+    /// The redis pool, for the features that only work with redis.
+    pub fn redis_pool(&self) -> Option<&Pool> {
+        match self {
+            MetadataStore::Redis(pool) => Some(pool),
+            MetadataStore::Memory(_) => None,
+        }
+    }
+
+    pub async fn get(&self, key: &str) -> Result<Option<String>, MetadataError> {
+        match self {
+            MetadataStore::Redis(pool) => Ok(pool.get().await?.get(key).await?),
+            MetadataStore::Memory(map) => {
+                Ok(map.read().expect("lock is not poisoned").get(key).cloned())
+            }
+        }
+    }
+
+    pub async fn get_many(&self, keys: &[String]) -> Result<Vec<Option<String>>, MetadataError> {
+        match self {
+            // MGET without keys is an error
+            _ if keys.is_empty() => Ok(Vec::new()),
+            MetadataStore::Redis(pool) => Ok(pool.get().await?.mget(keys).await?),
+            MetadataStore::Memory(map) => {
+                let map = map.read().expect("lock is not poisoned");
+                Ok(keys.iter().map(|key| map.get(key).cloned()).collect())
+            }
+        }
+    }
+
+    pub async fn set(&self, key: &str, value: &str) -> Result<(), MetadataError> {
+        match self {
+            MetadataStore::Redis(pool) => Ok(pool.get().await?.set(key, value).await?),
+            MetadataStore::Memory(map) => {
+                map.write()
+                    .expect("lock is not poisoned")
+                    .insert(key.to_string(), value.to_string());
+                Ok(())
+            }
+        }
+    }
+
+    /// Adds `amount` to the number stored at `key`, missing keys start at 0.
+    pub async fn incr_by(&self, key: &str, amount: u64) -> Result<u64, MetadataError> {
+        match self {
+            MetadataStore::Redis(pool) => Ok(pool.get().await?.incr(key, amount).await?),
+            MetadataStore::Memory(map) => {
+                let mut map = map.write().expect("lock is not poisoned");
+                let value = map
+                    .get(key)
+                    .and_then(|x| x.parse::<u64>().ok())
+                    .unwrap_or_default()
+                    + amount;
+                map.insert(key.to_string(), value.to_string());
+                Ok(value)
+            }
+        }
+    }
+
+    pub async fn delete(&self, key: &str) -> Result<(), MetadataError> {
+        match self {
+            MetadataStore::Redis(pool) => Ok(pool.get().await?.del(key).await?),
+            MetadataStore::Memory(map) => {
+                map.write().expect("lock is not poisoned").remove(key);
+                Ok(())
+            }
+        }
+    }
+
+    /// All keys that start with `prefix`.
+    pub async fn keys(&self, prefix: &str) -> Result<Vec<String>, MetadataError> {
+        match self {
+            MetadataStore::Redis(pool) => {
+                let mut conn = pool.get().await?;
+                let mut iter: AsyncIter<String> = conn.scan_match(format!("{}*", prefix)).await?;
+                let mut keys = Vec::new();
+                while let Some(key) = iter.next_item().await {
+                    keys.push(key);
+                }
+                Ok(keys)
+            }
+            MetadataStore::Memory(map) => Ok(map
+                .read()
+                .expect("lock is not poisoned")
+                .range(prefix.to_string()..)
+                .take_while(|(key, _)| key.starts_with(prefix))
+                .map(|(key, _)| key.clone())
+                .collect()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum MetadataError {
+    Pool(PoolError),
+    Redis(RedisError),
+}
+
+impl fmt::Display for MetadataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetadataError::Pool(error) => write!(f, "{}", error),
+            MetadataError::Redis(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for MetadataError {}
+
+impl From<PoolError> for MetadataError {
+    fn from(value: PoolError) -> Self {
+        MetadataError::Pool(value)
+    }
+}
+
+impl From<RedisError> for MetadataError {
+    fn from(value: RedisError) -> Self {
+        MetadataError::Redis(value)
+    }
+}
+
+#[tokio::test]
+async fn memory_store_lists_keys_by_prefix() {
+    let store = MetadataStore::memory();
+    store.set("secret_key::a", "1").await.unwrap();
+    store.set("secret_key::b", "2").await.unwrap();
+    store.set("other", "3").await.unwrap();
+
+    assert_eq!(
+        store.keys("secret_key::").await.unwrap(),
+        vec!["secret_key::a", "secret_key::b"]
+    );
+    assert_eq!(
+        store
+            .get_many(&[String::from("secret_key::b"), String::from("missing")])
+            .await
+            .unwrap(),
+        vec![Some(String::from("2")), None]
+    );
+}
@@ -0,0 +1,222 @@
+use crate::context::RequestSummary;
+use opendal::{Metakey, Operator};
+use prometheus::{Encoder, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio_stream::StreamExt;
+
+/// label used for namespaces above the cardinality limit
+const OVERFLOW_NAMESPACE: &str = "__other__";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MetricsConfig {
+    /// at most this many distinct namespaces get their own label value
+    #[serde(default = "default_max_namespaces")]
+    pub max_namespaces: usize,
+    /// how often the storage used per namespace is recalculated, 0 disables it
+    #[serde(default = "default_storage_refresh_secs")]
+    pub storage_refresh_secs: u64,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        MetricsConfig {
+            max_namespaces: default_max_namespaces(),
+            storage_refresh_secs: default_storage_refresh_secs(),
+        }
+    }
+}
+
+fn default_max_namespaces() -> usize {
+    1000
+}
+
+fn default_storage_refresh_secs() -> u64 {
+    300
+}
+
+/// Per namespace usage metrics, exported in the prometheus text format.
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    bytes_in: IntCounterVec,
+    bytes_out: IntCounterVec,
+    storage_bytes: IntGaugeVec,
+    storage_objects: IntGaugeVec,
+    namespaces: Mutex<HashSet<String>>,
+    max_namespaces: usize,
+}
+
+impl Metrics {
+    pub fn new(config: &MetricsConfig) -> anyhow::Result<Metrics> {
+        let registry = Registry::new_custom(Some(String::from("s3_proxy")), None)?;
+
+        let requests = IntCounterVec::new(
+            Opts::new("requests_total", "Number of handled requests"),
+            &["namespace", "operation", "status"],
+        )?;
+        let bytes_in = IntCounterVec::new(
+            Opts::new("received_bytes_total", "Bytes received in request bodies"),
+            &["namespace"],
+        )?;
+        let bytes_out = IntCounterVec::new(
+            Opts::new("sent_bytes_total", "Bytes sent in response bodies"),
+            &["namespace"],
+        )?;
+        let storage_bytes = IntGaugeVec::new(
+            Opts::new("storage_bytes", "Bytes stored in the backend"),
+            &["namespace"],
+        )?;
+        let storage_objects = IntGaugeVec::new(
+            Opts::new("storage_objects", "Objects stored in the backend"),
+            &["namespace"],
+        )?;
+
+        registry.register(Box::new(requests.clone()))?;
+        registry.register(Box::new(bytes_in.clone()))?;
+        registry.register(Box::new(bytes_out.clone()))?;
+        registry.register(Box::new(storage_bytes.clone()))?;
+        registry.register(Box::new(storage_objects.clone()))?;
+
+        Ok(Metrics {
+            registry,
+            requests,
+            bytes_in,
+            bytes_out,
+            storage_bytes,
+            storage_objects,
+            namespaces: Mutex::new(HashSet::new()),
+            max_namespaces: config.max_namespaces,
+        })
+    }
+
+    /// Maps the namespace to its label value, keeping the amount of label values bounded.
+    fn namespace_label<'a>(&self, namespace: &'a str) -> &'a str {
+        let mut namespaces = self.namespaces.lock().expect("poisoned lock");
+
+        if namespaces.contains(namespace) {
+            return namespace;
+        }
+
+        if namespaces.len() < self.max_namespaces {
+            namespaces.insert(namespace.to_string());
+            return namespace;
+        }
+
+        OVERFLOW_NAMESPACE
+    }
+
+    pub fn record(&self, summary: &RequestSummary) {
+        let context = summary.context;
+        // unauthenticated requests are not attributed to a tenant
+        let namespace = self.namespace_label(context.namespace().unwrap_or(""));
+
+        self.requests
+            .with_label_values(&[
+                namespace,
+                context.operation.as_str(),
+                summary.status.as_str(),
+            ])
+            .inc();
+        self.bytes_in
+            .with_label_values(&[namespace])
+            .inc_by(context.request_bytes());
+        self.bytes_out
+            .with_label_values(&[namespace])
+            .inc_by(summary.response_bytes);
+    }
+
+    pub fn set_storage(&self, namespace: &str, bytes: u64, objects: u64) {
+        let namespace = self.namespace_label(namespace);
+
+        self.storage_bytes
+            .with_label_values(&[namespace])
+            .set(bytes as i64);
+        self.storage_objects
+            .with_label_values(&[namespace])
+            .set(objects as i64);
+    }
+
+    pub fn encode(&self) -> anyhow::Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+
+        Ok(String::from_utf8(buffer)?)
+    }
+}
+
+/// Periodically walks the backend to calculate the storage used per namespace.
+pub async fn refresh_storage(
+    metrics: std::sync::Arc<Metrics>,
+    operator: Operator,
+    every: Duration,
+) {
+    let mut interval = tokio::time::interval(every);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(error) = calculate_storage(&metrics, &operator).await {
+            tracing::error!("unable to calculate storage metrics: {}", error);
+        }
+    }
+}
+
+async fn calculate_storage(metrics: &Metrics, operator: &Operator) -> opendal::Result<()> {
+    let mut namespaces = operator.lister("/").await?;
+
+    while let Some(entry) = namespaces.next().await {
+        let entry = entry?;
+        if !entry.metadata().is_dir() {
+            continue;
+        }
+
+        let namespace = entry.name().trim_end_matches('/');
+        let (total_bytes, total_objects) = storage_usage(operator, entry.path()).await?;
+
+        metrics.set_storage(namespace, total_bytes, total_objects);
+    }
+
+    Ok(())
+}
+
+/// Total bytes and object count below `path`, which has to end with a `/`.
+pub async fn storage_usage(operator: &Operator, path: &str) -> opendal::Result<(u64, u64)> {
+    let mut objects = operator
+        .lister_with(path)
+        .recursive(true)
+        .metakey(Metakey::ContentLength)
+        .await?;
+
+    let mut total_bytes = 0;
+    let mut total_objects = 0;
+    while let Some(object) = objects.next().await {
+        let object = object?;
+        if object.metadata().is_file() {
+            total_bytes += object.metadata().content_length();
+            total_objects += 1;
+        }
+    }
+
+    Ok((total_bytes, total_objects))
+}
+
+#[test]
+fn namespace_labels_are_bounded() {
+    let metrics = Metrics::new(&MetricsConfig {
+        max_namespaces: 2,
+        storage_refresh_secs: 0,
+    })
+    .unwrap();
+
+    assert_eq!(metrics.namespace_label("one"), "one");
+    assert_eq!(metrics.namespace_label("two"), "two");
+    assert_eq!(metrics.namespace_label("three"), OVERFLOW_NAMESPACE);
+    assert_eq!(metrics.namespace_label("one"), "one");
+
+    metrics.set_storage("three", 10, 1);
+    let output = metrics.encode().unwrap();
+    assert!(output.contains(r#"s3_proxy_storage_bytes{namespace="__other__"} 10"#));
+}
@@ -0,0 +1,124 @@
+use crate::credentials::{KEY_NAMESPACE_PREFIX, SECRET_KEY_PREFIX};
+use crate::metadata::{MetadataError, MetadataStore};
+use crate::namespaces::NAMESPACE_PREFIX;
+
+/// Holds the version of the record layout in the metadata store, missing is version 0.
+pub const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+/// Migration `n` upgrades the metadata from version `n` to `n + 1`. Only add to the end.
+const MIGRATIONS: &[&str] = &["namespace records for the access keys created before namespaces"];
+
+/// The version this build reads and writes.
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// Upgrades the metadata store to `SCHEMA_VERSION`, returns the version it was at.
+///
+/// Instances can start at the same time, so every migration has to be safe to run twice. A
+/// store written by a newer version is refused instead of being changed.
+pub async fn migrate(metadata: &MetadataStore) -> anyhow::Result<u32> {
+    let version = schema_version(metadata).await?;
+    if version > SCHEMA_VERSION {
+        anyhow::bail!(
+            "the metadata store has schema version {} but this s3-proxy only knows up to {}, \
+             upgrade s3-proxy instead",
+            version,
+            SCHEMA_VERSION
+        );
+    }
+
+    for (from, description) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        tracing::info!(
+            "migrating metadata to version {}: {}",
+            from + 1,
+            description
+        );
+        apply(metadata, from as u32).await?;
+        metadata
+            .set(SCHEMA_VERSION_KEY, &(from + 1).to_string())
+            .await?;
+    }
+
+    Ok(version)
+}
+
+pub async fn schema_version(metadata: &MetadataStore) -> anyhow::Result<u32> {
+    match metadata.get(SCHEMA_VERSION_KEY).await? {
+        Some(version) => version
+            .parse()
+            .map_err(|_| anyhow::anyhow!("{} is not a number: {}", SCHEMA_VERSION_KEY, version)),
+        None => Ok(0),
+    }
+}
+
+async fn apply(metadata: &MetadataStore, from: u32) -> Result<(), MetadataError> {
+    match from {
+        0 => legacy_namespaces(metadata).await,
+        _ => unreachable!("every version below SCHEMA_VERSION has a migration"),
+    }
+}
+
+/// Keys without a `key_namespace::` record are their own namespace, give those namespaces the
+/// records that the admin api creates for new ones.
+async fn legacy_namespaces(metadata: &MetadataStore) -> Result<(), MetadataError> {
+    for key in metadata.keys(SECRET_KEY_PREFIX).await? {
+        let Some(access_key) = key.strip_prefix(SECRET_KEY_PREFIX) else {
+            continue;
+        };
+
+        let mapping = format!("{}{}", KEY_NAMESPACE_PREFIX, access_key);
+        if metadata.get(&mapping).await?.is_some() {
+            continue;
+        }
+        let namespace = format!("{}{}", NAMESPACE_PREFIX, access_key);
+        if metadata.get(&namespace).await?.is_none() {
+            metadata.set(&namespace, "1").await?;
+        }
+        metadata.set(&mapping, access_key).await?;
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn legacy_keys_get_namespace_records() {
+    use crate::namespaces::Namespaces;
+
+    let metadata = MetadataStore::memory();
+    metadata
+        .set(&format!("{}OLDKEY", SECRET_KEY_PREFIX), "secret")
+        .await
+        .unwrap();
+    let created = Namespaces::new(&metadata)
+        .create_key("tenant")
+        .await
+        .unwrap()
+        .access_key;
+
+    assert_eq!(migrate(&metadata).await.unwrap(), 0);
+    assert_eq!(schema_version(&metadata).await.unwrap(), SCHEMA_VERSION);
+    assert_eq!(
+        Namespaces::new(&metadata).list().await.unwrap(),
+        vec!["OLDKEY"]
+    );
+    assert_eq!(
+        Namespaces::new(&metadata).keys("OLDKEY").await.unwrap(),
+        vec!["OLDKEY"]
+    );
+    assert_eq!(
+        metadata
+            .get(&format!("{}{}", KEY_NAMESPACE_PREFIX, created))
+            .await
+            .unwrap()
+            .as_deref(),
+        Some("tenant")
+    );
+
+    // the next start has nothing to do
+    assert_eq!(migrate(&metadata).await.unwrap(), SCHEMA_VERSION);
+
+    metadata
+        .set(SCHEMA_VERSION_KEY, &(SCHEMA_VERSION + 1).to_string())
+        .await
+        .unwrap();
+    assert!(migrate(&metadata).await.is_err());
+}
@@ -0,0 +1,317 @@
+use crate::acl::CannedAcl;
+use crate::axum_ext::{check_key, is_hidden};
+use crate::error::S3Error;
+use crate::metadata::{MetadataError, MetadataStore};
+use crate::object_lock::ObjectLock;
+use crate::{etags, integrity};
+use axum::http::{HeaderMap, HeaderName};
+use futures::TryStreamExt;
+use md5::{Digest, Md5};
+use opendal::Operator;
+use rand::distributions::{Alphanumeric, DistString};
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+/// `multipart_upload::{namespace}::{upload_id}` holds the pending upload as json
+pub const UPLOAD_PREFIX: &str = "multipart_upload::";
+/// The parts of pending uploads are staged in `{namespace}/.multipart/{upload_id}/`, next to the
+/// buckets so they are not listed.
+pub const MULTIPART_DIR: &str = ".multipart";
+/// `UploadPartCopy` copies (a range of) this object into the part instead of sending a body.
+pub static COPY_SOURCE_HEADER: HeaderName = HeaderName::from_static("x-amz-copy-source");
+pub static COPY_SOURCE_RANGE_HEADER: HeaderName =
+    HeaderName::from_static("x-amz-copy-source-range");
+/// Parts are numbered from 1 up to this, like S3.
+pub const MAX_PART_NUMBER: u32 = 10000;
+/// Every part but the last must be at least 5 MiB, like S3.
+pub const MIN_PART_SIZE: u64 = 5 * 1024 * 1024;
+
+/// A multipart upload that was initiated and not completed yet.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PendingUpload {
+    pub bucket: String,
+    pub key: String,
+    pub content_type: Option<String>,
+    /// unix timestamp
+    pub initiated: u64,
+    /// the lock asked for when the upload was initiated, given to the object on completion
+    #[serde(default, skip_serializing_if = "ObjectLock::is_empty")]
+    pub lock: ObjectLock,
+    /// the `x-amz-acl` of the upload, given to the object on completion
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acl: Option<CannedAcl>,
+    /// the `x-amz-storage-class` of the upload, given to the object on completion
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storage_class: Option<String>,
+}
+
+impl PendingUpload {
+    /// An upload of `bucket`/`key` initiated now.
+    pub fn new(bucket: &str, key: &str) -> PendingUpload {
+        PendingUpload {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            content_type: None,
+            initiated: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            lock: ObjectLock::default(),
+            acl: None,
+            storage_class: None,
+        }
+    }
+}
+
+/// The `<CompleteMultipartUpload>` body, the parts that make up the object in order.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct CompleteMultipartUpload {
+    #[serde(rename = "Part", default)]
+    pub parts: Vec<CompletedPart>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct CompletedPart {
+    #[serde(rename = "PartNumber")]
+    pub part_number: u32,
+    #[serde(rename = "ETag")]
+    pub etag: String,
+}
+
+fn record_key(namespace: &str, upload_id: &str) -> String {
+    format!("{}{}::{}", UPLOAD_PREFIX, namespace, upload_id)
+}
+
+/// The directory the parts of `upload_id` are staged in.
+pub fn staging_dir(namespace: &str, upload_id: &str) -> String {
+    format!("{}/{}/{}/", namespace, MULTIPART_DIR, upload_id)
+}
+
+/// The staged part, zero padded so the parts list in order.
+pub fn part_path(namespace: &str, upload_id: &str, part_number: u32) -> String {
+    format!("{}{:05}", staging_dir(namespace, upload_id), part_number)
+}
+
+/// The `partNumber` query parameter, `None` when it is outside of S3's bounds.
+pub fn part_number(value: &str) -> Option<u32> {
+    value
+        .parse()
+        .ok()
+        .filter(|x| (1..=MAX_PART_NUMBER).contains(x))
+}
+
+/// The bucket and key of `x-amz-copy-source`, the source is always in the caller's namespace.
+pub fn copy_source(headers: &HeaderMap) -> Result<Option<(String, String)>, S3Error> {
+    let Some(value) = headers.get(&COPY_SOURCE_HEADER) else {
+        return Ok(None);
+    };
+
+    let value = percent_encoding::percent_decode(value.as_bytes()).decode_utf8_lossy();
+    // there is only the null version of every object
+    let value = match value.split_once("?versionId=") {
+        Some((value, "null")) => value,
+        Some(_) => {
+            return Err(S3Error::NotImplemented(String::from(
+                "objects only have the null version, older versions are kept in the trash",
+            )))
+        }
+        None => &value,
+    };
+    match value.trim_start_matches('/').split_once('/') {
+        Some((bucket, key)) if !bucket.is_empty() && !key.is_empty() && !is_hidden(bucket) => {
+            check_key(key)?;
+            Ok(Some((bucket.to_string(), key.to_string())))
+        }
+        _ => Err(S3Error::InvalidArgument(String::from(
+            "Copy Source must mention the source bucket and key: sourcebucket/sourcekey",
+        ))),
+    }
+}
+
+/// Records a new upload, returns its upload id.
+pub async fn initiate(
+    metadata: &MetadataStore,
+    namespace: &str,
+    upload: &PendingUpload,
+) -> Result<String, MetadataError> {
+    let upload_id = Alphanumeric.sample_string(&mut rand::thread_rng(), 32);
+    let upload = serde_json::to_string(upload).expect("pending upload serializes");
+    metadata
+        .set(&record_key(namespace, &upload_id), &upload)
+        .await?;
+    Ok(upload_id)
+}
+
+/// The pending upload `upload_id` of the namespace, uploads of other namespaces are not found.
+pub async fn pending(
+    metadata: &MetadataStore,
+    namespace: &str,
+    upload_id: &str,
+) -> Result<Option<PendingUpload>, MetadataError> {
+    let upload = metadata.get(&record_key(namespace, upload_id)).await?;
+
+    Ok(upload.and_then(|x| serde_json::from_str(&x).ok()))
+}
+
+/// The pending uploads of the namespace with their upload ids.
+pub async fn uploads(
+    metadata: &MetadataStore,
+    namespace: &str,
+) -> Result<Vec<(String, PendingUpload)>, MetadataError> {
+    let prefix = format!("{}{}::", UPLOAD_PREFIX, namespace);
+    let keys = metadata.keys(&prefix).await?;
+    let uploads = metadata.get_many(&keys).await?;
+
+    Ok(keys
+        .iter()
+        .zip(uploads)
+        .filter_map(|(key, upload)| {
+            let upload = serde_json::from_str(&upload?).ok()?;
+            Some((key[prefix.len()..].to_string(), upload))
+        })
+        .collect())
+}
+
+/// The ETag S3 gives objects uploaded in parts, the md5 of the part md5s and the part count.
+///
+/// `None` when one of the part etags is not an md5.
+pub fn composite_etag<'a>(part_etags: impl ExactSizeIterator<Item = &'a str>) -> Option<String> {
+    let count = part_etags.len();
+    let mut hasher = Md5::new();
+    for etag in part_etags {
+        hasher.update(integrity::md5_etag(etag)?);
+    }
+
+    Some(format!("\"{}-{}\"", hex::encode(hasher.finalize()), count))
+}
+
+/// Forgets the upload and removes its staged parts, after it was completed or aborted.
+pub async fn remove(
+    operator: &Operator,
+    metadata: &MetadataStore,
+    namespace: &str,
+    upload_id: &str,
+) -> Result<(), S3Error> {
+    let staging_dir = staging_dir(namespace, upload_id);
+    let parts: Vec<_> = operator.lister(&staging_dir).await?.try_collect().await?;
+    for part in parts {
+        etags::remove(metadata, part.path()).await?;
+    }
+    operator.remove_all(&staging_dir).await?;
+    metadata.delete(&record_key(namespace, upload_id)).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn uploads_belong_to_their_namespace() {
+    let metadata = MetadataStore::memory();
+    let upload_id = initiate(
+        &metadata,
+        "tenant",
+        &PendingUpload {
+            content_type: Some(String::from("image/jpeg")),
+            acl: Some(CannedAcl::PublicRead),
+            storage_class: Some(String::from("GLACIER")),
+            ..PendingUpload::new("photos", "dir/a.jpg")
+        },
+    )
+    .await
+    .unwrap();
+
+    let upload = pending(&metadata, "tenant", &upload_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(upload.bucket, "photos");
+    assert_eq!(upload.key, "dir/a.jpg");
+    assert_eq!(upload.content_type.as_deref(), Some("image/jpeg"));
+    assert_eq!(upload.acl, Some(CannedAcl::PublicRead));
+    assert_eq!(upload.storage_class.as_deref(), Some("GLACIER"));
+
+    assert_eq!(pending(&metadata, "other", &upload_id).await.unwrap(), None);
+    let listed = uploads(&metadata, "tenant").await.unwrap();
+    assert_eq!(listed, vec![(upload_id.clone(), upload)]);
+    assert!(uploads(&metadata, "other").await.unwrap().is_empty());
+    assert_eq!(
+        part_path("tenant", &upload_id, 7),
+        format!("tenant/.multipart/{}/00007", upload_id)
+    );
+    assert_eq!(part_number("10000"), Some(10000));
+    assert_eq!(part_number("0"), None);
+    assert_eq!(part_number("10001"), None);
+    assert_eq!(part_number("one"), None);
+}
+
+#[test]
+fn copy_sources_name_a_bucket_and_key() {
+    use axum::http::HeaderValue;
+
+    let source = |value: &'static str| {
+        let mut headers = HeaderMap::new();
+        headers.insert(COPY_SOURCE_HEADER.clone(), HeaderValue::from_static(value));
+        copy_source(&headers).map(|x| x.unwrap())
+    };
+
+    let expected = (String::from("photos"), String::from("dir/a b.jpg"));
+    assert_eq!(source("photos/dir/a%20b.jpg").unwrap(), expected);
+    assert_eq!(source("/photos/dir/a%20b.jpg").unwrap(), expected);
+    assert_eq!(
+        source("photos%2Fdir%2Fa%20b.jpg?versionId=null").unwrap(),
+        expected
+    );
+    assert!(matches!(
+        source("photos/dir/a%20b.jpg?versionId=1"),
+        Err(S3Error::NotImplemented(_))
+    ));
+    assert!(source("photos").is_err());
+    assert!(source("photos/").is_err());
+    assert!(source(".trash/1/photos/a.jpg").is_err());
+    assert!(source("photos/a/../../other/photos/a.jpg").is_err());
+    assert_eq!(copy_source(&HeaderMap::new()).unwrap(), None);
+}
+
+#[tokio::test]
+async fn completed_uploads_are_cleaned_up() {
+    let xml = r#"<CompleteMultipartUpload xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+       <Part><ETag>"5eb63bbbe01eeed093cb22bb8f5acdc3"</ETag><PartNumber>1</PartNumber></Part>
+       <Part><ETag>"5d41402abc4b2a76b9719d911017c592"</ETag><PartNumber>2</PartNumber></Part>
+    </CompleteMultipartUpload>"#;
+    let body: CompleteMultipartUpload = quick_xml::de::from_str(xml).unwrap();
+    assert_eq!(body.parts.len(), 2);
+    assert_eq!(body.parts[1].part_number, 2);
+    assert_eq!(
+        composite_etag(body.parts.iter().map(|x| x.etag.as_str())),
+        Some(String::from("\"24adfd9e975123d0326970b01bb6a4d2-2\""))
+    );
+    assert_eq!(composite_etag(["\"abc\""].into_iter()), None);
+
+    let operator = Operator::new(opendal::services::Memory::default())
+        .unwrap()
+        .finish();
+    let metadata = MetadataStore::memory();
+    let upload_id = initiate(&metadata, "tenant", &PendingUpload::new("photos", "a.jpg"))
+        .await
+        .unwrap();
+    let part = part_path("tenant", &upload_id, 1);
+    operator.write(&part, "hello world").await.unwrap();
+    etags::store(&metadata, &part, &body.parts[0].etag)
+        .await
+        .unwrap();
+
+    remove(&operator, &metadata, "tenant", &upload_id)
+        .await
+        .unwrap();
+    assert!(!operator.is_exist(&part).await.unwrap());
+    assert_eq!(
+        metadata
+            .get(&format!("{}{}", etags::ETAG_PREFIX, part))
+            .await
+            .unwrap(),
+        None
+    );
+    assert_eq!(
+        pending(&metadata, "tenant", &upload_id).await.unwrap(),
+        None
+    );
+}
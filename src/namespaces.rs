@@ -0,0 +1,922 @@
+use crate::acl::CannedAcl;
+use crate::cors::CorsConfiguration;
+use crate::credentials::{
+    PreviousSecretKey, KEY_NAMESPACE_PREFIX, PREVIOUS_SECRET_KEY_PREFIX, SECRET_KEY_PREFIX,
+};
+use crate::inventory::{InventoryConfiguration, INVENTORY_RUN_PREFIX};
+use crate::lifecycle::LifecycleConfiguration;
+use crate::metadata::{MetadataError, MetadataStore};
+use crate::notifications::NotificationConfiguration;
+use crate::object_lock::ObjectLockConfiguration;
+use crate::rate_limit::NamespaceLimits;
+use crate::replication::ReplicationConfiguration;
+use crate::tagging::Tagging;
+use crate::versioning::VersioningStatus;
+use rand::distributions::{Alphanumeric, DistString};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime};
+
+pub const NAMESPACE_PREFIX: &str = "namespace::";
+pub const QUOTA_PREFIX: &str = "quota::";
+pub const POLICY_PREFIX: &str = "policy::";
+pub const LIMITS_PREFIX: &str = "limits::";
+pub const BUCKET_QUOTA_PREFIX: &str = "bucket_quota::";
+pub const FROZEN_PREFIX: &str = "frozen::";
+pub const NOTIFICATION_PREFIX: &str = "notification::";
+pub const REPLICATION_PREFIX: &str = "replication::";
+pub const INVENTORY_PREFIX: &str = "inventory::";
+pub const PUBLIC_PREFIX: &str = "public::";
+pub const REGION_PREFIX: &str = "region::";
+pub const TAGGING_PREFIX: &str = "tagging::";
+pub const VERSIONING_PREFIX: &str = "versioning::";
+pub const OBJECT_LOCK_CONFIGURATION_PREFIX: &str = "object_lock_configuration::";
+pub const LIFECYCLE_PREFIX: &str = "lifecycle::";
+pub const CORS_PREFIX: &str = "cors::";
+pub const BUCKET_POLICY_PREFIX: &str = "bucket_policy::";
+/// set for buckets with a `public-read` ACL, anyone may list them
+pub const BUCKET_ACL_PREFIX: &str = "bucket_acl::";
+/// the namespaces with a CORS configuration for a bucket name, keyed by the bucket name only,
+/// preflights are unsigned and do not tell the namespace
+pub const CORS_BUCKET_PREFIX: &str = "cors_bucket::";
+/// set once the namespace got the configured default buckets
+pub const DEFAULT_BUCKETS_PREFIX: &str = "default_buckets::";
+
+/// The records that belong to a bucket, their keys are `{prefix}{namespace}::{bucket}`.
+pub const BUCKET_RECORD_PREFIXES: &[&str] = &[
+    BUCKET_QUOTA_PREFIX,
+    FROZEN_PREFIX,
+    NOTIFICATION_PREFIX,
+    REPLICATION_PREFIX,
+    INVENTORY_PREFIX,
+    INVENTORY_RUN_PREFIX,
+    PUBLIC_PREFIX,
+    REGION_PREFIX,
+    TAGGING_PREFIX,
+    VERSIONING_PREFIX,
+    OBJECT_LOCK_CONFIGURATION_PREFIX,
+    LIFECYCLE_PREFIX,
+    CORS_PREFIX,
+    BUCKET_POLICY_PREFIX,
+    BUCKET_ACL_PREFIX,
+];
+
+const ACCESS_KEY_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+
+/// Limits of a namespace or bucket, `None` is unlimited.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Quota {
+    pub max_bytes: Option<u64>,
+    pub max_objects: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccessKey {
+    pub access_key: String,
+    pub secret_key: String,
+}
+
+fn generate_secret_key() -> String {
+    Alphanumeric.sample_string(&mut rand::thread_rng(), 40)
+}
+
+/// Namespaces are used as backend directories, so only a safe subset of names is allowed.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 63
+        && name
+            .bytes()
+            .all(|x| x.is_ascii_alphanumeric() || x == b'-' || x == b'_')
+}
+
+/// Admin operations on the namespace records in the metadata store.
+///
+/// Namespaces created before these records existed are named after their access key, those
+/// keep working without a `namespace::` record.
+pub struct Namespaces<'a> {
+    metadata: &'a MetadataStore,
+}
+
+impl<'a> Namespaces<'a> {
+    pub fn new(metadata: &'a MetadataStore) -> Namespaces<'a> {
+        Namespaces { metadata }
+    }
+
+    pub async fn list(&self) -> Result<Vec<String>, MetadataError> {
+        let mut namespaces: Vec<_> = self
+            .metadata
+            .keys(NAMESPACE_PREFIX)
+            .await?
+            .into_iter()
+            .filter_map(|key| key.strip_prefix(NAMESPACE_PREFIX).map(String::from))
+            .collect();
+        namespaces.sort();
+
+        Ok(namespaces)
+    }
+
+    pub async fn exists(&self, namespace: &str) -> Result<bool, MetadataError> {
+        Ok(self
+            .metadata
+            .get(&format!("{}{}", NAMESPACE_PREFIX, namespace))
+            .await?
+            .is_some())
+    }
+
+    /// Returns false when the namespace already exists.
+    pub async fn create(&self, namespace: &str) -> Result<bool, MetadataError> {
+        if self.exists(namespace).await? {
+            return Ok(false);
+        }
+
+        self.metadata
+            .set(&format!("{}{}", NAMESPACE_PREFIX, namespace), "1")
+            .await?;
+        Ok(true)
+    }
+
+    /// Removes the namespace with its keys, quotas and policy. The objects are kept.
+    pub async fn delete(&self, namespace: &str) -> Result<Vec<String>, MetadataError> {
+        let keys = self.keys(namespace).await?;
+        for access_key in &keys {
+            self.delete_key(access_key).await?;
+        }
+
+        for prefix in BUCKET_RECORD_PREFIXES {
+            let bucket_records = self
+                .metadata
+                .keys(&format!("{}{}::", prefix, namespace))
+                .await?;
+            for key in bucket_records {
+                self.metadata.delete(&key).await?;
+            }
+        }
+
+        self.metadata
+            .delete(&format!("{}{}", QUOTA_PREFIX, namespace))
+            .await?;
+        self.metadata
+            .delete(&format!("{}{}", POLICY_PREFIX, namespace))
+            .await?;
+        self.metadata
+            .delete(&format!("{}{}", LIMITS_PREFIX, namespace))
+            .await?;
+        self.metadata
+            .delete(&format!("{}{}", DEFAULT_BUCKETS_PREFIX, namespace))
+            .await?;
+        self.metadata
+            .delete(&format!("{}{}", NAMESPACE_PREFIX, namespace))
+            .await?;
+
+        Ok(keys)
+    }
+
+    /// The access keys of the namespace.
+    pub async fn keys(&self, namespace: &str) -> Result<Vec<String>, MetadataError> {
+        let mapped = self.metadata.keys(KEY_NAMESPACE_PREFIX).await?;
+        let namespaces = self.metadata.get_many(&mapped).await?;
+
+        let mut keys: Vec<_> = mapped
+            .iter()
+            .zip(namespaces)
+            .filter(|(_, x)| x.as_deref() == Some(namespace))
+            .filter_map(|(key, _)| key.strip_prefix(KEY_NAMESPACE_PREFIX).map(String::from))
+            .collect();
+
+        // the key the namespace is named after
+        let legacy = self
+            .metadata
+            .get(&format!("{}{}", SECRET_KEY_PREFIX, namespace))
+            .await?;
+        if legacy.is_some() && !mapped.contains(&format!("{}{}", KEY_NAMESPACE_PREFIX, namespace)) {
+            keys.push(namespace.to_string());
+        }
+        keys.sort();
+
+        Ok(keys)
+    }
+
+    pub async fn create_key(&self, namespace: &str) -> Result<AccessKey, MetadataError> {
+        let access_key = {
+            let mut rng = rand::thread_rng();
+            (0..20)
+                .map(|_| ACCESS_KEY_CHARS[rng.gen_range(0..ACCESS_KEY_CHARS.len())] as char)
+                .collect::<String>()
+        };
+        let secret_key = generate_secret_key();
+
+        self.metadata
+            .set(
+                &format!("{}{}", KEY_NAMESPACE_PREFIX, access_key),
+                namespace,
+            )
+            .await?;
+        self.metadata
+            .set(&format!("{}{}", SECRET_KEY_PREFIX, access_key), &secret_key)
+            .await?;
+
+        Ok(AccessKey {
+            access_key,
+            secret_key,
+        })
+    }
+
+    /// Gives the key a new secret, the old one keeps working for `grace`. Returns `None` for
+    /// unknown keys.
+    pub async fn rotate_key(
+        &self,
+        access_key: &str,
+        grace: Duration,
+    ) -> Result<Option<AccessKey>, MetadataError> {
+        let secret_key_record = format!("{}{}", SECRET_KEY_PREFIX, access_key);
+        let Some(previous) = self.metadata.get(&secret_key_record).await? else {
+            return Ok(None);
+        };
+
+        let previous_record = format!("{}{}", PREVIOUS_SECRET_KEY_PREFIX, access_key);
+        if grace.is_zero() {
+            self.metadata.delete(&previous_record).await?;
+        } else {
+            let expires_at = (SystemTime::now() + grace)
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            let previous = PreviousSecretKey {
+                secret_key: previous,
+                expires_at,
+            };
+            let previous = serde_json::to_string(&previous).expect("secret key serializes");
+            self.metadata.set(&previous_record, &previous).await?;
+        }
+
+        let secret_key = generate_secret_key();
+        self.metadata.set(&secret_key_record, &secret_key).await?;
+
+        Ok(Some(AccessKey {
+            access_key: access_key.to_string(),
+            secret_key,
+        }))
+    }
+
+    pub async fn delete_key(&self, access_key: &str) -> Result<(), MetadataError> {
+        self.metadata
+            .delete(&format!("{}{}", SECRET_KEY_PREFIX, access_key))
+            .await?;
+        self.metadata
+            .delete(&format!("{}{}", PREVIOUS_SECRET_KEY_PREFIX, access_key))
+            .await?;
+        self.metadata
+            .delete(&format!("{}{}", KEY_NAMESPACE_PREFIX, access_key))
+            .await
+    }
+
+    pub async fn quota(&self, namespace: &str) -> Result<Quota, MetadataError> {
+        let quota = self
+            .metadata
+            .get(&format!("{}{}", QUOTA_PREFIX, namespace))
+            .await?;
+
+        Ok(quota
+            .and_then(|x| serde_json::from_str(&x).ok())
+            .unwrap_or_default())
+    }
+
+    pub async fn set_quota(&self, namespace: &str, quota: &Quota) -> Result<(), MetadataError> {
+        let quota = serde_json::to_string(quota).expect("quota serializes");
+        self.metadata
+            .set(&format!("{}{}", QUOTA_PREFIX, namespace), &quota)
+            .await
+    }
+
+    pub async fn delete_quota(&self, namespace: &str) -> Result<(), MetadataError> {
+        self.metadata
+            .delete(&format!("{}{}", QUOTA_PREFIX, namespace))
+            .await
+    }
+
+    pub async fn limits(&self, namespace: &str) -> Result<NamespaceLimits, MetadataError> {
+        let limits = self
+            .metadata
+            .get(&format!("{}{}", LIMITS_PREFIX, namespace))
+            .await?;
+
+        Ok(limits
+            .and_then(|x| serde_json::from_str(&x).ok())
+            .unwrap_or_default())
+    }
+
+    pub async fn set_limits(
+        &self,
+        namespace: &str,
+        limits: &NamespaceLimits,
+    ) -> Result<(), MetadataError> {
+        let limits = serde_json::to_string(limits).expect("limits serialize");
+        self.metadata
+            .set(&format!("{}{}", LIMITS_PREFIX, namespace), &limits)
+            .await
+    }
+
+    pub async fn delete_limits(&self, namespace: &str) -> Result<(), MetadataError> {
+        self.metadata
+            .delete(&format!("{}{}", LIMITS_PREFIX, namespace))
+            .await
+    }
+
+    pub async fn bucket_quota(
+        &self,
+        namespace: &str,
+        bucket: &str,
+    ) -> Result<Quota, MetadataError> {
+        let quota = self
+            .metadata
+            .get(&bucket_quota_key(namespace, bucket))
+            .await?;
+
+        Ok(quota
+            .and_then(|x| serde_json::from_str(&x).ok())
+            .unwrap_or_default())
+    }
+
+    pub async fn set_bucket_quota(
+        &self,
+        namespace: &str,
+        bucket: &str,
+        quota: &Quota,
+    ) -> Result<(), MetadataError> {
+        let quota = serde_json::to_string(quota).expect("quota serializes");
+        self.metadata
+            .set(&bucket_quota_key(namespace, bucket), &quota)
+            .await
+    }
+
+    pub async fn delete_bucket_quota(
+        &self,
+        namespace: &str,
+        bucket: &str,
+    ) -> Result<(), MetadataError> {
+        self.metadata
+            .delete(&bucket_quota_key(namespace, bucket))
+            .await
+    }
+
+    /// Frozen buckets are read-only, writes and deletes are denied.
+    pub async fn is_frozen(&self, namespace: &str, bucket: &str) -> Result<bool, MetadataError> {
+        Ok(self
+            .metadata
+            .get(&format!("{}{}::{}", FROZEN_PREFIX, namespace, bucket))
+            .await?
+            .is_some())
+    }
+
+    pub async fn set_frozen(
+        &self,
+        namespace: &str,
+        bucket: &str,
+        frozen: bool,
+    ) -> Result<(), MetadataError> {
+        let key = format!("{}{}::{}", FROZEN_PREFIX, namespace, bucket);
+        if frozen {
+            self.metadata.set(&key, "1").await
+        } else {
+            self.metadata.delete(&key).await
+        }
+    }
+
+    /// Objects of public buckets can be downloaded without a signature.
+    pub async fn is_public(&self, namespace: &str, bucket: &str) -> Result<bool, MetadataError> {
+        Ok(self
+            .metadata
+            .get(&format!("{}{}::{}", PUBLIC_PREFIX, namespace, bucket))
+            .await?
+            .is_some())
+    }
+
+    pub async fn set_public(
+        &self,
+        namespace: &str,
+        bucket: &str,
+        public: bool,
+    ) -> Result<(), MetadataError> {
+        let key = format!("{}{}::{}", PUBLIC_PREFIX, namespace, bucket);
+        if public {
+            self.metadata.set(&key, "1").await
+        } else {
+            self.metadata.delete(&key).await
+        }
+    }
+
+    /// The `LocationConstraint` the bucket was created with, `None` accepts every region.
+    pub async fn region(
+        &self,
+        namespace: &str,
+        bucket: &str,
+    ) -> Result<Option<String>, MetadataError> {
+        self.metadata
+            .get(&format!("{}{}::{}", REGION_PREFIX, namespace, bucket))
+            .await
+    }
+
+    pub async fn set_region(
+        &self,
+        namespace: &str,
+        bucket: &str,
+        region: Option<&str>,
+    ) -> Result<(), MetadataError> {
+        let key = format!("{}{}::{}", REGION_PREFIX, namespace, bucket);
+        match region {
+            Some(region) => self.metadata.set(&key, region).await,
+            None => self.metadata.delete(&key).await,
+        }
+    }
+
+    /// The bucket notification configuration, empty when none is set.
+    pub async fn notification_configuration(
+        &self,
+        namespace: &str,
+        bucket: &str,
+    ) -> Result<NotificationConfiguration, MetadataError> {
+        let configuration = self
+            .metadata
+            .get(&format!("{}{}::{}", NOTIFICATION_PREFIX, namespace, bucket))
+            .await?;
+
+        Ok(configuration
+            .and_then(|x| serde_json::from_str(&x).ok())
+            .unwrap_or_default())
+    }
+
+    /// An empty configuration turns the notifications of the bucket off.
+    pub async fn set_notification_configuration(
+        &self,
+        namespace: &str,
+        bucket: &str,
+        configuration: &NotificationConfiguration,
+    ) -> Result<(), MetadataError> {
+        let key = format!("{}{}::{}", NOTIFICATION_PREFIX, namespace, bucket);
+        if configuration.queue_configurations.is_empty() {
+            return self.metadata.delete(&key).await;
+        }
+
+        let configuration =
+            serde_json::to_string(configuration).expect("notification configuration serializes");
+        self.metadata.set(&key, &configuration).await
+    }
+
+    /// The bucket replication configuration, empty when none is set.
+    pub async fn replication_configuration(
+        &self,
+        namespace: &str,
+        bucket: &str,
+    ) -> Result<ReplicationConfiguration, MetadataError> {
+        let configuration = self
+            .metadata
+            .get(&format!("{}{}::{}", REPLICATION_PREFIX, namespace, bucket))
+            .await?;
+
+        Ok(configuration
+            .and_then(|x| serde_json::from_str(&x).ok())
+            .unwrap_or_default())
+    }
+
+    pub async fn set_replication_configuration(
+        &self,
+        namespace: &str,
+        bucket: &str,
+        configuration: &ReplicationConfiguration,
+    ) -> Result<(), MetadataError> {
+        let configuration =
+            serde_json::to_string(configuration).expect("replication configuration serializes");
+        self.metadata
+            .set(
+                &format!("{}{}::{}", REPLICATION_PREFIX, namespace, bucket),
+                &configuration,
+            )
+            .await
+    }
+
+    pub async fn delete_replication_configuration(
+        &self,
+        namespace: &str,
+        bucket: &str,
+    ) -> Result<(), MetadataError> {
+        self.metadata
+            .delete(&format!("{}{}::{}", REPLICATION_PREFIX, namespace, bucket))
+            .await
+    }
+
+    /// The inventory configurations of the bucket, ordered by id.
+    pub async fn inventory_configurations(
+        &self,
+        namespace: &str,
+        bucket: &str,
+    ) -> Result<Vec<InventoryConfiguration>, MetadataError> {
+        let configurations = self
+            .metadata
+            .get(&format!("{}{}::{}", INVENTORY_PREFIX, namespace, bucket))
+            .await?;
+
+        Ok(configurations
+            .and_then(|x| serde_json::from_str(&x).ok())
+            .unwrap_or_default())
+    }
+
+    /// Replaces the inventory configurations, the record is removed when there are none left.
+    pub async fn set_inventory_configurations(
+        &self,
+        namespace: &str,
+        bucket: &str,
+        configurations: &[InventoryConfiguration],
+    ) -> Result<(), MetadataError> {
+        let key = format!("{}{}::{}", INVENTORY_PREFIX, namespace, bucket);
+        if configurations.is_empty() {
+            return self.metadata.delete(&key).await;
+        }
+
+        let configurations =
+            serde_json::to_string(configurations).expect("inventory configuration serializes");
+        self.metadata.set(&key, &configurations).await
+    }
+
+    /// The tags of the bucket, `None` when it has none.
+    pub async fn bucket_tagging(
+        &self,
+        namespace: &str,
+        bucket: &str,
+    ) -> Result<Option<Tagging>, MetadataError> {
+        let tagging = self
+            .metadata
+            .get(&format!("{}{}::{}", TAGGING_PREFIX, namespace, bucket))
+            .await?;
+
+        Ok(tagging.and_then(|x| serde_json::from_str(&x).ok()))
+    }
+
+    pub async fn set_bucket_tagging(
+        &self,
+        namespace: &str,
+        bucket: &str,
+        tagging: &Tagging,
+    ) -> Result<(), MetadataError> {
+        let tagging = serde_json::to_string(tagging).expect("tagging serializes");
+        self.metadata
+            .set(
+                &format!("{}{}::{}", TAGGING_PREFIX, namespace, bucket),
+                &tagging,
+            )
+            .await
+    }
+
+    pub async fn delete_bucket_tagging(
+        &self,
+        namespace: &str,
+        bucket: &str,
+    ) -> Result<(), MetadataError> {
+        self.metadata
+            .delete(&format!("{}{}::{}", TAGGING_PREFIX, namespace, bucket))
+            .await
+    }
+
+    /// `None` when versioning was never configured for the bucket.
+    pub async fn bucket_versioning(
+        &self,
+        namespace: &str,
+        bucket: &str,
+    ) -> Result<Option<VersioningStatus>, MetadataError> {
+        let status = self
+            .metadata
+            .get(&format!("{}{}::{}", VERSIONING_PREFIX, namespace, bucket))
+            .await?;
+
+        Ok(status.as_deref().and_then(VersioningStatus::parse))
+    }
+
+    pub async fn set_bucket_versioning(
+        &self,
+        namespace: &str,
+        bucket: &str,
+        status: VersioningStatus,
+    ) -> Result<(), MetadataError> {
+        self.metadata
+            .set(
+                &format!("{}{}::{}", VERSIONING_PREFIX, namespace, bucket),
+                status.as_str(),
+            )
+            .await
+    }
+
+    /// `None` when object lock was never enabled for the bucket, once enabled it stays on.
+    pub async fn object_lock_configuration(
+        &self,
+        namespace: &str,
+        bucket: &str,
+    ) -> Result<Option<ObjectLockConfiguration>, MetadataError> {
+        let configuration = self
+            .metadata
+            .get(&format!(
+                "{}{}::{}",
+                OBJECT_LOCK_CONFIGURATION_PREFIX, namespace, bucket
+            ))
+            .await?;
+
+        Ok(configuration.and_then(|x| serde_json::from_str(&x).ok()))
+    }
+
+    pub async fn set_object_lock_configuration(
+        &self,
+        namespace: &str,
+        bucket: &str,
+        configuration: &ObjectLockConfiguration,
+    ) -> Result<(), MetadataError> {
+        let configuration =
+            serde_json::to_string(configuration).expect("object lock configuration serializes");
+        self.metadata
+            .set(
+                &format!(
+                    "{}{}::{}",
+                    OBJECT_LOCK_CONFIGURATION_PREFIX, namespace, bucket
+                ),
+                &configuration,
+            )
+            .await
+    }
+
+    pub async fn lifecycle_configuration(
+        &self,
+        namespace: &str,
+        bucket: &str,
+    ) -> Result<Option<LifecycleConfiguration>, MetadataError> {
+        let configuration = self
+            .metadata
+            .get(&format!("{}{}::{}", LIFECYCLE_PREFIX, namespace, bucket))
+            .await?;
+
+        Ok(configuration.and_then(|x| serde_json::from_str(&x).ok()))
+    }
+
+    pub async fn set_lifecycle_configuration(
+        &self,
+        namespace: &str,
+        bucket: &str,
+        configuration: &LifecycleConfiguration,
+    ) -> Result<(), MetadataError> {
+        let configuration =
+            serde_json::to_string(configuration).expect("lifecycle configuration serializes");
+        self.metadata
+            .set(
+                &format!("{}{}::{}", LIFECYCLE_PREFIX, namespace, bucket),
+                &configuration,
+            )
+            .await
+    }
+
+    pub async fn delete_lifecycle_configuration(
+        &self,
+        namespace: &str,
+        bucket: &str,
+    ) -> Result<(), MetadataError> {
+        self.metadata
+            .delete(&format!("{}{}::{}", LIFECYCLE_PREFIX, namespace, bucket))
+            .await
+    }
+
+    pub async fn cors_configuration(
+        &self,
+        namespace: &str,
+        bucket: &str,
+    ) -> Result<Option<CorsConfiguration>, MetadataError> {
+        let configuration = self
+            .metadata
+            .get(&format!("{}{}::{}", CORS_PREFIX, namespace, bucket))
+            .await?;
+
+        Ok(configuration.and_then(|x| serde_json::from_str(&x).ok()))
+    }
+
+    pub async fn set_cors_configuration(
+        &self,
+        namespace: &str,
+        bucket: &str,
+        configuration: &CorsConfiguration,
+    ) -> Result<(), MetadataError> {
+        let configuration =
+            serde_json::to_string(configuration).expect("cors configuration serializes");
+        self.metadata
+            .set(
+                &format!("{}{}::{}", CORS_PREFIX, namespace, bucket),
+                &configuration,
+            )
+            .await?;
+
+        let mut namespaces = self.cors_namespaces(bucket).await?;
+        if !namespaces.iter().any(|x| x == namespace) {
+            namespaces.push(namespace.to_string());
+            self.set_cors_namespaces(bucket, &namespaces).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn delete_cors_configuration(
+        &self,
+        namespace: &str,
+        bucket: &str,
+    ) -> Result<(), MetadataError> {
+        self.metadata
+            .delete(&format!("{}{}::{}", CORS_PREFIX, namespace, bucket))
+            .await?;
+
+        let mut namespaces = self.cors_namespaces(bucket).await?;
+        if namespaces.iter().any(|x| x == namespace) {
+            namespaces.retain(|x| x != namespace);
+            self.set_cors_namespaces(bucket, &namespaces).await?;
+        }
+        Ok(())
+    }
+
+    /// The namespaces that have a CORS configuration for a bucket with this name. Deleted
+    /// namespaces may still be listed, they have no configuration anymore.
+    pub async fn cors_namespaces(&self, bucket: &str) -> Result<Vec<String>, MetadataError> {
+        let namespaces = self
+            .metadata
+            .get(&format!("{}{}", CORS_BUCKET_PREFIX, bucket))
+            .await?;
+
+        Ok(namespaces
+            .and_then(|x| serde_json::from_str(&x).ok())
+            .unwrap_or_default())
+    }
+
+    async fn set_cors_namespaces(
+        &self,
+        bucket: &str,
+        namespaces: &[String],
+    ) -> Result<(), MetadataError> {
+        let key = format!("{}{}", CORS_BUCKET_PREFIX, bucket);
+        if namespaces.is_empty() {
+            self.metadata.delete(&key).await
+        } else {
+            let namespaces = serde_json::to_string(namespaces).expect("namespaces serialize");
+            self.metadata.set(&key, &namespaces).await
+        }
+    }
+
+    /// Removes the quota, freeze and other records of a deleted bucket.
+    pub async fn delete_bucket_records(
+        &self,
+        namespace: &str,
+        bucket: &str,
+    ) -> Result<(), MetadataError> {
+        self.delete_cors_configuration(namespace, bucket).await?;
+        for prefix in BUCKET_RECORD_PREFIXES {
+            self.metadata
+                .delete(&format!("{}{}::{}", prefix, namespace, bucket))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// The policy document of the namespace, stored as is.
+    pub async fn policy(&self, namespace: &str) -> Result<Option<String>, MetadataError> {
+        self.metadata
+            .get(&format!("{}{}", POLICY_PREFIX, namespace))
+            .await
+    }
+
+    pub async fn set_policy(&self, namespace: &str, policy: &str) -> Result<(), MetadataError> {
+        self.metadata
+            .set(&format!("{}{}", POLICY_PREFIX, namespace), policy)
+            .await
+    }
+
+    pub async fn delete_policy(&self, namespace: &str) -> Result<(), MetadataError> {
+        self.metadata
+            .delete(&format!("{}{}", POLICY_PREFIX, namespace))
+            .await
+    }
+
+    /// The policy document of the bucket, stored as is.
+    pub async fn bucket_policy(
+        &self,
+        namespace: &str,
+        bucket: &str,
+    ) -> Result<Option<String>, MetadataError> {
+        self.metadata
+            .get(&format!(
+                "{}{}::{}",
+                BUCKET_POLICY_PREFIX, namespace, bucket
+            ))
+            .await
+    }
+
+    pub async fn set_bucket_policy(
+        &self,
+        namespace: &str,
+        bucket: &str,
+        policy: &str,
+    ) -> Result<(), MetadataError> {
+        self.metadata
+            .set(
+                &format!("{}{}::{}", BUCKET_POLICY_PREFIX, namespace, bucket),
+                policy,
+            )
+            .await
+    }
+
+    pub async fn delete_bucket_policy(
+        &self,
+        namespace: &str,
+        bucket: &str,
+    ) -> Result<(), MetadataError> {
+        self.metadata
+            .delete(&format!(
+                "{}{}::{}",
+                BUCKET_POLICY_PREFIX, namespace, bucket
+            ))
+            .await
+    }
+
+    /// The ACL set with `PutBucketAcl`, it is separate from the public flag of the admin api.
+    pub async fn bucket_acl(
+        &self,
+        namespace: &str,
+        bucket: &str,
+    ) -> Result<CannedAcl, MetadataError> {
+        let acl = self
+            .metadata
+            .get(&format!("{}{}::{}", BUCKET_ACL_PREFIX, namespace, bucket))
+            .await?;
+        Ok(match acl {
+            Some(_) => CannedAcl::PublicRead,
+            None => CannedAcl::Private,
+        })
+    }
+
+    pub async fn set_bucket_acl(
+        &self,
+        namespace: &str,
+        bucket: &str,
+        acl: CannedAcl,
+    ) -> Result<(), MetadataError> {
+        let key = format!("{}{}::{}", BUCKET_ACL_PREFIX, namespace, bucket);
+        if acl.is_public() {
+            self.metadata.set(&key, acl.as_str()).await
+        } else {
+            self.metadata.delete(&key).await
+        }
+    }
+}
+
+fn bucket_quota_key(namespace: &str, bucket: &str) -> String {
+    format!("{}{}::{}", BUCKET_QUOTA_PREFIX, namespace, bucket)
+}
+
+#[tokio::test]
+async fn namespace_keys_are_created_and_deleted() {
+    let metadata = MetadataStore::memory();
+    let namespaces = Namespaces::new(&metadata);
+
+    assert!(namespaces.create("tenant").await.unwrap());
+    assert!(!namespaces.create("tenant").await.unwrap());
+    assert_eq!(namespaces.list().await.unwrap(), vec!["tenant"]);
+
+    let key = namespaces.create_key("tenant").await.unwrap();
+    assert_eq!(key.access_key.len(), 20);
+
+    let quota = Quota {
+        max_bytes: Some(10),
+        max_objects: None,
+    };
+    namespaces
+        .set_bucket_quota("tenant", "photos", &quota)
+        .await
+        .unwrap();
+    assert_eq!(
+        namespaces.bucket_quota("tenant", "photos").await.unwrap(),
+        quota
+    );
+    assert_eq!(
+        namespaces.keys("tenant").await.unwrap(),
+        vec![key.access_key.clone()]
+    );
+
+    // keys from before namespaces existed are their own namespace
+    metadata.set("secret_key::OLDKEY", "secret").await.unwrap();
+    assert_eq!(namespaces.keys("OLDKEY").await.unwrap(), vec!["OLDKEY"]);
+
+    assert_eq!(
+        namespaces.delete("tenant").await.unwrap(),
+        vec![key.access_key.clone()]
+    );
+    assert!(namespaces.list().await.unwrap().is_empty());
+    assert_eq!(
+        namespaces.bucket_quota("tenant", "photos").await.unwrap(),
+        Quota::default()
+    );
+    assert_eq!(
+        metadata
+            .get(&format!("secret_key::{}", key.access_key))
+            .await
+            .unwrap(),
+        None
+    );
+}
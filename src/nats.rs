@@ -0,0 +1,137 @@
+use crate::notifications::{EventFormat, Notification, NotificationKind};
+use serde::Deserialize;
+
+/// Publishes object writes and deletes to a nats subject, needs the `nats` feature.
+#[derive(Debug, Clone, Deserialize)]
+pub struct NatsConfig {
+    /// like `nats://127.0.0.1:4222`
+    pub url: String,
+    /// `{namespace}`, `{bucket}` and `{event}` are filled in per event
+    #[serde(default = "default_subject")]
+    pub subject: String,
+    /// waits for the acknowledgement of a JetStream stream that captures the subject
+    #[serde(default)]
+    pub jetstream: bool,
+    #[serde(default)]
+    pub format: EventFormat,
+}
+
+fn default_subject() -> String {
+    String::from("s3.events.{namespace}.{bucket}")
+}
+
+/// Fills in the subject template, characters with a meaning in subjects are replaced.
+#[cfg_attr(not(feature = "nats"), allow(dead_code))]
+fn subject(template: &str, notification: &Notification) -> String {
+    let token = |value: &str| value.replace(['.', '*', '>', ' '], "_");
+    let event = match notification.kind {
+        NotificationKind::Put => "put",
+        NotificationKind::Delete => "delete",
+    };
+
+    template
+        .replace("{namespace}", &token(&notification.event.namespace))
+        .replace("{bucket}", &token(&notification.event.bucket))
+        .replace("{event}", event)
+}
+
+#[cfg(feature = "nats")]
+pub use sink::NatsSink;
+
+#[cfg(feature = "nats")]
+mod sink {
+    use super::{subject, NatsConfig};
+    use crate::notifications::{Notification, NotificationSink};
+    use anyhow::Context;
+    use async_nats::{Client, HeaderMap};
+    use async_trait::async_trait;
+    use tokio::sync::OnceCell;
+
+    /// Connects on the first event, so the proxy starts while the server is unreachable.
+    pub struct NatsSink {
+        config: NatsConfig,
+        client: OnceCell<Client>,
+    }
+
+    impl NatsSink {
+        pub fn new(config: NatsConfig) -> NatsSink {
+            NatsSink {
+                config,
+                client: OnceCell::new(),
+            }
+        }
+
+        async fn client(&self) -> anyhow::Result<&Client> {
+            self.client
+                .get_or_try_init(|| async {
+                    async_nats::connect(&self.config.url)
+                        .await
+                        .with_context(|| format!("unable to connect to {}", self.config.url))
+                })
+                .await
+        }
+    }
+
+    #[async_trait]
+    impl NotificationSink for NatsSink {
+        fn name(&self) -> &'static str {
+            "nats"
+        }
+
+        async fn publish(&self, notification: &Notification) -> anyhow::Result<()> {
+            let client = self.client().await?;
+            let subject = subject(&self.config.subject, notification);
+            let payload = serde_json::to_vec(&notification.render(self.config.format))?;
+            let mut headers = HeaderMap::new();
+            headers.insert("Content-Type", "application/json");
+
+            if self.config.jetstream {
+                async_nats::jetstream::new(client.clone())
+                    .publish_with_headers(subject, headers, payload.into())
+                    .await?
+                    .await?;
+            } else {
+                client
+                    .publish_with_headers(subject, headers, payload.into())
+                    .await?;
+            }
+
+            Ok(())
+        }
+    }
+}
+
+/// The event hook for the `S3_PROXY__NATS__*` settings.
+pub fn start(config: &NatsConfig) -> anyhow::Result<crate::notifications::Publisher> {
+    #[cfg(feature = "nats")]
+    return Ok(crate::notifications::Publisher::start(NatsSink::new(
+        config.clone(),
+    )));
+
+    #[cfg(not(feature = "nats"))]
+    {
+        let _ = config;
+        anyhow::bail!("nats is configured but s3-proxy is compiled without the `nats` feature")
+    }
+}
+
+#[test]
+fn subjects_are_filled_in() {
+    use crate::events::{ObjectEvent, ObjectMetadata};
+
+    let notification = Notification::new(
+        NotificationKind::Delete,
+        &ObjectEvent {
+            namespace: String::from("tenant"),
+            bucket: String::from("my.photos"),
+            key: String::from("a.jpg"),
+            metadata: ObjectMetadata::default(),
+        },
+    );
+
+    assert_eq!(
+        subject(&default_subject(), &notification),
+        "s3.events.tenant.my_photos"
+    );
+    assert_eq!(subject("events.{event}", &notification), "events.delete");
+}
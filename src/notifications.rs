@@ -0,0 +1,403 @@
+use crate::events::{ObjectEvent, ObjectEventHook};
+use async_trait::async_trait;
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tokio::sync::mpsc;
+
+const CHANNEL_SIZE: usize = 1024;
+
+/// The region in the `s3` event records, the proxy itself has none.
+const EVENT_REGION: &str = "us-east-1";
+
+/// The `configurationId` of events that are not sent for a bucket notification configuration.
+const DEFAULT_CONFIGURATION_ID: &str = "s3-proxy";
+
+/// How published events are serialized.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventFormat {
+    /// one flat json object per event
+    #[default]
+    Json,
+    /// the S3 event notification document, for consumers written against AWS
+    S3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NotificationKind {
+    Put,
+    Delete,
+}
+
+impl NotificationKind {
+    /// The `eventName` of S3 event notifications.
+    pub fn s3_event_name(&self) -> &'static str {
+        match self {
+            NotificationKind::Put => "ObjectCreated:Put",
+            NotificationKind::Delete => "ObjectRemoved:Delete",
+        }
+    }
+}
+
+/// An object event on its way to a sink.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub kind: NotificationKind,
+    pub event: ObjectEvent,
+    pub time: OffsetDateTime,
+}
+
+impl Notification {
+    pub fn new(kind: NotificationKind, event: &ObjectEvent) -> Notification {
+        Notification {
+            kind,
+            event: event.clone(),
+            time: OffsetDateTime::now_utc(),
+        }
+    }
+
+    pub fn render(&self, format: EventFormat) -> serde_json::Value {
+        let event = &self.event;
+        let time = self.time.format(&Rfc3339).unwrap_or_default();
+
+        match format {
+            EventFormat::Json => json!({
+                "event": match self.kind {
+                    NotificationKind::Put => "put",
+                    NotificationKind::Delete => "delete",
+                },
+                "time": time,
+                "namespace": event.namespace,
+                "bucket": event.bucket,
+                "key": event.key,
+                "content_type": event.metadata.content_type,
+                "content_length": event.metadata.content_length,
+                "etag": event.metadata.etag,
+            }),
+            EventFormat::S3 => self.s3_record(DEFAULT_CONFIGURATION_ID),
+        }
+    }
+
+    /// The S3 event notification document, as AWS sends it for the given configuration.
+    pub fn s3_record(&self, configuration_id: &str) -> serde_json::Value {
+        let event = &self.event;
+        let time = self.time.format(&Rfc3339).unwrap_or_default();
+
+        json!({
+            "Records": [{
+                "eventVersion": "2.1",
+                "eventSource": "aws:s3",
+                "awsRegion": EVENT_REGION,
+                "eventTime": time,
+                "eventName": self.kind.s3_event_name(),
+                "userIdentity": { "principalId": event.namespace },
+                "requestParameters": {},
+                "responseElements": {},
+                "s3": {
+                    "s3SchemaVersion": "1.0",
+                    "configurationId": configuration_id,
+                    "bucket": {
+                        "name": event.bucket,
+                        "ownerIdentity": { "principalId": event.namespace },
+                        "arn": format!("arn:aws:s3:::{}", event.bucket),
+                    },
+                    "object": {
+                        "key": encode_key(&event.key),
+                        "size": event.metadata.content_length,
+                        "eTag": event.metadata.etag.as_deref().unwrap_or_default().trim_matches('"'),
+                        "sequencer": format!("{:016X}", self.time.unix_timestamp_nanos()),
+                    },
+                },
+            }],
+        })
+    }
+}
+
+/// S3 event records carry the key form encoded, with `+` for spaces.
+fn encode_key(key: &str) -> String {
+    let mut encoded = String::with_capacity(key.len());
+    for byte in key.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'*' | b'/' => {
+                encoded.push(byte as char)
+            }
+            b' ' => encoded.push('+'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// The `NotificationConfiguration` of a bucket, set with `PutBucketNotificationConfiguration`.
+///
+/// Only queue targets are delivered, topics and lambda functions are rejected.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct NotificationConfiguration {
+    #[serde(default, rename = "QueueConfiguration")]
+    pub queue_configurations: Vec<QueueConfiguration>,
+    #[serde(default, rename = "TopicConfiguration", skip_serializing)]
+    pub topic_configurations: Vec<IgnoredAny>,
+    #[serde(default, rename = "CloudFunctionConfiguration", skip_serializing)]
+    pub lambda_function_configurations: Vec<IgnoredAny>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct QueueConfiguration {
+    #[serde(default)]
+    pub id: Option<String>,
+    /// the queue arn, or the url of an SQS compatible queue
+    pub queue: String,
+    /// like `s3:ObjectCreated:*` or `s3:ObjectRemoved:Delete`
+    #[serde(default, rename = "Event")]
+    pub events: Vec<String>,
+    #[serde(default)]
+    pub filter: Option<NotificationFilter>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NotificationFilter {
+    #[serde(rename = "S3Key")]
+    pub key: KeyFilter,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct KeyFilter {
+    #[serde(default, rename = "FilterRule")]
+    pub rules: Vec<FilterRule>,
+}
+
+/// A `prefix` or `suffix` the key has to match.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct FilterRule {
+    pub name: String,
+    pub value: String,
+}
+
+impl NotificationConfiguration {
+    /// Checks the events and filter rules, the error is the message for the client.
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.topic_configurations.is_empty() || !self.lambda_function_configurations.is_empty()
+        {
+            return Err(String::from("only queue configurations are supported"));
+        }
+
+        for queue in &self.queue_configurations {
+            if queue.events.is_empty() {
+                return Err(format!("{} has no events", queue.queue));
+            }
+            if let Some(event) = queue.events.iter().find(|x| !is_known_event(x)) {
+                return Err(format!("unsupported event {}", event));
+            }
+
+            let rules = queue.filter.iter().flat_map(|x| &x.key.rules);
+            for rule in rules {
+                if !rule.name.eq_ignore_ascii_case("prefix")
+                    && !rule.name.eq_ignore_ascii_case("suffix")
+                {
+                    return Err(format!("unsupported filter rule {}", rule.name));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl QueueConfiguration {
+    /// Whether the event of `kind` on `key` is sent to this queue.
+    pub fn matches(&self, kind: NotificationKind, key: &str) -> bool {
+        let event_name = kind.s3_event_name();
+        let category = event_name.split(':').next().unwrap_or_default();
+        let matches_event = self.events.iter().any(|event| {
+            let event = event.strip_prefix("s3:").unwrap_or(event);
+            event == event_name || event.strip_suffix(":*") == Some(category)
+        });
+
+        matches_event
+            && self.filter.iter().flat_map(|x| &x.key.rules).all(|rule| {
+                if rule.name.eq_ignore_ascii_case("prefix") {
+                    key.starts_with(&rule.value)
+                } else {
+                    key.ends_with(&rule.value)
+                }
+            })
+    }
+}
+
+fn is_known_event(event: &str) -> bool {
+    matches!(
+        event,
+        "s3:ObjectCreated:*"
+            | "s3:ObjectCreated:Put"
+            | "s3:ObjectRemoved:*"
+            | "s3:ObjectRemoved:Delete"
+    )
+}
+
+/// Somewhere object events are published to, like a message broker or a queue.
+#[async_trait]
+pub trait NotificationSink: Send + Sync + 'static {
+    /// Used in the logs when publishing fails.
+    fn name(&self) -> &'static str;
+
+    async fn publish(&self, notification: &Notification) -> anyhow::Result<()>;
+}
+
+/// An event hook that hands writes and deletes to a sink.
+///
+/// Events are published from a background task in the order they happened, when the sink can
+/// not keep up new events are dropped and logged instead of slowing down requests.
+pub struct Publisher {
+    name: &'static str,
+    sender: mpsc::Sender<Notification>,
+}
+
+impl Publisher {
+    pub fn start(sink: impl NotificationSink) -> Publisher {
+        let name = sink.name();
+        let (sender, receiver) = mpsc::channel(CHANNEL_SIZE);
+        tokio::spawn(publish_notifications(sink, receiver));
+
+        Publisher { name, sender }
+    }
+
+    fn send(&self, notification: Notification) {
+        if self.sender.try_send(notification).is_err() {
+            tracing::warn!(
+                "{} notification queue is full, dropping an event",
+                self.name
+            );
+        }
+    }
+}
+
+async fn publish_notifications(
+    sink: impl NotificationSink,
+    mut receiver: mpsc::Receiver<Notification>,
+) {
+    while let Some(notification) = receiver.recv().await {
+        if let Err(error) = sink.publish(&notification).await {
+            tracing::error!(
+                "unable to publish the {} event of {}/{} to {}: {:#}",
+                notification.kind.s3_event_name(),
+                notification.event.bucket,
+                notification.event.key,
+                sink.name(),
+                error
+            );
+        }
+    }
+}
+
+#[async_trait]
+impl ObjectEventHook for Publisher {
+    async fn on_put(&self, event: &ObjectEvent) {
+        self.send(Notification::new(NotificationKind::Put, event));
+    }
+
+    async fn on_delete(&self, event: &ObjectEvent) {
+        self.send(Notification::new(NotificationKind::Delete, event));
+    }
+}
+
+#[tokio::test]
+async fn notifications_are_published_in_order() {
+    use crate::events::ObjectMetadata;
+    use std::sync::{Arc, Mutex};
+
+    struct Recorder(Arc<Mutex<Vec<serde_json::Value>>>);
+
+    #[async_trait]
+    impl NotificationSink for Recorder {
+        fn name(&self) -> &'static str {
+            "recorder"
+        }
+
+        async fn publish(&self, notification: &Notification) -> anyhow::Result<()> {
+            self.0
+                .lock()
+                .unwrap()
+                .push(notification.render(EventFormat::S3));
+            Ok(())
+        }
+    }
+
+    let published = Arc::new(Mutex::new(Vec::new()));
+    let publisher = Publisher::start(Recorder(published.clone()));
+    let event = ObjectEvent {
+        namespace: String::from("tenant"),
+        bucket: String::from("photos"),
+        key: String::from("summer 2024/a+b.jpg"),
+        metadata: ObjectMetadata {
+            content_type: None,
+            content_length: 5,
+            etag: None,
+        },
+    };
+    publisher.on_put(&event).await;
+    publisher.on_delete(&event).await;
+    drop(publisher);
+
+    for _ in 0..100 {
+        if published.lock().unwrap().len() == 2 {
+            break;
+        }
+        tokio::task::yield_now().await;
+    }
+    let published = published.lock().unwrap();
+    let record = &published[0]["Records"][0];
+    assert_eq!(record["eventName"], "ObjectCreated:Put");
+    assert_eq!(record["s3"]["object"]["key"], "summer+2024/a%2Bb.jpg");
+    assert_eq!(record["s3"]["object"]["size"], 5);
+    assert_eq!(
+        published[1]["Records"][0]["eventName"],
+        "ObjectRemoved:Delete"
+    );
+}
+
+#[test]
+fn queue_configurations_match_events_and_keys() {
+    let xml = r#"<NotificationConfiguration xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+        <QueueConfiguration>
+            <Id>uploads</Id>
+            <Queue>arn:aws:sqs:us-east-1:000000000000:uploads</Queue>
+            <Event>s3:ObjectCreated:*</Event>
+            <Filter>
+                <S3Key>
+                    <FilterRule><Name>prefix</Name><Value>images/</Value></FilterRule>
+                    <FilterRule><Name>Suffix</Name><Value>.jpg</Value></FilterRule>
+                </S3Key>
+            </Filter>
+        </QueueConfiguration>
+        <QueueConfiguration>
+            <Queue>http://127.0.0.1:9324/000000000000/deletes</Queue>
+            <Event>s3:ObjectRemoved:Delete</Event>
+        </QueueConfiguration>
+    </NotificationConfiguration>"#;
+
+    let configuration: NotificationConfiguration = quick_xml::de::from_str(xml).unwrap();
+    assert_eq!(configuration.validate(), Ok(()));
+
+    let [uploads, deletes] = &configuration.queue_configurations[..] else {
+        panic!("expected two queue configurations");
+    };
+    assert_eq!(uploads.id.as_deref(), Some("uploads"));
+    assert!(uploads.matches(NotificationKind::Put, "images/a.jpg"));
+    assert!(!uploads.matches(NotificationKind::Put, "images/a.png"));
+    assert!(!uploads.matches(NotificationKind::Put, "docs/a.jpg"));
+    assert!(!uploads.matches(NotificationKind::Delete, "images/a.jpg"));
+    assert!(deletes.matches(NotificationKind::Delete, "docs/a.txt"));
+
+    let topics: NotificationConfiguration = quick_xml::de::from_str(
+        "<NotificationConfiguration><TopicConfiguration><Topic>arn</Topic>\
+         <Event>s3:ObjectCreated:*</Event></TopicConfiguration></NotificationConfiguration>",
+    )
+    .unwrap();
+    assert!(topics.validate().is_err());
+}
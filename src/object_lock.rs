@@ -0,0 +1,575 @@
+use crate::error::S3Error;
+use crate::metadata::{MetadataError, MetadataStore};
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+
+/// `object_lock::{namespace}/{bucket}/{key}` holds the retention and legal hold of an object as
+/// json
+pub const OBJECT_LOCK_PREFIX: &str = "object_lock::";
+
+/// `CreateBucket` with `true` creates the bucket with object lock, and versioning, enabled.
+pub static BUCKET_LOCK_ENABLED_HEADER: HeaderName =
+    HeaderName::from_static("x-amz-bucket-object-lock-enabled");
+pub static MODE_HEADER: HeaderName = HeaderName::from_static("x-amz-object-lock-mode");
+pub static RETAIN_UNTIL_DATE_HEADER: HeaderName =
+    HeaderName::from_static("x-amz-object-lock-retain-until-date");
+pub static LEGAL_HOLD_HEADER: HeaderName = HeaderName::from_static("x-amz-object-lock-legal-hold");
+/// Lets deletes, overwrites and retention changes through a `GOVERNANCE` retention.
+pub static BYPASS_GOVERNANCE_HEADER: HeaderName =
+    HeaderName::from_static("x-amz-bypass-governance-retention");
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+/// The longest default retention S3 accepts.
+const MAX_RETENTION_DAYS: i64 = 36500;
+const MAX_RETENTION_YEARS: i64 = 100;
+
+/// `GOVERNANCE` retentions can be lifted with the bypass header, `COMPLIANCE` ones only expire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum RetentionMode {
+    Governance,
+    Compliance,
+}
+
+impl RetentionMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RetentionMode::Governance => "GOVERNANCE",
+            RetentionMode::Compliance => "COMPLIANCE",
+        }
+    }
+
+    pub fn parse(mode: &str) -> Option<RetentionMode> {
+        match mode {
+            "GOVERNANCE" => Some(RetentionMode::Governance),
+            "COMPLIANCE" => Some(RetentionMode::Compliance),
+            _ => None,
+        }
+    }
+}
+
+/// The `<ObjectLockConfiguration>` document of a bucket, stored as json.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ObjectLockConfiguration {
+    pub object_lock_enabled: Option<String>,
+    pub rule: Option<ObjectLockRule>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ObjectLockRule {
+    pub default_retention: DefaultRetention,
+}
+
+/// The retention objects get when they are written without one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct DefaultRetention {
+    pub mode: RetentionMode,
+    pub days: Option<i64>,
+    pub years: Option<i64>,
+}
+
+impl ObjectLockConfiguration {
+    /// The configuration of a bucket created with object lock, without a default retention.
+    pub fn enabled() -> ObjectLockConfiguration {
+        ObjectLockConfiguration {
+            object_lock_enabled: Some(String::from("Enabled")),
+            rule: None,
+        }
+    }
+
+    /// Checks the document of `PutObjectLockConfiguration`, object lock cannot be disabled.
+    pub fn validate(&self) -> Result<(), S3Error> {
+        if self.object_lock_enabled.as_deref() != Some("Enabled") {
+            return Err(S3Error::MalformedXML);
+        }
+        let Some(rule) = &self.rule else {
+            return Ok(());
+        };
+
+        match (rule.default_retention.days, rule.default_retention.years) {
+            (Some(_), Some(_)) | (None, None) => Err(S3Error::MalformedXML),
+            (Some(period), None) | (None, Some(period)) if period <= 0 => {
+                Err(S3Error::InvalidArgument(String::from(
+                    "Default retention period must be a positive integer value",
+                )))
+            }
+            (Some(days), None) if days > MAX_RETENTION_DAYS => {
+                Err(S3Error::InvalidArgument(format!(
+                    "Default retention period is at most {} days",
+                    MAX_RETENTION_DAYS
+                )))
+            }
+            (None, Some(years)) if years > MAX_RETENTION_YEARS => {
+                Err(S3Error::InvalidArgument(format!(
+                    "Default retention period is at most {} years",
+                    MAX_RETENTION_YEARS
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Gives the default retention to a lock written at `now` without a retention of its own.
+    pub fn apply_default(&self, lock: &mut ObjectLock, now: i64) {
+        let Some(rule) = &self.rule else {
+            return;
+        };
+        if lock.retention.is_some() {
+            return;
+        }
+
+        let retention = &rule.default_retention;
+        // S3 counts a year as 365 days as well
+        let days = retention
+            .days
+            .or(retention.years.map(|x| x.saturating_mul(365)));
+        lock.retention = days.map(|days| Retention {
+            mode: retention.mode,
+            // validated periods fit, anything else locks for good instead of wrapping into the past
+            retain_until: days
+                .checked_mul(SECONDS_PER_DAY)
+                .and_then(|x| now.checked_add(x))
+                .unwrap_or(i64::MAX),
+        });
+    }
+}
+
+/// The retention of an object, until the unix timestamp `retain_until`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Retention {
+    pub mode: RetentionMode,
+    pub retain_until: i64,
+}
+
+impl Retention {
+    pub fn is_active(&self, now: i64) -> bool {
+        self.retain_until > now
+    }
+
+    /// `2030-01-01T00:00:00.000Z`
+    pub fn retain_until_date(&self) -> String {
+        chrono::DateTime::from_timestamp(self.retain_until, 0)
+            .unwrap_or_default()
+            .to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+    }
+}
+
+/// The `<Retention>` body of `PutObjectRetention`, without both a `GOVERNANCE` retention is
+/// lifted.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct RetentionDocument {
+    pub mode: Option<String>,
+    pub retain_until_date: Option<String>,
+}
+
+impl RetentionDocument {
+    pub fn retention(&self, now: i64) -> Result<Option<Retention>, S3Error> {
+        match (&self.mode, &self.retain_until_date) {
+            (Some(mode), Some(date)) => retention(mode, date, now).map(Some),
+            (None, None) => Ok(None),
+            _ => Err(S3Error::MalformedXML),
+        }
+    }
+}
+
+/// The `<LegalHold>` body of `PutObjectLegalHold`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct LegalHoldDocument {
+    pub status: String,
+}
+
+impl LegalHoldDocument {
+    pub fn is_on(&self) -> Result<bool, S3Error> {
+        legal_hold(&self.status).ok_or(S3Error::MalformedXML)
+    }
+}
+
+fn legal_hold(status: &str) -> Option<bool> {
+    match status {
+        "ON" => Some(true),
+        "OFF" => Some(false),
+        _ => None,
+    }
+}
+
+fn retention(mode: &str, date: &str, now: i64) -> Result<Retention, S3Error> {
+    let mode = RetentionMode::parse(mode)
+        .ok_or_else(|| S3Error::InvalidArgument(String::from("Unknown wormMode directive.")))?;
+    let retain_until = chrono::DateTime::parse_from_rfc3339(date)
+        .map_err(|_| {
+            S3Error::InvalidArgument(String::from(
+                "The retain until date must be provided in ISO 8601 format",
+            ))
+        })?
+        .timestamp();
+    if retain_until <= now {
+        return Err(S3Error::InvalidArgument(String::from(
+            "The retain until date must be in the future!",
+        )));
+    }
+
+    Ok(Retention { mode, retain_until })
+}
+
+/// The retention and legal hold of an object, objects without either are not stored.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ObjectLock {
+    pub retention: Option<Retention>,
+    #[serde(default)]
+    pub legal_hold: bool,
+}
+
+impl ObjectLock {
+    pub fn is_empty(&self) -> bool {
+        self.retention.is_none() && !self.legal_hold
+    }
+
+    /// Fails when the object may not be deleted or overwritten at `now`.
+    pub fn check(&self, bypass_governance: bool, now: i64) -> Result<(), S3Error> {
+        if self.legal_hold {
+            return Err(S3Error::ObjectLocked);
+        }
+        match self.retention {
+            Some(retention)
+                if retention.is_active(now)
+                    && (retention.mode == RetentionMode::Compliance || !bypass_governance) =>
+            {
+                Err(S3Error::ObjectLocked)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks a change of the retention, an active retention can only be extended or made
+    /// `COMPLIANCE` unless a `GOVERNANCE` one is bypassed.
+    pub fn check_retention_change(
+        &self,
+        retention: Option<&Retention>,
+        bypass_governance: bool,
+        now: i64,
+    ) -> Result<(), S3Error> {
+        let Some(current) = self.retention.filter(|x| x.is_active(now)) else {
+            return Ok(());
+        };
+        let stricter = retention.is_some_and(|x| {
+            x.retain_until >= current.retain_until
+                && (x.mode == current.mode || x.mode == RetentionMode::Compliance)
+        });
+
+        match current.mode {
+            _ if stricter => Ok(()),
+            RetentionMode::Governance if bypass_governance => Ok(()),
+            _ => Err(S3Error::ObjectLocked),
+        }
+    }
+
+    /// The `x-amz-object-lock-*` headers of `GET` and `HEAD`.
+    pub fn headers(&self) -> Vec<(HeaderName, HeaderValue)> {
+        let mut headers = Vec::new();
+        if let Some(retention) = &self.retention {
+            headers.push((
+                MODE_HEADER.clone(),
+                HeaderValue::from_static(retention.mode.as_str()),
+            ));
+            if let Ok(date) = HeaderValue::from_str(&retention.retain_until_date()) {
+                headers.push((RETAIN_UNTIL_DATE_HEADER.clone(), date));
+            }
+        }
+        if self.legal_hold {
+            headers.push((LEGAL_HOLD_HEADER.clone(), HeaderValue::from_static("ON")));
+        }
+        headers
+    }
+}
+
+/// The lock an upload asks for with the `x-amz-object-lock-*` headers.
+pub fn requested(headers: &HeaderMap, now: i64) -> Result<ObjectLock, S3Error> {
+    let header = |name: &HeaderName| {
+        headers
+            .get(name)
+            .map(|x| {
+                x.to_str()
+                    .map_err(|_| S3Error::InvalidArgument(format!("{} is not valid", name)))
+            })
+            .transpose()
+    };
+
+    let retention = match (header(&MODE_HEADER)?, header(&RETAIN_UNTIL_DATE_HEADER)?) {
+        (Some(mode), Some(date)) => Some(retention(mode, date, now)?),
+        (None, None) => None,
+        _ => {
+            return Err(S3Error::InvalidArgument(String::from(
+                "x-amz-object-lock-retain-until-date and x-amz-object-lock-mode must both be \
+                 supplied",
+            )))
+        }
+    };
+    let legal_hold = match header(&LEGAL_HOLD_HEADER)? {
+        Some(status) => legal_hold(status).ok_or_else(|| {
+            S3Error::InvalidArgument(String::from("Legal Hold must be either of 'ON' or 'OFF'"))
+        })?,
+        None => false,
+    };
+
+    Ok(ObjectLock {
+        retention,
+        legal_hold,
+    })
+}
+
+pub fn bypasses_governance(headers: &HeaderMap) -> bool {
+    headers
+        .get(&BYPASS_GOVERNANCE_HEADER)
+        .is_some_and(|x| x.as_bytes().eq_ignore_ascii_case(b"true"))
+}
+
+/// The lock of the object at `path`, empty when it has none.
+pub async fn stored(metadata: &MetadataStore, path: &str) -> Result<ObjectLock, MetadataError> {
+    let lock = metadata
+        .get(&format!("{}{}", OBJECT_LOCK_PREFIX, path))
+        .await?;
+
+    Ok(lock
+        .and_then(|x| serde_json::from_str(&x).ok())
+        .unwrap_or_default())
+}
+
+/// Replaces the lock of the object at `path`, an empty lock removes it.
+pub async fn store(
+    metadata: &MetadataStore,
+    path: &str,
+    lock: &ObjectLock,
+) -> Result<(), MetadataError> {
+    if lock.is_empty() {
+        return remove(metadata, path).await;
+    }
+
+    let lock = serde_json::to_string(lock).expect("object lock serializes");
+    metadata
+        .set(&format!("{}{}", OBJECT_LOCK_PREFIX, path), &lock)
+        .await
+}
+
+pub async fn remove(metadata: &MetadataStore, path: &str) -> Result<(), MetadataError> {
+    metadata
+        .delete(&format!("{}{}", OBJECT_LOCK_PREFIX, path))
+        .await
+}
+
+/// Fails when the object at `path` may not be deleted or overwritten yet.
+pub async fn check_unlocked(
+    metadata: &MetadataStore,
+    path: &str,
+    bypass_governance: bool,
+) -> Result<(), S3Error> {
+    stored(metadata, path)
+        .await?
+        .check(bypass_governance, chrono::Utc::now().timestamp())
+}
+
+/// The objects below `prefix` that may not be deleted or overwritten yet.
+pub async fn locked_below(
+    metadata: &MetadataStore,
+    prefix: &str,
+    bypass_governance: bool,
+) -> Result<Vec<String>, MetadataError> {
+    let keys = metadata
+        .keys(&format!("{}{}", OBJECT_LOCK_PREFIX, prefix))
+        .await?;
+    let locks = metadata.get_many(&keys).await?;
+    let now = chrono::Utc::now().timestamp();
+
+    Ok(keys
+        .iter()
+        .zip(locks)
+        .filter(|(_, lock)| {
+            lock.as_deref()
+                .and_then(|x| serde_json::from_str::<ObjectLock>(x).ok())
+                .is_some_and(|x| x.check(bypass_governance, now).is_err())
+        })
+        .map(|(key, _)| key[OBJECT_LOCK_PREFIX.len()..].to_string())
+        .collect())
+}
+
+#[tokio::test]
+async fn locked_objects_are_found_below_a_prefix() {
+    let metadata = MetadataStore::memory();
+    let governed = ObjectLock {
+        retention: Some(Retention {
+            mode: RetentionMode::Governance,
+            retain_until: chrono::Utc::now().timestamp() + 3600,
+        }),
+        legal_hold: false,
+    };
+    let held = ObjectLock {
+        retention: None,
+        legal_hold: true,
+    };
+    store(&metadata, "tenant/logs/a.log", &governed)
+        .await
+        .unwrap();
+    store(&metadata, "tenant/logs/b.log", &held).await.unwrap();
+    store(&metadata, "tenant/other/c.log", &held).await.unwrap();
+
+    let mut locked = locked_below(&metadata, "tenant/logs/", false)
+        .await
+        .unwrap();
+    locked.sort();
+    assert_eq!(locked, ["tenant/logs/a.log", "tenant/logs/b.log"]);
+    assert_eq!(
+        locked_below(&metadata, "tenant/logs/", true).await.unwrap(),
+        ["tenant/logs/b.log"]
+    );
+}
+
+#[test]
+fn object_lock_configurations_are_parsed() {
+    let xml = r#"<ObjectLockConfiguration xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+       <ObjectLockEnabled>Enabled</ObjectLockEnabled>
+       <Rule>
+          <DefaultRetention>
+             <Mode>COMPLIANCE</Mode>
+             <Days>30</Days>
+          </DefaultRetention>
+       </Rule>
+    </ObjectLockConfiguration>"#;
+    let configuration: ObjectLockConfiguration = quick_xml::de::from_str(xml).unwrap();
+    assert!(configuration.validate().is_ok());
+
+    let mut lock = ObjectLock::default();
+    configuration.apply_default(&mut lock, 1_000);
+    assert_eq!(
+        lock.retention,
+        Some(Retention {
+            mode: RetentionMode::Compliance,
+            retain_until: 1_000 + 30 * SECONDS_PER_DAY,
+        })
+    );
+
+    // a retention of the upload itself is kept
+    let mut lock = ObjectLock {
+        retention: Some(Retention {
+            mode: RetentionMode::Governance,
+            retain_until: 2_000,
+        }),
+        legal_hold: false,
+    };
+    configuration.apply_default(&mut lock, 1_000);
+    assert_eq!(lock.retention.unwrap().retain_until, 2_000);
+
+    let xml = r#"<ObjectLockConfiguration>
+       <ObjectLockEnabled>Enabled</ObjectLockEnabled>
+       <Rule><DefaultRetention><Mode>GOVERNANCE</Mode><Days>0</Days></DefaultRetention></Rule>
+    </ObjectLockConfiguration>"#;
+    let configuration: ObjectLockConfiguration = quick_xml::de::from_str(xml).unwrap();
+    assert!(configuration.validate().is_err());
+
+    let xml = r#"<ObjectLockConfiguration>
+       <ObjectLockEnabled>Enabled</ObjectLockEnabled>
+       <Rule><DefaultRetention><Mode>GOVERNANCE</Mode><Years>101</Years></DefaultRetention></Rule>
+    </ObjectLockConfiguration>"#;
+    let configuration: ObjectLockConfiguration = quick_xml::de::from_str(xml).unwrap();
+    assert!(configuration.validate().is_err());
+    let xml = r#"<ObjectLockConfiguration>
+       <ObjectLockEnabled>Enabled</ObjectLockEnabled>
+       <Rule>
+          <DefaultRetention><Mode>GOVERNANCE</Mode><Days>9223372036854775807</Days></DefaultRetention>
+       </Rule>
+    </ObjectLockConfiguration>"#;
+    let configuration: ObjectLockConfiguration = quick_xml::de::from_str(xml).unwrap();
+    assert!(configuration.validate().is_err());
+    let mut lock = ObjectLock::default();
+    configuration.apply_default(&mut lock, 1_000);
+    assert_eq!(lock.retention.unwrap().retain_until, i64::MAX);
+
+    assert!(ObjectLockConfiguration::default().validate().is_err());
+    assert!(ObjectLockConfiguration::enabled().validate().is_ok());
+}
+
+#[test]
+fn locked_objects_are_protected() {
+    let now = 1_000;
+    let governance = ObjectLock {
+        retention: Some(Retention {
+            mode: RetentionMode::Governance,
+            retain_until: 2_000,
+        }),
+        legal_hold: false,
+    };
+    assert!(governance.check(false, now).is_err());
+    assert!(governance.check(true, now).is_ok());
+    assert!(governance.check(false, 2_000).is_ok());
+
+    let compliance = ObjectLock {
+        retention: Some(Retention {
+            mode: RetentionMode::Compliance,
+            retain_until: 2_000,
+        }),
+        legal_hold: false,
+    };
+    assert!(compliance.check(true, now).is_err());
+    let longer = Retention {
+        mode: RetentionMode::Compliance,
+        retain_until: 3_000,
+    };
+    let shorter = Retention {
+        mode: RetentionMode::Compliance,
+        retain_until: 1_500,
+    };
+    assert!(compliance
+        .check_retention_change(Some(&longer), false, now)
+        .is_ok());
+    assert!(compliance
+        .check_retention_change(Some(&shorter), true, now)
+        .is_err());
+    assert!(compliance.check_retention_change(None, true, now).is_err());
+    assert!(governance.check_retention_change(None, false, now).is_err());
+    assert!(governance.check_retention_change(None, true, now).is_ok());
+    assert!(governance
+        .check_retention_change(Some(&longer), false, now)
+        .is_ok());
+
+    let held = ObjectLock {
+        retention: None,
+        legal_hold: true,
+    };
+    assert!(held.check(true, now).is_err());
+    assert_eq!(held.headers().len(), 1);
+    assert_eq!(
+        compliance.headers()[1].1,
+        HeaderValue::from_static("1970-01-01T00:33:20.000Z")
+    );
+}
+
+#[test]
+fn locks_are_read_from_the_headers() {
+    let now = 1_000;
+    let mut headers = HeaderMap::new();
+    assert!(requested(&headers, now).unwrap().is_empty());
+
+    headers.insert(&MODE_HEADER, HeaderValue::from_static("GOVERNANCE"));
+    assert!(requested(&headers, now).is_err());
+    headers.insert(
+        &RETAIN_UNTIL_DATE_HEADER,
+        HeaderValue::from_static("2030-01-01T00:00:00Z"),
+    );
+    headers.insert(&LEGAL_HOLD_HEADER, HeaderValue::from_static("ON"));
+    let lock = requested(&headers, now).unwrap();
+    assert_eq!(lock.retention.unwrap().mode, RetentionMode::Governance);
+    assert_eq!(lock.retention.unwrap().retain_until, 1_893_456_000);
+    assert!(lock.legal_hold);
+
+    // the date has to be in the future
+    assert!(requested(&headers, 1_893_456_000).is_err());
+    headers.insert(&MODE_HEADER, HeaderValue::from_static("governance"));
+    assert!(requested(&headers, now).is_err());
+
+    assert!(!bypasses_governance(&headers));
+    headers.insert(&BYPASS_GOVERNANCE_HEADER, HeaderValue::from_static("true"));
+    assert!(bypasses_governance(&headers));
+}
@@ -0,0 +1,465 @@
+use crate::axum_ext::has_query_key;
+use axum::http::{Method, Uri};
+
+/// The S3 operation a request maps to, derived from the method and path.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum S3Operation {
+    ListBuckets,
+    ListObjects,
+    ListObjectVersions,
+    CreateBucket,
+    DeleteBucket,
+    GetBucketLocation,
+    GetObject,
+    GetObjectAttributes,
+    GetObjectTagging,
+    PutObjectTagging,
+    DeleteObjectTagging,
+    GetObjectAcl,
+    PutObjectAcl,
+    GetObjectRetention,
+    PutObjectRetention,
+    GetObjectLegalHold,
+    PutObjectLegalHold,
+    CreateMultipartUpload,
+    SelectObjectContent,
+    UploadPart,
+    CompleteMultipartUpload,
+    PutObject,
+    DeleteObject,
+    DeleteObjects,
+    PostObject,
+    GetBucketNotification,
+    PutBucketNotification,
+    GetBucketReplication,
+    PutBucketReplication,
+    DeleteBucketReplication,
+    GetBucketLifecycleConfiguration,
+    PutBucketLifecycleConfiguration,
+    DeleteBucketLifecycle,
+    GetBucketTagging,
+    PutBucketTagging,
+    DeleteBucketTagging,
+    GetBucketCors,
+    PutBucketCors,
+    DeleteBucketCors,
+    GetBucketPolicy,
+    PutBucketPolicy,
+    DeleteBucketPolicy,
+    GetBucketAcl,
+    PutBucketAcl,
+    GetBucketVersioning,
+    PutBucketVersioning,
+    GetObjectLockConfiguration,
+    PutObjectLockConfiguration,
+    GetBucketInventoryConfiguration,
+    ListBucketInventoryConfigurations,
+    PutBucketInventoryConfiguration,
+    DeleteBucketInventoryConfiguration,
+    #[default]
+    Unknown,
+}
+
+impl S3Operation {
+    pub const ALL: &'static [S3Operation] = &[
+        S3Operation::ListBuckets,
+        S3Operation::ListObjects,
+        S3Operation::ListObjectVersions,
+        S3Operation::CreateBucket,
+        S3Operation::DeleteBucket,
+        S3Operation::GetBucketLocation,
+        S3Operation::GetObject,
+        S3Operation::GetObjectAttributes,
+        S3Operation::GetObjectTagging,
+        S3Operation::PutObjectTagging,
+        S3Operation::DeleteObjectTagging,
+        S3Operation::GetObjectAcl,
+        S3Operation::PutObjectAcl,
+        S3Operation::GetObjectRetention,
+        S3Operation::PutObjectRetention,
+        S3Operation::GetObjectLegalHold,
+        S3Operation::PutObjectLegalHold,
+        S3Operation::CreateMultipartUpload,
+        S3Operation::SelectObjectContent,
+        S3Operation::UploadPart,
+        S3Operation::CompleteMultipartUpload,
+        S3Operation::PutObject,
+        S3Operation::DeleteObject,
+        S3Operation::DeleteObjects,
+        S3Operation::PostObject,
+        S3Operation::GetBucketNotification,
+        S3Operation::PutBucketNotification,
+        S3Operation::GetBucketReplication,
+        S3Operation::PutBucketReplication,
+        S3Operation::DeleteBucketReplication,
+        S3Operation::GetBucketLifecycleConfiguration,
+        S3Operation::PutBucketLifecycleConfiguration,
+        S3Operation::DeleteBucketLifecycle,
+        S3Operation::GetBucketTagging,
+        S3Operation::PutBucketTagging,
+        S3Operation::DeleteBucketTagging,
+        S3Operation::GetBucketCors,
+        S3Operation::PutBucketCors,
+        S3Operation::DeleteBucketCors,
+        S3Operation::GetBucketPolicy,
+        S3Operation::PutBucketPolicy,
+        S3Operation::DeleteBucketPolicy,
+        S3Operation::GetBucketAcl,
+        S3Operation::PutBucketAcl,
+        S3Operation::GetBucketVersioning,
+        S3Operation::PutBucketVersioning,
+        S3Operation::GetObjectLockConfiguration,
+        S3Operation::PutObjectLockConfiguration,
+        S3Operation::GetBucketInventoryConfiguration,
+        S3Operation::ListBucketInventoryConfigurations,
+        S3Operation::PutBucketInventoryConfiguration,
+        S3Operation::DeleteBucketInventoryConfiguration,
+        S3Operation::Unknown,
+    ];
+
+    /// Looks up an operation by its name, ignoring case.
+    pub fn from_name(name: &str) -> Option<S3Operation> {
+        S3Operation::ALL
+            .iter()
+            .copied()
+            .find(|operation| operation.as_str().eq_ignore_ascii_case(name))
+    }
+
+    pub fn from_request(method: &Method, uri: &Uri) -> S3Operation {
+        let path = uri.path().trim_start_matches('/');
+
+        if path.is_empty() {
+            return match *method {
+                Method::GET => S3Operation::ListBuckets,
+                _ => S3Operation::Unknown,
+            };
+        }
+
+        if path.starts_with('_') {
+            return S3Operation::Unknown;
+        }
+
+        let has_key = matches!(path.split_once('/'), Some((_, key)) if !key.is_empty());
+        let query = uri.query().unwrap_or_default();
+        let subresource = |name| has_query_key(query, name);
+
+        match (method, has_key) {
+            (&Method::GET, false) if subresource("location") => S3Operation::GetBucketLocation,
+            (&Method::GET, false) if subresource("versions") => S3Operation::ListObjectVersions,
+            (&Method::POST, false) if subresource("delete") => S3Operation::DeleteObjects,
+            (&Method::GET, false) if subresource("notification") => {
+                S3Operation::GetBucketNotification
+            }
+            (&Method::PUT, false) if subresource("notification") => {
+                S3Operation::PutBucketNotification
+            }
+            (&Method::GET, false) if subresource("replication") => {
+                S3Operation::GetBucketReplication
+            }
+            (&Method::PUT, false) if subresource("replication") => {
+                S3Operation::PutBucketReplication
+            }
+            (&Method::DELETE, false) if subresource("replication") => {
+                S3Operation::DeleteBucketReplication
+            }
+            (&Method::GET, false) if subresource("lifecycle") => {
+                S3Operation::GetBucketLifecycleConfiguration
+            }
+            (&Method::PUT, false) if subresource("lifecycle") => {
+                S3Operation::PutBucketLifecycleConfiguration
+            }
+            (&Method::DELETE, false) if subresource("lifecycle") => {
+                S3Operation::DeleteBucketLifecycle
+            }
+            (&Method::GET, false) if subresource("tagging") => S3Operation::GetBucketTagging,
+            (&Method::PUT, false) if subresource("tagging") => S3Operation::PutBucketTagging,
+            (&Method::DELETE, false) if subresource("tagging") => S3Operation::DeleteBucketTagging,
+            (&Method::GET, false) if subresource("cors") => S3Operation::GetBucketCors,
+            (&Method::PUT, false) if subresource("cors") => S3Operation::PutBucketCors,
+            (&Method::DELETE, false) if subresource("cors") => S3Operation::DeleteBucketCors,
+            (&Method::GET, false) if subresource("policy") => S3Operation::GetBucketPolicy,
+            (&Method::PUT, false) if subresource("policy") => S3Operation::PutBucketPolicy,
+            (&Method::DELETE, false) if subresource("policy") => S3Operation::DeleteBucketPolicy,
+            (&Method::GET, false) if subresource("acl") => S3Operation::GetBucketAcl,
+            (&Method::PUT, false) if subresource("acl") => S3Operation::PutBucketAcl,
+            (&Method::GET, false) if subresource("versioning") => S3Operation::GetBucketVersioning,
+            (&Method::PUT, false) if subresource("versioning") => S3Operation::PutBucketVersioning,
+            (&Method::GET, false) if subresource("object-lock") => {
+                S3Operation::GetObjectLockConfiguration
+            }
+            (&Method::PUT, false) if subresource("object-lock") => {
+                S3Operation::PutObjectLockConfiguration
+            }
+            (&Method::GET, false) if subresource("inventory") && subresource("id") => {
+                S3Operation::GetBucketInventoryConfiguration
+            }
+            (&Method::GET, false) if subresource("inventory") => {
+                S3Operation::ListBucketInventoryConfigurations
+            }
+            (&Method::PUT, false) if subresource("inventory") => {
+                S3Operation::PutBucketInventoryConfiguration
+            }
+            (&Method::DELETE, false) if subresource("inventory") => {
+                S3Operation::DeleteBucketInventoryConfiguration
+            }
+            (&Method::GET, true) if subresource("attributes") => S3Operation::GetObjectAttributes,
+            (&Method::GET, true) if subresource("tagging") => S3Operation::GetObjectTagging,
+            (&Method::PUT, true) if subresource("tagging") => S3Operation::PutObjectTagging,
+            (&Method::DELETE, true) if subresource("tagging") => S3Operation::DeleteObjectTagging,
+            (&Method::GET, true) if subresource("acl") => S3Operation::GetObjectAcl,
+            (&Method::PUT, true) if subresource("acl") => S3Operation::PutObjectAcl,
+            (&Method::GET, true) if subresource("retention") => S3Operation::GetObjectRetention,
+            (&Method::PUT, true) if subresource("retention") => S3Operation::PutObjectRetention,
+            (&Method::GET, true) if subresource("legal-hold") => S3Operation::GetObjectLegalHold,
+            (&Method::PUT, true) if subresource("legal-hold") => S3Operation::PutObjectLegalHold,
+            (&Method::POST, true) if subresource("select") => S3Operation::SelectObjectContent,
+            (&Method::POST, true) if subresource("uploads") => S3Operation::CreateMultipartUpload,
+            (&Method::PUT, true) if subresource("uploadId") => S3Operation::UploadPart,
+            (&Method::POST, true) if subresource("uploadId") => {
+                S3Operation::CompleteMultipartUpload
+            }
+            (&Method::GET, false) => S3Operation::ListObjects,
+            (&Method::POST, false) => S3Operation::PostObject,
+            (&Method::PUT, false) => S3Operation::CreateBucket,
+            (&Method::DELETE, false) => S3Operation::DeleteBucket,
+            (&Method::GET, true) => S3Operation::GetObject,
+            (&Method::PUT, true) => S3Operation::PutObject,
+            (&Method::DELETE, true) => S3Operation::DeleteObject,
+            _ => S3Operation::Unknown,
+        }
+    }
+
+    /// Operations that change state and end up in the audit log.
+    pub fn is_mutating(&self) -> bool {
+        matches!(
+            self,
+            S3Operation::CreateBucket
+                | S3Operation::DeleteBucket
+                | S3Operation::PutObject
+                | S3Operation::DeleteObject
+                | S3Operation::DeleteObjects
+                | S3Operation::PostObject
+                | S3Operation::PutObjectTagging
+                | S3Operation::DeleteObjectTagging
+                | S3Operation::PutObjectAcl
+                | S3Operation::PutObjectRetention
+                | S3Operation::PutObjectLegalHold
+                | S3Operation::CreateMultipartUpload
+                | S3Operation::UploadPart
+                | S3Operation::CompleteMultipartUpload
+                | S3Operation::PutBucketNotification
+                | S3Operation::PutBucketReplication
+                | S3Operation::DeleteBucketReplication
+                | S3Operation::PutBucketLifecycleConfiguration
+                | S3Operation::DeleteBucketLifecycle
+                | S3Operation::PutBucketTagging
+                | S3Operation::DeleteBucketTagging
+                | S3Operation::PutBucketCors
+                | S3Operation::DeleteBucketCors
+                | S3Operation::PutBucketPolicy
+                | S3Operation::DeleteBucketPolicy
+                | S3Operation::PutBucketAcl
+                | S3Operation::PutBucketVersioning
+                | S3Operation::PutObjectLockConfiguration
+                | S3Operation::PutBucketInventoryConfiguration
+                | S3Operation::DeleteBucketInventoryConfiguration
+        )
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            S3Operation::ListBuckets => "ListBuckets",
+            S3Operation::ListObjects => "ListObjects",
+            S3Operation::ListObjectVersions => "ListObjectVersions",
+            S3Operation::CreateBucket => "CreateBucket",
+            S3Operation::DeleteBucket => "DeleteBucket",
+            S3Operation::GetBucketLocation => "GetBucketLocation",
+            S3Operation::GetObject => "GetObject",
+            S3Operation::GetObjectAttributes => "GetObjectAttributes",
+            S3Operation::GetObjectTagging => "GetObjectTagging",
+            S3Operation::PutObjectTagging => "PutObjectTagging",
+            S3Operation::DeleteObjectTagging => "DeleteObjectTagging",
+            S3Operation::GetObjectAcl => "GetObjectAcl",
+            S3Operation::PutObjectAcl => "PutObjectAcl",
+            S3Operation::GetObjectRetention => "GetObjectRetention",
+            S3Operation::PutObjectRetention => "PutObjectRetention",
+            S3Operation::GetObjectLegalHold => "GetObjectLegalHold",
+            S3Operation::PutObjectLegalHold => "PutObjectLegalHold",
+            S3Operation::CreateMultipartUpload => "CreateMultipartUpload",
+            S3Operation::SelectObjectContent => "SelectObjectContent",
+            S3Operation::UploadPart => "UploadPart",
+            S3Operation::CompleteMultipartUpload => "CompleteMultipartUpload",
+            S3Operation::PutObject => "PutObject",
+            S3Operation::DeleteObject => "DeleteObject",
+            S3Operation::DeleteObjects => "DeleteObjects",
+            S3Operation::PostObject => "PostObject",
+            S3Operation::GetBucketNotification => "GetBucketNotificationConfiguration",
+            S3Operation::PutBucketNotification => "PutBucketNotificationConfiguration",
+            S3Operation::GetBucketReplication => "GetBucketReplication",
+            S3Operation::PutBucketReplication => "PutBucketReplication",
+            S3Operation::DeleteBucketReplication => "DeleteBucketReplication",
+            S3Operation::GetBucketLifecycleConfiguration => "GetBucketLifecycleConfiguration",
+            S3Operation::PutBucketLifecycleConfiguration => "PutBucketLifecycleConfiguration",
+            S3Operation::DeleteBucketLifecycle => "DeleteBucketLifecycle",
+            S3Operation::GetBucketTagging => "GetBucketTagging",
+            S3Operation::PutBucketTagging => "PutBucketTagging",
+            S3Operation::DeleteBucketTagging => "DeleteBucketTagging",
+            S3Operation::GetBucketCors => "GetBucketCors",
+            S3Operation::PutBucketCors => "PutBucketCors",
+            S3Operation::DeleteBucketCors => "DeleteBucketCors",
+            S3Operation::GetBucketPolicy => "GetBucketPolicy",
+            S3Operation::PutBucketPolicy => "PutBucketPolicy",
+            S3Operation::DeleteBucketPolicy => "DeleteBucketPolicy",
+            S3Operation::GetBucketAcl => "GetBucketAcl",
+            S3Operation::PutBucketAcl => "PutBucketAcl",
+            S3Operation::GetBucketVersioning => "GetBucketVersioning",
+            S3Operation::PutBucketVersioning => "PutBucketVersioning",
+            S3Operation::GetObjectLockConfiguration => "GetObjectLockConfiguration",
+            S3Operation::PutObjectLockConfiguration => "PutObjectLockConfiguration",
+            S3Operation::GetBucketInventoryConfiguration => "GetBucketInventoryConfiguration",
+            S3Operation::ListBucketInventoryConfigurations => "ListBucketInventoryConfigurations",
+            S3Operation::PutBucketInventoryConfiguration => "PutBucketInventoryConfiguration",
+            S3Operation::DeleteBucketInventoryConfiguration => "DeleteBucketInventoryConfiguration",
+            S3Operation::Unknown => "Unknown",
+        }
+    }
+}
+
+impl std::fmt::Display for S3Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[test]
+fn operation_from_request_test() {
+    let cases = [
+        (Method::GET, "/", S3Operation::ListBuckets),
+        (Method::GET, "/bucket", S3Operation::ListObjects),
+        (Method::GET, "/bucket/", S3Operation::ListObjects),
+        (Method::PUT, "/bucket", S3Operation::CreateBucket),
+        (Method::DELETE, "/bucket", S3Operation::DeleteBucket),
+        (
+            Method::GET,
+            "/bucket?location",
+            S3Operation::GetBucketLocation,
+        ),
+        (
+            Method::GET,
+            "/bucket?versions&prefix=dir/",
+            S3Operation::ListObjectVersions,
+        ),
+        (Method::GET, "/bucket/key.txt", S3Operation::GetObject),
+        (
+            Method::PUT,
+            "/bucket/key.txt?x-id=PutObject",
+            S3Operation::PutObject,
+        ),
+        (
+            Method::DELETE,
+            "/bucket/dir/key.txt?x-id=DeleteObject",
+            S3Operation::DeleteObject,
+        ),
+        (
+            Method::GET,
+            "/bucket/key.txt?attributes",
+            S3Operation::GetObjectAttributes,
+        ),
+        (
+            Method::PUT,
+            "/bucket/dir/key.txt?tagging",
+            S3Operation::PutObjectTagging,
+        ),
+        (
+            Method::POST,
+            "/bucket/dir/key.txt?uploads",
+            S3Operation::CreateMultipartUpload,
+        ),
+        (
+            Method::POST,
+            "/bucket/data.csv?select&select-type=2",
+            S3Operation::SelectObjectContent,
+        ),
+        (
+            Method::PUT,
+            "/bucket/dir/key.txt?partNumber=1&uploadId=abc",
+            S3Operation::UploadPart,
+        ),
+        (
+            Method::POST,
+            "/bucket/key.txt?uploadId=abc",
+            S3Operation::CompleteMultipartUpload,
+        ),
+        (Method::POST, "/bucket?delete", S3Operation::DeleteObjects),
+        (Method::POST, "/bucket", S3Operation::PostObject),
+        (
+            Method::PUT,
+            "/bucket?notification",
+            S3Operation::PutBucketNotification,
+        ),
+        (
+            Method::DELETE,
+            "/bucket/?replication",
+            S3Operation::DeleteBucketReplication,
+        ),
+        (
+            Method::PUT,
+            "/bucket?lifecycle",
+            S3Operation::PutBucketLifecycleConfiguration,
+        ),
+        (
+            Method::DELETE,
+            "/bucket?cors",
+            S3Operation::DeleteBucketCors,
+        ),
+        (Method::PUT, "/bucket?policy", S3Operation::PutBucketPolicy),
+        (Method::GET, "/bucket?acl", S3Operation::GetBucketAcl),
+        (
+            Method::PUT,
+            "/bucket/key.txt?acl",
+            S3Operation::PutObjectAcl,
+        ),
+        (
+            Method::GET,
+            "/bucket?tagging",
+            S3Operation::GetBucketTagging,
+        ),
+        (
+            Method::PUT,
+            "/bucket?versioning",
+            S3Operation::PutBucketVersioning,
+        ),
+        (
+            Method::PUT,
+            "/bucket?object-lock",
+            S3Operation::PutObjectLockConfiguration,
+        ),
+        (
+            Method::GET,
+            "/bucket/key.txt?retention",
+            S3Operation::GetObjectRetention,
+        ),
+        (
+            Method::PUT,
+            "/bucket/key.txt?legal-hold",
+            S3Operation::PutObjectLegalHold,
+        ),
+        (
+            Method::GET,
+            "/bucket?inventory&id=daily",
+            S3Operation::GetBucketInventoryConfiguration,
+        ),
+        (
+            Method::GET,
+            "/bucket?inventory",
+            S3Operation::ListBucketInventoryConfigurations,
+        ),
+        (Method::GET, "/_metadata", S3Operation::Unknown),
+        (Method::POST, "/", S3Operation::Unknown),
+    ];
+
+    for (method, uri, expected) in cases {
+        let uri: Uri = uri.parse().unwrap();
+        assert_eq!(S3Operation::from_request(&method, &uri), expected, "{uri}");
+    }
+}
@@ -0,0 +1,219 @@
+use crate::context::RequestContext;
+use crate::hashing::{Sha256Hasher, Sha256Implementation};
+use axum::body::{Body, Bytes};
+use futures::Stream;
+use http_body::Frame;
+use http_body_util::BodyExt;
+use std::fmt;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+
+/// Bodies that are collected in memory (xml documents) may not be larger than this.
+pub const MAX_BUFFERED_BODY: usize = 8 * 1024 * 1024;
+
+pub const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+#[derive(Debug)]
+pub enum PayloadError {
+    /// the body does not match the signed `x-amz-content-sha256`
+    Mismatch,
+    TooLarge,
+    Body(axum::Error),
+}
+
+impl fmt::Display for PayloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PayloadError::Mismatch => {
+                f.write_str("payload does not match the x-amz-content-sha256 header")
+            }
+            PayloadError::TooLarge => f.write_str("payload is too large"),
+            PayloadError::Body(error) => write!(f, "unable to read payload: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for PayloadError {}
+
+/// Request body that is hashed while it is read.
+///
+/// The signature only covers the sha256 the client claims in `x-amz-content-sha256`, so the
+/// body is checked against that hash once the last chunk has been read. Consumers must not
+/// commit the data before the stream is exhausted without errors.
+pub struct VerifiedBody {
+    body: Body,
+    expected: Option<[u8; 32]>,
+    hasher: Sha256Hasher,
+    context: Option<Arc<RequestContext>>,
+}
+
+impl fmt::Debug for VerifiedBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VerifiedBody")
+            .field("expected", &self.expected.map(hex::encode))
+            .finish()
+    }
+}
+
+impl Default for VerifiedBody {
+    fn default() -> Self {
+        VerifiedBody::unsigned(Body::empty(), None)
+    }
+}
+
+impl VerifiedBody {
+    /// Body with a signed payload hash that still has to be checked.
+    pub fn signed(body: Body, expected: [u8; 32], context: Option<Arc<RequestContext>>) -> Self {
+        VerifiedBody {
+            body,
+            expected: Some(expected),
+            hasher: Sha256Hasher::default(),
+            context,
+        }
+    }
+
+    /// Body that is not covered by the signature, or has already been checked.
+    pub fn unsigned(body: Body, context: Option<Arc<RequestContext>>) -> Self {
+        VerifiedBody {
+            body,
+            expected: None,
+            hasher: Sha256Hasher::default(),
+            context,
+        }
+    }
+
+    /// Hashes the payload with `implementation` instead of the default sha2.
+    pub fn hashed_with(mut self, implementation: Sha256Implementation) -> Self {
+        self.hasher = implementation.hasher();
+        self
+    }
+
+    /// Returns the next chunk of the body, the final call checks the payload hash.
+    pub async fn chunk(&mut self) -> Option<Result<Bytes, PayloadError>> {
+        loop {
+            match self.frame().await? {
+                // trailers are not part of the payload
+                Ok(frame) => match frame.into_data() {
+                    Ok(data) => return Some(Ok(data)),
+                    Err(_) => continue,
+                },
+                Err(error) => return Some(Err(error)),
+            }
+        }
+    }
+
+    /// The chunks of the body as a stream, the last one checks the payload hash.
+    pub fn into_stream(self) -> impl Stream<Item = Result<Bytes, PayloadError>> {
+        futures::stream::unfold(self, |mut body| async move {
+            let chunk = body.chunk().await?;
+            Some((chunk, body))
+        })
+    }
+
+    /// Reads the whole body in memory, up to `MAX_BUFFERED_BODY`.
+    pub async fn bytes(mut self) -> Result<Bytes, PayloadError> {
+        let mut buffer = Vec::new();
+
+        while let Some(chunk) = self.chunk().await {
+            let chunk = chunk?;
+            if buffer.len() + chunk.len() > MAX_BUFFERED_BODY {
+                return Err(PayloadError::TooLarge);
+            }
+            buffer.extend_from_slice(&chunk);
+        }
+
+        Ok(Bytes::from(buffer))
+    }
+}
+
+impl http_body::Body for VerifiedBody {
+    type Data = Bytes;
+    type Error = PayloadError;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = &mut *self;
+
+        match ready!(Pin::new(&mut this.body).poll_frame(cx)) {
+            Some(Ok(frame)) => {
+                if let Some(data) = frame.data_ref() {
+                    if let Some(context) = &this.context {
+                        context.add_request_bytes(data.len() as u64);
+                    }
+                    if this.expected.is_some() {
+                        this.hasher.update(data);
+                    }
+                }
+                Poll::Ready(Some(Ok(frame)))
+            }
+            Some(Err(error)) => Poll::Ready(Some(Err(PayloadError::from(error)))),
+            None => {
+                let Some(expected) = this.expected.take() else {
+                    return Poll::Ready(None);
+                };
+                let actual = std::mem::take(&mut this.hasher).finalize();
+
+                if actual != expected {
+                    return Poll::Ready(Some(Err(PayloadError::Mismatch)));
+                }
+
+                Poll::Ready(None)
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.expected.is_none() && self.body.is_end_stream()
+    }
+}
+
+impl From<axum::Error> for PayloadError {
+    /// Keeps the original error when the body was already verified by an earlier layer.
+    fn from(error: axum::Error) -> Self {
+        match error.into_inner().downcast::<PayloadError>() {
+            Ok(error) => *error,
+            Err(error) => PayloadError::Body(axum::Error::new(error)),
+        }
+    }
+}
+
+/// Parses the `x-amz-content-sha256` value into the hash the body should have.
+pub fn parse_payload_hash(value: &str) -> Option<[u8; 32]> {
+    let mut hash = [0; 32];
+    hex::decode_to_slice(value, &mut hash).ok()?;
+    Some(hash)
+}
+
+#[tokio::test]
+async fn verified_body_accepts_matching_payload() {
+    let expected =
+        parse_payload_hash("2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824")
+            .unwrap();
+    let body = VerifiedBody::signed(Body::from("hello"), expected, None);
+
+    assert_eq!(body.bytes().await.unwrap(), Bytes::from("hello"));
+}
+
+#[tokio::test]
+async fn verified_body_rejects_tampered_payload() {
+    let expected =
+        parse_payload_hash("2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824")
+            .unwrap();
+    let body = VerifiedBody::signed(Body::from("hellO"), expected, None);
+
+    assert!(matches!(body.bytes().await, Err(PayloadError::Mismatch)));
+}
+
+#[tokio::test]
+async fn verified_body_keeps_mismatch_through_layers() {
+    let expected =
+        parse_payload_hash("2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824")
+            .unwrap();
+    let inner = VerifiedBody::signed(Body::from("hellO"), expected, None);
+    let body = VerifiedBody::unsigned(Body::new(inner), None);
+
+    assert!(matches!(body.bytes().await, Err(PayloadError::Mismatch)));
+}
@@ -0,0 +1,135 @@
+use crate::context::RequestContext;
+use crate::error::S3Error;
+use crate::operation::S3Operation;
+use crate::signature::Identity;
+use crate::AppState;
+use async_trait::async_trait;
+use axum::extract::{FromRequestParts, RawPathParams, Request, State};
+use axum::http::{response, HeaderMap};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use std::sync::Arc;
+
+/// The parts of a verified request an interceptor can look at and change.
+pub struct InterceptedRequest<'a> {
+    pub identity: &'a Identity,
+    pub operation: S3Operation,
+    pub bucket: Option<String>,
+    /// changing the bucket or key makes the handler use the new location
+    pub key: Option<String>,
+    pub headers: &'a mut HeaderMap,
+}
+
+/// Compiled-in plugin that sees every request after the signature check.
+///
+/// The request is verified against what the client sent, so changes made here are not
+/// checked against the signature.
+#[async_trait]
+pub trait RequestInterceptor: Send + Sync {
+    /// Returning an error rejects the request with it.
+    async fn on_request(&self, _request: &mut InterceptedRequest<'_>) -> Result<(), S3Error> {
+        Ok(())
+    }
+
+    async fn on_response(
+        &self,
+        _identity: &Identity,
+        _operation: S3Operation,
+        _response: &mut response::Parts,
+    ) {
+    }
+}
+
+/// Bucket and key set by an interceptor, the handlers use these instead of the path.
+#[derive(Debug, Clone)]
+pub(crate) struct RewrittenPath {
+    pub bucket: String,
+    pub key: Option<String>,
+}
+
+/// The registered interceptors, requests pass them in registration order and responses in
+/// reverse order.
+#[derive(Clone, Default)]
+pub struct Interceptors {
+    interceptors: Arc<Vec<Arc<dyn RequestInterceptor>>>,
+}
+
+impl Interceptors {
+    pub fn new(interceptors: Vec<Arc<dyn RequestInterceptor>>) -> Interceptors {
+        Interceptors {
+            interceptors: Arc::new(interceptors),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.interceptors.is_empty()
+    }
+}
+
+pub async fn intercept(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let (mut parts, body) = req.into_parts();
+
+    let Some(identity) = parts.extensions.get::<Identity>().cloned() else {
+        return S3Error::internal("interceptors run without the authenticate middleware")
+            .into_response();
+    };
+    let operation = match parts.extensions.get::<Arc<RequestContext>>() {
+        Some(context) => context.operation,
+        None => S3Operation::from_request(&parts.method, &parts.uri),
+    };
+
+    // the list buckets route has no path params
+    let (bucket, key) = match RawPathParams::from_request_parts(&mut parts, &state).await {
+        Ok(params) => path_params(&params),
+        Err(_) => (None, None),
+    };
+
+    let mut request = InterceptedRequest {
+        identity: &identity,
+        operation,
+        bucket: bucket.clone(),
+        key: key.clone(),
+        headers: &mut parts.headers,
+    };
+    for interceptor in state.interceptors.interceptors.iter() {
+        if let Err(error) = interceptor.on_request(&mut request).await {
+            return error.into_response();
+        }
+    }
+
+    if (&request.bucket, &request.key) != (&bucket, &key) {
+        let Some(new_bucket) = request.bucket.clone() else {
+            return S3Error::internal("an interceptor removed the bucket").into_response();
+        };
+        let new_key = request.key.clone();
+        parts.extensions.insert(RewrittenPath {
+            bucket: new_bucket,
+            key: new_key,
+        });
+    }
+
+    let response = next.run(Request::from_parts(parts, body)).await;
+
+    let (mut parts, body) = response.into_parts();
+    for interceptor in state.interceptors.interceptors.iter().rev() {
+        interceptor
+            .on_response(&identity, operation, &mut parts)
+            .await;
+    }
+
+    Response::from_parts(parts, body)
+}
+
+fn path_params(params: &RawPathParams) -> (Option<String>, Option<String>) {
+    let mut bucket = None;
+    let mut key = None;
+    for (name, value) in params {
+        match name {
+            "bucket_name" => bucket = Some(value.to_string()),
+            "object_name" => key = Some(value.to_string()),
+            _ => (),
+        }
+    }
+
+    (bucket, key)
+}
@@ -0,0 +1,294 @@
+use crate::error::S3Error;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+
+/// The only signature version browser uploads are accepted with.
+pub const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+
+/// Form fields that are never matched against the policy.
+const UNCHECKED_FIELDS: &[&str] = &["policy", "x-amz-signature", "file", "bucket"];
+
+#[derive(Debug, Deserialize)]
+struct PolicyDocument {
+    expiration: String,
+    #[serde(default)]
+    conditions: Vec<Value>,
+}
+
+/// A condition on the form fields, the field names are lowercase without `$`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Equals(String, String),
+    StartsWith(String, String),
+    /// the size of the file, inclusive
+    ContentLengthRange(u64, u64),
+}
+
+/// The policy document of a browser upload, what the form may contain and until when.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PostPolicy {
+    pub expiration: SystemTime,
+    pub conditions: Vec<Condition>,
+}
+
+fn invalid(message: &str) -> S3Error {
+    S3Error::InvalidPolicyDocument(format!("Invalid Policy: {}", message))
+}
+
+impl PostPolicy {
+    /// Decodes the base64 `policy` field.
+    pub fn decode(policy: &str) -> Result<PostPolicy, S3Error> {
+        let json = STANDARD
+            .decode(policy.trim())
+            .map_err(|_| invalid("Invalid Base64 Encoding."))?;
+        let document: PolicyDocument =
+            serde_json::from_slice(&json).map_err(|_| invalid("Invalid JSON."))?;
+
+        let expiration = chrono::DateTime::parse_from_rfc3339(&document.expiration)
+            .map_err(|_| invalid("Invalid 'expiration' value."))?;
+        let conditions = document
+            .conditions
+            .iter()
+            .map(condition)
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| invalid("Invalid Simple-Condition."))?;
+
+        Ok(PostPolicy {
+            expiration: expiration.into(),
+            conditions,
+        })
+    }
+
+    /// Checks the form fields against the policy, the names of `fields` are lowercase. Every
+    /// field has to be mentioned by a condition, like S3 requires.
+    pub fn check(&self, fields: &BTreeMap<String, String>, now: SystemTime) -> Result<(), S3Error> {
+        if self.expiration < now {
+            return Err(S3Error::InvalidAccordingToPolicy(String::from(
+                "Policy expired.",
+            )));
+        }
+
+        for condition in &self.conditions {
+            let (operator, field, value, holds) = match condition {
+                Condition::Equals(field, value) => (
+                    "eq",
+                    field,
+                    value,
+                    fields.get(field).is_some_and(|x| x == value),
+                ),
+                Condition::StartsWith(field, prefix) => (
+                    "starts-with",
+                    field,
+                    prefix,
+                    fields.get(field).is_some_and(|x| x.starts_with(prefix)),
+                ),
+                Condition::ContentLengthRange(..) => continue,
+            };
+            if !holds {
+                return Err(S3Error::InvalidAccordingToPolicy(format!(
+                    "Policy Condition failed: [\"{}\", \"${}\", \"{}\"]",
+                    operator, field, value
+                )));
+            }
+        }
+
+        let extra = fields.keys().find(|name| {
+            !UNCHECKED_FIELDS.contains(&name.as_str())
+                && !name.starts_with("x-ignore-")
+                && !self.conditions.iter().any(|x| match x {
+                    Condition::Equals(field, _) | Condition::StartsWith(field, _) => field == *name,
+                    Condition::ContentLengthRange(..) => false,
+                })
+        });
+        if let Some(extra) = extra {
+            return Err(S3Error::InvalidAccordingToPolicy(format!(
+                "Extra input fields: {}",
+                extra
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub fn content_length_range(&self) -> Option<(u64, u64)> {
+        self.conditions.iter().find_map(|x| match x {
+            Condition::ContentLengthRange(min, max) => Some((*min, *max)),
+            _ => None,
+        })
+    }
+}
+
+/// `{"field": "value"}`, `["eq", "$field", "value"]`, `["starts-with", "$field", "prefix"]` or
+/// `["content-length-range", min, max]`.
+fn condition(value: &Value) -> Option<Condition> {
+    let field = |name: &str| name.strip_prefix('$').unwrap_or(name).to_lowercase();
+
+    match value {
+        Value::Object(object) if object.len() == 1 => {
+            let (name, value) = object.iter().next()?;
+            Some(Condition::Equals(field(name), value.as_str()?.to_string()))
+        }
+        Value::Array(items) if items.len() == 3 => {
+            let operator = items[0].as_str()?.to_lowercase();
+            match operator.as_str() {
+                "content-length-range" => Some(Condition::ContentLengthRange(
+                    items[1].as_u64()?,
+                    items[2].as_u64()?,
+                )),
+                "eq" => Some(Condition::Equals(
+                    field(items[1].as_str()?),
+                    items[2].as_str()?.to_string(),
+                )),
+                "starts-with" => Some(Condition::StartsWith(
+                    field(items[1].as_str()?),
+                    items[2].as_str()?.to_string(),
+                )),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// The SigV4 signature of a policy, the base64 policy is the string to sign.
+pub fn signature(secret_key: &str, time: SystemTime, region: &str, policy: &str) -> String {
+    let signing_key = aws_sigv4::sign::v4::generate_signing_key(secret_key, time, region, "s3");
+    aws_sigv4::sign::v4::calculate_signature(signing_key, policy.as_bytes())
+}
+
+/// Fails the file once it is outside of the `content-length-range` of the policy, before the
+/// writer is closed.
+pub fn limit_length<S, E>(
+    file: S,
+    range: Option<(u64, u64)>,
+) -> impl Stream<Item = Result<Bytes, S3Error>>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    S3Error: From<E>,
+{
+    futures::stream::unfold(Some((file, 0u64)), move |state| async move {
+        let (mut file, length) = state?;
+        match file.next().await {
+            Some(Ok(chunk)) => {
+                let length = length + chunk.len() as u64;
+                match range {
+                    Some((_, max)) if length > max => Some((Err(S3Error::EntityTooLarge), None)),
+                    _ => Some((Ok(chunk), Some((file, length)))),
+                }
+            }
+            Some(Err(error)) => Some((Err(error.into()), None)),
+            None => match range {
+                Some((min, _)) if length < min => Some((Err(S3Error::EntityTooSmall), None)),
+                _ => None,
+            },
+        }
+    })
+}
+
+#[test]
+fn policies_are_decoded_and_checked() {
+    let document = r#"{
+        "expiration": "2030-01-01T12:00:00.000Z",
+        "conditions": [
+            {"bucket": "photos"},
+            ["starts-with", "$key", "user/"],
+            ["eq", "$Content-Type", "image/jpeg"],
+            ["content-length-range", 1, 1048576]
+        ]
+    }"#;
+    let policy = PostPolicy::decode(&STANDARD.encode(document)).unwrap();
+    assert_eq!(
+        policy.conditions[1],
+        Condition::StartsWith(String::from("key"), String::from("user/"))
+    );
+    assert_eq!(policy.content_length_range(), Some((1, 1048576)));
+
+    let mut fields: BTreeMap<_, _> = [
+        ("bucket", "photos"),
+        ("key", "user/a.jpg"),
+        ("content-type", "image/jpeg"),
+        ("x-amz-signature", "abc"),
+        ("x-ignore-tracking", "1"),
+    ]
+    .map(|(name, value)| (name.to_string(), value.to_string()))
+    .into_iter()
+    .collect();
+    let now = SystemTime::now();
+    assert!(policy.check(&fields, now).is_ok());
+
+    fields.insert(String::from("key"), String::from("other/a.jpg"));
+    assert!(policy.check(&fields, now).is_err());
+    fields.insert(String::from("key"), String::from("user/a.jpg"));
+    fields.insert(String::from("acl"), String::from("public-read"));
+    assert!(policy.check(&fields, now).is_err());
+    fields.remove("acl");
+
+    let later = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_900_000_000);
+    assert!(policy.check(&fields, later).is_err());
+
+    assert!(PostPolicy::decode("not base64!").is_err());
+    assert!(PostPolicy::decode(&STANDARD.encode(r#"{"expiration": "soon"}"#)).is_err());
+    assert!(PostPolicy::decode(&STANDARD.encode(
+        r#"{"expiration": "2030-01-01T12:00:00Z", "conditions": [["matches", "$key", "a"]]}"#
+    ))
+    .is_err());
+}
+
+#[test]
+fn policy_signatures_match_the_aws_example() {
+    // the example of "Examples: Browser-Based Upload using HTTP POST (Using AWS Signature
+    // Version 4)" in the S3 documentation
+    let policy = "eyAiZXhwaXJhdGlvbiI6ICIyMDE1LTEyLTMwVDEyOjAwOjAwLjAwMFoiLA0KICAiY29uZGl0aW9ucyI6IFsNCiAgICB7ImJ1Y2tldCI6ICJzaWd2NGV4YW1wbGVidWNrZXQifSwNCiAgICBbInN0YXJ0cy13aXRoIiwgIiRrZXkiLCAidXNlci91c2VyMS8iXSwNCiAgICB7ImFjbCI6ICJwdWJsaWMtcmVhZCJ9LA0KICAgIHsic3VjY2Vzc19hY3Rpb25fcmVkaXJlY3QiOiAiaHR0cDovL3NpZ3Y0ZXhhbXBsZWJ1Y2tldC5zMy5hbWF6b25hd3MuY29tL3N1Y2Nlc3NmdWxfdXBsb2FkLmh0bWwifSwNCiAgICBbInN0YXJ0cy13aXRoIiwgIiRDb250ZW50LVR5cGUiLCAiaW1hZ2UvIl0sDQogICAgeyJ4LWFtei1tZXRhLXV1aWQiOiAiMTQzNjUxMjM2NTEyNzQifSwNCiAgICB7IngtYW16LXNlcnZlci1zaWRlLWVuY3J5cHRpb24iOiAiQUVTMjU2In0sDQogICAgWyJzdGFydHMtd2l0aCIsICIkeC1hbXotbWV0YS10YWciLCAiIl0sDQoNCiAgICB7IngtYW16LWNyZWRlbnRpYWwiOiAiQUtJQUlPU0ZPRE5ON0VYQU1QTEUvMjAxNTEyMjkvdXMtZWFzdC0xL3MzL2F3czRfcmVxdWVzdCJ9LA0KICAgIHsieC1hbXotYWxnb3JpdGhtIjogIkFXUzQtSE1BQy1TSEEyNTYifSwNCiAgICB7IngtYW16LWRhdGUiOiAiMjAxNTEyMjlUMDAwMDAwWiIgfQ0KICBdDQp9";
+    let time = crate::signature::parse_date_time("20151229T000000Z").unwrap();
+
+    assert_eq!(
+        signature(
+            "wJalrXUtnFEMI/K7MDENG/bPxRfiCYEXAMPLEKEY",
+            time,
+            "us-east-1",
+            policy
+        ),
+        "8afdbf4008c03f22c2cd3cdb72e4afbb1f6a588f3255ac628749a66d7f09699e"
+    );
+}
+
+#[tokio::test]
+async fn files_are_limited_to_the_content_length_range() {
+    let file = |chunks: &[&'static str]| {
+        futures::stream::iter(
+            chunks
+                .iter()
+                .map(|x| Ok::<_, S3Error>(Bytes::from_static(x.as_bytes())))
+                .collect::<Vec<_>>(),
+        )
+    };
+    let read = |file, range| async move {
+        limit_length(file, range)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+    };
+
+    assert_eq!(
+        read(file(&["hello ", "world"]), Some((1, 11)))
+            .await
+            .unwrap(),
+        ["hello ", "world"]
+    );
+    assert!(matches!(
+        read(file(&["hello ", "world"]), Some((1, 10))).await,
+        Err(S3Error::EntityTooLarge)
+    ));
+    assert!(matches!(
+        read(file(&["hello"]), Some((6, 10))).await,
+        Err(S3Error::EntityTooSmall)
+    ));
+    assert!(read(file(&[]), None).await.unwrap().is_empty());
+}
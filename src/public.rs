@@ -0,0 +1,180 @@
+use crate::api::{content_type, http_date, insert_validators, not_modified};
+use crate::axum_ext::is_hidden;
+use crate::error::S3Error;
+use crate::etag_cache::{self, Validators};
+use crate::namespaces::{self, Namespaces};
+use crate::{acl, etags, AppState};
+use axum::body::Body;
+use axum::extract::{Path, State};
+use axum::http::header::{CACHE_CONTROL, CONTENT_LENGTH, CONTENT_TYPE};
+use axum::http::{HeaderMap, HeaderValue};
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+
+/// Downloads from buckets that are marked public on the admin api.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PublicConfig {
+    /// the `max-age` of the `Cache-Control` header
+    #[serde(default = "default_max_age_secs")]
+    pub max_age_secs: u64,
+}
+
+impl Default for PublicConfig {
+    fn default() -> Self {
+        PublicConfig {
+            max_age_secs: default_max_age_secs(),
+        }
+    }
+}
+
+fn default_max_age_secs() -> u64 {
+    300
+}
+
+/// `GET /_public/:namespace/:bucket/*key`, served without a signature.
+///
+/// Buckets that are not public answer like missing buckets, so their names do not leak, except
+/// for their objects with a public-read ACL.
+pub async fn get_object(
+    Path((namespace, bucket, key)): Path<(String, String, String)>,
+    State(AppState {
+        opendal_operator,
+        metadata,
+        etag_cache,
+        config,
+        ..
+    }): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, S3Error> {
+    if !namespaces::is_valid_name(&namespace) || is_hidden(&bucket) {
+        return Err(S3Error::NoSuchBucket);
+    }
+    if key.split('/').any(|x| x == "..") {
+        return Err(S3Error::NoSuchKey);
+    }
+
+    let filepath = format!("{}/{}/{}", namespace, bucket, key);
+    if !Namespaces::new(&metadata)
+        .is_public(&namespace, &bucket)
+        .await?
+        && !acl::stored(&metadata, &filepath).await?.is_public()
+    {
+        return Err(S3Error::NoSuchBucket);
+    }
+    let mut object = opendal_operator.stat(&filepath).await?;
+    if !object.is_file() {
+        return Err(S3Error::NoSuchKey);
+    }
+    etags::fill(&metadata, &filepath, &mut object).await?;
+
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(
+        CACHE_CONTROL,
+        HeaderValue::from_str(&format!("public, max-age={}", config.public.max_age_secs))?,
+    );
+
+    let validators = object.etag().map(|etag| Validators {
+        etag: etag.to_string(),
+        last_modified: object.last_modified().map(http_date),
+    });
+    if let Some(validators) = &validators {
+        etag_cache.insert(&filepath, validators.clone());
+
+        if etag_cache::if_none_match(&headers, &validators.etag) {
+            return Ok((response_headers, not_modified(validators)?).into_response());
+        }
+        insert_validators(&mut response_headers, validators)?;
+    }
+
+    response_headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_str(&content_type(object.content_type(), &key))?,
+    );
+    response_headers.insert(
+        CONTENT_LENGTH,
+        HeaderValue::from_str(&object.content_length().to_string())?,
+    );
+
+    let body = Body::from_stream(opendal_operator.reader(&filepath).await?);
+
+    Ok((response_headers, body).into_response())
+}
+
+#[tokio::test]
+async fn only_public_buckets_are_served() {
+    use axum::http::header::{ETAG, IF_NONE_MATCH};
+    use axum::http::{Request, StatusCode};
+    use tower::ServiceExt;
+
+    let state = AppState::builder(crate::Config::builder().build())
+        .metadata(crate::metadata::MetadataStore::memory())
+        .build()
+        .unwrap();
+    state
+        .opendal_operator
+        .write_with("tenant/site/index.html", "<html></html>")
+        .content_type("text/html")
+        .await
+        .unwrap();
+    let app = crate::router(state.clone());
+    let get = |uri: &str| Request::get(uri).body(Body::empty()).unwrap();
+
+    let response = app
+        .clone()
+        .oneshot(get("/_public/tenant/site/index.html"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    Namespaces::new(&state.metadata)
+        .set_public("tenant", "site", true)
+        .await
+        .unwrap();
+
+    let response = app
+        .clone()
+        .oneshot(get("/_public/tenant/site/index.html"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], "text/html");
+    assert_eq!(response.headers()[CACHE_CONTROL], "public, max-age=300");
+    let etag = response.headers().get(ETAG).cloned();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(body, "<html></html>");
+
+    if let Some(etag) = etag {
+        let response = app
+            .clone()
+            .oneshot(
+                Request::get("/_public/tenant/site/index.html")
+                    .header(IF_NONE_MATCH, etag)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    // objects stored without a content type get one from their extension
+    state
+        .opendal_operator
+        .write("tenant/site/style.css", "body {}")
+        .await
+        .unwrap();
+    let response = app
+        .clone()
+        .oneshot(get("/_public/tenant/site/style.css"))
+        .await
+        .unwrap();
+    assert_eq!(response.headers()[CONTENT_TYPE], "text/css");
+
+    let response = app
+        .oneshot(get("/_public/tenant/site/missing.html"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
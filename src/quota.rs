@@ -0,0 +1,152 @@
+use crate::error::S3Error;
+use crate::metadata::MetadataStore;
+use crate::metrics::storage_usage;
+use crate::multipart;
+use crate::namespaces::{Namespaces, Quota};
+use opendal::{ErrorKind, Operator};
+
+/// How many bytes an upload may still write before it exceeds the namespace or bucket quota,
+/// `None` when neither has a byte limit.
+///
+/// The usage is listed from the backend, so this is only done when a quota is set. An upload
+/// that replaces an object does not count that object twice. The parts staged for multipart
+/// uploads count as well, they are stored next to the buckets so they are added to the usage
+/// of the bucket they are uploaded to.
+pub async fn remaining_bytes(
+    operator: &Operator,
+    metadata: &MetadataStore,
+    namespace: &str,
+    bucket: &str,
+    key: &str,
+) -> Result<Option<u64>, S3Error> {
+    remaining_bytes_replacing(operator, metadata, namespace, bucket, key, 0).await
+}
+
+/// Like [`remaining_bytes`] for a write that also replaces `replaced` staged bytes: the parts a
+/// completed upload is assembled from, or a part that is uploaded again.
+pub async fn remaining_bytes_replacing(
+    operator: &Operator,
+    metadata: &MetadataStore,
+    namespace: &str,
+    bucket: &str,
+    key: &str,
+    replaced: u64,
+) -> Result<Option<u64>, S3Error> {
+    let namespaces = Namespaces::new(metadata);
+    // the usage of the namespace already lists the staged parts, that of the bucket does not
+    let limits = [
+        (
+            namespaces.quota(namespace).await?,
+            format!("{}/", namespace),
+            false,
+        ),
+        (
+            namespaces.bucket_quota(namespace, bucket).await?,
+            format!("{}/{}/", namespace, bucket),
+            true,
+        ),
+    ];
+    if limits
+        .iter()
+        .all(|(quota, _, _)| *quota == Quota::default())
+    {
+        return Ok(None);
+    }
+
+    let replaced_object = match operator
+        .stat(&format!("{}/{}/{}", namespace, bucket, key))
+        .await
+    {
+        Ok(existing) => Some(existing.content_length()),
+        Err(error) if error.kind() == ErrorKind::NotFound => None,
+        Err(error) => return Err(error.into()),
+    };
+
+    let mut remaining: Option<u64> = None;
+    for (quota, path, add_staged) in limits {
+        if quota == Quota::default() {
+            continue;
+        }
+
+        let (mut bytes, objects) = storage_usage(operator, &path).await?;
+        if let Some(max_objects) = quota.max_objects {
+            if replaced_object.is_none() && objects >= max_objects {
+                return Err(S3Error::QuotaExceeded);
+            }
+        }
+        if let Some(max_bytes) = quota.max_bytes {
+            if add_staged {
+                bytes += staged_bytes(operator, metadata, namespace, bucket).await?;
+            }
+            let used = bytes.saturating_sub(replaced_object.unwrap_or(0) + replaced);
+            let left = max_bytes.saturating_sub(used);
+            remaining = Some(remaining.map_or(left, |x| x.min(left)));
+        }
+    }
+
+    Ok(remaining)
+}
+
+/// The bytes staged for the pending multipart uploads to `bucket`.
+async fn staged_bytes(
+    operator: &Operator,
+    metadata: &MetadataStore,
+    namespace: &str,
+    bucket: &str,
+) -> Result<u64, S3Error> {
+    let mut bytes = 0;
+    for (upload_id, upload) in multipart::uploads(metadata, namespace).await? {
+        if upload.bucket == bucket {
+            let staging_dir = multipart::staging_dir(namespace, &upload_id);
+            bytes += storage_usage(operator, &staging_dir).await?.0;
+        }
+    }
+    Ok(bytes)
+}
+
+#[tokio::test]
+async fn bucket_and_namespace_quotas_are_combined() {
+    let operator = Operator::new(opendal::services::Memory::default())
+        .unwrap()
+        .finish();
+    let metadata = MetadataStore::memory();
+    let namespaces = Namespaces::new(&metadata);
+    operator
+        .write("tenant/photos/a.txt", "hello")
+        .await
+        .unwrap();
+
+    let remaining = |key: &'static str| {
+        let (operator, metadata) = (operator.clone(), metadata.clone());
+        async move { remaining_bytes(&operator, &metadata, "tenant", "photos", key).await }
+    };
+    assert_eq!(remaining("b.txt").await.unwrap(), None);
+
+    namespaces
+        .set_quota(
+            "tenant",
+            &Quota {
+                max_bytes: Some(100),
+                max_objects: None,
+            },
+        )
+        .await
+        .unwrap();
+    namespaces
+        .set_bucket_quota(
+            "tenant",
+            "photos",
+            &Quota {
+                max_bytes: Some(20),
+                max_objects: Some(1),
+            },
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(remaining("a.txt").await.unwrap(), Some(20));
+    assert!(matches!(
+        remaining("b.txt").await,
+        Err(S3Error::QuotaExceeded)
+    ));
+}
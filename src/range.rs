@@ -0,0 +1,126 @@
+use crate::error::S3Error;
+use axum::http::header::RANGE;
+use axum::http::HeaderMap;
+
+/// The inclusive byte range a `GetObject` asked for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn content_length(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    /// The `Content-Range` of the partial response.
+    pub fn content_range(&self, length: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, length)
+    }
+}
+
+/// The `Range` header resolved against an object of `length` bytes.
+///
+/// Like S3 only one range is served, headers that are not a single `bytes` range are ignored
+/// and the whole object is sent. Ranges that start after the end of the object are rejected.
+pub fn requested(headers: &HeaderMap, length: u64) -> Result<Option<ByteRange>, S3Error> {
+    let Some(spec) = headers
+        .get(RANGE)
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| x.trim().strip_prefix("bytes="))
+    else {
+        return Ok(None);
+    };
+    if spec.contains(',') {
+        return Ok(None);
+    }
+    let Some((start, end)) = spec.split_once('-') else {
+        return Ok(None);
+    };
+    let (start, end) = (start.trim(), end.trim());
+
+    let range = if start.is_empty() {
+        // the last `end` bytes
+        match end.parse::<u64>() {
+            Ok(suffix) if suffix > 0 && length > 0 => ByteRange {
+                start: length.saturating_sub(suffix),
+                end: length - 1,
+            },
+            Ok(_) => return Err(S3Error::InvalidRange),
+            Err(_) => return Ok(None),
+        }
+    } else {
+        let Ok(start) = start.parse::<u64>() else {
+            return Ok(None);
+        };
+        let end = match end.parse::<u64>() {
+            _ if end.is_empty() => u64::MAX,
+            Ok(end) if end >= start => end,
+            _ => return Ok(None),
+        };
+        ByteRange {
+            start,
+            end: end.min(length.saturating_sub(1)),
+        }
+    };
+
+    if range.start >= length {
+        return Err(S3Error::InvalidRange);
+    }
+    Ok(Some(range))
+}
+
+/// The `x-amz-copy-source-range` of `UploadPartCopy`, unlike `Range` both ends are required
+/// and have to be inside the source object.
+pub fn copy_source_range(value: &str, length: u64) -> Result<ByteRange, S3Error> {
+    let range = value
+        .trim()
+        .strip_prefix("bytes=")
+        .and_then(|x| x.split_once('-'))
+        .and_then(|(start, end)| Some((start.trim().parse().ok()?, end.trim().parse().ok()?)));
+
+    match range {
+        Some((start, end)) if start <= end && end < length => Ok(ByteRange { start, end }),
+        Some(_) => Err(S3Error::InvalidArgument(format!(
+            "Range specified is not valid for source object of size: {}",
+            length
+        ))),
+        None => Err(S3Error::InvalidArgument(String::from(
+            "The x-amz-copy-source-range value must be of the form bytes=first-last where first \
+             and last are the zero-based offsets of the first and last bytes to copy",
+        ))),
+    }
+}
+
+#[test]
+fn ranges_are_resolved_against_the_length() {
+    use axum::http::HeaderValue;
+
+    let range = |value: &'static str, length| {
+        let mut headers = HeaderMap::new();
+        headers.insert(RANGE, HeaderValue::from_static(value));
+        requested(&headers, length).map(|x| x.map(|x| (x.start, x.end)))
+    };
+
+    assert_eq!(range("bytes=0-99", 1000).unwrap(), Some((0, 99)));
+    assert_eq!(range("bytes=900-", 1000).unwrap(), Some((900, 999)));
+    assert_eq!(range("bytes=-100", 1000).unwrap(), Some((900, 999)));
+    assert_eq!(range("bytes=-2000", 1000).unwrap(), Some((0, 999)));
+    assert_eq!(range("bytes=990-2000", 1000).unwrap(), Some((990, 999)));
+    assert!(range("bytes=1000-", 1000).is_err());
+    assert!(range("bytes=-10", 0).is_err());
+    // ignored, the whole object is sent
+    assert_eq!(range("bytes=0-1,5-6", 1000).unwrap(), None);
+    assert_eq!(range("bytes=5-1", 1000).unwrap(), None);
+    assert_eq!(range("items=0-1", 1000).unwrap(), None);
+    assert_eq!(requested(&HeaderMap::new(), 1000).unwrap(), None);
+
+    let copy_range = |value, length| copy_source_range(value, length).map(|x| (x.start, x.end));
+    assert_eq!(copy_range("bytes=0-99", 1000).unwrap(), (0, 99));
+    assert_eq!(copy_range("bytes=999-999", 1000).unwrap(), (999, 999));
+    assert!(copy_range("bytes=990-1000", 1000).is_err());
+    assert!(copy_range("bytes=5-1", 1000).is_err());
+    assert!(copy_range("bytes=900-", 1000).is_err());
+    assert!(copy_range("bytes=-100", 1000).is_err());
+}
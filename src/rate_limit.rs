@@ -0,0 +1,382 @@
+use crate::error::S3Error;
+use crate::metadata::{MetadataError, MetadataStore};
+use crate::namespaces::Namespaces;
+use crate::signature::Identity;
+use crate::AppState;
+use axum::extract::{Request, State};
+use axum::http::header::RETRY_AFTER;
+use axum::http::HeaderValue;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use deadpool_redis::redis;
+use deadpool_redis::Pool;
+use http_body_util::BodyExt;
+use moka::sync::Cache;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// `rate::{namespace}::{unix second}` counts the requests of that second
+const RATE_PREFIX: &str = "rate::";
+/// `concurrent::{namespace}` counts the requests in flight
+const CONCURRENT_PREFIX: &str = "concurrent::";
+/// counters of crashed instances are forgotten after a quiet period this long
+const CONCURRENT_TTL_SECS: i64 = 300;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RateLimitConfig {
+    /// requests per second of a namespace, summed over all its keys and instances
+    pub requests_per_second: Option<u64>,
+    /// requests of a namespace handled at the same time
+    pub max_concurrent_requests: Option<u64>,
+    #[serde(default = "default_retry_after_secs")]
+    pub retry_after_secs: u64,
+    /// how long the limits of a namespace set through the admin api are cached
+    #[serde(default = "default_cache_ttl_secs")]
+    pub cache_ttl_secs: u64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            requests_per_second: None,
+            max_concurrent_requests: None,
+            retry_after_secs: default_retry_after_secs(),
+            cache_ttl_secs: default_cache_ttl_secs(),
+        }
+    }
+}
+
+fn default_retry_after_secs() -> u64 {
+    1
+}
+
+fn default_cache_ttl_secs() -> u64 {
+    10
+}
+
+/// Limits of one namespace, unset fields fall back to the configured defaults.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NamespaceLimits {
+    pub requests_per_second: Option<u64>,
+    pub max_concurrent_requests: Option<u64>,
+}
+
+impl NamespaceLimits {
+    fn or(self, defaults: &RateLimitConfig) -> NamespaceLimits {
+        NamespaceLimits {
+            requests_per_second: self.requests_per_second.or(defaults.requests_per_second),
+            max_concurrent_requests: self
+                .max_concurrent_requests
+                .or(defaults.max_concurrent_requests),
+        }
+    }
+
+    fn is_unlimited(&self) -> bool {
+        self.requests_per_second.is_none() && self.max_concurrent_requests.is_none()
+    }
+}
+
+/// Where the counters live, redis shares them between instances.
+enum Counters {
+    Redis(Pool),
+    Local(Mutex<LocalCounters>),
+}
+
+#[derive(Default)]
+struct LocalCounters {
+    /// the second and the requests in it per namespace
+    rate: HashMap<String, (u64, u64)>,
+    concurrent: HashMap<String, u64>,
+}
+
+/// Request rate and concurrency caps per namespace, shared by all keys of the tenant.
+///
+/// Limiting is best effort, when redis is unavailable requests are let through.
+pub struct RateLimiter {
+    defaults: RateLimitConfig,
+    limits: Option<Cache<String, NamespaceLimits>>,
+    counters: Arc<Counters>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig, metadata: &MetadataStore) -> RateLimiter {
+        let limits = (config.cache_ttl_secs > 0).then(|| {
+            Cache::builder()
+                .max_capacity(10_000)
+                .time_to_live(Duration::from_secs(config.cache_ttl_secs))
+                .build()
+        });
+        let counters = match metadata.redis_pool() {
+            Some(pool) => Counters::Redis(pool.clone()),
+            None => Counters::Local(Mutex::default()),
+        };
+
+        RateLimiter {
+            defaults: config.clone(),
+            limits,
+            counters: Arc::new(counters),
+        }
+    }
+
+    pub fn retry_after_secs(&self) -> u64 {
+        self.defaults.retry_after_secs
+    }
+
+    async fn limits(
+        &self,
+        metadata: &MetadataStore,
+        namespace: &str,
+    ) -> Result<NamespaceLimits, MetadataError> {
+        if let Some(limits) = self.limits.as_ref().and_then(|x| x.get(namespace)) {
+            return Ok(limits);
+        }
+
+        let limits = Namespaces::new(metadata)
+            .limits(namespace)
+            .await?
+            .or(&self.defaults);
+        if let Some(cache) = &self.limits {
+            cache.insert(namespace.to_string(), limits.clone());
+        }
+
+        Ok(limits)
+    }
+
+    /// Forgets changed limits on this instance, others pick them up after the cache ttl.
+    pub fn invalidate(&self, namespace: &str) {
+        if let Some(cache) = &self.limits {
+            cache.invalidate(namespace);
+        }
+    }
+
+    /// Counts the request, returns `None` when the namespace is over one of its limits.
+    pub async fn try_acquire(
+        &self,
+        metadata: &MetadataStore,
+        namespace: &str,
+    ) -> Result<Option<Permit>, MetadataError> {
+        let limits = self.limits(metadata, namespace).await?;
+        if limits.is_unlimited() {
+            return Ok(Some(Permit::default()));
+        }
+
+        if let Some(limit) = limits.requests_per_second {
+            if self.count_request(namespace).await? > limit {
+                return Ok(None);
+            }
+        }
+
+        let Some(limit) = limits.max_concurrent_requests else {
+            return Ok(Some(Permit::default()));
+        };
+        let permit = Permit {
+            held: Some((self.counters.clone(), namespace.to_string())),
+        };
+        if self.start_request(namespace).await? > limit {
+            // dropping the permit ends the request again
+            return Ok(None);
+        }
+
+        Ok(Some(permit))
+    }
+
+    /// The amount of requests in the current second, including this one.
+    async fn count_request(&self, namespace: &str) -> Result<u64, MetadataError> {
+        let second = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        match &*self.counters {
+            Counters::Redis(pool) => {
+                let key = format!("{}{}::{}", RATE_PREFIX, namespace, second);
+                let mut conn = pool.get().await?;
+                let (count,): (u64,) = redis::pipe()
+                    .atomic()
+                    .incr(&key, 1)
+                    .expire(&key, 2)
+                    .ignore()
+                    .query_async(&mut conn)
+                    .await?;
+                Ok(count)
+            }
+            Counters::Local(counters) => {
+                let mut counters = counters.lock().expect("lock is not poisoned");
+                let entry = counters
+                    .rate
+                    .entry(namespace.to_string())
+                    .or_insert((second, 0));
+                if entry.0 != second {
+                    *entry = (second, 0);
+                }
+                entry.1 += 1;
+                Ok(entry.1)
+            }
+        }
+    }
+
+    /// The amount of requests in flight, including this one.
+    async fn start_request(&self, namespace: &str) -> Result<u64, MetadataError> {
+        match &*self.counters {
+            Counters::Redis(pool) => {
+                let key = format!("{}{}", CONCURRENT_PREFIX, namespace);
+                let mut conn = pool.get().await?;
+                let (count,): (u64,) = redis::pipe()
+                    .atomic()
+                    .incr(&key, 1)
+                    .expire(&key, CONCURRENT_TTL_SECS)
+                    .ignore()
+                    .query_async(&mut conn)
+                    .await?;
+                Ok(count)
+            }
+            Counters::Local(counters) => {
+                let mut counters = counters.lock().expect("lock is not poisoned");
+                let count = counters
+                    .concurrent
+                    .entry(namespace.to_string())
+                    .or_default();
+                *count += 1;
+                Ok(*count)
+            }
+        }
+    }
+}
+
+/// Ends the request for the concurrency limit when it is dropped.
+#[derive(Default)]
+pub struct Permit {
+    held: Option<(Arc<Counters>, String)>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let Some((counters, namespace)) = self.held.take() else {
+            return;
+        };
+
+        match &*counters {
+            Counters::Redis(pool) => {
+                let pool = pool.clone();
+                tokio::spawn(async move {
+                    let key = format!("{}{}", CONCURRENT_PREFIX, namespace);
+                    let result: Result<(), MetadataError> = async {
+                        let mut conn = pool.get().await?;
+                        redis::cmd("DECR")
+                            .arg(&key)
+                            .query_async::<_, i64>(&mut conn)
+                            .await?;
+                        Ok(())
+                    }
+                    .await;
+                    if let Err(error) = result {
+                        tracing::warn!("unable to release the concurrency limit: {}", error);
+                    }
+                });
+            }
+            Counters::Local(counters) => {
+                let mut counters = counters.lock().expect("lock is not poisoned");
+                if let Some(count) = counters.concurrent.get_mut(&namespace) {
+                    *count = count.saturating_sub(1);
+                    if *count == 0 {
+                        counters.concurrent.remove(&namespace);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Rejects requests of namespaces over their limits with `SlowDown`, runs after authentication.
+pub async fn limit(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let Some(identity) = req.extensions().get::<Identity>() else {
+        return next.run(req).await;
+    };
+
+    let permit = match state
+        .rate_limiter
+        .try_acquire(&state.metadata, &identity.namespace)
+        .await
+    {
+        Ok(Some(permit)) => permit,
+        Ok(None) => {
+            let mut response = S3Error::SlowDown.into_response();
+            response.headers_mut().insert(
+                RETRY_AFTER,
+                HeaderValue::from(state.rate_limiter.retry_after_secs()),
+            );
+            return response;
+        }
+        Err(error) => {
+            tracing::warn!("unable to check the namespace limits: {}", error);
+            Permit::default()
+        }
+    };
+
+    let response = next.run(req).await;
+
+    // like the load shedder, the request ends once its response body is dropped
+    response.map(|body| {
+        axum::body::Body::new(body.map_frame(move |frame| {
+            let _ = &permit;
+            frame
+        }))
+    })
+}
+
+#[tokio::test]
+async fn namespaces_share_their_limits() {
+    let metadata = MetadataStore::memory();
+    let limiter = RateLimiter::new(
+        &RateLimitConfig {
+            max_concurrent_requests: Some(2),
+            ..Default::default()
+        },
+        &metadata,
+    );
+    Namespaces::new(&metadata)
+        .set_limits(
+            "slow",
+            &NamespaceLimits {
+                requests_per_second: Some(1),
+                max_concurrent_requests: None,
+            },
+        )
+        .await
+        .unwrap();
+
+    let first = limiter.try_acquire(&metadata, "tenant").await.unwrap();
+    let second = limiter.try_acquire(&metadata, "tenant").await.unwrap();
+    assert!(first.is_some() && second.is_some());
+    assert!(limiter
+        .try_acquire(&metadata, "tenant")
+        .await
+        .unwrap()
+        .is_none());
+    assert!(limiter
+        .try_acquire(&metadata, "other")
+        .await
+        .unwrap()
+        .is_some());
+    drop(first);
+    assert!(limiter
+        .try_acquire(&metadata, "tenant")
+        .await
+        .unwrap()
+        .is_some());
+
+    // the namespace limit overrides the rate and keeps the default concurrency
+    let slow = limiter.try_acquire(&metadata, "slow").await.unwrap();
+    assert!(slow.is_some());
+    let rejected = limiter.try_acquire(&metadata, "slow").await.unwrap();
+    // unless the second just ended
+    if rejected.is_some() {
+        assert!(limiter
+            .try_acquire(&metadata, "slow")
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
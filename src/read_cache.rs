@@ -0,0 +1,358 @@
+use axum::body::Bytes;
+use chrono::{DateTime, Utc};
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use moka::notification::RemovalCause;
+use moka::sync::Cache;
+use opendal::{EntryMode, Metadata};
+use serde::Deserialize;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReadCacheConfig {
+    /// local directory for the cached objects, not set disables the cache
+    pub dir: Option<String>,
+    /// the cache evicts the least used objects above this size
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: u64,
+    /// larger objects are always read from the backend
+    #[serde(default = "default_max_object_bytes")]
+    pub max_object_bytes: u64,
+    /// cached objects are served without asking the backend for this long, after that their
+    /// etag is compared with the backend first
+    #[serde(default)]
+    pub fresh_secs: u64,
+}
+
+impl Default for ReadCacheConfig {
+    fn default() -> Self {
+        ReadCacheConfig {
+            dir: None,
+            max_bytes: default_max_bytes(),
+            max_object_bytes: default_max_object_bytes(),
+            fresh_secs: 0,
+        }
+    }
+}
+
+fn default_max_bytes() -> u64 {
+    1024 * 1024 * 1024
+}
+
+fn default_max_object_bytes() -> u64 {
+    64 * 1024 * 1024
+}
+
+#[derive(Debug, Clone)]
+struct CachedObject {
+    file: PathBuf,
+    etag: String,
+    content_type: Option<String>,
+    content_length: u64,
+    last_modified: Option<DateTime<Utc>>,
+    /// shared so revalidations do not replace the entry, which would delete the file
+    revalidated_at: Arc<Mutex<Instant>>,
+}
+
+impl CachedObject {
+    /// Every field has to be set, opendal panics on reads of missing ones in debug builds.
+    fn metadata(&self) -> Option<Metadata> {
+        Some(
+            Metadata::new(EntryMode::FILE)
+                .with_etag(self.etag.clone())
+                .with_content_length(self.content_length)
+                // an empty content type is guessed from the key like a missing one
+                .with_content_type(self.content_type.clone().unwrap_or_default())
+                .with_last_modified(self.last_modified?),
+        )
+    }
+}
+
+struct Inner {
+    dir: PathBuf,
+    max_object_bytes: u64,
+    fresh: Duration,
+    index: Cache<String, CachedObject>,
+    next_file: AtomicU64,
+}
+
+/// Read-through cache of object bodies on local disk, for backends that are far away like
+/// another S3 service.
+///
+/// The index lives in memory, files of a previous run are removed on startup. Entries are
+/// keyed by path and only served while their etag matches the backend.
+#[derive(Clone, Default)]
+pub struct ReadCache {
+    inner: Option<Arc<Inner>>,
+}
+
+impl ReadCache {
+    pub fn new(config: &ReadCacheConfig) -> io::Result<ReadCache> {
+        let Some(dir) = &config.dir else {
+            return Ok(ReadCache::default());
+        };
+        let dir = PathBuf::from(dir);
+        std::fs::create_dir_all(&dir)?;
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            if is_cache_file(&entry.path()) {
+                std::fs::remove_file(entry.path())?;
+            }
+        }
+
+        let index = Cache::builder()
+            .max_capacity(config.max_bytes)
+            .weigher(|_, value: &CachedObject| {
+                value.content_length.try_into().unwrap_or(u32::MAX).max(1)
+            })
+            .eviction_listener(|_, value: CachedObject, _: RemovalCause| {
+                let _ = std::fs::remove_file(value.file);
+            })
+            .build();
+
+        Ok(ReadCache {
+            inner: Some(Arc::new(Inner {
+                dir,
+                max_object_bytes: config.max_object_bytes,
+                fresh: Duration::from_secs(config.fresh_secs),
+                index,
+                next_file: AtomicU64::new(0),
+            })),
+        })
+    }
+
+    /// The metadata of a cached object that was revalidated within `fresh_secs`.
+    pub fn fresh(&self, path: &str) -> Option<Metadata> {
+        let inner = self.inner.as_ref()?;
+        let cached = inner.index.get(path)?;
+        let revalidated_at = *cached.revalidated_at.lock().expect("lock is not poisoned");
+
+        if revalidated_at.elapsed() >= inner.fresh {
+            return None;
+        }
+
+        cached.metadata()
+    }
+
+    /// Opens the cached body when it has the etag the backend reported.
+    pub async fn open(
+        &self,
+        path: &str,
+        metadata: &Metadata,
+    ) -> Option<BoxStream<'static, io::Result<Bytes>>> {
+        let inner = self.inner.as_ref()?;
+        let cached = inner.index.get(path)?;
+        if Some(cached.etag.as_str()) != metadata.etag() {
+            inner.index.invalidate(path);
+            return None;
+        }
+
+        // an opened file can still be read after an eviction removed it
+        let Ok(file) = tokio::fs::File::open(&cached.file).await else {
+            inner.index.invalidate(path);
+            return None;
+        };
+        *cached.revalidated_at.lock().expect("lock is not poisoned") = Instant::now();
+
+        Some(read_file(file).boxed())
+    }
+
+    /// Passes `body` through and stores it, the entry is added once the body is complete.
+    pub fn fill(
+        &self,
+        path: &str,
+        metadata: &Metadata,
+        body: BoxStream<'static, io::Result<Bytes>>,
+    ) -> BoxStream<'static, io::Result<Bytes>> {
+        let Some(inner) = &self.inner else {
+            return body;
+        };
+        let Some(etag) = metadata.etag() else {
+            return body;
+        };
+        if metadata.content_length() > inner.max_object_bytes {
+            return body;
+        }
+
+        let file_name = inner.next_file.fetch_add(1, Ordering::Relaxed).to_string();
+        let fill = Fill {
+            inner: inner.clone(),
+            path: path.to_string(),
+            entry: CachedObject {
+                file: inner.dir.join(file_name),
+                etag: etag.to_string(),
+                content_type: metadata.content_type().map(String::from),
+                content_length: metadata.content_length(),
+                last_modified: metadata.last_modified(),
+                revalidated_at: Arc::new(Mutex::new(Instant::now())),
+            },
+            file: None,
+            written: 0,
+            state: FillState::Writing,
+        };
+
+        futures::stream::unfold((body, fill), |(mut body, mut fill)| async move {
+            match body.next().await {
+                Some(Ok(chunk)) => {
+                    fill.write(&chunk).await;
+                    Some((Ok(chunk), (body, fill)))
+                }
+                Some(Err(error)) => {
+                    fill.state = FillState::Failed;
+                    Some((Err(error), (body, fill)))
+                }
+                None => {
+                    fill.finish().await;
+                    None
+                }
+            }
+        })
+        .boxed()
+    }
+
+    /// Drops the cached object, for writes and deletes through the proxy.
+    pub fn invalidate(&self, path: &str) {
+        if let Some(inner) = &self.inner {
+            inner.index.invalidate(path);
+        }
+    }
+}
+
+/// Cache files are named by a counter, other files in the directory are left alone.
+fn is_cache_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|x| x.to_str())
+        .is_some_and(|x| x.parse::<u64>().is_ok())
+}
+
+fn read_file(file: tokio::fs::File) -> impl futures::Stream<Item = io::Result<Bytes>> {
+    futures::stream::unfold(Some(file), |file| async move {
+        let mut file = file?;
+        let mut buffer = vec![0; 64 * 1024];
+        match file.read(&mut buffer).await {
+            Ok(0) => None,
+            Ok(read) => {
+                buffer.truncate(read);
+                Some((Ok(Bytes::from(buffer)), Some(file)))
+            }
+            Err(error) => Some((Err(error), None)),
+        }
+    })
+}
+
+#[derive(PartialEq)]
+enum FillState {
+    Writing,
+    Failed,
+    Done,
+}
+
+struct Fill {
+    inner: Arc<Inner>,
+    path: String,
+    entry: CachedObject,
+    file: Option<tokio::fs::File>,
+    written: u64,
+    state: FillState,
+}
+
+impl Fill {
+    async fn write(&mut self, chunk: &[u8]) {
+        if self.state != FillState::Writing {
+            return;
+        }
+        if self.file.is_none() {
+            match tokio::fs::File::create(&self.entry.file).await {
+                Ok(file) => self.file = Some(file),
+                Err(error) => {
+                    tracing::warn!("unable to create read cache file: {}", error);
+                    self.state = FillState::Failed;
+                    return;
+                }
+            }
+        }
+
+        let file = self.file.as_mut().expect("file is created");
+        if let Err(error) = file.write_all(chunk).await {
+            tracing::warn!("unable to write read cache file: {}", error);
+            self.state = FillState::Failed;
+        }
+        self.written += chunk.len() as u64;
+    }
+
+    async fn finish(&mut self) {
+        if self.state != FillState::Writing || self.written != self.entry.content_length {
+            return;
+        }
+        // empty objects never created a file
+        let flushed = match self.file.as_mut() {
+            Some(file) => file.flush().await.is_ok(),
+            None => tokio::fs::File::create(&self.entry.file).await.is_ok(),
+        };
+        if flushed {
+            self.state = FillState::Done;
+            self.inner
+                .index
+                .insert(self.path.clone(), self.entry.clone());
+        }
+    }
+}
+
+impl Drop for Fill {
+    fn drop(&mut self) {
+        // the client went away or the read failed halfway
+        if self.state != FillState::Done {
+            let _ = std::fs::remove_file(&self.entry.file);
+        }
+    }
+}
+
+#[tokio::test]
+async fn objects_are_served_while_their_etag_matches() {
+    use futures::TryStreamExt;
+
+    let dir = std::env::temp_dir().join(format!("s3-proxy-read-cache-{}", std::process::id()));
+    let cache = ReadCache::new(&ReadCacheConfig {
+        dir: Some(dir.to_string_lossy().into_owned()),
+        fresh_secs: 60,
+        ..Default::default()
+    })
+    .unwrap();
+    let metadata = Metadata::new(EntryMode::FILE)
+        .with_etag(String::from("\"v1\""))
+        .with_content_length(11)
+        .with_content_type(String::from("text/plain"))
+        .with_last_modified(Utc::now());
+    let read = |body: BoxStream<'static, io::Result<Bytes>>| async move {
+        body.map_ok(|x| x.to_vec()).try_concat().await.unwrap()
+    };
+
+    assert!(cache.open("ns/bucket/a.txt", &metadata).await.is_none());
+    let body = futures::stream::iter([Ok(Bytes::from("hello ")), Ok(Bytes::from("world"))]);
+    let body = cache.fill("ns/bucket/a.txt", &metadata, body.boxed());
+    assert_eq!(read(body).await, b"hello world");
+
+    let fresh = cache.fresh("ns/bucket/a.txt").unwrap();
+    assert_eq!(fresh.etag(), Some("\"v1\""));
+    assert_eq!(fresh.content_type(), Some("text/plain"));
+    let body = cache.open("ns/bucket/a.txt", &fresh).await.unwrap();
+    assert_eq!(read(body).await, b"hello world");
+
+    // a changed object in the backend drops the entry
+    let changed = metadata.clone().with_etag(String::from("\"v2\""));
+    assert!(cache.open("ns/bucket/a.txt", &changed).await.is_none());
+    assert!(cache.fresh("ns/bucket/a.txt").is_none());
+
+    // incomplete bodies are not cached
+    let body = futures::stream::iter([Ok(Bytes::from("hello "))]);
+    let body = cache.fill("ns/bucket/b.txt", &metadata, body.boxed());
+    read(body).await;
+    assert!(cache.open("ns/bucket/b.txt", &metadata).await.is_none());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,516 @@
+use crate::events::{ObjectEvent, ObjectEventHook};
+use crate::lease::Leases;
+use crate::metadata::{MetadataError, MetadataStore};
+use crate::namespaces::Namespaces;
+use async_trait::async_trait;
+use opendal::Operator;
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+pub const REPLICATION_STATUS_PREFIX: &str = "replication_status::";
+
+#[cfg_attr(not(feature = "replication"), allow(dead_code))]
+const CHANNEL_SIZE: usize = 1024;
+
+/// The external S3 service buckets replicate to, needs the `replication` feature.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReplicationConfig {
+    pub access_key: String,
+    pub secret_key: String,
+    #[serde(default = "default_region")]
+    pub region: String,
+    /// like `http://127.0.0.1:9000` for minio, AWS when not set. Uses path style requests
+    pub endpoint: Option<String>,
+}
+
+fn default_region() -> String {
+    String::from("us-east-1")
+}
+
+/// The `ReplicationConfiguration` of a bucket, set with `PutBucketReplication`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ReplicationConfiguration {
+    /// required by S3 but not used, the proxy replicates with its own credentials
+    #[serde(default)]
+    pub role: String,
+    #[serde(default, rename = "Rule")]
+    pub rules: Vec<ReplicationRule>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ReplicationRule {
+    #[serde(default, rename = "ID")]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub priority: Option<i32>,
+    /// `Enabled` or `Disabled`
+    pub status: String,
+    /// the filter of the first version of the configuration
+    #[serde(default)]
+    pub prefix: Option<String>,
+    #[serde(default)]
+    pub filter: Option<ReplicationFilter>,
+    pub destination: ReplicationDestination,
+    #[serde(default)]
+    pub delete_marker_replication: Option<DeleteMarkerReplication>,
+}
+
+/// Only prefixes are supported, tag filters are rejected.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ReplicationFilter {
+    #[serde(default)]
+    pub prefix: Option<String>,
+    #[serde(default)]
+    pub and: Option<ReplicationFilterAnd>,
+    #[serde(default, skip_serializing)]
+    pub tag: Option<IgnoredAny>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ReplicationFilterAnd {
+    #[serde(default)]
+    pub prefix: Option<String>,
+    #[serde(default, rename = "Tag", skip_serializing)]
+    pub tags: Vec<IgnoredAny>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ReplicationDestination {
+    /// `arn:aws:s3:::{bucket}`
+    pub bucket: String,
+    #[serde(default)]
+    pub storage_class: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct DeleteMarkerReplication {
+    pub status: String,
+}
+
+impl ReplicationConfiguration {
+    /// Checks the rules, the error is the message for the client.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.rules.is_empty() {
+            return Err(String::from("the configuration has no rules"));
+        }
+
+        for rule in &self.rules {
+            if rule.status != "Enabled" && rule.status != "Disabled" {
+                return Err(format!("unsupported rule status {}", rule.status));
+            }
+            if rule.destination_bucket().is_none() {
+                return Err(format!("{} is not a bucket arn", rule.destination.bucket));
+            }
+            if let Some(filter) = &rule.filter {
+                let has_tags =
+                    filter.tag.is_some() || filter.and.as_ref().is_some_and(|x| !x.tags.is_empty());
+                if has_tags {
+                    return Err(String::from("tag filters are not supported"));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The enabled rule with the highest priority that covers the key.
+    pub fn rule_for(&self, key: &str) -> Option<&ReplicationRule> {
+        self.rules
+            .iter()
+            .filter(|x| x.status == "Enabled" && key.starts_with(x.key_prefix()))
+            .max_by_key(|x| x.priority.unwrap_or_default())
+    }
+}
+
+impl ReplicationRule {
+    fn key_prefix(&self) -> &str {
+        let filter = self.filter.as_ref();
+        filter
+            .and_then(|x| x.prefix.as_deref())
+            .or_else(|| filter.and_then(|x| x.and.as_ref()?.prefix.as_deref()))
+            .or(self.prefix.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// The bucket name of the destination arn.
+    pub fn destination_bucket(&self) -> Option<&str> {
+        self.destination
+            .bucket
+            .strip_prefix("arn:aws:s3:::")
+            .filter(|x| !x.is_empty() && !x.contains('/'))
+    }
+
+    pub fn replicates_deletes(&self) -> bool {
+        self.delete_marker_replication
+            .as_ref()
+            .is_some_and(|x| x.status == "Enabled")
+    }
+}
+
+/// The `x-amz-replication-status` of an object.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplicationStatus {
+    Pending,
+    Completed,
+    Failed,
+}
+
+impl ReplicationStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReplicationStatus::Pending => "PENDING",
+            ReplicationStatus::Completed => "COMPLETED",
+            ReplicationStatus::Failed => "FAILED",
+        }
+    }
+}
+
+fn status_key(namespace: &str, bucket: &str, key: &str) -> String {
+    format!(
+        "{}{}::{}::{}",
+        REPLICATION_STATUS_PREFIX, namespace, bucket, key
+    )
+}
+
+/// The replication status of an object, `None` when its bucket does not replicate it.
+pub async fn status(
+    metadata: &MetadataStore,
+    namespace: &str,
+    bucket: &str,
+    key: &str,
+) -> Result<Option<String>, MetadataError> {
+    metadata.get(&status_key(namespace, bucket, key)).await
+}
+
+async fn set_status(
+    metadata: &MetadataStore,
+    event: &ObjectEvent,
+    status: ReplicationStatus,
+) -> Result<(), MetadataError> {
+    metadata
+        .set(
+            &status_key(&event.namespace, &event.bucket, &event.key),
+            status.as_str(),
+        )
+        .await
+}
+
+/// A write or delete to repeat on the destination bucket.
+#[cfg_attr(not(feature = "replication"), allow(dead_code))]
+#[derive(Debug)]
+enum Job {
+    Put {
+        event: ObjectEvent,
+        destination: String,
+    },
+    Delete {
+        event: ObjectEvent,
+        destination: String,
+    },
+}
+
+/// The event hook that queues the writes and deletes of replicated buckets.
+///
+/// Objects are `PENDING` until the background task copied them, when the queue is full the
+/// object stays `PENDING` and the next write to it is replicated again.
+pub struct Replicator {
+    metadata: MetadataStore,
+    sender: mpsc::Sender<Job>,
+}
+
+impl Replicator {
+    /// The event hook for the `S3_PROXY__REPLICATION__*` settings.
+    pub fn start(
+        config: &ReplicationConfig,
+        operator: &Operator,
+        metadata: &MetadataStore,
+        leases: &Arc<Leases>,
+    ) -> anyhow::Result<Replicator> {
+        #[cfg(feature = "replication")]
+        {
+            let (sender, receiver) = mpsc::channel(CHANNEL_SIZE);
+            tokio::spawn(target::replicate(
+                target::client(config),
+                operator.clone(),
+                metadata.clone(),
+                leases.clone(),
+                receiver,
+            ));
+
+            Ok(Replicator {
+                metadata: metadata.clone(),
+                sender,
+            })
+        }
+
+        #[cfg(not(feature = "replication"))]
+        {
+            let _ = (config, operator, metadata, leases);
+            anyhow::bail!(
+                "replication is configured but s3-proxy is compiled without the `replication` feature"
+            )
+        }
+    }
+
+    async fn configuration(
+        &self,
+        event: &ObjectEvent,
+    ) -> Result<ReplicationConfiguration, MetadataError> {
+        Namespaces::new(&self.metadata)
+            .replication_configuration(&event.namespace, &event.bucket)
+            .await
+    }
+
+    fn send(&self, job: Job) {
+        if self.sender.try_send(job).is_err() {
+            tracing::warn!("replication queue is full, dropping an object");
+        }
+    }
+}
+
+#[async_trait]
+impl ObjectEventHook for Replicator {
+    async fn on_put(&self, event: &ObjectEvent) {
+        let result = async {
+            let configuration = self.configuration(event).await?;
+            let Some(destination) = configuration
+                .rule_for(&event.key)
+                .and_then(|x| x.destination_bucket())
+            else {
+                return self
+                    .metadata
+                    .delete(&status_key(&event.namespace, &event.bucket, &event.key))
+                    .await;
+            };
+
+            set_status(&self.metadata, event, ReplicationStatus::Pending).await?;
+            self.send(Job::Put {
+                event: event.clone(),
+                destination: destination.to_string(),
+            });
+
+            Ok(())
+        };
+
+        if let Err(error) = result.await {
+            tracing::error!(
+                "unable to queue the replication of {}/{}: {}",
+                event.bucket,
+                event.key,
+                error
+            );
+        }
+    }
+
+    async fn on_delete(&self, event: &ObjectEvent) {
+        let result = async {
+            self.metadata
+                .delete(&status_key(&event.namespace, &event.bucket, &event.key))
+                .await?;
+
+            let configuration = self.configuration(event).await?;
+            let rule = configuration.rule_for(&event.key);
+            if let Some(destination) = rule
+                .filter(|x| x.replicates_deletes())
+                .and_then(|x| x.destination_bucket())
+            {
+                self.send(Job::Delete {
+                    event: event.clone(),
+                    destination: destination.to_string(),
+                });
+            }
+
+            Ok::<_, MetadataError>(())
+        };
+
+        if let Err(error) = result.await {
+            tracing::error!(
+                "unable to queue the replicated delete of {}/{}: {}",
+                event.bucket,
+                event.key,
+                error
+            );
+        }
+    }
+}
+
+#[cfg(feature = "replication")]
+mod target {
+    use super::{set_status, Job, ReplicationConfig, ReplicationStatus};
+    use crate::lease::Leases;
+    use crate::metadata::MetadataStore;
+    use aws_sdk_s3::config::{BehaviorVersion, Credentials, Region};
+    use aws_sdk_s3::primitives::ByteStream;
+    use aws_sdk_s3::Client;
+    use opendal::Operator;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+
+    /// how long a job waits for another instance replicating the same key
+    const LEASE_WAIT: Duration = Duration::from_secs(60);
+
+    pub fn client(config: &ReplicationConfig) -> Client {
+        let mut client_config = aws_sdk_s3::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new(config.region.clone()))
+            .credentials_provider(Credentials::new(
+                &config.access_key,
+                &config.secret_key,
+                None,
+                None,
+                "s3-proxy",
+            ));
+        if let Some(endpoint) = &config.endpoint {
+            client_config = client_config.endpoint_url(endpoint).force_path_style(true);
+        }
+
+        Client::from_conf(client_config.build())
+    }
+
+    /// Jobs run one at a time and hold the lease of their key, so the writes to a key reach the
+    /// destination in order, also when they were received by different instances.
+    pub async fn replicate(
+        client: Client,
+        operator: Operator,
+        metadata: MetadataStore,
+        leases: Arc<Leases>,
+        mut receiver: mpsc::Receiver<Job>,
+    ) {
+        while let Some(job) = receiver.recv().await {
+            let (Job::Put { event, .. } | Job::Delete { event, .. }) = &job;
+            let name = format!(
+                "replication::{}/{}/{}",
+                event.namespace, event.bucket, event.key
+            );
+            let _lease = match leases.acquire(&name, LEASE_WAIT).await {
+                Ok(Some(lease)) => Some(lease),
+                Ok(None) => {
+                    tracing::warn!("{} is still held, replicating anyway", name);
+                    None
+                }
+                Err(error) => {
+                    tracing::warn!("unable to take {}, replicating anyway: {}", name, error);
+                    None
+                }
+            };
+
+            match job {
+                Job::Put { event, destination } => {
+                    let status = match copy(&client, &operator, &event, &destination).await {
+                        Ok(()) => ReplicationStatus::Completed,
+                        Err(error) => {
+                            tracing::error!(
+                                "unable to replicate {}/{} to {}: {:#}",
+                                event.bucket,
+                                event.key,
+                                destination,
+                                error
+                            );
+                            ReplicationStatus::Failed
+                        }
+                    };
+
+                    if let Err(error) = set_status(&metadata, &event, status).await {
+                        tracing::error!("unable to store the replication status: {}", error);
+                    }
+                }
+                Job::Delete { event, destination } => {
+                    let deleted = client
+                        .delete_object()
+                        .bucket(&destination)
+                        .key(&event.key)
+                        .send()
+                        .await;
+                    if let Err(error) = deleted {
+                        tracing::error!(
+                            "unable to replicate the delete of {}/{} to {}: {}",
+                            event.bucket,
+                            event.key,
+                            destination,
+                            aws_sdk_s3::error::DisplayErrorContext(error)
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    async fn copy(
+        client: &Client,
+        operator: &Operator,
+        event: &crate::events::ObjectEvent,
+        destination: &str,
+    ) -> anyhow::Result<()> {
+        let path = format!("{}/{}/{}", event.namespace, event.bucket, event.key);
+        let metadata = operator.stat(&path).await?;
+        let data = operator.read(&path).await?;
+
+        let mut request = client
+            .put_object()
+            .bucket(destination)
+            .key(&event.key)
+            .body(ByteStream::from(data));
+        if let Some(content_type) = metadata.content_type() {
+            request = request.content_type(content_type);
+        }
+        request.send().await?;
+
+        Ok(())
+    }
+}
+
+#[test]
+fn replication_rules_are_picked_by_prefix_and_priority() {
+    let xml = r#"<ReplicationConfiguration xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+        <Role>arn:aws:iam::000000000000:role/replication</Role>
+        <Rule>
+            <ID>everything</ID>
+            <Priority>1</Priority>
+            <Status>Enabled</Status>
+            <Filter><Prefix></Prefix></Filter>
+            <Destination><Bucket>arn:aws:s3:::backup</Bucket></Destination>
+        </Rule>
+        <Rule>
+            <ID>images</ID>
+            <Priority>2</Priority>
+            <Status>Enabled</Status>
+            <Filter><And><Prefix>images/</Prefix></And></Filter>
+            <Destination><Bucket>arn:aws:s3:::images</Bucket></Destination>
+            <DeleteMarkerReplication><Status>Enabled</Status></DeleteMarkerReplication>
+        </Rule>
+        <Rule>
+            <Status>Disabled</Status>
+            <Prefix>images/raw/</Prefix>
+            <Destination><Bucket>arn:aws:s3:::raw</Bucket></Destination>
+        </Rule>
+    </ReplicationConfiguration>"#;
+
+    let configuration: ReplicationConfiguration = quick_xml::de::from_str(xml).unwrap();
+    assert_eq!(configuration.validate(), Ok(()));
+
+    let rule = configuration.rule_for("images/raw/a.jpg").unwrap();
+    assert_eq!(rule.destination_bucket(), Some("images"));
+    assert!(rule.replicates_deletes());
+
+    let rule = configuration.rule_for("docs/a.txt").unwrap();
+    assert_eq!(rule.destination_bucket(), Some("backup"));
+    assert!(!rule.replicates_deletes());
+
+    let tagged: ReplicationConfiguration = quick_xml::de::from_str(
+        "<ReplicationConfiguration><Rule><Status>Enabled</Status>\
+         <Filter><Tag><Key>a</Key><Value>b</Value></Tag></Filter>\
+         <Destination><Bucket>arn:aws:s3:::backup</Bucket></Destination>\
+         </Rule></ReplicationConfiguration>",
+    )
+    .unwrap();
+    assert!(tagged.validate().is_err());
+}
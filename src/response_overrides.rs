@@ -0,0 +1,72 @@
+use crate::axum_ext::decoded_query_value;
+use crate::error::S3Error;
+use axum::http::header::{
+    CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_ENCODING, CONTENT_LANGUAGE, CONTENT_TYPE, EXPIRES,
+};
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+
+/// The query parameters of `GetObject` that replace a header of the response, browsers get
+/// download links with a filename this way.
+pub const PARAMETERS: &[(&str, HeaderName)] = &[
+    ("response-cache-control", CACHE_CONTROL),
+    ("response-content-disposition", CONTENT_DISPOSITION),
+    ("response-content-encoding", CONTENT_ENCODING),
+    ("response-content-language", CONTENT_LANGUAGE),
+    ("response-content-type", CONTENT_TYPE),
+    ("response-expires", EXPIRES),
+];
+
+/// The headers the query overrides, with their percent-decoded values.
+pub fn requested(query: &str) -> Result<Vec<(HeaderName, HeaderValue)>, S3Error> {
+    PARAMETERS
+        .iter()
+        .filter_map(|(parameter, header)| {
+            let value = decoded_query_value(query, parameter)?;
+            Some(
+                HeaderValue::from_str(&value)
+                    .map(|value| (header.clone(), value))
+                    .map_err(|_| {
+                        S3Error::InvalidArgument(format!(
+                            "{} is not a valid header value",
+                            parameter
+                        ))
+                    }),
+            )
+        })
+        .collect()
+}
+
+/// Replaces the headers of the response, the stored metadata is left as is.
+pub fn apply(headers: &mut HeaderMap, overrides: &[(HeaderName, HeaderValue)]) {
+    for (name, value) in overrides {
+        headers.insert(name.clone(), value.clone());
+    }
+}
+
+#[test]
+fn overrides_are_read_from_the_query() {
+    let overrides = requested(
+        "x-id=GetObject&response-content-type=text%2Fcsv\
+         &response-content-disposition=attachment%3B%20filename%3D%22report.csv%22",
+    )
+    .unwrap();
+    assert_eq!(
+        overrides,
+        [
+            (
+                CONTENT_DISPOSITION,
+                HeaderValue::from_static("attachment; filename=\"report.csv\"")
+            ),
+            (CONTENT_TYPE, HeaderValue::from_static("text/csv")),
+        ]
+    );
+
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+    apply(&mut headers, &overrides);
+    assert_eq!(headers[CONTENT_TYPE], "text/csv");
+    assert_eq!(headers.len(), 2);
+
+    assert!(requested("response-cache-control=a%0Ab").is_err());
+    assert!(requested("x-id=GetObject").unwrap().is_empty());
+}
@@ -0,0 +1,137 @@
+use crate::context::RequestSummary;
+use crate::operation::S3Operation;
+use crate::REQUEST_ID_HEADER;
+use axum::http::Request;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Arc;
+use tower_http::trace::MakeSpan;
+use tracing::Span;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TraceSamplingConfig {
+    /// fraction of requests that get a trace span, between 0.0 and 1.0
+    #[serde(default = "default_rate")]
+    pub rate: f64,
+    /// per operation overrides of the rate, keyed by the operation name (case insensitive)
+    #[serde(default)]
+    pub operations: HashMap<String, f64>,
+}
+
+impl Default for TraceSamplingConfig {
+    fn default() -> Self {
+        TraceSamplingConfig {
+            rate: default_rate(),
+            operations: HashMap::new(),
+        }
+    }
+}
+
+fn default_rate() -> f64 {
+    1.0
+}
+
+impl TraceSamplingConfig {
+    pub fn rate_for(&self, operation: S3Operation) -> f64 {
+        self.operations
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(operation.as_str()))
+            .map(|(_, rate)| *rate)
+            .unwrap_or(self.rate)
+    }
+
+    /// Head based sampling decision, derived from the request id so every
+    /// instance makes the same decision for the same request.
+    pub fn should_sample(&self, operation: S3Operation, request_id: &str) -> bool {
+        let rate = self.rate_for(operation);
+        if rate >= 1.0 {
+            return true;
+        }
+        if rate <= 0.0 {
+            return false;
+        }
+
+        let mut hasher = DefaultHasher::new();
+        request_id.hash(&mut hasher);
+        let fraction = (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64;
+
+        fraction < rate
+    }
+}
+
+/// Creates the request span for sampled requests only.
+#[derive(Debug, Clone)]
+pub struct SampledMakeSpan {
+    config: Arc<TraceSamplingConfig>,
+}
+
+impl SampledMakeSpan {
+    pub fn new(config: TraceSamplingConfig) -> SampledMakeSpan {
+        SampledMakeSpan {
+            config: Arc::new(config),
+        }
+    }
+}
+
+impl<B> MakeSpan<B> for SampledMakeSpan {
+    fn make_span(&mut self, request: &Request<B>) -> Span {
+        let operation = S3Operation::from_request(request.method(), request.uri());
+        let request_id = request
+            .headers()
+            .get(&REQUEST_ID_HEADER)
+            .and_then(|x| x.to_str().ok())
+            .unwrap_or_default();
+
+        if !self.config.should_sample(operation, request_id) {
+            return Span::none();
+        }
+
+        tracing::debug_span!(
+            "request",
+            method = %request.method(),
+            uri = %request.uri(),
+            version = ?request.version(),
+            %operation,
+            request_id,
+        )
+    }
+}
+
+/// Server errors are always logged with the full request context, also for unsampled requests.
+pub fn report(summary: &RequestSummary) {
+    if !summary.status.is_server_error() {
+        return;
+    }
+
+    let context = summary.context;
+    tracing::error!(
+        operation = %context.operation,
+        method = %context.method,
+        path = %context.path,
+        request_id = %context.request_id,
+        namespace = context.namespace().unwrap_or("-"),
+        status = summary.status.as_u16(),
+        total_ms = summary.total.as_millis() as u64,
+        "request failed"
+    );
+}
+
+#[test]
+fn sampling_uses_operation_overrides() {
+    let config = TraceSamplingConfig {
+        rate: 0.0,
+        operations: HashMap::from([
+            (String::from("getobject"), 1.0),
+            (String::from("PutObject"), 0.5),
+        ]),
+    };
+
+    assert!(config.should_sample(S3Operation::GetObject, "abc"));
+    assert!(!config.should_sample(S3Operation::ListBuckets, "abc"));
+
+    let sampled = (0..1000)
+        .filter(|i| config.should_sample(S3Operation::PutObject, &i.to_string()))
+        .count();
+    assert!((400..600).contains(&sampled), "{sampled}");
+}
@@ -0,0 +1,309 @@
+use crate::credentials::{KEY_NAMESPACE_PREFIX, SECRET_KEY_PREFIX};
+use crate::metadata::MetadataStore;
+use crate::namespaces::{
+    BUCKET_RECORD_PREFIXES, DEFAULT_BUCKETS_PREFIX, NAMESPACE_PREFIX, POLICY_PREFIX, QUOTA_PREFIX,
+};
+use crate::object_lock;
+use crate::AppState;
+use anyhow::Context;
+use futures::StreamExt;
+use opendal::Operator;
+use std::collections::BTreeSet;
+
+/// Orphaned objects are moved below this directory, it is skipped by the scan itself.
+pub const QUARANTINE_DIR: &str = ".quarantine/";
+
+/// Backend objects and metadata records that do not belong to each other.
+#[derive(Debug, Default, PartialEq)]
+pub struct ScanReport {
+    /// objects of unknown namespaces, or outside of a bucket
+    pub orphan_objects: Vec<String>,
+    /// namespace directories without a namespace record or key
+    pub orphan_namespaces: Vec<String>,
+    /// metadata keys that point to a namespace or bucket that does not exist
+    pub stale_records: Vec<String>,
+    /// orphaned objects that are kept because they are locked
+    pub locked_objects: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScanAction {
+    Report,
+    Quarantine,
+    Delete,
+}
+
+/// Compares the backend with the metadata store, nothing is changed.
+///
+/// Namespaces are known when they have a `namespace::` record or, for namespaces from before
+/// those records, a key named after them.
+pub async fn scan(operator: &Operator, metadata: &MetadataStore) -> anyhow::Result<ScanReport> {
+    let mut known: BTreeSet<String> = metadata
+        .keys(NAMESPACE_PREFIX)
+        .await?
+        .into_iter()
+        .filter_map(|x| x.strip_prefix(NAMESPACE_PREFIX).map(String::from))
+        .collect();
+    known.extend(
+        metadata
+            .keys(SECRET_KEY_PREFIX)
+            .await?
+            .into_iter()
+            .filter_map(|x| x.strip_prefix(SECRET_KEY_PREFIX).map(String::from)),
+    );
+
+    let mut report = ScanReport::default();
+    let mut buckets = BTreeSet::new();
+
+    let mut namespaces = operator.lister("/").await?;
+    while let Some(entry) = namespaces.next().await {
+        let entry = entry?;
+        let path = entry.path().to_string();
+        if path == "/" || path == QUARANTINE_DIR {
+            continue;
+        }
+        if !entry.metadata().is_dir() {
+            report.orphan_objects.push(path);
+            continue;
+        }
+
+        let namespace = path.trim_end_matches('/');
+        if !known.contains(namespace) {
+            report.orphan_namespaces.push(namespace.to_string());
+            report
+                .orphan_objects
+                .extend(list_files(operator, &path).await?);
+            continue;
+        }
+
+        let mut entries = operator.lister(&path).await?;
+        while let Some(entry) = entries.next().await {
+            let entry = entry?;
+            if entry.path() == path {
+                continue;
+            }
+            if entry.metadata().is_dir() {
+                buckets.insert(entry.path().trim_end_matches('/').to_string());
+            } else {
+                report.orphan_objects.push(entry.path().to_string());
+            }
+        }
+    }
+
+    let mappings = metadata.keys(KEY_NAMESPACE_PREFIX).await?;
+    let mapped = metadata.get_many(&mappings).await?;
+    for (key, namespace) in mappings.into_iter().zip(mapped) {
+        if namespace.is_some_and(|x| !known.contains(&x)) {
+            report.stale_records.push(key);
+        }
+    }
+    for prefix in [QUOTA_PREFIX, POLICY_PREFIX, DEFAULT_BUCKETS_PREFIX] {
+        for key in metadata.keys(prefix).await? {
+            if !known.contains(&key[prefix.len()..]) {
+                report.stale_records.push(key);
+            }
+        }
+    }
+    for prefix in BUCKET_RECORD_PREFIXES {
+        for key in metadata.keys(prefix).await? {
+            let bucket = key[prefix.len()..].replacen("::", "/", 1);
+            if !buckets.contains(&bucket) {
+                report.stale_records.push(key);
+            }
+        }
+    }
+
+    report.orphan_objects.sort();
+    report.stale_records.sort();
+
+    // locked objects are kept, also when their namespace or bucket is gone
+    let now = chrono::Utc::now().timestamp();
+    for path in std::mem::take(&mut report.orphan_objects) {
+        match object_lock::stored(metadata, &path)
+            .await?
+            .check(false, now)
+        {
+            Ok(()) => report.orphan_objects.push(path),
+            Err(_) => report.locked_objects.push(path),
+        }
+    }
+
+    Ok(report)
+}
+
+async fn list_files(operator: &Operator, path: &str) -> opendal::Result<Vec<String>> {
+    let mut files = Vec::new();
+    let mut lister = operator.lister_with(path).recursive(true).await?;
+    while let Some(entry) = lister.next().await {
+        let entry = entry?;
+        if entry.metadata().is_file() {
+            files.push(entry.path().to_string());
+        }
+    }
+
+    Ok(files)
+}
+
+/// Quarantines or deletes the orphaned objects. Deleting also removes the stale records and
+/// the empty orphan namespace directories.
+pub async fn apply(
+    operator: &Operator,
+    metadata: &MetadataStore,
+    report: &ScanReport,
+    action: ScanAction,
+) -> anyhow::Result<()> {
+    match action {
+        ScanAction::Report => (),
+        ScanAction::Quarantine => {
+            // read and write, not every backend can copy or rename
+            for path in &report.orphan_objects {
+                let data = operator.read(path).await?;
+                operator
+                    .write(&format!("{}{}", QUARANTINE_DIR, path), data)
+                    .await?;
+                operator.delete(path).await?;
+            }
+        }
+        ScanAction::Delete => {
+            for path in &report.orphan_objects {
+                operator.delete(path).await?;
+            }
+            for namespace in &report.orphan_namespaces {
+                let path = format!("{}/", namespace);
+                if !report.locked_objects.iter().any(|x| x.starts_with(&path)) {
+                    operator.remove_all(&path).await?;
+                }
+            }
+            for key in &report.stale_records {
+                metadata.delete(key).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+const USAGE: &str = "usage: s3-proxy scan-orphans [--quarantine | --delete]";
+
+/// The `scan-orphans` subcommand, uses the backend and metadata store of the server config.
+pub async fn run(app_state: &AppState, args: &[String]) -> anyhow::Result<()> {
+    let action = match args {
+        [] => ScanAction::Report,
+        [flag] if flag == "--quarantine" => ScanAction::Quarantine,
+        [flag] if flag == "--delete" => ScanAction::Delete,
+        _ => anyhow::bail!(USAGE),
+    };
+
+    let operator = &app_state.opendal_operator;
+    let report = scan(operator, &app_state.metadata)
+        .await
+        .context("unable to scan the backend")?;
+
+    for namespace in &report.orphan_namespaces {
+        println!("namespace without record: {}", namespace);
+    }
+    for path in &report.orphan_objects {
+        println!("orphaned object: {}", path);
+    }
+    for key in &report.stale_records {
+        println!("stale record: {}", key);
+    }
+    for path in &report.locked_objects {
+        println!("locked orphaned object, kept: {}", path);
+    }
+
+    apply(operator, &app_state.metadata, &report, action).await?;
+
+    match action {
+        ScanAction::Report => println!(
+            "{} orphaned objects, {} stale records",
+            report.orphan_objects.len(),
+            report.stale_records.len()
+        ),
+        ScanAction::Quarantine => println!(
+            "moved {} objects to {}",
+            report.orphan_objects.len(),
+            QUARANTINE_DIR
+        ),
+        ScanAction::Delete => println!(
+            "deleted {} objects and {} records",
+            report.orphan_objects.len(),
+            report.stale_records.len()
+        ),
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn orphans_are_found_and_quarantined() {
+    let operator = Operator::new(opendal::services::Memory::default())
+        .unwrap()
+        .finish();
+    let metadata = MetadataStore::memory();
+    metadata.set("namespace::tenant", "1").await.unwrap();
+    metadata.set("secret_key::OLDKEY", "secret").await.unwrap();
+    metadata.set("quota::removed", "{}").await.unwrap();
+    metadata
+        .set("bucket_quota::tenant::photos", "{}")
+        .await
+        .unwrap();
+    metadata
+        .set("bucket_quota::tenant::removed", "{}")
+        .await
+        .unwrap();
+
+    for path in [
+        "tenant/photos/a.txt",
+        "tenant/stray.txt",
+        "OLDKEY/logs/b.txt",
+        "unknown/bucket/c.txt",
+        "unknown/bucket/held.txt",
+    ] {
+        operator.write(path, "data").await.unwrap();
+    }
+    object_lock::store(
+        &metadata,
+        "unknown/bucket/held.txt",
+        &object_lock::ObjectLock {
+            retention: None,
+            legal_hold: true,
+        },
+    )
+    .await
+    .unwrap();
+
+    let report = scan(&operator, &metadata).await.unwrap();
+    assert_eq!(
+        report,
+        ScanReport {
+            orphan_objects: vec![
+                String::from("tenant/stray.txt"),
+                String::from("unknown/bucket/c.txt")
+            ],
+            orphan_namespaces: vec![String::from("unknown")],
+            stale_records: vec![
+                String::from("bucket_quota::tenant::removed"),
+                String::from("quota::removed")
+            ],
+            locked_objects: vec![String::from("unknown/bucket/held.txt")],
+        }
+    );
+
+    apply(&operator, &metadata, &report, ScanAction::Quarantine)
+        .await
+        .unwrap();
+    assert!(operator
+        .is_exist(".quarantine/tenant/stray.txt")
+        .await
+        .unwrap());
+    assert!(!operator.is_exist("tenant/stray.txt").await.unwrap());
+    assert!(operator.is_exist("tenant/photos/a.txt").await.unwrap());
+
+    // the namespace of a locked object is not removed
+    let report = scan(&operator, &metadata).await.unwrap();
+    apply(&operator, &metadata, &report, ScanAction::Delete)
+        .await
+        .unwrap();
+    assert!(operator.is_exist("unknown/bucket/held.txt").await.unwrap());
+}
@@ -0,0 +1,1292 @@
+use crate::error::S3Error;
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::io;
+
+/// The content type of the event stream `SelectObjectContent` answers with.
+pub const EVENT_STREAM_CONTENT_TYPE: &str = "application/vnd.amazon.eventstream";
+
+/// The `<SelectObjectContentRequest>` body.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct SelectRequest {
+    pub expression: String,
+    pub expression_type: String,
+    pub input_serialization: InputSerialization,
+    pub output_serialization: OutputSerialization,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct InputSerialization {
+    pub compression_type: Option<String>,
+    #[serde(rename = "CSV")]
+    pub csv: Option<CsvInput>,
+    #[serde(rename = "JSON")]
+    pub json: Option<JsonInput>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CsvInput {
+    pub file_header_info: Option<String>,
+    pub comments: Option<String>,
+    pub field_delimiter: Option<String>,
+    pub quote_character: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct JsonInput {
+    #[serde(rename = "Type")]
+    pub json_type: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct OutputSerialization {
+    #[serde(rename = "CSV")]
+    pub csv: Option<CsvOutput>,
+    #[serde(rename = "JSON")]
+    pub json: Option<JsonOutput>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CsvOutput {
+    pub field_delimiter: Option<String>,
+    pub record_delimiter: Option<String>,
+    pub quote_character: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct JsonOutput {
+    pub record_delimiter: Option<String>,
+}
+
+/// A parsed `SelectObjectContent` request, ready to run over the object.
+#[derive(Debug, Clone)]
+pub struct Selection {
+    query: Query,
+    input: Input,
+    output: Output,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FileHeaderInfo {
+    /// the first line names the columns
+    Use,
+    /// the first line is skipped
+    Ignore,
+    None,
+}
+
+#[derive(Debug, Clone)]
+enum Input {
+    Csv {
+        header: FileHeaderInfo,
+        comments: Option<u8>,
+        delimiter: u8,
+        quote: u8,
+    },
+    JsonLines,
+    /// the object is read whole, every top level value is a record
+    JsonDocument,
+}
+
+#[derive(Debug, Clone)]
+enum Output {
+    Csv {
+        delimiter: String,
+        record_delimiter: String,
+        quote: char,
+    },
+    Json {
+        record_delimiter: String,
+    },
+}
+
+fn single_byte(value: Option<&str>, default: u8, name: &str) -> Result<u8, S3Error> {
+    match value {
+        None => Ok(default),
+        Some(value) if value.len() == 1 => Ok(value.as_bytes()[0]),
+        Some(_) => Err(S3Error::InvalidArgument(format!(
+            "{} must be a single character",
+            name
+        ))),
+    }
+}
+
+impl Selection {
+    pub fn new(request: &SelectRequest) -> Result<Selection, S3Error> {
+        if !request.expression_type.eq_ignore_ascii_case("SQL") {
+            return Err(S3Error::InvalidArgument(String::from(
+                "The ExpressionType is invalid. Only SQL expressions are supported.",
+            )));
+        }
+        if let Some(compression) = &request.input_serialization.compression_type {
+            if !compression.eq_ignore_ascii_case("NONE") {
+                return Err(S3Error::NotImplemented(format!(
+                    "{} compressed objects can not be selected from",
+                    compression
+                )));
+            }
+        }
+
+        let input = match (
+            &request.input_serialization.csv,
+            &request.input_serialization.json,
+        ) {
+            (Some(csv), None) => Input::Csv {
+                header: match csv.file_header_info.as_deref().map(str::to_uppercase) {
+                    Some(x) if x == "USE" => FileHeaderInfo::Use,
+                    Some(x) if x == "IGNORE" => FileHeaderInfo::Ignore,
+                    Some(x) if x == "NONE" => FileHeaderInfo::None,
+                    None => FileHeaderInfo::None,
+                    Some(_) => {
+                        return Err(S3Error::InvalidArgument(String::from(
+                            "FileHeaderInfo must be USE, IGNORE or NONE",
+                        )))
+                    }
+                },
+                comments: csv
+                    .comments
+                    .as_deref()
+                    .map(|x| single_byte(Some(x), b'#', "Comments"))
+                    .transpose()?,
+                delimiter: single_byte(csv.field_delimiter.as_deref(), b',', "FieldDelimiter")?,
+                quote: single_byte(csv.quote_character.as_deref(), b'"', "QuoteCharacter")?,
+            },
+            (None, Some(json)) => match json.json_type.as_deref().map(str::to_uppercase) {
+                Some(x) if x == "LINES" => Input::JsonLines,
+                Some(x) if x == "DOCUMENT" => Input::JsonDocument,
+                _ => {
+                    return Err(S3Error::InvalidArgument(String::from(
+                        "The JSON Type must be DOCUMENT or LINES",
+                    )))
+                }
+            },
+            _ => {
+                return Err(S3Error::InvalidArgument(String::from(
+                    "The InputSerialization must be one of CSV or JSON",
+                )))
+            }
+        };
+
+        let output = match (
+            &request.output_serialization.csv,
+            &request.output_serialization.json,
+        ) {
+            (Some(csv), None) => Output::Csv {
+                delimiter: csv
+                    .field_delimiter
+                    .clone()
+                    .unwrap_or_else(|| String::from(",")),
+                record_delimiter: csv
+                    .record_delimiter
+                    .clone()
+                    .unwrap_or_else(|| String::from("\n")),
+                quote: single_byte(csv.quote_character.as_deref(), b'"', "QuoteCharacter")? as char,
+            },
+            (None, Some(json)) => Output::Json {
+                record_delimiter: json
+                    .record_delimiter
+                    .clone()
+                    .unwrap_or_else(|| String::from("\n")),
+            },
+            _ => {
+                return Err(S3Error::InvalidArgument(String::from(
+                    "The OutputSerialization must be one of CSV or JSON",
+                )))
+            }
+        };
+
+        Ok(Selection {
+            query: parse_query(&request.expression).map_err(S3Error::InvalidRequest)?,
+            input,
+            output,
+        })
+    }
+
+    /// Runs the query over the object and frames the results as S3 event stream messages,
+    /// records are sent as the object is read. A record that can not be read ends the stream
+    /// with an error message.
+    pub fn run<S>(self, object: S) -> impl Stream<Item = io::Result<Bytes>> + Send
+    where
+        S: Stream<Item = io::Result<Bytes>> + Send + Unpin,
+    {
+        let scan = Scan {
+            selection: self,
+            buffer: Vec::new(),
+            columns: None,
+            first_record: true,
+            returned_records: 0,
+            bytes_scanned: 0,
+            bytes_returned: 0,
+        };
+
+        futures::stream::unfold(Some((object, scan)), |state| async move {
+            let (mut object, mut scan) = state?;
+            loop {
+                if scan.limit_reached() {
+                    return Some((Ok(scan.finish(Vec::new())), None));
+                }
+
+                let records = match object.next().await {
+                    Some(Ok(chunk)) => scan.feed(&chunk),
+                    Some(Err(error)) => return Some((Err(error), None)),
+                    None => {
+                        return match scan.flush() {
+                            Ok(records) => Some((Ok(scan.finish(records)), None)),
+                            Err(message) => Some((Ok(error_message(&message)), None)),
+                        }
+                    }
+                };
+                match records {
+                    Ok(records) if records.is_empty() => continue,
+                    Ok(records) => {
+                        scan.bytes_returned += records.len() as u64;
+                        return Some((Ok(records_message(&records)), Some((object, scan))));
+                    }
+                    Err(message) => return Some((Ok(error_message(&message)), None)),
+                }
+            }
+        })
+    }
+}
+
+/// The state of a running selection.
+struct Scan {
+    selection: Selection,
+    /// the start of a record that was not read completely yet
+    buffer: Vec<u8>,
+    /// the column names of a csv with a header line
+    columns: Option<Vec<String>>,
+    first_record: bool,
+    returned_records: u64,
+    bytes_scanned: u64,
+    bytes_returned: u64,
+}
+
+impl Scan {
+    fn limit_reached(&self) -> bool {
+        self.selection
+            .query
+            .limit
+            .is_some_and(|x| self.returned_records >= x)
+    }
+
+    /// The output of the records completed by `chunk`.
+    fn feed(&mut self, chunk: &[u8]) -> Result<Vec<u8>, String> {
+        self.bytes_scanned += chunk.len() as u64;
+        self.buffer.extend_from_slice(chunk);
+        if matches!(self.selection.input, Input::JsonDocument) {
+            return Ok(Vec::new());
+        }
+
+        let quote = match self.selection.input {
+            Input::Csv { quote, .. } => Some(quote),
+            _ => None,
+        };
+        let mut output = Vec::new();
+        let mut start = 0;
+        while let Some(end) = record_end(&self.buffer[start..], quote) {
+            let line = self.buffer[start..start + end].to_vec();
+            start += end + 1;
+            self.record(&line, &mut output)?;
+            if self.limit_reached() {
+                break;
+            }
+        }
+        self.buffer.drain(..start);
+
+        Ok(output)
+    }
+
+    /// The output of the last record, or of the whole document.
+    fn flush(&mut self) -> Result<Vec<u8>, String> {
+        let rest = std::mem::take(&mut self.buffer);
+        let mut output = Vec::new();
+        if matches!(self.selection.input, Input::JsonDocument) {
+            for value in serde_json::Deserializer::from_slice(&rest).into_iter::<Value>() {
+                let value = value.map_err(|error| format!("Invalid JSON: {}", error))?;
+                self.evaluate(&Record::Json(value), &mut output);
+                if self.limit_reached() {
+                    break;
+                }
+            }
+        } else if !self.limit_reached() {
+            self.record(&rest, &mut output)?;
+        }
+
+        Ok(output)
+    }
+
+    fn record(&mut self, line: &[u8], output: &mut Vec<u8>) -> Result<(), String> {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.iter().all(u8::is_ascii_whitespace) {
+            return Ok(());
+        }
+
+        let record = match self.selection.input {
+            Input::Csv {
+                header,
+                comments,
+                delimiter,
+                quote,
+            } => {
+                if comments.is_some_and(|x| line.first() == Some(&x)) {
+                    return Ok(());
+                }
+                let fields = csv_fields(line, delimiter, quote);
+                if std::mem::take(&mut self.first_record) && header != FileHeaderInfo::None {
+                    if header == FileHeaderInfo::Use {
+                        self.columns = Some(fields);
+                    }
+                    return Ok(());
+                }
+                Record::Csv {
+                    columns: self.columns.as_deref(),
+                    fields,
+                }
+            }
+            Input::JsonLines | Input::JsonDocument => Record::Json(
+                serde_json::from_slice(line).map_err(|error| format!("Invalid JSON: {}", error))?,
+            ),
+        };
+
+        // not `evaluate`, the record borrows the columns
+        if self.selection.query.matches(&record) {
+            output.extend_from_slice(&render(&self.selection, &record));
+            self.returned_records += 1;
+        }
+        Ok(())
+    }
+
+    fn evaluate(&mut self, record: &Record, output: &mut Vec<u8>) {
+        if self.selection.query.matches(record) {
+            output.extend_from_slice(&render(&self.selection, record));
+            self.returned_records += 1;
+        }
+    }
+
+    /// The last records followed by the `Stats` and `End` messages.
+    fn finish(&mut self, records: Vec<u8>) -> Bytes {
+        let mut messages = Vec::new();
+        if !records.is_empty() {
+            self.bytes_returned += records.len() as u64;
+            messages.extend_from_slice(&records_message(&records));
+        }
+        messages.extend_from_slice(&stats_message(
+            self.bytes_scanned,
+            self.bytes_scanned,
+            self.bytes_returned,
+        ));
+        messages.extend_from_slice(&end_message());
+
+        Bytes::from(messages)
+    }
+}
+
+/// The end of the first record in `data`, newlines between quotes are part of the field.
+fn record_end(data: &[u8], quote: Option<u8>) -> Option<usize> {
+    let mut quoted = false;
+    for (index, byte) in data.iter().enumerate() {
+        match *byte {
+            b'\n' if !quoted => return Some(index),
+            x if Some(x) == quote => quoted = !quoted,
+            _ => (),
+        }
+    }
+    None
+}
+
+fn csv_fields(line: &[u8], delimiter: u8, quote: u8) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = Vec::new();
+    let mut quoted = false;
+    let mut index = 0;
+    while index < line.len() {
+        let byte = line[index];
+        if quoted {
+            if byte == quote && line.get(index + 1) == Some(&quote) {
+                field.push(quote);
+                index += 1;
+            } else if byte == quote {
+                quoted = false;
+            } else {
+                field.push(byte);
+            }
+        } else if byte == quote {
+            quoted = true;
+        } else if byte == delimiter {
+            fields.push(String::from_utf8_lossy(&field).into_owned());
+            field.clear();
+        } else {
+            field.push(byte);
+        }
+        index += 1;
+    }
+    fields.push(String::from_utf8_lossy(&field).into_owned());
+    fields
+}
+
+enum Record<'a> {
+    Csv {
+        columns: Option<&'a [String]>,
+        fields: Vec<String>,
+    },
+    Json(Value),
+}
+
+impl Record<'_> {
+    /// The value of a column, `_1` is the first field of a csv record.
+    fn get(&self, column: &str) -> Option<Value> {
+        match self {
+            Record::Csv { columns, fields } => {
+                let index = match column
+                    .strip_prefix('_')
+                    .and_then(|x| x.parse::<usize>().ok())
+                {
+                    Some(position) if position > 0 => position - 1,
+                    _ => {
+                        let columns = columns.as_ref()?;
+                        columns.iter().position(|x| x == column).or_else(|| {
+                            columns.iter().position(|x| x.eq_ignore_ascii_case(column))
+                        })?
+                    }
+                };
+                fields.get(index).cloned().map(Value::String)
+            }
+            Record::Json(value) => {
+                let mut value = value;
+                for name in column.split('.') {
+                    let object = value.as_object()?;
+                    value = object.get(name).or_else(|| {
+                        object
+                            .iter()
+                            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                            .map(|(_, value)| value)
+                    })?;
+                }
+                Some(value.clone())
+            }
+        }
+    }
+
+    /// The fields of `SELECT *`, named like S3 does for json output.
+    fn all(&self) -> Vec<(String, Value)> {
+        match self {
+            Record::Csv { columns, fields } => fields
+                .iter()
+                .enumerate()
+                .map(|(index, field)| {
+                    let name = columns
+                        .and_then(|x| x.get(index).cloned())
+                        .unwrap_or_else(|| format!("_{}", index + 1));
+                    (name, Value::String(field.clone()))
+                })
+                .collect(),
+            Record::Json(Value::Object(object)) => object
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+            Record::Json(value) => vec![(String::from("_1"), value.clone())],
+        }
+    }
+}
+
+fn render(selection: &Selection, record: &Record) -> Vec<u8> {
+    let fields: Vec<(String, Option<Value>)> = match &selection.query.projection {
+        Projection::All => match (record, &selection.output) {
+            // the whole document, not only its fields
+            (Record::Json(value), Output::Json { record_delimiter }) => {
+                let mut rendered = value.to_string().into_bytes();
+                rendered.extend_from_slice(record_delimiter.as_bytes());
+                return rendered;
+            }
+            _ => record
+                .all()
+                .into_iter()
+                .map(|(name, value)| (name, Some(value)))
+                .collect(),
+        },
+        Projection::Columns(columns) => columns
+            .iter()
+            .map(|column| (column.output_name(), record.get(&column.path)))
+            .collect(),
+    };
+
+    match &selection.output {
+        Output::Csv {
+            delimiter,
+            record_delimiter,
+            quote,
+        } => {
+            let fields: Vec<_> = fields
+                .iter()
+                .map(|(_, value)| csv_field(value.as_ref(), delimiter, *quote))
+                .collect();
+            let mut rendered = fields.join(delimiter);
+            rendered.push_str(record_delimiter);
+            rendered.into_bytes()
+        }
+        Output::Json { record_delimiter } => {
+            // missing values are left out like S3 does
+            let object: Map<String, Value> = fields
+                .into_iter()
+                .filter_map(|(name, value)| Some((name, value?)))
+                .collect();
+            let mut rendered = Value::Object(object).to_string();
+            rendered.push_str(record_delimiter);
+            rendered.into_bytes()
+        }
+    }
+}
+
+fn csv_field(value: Option<&Value>, delimiter: &str, quote: char) -> String {
+    let text = match value {
+        None | Some(Value::Null) => return String::new(),
+        Some(Value::String(text)) => text.clone(),
+        Some(value) => value.to_string(),
+    };
+
+    if text.contains(delimiter) || text.contains(quote) || text.contains(['\r', '\n']) {
+        let escaped = text.replace(quote, &format!("{}{}", quote, quote));
+        format!("{}{}{}", quote, escaped, quote)
+    } else {
+        text
+    }
+}
+
+/// `SELECT columns FROM S3Object [alias] [WHERE condition] [LIMIT n]`
+#[derive(Debug, Clone, PartialEq)]
+struct Query {
+    projection: Projection,
+    filter: Option<Condition>,
+    limit: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Projection {
+    All,
+    Columns(Vec<Column>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Column {
+    /// without the alias of the table
+    path: String,
+    alias: Option<String>,
+}
+
+impl Column {
+    fn output_name(&self) -> String {
+        match &self.alias {
+            Some(alias) => alias.clone(),
+            None => self.path.rsplit('.').next().unwrap_or_default().to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Column(String),
+    Literal(Value),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Condition {
+    Compare(Operand, Comparison, Operand),
+    Like(Operand, String),
+    IsNull(Operand),
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+impl Query {
+    fn matches(&self, record: &Record) -> bool {
+        self.filter.as_ref().is_none_or(|x| x.holds(record))
+    }
+}
+
+impl Operand {
+    fn value(&self, record: &Record) -> Option<Value> {
+        match self {
+            Operand::Column(column) => record.get(column),
+            Operand::Literal(value) => Some(value.clone()),
+        }
+    }
+}
+
+impl Condition {
+    fn holds(&self, record: &Record) -> bool {
+        match self {
+            Condition::Compare(left, comparison, right) => {
+                let (Some(left), Some(right)) = (left.value(record), right.value(record)) else {
+                    return false;
+                };
+                let Some(ordering) = compare(&left, &right) else {
+                    return false;
+                };
+                match comparison {
+                    Comparison::Equal => ordering == Ordering::Equal,
+                    Comparison::NotEqual => ordering != Ordering::Equal,
+                    Comparison::Less => ordering == Ordering::Less,
+                    Comparison::LessOrEqual => ordering != Ordering::Greater,
+                    Comparison::Greater => ordering == Ordering::Greater,
+                    Comparison::GreaterOrEqual => ordering != Ordering::Less,
+                }
+            }
+            Condition::Like(operand, pattern) => operand
+                .value(record)
+                .is_some_and(|x| like(&text(&x), pattern)),
+            Condition::IsNull(operand) => operand
+                .value(record)
+                .is_none_or(|x| x.is_null() || x == Value::String(String::new())),
+            Condition::Not(condition) => !condition.holds(record),
+            Condition::And(left, right) => left.holds(record) && right.holds(record),
+            Condition::Or(left, right) => left.holds(record) || right.holds(record),
+        }
+    }
+}
+
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Numbers compare as numbers, csv fields are strings so they are parsed when compared with
+/// a number. Everything else compares as text.
+fn compare(left: &Value, right: &Value) -> Option<Ordering> {
+    let number = |value: &Value| match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => text.trim().parse::<f64>().ok(),
+        _ => None,
+    };
+
+    if left.is_number() || right.is_number() {
+        return number(left)?.partial_cmp(&number(right)?);
+    }
+    Some(text(left).cmp(&text(right)))
+}
+
+/// SQL `LIKE`, `%` matches any text and `_` one character.
+fn like(text: &str, pattern: &str) -> bool {
+    let text: Vec<char> = text.chars().collect();
+    let pattern: Vec<char> = pattern.chars().collect();
+
+    // the positions in the text the pattern matched up to so far
+    let mut positions = vec![false; text.len() + 1];
+    positions[0] = true;
+    for wildcard in pattern {
+        let mut next = vec![false; text.len() + 1];
+        for index in 0..=text.len() {
+            if !positions[index] {
+                continue;
+            }
+            match wildcard {
+                '%' => next[index..].iter_mut().for_each(|x| *x = true),
+                '_' if index < text.len() => next[index + 1] = true,
+                x if index < text.len() && text[index] == x => next[index + 1] = true,
+                _ => (),
+            }
+        }
+        positions = next;
+    }
+
+    positions[text.len()]
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    /// `"name"`, never a keyword
+    QuotedWord(String),
+    Text(String),
+    Number(f64),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: &[&str] = &[
+    "<=", ">=", "<>", "!=", "=", "<", ">", "*", ",", "(", ")", "[", "]", "-",
+];
+
+fn tokenize(sql: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = sql.trim();
+    while !rest.is_empty() {
+        let first = rest.chars().next().unwrap_or_default();
+        if first.is_whitespace() {
+            rest = rest.trim_start();
+            continue;
+        }
+
+        if first == '\'' || first == '"' {
+            // the quote is escaped by doubling it
+            let mut value = String::new();
+            let mut chars = rest.char_indices().skip(1).peekable();
+            let mut end = None;
+            while let Some((index, char)) = chars.next() {
+                if char == first {
+                    if chars.peek().is_some_and(|(_, x)| *x == first) {
+                        chars.next();
+                        value.push(first);
+                        continue;
+                    }
+                    end = Some(index);
+                    break;
+                }
+                value.push(char);
+            }
+            let end =
+                end.ok_or_else(|| String::from("The SQL expression has an unclosed quote"))?;
+            tokens.push(if first == '\'' {
+                Token::Text(value)
+            } else {
+                Token::QuotedWord(value)
+            });
+            rest = &rest[end + 1..];
+        } else if first.is_ascii_digit() {
+            let end = rest
+                .find(|x: char| !(x.is_ascii_digit() || x == '.'))
+                .unwrap_or(rest.len());
+            let number = rest[..end]
+                .parse()
+                .map_err(|_| format!("Invalid number {}", &rest[..end]))?;
+            tokens.push(Token::Number(number));
+            rest = &rest[end..];
+        } else if first.is_alphabetic() || first == '_' {
+            let end = rest
+                .find(|x: char| !(x.is_alphanumeric() || x == '_' || x == '.'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Word(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else {
+            let symbol = SYMBOLS
+                .iter()
+                .find(|x| rest.starts_with(**x))
+                .ok_or_else(|| format!("Unexpected character {} in the SQL expression", first))?;
+            tokens.push(Token::Symbol(symbol));
+            rest = &rest[symbol.len()..];
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    /// the name the query gave `S3Object`, columns may be prefixed with it
+    alias: Option<String>,
+}
+
+fn parse_query(sql: &str) -> Result<Query, String> {
+    let mut parser = Parser {
+        tokens: tokenize(sql)?,
+        position: 0,
+        alias: None,
+    };
+    parser.keyword("SELECT")?;
+
+    // the columns are read before the alias is known
+    let start = parser.position;
+    while parser.peek().is_some() && !parser.is_keyword("FROM") {
+        parser.position += 1;
+    }
+    let end = parser.position;
+    parser.keyword("FROM")?;
+    match parser.next() {
+        Some(Token::Word(table)) if table.eq_ignore_ascii_case("S3Object") => (),
+        _ => return Err(String::from("Only S3Object can be selected from")),
+    }
+    // `S3Object[*]`, the records of a json document
+    if parser.peek() == Some(&Token::Symbol("[")) {
+        parser.symbol("[")?;
+        parser.symbol("*")?;
+        parser.symbol("]")?;
+    }
+    if parser.is_keyword("AS") {
+        parser.position += 1;
+    }
+    if let Some(Token::Word(alias)) = parser.peek() {
+        if !["WHERE", "LIMIT"]
+            .iter()
+            .any(|x| alias.eq_ignore_ascii_case(x))
+        {
+            parser.alias = Some(alias.clone());
+            parser.position += 1;
+        }
+    }
+
+    let filter = if parser.is_keyword("WHERE") {
+        parser.position += 1;
+        Some(parser.condition()?)
+    } else {
+        None
+    };
+    let limit = if parser.is_keyword("LIMIT") {
+        parser.position += 1;
+        match parser.next() {
+            Some(Token::Number(limit)) if limit.fract() == 0.0 && limit >= 0.0 => {
+                Some(limit as u64)
+            }
+            _ => return Err(String::from("LIMIT must be followed by a whole number")),
+        }
+    } else {
+        None
+    };
+    if let Some(token) = parser.peek() {
+        return Err(format!("Unexpected {:?} in the SQL expression", token));
+    }
+
+    let rest = std::mem::replace(&mut parser.position, start);
+    let projection = parser.projection(end)?;
+    parser.position = rest;
+
+    Ok(Query {
+        projection,
+        filter,
+        limit,
+    })
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn keyword(&mut self, keyword: &str) -> Result<(), String> {
+        if !self.is_keyword(keyword) {
+            return Err(format!("Expected {} in the SQL expression", keyword));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn symbol(&mut self, symbol: &str) -> Result<(), String> {
+        match self.next() {
+            Some(Token::Symbol(x)) if x == symbol => Ok(()),
+            _ => Err(format!("Expected {} in the SQL expression", symbol)),
+        }
+    }
+
+    /// `*` or the column list, up to the token at `end`.
+    fn projection(&mut self, end: usize) -> Result<Projection, String> {
+        let is_all = |token: Option<&Token>| match token {
+            Some(Token::Symbol("*")) => true,
+            Some(Token::Word(word)) => word.ends_with('.'),
+            _ => false,
+        };
+        if is_all(self.peek()) {
+            // `*` or `s.*`
+            if self.peek() != Some(&Token::Symbol("*")) {
+                self.position += 1;
+            }
+            self.symbol("*")?;
+            if self.position != end {
+                return Err(String::from("Unexpected columns after *"));
+            }
+            return Ok(Projection::All);
+        }
+
+        let mut columns = Vec::new();
+        loop {
+            let path = match self.next() {
+                Some(Token::Word(word)) | Some(Token::QuotedWord(word)) => self.column(word),
+                _ => return Err(String::from("Expected a column in the SQL expression")),
+            };
+            let alias = if self.is_keyword("AS") {
+                self.position += 1;
+                match self.next() {
+                    Some(Token::Word(alias)) | Some(Token::QuotedWord(alias)) => Some(alias),
+                    _ => return Err(String::from("Expected a name after AS")),
+                }
+            } else {
+                None
+            };
+            columns.push(Column { path, alias });
+
+            if self.position >= end {
+                break;
+            }
+            self.symbol(",")?;
+        }
+
+        Ok(Projection::Columns(columns))
+    }
+
+    /// The column starting with `word`, `s."first name"` is read as one column.
+    fn column(&mut self, mut word: String) -> String {
+        if word.ends_with('.') {
+            if let Some(Token::QuotedWord(name)) = self.peek() {
+                word.push_str(name);
+                self.position += 1;
+            }
+        }
+        self.column_path(&word)
+    }
+
+    /// The column without the alias of `S3Object`.
+    fn column_path(&self, word: &str) -> String {
+        let prefixes = self.alias.iter().map(String::as_str).chain(["S3Object"]);
+        for prefix in prefixes {
+            // words can hold any alphanumeric character, so the prefix may end inside one
+            if word
+                .get(..prefix.len())
+                .is_some_and(|x| x.eq_ignore_ascii_case(prefix))
+                && word.as_bytes().get(prefix.len()) == Some(&b'.')
+            {
+                return word[prefix.len() + 1..].to_string();
+            }
+        }
+        word.to_string()
+    }
+
+    fn condition(&mut self) -> Result<Condition, String> {
+        let mut condition = self.and()?;
+        while self.is_keyword("OR") {
+            self.position += 1;
+            condition = Condition::Or(Box::new(condition), Box::new(self.and()?));
+        }
+        Ok(condition)
+    }
+
+    fn and(&mut self) -> Result<Condition, String> {
+        let mut condition = self.not()?;
+        while self.is_keyword("AND") {
+            self.position += 1;
+            condition = Condition::And(Box::new(condition), Box::new(self.not()?));
+        }
+        Ok(condition)
+    }
+
+    fn not(&mut self) -> Result<Condition, String> {
+        if self.is_keyword("NOT") {
+            self.position += 1;
+            return Ok(Condition::Not(Box::new(self.not()?)));
+        }
+        if self.peek() == Some(&Token::Symbol("(")) {
+            self.position += 1;
+            let condition = self.condition()?;
+            self.symbol(")")?;
+            return Ok(condition);
+        }
+        self.predicate()
+    }
+
+    fn predicate(&mut self) -> Result<Condition, String> {
+        let left = self.operand()?;
+
+        if self.is_keyword("IS") {
+            self.position += 1;
+            let negated = self.is_keyword("NOT");
+            if negated {
+                self.position += 1;
+            }
+            self.keyword("NULL")?;
+            let condition = Condition::IsNull(left);
+            return Ok(if negated {
+                Condition::Not(Box::new(condition))
+            } else {
+                condition
+            });
+        }
+
+        let negated = self.is_keyword("NOT");
+        if negated {
+            self.position += 1;
+        }
+        if self.is_keyword("LIKE") {
+            self.position += 1;
+            let Some(Token::Text(pattern)) = self.next() else {
+                return Err(String::from("LIKE must be followed by a string"));
+            };
+            let condition = Condition::Like(left, pattern);
+            return Ok(if negated {
+                Condition::Not(Box::new(condition))
+            } else {
+                condition
+            });
+        }
+        if negated {
+            return Err(String::from("NOT must be followed by LIKE"));
+        }
+
+        let comparison = match self.next() {
+            Some(Token::Symbol("=")) => Comparison::Equal,
+            Some(Token::Symbol("!=")) | Some(Token::Symbol("<>")) => Comparison::NotEqual,
+            Some(Token::Symbol("<")) => Comparison::Less,
+            Some(Token::Symbol("<=")) => Comparison::LessOrEqual,
+            Some(Token::Symbol(">")) => Comparison::Greater,
+            Some(Token::Symbol(">=")) => Comparison::GreaterOrEqual,
+            _ => return Err(String::from("Expected a comparison in the SQL expression")),
+        };
+        Ok(Condition::Compare(left, comparison, self.operand()?))
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        match self.next() {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case("TRUE") => {
+                Ok(Operand::Literal(Value::Bool(true)))
+            }
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case("FALSE") => {
+                Ok(Operand::Literal(Value::Bool(false)))
+            }
+            Some(Token::Word(word)) | Some(Token::QuotedWord(word)) => {
+                Ok(Operand::Column(self.column(word)))
+            }
+            Some(Token::Text(text)) => Ok(Operand::Literal(Value::String(text))),
+            Some(Token::Number(number)) => Ok(Operand::Literal(number_value(number))),
+            Some(Token::Symbol("-")) => match self.next() {
+                Some(Token::Number(number)) => Ok(Operand::Literal(number_value(-number))),
+                _ => Err(String::from("Expected a number after -")),
+            },
+            _ => Err(String::from(
+                "Expected a column or a value in the SQL expression",
+            )),
+        }
+    }
+}
+
+fn number_value(number: f64) -> Value {
+    serde_json::Number::from_f64(number).map_or(Value::Null, Value::Number)
+}
+
+/// An S3 event stream message, every header is a string.
+fn message(headers: &[(&str, &str)], payload: &[u8]) -> Vec<u8> {
+    let mut encoded_headers = Vec::new();
+    for (name, value) in headers {
+        encoded_headers.push(name.len() as u8);
+        encoded_headers.extend_from_slice(name.as_bytes());
+        encoded_headers.push(7);
+        encoded_headers.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        encoded_headers.extend_from_slice(value.as_bytes());
+    }
+
+    // the prelude and the trailing crc
+    let length = 12 + encoded_headers.len() + payload.len() + 4;
+    let mut message = Vec::with_capacity(length);
+    message.extend_from_slice(&(length as u32).to_be_bytes());
+    message.extend_from_slice(&(encoded_headers.len() as u32).to_be_bytes());
+    message.extend_from_slice(&crc32fast::hash(&message).to_be_bytes());
+    message.extend_from_slice(&encoded_headers);
+    message.extend_from_slice(payload);
+    message.extend_from_slice(&crc32fast::hash(&message).to_be_bytes());
+    message
+}
+
+fn records_message(records: &[u8]) -> Bytes {
+    Bytes::from(message(
+        &[
+            (":message-type", "event"),
+            (":event-type", "Records"),
+            (":content-type", "application/octet-stream"),
+        ],
+        records,
+    ))
+}
+
+fn stats_message(scanned: u64, processed: u64, returned: u64) -> Vec<u8> {
+    let stats = format!(
+        "<Stats><BytesScanned>{}</BytesScanned><BytesProcessed>{}</BytesProcessed>\
+         <BytesReturned>{}</BytesReturned></Stats>",
+        scanned, processed, returned
+    );
+    message(
+        &[
+            (":message-type", "event"),
+            (":event-type", "Stats"),
+            (":content-type", "text/xml"),
+        ],
+        stats.as_bytes(),
+    )
+}
+
+fn end_message() -> Vec<u8> {
+    message(&[(":message-type", "event"), (":event-type", "End")], &[])
+}
+
+fn error_message(message_text: &str) -> Bytes {
+    Bytes::from(message(
+        &[
+            (":message-type", "error"),
+            (":error-code", "InvalidTextEncoding"),
+            (":error-message", message_text),
+        ],
+        &[],
+    ))
+}
+
+#[test]
+fn queries_are_parsed() {
+    let query =
+        parse_query("select s.name, s.\"age\" as years from S3Object s where s.age >= 30 and not (s.city = 'Oslo' or s.name like 'J%') limit 5")
+            .unwrap();
+    assert_eq!(
+        query.projection,
+        Projection::Columns(vec![
+            Column {
+                path: String::from("name"),
+                alias: None,
+            },
+            Column {
+                path: String::from("age"),
+                alias: Some(String::from("years")),
+            },
+        ])
+    );
+    assert_eq!(query.limit, Some(5));
+
+    assert_eq!(
+        parse_query("SELECT * FROM S3Object[*]").unwrap().projection,
+        Projection::All
+    );
+    assert_eq!(
+        parse_query("SELECT s.* FROM S3Object s")
+            .unwrap()
+            .projection,
+        Projection::All
+    );
+    let query = parse_query("SELECT abcdefgé.x, s.größe FROM S3Object s").unwrap();
+    assert_eq!(
+        query.projection,
+        Projection::Columns(vec![
+            Column {
+                path: String::from("abcdefgé.x"),
+                alias: None,
+            },
+            Column {
+                path: String::from("größe"),
+                alias: None,
+            },
+        ])
+    );
+    assert!(parse_query("SELECT * FROM other").is_err());
+    assert!(parse_query("SELECT * FROM S3Object WHERE").is_err());
+    assert!(parse_query("SELECT * FROM S3Object LIMIT x").is_err());
+    assert!(parse_query("SELECT * FROM S3Object WHERE a = 'open").is_err());
+}
+
+#[test]
+fn records_are_filtered() {
+    let columns = vec![String::from("name"), String::from("age")];
+    let record = |name: &str, age: &str| Record::Csv {
+        columns: Some(&columns),
+        fields: vec![name.to_string(), age.to_string()],
+    };
+    let query = parse_query(
+        "SELECT * FROM S3Object s WHERE s.age > 9 AND (s.name LIKE 'J_n%' OR _1 = 'Ann')",
+    )
+    .unwrap();
+
+    assert!(query.matches(&record("Jane", "31")));
+    assert!(query.matches(&record("Ann", "10")));
+    // compared as numbers, not as text
+    assert!(!query.matches(&record("Jane", "8")));
+    assert!(!query.matches(&record("Bob", "40")));
+
+    let json = Record::Json(serde_json::json!({"user": {"name": "Jane"}, "score": null}));
+    assert!(
+        parse_query("SELECT * FROM S3Object s WHERE s.user.name = 'Jane'")
+            .unwrap()
+            .matches(&json)
+    );
+    assert!(parse_query("SELECT * FROM S3Object WHERE score IS NULL")
+        .unwrap()
+        .matches(&json));
+    assert!(parse_query("SELECT * FROM S3Object WHERE missing IS NULL")
+        .unwrap()
+        .matches(&json));
+    assert!(!parse_query(
+        "SELECT * FROM S3Object WHERE user.name IS NOT NULL AND user.name <> 'Jane'"
+    )
+    .unwrap()
+    .matches(&json));
+
+    assert_eq!(
+        csv_fields(b"a,\"b,c\",\"say \"\"hi\"\"\"", b',', b'"'),
+        ["a", "b,c", "say \"hi\""]
+    );
+    assert_eq!(record_end(b"\"a\nb\",c\nd", Some(b'"')), Some(7));
+}
+
+#[tokio::test]
+async fn selections_are_framed_as_event_streams() {
+    let request: SelectRequest = quick_xml::de::from_str(
+        r#"<SelectObjectContentRequest xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+           <Expression>SELECT s.name FROM S3Object s WHERE s.age &lt; 40 LIMIT 2</Expression>
+           <ExpressionType>SQL</ExpressionType>
+           <InputSerialization>
+              <CSV><FileHeaderInfo>USE</FileHeaderInfo></CSV>
+              <CompressionType>NONE</CompressionType>
+           </InputSerialization>
+           <OutputSerialization><JSON></JSON></OutputSerialization>
+        </SelectObjectContentRequest>"#,
+    )
+    .unwrap();
+    let selection = Selection::new(&request).unwrap();
+
+    let object = futures::stream::iter(
+        ["name,age\nAnn,3", "0\nBob,50\r\nJane,31\nJohn,20\n"]
+            .map(|x| Ok(Bytes::from_static(x.as_bytes()))),
+    );
+    let body: Vec<u8> = selection
+        .run(object)
+        .map(|x| x.unwrap().to_vec())
+        .concat()
+        .await;
+
+    // the payloads of the messages, after their headers
+    let mut payloads = Vec::new();
+    let mut rest = &body[..];
+    while !rest.is_empty() {
+        let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+        let headers = u32::from_be_bytes(rest[4..8].try_into().unwrap()) as usize;
+        assert_eq!(
+            crc32fast::hash(&rest[..length - 4]).to_be_bytes(),
+            rest[length - 4..length]
+        );
+        payloads.push(String::from_utf8(rest[12 + headers..length - 4].to_vec()).unwrap());
+        rest = &rest[length..];
+    }
+
+    assert_eq!(
+        payloads,
+        [
+            // Ann is only complete in the second chunk
+            "{\"name\":\"Ann\"}\n{\"name\":\"Jane\"}\n",
+            "<Stats><BytesScanned>40</BytesScanned><BytesProcessed>40</BytesProcessed>\
+             <BytesReturned>31</BytesReturned></Stats>",
+            "",
+        ]
+    );
+}
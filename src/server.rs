@@ -0,0 +1,111 @@
+use axum::Router;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto::Builder;
+use hyper_util::service::TowerToHyperService;
+use serde::Deserialize;
+use std::io;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpSocket};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HttpConfig {
+    /// reuse http/1 connections for multiple requests
+    #[serde(default = "crate::default_true")]
+    pub keep_alive: bool,
+    /// interval of the http/2 keep-alive pings, disabled when not set
+    pub http2_keep_alive_interval_secs: Option<u64>,
+    #[serde(default = "crate::default_true")]
+    pub tcp_nodelay: bool,
+    /// maximum amount of pending connections that are not accepted yet
+    #[serde(default = "default_backlog")]
+    pub backlog: u32,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            keep_alive: true,
+            http2_keep_alive_interval_secs: None,
+            tcp_nodelay: true,
+            backlog: default_backlog(),
+        }
+    }
+}
+
+fn default_backlog() -> u32 {
+    1024
+}
+
+/// Binds to the first address `host` resolves to, with the configured accept backlog.
+pub async fn bind(host: &str, config: &HttpConfig) -> io::Result<TcpListener> {
+    let mut last_error = None;
+
+    for address in tokio::net::lookup_host(host).await? {
+        let socket = if address.is_ipv4() {
+            TcpSocket::new_v4()?
+        } else {
+            TcpSocket::new_v6()?
+        };
+        socket.set_reuseaddr(true)?;
+
+        match socket.bind(address) {
+            Ok(()) => return socket.listen(config.backlog),
+            Err(error) => last_error = Some(error),
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::AddrNotAvailable,
+            format!("{} does not resolve to an address", host),
+        )
+    }))
+}
+
+/// Like `axum::serve`, but with the connection settings from `HttpConfig`.
+pub async fn serve(listener: TcpListener, app: Router, config: HttpConfig) -> io::Result<()> {
+    let mut builder = Builder::new(TokioExecutor::new());
+    builder.http1().keep_alive(config.keep_alive);
+    if let Some(interval) = config.http2_keep_alive_interval_secs {
+        builder
+            .http2()
+            .timer(TokioTimer::new())
+            .keep_alive_interval(Duration::from_secs(interval));
+    }
+
+    loop {
+        let (stream, _) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(error) => {
+                // mostly running out of file descriptors, give the open connections some time
+                tracing::error!("unable to accept connection: {}", error);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+
+        if config.tcp_nodelay {
+            if let Err(error) = stream.set_nodelay(true) {
+                tracing::warn!("unable to set TCP_NODELAY: {}", error);
+            }
+        }
+
+        let builder = builder.clone();
+        let service = TowerToHyperService::new(app.clone());
+        tokio::spawn(async move {
+            if let Err(error) = builder
+                .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                .await
+            {
+                tracing::debug!("connection closed with error: {}", error);
+            }
+        });
+    }
+}
+
+#[tokio::test]
+async fn bind_uses_resolved_address() {
+    let listener = bind("localhost:0", &HttpConfig::default()).await.unwrap();
+
+    assert!(listener.local_addr().unwrap().ip().is_loopback());
+}
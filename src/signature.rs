@@ -7,15 +7,17 @@ use aws_sigv4::http_request::{
 };
 use aws_sigv4::sign::v4::SigningParams;
 use axum::body::{Body, Bytes};
-use axum::extract::{FromRequest, FromRequestParts, OriginalUri, Request};
-use axum::http::header::AUTHORIZATION;
-use axum::http::{HeaderMap, HeaderValue, Method, Response, StatusCode};
-use deadpool_redis::redis::{AsyncCommands, RedisError};
-use deadpool_redis::PoolError;
-use std::convert::Infallible;
-use std::time::SystemTime;
+use axum::extract::{FromRequest, OriginalUri, Request, State};
+use axum::http::header::{AUTHORIZATION, CONTENT_TYPE, HOST};
+use axum::http::request::Parts;
+#[cfg(test)]
+use axum::http::HeaderValue;
+use axum::http::{HeaderMap, Method, Response, Uri};
+use axum::middleware::Next;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
 use time::error::Parse;
-use tracing::error;
 
 #[derive(Debug, Default, PartialEq)]
 pub struct S3V4Params<'a> {
@@ -28,141 +30,503 @@ pub struct S3V4Params<'a> {
     pub signature: &'a str,
 }
 
-use time::{format_description, PrimitiveDateTime};
-
+use time::format_description::BorrowedFormatItem;
+use time::macros::format_description;
+use time::PrimitiveDateTime;
+
+use crate::auth::AuthRequest;
+use crate::bucket_policy::{self, BucketPolicy, Decision, Requester, Resource};
+use crate::context::RequestContext;
+use crate::domains::DomainMapping;
+use crate::error::S3Error;
+use crate::multipart;
+use crate::namespaces::Namespaces;
+use crate::operation::S3Operation;
+use crate::payload::{parse_payload_hash, VerifiedBody, MAX_BUFFERED_BODY, UNSIGNED_PAYLOAD};
+use crate::post_policy;
 use crate::AppState;
 
-const DATE_TIME_FORMAT: &str = "[year][month][day]T[hour][minute][second]Z";
+const CONTENT_SHA256: &str = "x-amz-content-sha256";
 
-#[derive(Debug, Default, PartialEq)]
+const DATE_TIME_FORMAT: &[BorrowedFormatItem<'_>] =
+    format_description!("[year][month][day]T[hour][minute][second]Z");
+
+/// Who signed the request, added to the request extensions by `authenticate`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Identity {
+    pub access_key: String,
+    pub namespace: String,
+}
+
+#[derive(Debug, Default)]
 pub struct VerifiedRequest {
     pub access_key: String,
     pub namespace: String,
-    pub bytes: Bytes,
+    pub operation: S3Operation,
+    pub headers: HeaderMap,
+    pub body: VerifiedBody,
 }
 
-pub enum VerifiedRequestError {
-    FormattedResponse(Response<Body>),
-    Pool(PoolError),
-    Redis(RedisError),
+/// Checks the signature of the request before it reaches the handler.
+///
+/// The payload hash is only verified while the body is read, so handlers still have to read
+/// the body to the end before committing anything.
+pub async fn authenticate(
+    State(state): State<AppState>,
+    req: Request,
+    next: Next,
+) -> Response<Body> {
+    match verify_request(&state, req).await {
+        Ok(req) => next.run(req).await,
+        Err(error) => error.into_response(),
+    }
 }
 
-impl IntoResponse for VerifiedRequestError {
-    fn into_response(self) -> Response<Body> {
-        match self {
-            VerifiedRequestError::FormattedResponse(response) => response,
-            VerifiedRequestError::Pool(error) => {
-                error!("{}", error.to_string());
+async fn verify_request(state: &AppState, req: Request) -> Result<Request, S3Error> {
+    let started = Instant::now();
+    let config = &state.config;
 
-                let mut response = Response::default();
-                *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
-                response
-            }
-            VerifiedRequestError::Redis(error) => {
-                error!("{}", error.to_string());
+    let (mut parts, body) = req.into_parts();
+    let original_uri = match parts.extensions.get::<OriginalUri>() {
+        Some(OriginalUri(uri)) => uri.clone(),
+        None => parts.uri.clone(),
+    };
+    let context = parts.extensions.get::<Arc<RequestContext>>().cloned();
+
+    // the handler checks the signature in the form, after reading the fields before the file
+    if is_form_upload(&parts) {
+        return Ok(Request::from_parts(parts, body));
+    }
 
-                let mut response = Response::default();
-                *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
-                response
+    let params = match parse_authorization_header(&parts.headers) {
+        Some(params) => params,
+        None => {
+            let identity = anonymous(state, &parts).await?;
+            parts.extensions.insert(identity);
+            let body = VerifiedBody::unsigned(body, context);
+            return Ok(Request::from_parts(parts, Body::new(body)));
+        }
+    };
+
+    // the body is only read up front when the client did not send the payload hash,
+    // otherwise it is verified while the handler streams it
+    let payload_hash = parts
+        .headers
+        .get(CONTENT_SHA256)
+        .map(|x| x.to_str().unwrap_or_default());
+    let (bytes, body) = match payload_hash {
+        None => {
+            let bytes = axum::body::to_bytes(body, MAX_BUFFERED_BODY)
+                .await
+                .map_err(|_| S3Error::EntityTooLarge)?;
+            let body = VerifiedBody::unsigned(Body::from(bytes.clone()), context.clone());
+            (bytes, body)
+        }
+        Some(UNSIGNED_PAYLOAD) => (Bytes::new(), VerifiedBody::unsigned(body, context.clone())),
+        Some(value) => match parse_payload_hash(value) {
+            Some(expected) => (
+                Bytes::new(),
+                VerifiedBody::signed(body, expected, context.clone()).hashed_with(state.sha256),
+            ),
+            None => {
+                return Err(S3Error::NotImplemented(format!(
+                    "unsupported x-amz-content-sha256: {}",
+                    value
+                )))
             }
+        },
+    };
+
+    let secret_key = match state.auth.secret_key(params.access_key).await? {
+        Some(result) => result,
+        None => return Err(S3Error::InvalidAccessKeyId),
+    };
+
+    let mapping = parts.extensions.get::<DomainMapping>().cloned();
+    let url = signed_url(
+        &config.external_server_host,
+        &parts,
+        mapping.is_some(),
+        &original_uri,
+    );
+    let verify = |secret_key: &str| {
+        verify_headers(
+            &parts.headers,
+            &params,
+            &parts.method,
+            &url,
+            secret_key,
+            &bytes,
+        )
+    };
+    // clients that still use the secret from before a rotation keep working during its grace
+    if !verify(&secret_key) {
+        match state.auth.previous_secret_key(params.access_key).await? {
+            Some(previous) if verify(&previous) => (),
+            _ => return Err(S3Error::SignatureDoesNotMatch),
+        }
+    }
+
+    let identity = authorize(state, &parts, params.access_key, params.region, started).await?;
+
+    parts.extensions.insert(identity);
+    Ok(Request::from_parts(parts, Body::new(body)))
+}
+
+/// The checks after the signature matched, for signed headers and for browser uploads.
+async fn authorize(
+    state: &AppState,
+    parts: &Parts,
+    access_key: &str,
+    signed_region: &str,
+    started: Instant,
+) -> Result<Identity, S3Error> {
+    let context = parts.extensions.get::<Arc<RequestContext>>();
+    let operation = match context {
+        Some(context) => context.operation,
+        None => S3Operation::from_request(&parts.method, &parts.uri),
+    };
+    let identity = state
+        .auth
+        .authorize(&AuthRequest {
+            access_key,
+            operation,
+            parts,
+        })
+        .await?;
+    let namespace = check_bucket_policy(state, &identity, parts, operation).await?;
+    state
+        .default_buckets
+        .ensure(
+            &state.metadata,
+            &state.opendal_operator,
+            &identity.namespace,
+        )
+        .await?;
+    // keys of other namespaces that the policy allows act on the bucket in its namespace
+    let identity = Identity {
+        namespace,
+        ..identity
+    };
+    check_frozen(state, &identity, parts).await?;
+    check_region(state, &identity, parts, operation, signed_region).await?;
+
+    if let Some(context) = context {
+        context.record_auth(&identity.access_key, &identity.namespace, started.elapsed());
+    }
+
+    Ok(identity)
+}
+
+/// Checks the request against the policy of its bucket, returns the namespace the request acts
+/// in. Keys of other namespaces only reach a bucket through its custom domain, otherwise they
+/// would reach their own bucket of the same name, and need a statement that allows them.
+async fn check_bucket_policy(
+    state: &AppState,
+    identity: &Identity,
+    parts: &Parts,
+    operation: S3Operation,
+) -> Result<String, S3Error> {
+    let namespace = match parts.extensions.get::<DomainMapping>() {
+        Some(mapping) => &mapping.namespace,
+        None => &identity.namespace,
+    };
+    let requester = Requester {
+        access_key: &identity.access_key,
+        namespace: &identity.namespace,
+    };
+
+    let path = parts.uri.path().trim_start_matches('/');
+    let (bucket, key) = path.split_once('/').unwrap_or((path, ""));
+    let key = percent_encoding::percent_decode_str(key).decode_utf8_lossy();
+    let action = bucket_policy::action(operation, &parts.method, !key.is_empty());
+
+    match action {
+        Some(action) if !bucket.is_empty() => {
+            let resource = bucket_policy::resource(operation, bucket, &key);
+            check_policy_of(state, &requester, namespace, action, resource).await?;
+        }
+        _ if *namespace != identity.namespace => return Err(S3Error::AccessDenied),
+        _ => (),
+    }
+
+    // copies also read the source object
+    if matches!(operation, S3Operation::PutObject | S3Operation::UploadPart) {
+        if let Some((bucket, key)) = multipart::copy_source(&parts.headers)? {
+            let resource = Resource::Object(&bucket, &key);
+            check_policy_of(state, &requester, namespace, "s3:GetObject", resource).await?;
         }
     }
+
+    Ok(namespace.clone())
+}
+
+/// Requests without a signature list buckets with a `public-read` ACL, only on their custom
+/// domain since nothing else tells the namespace. Unsigned reads of public objects never get
+/// here, the custom domain sends those to `/_public/`.
+async fn anonymous(state: &AppState, parts: &Parts) -> Result<Identity, S3Error> {
+    let Some(mapping) = parts.extensions.get::<DomainMapping>() else {
+        return Err(S3Error::AccessDenied);
+    };
+    let operation = S3Operation::from_request(&parts.method, &parts.uri);
+    let path = parts.uri.path().trim_start_matches('/');
+    let (bucket, key) = path.split_once('/').unwrap_or((path, ""));
+    let lists = match parts.method {
+        Method::HEAD => key.is_empty(),
+        _ => operation == S3Operation::ListObjects,
+    };
+    if !lists || bucket != mapping.bucket {
+        return Err(S3Error::AccessDenied);
+    }
+
+    let acl = Namespaces::new(&state.metadata)
+        .bucket_acl(&mapping.namespace, &mapping.bucket)
+        .await?;
+    // statements that deny everyone also hold for anonymous requests
+    let requester = Requester {
+        access_key: "",
+        namespace: "",
+    };
+    let resource = Resource::Bucket(bucket);
+    let decision = policy_decision(
+        state,
+        &requester,
+        &mapping.namespace,
+        "s3:ListBucket",
+        &resource,
+    )
+    .await?;
+    if !acl.is_public() || decision == Decision::Deny {
+        return Err(S3Error::AccessDenied);
+    }
+
+    Ok(Identity {
+        access_key: String::new(),
+        namespace: mapping.namespace.clone(),
+    })
 }
 
-impl From<Response<Body>> for VerifiedRequestError {
-    fn from(value: Response<Body>) -> Self {
-        VerifiedRequestError::FormattedResponse(value)
+/// The decision of the policy of the bucket together with the policy of its namespace.
+async fn policy_decision(
+    state: &AppState,
+    requester: &Requester<'_>,
+    namespace: &str,
+    action: &str,
+    resource: &Resource<'_>,
+) -> Result<Decision, S3Error> {
+    let namespaces = Namespaces::new(&state.metadata);
+    let policies = [
+        namespaces.policy(namespace).await?,
+        namespaces
+            .bucket_policy(namespace, resource.bucket())
+            .await?,
+    ];
+
+    let mut decision = Decision::Default;
+    for policy in policies.iter().flatten() {
+        // namespace policies stored before they were checked may not parse, those deny
+        // everything instead of being skipped
+        decision = decision.and(match serde_json::from_str::<BucketPolicy>(policy) {
+            Ok(policy) => policy.evaluate(requester, action, resource),
+            Err(_) => Decision::Deny,
+        });
     }
+    Ok(decision)
 }
 
-impl From<Infallible> for VerifiedRequestError {
-    fn from(_: Infallible) -> Self {
-        unreachable!()
+async fn check_policy_of(
+    state: &AppState,
+    requester: &Requester<'_>,
+    namespace: &str,
+    action: &str,
+    resource: Resource<'_>,
+) -> Result<(), S3Error> {
+    let decision = policy_decision(state, requester, namespace, action, &resource).await?;
+
+    match decision {
+        Decision::Allow => Ok(()),
+        Decision::Default if requester.namespace == namespace => Ok(()),
+        Decision::Default | Decision::Deny => Err(S3Error::AccessDenied),
     }
 }
 
-impl From<PoolError> for VerifiedRequestError {
-    fn from(value: PoolError) -> Self {
-        VerifiedRequestError::Pool(value)
+/// Browser uploads are `POST`s of a form to the bucket, they have no `Authorization` header.
+fn is_form_upload(parts: &Parts) -> bool {
+    !parts.headers.contains_key(AUTHORIZATION)
+        && S3Operation::from_request(&parts.method, &parts.uri) == S3Operation::PostObject
+        && parts
+            .headers
+            .get(CONTENT_TYPE)
+            .and_then(|x| x.to_str().ok())
+            .is_some_and(|x| x.starts_with("multipart/form-data"))
+}
+
+/// Verifies the policy signature of a browser upload, `fields` are the form fields before the
+/// file with lowercase names. Returns who signed the policy.
+pub(crate) async fn verify_form(
+    state: &AppState,
+    parts: &Parts,
+    fields: &BTreeMap<String, String>,
+) -> Result<Identity, S3Error> {
+    let started = Instant::now();
+    let field = |name: &str| fields.get(name).map(String::as_str);
+
+    let (Some(policy), Some(signature)) = (field("policy"), field("x-amz-signature")) else {
+        return Err(S3Error::AccessDenied);
+    };
+    if field("x-amz-algorithm") != Some(post_policy::ALGORITHM) {
+        return Err(S3Error::InvalidArgument(format!(
+            "Only {} is supported for browser uploads",
+            post_policy::ALGORITHM
+        )));
+    }
+    let credential: Vec<_> = field("x-amz-credential")
+        .unwrap_or_default()
+        .split('/')
+        .collect();
+    let [access_key, _date, region, "s3", "aws4_request"] = credential[..] else {
+        return Err(S3Error::InvalidArgument(String::from(
+            "x-amz-credential is not of the form <key>/<date>/<region>/s3/aws4_request",
+        )));
+    };
+    let time = field("x-amz-date")
+        .and_then(|x| parse_date_time(x).ok())
+        .ok_or_else(|| S3Error::InvalidArgument(String::from("x-amz-date is invalid")))?;
+
+    let Some(secret_key) = state.auth.secret_key(access_key).await? else {
+        return Err(S3Error::InvalidAccessKeyId);
+    };
+    if post_policy::signature(&secret_key, time, region, policy) != signature {
+        match state.auth.previous_secret_key(access_key).await? {
+            Some(previous)
+                if post_policy::signature(&previous, time, region, policy) == signature => {}
+            _ => return Err(S3Error::SignatureDoesNotMatch),
+        }
     }
+
+    authorize(state, parts, access_key, region, started).await
 }
 
-impl From<RedisError> for VerifiedRequestError {
-    fn from(value: RedisError) -> Self {
-        VerifiedRequestError::Redis(value)
+/// The url the client signed, requests to custom domains are signed with their own host and
+/// the path before it was rewritten to the bucket.
+fn signed_url(
+    external_host: &str,
+    parts: &Parts,
+    custom_domain: bool,
+    original_uri: &Uri,
+) -> String {
+    let host = parts.headers.get(HOST).and_then(|x| x.to_str().ok());
+    match host {
+        Some(host) if custom_domain => {
+            let scheme = external_host.split_once("://").map_or("http", |x| x.0);
+            format!("{scheme}://{host}{original_uri}")
+        }
+        _ => format!("{external_host}{original_uri}"),
     }
 }
 
-#[async_trait]
-impl FromRequest<AppState> for VerifiedRequest {
-    type Rejection = VerifiedRequestError;
+/// Buckets frozen by an admin keep serving reads, writes and deletes are denied.
+async fn check_frozen(state: &AppState, identity: &Identity, parts: &Parts) -> Result<(), S3Error> {
+    if matches!(parts.method, Method::GET | Method::HEAD | Method::OPTIONS) {
+        return Ok(());
+    }
 
-    async fn from_request(req: Request, state: &AppState) -> Result<Self, Self::Rejection> {
-        let metadata_pool = &state.metadata_pool;
-        let config = &state.config;
-        let (mut parts, body) = req.into_parts();
-        let header_map = HeaderMap::from_request_parts(&mut parts, state).await?;
-        let OriginalUri(original_uri) = OriginalUri::from_request_parts(&mut parts, state).await?;
-        let http_method = &parts.method;
+    let bucket = parts
+        .uri
+        .path()
+        .trim_start_matches('/')
+        .split('/')
+        .next()
+        .unwrap_or_default();
+    if bucket.is_empty() {
+        return Ok(());
+    }
 
-        let cloned_parts = parts.clone();
+    if Namespaces::new(&state.metadata)
+        .is_frozen(&identity.namespace, bucket)
+        .await?
+    {
+        return Err(S3Error::AccessDenied);
+    }
 
-        let extra_requests = Request::from_parts(cloned_parts, body);
-        let bytes = Bytes::from_request(extra_requests, &state)
-            .await
-            .map_err(|e| e.into_response())?;
+    Ok(())
+}
 
-        let params = match parse_authorization_header(&header_map) {
-            Some(params) => params,
-            None => {
-                let mut response = String::from("asdfag").into_response();
-                *response.status_mut() = StatusCode::NOT_FOUND;
-                return Err(response.into());
-            }
-        };
+/// Buckets created with a `LocationConstraint` only accept requests signed for that region,
+/// like S3 does. Clients look up the region with `GetBucketLocation`, which is always allowed.
+async fn check_region(
+    state: &AppState,
+    identity: &Identity,
+    parts: &Parts,
+    operation: S3Operation,
+    signed_region: &str,
+) -> Result<(), S3Error> {
+    // a bucket that is created again gets the region of the new request
+    if matches!(
+        operation,
+        S3Operation::GetBucketLocation | S3Operation::CreateBucket
+    ) {
+        return Ok(());
+    }
 
-        let mut conn = metadata_pool.get().await?;
-        let secret_key: String = match conn.get(format!("secret_key::{}", params.access_key)).await
-        {
-            Ok(Some(result)) => result,
-            Ok(None) => {
-                let mut response = String::from("secret key not found").into_response();
-                *response.status_mut() = StatusCode::NOT_FOUND;
-                return Err(response.into());
-            }
-            Err(error) => return Err(VerifiedRequestError::from(error)),
-        };
+    let bucket = parts
+        .uri
+        .path()
+        .trim_start_matches('/')
+        .split('/')
+        .next()
+        .unwrap_or_default();
+    if bucket.is_empty() {
+        return Ok(());
+    }
+
+    match Namespaces::new(&state.metadata)
+        .region(&identity.namespace, bucket)
+        .await?
+    {
+        Some(region) if region != signed_region => {
+            Err(S3Error::AuthorizationHeaderMalformed(region))
+        }
+        _ => Ok(()),
+    }
+}
 
-        let external_host = &config.external_server_host;
+#[async_trait]
+impl FromRequest<AppState> for VerifiedRequest {
+    type Rejection = S3Error;
 
-        if !verify_headers(
-            &header_map,
-            &params,
-            http_method,
-            &format!("{external_host}{original_uri}"),
-            &secret_key,
-            &bytes,
-        ) {
-            let mut response = String::from("not allowed :( ").into_response();
-            *response.status_mut() = StatusCode::UNAUTHORIZED;
-            return Err(response.into());
+    async fn from_request(req: Request, _state: &AppState) -> Result<Self, Self::Rejection> {
+        let (mut parts, body) = req.into_parts();
+
+        let Some(Identity {
+            access_key,
+            namespace,
+        }) = parts.extensions.remove::<Identity>()
+        else {
+            return Err(S3Error::internal(
+                "VerifiedRequest is used on a route without the authenticate middleware",
+            ));
+        };
+
+        let operation = match parts.extensions.get::<Arc<RequestContext>>() {
+            Some(context) => context.operation,
+            None => S3Operation::from_request(&parts.method, &parts.uri),
         };
 
         Ok(VerifiedRequest {
-            access_key: params.access_key.to_string(),
-            namespace: params.access_key.to_string(),
-            bytes,
+            access_key,
+            namespace,
+            operation,
+            headers: std::mem::take(&mut parts.headers),
+            // the body is verified by the authenticate middleware, this passes its errors on
+            body: VerifiedBody::unsigned(body, None),
         })
     }
 }
 
 /// Parses `YYYYMMDD'T'HHMMSS'Z'` formatted dates into a `SystemTime`.
 pub(crate) fn parse_date_time(date_time_str: &str) -> Result<SystemTime, Parse> {
-    let date_time = PrimitiveDateTime::parse(
-        date_time_str,
-        &format_description::parse(DATE_TIME_FORMAT).unwrap(),
-    )?
-    .assume_utc();
+    let date_time = PrimitiveDateTime::parse(date_time_str, DATE_TIME_FORMAT)?.assume_utc();
     Ok(date_time.into())
 }
 
@@ -174,10 +538,10 @@ pub fn verify_headers(
     secret_key: &str,
     bytes: &[u8],
 ) -> bool {
-    let payload = match header_map.get("x-amz-content-sha256") {
-        Some(header_value) if header_value == HeaderValue::from_static("UNSIGNED-PAYLOAD") => {
-            SignableBody::UnsignedPayload
-        }
+    // the signature covers the claimed payload hash, the body itself is checked while reading
+    let payload = match header_map.get(CONTENT_SHA256).map(|x| x.to_str()) {
+        Some(Ok(UNSIGNED_PAYLOAD)) => SignableBody::UnsignedPayload,
+        Some(Ok(payload_hash)) => SignableBody::Precomputed(payload_hash.to_string()),
         _ => SignableBody::Bytes(bytes),
     };
 
@@ -233,7 +597,7 @@ pub fn verify_headers(
     false
 }
 
-pub fn parse_authorization_header(header_map: &HeaderMap) -> Option<S3V4Params> {
+pub fn parse_authorization_header(header_map: &HeaderMap) -> Option<S3V4Params<'_>> {
     let mut params = S3V4Params::default();
     let authorization = header_map
         .get(AUTHORIZATION)
@@ -264,7 +628,7 @@ pub fn parse_authorization_header(header_map: &HeaderMap) -> Option<S3V4Params>
 
     // validations
 
-    if params.access_key == "" {
+    if params.access_key.is_empty() {
         return None;
     }
     if params
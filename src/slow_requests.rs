@@ -0,0 +1,34 @@
+use crate::context::RequestSummary;
+use crate::Config;
+use std::time::Duration;
+
+/// Logs the request at WARN level when it took longer than the configured threshold.
+pub fn report(config: &Config, summary: &RequestSummary) {
+    let Some(threshold) = config.slow_request_threshold_ms.map(Duration::from_millis) else {
+        return;
+    };
+
+    if summary.total < threshold {
+        return;
+    }
+
+    let context = summary.context;
+    let auth = context.auth_duration().unwrap_or_default();
+
+    tracing::warn!(
+        operation = %context.operation,
+        method = %context.method,
+        path = %context.path,
+        status = summary.status.as_u16(),
+        namespace = context.namespace().unwrap_or("-"),
+        access_key = context.access_key().unwrap_or("-"),
+        request_bytes = context.request_bytes(),
+        response_bytes = summary.response_bytes,
+        auth_ms = auth.as_millis() as u64,
+        handler_ms = summary.handler.saturating_sub(auth).as_millis() as u64,
+        body_ms = summary.body.as_millis() as u64,
+        total_ms = summary.total.as_millis() as u64,
+        threshold_ms = threshold.as_millis() as u64,
+        "slow request"
+    );
+}
@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+
+/// `*.example.com` is read from `_.example.com.pem`, `*` is awkward in file names.
+const WILDCARD_FILE_PREFIX: &str = "_.";
+
+/// The host name a `{name}.pem` file of the certificate directory serves.
+pub fn host_name(file_name: &str) -> Option<String> {
+    let name = file_name.strip_suffix(".pem")?.to_ascii_lowercase();
+    if name.is_empty() {
+        return None;
+    }
+
+    Some(match name.strip_prefix(WILDCARD_FILE_PREFIX) {
+        Some(parent) => format!("*.{}", parent),
+        None => name,
+    })
+}
+
+/// The certificate of `server_name`, an exact match before a wildcard one level up.
+pub fn lookup<'a, T>(certificates: &'a HashMap<String, T>, server_name: &str) -> Option<&'a T> {
+    let server_name = server_name.trim_end_matches('.').to_ascii_lowercase();
+
+    certificates.get(&server_name).or_else(|| {
+        let (_, parent) = server_name.split_once('.')?;
+        certificates.get(&format!("*.{}", parent))
+    })
+}
+
+#[cfg(feature = "http3")]
+pub(crate) mod resolver {
+    use super::{host_name, lookup};
+    use crate::http3::Http3Config;
+    use anyhow::Context;
+    use quinn::rustls;
+    use rustls::server::{ClientHello, ResolvesServerCert};
+    use rustls::sign::CertifiedKey;
+    use std::collections::HashMap;
+    use std::path::{Path, PathBuf};
+    use std::sync::{Arc, RwLock};
+    use std::time::Duration;
+
+    #[derive(Debug)]
+    struct Certificates {
+        /// for clients without SNI and names without a certificate of their own
+        default: Arc<CertifiedKey>,
+        by_name: HashMap<String, Arc<CertifiedKey>>,
+    }
+
+    /// Picks the certificate by the server name of the TLS handshake.
+    #[derive(Debug)]
+    pub struct SniResolver {
+        cert_path: PathBuf,
+        key_path: PathBuf,
+        cert_dir: Option<PathBuf>,
+        certificates: RwLock<Arc<Certificates>>,
+    }
+
+    impl SniResolver {
+        pub fn load(config: &Http3Config) -> anyhow::Result<SniResolver> {
+            let cert_path = PathBuf::from(&config.cert_path);
+            let key_path = PathBuf::from(&config.key_path);
+            let cert_dir = config.cert_dir.as_ref().map(PathBuf::from);
+            let certificates = read(&cert_path, &key_path, cert_dir.as_deref())?;
+
+            Ok(SniResolver {
+                cert_path,
+                key_path,
+                cert_dir,
+                certificates: RwLock::new(Arc::new(certificates)),
+            })
+        }
+
+        /// Reads the certificates again, the previous ones stay in use when that fails.
+        pub fn reload(&self) -> anyhow::Result<usize> {
+            let certificates = read(&self.cert_path, &self.key_path, self.cert_dir.as_deref())?;
+            let loaded = certificates.by_name.len();
+            *self.certificates.write().expect("lock is not poisoned") = Arc::new(certificates);
+
+            Ok(loaded)
+        }
+
+        /// Picks up renewed certificates without a restart.
+        pub async fn reload_periodically(self: Arc<Self>, every: Duration) {
+            let mut interval = tokio::time::interval(every);
+            // the certificates were just loaded
+            interval.tick().await;
+
+            loop {
+                interval.tick().await;
+
+                match self.reload() {
+                    Ok(loaded) => tracing::debug!("reloaded {} certificates", loaded),
+                    Err(error) => tracing::warn!(
+                        "unable to reload the certificates, keeping the previous ones: {:#}",
+                        error
+                    ),
+                }
+            }
+        }
+    }
+
+    impl ResolvesServerCert for SniResolver {
+        fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+            let certificates = self
+                .certificates
+                .read()
+                .expect("lock is not poisoned")
+                .clone();
+
+            let certificate = client_hello
+                .server_name()
+                .and_then(|name| lookup(&certificates.by_name, name))
+                .unwrap_or(&certificates.default);
+            Some(certificate.clone())
+        }
+    }
+
+    fn read(
+        cert_path: &Path,
+        key_path: &Path,
+        cert_dir: Option<&Path>,
+    ) -> anyhow::Result<Certificates> {
+        let certs = std::fs::read(cert_path)
+            .with_context(|| format!("unable to read {}", cert_path.display()))?;
+        let key = std::fs::read(key_path)
+            .with_context(|| format!("unable to read {}", key_path.display()))?;
+        let default = certified_key(&certs, &key)
+            .with_context(|| format!("invalid certificate {}", cert_path.display()))?;
+
+        let mut by_name = HashMap::new();
+        let entries = match cert_dir {
+            Some(dir) => std::fs::read_dir(dir)
+                .with_context(|| format!("unable to read {}", dir.display()))?
+                .collect::<Result<Vec<_>, _>>()?,
+            None => Vec::new(),
+        };
+        for entry in entries {
+            let Some(name) = entry.file_name().to_str().and_then(host_name) else {
+                continue;
+            };
+            let path = entry.path();
+            // the chain and the key in one file, like the output of most ACME clients combined
+            let pem = std::fs::read(&path)
+                .with_context(|| format!("unable to read {}", path.display()))?;
+            let certified_key = certified_key(&pem, &pem)
+                .with_context(|| format!("invalid certificate {}", path.display()))?;
+            by_name.insert(name, Arc::new(certified_key));
+        }
+
+        Ok(Certificates {
+            default: Arc::new(default),
+            by_name,
+        })
+    }
+
+    fn certified_key(certs: &[u8], key: &[u8]) -> anyhow::Result<CertifiedKey> {
+        let certs = rustls_pemfile::certs(&mut &certs[..]).collect::<Result<Vec<_>, _>>()?;
+        anyhow::ensure!(!certs.is_empty(), "no certificate");
+        let key = rustls_pemfile::private_key(&mut &key[..])?.context("no private key")?;
+        let key = rustls::crypto::ring::sign::any_supported_type(&key)?;
+
+        Ok(CertifiedKey::new(certs, key))
+    }
+}
+
+#[test]
+fn certificates_are_found_by_server_name() {
+    assert_eq!(
+        host_name("assets.example.com.pem").as_deref(),
+        Some("assets.example.com")
+    );
+    assert_eq!(
+        host_name("_.S3.example.com.pem").as_deref(),
+        Some("*.s3.example.com")
+    );
+    assert_eq!(host_name("assets.example.com.key"), None);
+    assert_eq!(host_name(".pem"), None);
+
+    let certificates = HashMap::from([
+        (String::from("assets.example.com"), "assets"),
+        (String::from("*.s3.example.com"), "buckets"),
+    ]);
+    assert_eq!(lookup(&certificates, "Assets.Example.com"), Some(&"assets"));
+    assert_eq!(
+        lookup(&certificates, "photos.s3.example.com."),
+        Some(&"buckets")
+    );
+    // wildcards match one label only
+    assert_eq!(lookup(&certificates, "a.photos.s3.example.com"), None);
+    assert_eq!(lookup(&certificates, "s3.example.com"), None);
+}
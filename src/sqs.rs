@@ -0,0 +1,202 @@
+use crate::metadata::MetadataStore;
+use serde::Deserialize;
+
+/// Delivers bucket notifications to SQS compatible queues, needs the `sqs` feature.
+///
+/// Buckets opt in with `PutBucketNotificationConfiguration`, every matching queue gets the
+/// S3 event notification document as message body.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SqsConfig {
+    pub access_key: String,
+    pub secret_key: String,
+    #[serde(default = "default_region")]
+    pub region: String,
+    /// like `http://127.0.0.1:9324` for elasticmq, queue arns resolve to AWS when not set
+    pub endpoint: Option<String>,
+}
+
+fn default_region() -> String {
+    String::from("us-east-1")
+}
+
+/// The queue of a queue configuration, an arn or the url of the queue.
+#[derive(Debug, PartialEq)]
+pub enum QueueTarget<'a> {
+    /// `arn:aws:sqs:{region}:{account}:{name}`
+    Arn {
+        region: &'a str,
+        account: &'a str,
+        name: &'a str,
+    },
+    Url(&'a str),
+}
+
+impl<'a> QueueTarget<'a> {
+    pub fn parse(queue: &'a str) -> Option<QueueTarget<'a>> {
+        if queue.starts_with("http://") || queue.starts_with("https://") {
+            return Some(QueueTarget::Url(queue));
+        }
+
+        let mut parts = queue.strip_prefix("arn:")?.splitn(5, ':');
+        let (Some(_partition), Some("sqs"), Some(region), Some(account), Some(name)) = (
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+        ) else {
+            return None;
+        };
+        if account.is_empty() || name.is_empty() || name.contains([':', '/']) {
+            return None;
+        }
+
+        Some(QueueTarget::Arn {
+            region,
+            account,
+            name,
+        })
+    }
+
+    /// Arns resolve to `{endpoint}/{account}/{name}`, like the queue urls of AWS and elasticmq.
+    #[cfg_attr(not(feature = "sqs"), allow(dead_code))]
+    fn url(&self, endpoint: Option<&str>) -> String {
+        match (self, endpoint) {
+            (QueueTarget::Url(url), _) => url.to_string(),
+            (QueueTarget::Arn { account, name, .. }, Some(endpoint)) => {
+                format!("{}/{}/{}", endpoint.trim_end_matches('/'), account, name)
+            }
+            (
+                QueueTarget::Arn {
+                    region,
+                    account,
+                    name,
+                },
+                None,
+            ) => format!("https://sqs.{}.amazonaws.com/{}/{}", region, account, name),
+        }
+    }
+}
+
+#[cfg(feature = "sqs")]
+pub use sink::SqsSink;
+
+#[cfg(feature = "sqs")]
+mod sink {
+    use super::{QueueTarget, SqsConfig};
+    use crate::metadata::MetadataStore;
+    use crate::namespaces::Namespaces;
+    use crate::notifications::{Notification, NotificationSink};
+    use anyhow::Context;
+    use async_trait::async_trait;
+    use aws_sdk_sqs::config::{BehaviorVersion, Credentials, Region};
+
+    /// Looks up the notification configuration of the bucket for every event.
+    pub struct SqsSink {
+        config: SqsConfig,
+        metadata: MetadataStore,
+        client: aws_sdk_sqs::Client,
+    }
+
+    impl SqsSink {
+        pub fn new(config: SqsConfig, metadata: MetadataStore) -> SqsSink {
+            let mut client_config = aws_sdk_sqs::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .region(Region::new(config.region.clone()))
+                .credentials_provider(Credentials::new(
+                    &config.access_key,
+                    &config.secret_key,
+                    None,
+                    None,
+                    "s3-proxy",
+                ));
+            if let Some(endpoint) = &config.endpoint {
+                client_config = client_config.endpoint_url(endpoint);
+            }
+
+            SqsSink {
+                config,
+                metadata,
+                client: aws_sdk_sqs::Client::from_conf(client_config.build()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl NotificationSink for SqsSink {
+        fn name(&self) -> &'static str {
+            "sqs"
+        }
+
+        async fn publish(&self, notification: &Notification) -> anyhow::Result<()> {
+            let event = &notification.event;
+            let configuration = Namespaces::new(&self.metadata)
+                .notification_configuration(&event.namespace, &event.bucket)
+                .await?;
+
+            // one unreachable queue does not keep the event from the others
+            let mut result = Ok(());
+            for queue in configuration
+                .queue_configurations
+                .iter()
+                .filter(|x| x.matches(notification.kind, &event.key))
+            {
+                let Some(target) = QueueTarget::parse(&queue.queue) else {
+                    continue;
+                };
+                let body = notification.s3_record(queue.id.as_deref().unwrap_or_default());
+
+                let sent = self
+                    .client
+                    .send_message()
+                    .queue_url(target.url(self.config.endpoint.as_deref()))
+                    .message_body(body.to_string())
+                    .send()
+                    .await
+                    .with_context(|| format!("unable to send to {}", queue.queue));
+                if let Err(error) = sent {
+                    result = Err(error);
+                }
+            }
+
+            result
+        }
+    }
+}
+
+/// The event hook for the `S3_PROXY__SQS__*` settings.
+pub fn start(
+    config: &SqsConfig,
+    metadata: &MetadataStore,
+) -> anyhow::Result<crate::notifications::Publisher> {
+    #[cfg(feature = "sqs")]
+    return Ok(crate::notifications::Publisher::start(SqsSink::new(
+        config.clone(),
+        metadata.clone(),
+    )));
+
+    #[cfg(not(feature = "sqs"))]
+    {
+        let _ = (config, metadata);
+        anyhow::bail!("sqs is configured but s3-proxy is compiled without the `sqs` feature")
+    }
+}
+
+#[test]
+fn queue_arns_resolve_to_urls() {
+    let arn = QueueTarget::parse("arn:aws:sqs:eu-west-1:123456789012:uploads").unwrap();
+    assert_eq!(
+        arn.url(None),
+        "https://sqs.eu-west-1.amazonaws.com/123456789012/uploads"
+    );
+    assert_eq!(
+        arn.url(Some("http://127.0.0.1:9324/")),
+        "http://127.0.0.1:9324/123456789012/uploads"
+    );
+
+    let url = "http://127.0.0.1:9324/000000000000/uploads";
+    assert_eq!(QueueTarget::parse(url).unwrap().url(None), url);
+
+    assert_eq!(QueueTarget::parse("arn:aws:sns:us-east-1:1:topic"), None);
+    assert_eq!(QueueTarget::parse("uploads"), None);
+}
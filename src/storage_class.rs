@@ -0,0 +1,134 @@
+use crate::error::S3Error;
+use crate::metadata::{MetadataError, MetadataStore};
+use axum::http::{HeaderMap, HeaderName};
+
+/// `storage_class::{namespace}/{bucket}/{key}` holds the storage class the object was uploaded
+/// with, objects without one are `STANDARD`
+pub const STORAGE_CLASS_PREFIX: &str = "storage_class::";
+
+pub static STORAGE_CLASS_HEADER: HeaderName = HeaderName::from_static("x-amz-storage-class");
+
+pub const STANDARD: &str = "STANDARD";
+
+/// The storage classes S3 accepts, all of them are kept on the same backend.
+pub const STORAGE_CLASSES: &[&str] = &[
+    STANDARD,
+    "REDUCED_REDUNDANCY",
+    "STANDARD_IA",
+    "ONEZONE_IA",
+    "INTELLIGENT_TIERING",
+    "GLACIER",
+    "GLACIER_IR",
+    "DEEP_ARCHIVE",
+    "OUTPOSTS",
+    "SNOW",
+    "EXPRESS_ONEZONE",
+];
+
+/// The `x-amz-storage-class` of an upload.
+pub fn requested(headers: &HeaderMap) -> Result<Option<&'static str>, S3Error> {
+    let Some(value) = headers.get(&STORAGE_CLASS_HEADER) else {
+        return Ok(None);
+    };
+    STORAGE_CLASSES
+        .iter()
+        .find(|x| value.as_bytes() == x.as_bytes())
+        .map(|x| Some(*x))
+        .ok_or(S3Error::InvalidStorageClass)
+}
+
+/// Stores the class of the object at `path`, `STANDARD` is the default so it is not stored.
+pub async fn store(
+    metadata: &MetadataStore,
+    path: &str,
+    storage_class: Option<&str>,
+) -> Result<(), MetadataError> {
+    match storage_class.filter(|x| *x != STANDARD) {
+        Some(storage_class) => {
+            metadata
+                .set(&format!("{}{}", STORAGE_CLASS_PREFIX, path), storage_class)
+                .await
+        }
+        None => remove(metadata, path).await,
+    }
+}
+
+pub async fn remove(metadata: &MetadataStore, path: &str) -> Result<(), MetadataError> {
+    metadata
+        .delete(&format!("{}{}", STORAGE_CLASS_PREFIX, path))
+        .await
+}
+
+/// The class of the object at `path`, `None` for `STANDARD`.
+pub async fn stored(metadata: &MetadataStore, path: &str) -> Result<Option<String>, MetadataError> {
+    metadata
+        .get(&format!("{}{}", STORAGE_CLASS_PREFIX, path))
+        .await
+}
+
+/// The classes of the objects of a listing, `root` is the path their keys are relative to.
+pub async fn stored_many(
+    metadata: &MetadataStore,
+    root: &str,
+    objects: &[(String, opendal::Metadata)],
+) -> Result<Vec<String>, MetadataError> {
+    if objects.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let keys: Vec<_> = objects
+        .iter()
+        .map(|(key, _)| format!("{}{}{}", STORAGE_CLASS_PREFIX, root, key))
+        .collect();
+    Ok(metadata
+        .get_many(&keys)
+        .await?
+        .into_iter()
+        .map(|x| x.unwrap_or_else(|| String::from(STANDARD)))
+        .collect())
+}
+
+#[tokio::test]
+async fn storage_classes_are_stored_per_object() {
+    let mut headers = HeaderMap::new();
+    assert_eq!(requested(&headers).unwrap(), None);
+    headers.insert(&STORAGE_CLASS_HEADER, "GLACIER".parse().unwrap());
+    assert_eq!(requested(&headers).unwrap(), Some("GLACIER"));
+    headers.insert(&STORAGE_CLASS_HEADER, "glacier".parse().unwrap());
+    assert!(matches!(
+        requested(&headers),
+        Err(S3Error::InvalidStorageClass)
+    ));
+
+    let metadata = MetadataStore::memory();
+    store(&metadata, "ns/bucket/a.txt", Some("GLACIER"))
+        .await
+        .unwrap();
+    store(&metadata, "ns/bucket/b.txt", Some(STANDARD))
+        .await
+        .unwrap();
+    assert_eq!(
+        stored(&metadata, "ns/bucket/a.txt")
+            .await
+            .unwrap()
+            .as_deref(),
+        Some("GLACIER")
+    );
+    assert_eq!(stored(&metadata, "ns/bucket/b.txt").await.unwrap(), None);
+
+    let object = opendal::Metadata::new(opendal::EntryMode::FILE);
+    let objects = vec![
+        (String::from("a.txt"), object.clone()),
+        (String::from("b.txt"), object),
+    ];
+    assert_eq!(
+        stored_many(&metadata, "ns/bucket/", &objects)
+            .await
+            .unwrap(),
+        ["GLACIER", STANDARD]
+    );
+
+    // overwriting without a class makes the object STANDARD again
+    store(&metadata, "ns/bucket/a.txt", None).await.unwrap();
+    assert_eq!(stored(&metadata, "ns/bucket/a.txt").await.unwrap(), None);
+}
@@ -0,0 +1,217 @@
+use crate::error::S3Error;
+use crate::operation::S3Operation;
+use axum::extract::Request;
+use axum::http::{HeaderMap, Uri};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+/// `x-amz-*` headers the proxy acts on, the signature and checksum headers.
+const SUPPORTED_HEADERS: &[&str] = &[
+    "x-amz-content-sha256",
+    "x-amz-date",
+    "x-amz-user-agent",
+    "x-amz-sdk-checksum-algorithm",
+    "x-amz-checksum-algorithm",
+    "x-amz-checksum-crc32",
+    "x-amz-checksum-crc32c",
+    "x-amz-checksum-sha1",
+    "x-amz-checksum-sha256",
+    "x-amz-checksum-mode",
+    "x-amz-object-attributes",
+    "x-amz-copy-source",
+    "x-amz-copy-source-range",
+    "x-amz-storage-class",
+    "x-amz-acl",
+    "x-amz-bucket-object-lock-enabled",
+    "x-amz-object-lock-mode",
+    "x-amz-object-lock-retain-until-date",
+    "x-amz-object-lock-legal-hold",
+    "x-amz-bypass-governance-retention",
+];
+
+/// Query parameters the operation acts on, `x-id` is added by the SDKs to every request.
+fn supported_query(operation: S3Operation) -> &'static [&'static str] {
+    match operation {
+        S3Operation::ListBuckets => &["x-id", "format"],
+        S3Operation::ListObjects => &[
+            "x-id",
+            "format",
+            "list-type",
+            "continuation-token",
+            "start-after",
+            "fetch-owner",
+            "max-keys",
+            "marker",
+            "prefix",
+            "delimiter",
+        ],
+        S3Operation::ListObjectVersions => &[
+            "x-id",
+            "versions",
+            "key-marker",
+            "version-id-marker",
+            "max-keys",
+            "prefix",
+            "delimiter",
+        ],
+        S3Operation::GetBucketLocation => &["x-id", "location"],
+        S3Operation::DeleteObjects => &["x-id", "delete"],
+        S3Operation::PostObject => &["x-id"],
+        S3Operation::GetObject => &[
+            "x-id",
+            "response-cache-control",
+            "response-content-disposition",
+            "response-content-encoding",
+            "response-content-language",
+            "response-content-type",
+            "response-expires",
+        ],
+        S3Operation::GetObjectAttributes => &["x-id", "attributes"],
+        S3Operation::CreateMultipartUpload => &["x-id", "uploads"],
+        S3Operation::SelectObjectContent => &["x-id", "select", "select-type"],
+        S3Operation::UploadPart => &["x-id", "partNumber", "uploadId"],
+        S3Operation::CompleteMultipartUpload => &["x-id", "uploadId"],
+        S3Operation::GetObjectTagging
+        | S3Operation::PutObjectTagging
+        | S3Operation::DeleteObjectTagging
+        | S3Operation::GetBucketTagging
+        | S3Operation::PutBucketTagging
+        | S3Operation::DeleteBucketTagging => &["x-id", "tagging"],
+        S3Operation::GetBucketCors | S3Operation::PutBucketCors | S3Operation::DeleteBucketCors => {
+            &["x-id", "cors"]
+        }
+        S3Operation::GetBucketPolicy
+        | S3Operation::PutBucketPolicy
+        | S3Operation::DeleteBucketPolicy => &["x-id", "policy"],
+        S3Operation::GetObjectAcl
+        | S3Operation::PutObjectAcl
+        | S3Operation::GetBucketAcl
+        | S3Operation::PutBucketAcl => &["x-id", "acl"],
+        S3Operation::GetBucketVersioning | S3Operation::PutBucketVersioning => {
+            &["x-id", "versioning"]
+        }
+        S3Operation::GetObjectLockConfiguration | S3Operation::PutObjectLockConfiguration => {
+            &["x-id", "object-lock"]
+        }
+        S3Operation::GetObjectRetention | S3Operation::PutObjectRetention => &["x-id", "retention"],
+        S3Operation::GetObjectLegalHold | S3Operation::PutObjectLegalHold => {
+            &["x-id", "legal-hold"]
+        }
+        S3Operation::GetBucketNotification | S3Operation::PutBucketNotification => {
+            &["x-id", "notification"]
+        }
+        S3Operation::GetBucketReplication
+        | S3Operation::PutBucketReplication
+        | S3Operation::DeleteBucketReplication => &["x-id", "replication"],
+        S3Operation::GetBucketLifecycleConfiguration
+        | S3Operation::PutBucketLifecycleConfiguration
+        | S3Operation::DeleteBucketLifecycle => &["x-id", "lifecycle"],
+        S3Operation::GetBucketInventoryConfiguration
+        | S3Operation::PutBucketInventoryConfiguration
+        | S3Operation::DeleteBucketInventoryConfiguration => &["x-id", "inventory", "id"],
+        S3Operation::ListBucketInventoryConfigurations => {
+            &["x-id", "inventory", "continuation-token"]
+        }
+        S3Operation::CreateBucket
+        | S3Operation::DeleteBucket
+        | S3Operation::PutObject
+        | S3Operation::DeleteObject
+        | S3Operation::Unknown => &["x-id"],
+    }
+}
+
+/// Rejects headers and query parameters that would otherwise be ignored, like `x-amz-grant-read`,
+/// `?versionId` or `?prefix`.
+pub fn check(operation: S3Operation, uri: &Uri, headers: &HeaderMap) -> Result<(), S3Error> {
+    if let Some(header) = headers.keys().find(|name| {
+        name.as_str().starts_with("x-amz-") && !SUPPORTED_HEADERS.contains(&name.as_str())
+    }) {
+        return Err(S3Error::NotImplemented(format!(
+            "the {} header is not supported",
+            header
+        )));
+    }
+
+    let supported = supported_query(operation);
+    if let Some(key) = uri
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .map(|pair| pair.split_once('=').map_or(pair, |(name, _)| name))
+        .find(|name| !name.is_empty() && !supported.contains(name))
+    {
+        return Err(S3Error::NotImplemented(format!(
+            "the {} query parameter is not supported by {}",
+            key, operation
+        )));
+    }
+
+    Ok(())
+}
+
+/// Enabled with `S3_PROXY__STRICT`, runs after authentication so only clients with a key
+/// learn what is not supported.
+pub async fn validate(req: Request, next: Next) -> Response {
+    let operation = S3Operation::from_request(req.method(), req.uri());
+    if let Err(error) = check(operation, req.uri(), req.headers()) {
+        return error.into_response();
+    }
+
+    next.run(req).await
+}
+
+#[test]
+fn unsupported_headers_and_query_parameters_are_rejected() {
+    use axum::http::HeaderValue;
+
+    let mut headers = HeaderMap::new();
+    headers.insert("x-amz-date", HeaderValue::from_static("20240203T125727Z"));
+    headers.insert("content-type", HeaderValue::from_static("text/plain"));
+    let check = |operation, uri: &str, headers: &HeaderMap| {
+        check(operation, &uri.parse().unwrap(), headers).map_err(|x| x.message())
+    };
+
+    assert!(check(
+        S3Operation::PutObject,
+        "/bucket/a.txt?x-id=PutObject",
+        &headers
+    )
+    .is_ok());
+    assert!(check(S3Operation::ListObjects, "/bucket?list-type=2", &headers).is_ok());
+    assert!(check(
+        S3Operation::PutBucketNotification,
+        "/bucket?notification",
+        &headers
+    )
+    .is_ok());
+    assert_eq!(
+        check(
+            S3Operation::ListObjects,
+            "/bucket?list-type=2&prefix=a&encoding-type=url",
+            &headers
+        ),
+        Err(String::from(
+            "the encoding-type query parameter is not supported by ListObjects"
+        ))
+    );
+    assert!(check(
+        S3Operation::GetObject,
+        "/bucket/a.txt?versionId=1",
+        &headers
+    )
+    .is_err());
+    assert!(check(
+        S3Operation::GetObject,
+        "/bucket/a.txt?response-content-type=text%2Fcsv",
+        &headers
+    )
+    .is_ok());
+
+    headers.insert("x-amz-acl", HeaderValue::from_static("public-read"));
+    assert!(check(S3Operation::PutObject, "/bucket/a.txt", &headers).is_ok());
+    headers.insert("x-amz-grant-read", HeaderValue::from_static("id=tenant"));
+    assert_eq!(
+        check(S3Operation::PutObject, "/bucket/a.txt", &headers),
+        Err(String::from("the x-amz-grant-read header is not supported"))
+    );
+}
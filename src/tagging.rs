@@ -0,0 +1,148 @@
+use crate::metadata::{MetadataError, MetadataStore};
+use axum::http::HeaderName;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// `object_tagging::{namespace}/{bucket}/{key}` holds the tag set of an object as json
+pub const OBJECT_TAGGING_PREFIX: &str = "object_tagging::";
+/// The amount of tags of an object, on `GET` and `HEAD`.
+pub static TAGGING_COUNT_HEADER: HeaderName = HeaderName::from_static("x-amz-tagging-count");
+
+/// Objects and buckets have at most this many tags, like S3.
+pub const MAX_OBJECT_TAGS: usize = 10;
+pub const MAX_BUCKET_TAGS: usize = 50;
+const MAX_KEY_LENGTH: usize = 128;
+const MAX_VALUE_LENGTH: usize = 256;
+
+/// The `<Tagging>` document of the `?tagging` subresource.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Tagging {
+    #[serde(default)]
+    pub tag_set: TagSet,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TagSet {
+    #[serde(rename = "Tag", default)]
+    pub tags: Vec<Tag>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Tag {
+    pub key: String,
+    pub value: String,
+}
+
+impl Tagging {
+    pub fn tags(&self) -> &[Tag] {
+        &self.tag_set.tags
+    }
+
+    /// Checks the limits of S3, the message is sent back with `InvalidTag`.
+    pub fn validate(&self, max_tags: usize) -> Result<(), String> {
+        if self.tags().len() > max_tags {
+            return Err(format!("Tag count cannot be greater than {}", max_tags));
+        }
+
+        let mut keys = HashSet::new();
+        for tag in self.tags() {
+            if tag.key.is_empty() || tag.key.chars().count() > MAX_KEY_LENGTH {
+                return Err(String::from("The TagKey you have provided is invalid"));
+            }
+            if tag.value.chars().count() > MAX_VALUE_LENGTH {
+                return Err(String::from("The TagValue you have provided is invalid"));
+            }
+            if !keys.insert(tag.key.as_str()) {
+                return Err(String::from(
+                    "Cannot provide multiple Tags with the same key",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The tags of the object at `path`, empty when it has none.
+pub async fn object_tags(metadata: &MetadataStore, path: &str) -> Result<Tagging, MetadataError> {
+    let tagging = metadata
+        .get(&format!("{}{}", OBJECT_TAGGING_PREFIX, path))
+        .await?;
+
+    Ok(tagging
+        .and_then(|x| serde_json::from_str(&x).ok())
+        .unwrap_or_default())
+}
+
+/// Replaces the tags of the object at `path`, an empty tag set removes them.
+pub async fn set_object_tags(
+    metadata: &MetadataStore,
+    path: &str,
+    tagging: &Tagging,
+) -> Result<(), MetadataError> {
+    let key = format!("{}{}", OBJECT_TAGGING_PREFIX, path);
+    if tagging.tags().is_empty() {
+        return metadata.delete(&key).await;
+    }
+
+    let tagging = serde_json::to_string(tagging).expect("tagging serializes");
+    metadata.set(&key, &tagging).await
+}
+
+pub async fn remove_object_tags(metadata: &MetadataStore, path: &str) -> Result<(), MetadataError> {
+    metadata
+        .delete(&format!("{}{}", OBJECT_TAGGING_PREFIX, path))
+        .await
+}
+
+#[tokio::test]
+async fn tags_are_validated_and_stored() {
+    let xml = r#"<Tagging xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+       <TagSet>
+          <Tag><Key>team</Key><Value>storage</Value></Tag>
+          <Tag><Key>env</Key><Value></Value></Tag>
+       </TagSet>
+    </Tagging>"#;
+    let tagging: Tagging = quick_xml::de::from_str(xml).unwrap();
+    assert_eq!(
+        tagging.tags(),
+        &[
+            Tag {
+                key: String::from("team"),
+                value: String::from("storage"),
+            },
+            Tag {
+                key: String::from("env"),
+                value: String::new(),
+            },
+        ]
+    );
+    assert!(tagging.validate(MAX_OBJECT_TAGS).is_ok());
+    assert!(tagging.validate(1).is_err());
+
+    let duplicate = Tagging {
+        tag_set: TagSet {
+            tags: vec![tagging.tags()[0].clone(), tagging.tags()[0].clone()],
+        },
+    };
+    assert!(duplicate.validate(MAX_OBJECT_TAGS).is_err());
+
+    let metadata = MetadataStore::memory();
+    set_object_tags(&metadata, "ns/bucket/a.txt", &tagging)
+        .await
+        .unwrap();
+    assert_eq!(
+        object_tags(&metadata, "ns/bucket/a.txt").await.unwrap(),
+        tagging
+    );
+    set_object_tags(&metadata, "ns/bucket/a.txt", &Tagging::default())
+        .await
+        .unwrap();
+    assert!(object_tags(&metadata, "ns/bucket/a.txt")
+        .await
+        .unwrap()
+        .tags()
+        .is_empty());
+}
@@ -1,3 +1,11 @@
+use crate::acl::CannedAcl;
+use crate::cors::CorsConfiguration;
+use crate::inventory::InventoryConfiguration;
+use crate::lifecycle::LifecycleConfiguration;
+use crate::notifications::NotificationConfiguration;
+use crate::object_lock::DefaultRetention;
+use crate::replication::ReplicationConfiguration;
+use crate::tagging::Tagging;
 use askama::Template;
 use serde::Deserialize;
 use std::borrow::Cow;
@@ -16,30 +24,263 @@ pub struct ListBucketsTemplate<'a> {
     pub buckets: Vec<ListBucketItem<'a>>,
 }
 
-#[derive(Debug)]
+/// Object listings are streamed, so the response is rendered in three parts: the start, one
+/// `ListObjectItem` per object and the end.
+#[derive(Debug, Template)]
+#[template(path = "list_objects_start.xml")]
+pub struct ListObjectsStartTemplate<'a> {
+    pub marker: Cow<'a, str>,
+    pub bucket_name: Cow<'a, str>,
+    pub prefix: Cow<'a, str>,
+    /// empty when the listing is not grouped
+    pub delimiter: Cow<'a, str>,
+    pub max_keys: u64,
+}
+
+#[derive(Debug, Template)]
+#[template(path = "list_object.xml")]
 pub struct ListObjectItem<'a> {
     pub etag: Option<Cow<'a, str>>,
     pub key: Cow<'a, str>,
     pub last_modified: Option<Cow<'a, str>>,
+    /// only listed with `fetch-owner=true`
+    pub owner: Option<Cow<'a, str>>,
     pub size: u64,
+    pub storage_class: Cow<'a, str>,
 }
 
 #[derive(Debug, Template)]
-#[template(path = "list_objects.xml")]
-pub struct ListObjectsTemplate<'a> {
+#[template(path = "list_objects_v2.xml")]
+pub struct ListObjectsV2Template<'a> {
+    pub bucket_name: &'a str,
+    pub prefix: &'a str,
+    pub delimiter: &'a str,
+    pub key_count: usize,
+    pub max_keys: usize,
+    pub is_truncated: bool,
+    pub continuation_token: Option<&'a str>,
+    pub next_continuation_token: Option<String>,
+    pub start_after: Option<&'a str>,
+    /// the rendered `<Contents>` elements
+    pub contents: String,
+    pub common_prefixes: &'a [String],
+}
+
+/// `ListObjectVersions`, every object is listed as its latest `null` version.
+#[derive(Debug, Template)]
+#[template(path = "list_object_versions.xml")]
+pub struct ListObjectVersionsTemplate<'a> {
+    pub bucket_name: &'a str,
+    pub prefix: &'a str,
+    pub delimiter: &'a str,
+    pub key_marker: &'a str,
+    pub version_id_marker: &'a str,
+    pub next_key_marker: Option<&'a str>,
+    /// `None` when the page ends with a common prefix
+    pub next_version_id_marker: Option<&'a str>,
+    pub max_keys: usize,
+    pub is_truncated: bool,
+    pub versions: &'a [ObjectVersion<'a>],
+    pub owner: &'a str,
+    pub common_prefixes: &'a [String],
+}
+
+#[derive(Debug)]
+pub struct ObjectVersion<'a> {
+    pub key: &'a str,
+    /// unquoted
+    pub etag: Option<&'a str>,
+    pub last_modified: Option<String>,
+    pub size: u64,
+    pub storage_class: &'a str,
+}
+
+#[derive(Debug, Template)]
+#[template(path = "list_objects_end.xml")]
+pub struct ListObjectsEndTemplate<'a> {
+    pub common_prefixes: &'a [String],
     pub is_truncated: bool,
-    pub marker: Cow<'a, str>,
     pub next_marker: Cow<'a, str>,
-    pub bucket_name: Cow<'a, str>,
-    pub prefix: Cow<'a, str>,
-    pub max_keys: u64,
-    pub objects: Vec<ListObjectItem<'a>>,
+}
+
+#[derive(Debug, Template)]
+#[template(path = "error.xml")]
+pub struct ErrorTemplate<'a> {
+    pub code: &'a str,
+    pub message: &'a str,
+    pub resource: &'a str,
+    pub request_id: &'a str,
+}
+
+#[derive(Debug, Template)]
+#[template(path = "notification_configuration.xml")]
+pub struct NotificationConfigurationTemplate<'a> {
+    pub configuration: &'a NotificationConfiguration,
+}
+
+#[derive(Debug, Template)]
+#[template(path = "replication_configuration.xml")]
+pub struct ReplicationConfigurationTemplate<'a> {
+    pub configuration: &'a ReplicationConfiguration,
+}
+
+/// Rules without a filter are sent back with an empty prefix, the SDKs fail on an empty
+/// `<Filter>`.
+#[derive(Debug, Template)]
+#[template(path = "lifecycle_configuration.xml")]
+pub struct LifecycleConfigurationTemplate<'a> {
+    pub configuration: &'a LifecycleConfiguration,
+}
+
+#[derive(Debug, Template)]
+#[template(path = "inventory_configuration.xml")]
+pub struct InventoryConfigurationTemplate<'a> {
+    pub configuration: &'a InventoryConfiguration,
+}
+
+#[derive(Debug, Template)]
+#[template(path = "list_inventory_configurations.xml")]
+pub struct ListInventoryConfigurationsTemplate<'a> {
+    pub configurations: &'a [InventoryConfiguration],
+}
+
+#[derive(Debug, Template)]
+#[template(path = "cors_configuration.xml")]
+pub struct CorsConfigurationTemplate<'a> {
+    pub configuration: &'a CorsConfiguration,
+}
+
+#[derive(Debug, Template)]
+#[template(path = "tagging.xml")]
+pub struct TaggingTemplate<'a> {
+    pub tagging: &'a Tagging,
+}
+
+#[derive(Debug, Template)]
+#[template(path = "access_control_policy.xml")]
+pub struct AccessControlPolicyTemplate<'a> {
+    pub owner: &'a str,
+    pub acl: CannedAcl,
+    pub all_users: &'a str,
+}
+
+#[derive(Debug, Template)]
+#[template(path = "initiate_multipart_upload.xml")]
+pub struct InitiateMultipartUploadTemplate<'a> {
+    pub bucket: &'a str,
+    pub key: &'a str,
+    pub upload_id: &'a str,
+}
+
+#[derive(Debug, Template)]
+#[template(path = "copy_part_result.xml")]
+pub struct CopyPartResultTemplate<'a> {
+    /// quoted, the md5 of the copied range
+    pub etag: &'a str,
+    pub last_modified: &'a str,
+}
+
+#[derive(Debug, Template)]
+#[template(path = "complete_multipart_upload.xml")]
+pub struct CompleteMultipartUploadTemplate<'a> {
+    pub location: &'a str,
+    pub bucket: &'a str,
+    pub key: &'a str,
+    /// quoted, the composite etag of the parts
+    pub etag: &'a str,
+}
+
+#[derive(Debug, Template)]
+#[template(path = "post_response.xml")]
+pub struct PostResponseTemplate<'a> {
+    pub location: &'a str,
+    pub bucket: &'a str,
+    pub key: &'a str,
+    pub etag: &'a str,
+}
+
+#[derive(Debug, Template)]
+#[template(path = "versioning_configuration.xml")]
+pub struct VersioningConfigurationTemplate {
+    /// `None` when versioning was never configured
+    pub status: Option<&'static str>,
+}
+
+#[derive(Debug, Template)]
+#[template(path = "object_lock_configuration.xml")]
+pub struct ObjectLockConfigurationTemplate<'a> {
+    pub default_retention: Option<&'a DefaultRetention>,
+}
+
+/// The root of `GetObjectRetention` and `GetObjectLegalHold` is named after the shape, like the
+/// SDKs parse it.
+#[derive(Debug, Template)]
+#[template(path = "retention.xml")]
+pub struct RetentionTemplate {
+    pub mode: &'static str,
+    pub retain_until_date: String,
+}
+
+#[derive(Debug, Template)]
+#[template(path = "legal_hold.xml")]
+pub struct LegalHoldTemplate {
+    /// `ON` or `OFF`
+    pub status: &'static str,
+}
+
+#[derive(Debug, Template)]
+#[template(path = "location_constraint.xml")]
+pub struct LocationConstraintTemplate<'a> {
+    /// empty for `us-east-1`
+    pub region: &'a str,
+}
+
+/// `GetObjectAttributes`, only the attributes asked for are set.
+#[derive(Debug, Template)]
+#[template(path = "object_attributes.xml")]
+pub struct ObjectAttributesTemplate<'a> {
+    /// unquoted, like S3 returns it here
+    pub etag: Option<&'a str>,
+    /// the element name and the base64 checksum
+    pub checksum: Option<(&'static str, &'a str)>,
+    pub storage_class: Option<&'a str>,
+    pub object_size: Option<u64>,
+}
+
+/// The `<Delete>` body of `DeleteObjects`.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct DeleteObjectsRequest {
+    #[serde(default)]
+    pub quiet: bool,
+    #[serde(rename = "Object", default)]
+    pub objects: Vec<DeleteObjectsEntry>,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct DeleteObjectsEntry {
+    pub key: String,
+}
+
+#[derive(Debug)]
+pub struct DeleteError {
+    pub key: String,
+    pub code: &'static str,
+    pub message: String,
+}
+
+#[derive(Debug, Template)]
+#[template(path = "delete_result.xml")]
+pub struct DeleteResultTemplate<'a> {
+    pub deleted: &'a [String],
+    pub errors: &'a [DeleteError],
 }
 
 #[derive(Debug, Deserialize, PartialEq)]
 #[serde(rename_all = "PascalCase")]
 pub struct CreateBucket {
-    location_constraint: Option<String>,
+    pub location_constraint: Option<String>,
     location: Option<CreateBucketLocation>,
     bucket: Option<CreateBucketBucket>,
 }
@@ -85,30 +326,85 @@ fn renders_list_objects_xml() {
             key: "example1.jpg".into(),
             last_modified: Some("2019-10-12T17:50:30.000Z".into()),
             size: 1234,
+            storage_class: "STANDARD".into(),
+            owner: None,
         },
         ListObjectItem {
             etag: None,
             key: "example2.jpg".into(),
             last_modified: None,
             size: 1234,
+            storage_class: "GLACIER".into(),
+            owner: None,
         },
     ];
-    let template = ListObjectsTemplate {
-        is_truncated: false,
+    let start = ListObjectsStartTemplate {
         marker: "".into(),
-        next_marker: "".into(),
         bucket_name: "bucket1".into(),
         prefix: "".into(),
+        delimiter: "/".into(),
         max_keys: 1000,
-        objects,
     };
-    let template_str = template.render().expect("Unable to render template");
+    let end = ListObjectsEndTemplate {
+        common_prefixes: &[String::from("photos/")],
+        is_truncated: false,
+        next_marker: "".into(),
+    };
+
+    let mut template_str = start.render().expect("Unable to render template");
+    for object in objects {
+        template_str.push_str(&object.render().expect("Unable to render template"));
+    }
+    template_str.push_str(&end.render().expect("Unable to render template"));
+
     assert!(template_str.contains("fba9dede5f27731c9771645a39863328"));
     assert!(template_str.contains("2019-10-12T17:50:30.000Z"));
     assert!(template_str.contains("1234"));
     assert!(template_str.contains("example1.jpg"));
     assert!(template_str.contains("example2.jpg"));
+    assert!(template_str.contains("<StorageClass>GLACIER</StorageClass>"));
     assert!(template_str.contains("bucket1"));
+    assert!(template_str.contains("<Delimiter>/</Delimiter>"));
+    assert!(template_str.contains("<CommonPrefixes>"));
+    assert!(template_str.contains("<Prefix>photos/</Prefix>"));
+    assert!(template_str.ends_with("</ListBucketResult>"));
+    assert!(quick_xml::de::from_str::<serde::de::IgnoredAny>(&template_str).is_ok());
+}
+
+#[test]
+fn loads_delete_objects_xml() {
+    let xml = r#"<Delete xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+       <Object><Key>a.txt</Key></Object>
+       <Object><Key>dir/b.txt</Key><VersionId>null</VersionId></Object>
+       <Quiet>true</Quiet>
+    </Delete>"#;
+
+    let body: DeleteObjectsRequest = quick_xml::de::from_str(xml).unwrap();
+    assert!(body.quiet);
+    assert_eq!(
+        body.objects,
+        vec![
+            DeleteObjectsEntry {
+                key: String::from("a.txt")
+            },
+            DeleteObjectsEntry {
+                key: String::from("dir/b.txt")
+            },
+        ]
+    );
+
+    let rendered = DeleteResultTemplate {
+        deleted: &[String::from("a&b.txt")],
+        errors: &[DeleteError {
+            key: String::from("c.txt"),
+            code: "AccessDenied",
+            message: String::from("Access Denied"),
+        }],
+    }
+    .render()
+    .unwrap();
+    assert!(rendered.contains("<Key>a&amp;b.txt</Key>"));
+    assert!(rendered.contains("<Code>AccessDenied</Code>"));
 }
 
 #[test]
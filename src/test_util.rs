@@ -0,0 +1,120 @@
+use crate::builder::AppStateBuilder;
+use crate::metadata::MetadataStore;
+use crate::{server, AppState, Config};
+use aws_sdk_s3::config::{BehaviorVersion, Credentials, Region};
+use opendal::{Operator, Scheme};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use tokio::task::JoinHandle;
+
+pub const TEST_ACCESS_KEY: &str = "ANOTREAL";
+pub const TEST_SECRET_KEY: &str = "notrealrnrELgWzOk3IfjzDKtFBhDby";
+
+/// The S3 api on a random local port, backed by in-memory storage and metadata.
+///
+/// The server is stopped when the `TestServer` is dropped.
+pub struct TestServer {
+    addr: SocketAddr,
+    app_state: AppState,
+    handle: JoinHandle<std::io::Result<()>>,
+}
+
+impl TestServer {
+    pub async fn start() -> anyhow::Result<TestServer> {
+        TestServer::start_with(|_| {}).await
+    }
+
+    /// Starts the server with a changed config, the storage and metadata stay in-memory.
+    pub async fn start_with(f: impl FnOnce(&mut Config)) -> anyhow::Result<TestServer> {
+        TestServer::start_inner(f, |builder| builder).await
+    }
+
+    /// Starts the server with extra hooks or interceptors registered on the `AppStateBuilder`.
+    pub async fn start_with_state(
+        f: impl FnOnce(AppStateBuilder) -> AppStateBuilder,
+    ) -> anyhow::Result<TestServer> {
+        TestServer::start_inner(|_| {}, f).await
+    }
+
+    async fn start_inner(
+        config_fn: impl FnOnce(&mut Config),
+        state_fn: impl FnOnce(AppStateBuilder) -> AppStateBuilder,
+    ) -> anyhow::Result<TestServer> {
+        let mut config = Config::builder()
+            .server_host("127.0.0.1:0")
+            .opendal(Scheme::Memory, HashMap::new())
+            .with(config_fn)
+            .build();
+
+        let listener = server::bind(&config.server_host, &config.http).await?;
+        let addr = listener.local_addr()?;
+        config.external_server_host = format!("http://{}", addr);
+
+        let metadata = MetadataStore::memory();
+        metadata
+            .set(
+                &format!(
+                    "{}{}",
+                    crate::credentials::SECRET_KEY_PREFIX,
+                    TEST_ACCESS_KEY
+                ),
+                TEST_SECRET_KEY,
+            )
+            .await?;
+
+        let builder = AppState::builder(config)
+            .metadata(metadata)
+            .opendal_operator(Operator::via_map(Scheme::Memory, HashMap::new())?);
+        let app_state = state_fn(builder).build()?;
+
+        let handle = tokio::spawn(server::serve(
+            listener,
+            crate::router(app_state.clone()),
+            app_state.config.http.clone(),
+        ));
+
+        Ok(TestServer {
+            addr,
+            app_state,
+            handle,
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn endpoint_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// The state of the running server, to seed metadata or inspect the storage.
+    pub fn app_state(&self) -> &AppState {
+        &self.app_state
+    }
+
+    /// A client signing with the test credentials.
+    pub fn client(&self) -> aws_sdk_s3::Client {
+        let config = aws_sdk_s3::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("us-west-2"))
+            .credentials_provider(Credentials::new(
+                TEST_ACCESS_KEY,
+                TEST_SECRET_KEY,
+                None,
+                None,
+                "s3-proxy-test",
+            ))
+            .endpoint_url(self.endpoint_url())
+            .force_path_style(true)
+            .build();
+
+        aws_sdk_s3::Client::from_conf(config)
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
@@ -0,0 +1,133 @@
+use crate::etag_cache::EtagCache;
+use futures::StreamExt;
+use opendal::{Metakey, Operator};
+use serde::{Deserialize, Serialize};
+
+/// A bucket, or a prefix in it, of a namespace.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Location {
+    pub namespace: String,
+    pub bucket: String,
+    #[serde(default)]
+    pub prefix: String,
+}
+
+impl Location {
+    fn path(&self) -> String {
+        format!("{}/{}/{}", self.namespace, self.bucket, self.prefix)
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct TransferSummary {
+    pub objects: u64,
+    pub bytes: u64,
+}
+
+/// Copies every object below `from` to `to` within the backend, the content type is kept.
+///
+/// With `remove_source` the objects are deleted once all of them are copied, and an emptied
+/// bucket is removed as well, so a failed move leaves the source complete.
+pub async fn transfer(
+    operator: &Operator,
+    etag_cache: &EtagCache,
+    from: &Location,
+    to: &Location,
+    remove_source: bool,
+) -> opendal::Result<TransferSummary> {
+    let source = from.path();
+    let target = to.path();
+    let can_copy = operator.info().full_capability().copy;
+
+    let mut objects = operator
+        .lister_with(&source)
+        .recursive(true)
+        .metakey(Metakey::ContentLength | Metakey::ContentType)
+        .await?;
+
+    let mut summary = TransferSummary::default();
+    let mut copied = Vec::new();
+    while let Some(entry) = objects.next().await {
+        let entry = entry?;
+        if !entry.metadata().is_file() {
+            continue;
+        }
+
+        let path = entry.path();
+        let destination = format!("{}{}", target, &path[source.len()..]);
+        if can_copy {
+            operator.copy(path, &destination).await?;
+        } else {
+            let data = operator.read(path).await?;
+            let mut writer = operator.write_with(&destination, data);
+            if let Some(content_type) = entry.metadata().content_type() {
+                writer = writer.content_type(content_type);
+            }
+            writer.await?;
+        }
+        etag_cache.invalidate(&destination);
+
+        summary.objects += 1;
+        summary.bytes += entry.metadata().content_length();
+        copied.push(path.to_string());
+    }
+
+    if remove_source {
+        for path in &copied {
+            operator.delete(path).await?;
+            etag_cache.invalidate(path);
+        }
+        if from.prefix.is_empty() {
+            operator.remove_all(&source).await?;
+        }
+    }
+
+    Ok(summary)
+}
+
+#[tokio::test]
+async fn prefixes_are_moved_between_namespaces() {
+    let operator = Operator::new(opendal::services::Memory::default())
+        .unwrap()
+        .finish();
+    let etag_cache = EtagCache::new(&Default::default());
+    operator
+        .write_with("old/photos/2023/a.jpg", "image")
+        .content_type("image/jpeg")
+        .await
+        .unwrap();
+    operator
+        .write("old/photos/2024/b.jpg", "image")
+        .await
+        .unwrap();
+
+    let summary = transfer(
+        &operator,
+        &etag_cache,
+        &Location {
+            namespace: String::from("old"),
+            bucket: String::from("photos"),
+            prefix: String::from("2023/"),
+        },
+        &Location {
+            namespace: String::from("new"),
+            bucket: String::from("archive"),
+            prefix: String::new(),
+        },
+        true,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        summary,
+        TransferSummary {
+            objects: 1,
+            bytes: 5
+        }
+    );
+    assert!(!operator.is_exist("old/photos/2023/a.jpg").await.unwrap());
+    assert!(operator.is_exist("old/photos/2024/b.jpg").await.unwrap());
+    let moved = operator.stat("new/archive/a.jpg").await.unwrap();
+    assert_eq!(moved.content_type(), Some("image/jpeg"));
+}
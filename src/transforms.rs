@@ -0,0 +1,159 @@
+use crate::error::S3Error;
+use async_trait::async_trait;
+use axum::http::HeaderMap;
+use axum::BoxError;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use std::sync::Arc;
+
+/// What a GET asked for, transforms can take their parameters from the query string.
+pub struct TransformRequest<'a> {
+    pub namespace: &'a str,
+    pub bucket: &'a str,
+    pub key: &'a str,
+    /// the raw query string, empty when there is none
+    pub query: &'a str,
+    pub headers: &'a HeaderMap,
+}
+
+/// An object on its way to the client, the body streams from the backend.
+pub struct TransformedObject {
+    pub content_type: Option<String>,
+    pub body: BoxStream<'static, Result<Bytes, BoxError>>,
+}
+
+/// Compiled-in plugin that rewrites GET responses, like resizing images or redacting fields.
+///
+/// Transforms run between the backend read and the response, the response loses its
+/// `ETag` and `Content-Length` because they describe the stored object.
+#[async_trait]
+pub trait ObjectTransform: Send + Sync {
+    /// Transforms that do not apply leave the response untouched, like a resize without size.
+    fn applies(&self, _request: &TransformRequest<'_>) -> bool {
+        true
+    }
+
+    /// Returning an error fails the request with it.
+    async fn transform(
+        &self,
+        request: &TransformRequest<'_>,
+        object: TransformedObject,
+    ) -> Result<TransformedObject, S3Error>;
+}
+
+struct Registration {
+    bucket: String,
+    prefix: String,
+    transform: Arc<dyn ObjectTransform>,
+}
+
+/// The registered transforms, the matching ones run in registration order.
+#[derive(Clone, Default)]
+pub struct Transforms {
+    registrations: Arc<Vec<Registration>>,
+}
+
+impl Transforms {
+    /// Takes `(bucket, key prefix, transform)`, the bucket is matched in every namespace.
+    pub fn new(transforms: Vec<(String, String, Arc<dyn ObjectTransform>)>) -> Transforms {
+        Transforms {
+            registrations: Arc::new(
+                transforms
+                    .into_iter()
+                    .map(|(bucket, prefix, transform)| Registration {
+                        bucket,
+                        prefix,
+                        transform,
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.registrations.is_empty()
+    }
+
+    /// The transforms that apply to the request.
+    pub fn matching(&self, request: &TransformRequest<'_>) -> Vec<Arc<dyn ObjectTransform>> {
+        self.registrations
+            .iter()
+            .filter(|x| x.bucket == request.bucket && request.key.starts_with(&x.prefix))
+            .filter(|x| x.transform.applies(request))
+            .map(|x| x.transform.clone())
+            .collect()
+    }
+}
+
+/// Passes the object through the transforms in order.
+pub async fn apply(
+    transforms: &[Arc<dyn ObjectTransform>],
+    request: &TransformRequest<'_>,
+    mut object: TransformedObject,
+) -> Result<TransformedObject, S3Error> {
+    for transform in transforms {
+        object = transform.transform(request, object).await?;
+    }
+
+    Ok(object)
+}
+
+#[tokio::test]
+async fn transforms_match_bucket_prefix_and_query() {
+    use futures::{StreamExt, TryStreamExt};
+
+    struct Uppercase;
+
+    #[async_trait]
+    impl ObjectTransform for Uppercase {
+        fn applies(&self, request: &TransformRequest<'_>) -> bool {
+            request.query.contains("upper")
+        }
+
+        async fn transform(
+            &self,
+            _request: &TransformRequest<'_>,
+            object: TransformedObject,
+        ) -> Result<TransformedObject, S3Error> {
+            Ok(TransformedObject {
+                content_type: Some(String::from("text/plain")),
+                body: object
+                    .body
+                    .map_ok(|chunk| Bytes::from(chunk.to_ascii_uppercase()))
+                    .boxed(),
+            })
+        }
+    }
+
+    let transforms = Transforms::new(vec![(
+        String::from("docs"),
+        String::from("public/"),
+        Arc::new(Uppercase),
+    )]);
+    let headers = HeaderMap::new();
+    let request = |key, query| TransformRequest {
+        namespace: "tenant",
+        bucket: "docs",
+        key,
+        query,
+        headers: &headers,
+    };
+
+    assert!(transforms
+        .matching(&request("private/a.txt", "upper"))
+        .is_empty());
+    assert!(transforms.matching(&request("public/a.txt", "")).is_empty());
+
+    let request = request("public/a.txt", "upper");
+    let matching = transforms.matching(&request);
+    assert_eq!(matching.len(), 1);
+
+    let object = TransformedObject {
+        content_type: None,
+        body: futures::stream::iter([Ok(Bytes::from("hello"))]).boxed(),
+    };
+    let object = apply(&matching, &request, object).await.unwrap();
+    assert_eq!(object.content_type.as_deref(), Some("text/plain"));
+    let body: Vec<Bytes> = object.body.try_collect().await.unwrap();
+    assert_eq!(body, vec![Bytes::from("HELLO")]);
+}
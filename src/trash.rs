@@ -0,0 +1,313 @@
+use crate::lease::Leases;
+use futures::{StreamExt, TryStreamExt};
+use opendal::{ErrorKind, Metakey, Operator};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// The hidden directory in every namespace that holds the deleted objects.
+///
+/// Bucket names can not start with a `.`, so it never collides with a bucket.
+pub const TRASH_DIR: &str = ".trash";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TrashConfig {
+    /// deleted objects are moved to the trash instead of removed
+    #[serde(default)]
+    pub enabled: bool,
+    /// how long deleted objects can be restored
+    #[serde(default = "default_retention_days")]
+    pub retention_days: u64,
+    #[serde(default = "default_purge_interval_secs")]
+    pub purge_interval_secs: u64,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        TrashConfig {
+            enabled: false,
+            retention_days: default_retention_days(),
+            purge_interval_secs: default_purge_interval_secs(),
+        }
+    }
+}
+
+fn default_retention_days() -> u64 {
+    7
+}
+
+fn default_purge_interval_secs() -> u64 {
+    3600
+}
+
+/// A deleted object, the id is `{deleted_at}/{bucket}/{key}`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TrashEntry {
+    pub id: String,
+    pub bucket: String,
+    pub key: String,
+    pub size: u64,
+    /// unix timestamp in seconds
+    pub deleted_at: u64,
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn trash_root(namespace: &str) -> String {
+    format!("{}/{}/", namespace, TRASH_DIR)
+}
+
+/// Moves an object into the trash of its namespace, the content type is kept.
+pub async fn discard(
+    operator: &Operator,
+    namespace: &str,
+    bucket: &str,
+    key: &str,
+) -> opendal::Result<()> {
+    let path = format!("{}/{}/{}", namespace, bucket, key);
+    let destination = destination(operator, namespace, bucket, key).await?;
+    move_object(operator, &path, &destination).await
+}
+
+/// Copies an object into the trash of its namespace before it is overwritten, the object itself
+/// is left in place.
+pub async fn preserve(
+    operator: &Operator,
+    namespace: &str,
+    bucket: &str,
+    key: &str,
+) -> opendal::Result<()> {
+    let path = format!("{}/{}/{}", namespace, bucket, key);
+    let destination = destination(operator, namespace, bucket, key).await?;
+    copy_object(operator, &path, &destination).await
+}
+
+/// The path in the trash for an object discarded now, an object discarded earlier in the same
+/// second moves it to the next second so it is not overwritten.
+async fn destination(
+    operator: &Operator,
+    namespace: &str,
+    bucket: &str,
+    key: &str,
+) -> opendal::Result<String> {
+    let mut deleted_at = unix_now();
+    loop {
+        let path = format!("{}{}/{}/{}", trash_root(namespace), deleted_at, bucket, key);
+        if !operator.is_exist(&path).await? {
+            return Ok(path);
+        }
+        deleted_at += 1;
+    }
+}
+
+async fn move_object(operator: &Operator, from: &str, to: &str) -> opendal::Result<()> {
+    copy_object(operator, from, to).await?;
+    operator.delete(from).await
+}
+
+async fn copy_object(operator: &Operator, from: &str, to: &str) -> opendal::Result<()> {
+    // streamed from a reader into a writer, not every backend can copy or rename
+    let metadata = operator.stat(from).await?;
+    let reader = operator.reader(from).await?.map_err(|error| {
+        opendal::Error::new(ErrorKind::Unexpected, "reading the object failed").set_source(error)
+    });
+    let mut writer = operator.writer_with(to);
+    if let Some(content_type) = metadata.content_type() {
+        writer = writer.content_type(content_type);
+    }
+    let mut writer = writer.await?;
+    if let Err(error) = writer.sink(reader).await {
+        writer.abort().await?;
+        return Err(error);
+    }
+    writer.close().await
+}
+
+/// The deleted objects of a namespace, oldest first.
+pub async fn list(operator: &Operator, namespace: &str) -> opendal::Result<Vec<TrashEntry>> {
+    let root = trash_root(namespace);
+    let mut lister = operator
+        .lister_with(&root)
+        .recursive(true)
+        .metakey(Metakey::ContentLength)
+        .await?;
+
+    let mut entries = Vec::new();
+    while let Some(entry) = lister.next().await {
+        let entry = entry?;
+        if !entry.metadata().is_file() {
+            continue;
+        }
+
+        let id = &entry.path()[root.len()..];
+        let mut parts = id.splitn(3, '/');
+        let (Some(deleted_at), Some(bucket), Some(key)) = (
+            parts.next().and_then(|x| x.parse().ok()),
+            parts.next(),
+            parts.next(),
+        ) else {
+            continue;
+        };
+
+        entries.push(TrashEntry {
+            id: id.to_string(),
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            size: entry.metadata().content_length(),
+            deleted_at,
+        });
+    }
+    entries.sort_by(|a, b| (a.deleted_at, &a.id).cmp(&(b.deleted_at, &b.id)));
+
+    Ok(entries)
+}
+
+/// Puts a deleted object back, replacing an object that was written to the key since.
+///
+/// Returns false when the id is not in the trash.
+pub async fn restore(operator: &Operator, namespace: &str, id: &str) -> opendal::Result<bool> {
+    let Some((bucket, key)) = id
+        .split_once('/')
+        .and_then(|(_, rest)| rest.split_once('/'))
+    else {
+        return Ok(false);
+    };
+    if id.split('/').any(|x| x == "..") {
+        return Ok(false);
+    }
+
+    let path = format!("{}{}", trash_root(namespace), id);
+    if !operator.is_exist(&path).await? {
+        return Ok(false);
+    }
+
+    operator
+        .create_dir(&format!("{}/{}/", namespace, bucket))
+        .await?;
+    move_object(
+        operator,
+        &path,
+        &format!("{}/{}/{}", namespace, bucket, key),
+    )
+    .await?;
+
+    Ok(true)
+}
+
+/// Removes the deleted objects of every namespace that are older than `retention`.
+///
+/// Locked objects never reach the trash, deleting or overwriting them is refused, so the purge
+/// does not look at object locks.
+pub async fn purge(operator: &Operator, retention: Duration) -> opendal::Result<u64> {
+    let cutoff = unix_now().saturating_sub(retention.as_secs());
+    let mut purged = 0;
+
+    let mut namespaces = operator.lister("/").await?;
+    while let Some(namespace) = namespaces.next().await {
+        let namespace = namespace?;
+        if !namespace.metadata().is_dir() || namespace.path() == "/" {
+            continue;
+        }
+
+        let root = trash_root(namespace.path().trim_end_matches('/'));
+        if !operator.is_exist(&root).await? {
+            continue;
+        }
+        let mut batches = operator.lister(&root).await?;
+        while let Some(batch) = batches.next().await {
+            let batch = batch?;
+            let deleted_at = batch.name().trim_end_matches('/').parse::<u64>();
+            if deleted_at.is_ok_and(|x| x < cutoff) {
+                operator.remove_all(batch.path()).await?;
+                purged += 1;
+            }
+        }
+    }
+
+    Ok(purged)
+}
+
+pub async fn purge_periodically(
+    operator: Operator,
+    leases: Arc<Leases>,
+    retention: Duration,
+    every: Duration,
+) {
+    let mut interval = tokio::time::interval(every);
+
+    loop {
+        interval.tick().await;
+        let Some(_lease) = leases.for_job("trash").await else {
+            continue;
+        };
+
+        match purge(&operator, retention).await {
+            Ok(0) => (),
+            Ok(purged) => tracing::info!("purged {} expired trash batches", purged),
+            Err(error) => tracing::error!("unable to purge the trash: {}", error),
+        }
+    }
+}
+
+#[tokio::test]
+async fn deleted_objects_are_restored_and_purged() {
+    let operator = Operator::new(opendal::services::Memory::default())
+        .unwrap()
+        .finish();
+    operator
+        .write_with("tenant/photos/dir/a.jpg", "image")
+        .content_type("image/jpeg")
+        .await
+        .unwrap();
+
+    discard(&operator, "tenant", "photos", "dir/a.jpg")
+        .await
+        .unwrap();
+    assert!(!operator.is_exist("tenant/photos/dir/a.jpg").await.unwrap());
+
+    let entries = list(&operator, "tenant").await.unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].bucket, "photos");
+    assert_eq!(entries[0].key, "dir/a.jpg");
+    assert_eq!(entries[0].size, 5);
+
+    assert!(restore(&operator, "tenant", &entries[0].id).await.unwrap());
+    let restored = operator.stat("tenant/photos/dir/a.jpg").await.unwrap();
+    assert_eq!(restored.content_type(), Some("image/jpeg"));
+    assert!(list(&operator, "tenant").await.unwrap().is_empty());
+
+    // kept next to each other within the same second
+    preserve(&operator, "tenant", "photos", "dir/a.jpg")
+        .await
+        .unwrap();
+    discard(&operator, "tenant", "photos", "dir/a.jpg")
+        .await
+        .unwrap();
+    let entries = list(&operator, "tenant").await.unwrap();
+    assert_eq!(entries.len(), 2);
+    for entry in entries {
+        assert!(restore(&operator, "tenant", &entry.id).await.unwrap());
+    }
+
+    discard(&operator, "tenant", "photos", "dir/a.jpg")
+        .await
+        .unwrap();
+    assert_eq!(
+        purge(&operator, Duration::from_secs(3600)).await.unwrap(),
+        0
+    );
+    operator
+        .write("tenant/.trash/1/photos/old.txt", "old")
+        .await
+        .unwrap();
+    assert_eq!(
+        purge(&operator, Duration::from_secs(3600)).await.unwrap(),
+        1
+    );
+    assert_eq!(list(&operator, "tenant").await.unwrap().len(), 1);
+}
@@ -0,0 +1,97 @@
+use crate::axum_ext::query_value;
+use crate::error::S3Error;
+use axum::extract::Request;
+use axum::http::Uri;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+
+/// The versioning state of a bucket, buckets that never had it configured have neither.
+///
+/// Objects are written in place, so an `Enabled` bucket keeps the objects it overwrites and
+/// deletes in the trash of the namespace, where they can be restored from. They are only purged
+/// when the trash is enabled in the config. This is recovery through the trash, not S3
+/// versioning: every object only has the `null` version, no `x-amz-version-id` is returned and
+/// other version ids are refused by [`reject_version_ids`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersioningStatus {
+    Enabled,
+    Suspended,
+}
+
+impl VersioningStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VersioningStatus::Enabled => "Enabled",
+            VersioningStatus::Suspended => "Suspended",
+        }
+    }
+
+    pub fn parse(status: &str) -> Option<VersioningStatus> {
+        match status {
+            "Enabled" => Some(VersioningStatus::Enabled),
+            "Suspended" => Some(VersioningStatus::Suspended),
+            _ => None,
+        }
+    }
+}
+
+/// The `<VersioningConfiguration>` body of `PutBucketVersioning`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct VersioningConfiguration {
+    pub status: Option<String>,
+    pub mfa_delete: Option<String>,
+}
+
+/// Refuses `?versionId` naming another version than `null`, ignoring it would read, tag or
+/// delete the current object while the client asked for an older one.
+pub fn check_version_id(uri: &Uri) -> Result<(), S3Error> {
+    match query_value(uri.query().unwrap_or_default(), "versionId") {
+        Some(version_id) if version_id != "null" => Err(S3Error::NotImplemented(String::from(
+            "objects only have the null version, older versions are kept in the trash",
+        ))),
+        _ => Ok(()),
+    }
+}
+
+pub async fn reject_version_ids(req: Request, next: Next) -> Response {
+    if let Err(error) = check_version_id(req.uri()) {
+        return error.into_response();
+    }
+
+    next.run(req).await
+}
+
+#[test]
+fn version_ids_other_than_null_are_refused() {
+    let check = |uri: &str| check_version_id(&uri.parse().unwrap());
+    assert!(check("/bucket/a.txt").is_ok());
+    assert!(check("/bucket/a.txt?versionId=null").is_ok());
+    assert!(matches!(
+        check("/bucket/a.txt?tagging&versionId=3HL4kqtJlcpXroDTDmJ"),
+        Err(S3Error::NotImplemented(_))
+    ));
+}
+
+#[test]
+fn versioning_configurations_are_parsed() {
+    let xml = r#"<VersioningConfiguration xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+       <Status>Enabled</Status>
+    </VersioningConfiguration>"#;
+    let configuration: VersioningConfiguration = quick_xml::de::from_str(xml).unwrap();
+    assert_eq!(
+        configuration
+            .status
+            .as_deref()
+            .and_then(VersioningStatus::parse),
+        Some(VersioningStatus::Enabled)
+    );
+    assert_eq!(configuration.mfa_delete, None);
+
+    assert_eq!(
+        VersioningStatus::parse("Suspended"),
+        Some(VersioningStatus::Suspended)
+    );
+    assert_eq!(VersioningStatus::parse("enabled"), None);
+}
@@ -1,40 +1,34 @@
 use std::path::Path;
-use std::process::{Child, Command};
+use std::time::Duration;
 
-use aws_config::meta::region::RegionProviderChain;
-use aws_sdk_s3::config::Region;
-use aws_sdk_s3::primitives::ByteStream;
-use aws_sdk_s3::types::{Bucket, Owner};
-use aws_sdk_s3::Client;
-
-/// `setup()` is used to prepare the environment and spawn the child process for the test cases.
-fn setup() -> std::io::Result<Child> {
-    let path = assert_cmd::cargo::cargo_bin(env!("CARGO_PKG_NAME"));
-
-    let process = Command::new(path)
-        .env("S3_PROXY__REDIS__URL", "redis://127.0.0.1:6379")
-        .env("S3_PROXY__OPENDAL_PROVIDER", "memory")
-        .env("S3_PROXY__OPENDAL__ROOT", "/tmp")
-        .spawn();
-
-    // tracing_subscriber::fmt().with_max_level(tracing::Level::TRACE).init();
-
-    process
-}
+use aws_sdk_s3::config::interceptors::BeforeTransmitInterceptorContextMut;
+use aws_sdk_s3::config::{ConfigBag, Credentials, Intercept, Region, RuntimeComponents};
+use aws_sdk_s3::primitives::{ByteStream, DateTime, SdkBody};
+use aws_sdk_s3::types::{
+    AbortIncompleteMultipartUpload, Bucket, BucketLifecycleConfiguration, BucketLocationConstraint,
+    BucketVersioningStatus, ChecksumAlgorithm, ChecksumMode, CompletedMultipartUpload,
+    CompletedPart, CorsConfiguration, CorsRule, CreateBucketConfiguration, CsvInput, CsvOutput,
+    DefaultRetention, Event, ExpirationStatus, ExpressionType, FileHeaderInfo, InputSerialization,
+    JsonOutput, LifecycleExpiration, LifecycleRule, LifecycleRuleFilter, NotificationConfiguration,
+    ObjectAttributes, ObjectLockConfiguration, ObjectLockEnabled, ObjectLockLegalHold,
+    ObjectLockLegalHoldStatus, ObjectLockMode, ObjectLockRetention, ObjectLockRetentionMode,
+    ObjectLockRule, OutputSerialization, Owner, QueueConfiguration, SelectObjectContentEventStream,
+    StorageClass, Tag, Tagging, Transition, TransitionStorageClass, VersioningConfiguration,
+};
+use s3_proxy::client::{Client, ClientConfig};
+use s3_proxy::domains::DomainMapping;
+use s3_proxy::error::S3Error;
+use s3_proxy::namespaces::{Namespaces, Quota};
+use s3_proxy::notifications;
+use s3_proxy::plugins::{InterceptedRequest, RequestInterceptor};
+use s3_proxy::test_util::{TestServer, TEST_ACCESS_KEY, TEST_SECRET_KEY};
+use s3_proxy::transforms::{ObjectTransform, TransformRequest, TransformedObject};
+use s3_proxy::trash;
 
 #[tokio::test]
 async fn test_it_runs() {
-    let mut process = setup().unwrap();
-
-    let region_provider = RegionProviderChain::first_try(Region::new("us-west-2"));
-
-    let shared_config = aws_config::from_env()
-        .region(region_provider)
-        .test_credentials()
-        .endpoint_url("http://127.0.0.1:3000")
-        .load()
-        .await;
-    let client = Client::new(&shared_config);
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
 
     let create_bucket_req1 = client.create_bucket().bucket("testing");
     let create_bucket_req2 = client.create_bucket().bucket("testing2");
@@ -63,7 +57,7 @@ async fn test_it_runs() {
         .send()
         .await;
 
-    process.kill().expect("command couldn't be killed");
+    drop(server);
 
     let out = list_bucket_res.unwrap();
 
@@ -88,8 +82,10 @@ async fn test_it_runs() {
     assert_eq!(owner, Some(&expected_owner));
     put_object_res.unwrap();
 
-    let _response = list_object_res.unwrap();
-    // do assertions here of list
+    let response = list_object_res.unwrap();
+    let keys: Vec<_> = response.contents().iter().filter_map(|x| x.key()).collect();
+    assert_eq!(keys, vec!["Cargo.toml"]);
+    assert!(response.contents()[0].size().unwrap_or_default() > 0);
 
     let response = get_object_res.unwrap();
     let content_type = response.content_type();
@@ -99,3 +95,3658 @@ async fn test_it_runs() {
     let body = String::from_utf8(response.body.collect().await.unwrap().to_vec()).unwrap();
     assert!(body.contains("s3-proxy"));
 }
+
+struct AliasInterceptor;
+
+#[async_trait::async_trait]
+impl RequestInterceptor for AliasInterceptor {
+    async fn on_request(&self, request: &mut InterceptedRequest<'_>) -> Result<(), S3Error> {
+        if request
+            .headers
+            .get("content-type")
+            .and_then(|x| x.to_str().ok())
+            == Some("application/x-blocked")
+        {
+            return Err(S3Error::InvalidArgument(String::from(
+                "blocked content type",
+            )));
+        }
+        if request.key.as_deref() == Some("alias") {
+            request.key = Some(String::from("target"));
+        }
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn interceptors_rewrite_and_block_requests() {
+    let server = TestServer::start_with_state(|builder| builder.interceptor(AliasInterceptor))
+        .await
+        .unwrap();
+    let client = server.client();
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    client
+        .put_object()
+        .bucket("testing")
+        .key("alias")
+        .body(ByteStream::from_static(b"hello"))
+        .send()
+        .await
+        .unwrap();
+
+    let response = client
+        .get_object()
+        .bucket("testing")
+        .key("target")
+        .send()
+        .await
+        .unwrap();
+    let body = response.body.collect().await.unwrap().to_vec();
+    assert_eq!(body, b"hello");
+
+    let error = client
+        .put_object()
+        .bucket("testing")
+        .key("blocked")
+        .content_type("application/x-blocked")
+        .body(ByteStream::from_static(b"hello"))
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("InvalidArgument")
+    );
+}
+
+#[tokio::test]
+async fn client_subcommands_talk_to_the_proxy() {
+    let server = TestServer::start().await.unwrap();
+    let client = Client::new(ClientConfig {
+        endpoint: server.endpoint_url(),
+        access_key: Some(TEST_ACCESS_KEY.to_string()),
+        secret_key: Some(TEST_SECRET_KEY.to_string()),
+        ..ClientConfig::default()
+    })
+    .unwrap();
+
+    client.create_bucket("testing").await.unwrap();
+    client
+        .put_object("testing", "dir/hello world.txt", "hello".into())
+        .await
+        .unwrap();
+
+    assert_eq!(client.list_buckets().await.unwrap(), vec!["testing"]);
+    let objects = client.list_objects("testing", "dir/").await.unwrap();
+    assert_eq!(objects.len(), 1);
+    assert_eq!(objects[0].key, "dir/hello world.txt");
+    assert_eq!(
+        client
+            .get_object("testing", "dir/hello world.txt")
+            .await
+            .unwrap(),
+        "hello"
+    );
+}
+
+#[tokio::test]
+async fn custom_domains_verify_the_signed_host_and_path() {
+    let server = TestServer::start().await.unwrap();
+    server
+        .client()
+        .create_bucket()
+        .bucket("assets")
+        .send()
+        .await
+        .unwrap();
+
+    let map = |namespace: &str| {
+        let app_state = server.app_state();
+        let mapping = DomainMapping {
+            namespace: namespace.to_string(),
+            bucket: String::from("assets"),
+        };
+        async move {
+            app_state
+                .domains
+                .set(&app_state.metadata, "localhost", &mapping)
+                .await
+                .unwrap()
+        }
+    };
+    map(TEST_ACCESS_KEY).await;
+
+    // the client signs `/img/logo.svg` for the host `localhost`, the proxy serves that from
+    // the mapped bucket
+    let client = Client::new(ClientConfig {
+        endpoint: format!("http://localhost:{}", server.addr().port()),
+        access_key: Some(TEST_ACCESS_KEY.to_string()),
+        secret_key: Some(TEST_SECRET_KEY.to_string()),
+        ..ClientConfig::default()
+    })
+    .unwrap();
+    client
+        .put_object("img", "logo.svg", "<svg/>".into())
+        .await
+        .unwrap();
+    assert_eq!(
+        client.get_object("img", "logo.svg").await.unwrap(),
+        "<svg/>"
+    );
+
+    let response = server
+        .client()
+        .get_object()
+        .bucket("assets")
+        .key("img/logo.svg")
+        .send()
+        .await
+        .unwrap();
+    let body = response.body.collect().await.unwrap().to_vec();
+    assert_eq!(body, b"<svg/>");
+
+    map("other").await;
+    let error = client.get_object("img", "logo.svg").await.unwrap_err();
+    assert!(error.to_string().contains("AccessDenied"), "{error}");
+}
+
+#[tokio::test]
+async fn namespaces_get_their_default_buckets_on_first_use() {
+    let server = TestServer::start_with(|config| {
+        config.default_buckets = vec![String::from("uploads"), String::from("exports")];
+    })
+    .await
+    .unwrap();
+
+    let response = server.client().list_buckets().send().await.unwrap();
+    let mut buckets: Vec<_> = response.buckets().iter().filter_map(|x| x.name()).collect();
+    buckets.sort();
+    assert_eq!(buckets, vec!["exports", "uploads"]);
+}
+
+#[tokio::test]
+async fn frozen_buckets_are_read_only() {
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    client
+        .put_object()
+        .bucket("testing")
+        .key("a.txt")
+        .body(ByteStream::from_static(b"hello"))
+        .send()
+        .await
+        .unwrap();
+
+    Namespaces::new(&server.app_state().metadata)
+        .set_frozen(TEST_ACCESS_KEY, "testing", true)
+        .await
+        .unwrap();
+
+    let error = client
+        .put_object()
+        .bucket("testing")
+        .key("b.txt")
+        .body(ByteStream::from_static(b"hello"))
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("AccessDenied")
+    );
+
+    let response = client
+        .get_object()
+        .bucket("testing")
+        .key("a.txt")
+        .send()
+        .await
+        .unwrap();
+    let body = response.body.collect().await.unwrap().to_vec();
+    assert_eq!(body, b"hello");
+}
+
+#[tokio::test]
+async fn deleted_objects_are_gone() {
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    client
+        .put_object()
+        .bucket("testing")
+        .key("dir/a.txt")
+        .body(ByteStream::from_static(b"hello"))
+        .send()
+        .await
+        .unwrap();
+
+    client
+        .delete_object()
+        .bucket("testing")
+        .key("dir/a.txt")
+        .send()
+        .await
+        .unwrap();
+
+    let error = client
+        .get_object()
+        .bucket("testing")
+        .key("dir/a.txt")
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(error.into_service_error().meta().code(), Some("NoSuchKey"));
+    let listing = client
+        .list_objects()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    assert!(listing.contents().is_empty());
+
+    let error = client
+        .delete_object()
+        .bucket("testing")
+        .key("dir/a.txt")
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(error.into_service_error().meta().code(), Some("NoSuchKey"));
+}
+
+#[tokio::test]
+async fn keys_cannot_leave_their_bucket() {
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+    let operator = &server.app_state().opendal_operator;
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    operator
+        .write("other/bucket/secret.txt", "secret")
+        .await
+        .unwrap();
+
+    let key = "a/../../../other/bucket/secret.txt";
+    let error = client
+        .get_object()
+        .bucket("testing")
+        .key(key)
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("InvalidArgument")
+    );
+    let error = client
+        .put_object()
+        .bucket("testing")
+        .key(key)
+        .body(ByteStream::from_static(b"overwritten"))
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("InvalidArgument")
+    );
+    let error = client
+        .delete_object()
+        .bucket("testing")
+        .key(key)
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("InvalidArgument")
+    );
+    for key in ["a//b.txt", "./b.txt"] {
+        let error = client
+            .put_object()
+            .bucket("testing")
+            .key(key)
+            .body(ByteStream::from_static(b"data"))
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.into_service_error().meta().code(),
+            Some("InvalidArgument")
+        );
+    }
+
+    assert_eq!(
+        operator
+            .read("other/bucket/secret.txt")
+            .await
+            .unwrap()
+            .to_vec(),
+        b"secret"
+    );
+}
+
+#[tokio::test]
+async fn objects_are_deleted_in_batches() {
+    use aws_sdk_s3::types::{Delete, ObjectIdentifier};
+
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    for key in ["a.txt", "dir/b.txt", "c.txt"] {
+        client
+            .put_object()
+            .bucket("testing")
+            .key(key)
+            .body(ByteStream::from_static(b"hello"))
+            .send()
+            .await
+            .unwrap();
+    }
+
+    let delete = |keys: &[&str], quiet: bool| {
+        Delete::builder()
+            .set_objects(Some(
+                keys.iter()
+                    .map(|key| ObjectIdentifier::builder().key(*key).build().unwrap())
+                    .collect(),
+            ))
+            .quiet(quiet)
+            .build()
+            .unwrap()
+    };
+    let response = client
+        .delete_objects()
+        .bucket("testing")
+        .delete(delete(&["a.txt", "dir/b.txt", "missing.txt"], false))
+        .send()
+        .await
+        .unwrap();
+    let deleted: Vec<_> = response.deleted().iter().filter_map(|x| x.key()).collect();
+    assert_eq!(deleted, vec!["a.txt", "dir/b.txt", "missing.txt"]);
+    assert!(response.errors().is_empty());
+
+    let response = client
+        .delete_objects()
+        .bucket("testing")
+        .delete(delete(&["c.txt"], true))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.deleted().is_empty());
+
+    let listing = client
+        .list_objects()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    assert!(listing.contents().is_empty());
+}
+
+#[tokio::test]
+async fn list_objects_pages_with_markers() {
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    for key in ["c.txt", "a.txt", "b.txt"] {
+        client
+            .put_object()
+            .bucket("testing")
+            .key(key)
+            .body(ByteStream::from_static(b"hello"))
+            .send()
+            .await
+            .unwrap();
+    }
+
+    let response = client
+        .list_objects()
+        .bucket("testing")
+        .max_keys(2)
+        .send()
+        .await
+        .unwrap();
+    let keys: Vec<_> = response.contents().iter().filter_map(|x| x.key()).collect();
+    assert_eq!(keys, vec!["a.txt", "b.txt"]);
+    assert_eq!(response.is_truncated(), Some(true));
+    assert_eq!(response.max_keys(), Some(2));
+    assert_eq!(response.next_marker(), Some("b.txt"));
+
+    let response = client
+        .list_objects()
+        .bucket("testing")
+        .max_keys(2)
+        .marker("b.txt")
+        .send()
+        .await
+        .unwrap();
+    let keys: Vec<_> = response.contents().iter().filter_map(|x| x.key()).collect();
+    assert_eq!(keys, vec!["c.txt"]);
+    assert_eq!(response.is_truncated(), Some(false));
+    assert_eq!(response.marker(), Some("b.txt"));
+}
+
+#[tokio::test]
+async fn listings_group_keys_by_delimiter() {
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    for key in [
+        "readme.txt",
+        "photos/2023/a.jpg",
+        "photos/2024/b.jpg",
+        "photos/c.jpg",
+        "videos/d.mp4",
+    ] {
+        client
+            .put_object()
+            .bucket("testing")
+            .key(key)
+            .body(ByteStream::from_static(b"hello"))
+            .send()
+            .await
+            .unwrap();
+    }
+
+    let response = client
+        .list_objects()
+        .bucket("testing")
+        .delimiter("/")
+        .send()
+        .await
+        .unwrap();
+    let keys: Vec<_> = response.contents().iter().filter_map(|x| x.key()).collect();
+    let prefixes: Vec<_> = response
+        .common_prefixes()
+        .iter()
+        .filter_map(|x| x.prefix())
+        .collect();
+    assert_eq!(keys, vec!["readme.txt"]);
+    assert_eq!(prefixes, vec!["photos/", "videos/"]);
+
+    let response = client
+        .list_objects_v2()
+        .bucket("testing")
+        .prefix("photos/")
+        .delimiter("/")
+        .max_keys(2)
+        .send()
+        .await
+        .unwrap();
+    let prefixes: Vec<_> = response
+        .common_prefixes()
+        .iter()
+        .filter_map(|x| x.prefix())
+        .collect();
+    assert_eq!(prefixes, vec!["photos/2023/", "photos/2024/"]);
+    assert!(response.contents().is_empty());
+    assert_eq!(response.key_count(), Some(2));
+    assert_eq!(response.is_truncated(), Some(true));
+
+    let response = client
+        .list_objects_v2()
+        .bucket("testing")
+        .prefix("photos/")
+        .delimiter("/")
+        .continuation_token(response.next_continuation_token().unwrap())
+        .send()
+        .await
+        .unwrap();
+    let keys: Vec<_> = response.contents().iter().filter_map(|x| x.key()).collect();
+    assert_eq!(keys, vec!["photos/c.jpg"]);
+    assert!(response.common_prefixes().is_empty());
+
+    let response = client
+        .list_objects_v2()
+        .bucket("testing")
+        .prefix("vid")
+        .send()
+        .await
+        .unwrap();
+    let keys: Vec<_> = response.contents().iter().filter_map(|x| x.key()).collect();
+    assert_eq!(keys, vec!["videos/d.mp4"]);
+}
+
+#[tokio::test]
+async fn list_objects_v2_pages_with_continuation_tokens() {
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    for key in ["c.txt", "a.txt", "dir/b.txt", "d e.txt"] {
+        client
+            .put_object()
+            .bucket("testing")
+            .key(key)
+            .body(ByteStream::from_static(b"hello"))
+            .send()
+            .await
+            .unwrap();
+    }
+
+    let mut pages = Vec::new();
+    let mut token = None;
+    loop {
+        let response = client
+            .list_objects_v2()
+            .bucket("testing")
+            .max_keys(3)
+            .set_continuation_token(token)
+            .send()
+            .await
+            .unwrap();
+        let keys: Vec<_> = response
+            .contents()
+            .iter()
+            .filter_map(|x| x.key().map(String::from))
+            .collect();
+        assert_eq!(response.key_count(), Some(keys.len() as i32));
+        pages.push(keys);
+        token = response.next_continuation_token().map(String::from);
+        if !response.is_truncated().unwrap_or_default() {
+            break;
+        }
+    }
+    assert_eq!(
+        pages,
+        vec![vec!["a.txt", "c.txt", "d e.txt"], vec!["dir/b.txt"]]
+    );
+
+    let response = client
+        .list_objects_v2()
+        .bucket("testing")
+        .start_after("d e.txt")
+        .fetch_owner(true)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.start_after(), Some("d e.txt"));
+    assert_eq!(response.contents().len(), 1);
+    assert_eq!(response.contents()[0].key(), Some("dir/b.txt"));
+    assert_eq!(
+        response.contents()[0].owner().and_then(|x| x.id()),
+        Some(TEST_ACCESS_KEY)
+    );
+    assert!(!response.is_truncated().unwrap_or_default());
+}
+
+#[tokio::test]
+async fn only_empty_buckets_are_deleted() {
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    client
+        .put_object()
+        .bucket("testing")
+        .key("a.txt")
+        .body(ByteStream::from_static(b"hello"))
+        .send()
+        .await
+        .unwrap();
+
+    let error = client
+        .delete_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("BucketNotEmpty")
+    );
+
+    client
+        .delete_object()
+        .bucket("testing")
+        .key("a.txt")
+        .send()
+        .await
+        .unwrap();
+    client
+        .delete_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+
+    let buckets = client.list_buckets().send().await.unwrap();
+    assert!(buckets.buckets().is_empty());
+    let error = client
+        .delete_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("NoSuchBucket")
+    );
+
+    // objects are not written into buckets that do not exist
+    let error = client
+        .put_object()
+        .bucket("testing")
+        .key("a.txt")
+        .body(ByteStream::from_static(b"hello"))
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("NoSuchBucket")
+    );
+    let buckets = client.list_buckets().send().await.unwrap();
+    assert!(buckets.buckets().is_empty());
+}
+
+#[tokio::test]
+async fn bucket_notification_configurations_are_stored() {
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+
+    let queue = QueueConfiguration::builder()
+        .queue_arn("arn:aws:sqs:us-east-1:000000000000:uploads")
+        .events(Event::S3ObjectCreated)
+        .build()
+        .unwrap();
+    let error = client
+        .put_bucket_notification_configuration()
+        .bucket("testing")
+        .notification_configuration(
+            NotificationConfiguration::builder()
+                .queue_configurations(queue)
+                .build(),
+        )
+        .send()
+        .await
+        .unwrap_err();
+    // without `S3_PROXY__SQS__*` there is nothing to deliver the events
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("InvalidArgument")
+    );
+
+    let configuration = notifications::NotificationConfiguration {
+        queue_configurations: vec![notifications::QueueConfiguration {
+            id: Some(String::from("uploads")),
+            queue: String::from("arn:aws:sqs:us-east-1:000000000000:uploads"),
+            events: vec![String::from("s3:ObjectCreated:*")],
+            filter: None,
+        }],
+        ..Default::default()
+    };
+    Namespaces::new(&server.app_state().metadata)
+        .set_notification_configuration(TEST_ACCESS_KEY, "testing", &configuration)
+        .await
+        .unwrap();
+
+    let response = client
+        .get_bucket_notification_configuration()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    let queues = response.queue_configurations();
+    assert_eq!(queues.len(), 1);
+    assert_eq!(queues[0].id(), Some("uploads"));
+    assert_eq!(
+        queues[0].queue_arn(),
+        "arn:aws:sqs:us-east-1:000000000000:uploads"
+    );
+    assert_eq!(queues[0].events(), &[Event::S3ObjectCreated]);
+}
+
+#[cfg(feature = "replication")]
+#[tokio::test]
+async fn objects_are_replicated_to_the_destination() {
+    use aws_sdk_s3::types::{
+        Destination, ReplicationConfiguration, ReplicationRule, ReplicationRuleFilter,
+        ReplicationRuleStatus,
+    };
+
+    let destination = TestServer::start().await.unwrap();
+    destination
+        .client()
+        .create_bucket()
+        .bucket("backup")
+        .send()
+        .await
+        .unwrap();
+
+    let source = TestServer::start_with(|config| {
+        config.replication = Some(s3_proxy::replication::ReplicationConfig {
+            access_key: TEST_ACCESS_KEY.to_string(),
+            secret_key: TEST_SECRET_KEY.to_string(),
+            region: String::from("us-east-1"),
+            endpoint: Some(destination.endpoint_url()),
+        })
+    })
+    .await
+    .unwrap();
+    let client = source.client();
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    let rule = ReplicationRule::builder()
+        .id("backup")
+        .status(ReplicationRuleStatus::Enabled)
+        .filter(ReplicationRuleFilter::Prefix(String::from("docs/")))
+        .destination(
+            Destination::builder()
+                .bucket("arn:aws:s3:::backup")
+                .build()
+                .unwrap(),
+        )
+        .build()
+        .unwrap();
+    client
+        .put_bucket_replication()
+        .bucket("testing")
+        .replication_configuration(
+            ReplicationConfiguration::builder()
+                .role("arn:aws:iam::000000000000:role/replication")
+                .rules(rule)
+                .build()
+                .unwrap(),
+        )
+        .send()
+        .await
+        .unwrap();
+
+    for key in ["docs/a.txt", "other/b.txt"] {
+        client
+            .put_object()
+            .bucket("testing")
+            .key(key)
+            .body(ByteStream::from_static(b"hello"))
+            .send()
+            .await
+            .unwrap();
+    }
+
+    let mut status = None;
+    for _ in 0..100 {
+        let response = client
+            .head_object()
+            .bucket("testing")
+            .key("docs/a.txt")
+            .send()
+            .await
+            .unwrap();
+        status = response
+            .replication_status()
+            .map(|x| x.as_str().to_string());
+        if status.as_deref() == Some("COMPLETED") {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert_eq!(status.as_deref(), Some("COMPLETED"));
+
+    let response = destination
+        .client()
+        .get_object()
+        .bucket("backup")
+        .key("docs/a.txt")
+        .send()
+        .await
+        .unwrap();
+    let body = response.body.collect().await.unwrap().to_vec();
+    assert_eq!(body, b"hello");
+
+    let response = client
+        .head_object()
+        .bucket("testing")
+        .key("other/b.txt")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.replication_status(), None);
+}
+
+struct Redact;
+
+#[async_trait::async_trait]
+impl ObjectTransform for Redact {
+    fn applies(&self, request: &TransformRequest<'_>) -> bool {
+        request.query.split('&').any(|x| x == "redact")
+    }
+
+    async fn transform(
+        &self,
+        _request: &TransformRequest<'_>,
+        object: TransformedObject,
+    ) -> Result<TransformedObject, S3Error> {
+        use futures::{StreamExt, TryStreamExt};
+
+        let body = object
+            .body
+            .try_fold(Vec::new(), |mut body, chunk| async move {
+                body.extend_from_slice(&chunk);
+                Ok(body)
+            })
+            .await
+            .map_err(S3Error::internal)?;
+        let mut document: serde_json::Value = serde_json::from_slice(&body)
+            .map_err(|_| S3Error::InvalidRequest("not json".into()))?;
+        document["secret"] = serde_json::Value::Null;
+
+        Ok(TransformedObject {
+            content_type: Some(String::from("application/json")),
+            body: futures::stream::once(async move { Ok(document.to_string().into()) }).boxed(),
+        })
+    }
+}
+
+#[tokio::test]
+async fn transforms_rewrite_get_responses() {
+    let server = TestServer::start_with_state(|builder| {
+        builder.object_transform("testing", "documents/", Redact)
+    })
+    .await
+    .unwrap();
+    let client = server.client();
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    client
+        .put_object()
+        .bucket("testing")
+        .key("documents/a.json")
+        .body(ByteStream::from_static(br#"{"name":"a","secret":"b"}"#))
+        .send()
+        .await
+        .unwrap();
+
+    let response = client
+        .get_object()
+        .bucket("testing")
+        .key("documents/a.json")
+        .send()
+        .await
+        .unwrap();
+    let body = response.body.collect().await.unwrap().to_vec();
+    assert_eq!(body, br#"{"name":"a","secret":"b"}"#);
+
+    let response = client
+        .get_object()
+        .bucket("testing")
+        .key("documents/a.json")
+        .customize()
+        .mutate_request(|request| {
+            let uri = format!("{}&redact", request.uri());
+            request.set_uri(uri).unwrap();
+        })
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.e_tag(), None);
+    let body = response.body.collect().await.unwrap().to_vec();
+    assert_eq!(body, br#"{"name":"a","secret":null}"#);
+}
+
+#[tokio::test]
+async fn listings_are_json_with_format_json() {
+    use aws_sdk_s3::error::SdkError;
+    use aws_smithy_runtime_api::http::{Request, Response};
+
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    client
+        .put_object()
+        .bucket("testing")
+        .key("dir/a.txt")
+        .body(ByteStream::from_static(b"hello"))
+        .send()
+        .await
+        .unwrap();
+
+    // the sdk can not parse the json, its error still carries the raw response
+    fn json_body<E>(error: SdkError<E, Response>) -> serde_json::Value {
+        let raw = error.raw_response().expect("the error has a response");
+        serde_json::from_slice(raw.body().bytes().unwrap()).unwrap()
+    }
+    let with_format = |request: &mut Request| {
+        let separator = if request.uri().contains('?') {
+            '&'
+        } else {
+            '?'
+        };
+        let uri = format!("{}{}format=json", request.uri(), separator);
+        request.set_uri(uri).unwrap();
+    };
+
+    let error = client
+        .list_buckets()
+        .customize()
+        .mutate_request(with_format)
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        json_body(error),
+        serde_json::json!({ "buckets": [{ "name": "testing" }] })
+    );
+
+    let error = client
+        .list_objects()
+        .bucket("testing")
+        .customize()
+        .mutate_request(with_format)
+        .send()
+        .await
+        .unwrap_err();
+    let body = json_body(error);
+    assert_eq!(body["bucket"], "testing");
+    assert_eq!(body["is_truncated"], false);
+    assert_eq!(body["objects"][0]["key"], "dir/a.txt");
+    assert_eq!(body["objects"][0]["size"], 5);
+}
+
+#[tokio::test]
+async fn buckets_only_accept_requests_signed_for_their_region() {
+    let server = TestServer::start().await.unwrap();
+    let config = server
+        .client()
+        .config()
+        .to_builder()
+        .region(Region::new("eu-west-1"))
+        .build();
+    let client = aws_sdk_s3::Client::from_conf(config);
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .create_bucket_configuration(
+            CreateBucketConfiguration::builder()
+                .location_constraint(BucketLocationConstraint::EuWest1)
+                .build(),
+        )
+        .send()
+        .await
+        .unwrap();
+    client
+        .put_object()
+        .bucket("testing")
+        .key("a.txt")
+        .body(ByteStream::from_static(b"hello"))
+        .send()
+        .await
+        .unwrap();
+
+    let response = server
+        .client()
+        .get_bucket_location()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        response.location_constraint(),
+        Some(&BucketLocationConstraint::EuWest1)
+    );
+
+    let error = server
+        .client()
+        .get_object()
+        .bucket("testing")
+        .key("a.txt")
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("AuthorizationHeaderMalformed")
+    );
+}
+
+#[tokio::test]
+async fn rotated_secrets_work_until_the_grace_period_ends() {
+    let server = TestServer::start().await.unwrap();
+    let metadata = &server.app_state().metadata;
+    let with_secret = |secret_key: &str| {
+        let config = server
+            .client()
+            .config()
+            .to_builder()
+            .credentials_provider(Credentials::new(
+                TEST_ACCESS_KEY,
+                secret_key,
+                None,
+                None,
+                "test",
+            ))
+            .build();
+        aws_sdk_s3::Client::from_conf(config)
+    };
+
+    let rotated = Namespaces::new(metadata)
+        .rotate_key(TEST_ACCESS_KEY, Duration::from_secs(60))
+        .await
+        .unwrap()
+        .unwrap();
+    server.app_state().credentials.invalidate(TEST_ACCESS_KEY);
+
+    with_secret(&rotated.secret_key)
+        .list_buckets()
+        .send()
+        .await
+        .unwrap();
+    with_secret(TEST_SECRET_KEY)
+        .list_buckets()
+        .send()
+        .await
+        .unwrap();
+
+    // rotating again without a grace period drops both older secrets
+    let rotated_again = Namespaces::new(metadata)
+        .rotate_key(TEST_ACCESS_KEY, Duration::ZERO)
+        .await
+        .unwrap()
+        .unwrap();
+    server.app_state().credentials.invalidate(TEST_ACCESS_KEY);
+
+    with_secret(&rotated_again.secret_key)
+        .list_buckets()
+        .send()
+        .await
+        .unwrap();
+    for secret_key in [TEST_SECRET_KEY, rotated.secret_key.as_str()] {
+        let error = with_secret(secret_key)
+            .list_buckets()
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.into_service_error().meta().code(),
+            Some("SignatureDoesNotMatch")
+        );
+    }
+}
+
+#[tokio::test]
+async fn objects_with_a_ttl_report_their_expiration() {
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+
+    let response = client
+        .put_object()
+        .bucket("testing")
+        .key("a.txt")
+        .body(ByteStream::from_static(b"hello"))
+        .customize()
+        .mutate_request(|request| {
+            request
+                .headers_mut()
+                .insert("x-s3proxy-ttl-seconds", "3600");
+        })
+        .send()
+        .await
+        .unwrap();
+    let expiration = response.expiration().unwrap().to_string();
+    assert!(expiration.starts_with("expiry-date=\""), "{expiration}");
+    assert!(expiration.ends_with("\", rule-id=\"ttl\""), "{expiration}");
+
+    let response = client
+        .head_object()
+        .bucket("testing")
+        .key("a.txt")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.expiration(), Some(expiration.as_str()));
+
+    let response = client
+        .put_object()
+        .bucket("testing")
+        .key("a.txt")
+        .body(ByteStream::from_static(b"hello"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.expiration(), None);
+    let response = client
+        .get_object()
+        .bucket("testing")
+        .key("a.txt")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.expiration(), None);
+}
+
+#[tokio::test]
+async fn byte_ranges_are_served_partially() {
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    client
+        .put_object()
+        .bucket("testing")
+        .key("a.txt")
+        .body(ByteStream::from_static(b"hello world"))
+        .send()
+        .await
+        .unwrap();
+
+    let response = client
+        .get_object()
+        .bucket("testing")
+        .key("a.txt")
+        .range("bytes=1-3")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.content_range(), Some("bytes 1-3/11"));
+    assert_eq!(response.content_length(), Some(3));
+    let body = response.body.collect().await.unwrap().to_vec();
+    assert_eq!(body, b"ell");
+
+    let response = client
+        .get_object()
+        .bucket("testing")
+        .key("a.txt")
+        .range("bytes=-5")
+        .send()
+        .await
+        .unwrap();
+    let body = response.body.collect().await.unwrap().to_vec();
+    assert_eq!(body, b"world");
+
+    let error = client
+        .get_object()
+        .bucket("testing")
+        .key("a.txt")
+        .range("bytes=20-")
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("InvalidRange")
+    );
+}
+
+#[tokio::test]
+async fn byte_ranges_are_not_compressed() {
+    use aws_smithy_runtime_api::http::Request;
+
+    let server = TestServer::start_with(|config| config.compression.enabled = true)
+        .await
+        .unwrap();
+    let client = server.client();
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    let text = "hello world\n".repeat(400);
+    client
+        .put_object()
+        .bucket("testing")
+        .key("a.txt")
+        .content_type("text/plain")
+        .body(ByteStream::from(text.clone().into_bytes()))
+        .send()
+        .await
+        .unwrap();
+
+    let accept_gzip = |request: &mut Request| {
+        request.headers_mut().insert("accept-encoding", "gzip");
+    };
+    let response = client
+        .get_object()
+        .bucket("testing")
+        .key("a.txt")
+        .customize()
+        .mutate_request(accept_gzip)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.content_encoding(), Some("gzip"));
+
+    let response = client
+        .get_object()
+        .bucket("testing")
+        .key("a.txt")
+        .range("bytes=0-2047")
+        .customize()
+        .mutate_request(accept_gzip)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.content_encoding(), None);
+    assert_eq!(response.content_range(), Some("bytes 0-2047/4800"));
+    let body = response.body.collect().await.unwrap().to_vec();
+    assert_eq!(body, text.as_bytes()[..2048]);
+}
+
+#[tokio::test]
+async fn conditional_reads_are_answered() {
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    client
+        .put_object()
+        .bucket("testing")
+        .key("a.txt")
+        .body(ByteStream::from_static(b"hello"))
+        .send()
+        .await
+        .unwrap();
+
+    let error = client
+        .get_object()
+        .bucket("testing")
+        .key("a.txt")
+        .if_none_match("*")
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(error.raw_response().unwrap().status().as_u16(), 304);
+
+    let error = client
+        .get_object()
+        .bucket("testing")
+        .key("a.txt")
+        .if_match("\"not-the-etag\"")
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("PreconditionFailed")
+    );
+    // If-Match compares strongly
+    let error = client
+        .get_object()
+        .bucket("testing")
+        .key("a.txt")
+        .if_match("W/\"5d41402abc4b2a76b9719d911017c592\"")
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("PreconditionFailed")
+    );
+    client
+        .get_object()
+        .bucket("testing")
+        .key("a.txt")
+        .if_match("\"5d41402abc4b2a76b9719d911017c592\"")
+        .send()
+        .await
+        .unwrap();
+
+    let response = client
+        .get_object()
+        .bucket("testing")
+        .key("a.txt")
+        .if_match("*")
+        .send()
+        .await
+        .unwrap();
+    let body = response.body.collect().await.unwrap().to_vec();
+    assert_eq!(body, b"hello");
+}
+
+#[tokio::test]
+async fn uploads_get_md5_etags() {
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    let response = client
+        .put_object()
+        .bucket("testing")
+        .key("a.txt")
+        .body(ByteStream::from_static(b"hello world"))
+        .send()
+        .await
+        .unwrap();
+    // md5 of `hello world`
+    let etag = "\"5eb63bbbe01eeed093cb22bb8f5acdc3\"";
+    assert_eq!(response.e_tag(), Some(etag));
+
+    let response = client
+        .get_object()
+        .bucket("testing")
+        .key("a.txt")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.e_tag(), Some(etag));
+
+    let response = client
+        .head_object()
+        .bucket("testing")
+        .key("a.txt")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.e_tag(), Some(etag));
+
+    let listing = client
+        .list_objects_v2()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(listing.contents()[0].e_tag(), Some(etag));
+}
+
+/// Swaps the body after the request was signed, like a proxy in the middle would.
+#[derive(Debug)]
+struct TamperBody;
+
+impl Intercept for TamperBody {
+    fn name(&self) -> &'static str {
+        "TamperBody"
+    }
+
+    fn modify_before_transmit(
+        &self,
+        context: &mut BeforeTransmitInterceptorContextMut<'_>,
+        _runtime_components: &RuntimeComponents,
+        _cfg: &mut ConfigBag,
+    ) -> Result<(), aws_sdk_s3::error::BoxError> {
+        *context.request_mut().body_mut() = SdkBody::from("hellO");
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn tampered_payloads_are_rejected() {
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    let error = client
+        .put_object()
+        .bucket("testing")
+        .key("a.txt")
+        .body(ByteStream::from_static(b"hello"))
+        .customize()
+        .interceptor(TamperBody)
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("XAmzContentSHA256Mismatch")
+    );
+
+    let error = client
+        .get_object()
+        .bucket("testing")
+        .key("a.txt")
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(error.into_service_error().meta().code(), Some("NoSuchKey"));
+}
+
+#[tokio::test]
+async fn checksums_are_validated_and_returned() {
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    let response = client
+        .put_object()
+        .bucket("testing")
+        .key("a.txt")
+        .body(ByteStream::from_static(b"hello world"))
+        .checksum_algorithm(ChecksumAlgorithm::Crc32C)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.checksum_crc32_c(), Some("yZRlqg=="));
+
+    let response = client
+        .get_object()
+        .bucket("testing")
+        .key("a.txt")
+        .checksum_mode(ChecksumMode::Enabled)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.checksum_crc32_c(), Some("yZRlqg=="));
+    let body = response.body.collect().await.unwrap().to_vec();
+    assert_eq!(body, b"hello world");
+
+    let response = client
+        .get_object()
+        .bucket("testing")
+        .key("a.txt")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.checksum_crc32_c(), None);
+
+    let error = client
+        .put_object()
+        .bucket("testing")
+        .key("b.txt")
+        .body(ByteStream::from_static(b"hello world"))
+        .checksum_sha1("AAAAAAAAAAAAAAAAAAAAAAAAAAA=")
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(error.into_service_error().meta().code(), Some("BadDigest"));
+}
+
+#[tokio::test]
+async fn object_attributes_are_returned_without_the_body() {
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    client
+        .put_object()
+        .bucket("testing")
+        .key("dir/a.txt")
+        .body(ByteStream::from_static(b"hello world"))
+        .checksum_algorithm(ChecksumAlgorithm::Sha256)
+        .send()
+        .await
+        .unwrap();
+
+    let response = client
+        .get_object_attributes()
+        .bucket("testing")
+        .key("dir/a.txt")
+        .object_attributes(ObjectAttributes::Etag)
+        .object_attributes(ObjectAttributes::Checksum)
+        .object_attributes(ObjectAttributes::ObjectSize)
+        .object_attributes(ObjectAttributes::StorageClass)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.e_tag(), Some("5eb63bbbe01eeed093cb22bb8f5acdc3"));
+    assert_eq!(response.object_size(), Some(11));
+    assert_eq!(response.storage_class(), Some(&StorageClass::Standard));
+    assert_eq!(
+        response.checksum().and_then(|x| x.checksum_sha256()),
+        Some("uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=")
+    );
+
+    let response = client
+        .get_object_attributes()
+        .bucket("testing")
+        .key("dir/a.txt")
+        .object_attributes(ObjectAttributes::ObjectSize)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.e_tag(), None);
+    assert_eq!(response.object_size(), Some(11));
+
+    let error = client
+        .get_object_attributes()
+        .bucket("testing")
+        .key("dir/b.txt")
+        .object_attributes(ObjectAttributes::ObjectSize)
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(error.into_service_error().meta().code(), Some("NoSuchKey"));
+    let error = client
+        .get_object_attributes()
+        .bucket("missing")
+        .key("dir/a.txt")
+        .object_attributes(ObjectAttributes::ObjectSize)
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("NoSuchBucket")
+    );
+}
+
+#[tokio::test]
+async fn objects_are_tagged() {
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    client
+        .put_object()
+        .bucket("testing")
+        .key("a.txt")
+        .body(ByteStream::from_static(b"hello"))
+        .send()
+        .await
+        .unwrap();
+
+    let tagging = Tagging::builder()
+        .tag_set(Tag::builder().key("team").value("storage").build().unwrap())
+        .tag_set(Tag::builder().key("env").value("test").build().unwrap())
+        .build()
+        .unwrap();
+    client
+        .put_object_tagging()
+        .bucket("testing")
+        .key("a.txt")
+        .tagging(tagging)
+        .send()
+        .await
+        .unwrap();
+
+    let response = client
+        .get_object_tagging()
+        .bucket("testing")
+        .key("a.txt")
+        .send()
+        .await
+        .unwrap();
+    let tags: Vec<_> = response
+        .tag_set()
+        .iter()
+        .map(|x| (x.key(), x.value()))
+        .collect();
+    assert_eq!(tags, vec![("team", "storage"), ("env", "test")]);
+
+    let response = client
+        .get_object()
+        .bucket("testing")
+        .key("a.txt")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.tag_count(), Some(2));
+
+    client
+        .delete_object_tagging()
+        .bucket("testing")
+        .key("a.txt")
+        .send()
+        .await
+        .unwrap();
+    let response = client
+        .get_object_tagging()
+        .bucket("testing")
+        .key("a.txt")
+        .send()
+        .await
+        .unwrap();
+    assert!(response.tag_set().is_empty());
+
+    let error = client
+        .get_object_tagging()
+        .bucket("testing")
+        .key("b.txt")
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(error.into_service_error().meta().code(), Some("NoSuchKey"));
+}
+
+#[tokio::test]
+async fn buckets_are_tagged() {
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+
+    let error = client
+        .get_bucket_tagging()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("NoSuchTagSet")
+    );
+
+    let tagging = Tagging::builder()
+        .tag_set(
+            Tag::builder()
+                .key("cost-center")
+                .value("42")
+                .build()
+                .unwrap(),
+        )
+        .build()
+        .unwrap();
+    client
+        .put_bucket_tagging()
+        .bucket("testing")
+        .tagging(tagging)
+        .send()
+        .await
+        .unwrap();
+    let response = client
+        .get_bucket_tagging()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.tag_set()[0].key(), "cost-center");
+    assert_eq!(response.tag_set()[0].value(), "42");
+
+    client
+        .delete_bucket_tagging()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    assert!(client
+        .get_bucket_tagging()
+        .bucket("testing")
+        .send()
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn multipart_uploads_are_initiated() {
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+
+    let response = client
+        .create_multipart_upload()
+        .bucket("testing")
+        .key("dir/large.bin")
+        .content_type("application/octet-stream")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.bucket(), Some("testing"));
+    assert_eq!(response.key(), Some("dir/large.bin"));
+    let upload_id = response.upload_id().unwrap();
+    assert!(!upload_id.is_empty());
+
+    let other = client
+        .create_multipart_upload()
+        .bucket("testing")
+        .key("dir/large.bin")
+        .send()
+        .await
+        .unwrap();
+    assert_ne!(other.upload_id(), Some(upload_id));
+
+    let error = client
+        .create_multipart_upload()
+        .bucket("missing")
+        .key("large.bin")
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("NoSuchBucket")
+    );
+}
+
+#[tokio::test]
+async fn parts_are_staged_for_their_upload() {
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    let upload = client
+        .create_multipart_upload()
+        .bucket("testing")
+        .key("large.bin")
+        .send()
+        .await
+        .unwrap();
+    let upload_id = upload.upload_id().unwrap();
+
+    let response = client
+        .upload_part()
+        .bucket("testing")
+        .key("large.bin")
+        .upload_id(upload_id)
+        .part_number(1)
+        .body(ByteStream::from_static(b"hello world"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        response.e_tag(),
+        Some("\"5eb63bbbe01eeed093cb22bb8f5acdc3\"")
+    );
+
+    // the upload belongs to another key
+    let error = client
+        .upload_part()
+        .bucket("testing")
+        .key("other.bin")
+        .upload_id(upload_id)
+        .part_number(1)
+        .body(ByteStream::from_static(b"hello world"))
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("NoSuchUpload")
+    );
+    let error = client
+        .upload_part()
+        .bucket("testing")
+        .key("large.bin")
+        .upload_id("unknown")
+        .part_number(1)
+        .body(ByteStream::from_static(b"hello world"))
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("NoSuchUpload")
+    );
+
+    // staged parts are not objects yet
+    let objects = client
+        .list_objects_v2()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    assert!(objects.contents().is_empty());
+    let buckets = client.list_buckets().send().await.unwrap();
+    assert_eq!(buckets.buckets().len(), 1);
+}
+
+#[tokio::test]
+async fn multipart_uploads_are_completed() {
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    let upload = client
+        .create_multipart_upload()
+        .bucket("testing")
+        .key("large.txt")
+        .content_type("text/plain")
+        .storage_class(StorageClass::Glacier)
+        .send()
+        .await
+        .unwrap();
+    let upload_id = upload.upload_id().unwrap();
+
+    let mut parts = Vec::new();
+    for (part_number, body) in [(1, "hello "), (2, "multipart "), (3, "world")] {
+        let response = client
+            .upload_part()
+            .bucket("testing")
+            .key("large.txt")
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .body(ByteStream::from_static(body.as_bytes()))
+            .send()
+            .await
+            .unwrap();
+        parts.push(
+            CompletedPart::builder()
+                .part_number(part_number)
+                .e_tag(response.e_tag().unwrap())
+                .build(),
+        );
+    }
+
+    let complete = |parts: Vec<CompletedPart>| {
+        client
+            .complete_multipart_upload()
+            .bucket("testing")
+            .key("large.txt")
+            .upload_id(upload_id)
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(parts))
+                    .build(),
+            )
+            .send()
+    };
+
+    let error = complete(vec![parts[1].clone(), parts[0].clone()])
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("InvalidPartOrder")
+    );
+    let wrong_etag = CompletedPart::builder()
+        .part_number(2)
+        .e_tag("\"5eb63bbbe01eeed093cb22bb8f5acdc3\"")
+        .build();
+    let error = complete(vec![parts[0].clone(), wrong_etag])
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("InvalidPart")
+    );
+    // only the last part may be smaller than 5 MiB
+    let error = complete(parts.clone()).await.unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("EntityTooSmall")
+    );
+
+    let padding = vec![b'.'; 5 * 1024 * 1024];
+    for (part_number, body) in [(1, "hello "), (2, "multipart ")] {
+        let body = [body.as_bytes(), &padding].concat();
+        let response = client
+            .upload_part()
+            .bucket("testing")
+            .key("large.txt")
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .body(ByteStream::from(body))
+            .send()
+            .await
+            .unwrap();
+        parts[part_number as usize - 1] = CompletedPart::builder()
+            .part_number(part_number)
+            .e_tag(response.e_tag().unwrap())
+            .build();
+    }
+
+    let response = complete(parts.clone()).await.unwrap();
+    let etag = response.e_tag().unwrap();
+    assert!(etag.ends_with("-3\""), "{etag}");
+
+    let object = client
+        .get_object()
+        .bucket("testing")
+        .key("large.txt")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(object.e_tag(), Some(etag));
+    assert_eq!(object.content_type(), Some("text/plain"));
+    assert_eq!(object.storage_class(), Some(&StorageClass::Glacier));
+    let body = object.body.collect().await.unwrap().into_bytes();
+    let expected = [
+        b"hello ".as_slice(),
+        &padding,
+        b"multipart ",
+        &padding,
+        b"world",
+    ]
+    .concat();
+    assert!(body == expected);
+
+    // the upload is gone once it is completed
+    let error = complete(parts).await.unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("NoSuchUpload")
+    );
+}
+
+#[tokio::test]
+async fn multipart_uploads_count_against_quotas() {
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+    let namespaces = Namespaces::new(&server.app_state().metadata);
+    const MIB: u64 = 1024 * 1024;
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    let set_quota = |max_bytes: u64| {
+        let namespaces = &namespaces;
+        async move {
+            let quota = Quota {
+                max_bytes: Some(max_bytes),
+                max_objects: None,
+            };
+            namespaces
+                .set_bucket_quota(TEST_ACCESS_KEY, "testing", &quota)
+                .await
+        }
+    };
+    set_quota(6 * MIB).await.unwrap();
+    let upload = client
+        .create_multipart_upload()
+        .bucket("testing")
+        .key("large.txt")
+        .send()
+        .await
+        .unwrap();
+    let upload_id = upload.upload_id().unwrap();
+    let upload_part = |part_number: i32, length: u64| {
+        client
+            .upload_part()
+            .bucket("testing")
+            .key("large.txt")
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .body(ByteStream::from(vec![b'.'; length as usize]))
+            .send()
+    };
+
+    let first = upload_part(1, 5 * MIB).await.unwrap();
+    // the staged first part counts against the bucket
+    let error = upload_part(2, 2 * MIB).await.unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("QuotaExceeded")
+    );
+    // a part uploaded again is not counted twice
+    let first_again = upload_part(1, 5 * MIB).await.unwrap();
+    assert_eq!(first.e_tag(), first_again.e_tag());
+    let second = upload_part(2, MIB).await.unwrap();
+
+    let parts = vec![
+        CompletedPart::builder()
+            .part_number(1)
+            .e_tag(first.e_tag().unwrap())
+            .build(),
+        CompletedPart::builder()
+            .part_number(2)
+            .e_tag(second.e_tag().unwrap())
+            .build(),
+    ];
+    let complete = || {
+        client
+            .complete_multipart_upload()
+            .bucket("testing")
+            .key("large.txt")
+            .upload_id(upload_id)
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(parts.clone()))
+                    .build(),
+            )
+            .send()
+    };
+    // the quota was lowered after the parts were uploaded
+    set_quota(5 * MIB).await.unwrap();
+    let error = complete().await.unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("QuotaExceeded")
+    );
+    set_quota(6 * MIB).await.unwrap();
+    complete().await.unwrap();
+
+    let object = client
+        .head_object()
+        .bucket("testing")
+        .key("large.txt")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(object.content_length(), Some(6 * MIB as i64));
+}
+
+#[tokio::test]
+async fn parts_are_copied_from_objects() {
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    let source = [
+        b"hello multipart world".as_slice(),
+        &[b'.'; 5 * 1024 * 1024],
+    ]
+    .concat();
+    client
+        .put_object()
+        .bucket("testing")
+        .key("source.txt")
+        .body(ByteStream::from(source.clone()))
+        .send()
+        .await
+        .unwrap();
+    let upload = client
+        .create_multipart_upload()
+        .bucket("testing")
+        .key("copy.txt")
+        .send()
+        .await
+        .unwrap();
+    let upload_id = upload.upload_id().unwrap();
+
+    let mut parts = Vec::new();
+    let rest = format!("bytes=5-{}", source.len() - 1);
+    for (part_number, range) in [(1, rest.as_str()), (2, "bytes=0-4")] {
+        let response = client
+            .upload_part_copy()
+            .bucket("testing")
+            .key("copy.txt")
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .copy_source("testing/source.txt")
+            .copy_source_range(range)
+            .send()
+            .await
+            .unwrap();
+        let etag = response.copy_part_result().unwrap().e_tag().unwrap();
+        parts.push(
+            CompletedPart::builder()
+                .part_number(part_number)
+                .e_tag(etag)
+                .build(),
+        );
+    }
+
+    let error = client
+        .upload_part_copy()
+        .bucket("testing")
+        .key("copy.txt")
+        .upload_id(upload_id)
+        .part_number(3)
+        .copy_source("testing/source.txt")
+        .copy_source_range(format!("bytes=16-{}", source.len()))
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("InvalidArgument")
+    );
+    let error = client
+        .upload_part_copy()
+        .bucket("testing")
+        .key("copy.txt")
+        .upload_id(upload_id)
+        .part_number(4)
+        .copy_source("testing/missing.txt")
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(error.into_service_error().meta().code(), Some("NoSuchKey"));
+
+    client
+        .complete_multipart_upload()
+        .bucket("testing")
+        .key("copy.txt")
+        .upload_id(upload_id)
+        .multipart_upload(
+            CompletedMultipartUpload::builder()
+                .set_parts(Some(parts))
+                .build(),
+        )
+        .send()
+        .await
+        .unwrap();
+
+    let object = client
+        .get_object()
+        .bucket("testing")
+        .key("copy.txt")
+        .send()
+        .await
+        .unwrap();
+    let body = object.body.collect().await.unwrap().into_bytes();
+    assert!(body == [&source[5..], b"hello"].concat());
+}
+
+#[tokio::test]
+async fn object_versions_are_listed() {
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    for key in ["a.txt", "b.txt", "dir/c.txt"] {
+        client
+            .put_object()
+            .bucket("testing")
+            .key(key)
+            .body(ByteStream::from_static(b"hello world"))
+            .send()
+            .await
+            .unwrap();
+    }
+
+    let response = client
+        .list_object_versions()
+        .bucket("testing")
+        .delimiter("/")
+        .max_keys(2)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.is_truncated(), Some(true));
+    assert_eq!(response.next_key_marker(), Some("b.txt"));
+    assert_eq!(response.next_version_id_marker(), Some("null"));
+    let versions = response.versions();
+    assert_eq!(versions.len(), 2);
+    assert_eq!(versions[0].key(), Some("a.txt"));
+    assert_eq!(versions[0].version_id(), Some("null"));
+    assert_eq!(versions[0].is_latest(), Some(true));
+    assert_eq!(
+        versions[0].e_tag(),
+        Some("\"5eb63bbbe01eeed093cb22bb8f5acdc3\"")
+    );
+    assert_eq!(versions[0].size(), Some(11));
+
+    let response = client
+        .list_object_versions()
+        .bucket("testing")
+        .delimiter("/")
+        .key_marker("b.txt")
+        .version_id_marker("null")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.is_truncated(), Some(false));
+    assert!(response.versions().is_empty());
+    assert!(response.delete_markers().is_empty());
+    assert_eq!(response.common_prefixes()[0].prefix(), Some("dir/"));
+
+    // keys only have the null version
+    let error = client
+        .list_object_versions()
+        .bucket("testing")
+        .key_marker("b.txt")
+        .version_id_marker("3HL4kqtJlcpXroDTDmJ")
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("InvalidArgument")
+    );
+    let error = client
+        .list_object_versions()
+        .bucket("testing")
+        .version_id_marker("null")
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("InvalidArgument")
+    );
+}
+
+#[tokio::test]
+async fn versioned_buckets_keep_replaced_objects() {
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+    let operator = &server.app_state().opendal_operator;
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    let response = client
+        .get_bucket_versioning()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), None);
+
+    // written in place before versioning is enabled
+    for body in ["first", "second"] {
+        client
+            .put_object()
+            .bucket("testing")
+            .key("a.txt")
+            .body(ByteStream::from_static(body.as_bytes()))
+            .send()
+            .await
+            .unwrap();
+    }
+    assert!(trash::list(operator, TEST_ACCESS_KEY)
+        .await
+        .unwrap()
+        .is_empty());
+
+    client
+        .put_bucket_versioning()
+        .bucket("testing")
+        .versioning_configuration(
+            VersioningConfiguration::builder()
+                .status(BucketVersioningStatus::Enabled)
+                .build(),
+        )
+        .send()
+        .await
+        .unwrap();
+    let response = client
+        .get_bucket_versioning()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), Some(&BucketVersioningStatus::Enabled));
+
+    // a rejected body keeps the object in place and nothing in the trash
+    let error = client
+        .put_object()
+        .bucket("testing")
+        .key("a.txt")
+        .body(ByteStream::from_static(b"third"))
+        .checksum_sha1("AAAAAAAAAAAAAAAAAAAAAAAAAAA=")
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(error.into_service_error().meta().code(), Some("BadDigest"));
+    assert!(trash::list(operator, TEST_ACCESS_KEY)
+        .await
+        .unwrap()
+        .is_empty());
+
+    client
+        .put_object()
+        .bucket("testing")
+        .key("a.txt")
+        .body(ByteStream::from_static(b"third"))
+        .send()
+        .await
+        .unwrap();
+    client
+        .delete_object()
+        .bucket("testing")
+        .key("a.txt")
+        .send()
+        .await
+        .unwrap();
+
+    let entries = trash::list(operator, TEST_ACCESS_KEY).await.unwrap();
+    assert_eq!(entries.len(), 2);
+    assert!(entries.iter().all(|x| x.key == "a.txt"));
+    let mut kept = Vec::new();
+    for entry in entries {
+        let path = format!("{}/.trash/{}", TEST_ACCESS_KEY, entry.id);
+        kept.push(operator.read(&path).await.unwrap());
+    }
+    kept.sort();
+    assert_eq!(kept, [b"second".to_vec(), b"third".to_vec()]);
+
+    // the kept objects are not versions, asking for one is refused instead of ignored
+    let error = client
+        .get_object()
+        .bucket("testing")
+        .key("a.txt")
+        .version_id("3HL4kqtJlcpXroDTDmJ")
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("NotImplemented")
+    );
+}
+
+#[tokio::test]
+async fn objects_are_queried_with_select() {
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    client
+        .put_object()
+        .bucket("testing")
+        .key("people.csv")
+        .body(ByteStream::from_static(
+            b"name,age,city\nAnn,34,Oslo\nBob,51,\"Paris, France\"\nJane,28,Oslo\n",
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    let select = |expression: &str, output: OutputSerialization| {
+        client
+            .select_object_content()
+            .bucket("testing")
+            .key("people.csv")
+            .expression(expression)
+            .expression_type(ExpressionType::Sql)
+            .input_serialization(
+                InputSerialization::builder()
+                    .csv(
+                        CsvInput::builder()
+                            .file_header_info(FileHeaderInfo::Use)
+                            .build(),
+                    )
+                    .build(),
+            )
+            .output_serialization(output)
+            .send()
+    };
+    let records =
+        |mut response: aws_sdk_s3::operation::select_object_content::SelectObjectContentOutput| async move {
+            let mut records = String::new();
+            let mut ended = false;
+            while let Some(event) = response.payload.recv().await.unwrap() {
+                match event {
+                    SelectObjectContentEventStream::Records(event) => records
+                        .push_str(std::str::from_utf8(event.payload().unwrap().as_ref()).unwrap()),
+                    SelectObjectContentEventStream::End(_) => ended = true,
+                    _ => (),
+                }
+            }
+            assert!(ended);
+            records
+        };
+
+    let csv = OutputSerialization::builder()
+        .csv(CsvOutput::builder().build())
+        .build();
+    let response = select(
+        "SELECT s.name, s.city FROM S3Object s WHERE s.age > 30",
+        csv.clone(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(records(response).await, "Ann,Oslo\nBob,\"Paris, France\"\n");
+
+    let json = OutputSerialization::builder()
+        .json(JsonOutput::builder().build())
+        .build();
+    let response = select(
+        "SELECT * FROM S3Object s WHERE s.city = 'Oslo' LIMIT 1",
+        json,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        records(response).await,
+        "{\"age\":\"34\",\"city\":\"Oslo\",\"name\":\"Ann\"}\n"
+    );
+
+    let error = select("SELECT name FROM people", csv)
+        .await
+        .unwrap_err()
+        .into_service_error();
+    assert_eq!(error.meta().code(), Some("InvalidRequest"));
+}
+
+#[tokio::test]
+async fn browsers_upload_with_signed_policies() {
+    use base64::Engine;
+    use http_body_util::{BodyExt, Full};
+
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+    client
+        .create_bucket()
+        .bucket("uploads")
+        .send()
+        .await
+        .unwrap();
+
+    let now = std::time::SystemTime::now();
+    let date = chrono::DateTime::<chrono::Utc>::from(now);
+    let credential = format!(
+        "{}/{}/us-east-1/s3/aws4_request",
+        TEST_ACCESS_KEY,
+        date.format("%Y%m%d")
+    );
+    let amz_date = date.format("%Y%m%dT%H%M%SZ").to_string();
+    let policy = serde_json::json!({
+        "expiration": (date + chrono::Duration::hours(1)).to_rfc3339(),
+        "conditions": [
+            {"bucket": "uploads"},
+            ["starts-with", "$key", "user/"],
+            ["starts-with", "$Content-Type", "text/"],
+            {"success_action_status": "201"},
+            ["content-length-range", 1, 16],
+            {"x-amz-algorithm": "AWS4-HMAC-SHA256"},
+            {"x-amz-credential": credential},
+            {"x-amz-date": amz_date},
+        ],
+    });
+    let policy = base64::engine::general_purpose::STANDARD.encode(policy.to_string());
+    let signature = s3_proxy::post_policy::signature(TEST_SECRET_KEY, now, "us-east-1", &policy);
+
+    let http = hyper_util::client::legacy::Client::builder(hyper_util::rt::TokioExecutor::new())
+        .build_http::<Full<bytes::Bytes>>();
+    let post = |key: &str, signature: &str, file: &str| {
+        let fields = [
+            ("key", key),
+            ("Content-Type", "text/plain"),
+            ("success_action_status", "201"),
+            ("X-Amz-Algorithm", "AWS4-HMAC-SHA256"),
+            ("X-Amz-Credential", &credential),
+            ("X-Amz-Date", &amz_date),
+            ("Policy", &policy),
+            ("X-Amz-Signature", signature),
+        ];
+        let mut body = String::new();
+        for (name, value) in fields {
+            body.push_str(&format!(
+                "--boundary\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                name, value
+            ));
+        }
+        body.push_str(&format!(
+            "--boundary\r\nContent-Disposition: form-data; name=\"file\"; \
+             filename=\"hello.txt\"\r\nContent-Type: application/octet-stream\r\n\r\n{}\r\n\
+             --boundary--\r\n",
+            file
+        ));
+
+        let request = hyper::Request::post(format!("{}/uploads", server.endpoint_url()))
+            .header("content-type", "multipart/form-data; boundary=boundary")
+            .body(Full::new(bytes::Bytes::from(body)))
+            .unwrap();
+        let response = http.request(request);
+        async move {
+            let response = response.await.unwrap();
+            let status = response.status();
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            (status, String::from_utf8(body.to_vec()).unwrap())
+        }
+    };
+
+    let (status, body) = post("user/${filename}", &signature, "hello world").await;
+    assert_eq!(status, 201);
+    assert!(body.contains("<Key>user/hello.txt</Key>"));
+    let object = client
+        .get_object()
+        .bucket("uploads")
+        .key("user/hello.txt")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(object.content_type(), Some("text/plain"));
+    assert_eq!(
+        object.body.collect().await.unwrap().into_bytes(),
+        "hello world"
+    );
+
+    let (status, body) = post("admin/hello.txt", &signature, "hello world").await;
+    assert_eq!(status, 403);
+    assert!(body.contains("<Code>AccessDenied</Code>"));
+
+    let (status, body) = post("user/hello.txt", &"0".repeat(64), "hello world").await;
+    assert_eq!(status, 403);
+    assert!(body.contains("<Code>SignatureDoesNotMatch</Code>"));
+
+    let (status, body) = post("user/large.txt", &signature, "hello large world").await;
+    assert_eq!(status, 400);
+    assert!(body.contains("<Code>EntityTooLarge</Code>"));
+    assert!(client
+        .head_object()
+        .bucket("uploads")
+        .key("user/large.txt")
+        .send()
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn response_headers_are_overridden_from_the_query() {
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    client
+        .put_object()
+        .bucket("testing")
+        .key("report")
+        .content_type("text/plain")
+        .body(ByteStream::from_static(b"a,b\n1,2\n"))
+        .send()
+        .await
+        .unwrap();
+
+    let object = client
+        .get_object()
+        .bucket("testing")
+        .key("report")
+        .response_content_type("text/csv")
+        .response_content_disposition("attachment; filename=\"report.csv\"")
+        .response_cache_control("no-store")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(object.content_type(), Some("text/csv"));
+    assert_eq!(
+        object.content_disposition(),
+        Some("attachment; filename=\"report.csv\"")
+    );
+    assert_eq!(object.cache_control(), Some("no-store"));
+
+    // the stored metadata is unchanged
+    let object = client
+        .get_object()
+        .bucket("testing")
+        .key("report")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(object.content_type(), Some("text/plain"));
+    assert_eq!(object.content_disposition(), None);
+}
+
+#[tokio::test]
+async fn storage_classes_are_kept_with_the_object() {
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    client
+        .put_object()
+        .bucket("testing")
+        .key("archive.txt")
+        .body(ByteStream::from_static(b"hello world"))
+        .storage_class(StorageClass::Glacier)
+        .send()
+        .await
+        .unwrap();
+    client
+        .put_object()
+        .bucket("testing")
+        .key("current.txt")
+        .body(ByteStream::from_static(b"hello world"))
+        .send()
+        .await
+        .unwrap();
+
+    let response = client
+        .head_object()
+        .bucket("testing")
+        .key("archive.txt")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.storage_class(), Some(&StorageClass::Glacier));
+    let response = client
+        .get_object()
+        .bucket("testing")
+        .key("current.txt")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.storage_class(), None);
+
+    let response = client
+        .list_objects_v2()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    let classes: Vec<_> = response
+        .contents()
+        .iter()
+        .map(|x| x.storage_class().map(|x| x.as_str()))
+        .collect();
+    assert_eq!(classes, [Some("GLACIER"), Some("STANDARD")]);
+
+    let response = client
+        .get_object_attributes()
+        .bucket("testing")
+        .key("archive.txt")
+        .object_attributes(ObjectAttributes::StorageClass)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.storage_class(), Some(&StorageClass::Glacier));
+
+    // an overwrite without a class is STANDARD again
+    client
+        .put_object()
+        .bucket("testing")
+        .key("archive.txt")
+        .body(ByteStream::from_static(b"hello world"))
+        .send()
+        .await
+        .unwrap();
+    let response = client
+        .head_object()
+        .bucket("testing")
+        .key("archive.txt")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.storage_class(), None);
+
+    let error = client
+        .put_object()
+        .bucket("testing")
+        .key("b.txt")
+        .body(ByteStream::from_static(b"hello world"))
+        .storage_class(StorageClass::from("COLD"))
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("InvalidStorageClass")
+    );
+}
+
+#[tokio::test]
+async fn locked_objects_are_not_deleted_or_overwritten() {
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+
+    client
+        .create_bucket()
+        .bucket("backups")
+        .object_lock_enabled_for_bucket(true)
+        .send()
+        .await
+        .unwrap();
+    let response = client
+        .get_bucket_versioning()
+        .bucket("backups")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), Some(&BucketVersioningStatus::Enabled));
+
+    client
+        .put_object_lock_configuration()
+        .bucket("backups")
+        .object_lock_configuration(
+            ObjectLockConfiguration::builder()
+                .object_lock_enabled(ObjectLockEnabled::Enabled)
+                .rule(
+                    ObjectLockRule::builder()
+                        .default_retention(
+                            DefaultRetention::builder()
+                                .mode(ObjectLockRetentionMode::Governance)
+                                .days(1)
+                                .build(),
+                        )
+                        .build(),
+                )
+                .build(),
+        )
+        .send()
+        .await
+        .unwrap();
+    let response = client
+        .get_object_lock_configuration()
+        .bucket("backups")
+        .send()
+        .await
+        .unwrap();
+    let retention = response
+        .object_lock_configuration()
+        .and_then(|x| x.rule())
+        .and_then(|x| x.default_retention())
+        .unwrap();
+    assert_eq!(retention.mode(), Some(&ObjectLockRetentionMode::Governance));
+    assert_eq!(retention.days(), Some(1));
+
+    // the default retention is governance, which can be bypassed
+    client
+        .put_object()
+        .bucket("backups")
+        .key("a.txt")
+        .body(ByteStream::from_static(b"hello world"))
+        .send()
+        .await
+        .unwrap();
+    let response = client
+        .head_object()
+        .bucket("backups")
+        .key("a.txt")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        response.object_lock_mode(),
+        Some(&ObjectLockMode::Governance)
+    );
+    let error = client
+        .delete_object()
+        .bucket("backups")
+        .key("a.txt")
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("AccessDenied")
+    );
+    client
+        .delete_object()
+        .bucket("backups")
+        .key("a.txt")
+        .bypass_governance_retention(true)
+        .send()
+        .await
+        .unwrap();
+
+    let retain_until = DateTime::from_secs(chrono::Utc::now().timestamp() + 3600);
+    client
+        .put_object()
+        .bucket("backups")
+        .key("b.txt")
+        .body(ByteStream::from_static(b"hello world"))
+        .object_lock_mode(ObjectLockMode::Compliance)
+        .object_lock_retain_until_date(retain_until)
+        .object_lock_legal_hold_status(ObjectLockLegalHoldStatus::On)
+        .send()
+        .await
+        .unwrap();
+    let response = client
+        .get_object()
+        .bucket("backups")
+        .key("b.txt")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        response.object_lock_mode(),
+        Some(&ObjectLockMode::Compliance)
+    );
+    assert_eq!(
+        response.object_lock_retain_until_date(),
+        Some(&retain_until)
+    );
+    assert_eq!(
+        response.object_lock_legal_hold_status(),
+        Some(&ObjectLockLegalHoldStatus::On)
+    );
+
+    client
+        .put_object_legal_hold()
+        .bucket("backups")
+        .key("b.txt")
+        .legal_hold(
+            ObjectLockLegalHold::builder()
+                .status(ObjectLockLegalHoldStatus::Off)
+                .build(),
+        )
+        .send()
+        .await
+        .unwrap();
+    let response = client
+        .get_object_legal_hold()
+        .bucket("backups")
+        .key("b.txt")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        response.legal_hold().and_then(|x| x.status()),
+        Some(&ObjectLockLegalHoldStatus::Off)
+    );
+
+    // compliance retentions are only extended, also with the bypass
+    let error = client
+        .put_object_retention()
+        .bucket("backups")
+        .key("b.txt")
+        .retention(
+            ObjectLockRetention::builder()
+                .mode(ObjectLockRetentionMode::Compliance)
+                .retain_until_date(DateTime::from_secs(chrono::Utc::now().timestamp() + 60))
+                .build(),
+        )
+        .bypass_governance_retention(true)
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("AccessDenied")
+    );
+    let extended = DateTime::from_secs(chrono::Utc::now().timestamp() + 7200);
+    client
+        .put_object_retention()
+        .bucket("backups")
+        .key("b.txt")
+        .retention(
+            ObjectLockRetention::builder()
+                .mode(ObjectLockRetentionMode::Compliance)
+                .retain_until_date(extended)
+                .build(),
+        )
+        .send()
+        .await
+        .unwrap();
+    let response = client
+        .get_object_retention()
+        .bucket("backups")
+        .key("b.txt")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        response.retention().and_then(|x| x.retain_until_date()),
+        Some(&extended)
+    );
+
+    let error = client
+        .delete_object()
+        .bucket("backups")
+        .key("b.txt")
+        .bypass_governance_retention(true)
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("AccessDenied")
+    );
+    let error = client
+        .put_object()
+        .bucket("backups")
+        .key("b.txt")
+        .body(ByteStream::from_static(b"replaced"))
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("AccessDenied")
+    );
+
+    let error = client
+        .put_bucket_versioning()
+        .bucket("backups")
+        .versioning_configuration(
+            VersioningConfiguration::builder()
+                .status(BucketVersioningStatus::Suspended)
+                .build(),
+        )
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("InvalidBucketState")
+    );
+
+    // buckets without object lock take no locks
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    let error = client
+        .put_object()
+        .bucket("testing")
+        .key("a.txt")
+        .body(ByteStream::from_static(b"hello world"))
+        .object_lock_legal_hold_status(ObjectLockLegalHoldStatus::On)
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("InvalidRequest")
+    );
+    let error = client
+        .get_object_lock_configuration()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("ObjectLockConfigurationNotFoundError")
+    );
+}
+
+#[tokio::test]
+async fn lifecycle_configurations_are_stored_per_bucket() {
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+
+    client
+        .create_bucket()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    let error = client
+        .get_bucket_lifecycle_configuration()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("NoSuchLifecycleConfiguration")
+    );
+
+    let logs = LifecycleRule::builder()
+        .id("logs")
+        .filter(LifecycleRuleFilter::Prefix(String::from("logs/")))
+        .status(ExpirationStatus::Enabled)
+        .expiration(LifecycleExpiration::builder().days(30).build())
+        .abort_incomplete_multipart_upload(
+            AbortIncompleteMultipartUpload::builder()
+                .days_after_initiation(7)
+                .build(),
+        )
+        .build()
+        .unwrap();
+    let everything = LifecycleRule::builder()
+        .id("everything")
+        .filter(LifecycleRuleFilter::Prefix(String::new()))
+        .status(ExpirationStatus::Disabled)
+        .expiration(LifecycleExpiration::builder().days(365).build())
+        .build()
+        .unwrap();
+    client
+        .put_bucket_lifecycle_configuration()
+        .bucket("testing")
+        .lifecycle_configuration(
+            BucketLifecycleConfiguration::builder()
+                .rules(logs.clone())
+                .rules(everything.clone())
+                .build()
+                .unwrap(),
+        )
+        .send()
+        .await
+        .unwrap();
+
+    let response = client
+        .get_bucket_lifecycle_configuration()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.rules(), [logs.clone(), everything]);
+
+    let error = client
+        .put_bucket_lifecycle_configuration()
+        .bucket("testing")
+        .lifecycle_configuration(
+            BucketLifecycleConfiguration::builder()
+                .rules(
+                    LifecycleRule::builder()
+                        .status(ExpirationStatus::Enabled)
+                        .filter(LifecycleRuleFilter::Prefix(String::new()))
+                        .transitions(
+                            Transition::builder()
+                                .days(30)
+                                .storage_class(TransitionStorageClass::Glacier)
+                                .build(),
+                        )
+                        .build()
+                        .unwrap(),
+                )
+                .build()
+                .unwrap(),
+        )
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("NotImplemented")
+    );
+
+    client
+        .delete_bucket_lifecycle()
+        .bucket("testing")
+        .send()
+        .await
+        .unwrap();
+    assert!(client
+        .get_bucket_lifecycle_configuration()
+        .bucket("testing")
+        .send()
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn buckets_answer_preflights_with_their_cors_rules() {
+    use http_body_util::Full;
+
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+
+    client
+        .create_bucket()
+        .bucket("uploads")
+        .send()
+        .await
+        .unwrap();
+    let error = client
+        .get_bucket_cors()
+        .bucket("uploads")
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("NoSuchCORSConfiguration")
+    );
+
+    let rule = CorsRule::builder()
+        .id("app")
+        .allowed_origins("https://app.example.com")
+        .allowed_methods("PUT")
+        .allowed_methods("POST")
+        .allowed_headers("*")
+        .expose_headers("ETag")
+        .max_age_seconds(3000)
+        .build()
+        .unwrap();
+    client
+        .put_bucket_cors()
+        .bucket("uploads")
+        .cors_configuration(
+            CorsConfiguration::builder()
+                .cors_rules(rule.clone())
+                .build()
+                .unwrap(),
+        )
+        .send()
+        .await
+        .unwrap();
+    let response = client
+        .get_bucket_cors()
+        .bucket("uploads")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.cors_rules(), [rule]);
+
+    let http = hyper_util::client::legacy::Client::builder(hyper_util::rt::TokioExecutor::new())
+        .build_http::<Full<bytes::Bytes>>();
+    let preflight = |method: &str| {
+        let request = hyper::Request::options(format!("{}/uploads/a.txt", server.endpoint_url()))
+            .header("origin", "https://app.example.com")
+            .header("access-control-request-method", method)
+            .header("access-control-request-headers", "content-type, x-amz-date")
+            .body(Full::default())
+            .unwrap();
+        http.request(request)
+    };
+
+    let response = preflight("PUT").await.unwrap();
+    assert_eq!(response.status(), 200);
+    let headers = response.headers();
+    assert_eq!(
+        headers["access-control-allow-origin"],
+        "https://app.example.com"
+    );
+    assert_eq!(headers["access-control-allow-methods"], "PUT, POST");
+    assert_eq!(
+        headers["access-control-allow-headers"],
+        "content-type, x-amz-date"
+    );
+    assert_eq!(headers["access-control-max-age"], "3000");
+    assert_eq!(preflight("DELETE").await.unwrap().status(), 403);
+
+    client
+        .delete_bucket_cors()
+        .bucket("uploads")
+        .send()
+        .await
+        .unwrap();
+    assert_ne!(preflight("PUT").await.unwrap().status(), 200);
+}
+
+#[tokio::test]
+async fn bucket_policies_grant_and_deny_access() {
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+    let metadata = &server.app_state().metadata;
+
+    client
+        .create_bucket()
+        .bucket("shared")
+        .send()
+        .await
+        .unwrap();
+    for key in ["docs/a.txt", "private/b.txt"] {
+        client
+            .put_object()
+            .bucket("shared")
+            .key(key)
+            .body(ByteStream::from_static(b"hello"))
+            .send()
+            .await
+            .unwrap();
+    }
+    let error = client
+        .get_bucket_policy()
+        .bucket("shared")
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("NoSuchBucketPolicy")
+    );
+
+    // the partner reaches the bucket through its custom domain
+    let namespaces = Namespaces::new(metadata);
+    namespaces.create("partner").await.unwrap();
+    let partner_key = namespaces.create_key("partner").await.unwrap();
+    server
+        .app_state()
+        .domains
+        .set(
+            metadata,
+            "localhost",
+            &DomainMapping {
+                namespace: TEST_ACCESS_KEY.to_string(),
+                bucket: String::from("shared"),
+            },
+        )
+        .await
+        .unwrap();
+    let partner = Client::new(ClientConfig {
+        endpoint: format!("http://localhost:{}", server.addr().port()),
+        access_key: Some(partner_key.access_key.clone()),
+        secret_key: Some(partner_key.secret_key.clone()),
+        ..ClientConfig::default()
+    })
+    .unwrap();
+    let error = partner.get_object("docs", "a.txt").await.unwrap_err();
+    assert!(error.to_string().contains("AccessDenied"), "{error}");
+
+    let policy = serde_json::json!({
+        "Version": "2012-10-17",
+        "Statement": [
+            {
+                "Sid": "PartnerReadsDocs",
+                "Effect": "Allow",
+                "Principal": {"AWS": [partner_key.access_key]},
+                "Action": ["s3:GetObject"],
+                "Resource": "arn:aws:s3:::shared/docs/*"
+            },
+            {
+                "Effect": "Deny",
+                "Principal": "*",
+                "Action": "s3:DeleteObject",
+                "Resource": "arn:aws:s3:::shared/docs/*"
+            }
+        ]
+    })
+    .to_string();
+    client
+        .put_bucket_policy()
+        .bucket("shared")
+        .policy(&policy)
+        .send()
+        .await
+        .unwrap();
+    let response = client
+        .get_bucket_policy()
+        .bucket("shared")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.policy(), Some(policy.as_str()));
+
+    assert_eq!(partner.get_object("docs", "a.txt").await.unwrap(), "hello");
+    let error = partner.get_object("private", "b.txt").await.unwrap_err();
+    assert!(error.to_string().contains("AccessDenied"), "{error}");
+    let error = partner
+        .put_object("docs", "c.txt", "hi".into())
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("AccessDenied"), "{error}");
+
+    // deny statements also hold for the keys of the namespace of the bucket
+    let error = client
+        .delete_object()
+        .bucket("shared")
+        .key("docs/a.txt")
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("AccessDenied")
+    );
+    client
+        .delete_object()
+        .bucket("shared")
+        .key("private/b.txt")
+        .send()
+        .await
+        .unwrap();
+
+    let error = client
+        .put_bucket_policy()
+        .bucket("shared")
+        .policy(r#"{"Statement": [{"Effect": "Allow", "Principal": "*", "Action": "s3:GetObject", "Resource": "arn:aws:s3:::other/*"}]}"#)
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("MalformedPolicy")
+    );
+
+    client
+        .delete_bucket_policy()
+        .bucket("shared")
+        .send()
+        .await
+        .unwrap();
+    let error = partner.get_object("docs", "a.txt").await.unwrap_err();
+    assert!(error.to_string().contains("AccessDenied"), "{error}");
+}
+
+#[tokio::test]
+async fn canned_acls_make_buckets_listable_and_objects_public() {
+    use aws_sdk_s3::types::{
+        AccessControlPolicy, BucketCannedAcl, Grant, Grantee, ObjectCannedAcl, Permission, Type,
+    };
+    use http_body_util::{BodyExt, Full};
+
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+
+    let http = hyper_util::client::legacy::Client::builder(hyper_util::rt::TokioExecutor::new())
+        .build_http::<Full<bytes::Bytes>>();
+    let public_get = |path: &str| {
+        let response = http.get(
+            format!(
+                "{}/_public/{}/{}",
+                server.endpoint_url(),
+                TEST_ACCESS_KEY,
+                path
+            )
+            .parse()
+            .unwrap(),
+        );
+        async move { response.await.unwrap().status() }
+    };
+
+    client.create_bucket().bucket("site").send().await.unwrap();
+    client
+        .put_object()
+        .bucket("site")
+        .key("index.html")
+        .acl(ObjectCannedAcl::PublicRead)
+        .body(ByteStream::from_static(b"<html></html>"))
+        .send()
+        .await
+        .unwrap();
+    client
+        .put_object()
+        .bucket("site")
+        .key("draft.html")
+        .body(ByteStream::from_static(b"<html></html>"))
+        .send()
+        .await
+        .unwrap();
+
+    let acl = client
+        .get_object_acl()
+        .bucket("site")
+        .key("index.html")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(acl.owner().and_then(|x| x.id()), Some(TEST_ACCESS_KEY));
+    let grants: Vec<_> = acl
+        .grants()
+        .iter()
+        .map(|x| {
+            (
+                x.grantee().and_then(|x| x.uri().or(x.id())).unwrap(),
+                x.permission().unwrap().as_str(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        grants,
+        [
+            (TEST_ACCESS_KEY, "FULL_CONTROL"),
+            ("http://acs.amazonaws.com/groups/global/AllUsers", "READ"),
+        ]
+    );
+    assert_eq!(public_get("site/index.html").await, 200);
+    assert_eq!(public_get("site/draft.html").await, 404);
+
+    client
+        .put_object_acl()
+        .bucket("site")
+        .key("index.html")
+        .acl(ObjectCannedAcl::Private)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(public_get("site/index.html").await, 404);
+    let error = client
+        .put_object_acl()
+        .bucket("site")
+        .key("index.html")
+        .acl(ObjectCannedAcl::PublicReadWrite)
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.into_service_error().meta().code(),
+        Some("NotImplemented")
+    );
+
+    // a public-read bucket can be listed on its custom domain, its objects stay private
+    let namespaces = Namespaces::new(&server.app_state().metadata);
+    server
+        .app_state()
+        .domains
+        .set(
+            &server.app_state().metadata,
+            "localhost",
+            &DomainMapping {
+                namespace: TEST_ACCESS_KEY.to_string(),
+                bucket: String::from("site"),
+            },
+        )
+        .await
+        .unwrap();
+    let domain_get = |path: &str| {
+        let response = http.get(
+            format!("http://localhost:{}/{}", server.addr().port(), path)
+                .parse()
+                .unwrap(),
+        );
+        async move {
+            let response = response.await.unwrap();
+            let status = response.status();
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            (status, String::from_utf8(body.to_vec()).unwrap())
+        }
+    };
+    assert_eq!(domain_get("").await.0, 403);
+
+    client
+        .put_bucket_acl()
+        .bucket("site")
+        .access_control_policy(
+            AccessControlPolicy::builder()
+                .grants(
+                    Grant::builder()
+                        .grantee(
+                            Grantee::builder()
+                                .r#type(Type::Group)
+                                .uri("http://acs.amazonaws.com/groups/global/AllUsers")
+                                .build()
+                                .unwrap(),
+                        )
+                        .permission(Permission::Read)
+                        .build(),
+                )
+                .build(),
+        )
+        .send()
+        .await
+        .unwrap();
+    let acl = client.get_bucket_acl().bucket("site").send().await.unwrap();
+    assert_eq!(acl.grants().len(), 2);
+    let (status, body) = domain_get("?list-type=2").await;
+    assert_eq!(status, 200);
+    assert!(body.contains("<Key>draft.html</Key>"), "{body}");
+    assert_eq!(domain_get("draft.html").await.0, 403);
+    assert_eq!(public_get("site/draft.html").await, 404);
+    // the public flag belongs to the admin api
+    assert!(!namespaces.is_public(TEST_ACCESS_KEY, "site").await.unwrap());
+
+    namespaces
+        .set_public(TEST_ACCESS_KEY, "assets", true)
+        .await
+        .unwrap();
+    client
+        .create_bucket()
+        .bucket("assets")
+        .acl(BucketCannedAcl::PublicRead)
+        .send()
+        .await
+        .unwrap();
+    client
+        .put_bucket_acl()
+        .bucket("assets")
+        .acl(BucketCannedAcl::Private)
+        .send()
+        .await
+        .unwrap();
+    let acl = client
+        .get_bucket_acl()
+        .bucket("assets")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(acl.grants().len(), 1);
+    // a private ACL does not unpublish a bucket an admin made public
+    assert!(namespaces
+        .is_public(TEST_ACCESS_KEY, "assets")
+        .await
+        .unwrap());
+}
+
+#[tokio::test]
+async fn namespace_policies_apply_to_every_bucket() {
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+
+    for bucket in ["photos", "logs"] {
+        client.create_bucket().bucket(bucket).send().await.unwrap();
+        client
+            .put_object()
+            .bucket(bucket)
+            .key("a.txt")
+            .body(ByteStream::from_static(b"hello"))
+            .send()
+            .await
+            .unwrap();
+    }
+
+    Namespaces::new(&server.app_state().metadata)
+        .set_policy(
+            TEST_ACCESS_KEY,
+            &serde_json::json!({
+                "Statement": [{
+                    "Effect": "Deny",
+                    "Principal": "*",
+                    "Action": "s3:DeleteObject",
+                    "Resource": "arn:aws:s3:::*/*"
+                }]
+            })
+            .to_string(),
+        )
+        .await
+        .unwrap();
+
+    for bucket in ["photos", "logs"] {
+        let error = client
+            .delete_object()
+            .bucket(bucket)
+            .key("a.txt")
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.into_service_error().meta().code(),
+            Some("AccessDenied")
+        );
+    }
+    client
+        .get_object()
+        .bucket("logs")
+        .key("a.txt")
+        .send()
+        .await
+        .unwrap();
+
+    Namespaces::new(&server.app_state().metadata)
+        .delete_policy(TEST_ACCESS_KEY)
+        .await
+        .unwrap();
+    client
+        .delete_object()
+        .bucket("logs")
+        .key("a.txt")
+        .send()
+        .await
+        .unwrap();
+}